@@ -0,0 +1,256 @@
+//! ABI conformance checks for wasm-bindgen-lite exports.
+//!
+//! The generated JS glue wraps a kernel as `(in_ptr, in_len, out_ptr,
+//! out_len) -> i32` and allocates through `alloc_bytes(len) -> ptr` and
+//! `free_bytes(ptr, len)`. An export with any other signature only fails
+//! once the glue calls it, usually as garbage output rather than an error,
+//! so this lint reports the mismatch up front.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use wasmparser::{ExternalKind, FuncType, Parser as WasmParser, Payload, TypeRef, ValType};
+
+const I32: ValType = ValType::I32;
+
+/// The signature the glue expects for each wrapped kernel.
+const KERNEL: (&[ValType], &[ValType]) = (&[I32, I32, I32, I32], &[I32]);
+
+/// Allocator exports the glue calls, with their expected signatures.
+const ALLOCATORS: [(&str, &[ValType], &[ValType]); 2] = [
+    ("alloc_bytes", &[I32], &[I32]),
+    ("free_bytes", &[I32, I32], &[]),
+];
+
+#[derive(Debug, Serialize)]
+pub struct AbiViolation {
+    pub export: String,
+    /// The export's signature, or `None` when it is missing.
+    pub signature: Option<String>,
+    pub expected: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct AbiReport {
+    /// Exports with the standard kernel signature.
+    pub kernels: Vec<String>,
+    /// Exports the glue would call with the wrong signature, or that are
+    /// missing.
+    pub violations: Vec<AbiViolation>,
+    /// Exported functions that are neither kernels nor allocators. Only
+    /// listed when no `--abi-exports` list says which exports are wrapped.
+    pub other: Vec<String>,
+}
+
+fn signature(params: &[ValType], results: &[ValType]) -> String {
+    let list = |types: &[ValType]| {
+        types
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    format!("({}) -> ({})", list(params), list(results))
+}
+
+/// Exported functions of the module with their types.
+pub fn exported_functions(
+    wasm: &[u8],
+) -> Result<Vec<(String, FuncType)>, wasmparser::BinaryReaderError> {
+    let mut types = Vec::new();
+    // Type index of every function, imports first.
+    let mut functions = Vec::new();
+    let mut exports = Vec::new();
+    for payload in WasmParser::new(0).parse_all(wasm) {
+        match payload? {
+            Payload::TypeSection(reader) => {
+                for ty in reader.into_iter_err_on_gc_types() {
+                    types.push(ty?);
+                }
+            }
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    if let TypeRef::Func(ty) = import?.ty {
+                        functions.push(ty);
+                    }
+                }
+            }
+            Payload::FunctionSection(reader) => {
+                for ty in reader {
+                    functions.push(ty?);
+                }
+            }
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export?;
+                    if export.kind == ExternalKind::Func {
+                        exports.push((export.name.to_string(), export.index));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(exports
+        .into_iter()
+        .filter_map(|(name, index)| {
+            let ty = types.get(*functions.get(index as usize)? as usize)?;
+            Some((name, ty.clone()))
+        })
+        .collect())
+}
+
+/// Check the exports against the glue's conventions. With `wrapped`, only
+/// those exports must be kernels (and must exist); otherwise every exported
+/// function that is not an allocator is classified. The allocators are
+/// required as soon as there is a kernel to call.
+pub fn check(exports: &[(String, FuncType)], wrapped: Option<&[String]>) -> AbiReport {
+    let by_name: HashMap<&str, &FuncType> = exports.iter().map(|(n, t)| (n.as_str(), t)).collect();
+    let mut report = AbiReport::default();
+    let expect = |report: &mut AbiReport, name: &str, params: &[ValType], results: &[ValType]| {
+        let found = by_name.get(name);
+        match found {
+            Some(ty) if ty.params() == params && ty.results() == results => true,
+            _ => {
+                report.violations.push(AbiViolation {
+                    export: name.to_string(),
+                    signature: found.map(|ty| signature(ty.params(), ty.results())),
+                    expected: signature(params, results),
+                });
+                false
+            }
+        }
+    };
+
+    match wrapped {
+        Some(names) => {
+            for name in names {
+                if expect(&mut report, name, KERNEL.0, KERNEL.1) {
+                    report.kernels.push(name.clone());
+                }
+            }
+        }
+        None => {
+            for (name, ty) in exports {
+                if ALLOCATORS.iter().any(|(n, ..)| n == name) {
+                    continue;
+                }
+                if ty.params() == KERNEL.0 && ty.results() == KERNEL.1 {
+                    report.kernels.push(name.clone());
+                } else {
+                    report.other.push(name.clone());
+                }
+            }
+        }
+    }
+    if !report.kernels.is_empty() || wrapped.is_some_and(|names| !names.is_empty()) {
+        for (name, params, results) in ALLOCATORS {
+            expect(&mut report, name, params, results);
+        }
+    }
+    report.kernels.sort();
+    report.other.sort();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{Module, I32};
+
+    /// An import, the allocators, one kernel, a kernel returning nothing and
+    /// a helper.
+    fn exports() -> Vec<(String, FuncType)> {
+        let mut module = Module::default();
+        module.import("wbl_log", &[I32; 3], &[]);
+        let body = crate::fixture::i32_const(0);
+        let alloc = module.func(&[I32], &[I32], 0, &body);
+        let free = module.func(&[I32; 2], &[], 0, &[]);
+        let kernel = module.func(&[I32; 4], &[I32], 0, &body);
+        let void = module.func(&[I32; 4], &[], 0, &[]);
+        let helper = module.func(&[], &[I32], 0, &body);
+        module
+            .export("alloc_bytes", alloc)
+            .export("free_bytes", free)
+            .export("kernel", kernel)
+            .export("void_kernel", void)
+            .export("helper", helper);
+        exported_functions(&module.build()).unwrap()
+    }
+
+    #[test]
+    fn reads_export_signatures_past_imports() {
+        let exports = exports();
+        let found: Vec<_> = exports
+            .iter()
+            .map(|(name, ty)| (name.as_str(), signature(ty.params(), ty.results())))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("alloc_bytes", "(i32) -> (i32)".to_string()),
+                ("free_bytes", "(i32, i32) -> ()".to_string()),
+                ("kernel", "(i32, i32, i32, i32) -> (i32)".to_string()),
+                ("void_kernel", "(i32, i32, i32, i32) -> ()".to_string()),
+                ("helper", "() -> (i32)".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn classifies_every_export_without_a_list() {
+        let report = check(&exports(), None);
+        assert_eq!(report.kernels, ["kernel"]);
+        assert_eq!(report.other, ["helper", "void_kernel"]);
+        assert!(report.violations.is_empty());
+    }
+
+    #[test]
+    fn reports_wrapped_exports_with_the_wrong_signature_or_missing() {
+        let wrapped = ["kernel", "void_kernel", "gone"].map(String::from);
+        let report = check(&exports(), Some(&wrapped));
+        assert_eq!(report.kernels, ["kernel"]);
+        assert!(report.other.is_empty());
+        let violations: Vec<_> = report
+            .violations
+            .iter()
+            .map(|v| {
+                (
+                    v.export.as_str(),
+                    v.signature.as_deref(),
+                    v.expected.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            violations,
+            [
+                (
+                    "void_kernel",
+                    Some("(i32, i32, i32, i32) -> ()"),
+                    "(i32, i32, i32, i32) -> (i32)"
+                ),
+                ("gone", None, "(i32, i32, i32, i32) -> (i32)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn requires_allocators_only_once_there_is_a_kernel() {
+        let helper_only: Vec<_> = exports()
+            .into_iter()
+            .filter(|(name, _)| name == "helper")
+            .collect();
+        assert!(check(&helper_only, None).violations.is_empty());
+
+        let kernel_only: Vec<_> = exports()
+            .into_iter()
+            .filter(|(name, _)| name == "kernel")
+            .collect();
+        let missing: Vec<_> = check(&kernel_only, None)
+            .violations
+            .into_iter()
+            .map(|v| v.export)
+            .collect();
+        assert_eq!(missing, ["alloc_bytes", "free_bytes"]);
+    }
+}
@@ -0,0 +1,191 @@
+//! Signals that the module is an unoptimized (debug) build.
+//!
+//! Debug builds keep every generic helper out of line and compile in
+//! overflow and precondition checks, so their SIMD density and opcode
+//! counts say little about the release binary users ship. Analyzing one by
+//! mistake is the most common cause of a misleading report, so these
+//! indicators are surfaced as a prominent warning rather than left for the
+//! reader to spot.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use wasmparser::{Parser as WasmParser, Payload};
+
+/// Share of all named functions that may be `core::iter` or `core::option`
+/// helpers before the build counts as unoptimized. They are inlined into
+/// almost nothing in an optimized build (under 1% here), while a debug build
+/// keeps every adapter method out of line (over 10%).
+const HELPER_SHARE_LIMIT: f64 = 0.05;
+
+/// Panic functions behind `overflow-checks`, which only debug builds
+/// enable by default.
+const OVERFLOW_PANICS: [&str; 6] = [
+    "panic_const_add_overflow",
+    "panic_const_sub_overflow",
+    "panic_const_mul_overflow",
+    "panic_const_neg_overflow",
+    "panic_const_shl_overflow",
+    "panic_const_shr_overflow",
+];
+
+#[derive(Debug, Serialize)]
+pub struct DebugIndicator {
+    /// Short identifier, e.g. `overflow_checks`.
+    pub kind: &'static str,
+    pub detail: String,
+}
+
+/// Look for debug-build signals in the function names (from the name
+/// section), the DWARF sections and the SIMD op count already gathered.
+pub fn indicators(
+    wasm: &[u8],
+    func_names: &HashMap<u32, String>,
+    total_simd_ops: u32,
+) -> Vec<DebugIndicator> {
+    let count = |patterns: &[&str]| {
+        func_names
+            .values()
+            .filter(|name| patterns.iter().any(|p| name.contains(p)))
+            .count()
+    };
+    let mut found = Vec::new();
+
+    // Mangled and demangled spellings of the same paths.
+    let helpers = count(&[
+        "4core4iter",
+        "4core6option",
+        "core::iter::",
+        "core::option::",
+    ]);
+    if helpers > 50 && helpers as f64 > HELPER_SHARE_LIMIT * func_names.len() as f64 {
+        found.push(DebugIndicator {
+            kind: "uninlined_helpers",
+            detail: format!(
+                "{helpers} of {} functions are out-of-line core::iter/core::option helpers",
+                func_names.len()
+            ),
+        });
+    }
+    // Division overflow and by zero are checked in every profile.
+    let overflow = count(&OVERFLOW_PANICS);
+    if overflow > 0 {
+        found.push(DebugIndicator {
+            kind: "overflow_checks",
+            detail: format!("{overflow} arithmetic overflow panic functions"),
+        });
+    }
+    let checks = count(&["precondition_check"]);
+    if checks > 0 {
+        found.push(DebugIndicator {
+            kind: "debug_assertions",
+            detail: format!("{checks} debug precondition checks"),
+        });
+    }
+    if total_simd_ops == 0 && dwarf_mentions(wasm, b"simd128.rs") {
+        found.push(DebugIndicator {
+            kind: "simd_not_emitted",
+            detail: "DWARF references core::arch::wasm32 SIMD intrinsics, \
+                     but the code has no SIMD instructions"
+                .to_string(),
+        });
+    }
+    found
+}
+
+/// Whether any DWARF custom section contains `needle`, e.g. a source file
+/// name from the line tables.
+fn dwarf_mentions(wasm: &[u8], needle: &[u8]) -> bool {
+    WasmParser::new(0)
+        .parse_all(wasm)
+        .any(|payload| match payload {
+            Ok(Payload::CustomSection(section)) => {
+                section.name().starts_with(".debug_")
+                    && section.data().windows(needle.len()).any(|w| w == needle)
+            }
+            _ => false,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Module;
+
+    /// A module with one function per name and an optional DWARF line
+    /// section, analyzed the way `analyze_wasm` does.
+    fn analyze(names: &[String], debug_line: Option<&[u8]>, simd_ops: u32) -> Vec<&'static str> {
+        let mut module = Module::default();
+        for name in names {
+            let index = module.func(&[], &[], 0, &[]);
+            module.name(index, name);
+        }
+        if let Some(data) = debug_line {
+            module.custom(".debug_line", data);
+        }
+        let wasm = module.build();
+        let func_names = crate::parse_name_section(&wasm);
+        assert_eq!(func_names.len(), names.len());
+        indicators(&wasm, &func_names, simd_ops)
+            .into_iter()
+            .map(|i| i.kind)
+            .collect()
+    }
+
+    fn kernels(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("kernel_{i}")).collect()
+    }
+
+    #[test]
+    fn release_build_has_no_indicators() {
+        let mut names = kernels(200);
+        // A few helpers survive inlining, and division is checked in every
+        // profile.
+        names.extend((0..5).map(|i| format!("core::iter::adapters::map::{i}")));
+        names.push("core::panicking::panic_const::panic_const_div_by_zero".into());
+        assert!(analyze(&names, Some(b"src/lib.rs"), 40).is_empty());
+    }
+
+    #[test]
+    fn debug_build_is_flagged() {
+        let mut names = kernels(200);
+        names.extend((0..60).map(|i| format!("_ZN4core4iter8adapters3map{i}")));
+        names.push("core::panicking::panic_const::panic_const_add_overflow".into());
+        names.push("core::ub_checks::precondition_check".into());
+        assert_eq!(
+            analyze(
+                &names,
+                Some(b"src/lib.rs\0core/src/../../stdarch/simd128.rs"),
+                0
+            ),
+            [
+                "uninlined_helpers",
+                "overflow_checks",
+                "debug_assertions",
+                "simd_not_emitted"
+            ]
+        );
+    }
+
+    #[test]
+    fn helpers_must_be_many_and_a_large_share() {
+        let helpers = |count| (0..count).map(|i| format!("core::option::Option::map{i}"));
+        let mut few = kernels(10);
+        few.extend(helpers(40));
+        assert!(analyze(&few, None, 1).is_empty(), "too few to count");
+        let mut diluted = kernels(2000);
+        diluted.extend(helpers(60));
+        assert!(
+            analyze(&diluted, None, 1).is_empty(),
+            "under 5% of functions"
+        );
+    }
+
+    #[test]
+    fn missing_simd_needs_both_dwarf_and_no_simd_ops() {
+        let names = kernels(3);
+        let dwarf: &[u8] = b"stdarch/crates/core_arch/src/wasm32/simd128.rs";
+        assert_eq!(analyze(&names, Some(dwarf), 0), ["simd_not_emitted"]);
+        assert!(analyze(&names, Some(dwarf), 12).is_empty());
+        assert!(analyze(&names, None, 0).is_empty());
+    }
+}
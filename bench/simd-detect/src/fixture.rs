@@ -0,0 +1,238 @@
+//! Hand-assembled wasm modules for the analyzers' tests.
+//!
+//! Only the sections the analyzers read are emitted: types, function
+//! imports, functions, one memory, exports, code, the `name` section and
+//! arbitrary custom sections. Bodies are raw instruction bytes; the helpers
+//! below spell out the few instructions the tests need.
+
+use std::path::PathBuf;
+
+pub const I32: u8 = 0x7f;
+
+#[derive(Default)]
+pub struct Module {
+    types: Vec<(Vec<u8>, Vec<u8>)>,
+    imports: Vec<(String, u32)>,
+    funcs: Vec<(u32, Vec<u8>)>,
+    exports: Vec<(String, u32)>,
+    names: Vec<(u32, String)>,
+    custom: Vec<(String, Vec<u8>)>,
+}
+
+fn uleb(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn sleb(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        out.push(if done { byte } else { byte | 0x80 });
+        if done {
+            return;
+        }
+    }
+}
+
+fn name(out: &mut Vec<u8>, name: &str) {
+    uleb(out, name.len() as u64);
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn section(out: &mut Vec<u8>, id: u8, count: usize, body: &[u8]) {
+    let mut content = Vec::new();
+    uleb(&mut content, count as u64);
+    content.extend_from_slice(body);
+    out.push(id);
+    uleb(out, content.len() as u64);
+    out.extend_from_slice(&content);
+}
+
+impl Module {
+    fn ty(&mut self, params: &[u8], results: &[u8]) -> u32 {
+        let ty = (params.to_vec(), results.to_vec());
+        match self.types.iter().position(|t| *t == ty) {
+            Some(index) => index as u32,
+            None => {
+                self.types.push(ty);
+                self.types.len() as u32 - 1
+            }
+        }
+    }
+
+    /// Import `env.<field>`; call before defining functions so indices stay
+    /// in order.
+    pub fn import(&mut self, field: &str, params: &[u8], results: &[u8]) -> u32 {
+        assert!(self.funcs.is_empty(), "imports come first");
+        let ty = self.ty(params, results);
+        self.imports.push((field.to_string(), ty));
+        self.imports.len() as u32 - 1
+    }
+
+    /// Define a function with `locals` extra `i32` locals and body `code`
+    /// (without the final `end`). Returns its function index.
+    pub fn func(&mut self, params: &[u8], results: &[u8], locals: u32, code: &[u8]) -> u32 {
+        let ty = self.ty(params, results);
+        let mut body = Vec::new();
+        if locals == 0 {
+            body.push(0);
+        } else {
+            body.push(1);
+            uleb(&mut body, locals as u64);
+            body.push(I32);
+        }
+        body.extend_from_slice(code);
+        body.push(0x0b);
+        self.funcs.push((ty, body));
+        (self.imports.len() + self.funcs.len()) as u32 - 1
+    }
+
+    pub fn export(&mut self, name: &str, index: u32) -> &mut Self {
+        self.exports.push((name.to_string(), index));
+        self
+    }
+
+    /// Record `name` for function `index` in the `name` section.
+    pub fn name(&mut self, index: u32, name: &str) -> &mut Self {
+        self.names.push((index, name.to_string()));
+        self
+    }
+
+    pub fn custom(&mut self, name: &str, data: &[u8]) -> &mut Self {
+        self.custom.push((name.to_string(), data.to_vec()));
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut out = b"\0asm\x01\0\0\0".to_vec();
+
+        let mut body = Vec::new();
+        for (params, results) in &self.types {
+            body.push(0x60);
+            uleb(&mut body, params.len() as u64);
+            body.extend_from_slice(params);
+            uleb(&mut body, results.len() as u64);
+            body.extend_from_slice(results);
+        }
+        section(&mut out, 1, self.types.len(), &body);
+
+        if !self.imports.is_empty() {
+            let mut body = Vec::new();
+            for (field, ty) in &self.imports {
+                name(&mut body, "env");
+                name(&mut body, field);
+                body.push(0x00);
+                uleb(&mut body, *ty as u64);
+            }
+            section(&mut out, 2, self.imports.len(), &body);
+        }
+
+        let mut body = Vec::new();
+        for (ty, _) in &self.funcs {
+            uleb(&mut body, *ty as u64);
+        }
+        section(&mut out, 3, self.funcs.len(), &body);
+
+        // One page, no maximum.
+        section(&mut out, 5, 1, &[0x00, 0x01]);
+
+        let mut body = Vec::new();
+        for (field, index) in &self.exports {
+            name(&mut body, field);
+            body.push(0x00);
+            uleb(&mut body, *index as u64);
+        }
+        section(&mut out, 7, self.exports.len(), &body);
+
+        let mut body = Vec::new();
+        for (_, code) in &self.funcs {
+            uleb(&mut body, code.len() as u64);
+            body.extend_from_slice(code);
+        }
+        section(&mut out, 10, self.funcs.len(), &body);
+
+        if !self.names.is_empty() {
+            let mut names = Vec::new();
+            uleb(&mut names, self.names.len() as u64);
+            for (index, func) in &self.names {
+                uleb(&mut names, *index as u64);
+                name(&mut names, func);
+            }
+            let mut data = Vec::new();
+            name(&mut data, "name");
+            data.push(1);
+            uleb(&mut data, names.len() as u64);
+            data.extend_from_slice(&names);
+            out.push(0);
+            uleb(&mut out, data.len() as u64);
+            out.extend_from_slice(&data);
+        }
+
+        for (section_name, bytes) in &self.custom {
+            let mut data = Vec::new();
+            name(&mut data, section_name);
+            data.extend_from_slice(bytes);
+            out.push(0);
+            uleb(&mut out, data.len() as u64);
+            out.extend_from_slice(&data);
+        }
+        out
+    }
+}
+
+pub fn local_get(index: u32) -> Vec<u8> {
+    let mut out = vec![0x20];
+    uleb(&mut out, index as u64);
+    out
+}
+
+pub fn local_set(index: u32) -> Vec<u8> {
+    let mut out = vec![0x21];
+    uleb(&mut out, index as u64);
+    out
+}
+
+pub fn i32_const(value: i32) -> Vec<u8> {
+    let mut out = vec![0x41];
+    sleb(&mut out, value as i64);
+    out
+}
+
+pub const I32_ADD: u8 = 0x6a;
+pub const DROP: u8 = 0x1a;
+
+fn simd(op: u32, align: u32, offset: u64) -> Vec<u8> {
+    let mut out = vec![0xfd];
+    uleb(&mut out, op as u64);
+    uleb(&mut out, align.trailing_zeros() as u64);
+    uleb(&mut out, offset);
+    out
+}
+
+/// `v128.load` declaring `align` bytes (a power of two).
+pub fn v128_load(align: u32, offset: u64) -> Vec<u8> {
+    simd(0x00, align, offset)
+}
+
+/// `v128.store` declaring `align` bytes (a power of two).
+pub fn v128_store(align: u32, offset: u64) -> Vec<u8> {
+    simd(0x0b, align, offset)
+}
+
+/// A path in the temp directory unique to this process and `name`, removed
+/// first if a previous run left it behind.
+pub fn temp_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("simd-detect-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_dir_all(&path);
+    path
+}
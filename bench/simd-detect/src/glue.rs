@@ -0,0 +1,294 @@
+//! Cross-check of the JS glue against the module's exports.
+//!
+//! With `--glue`, the generated glue (or the npm package directory holding
+//! it) is scanned for the exports it uses. Exports it never mentions are
+//! candidates for trimming; names it requires that the module does not
+//! export mean the glue and the wasm have drifted apart, and only fail once
+//! that code path runs.
+//!
+//! The scan is textual. Any whole-word mention of an export counts as a use,
+//! so comments can hide an unused export. The kernels the glue wraps
+//! (`callWasm("name", ...)`) and the allocators every wrapper calls are
+//! reported as missing when absent. The generated runtime also references
+//! helper exports (`realloc_bytes`, `last_error_message`, ...) that only
+//! the helper using them needs; absent ones are listed as unavailable
+//! rather than missing. Feature checks such as `exports.name?.()` or
+//! `exports.name ? a : b` are never reported.
+
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const EXTENSIONS: [&str; 3] = ["js", "mjs", "cjs"];
+
+/// Glue helpers whose first argument names the export they call.
+const CALLERS: [&str; 2] = ["callWasm", "callCallee"];
+
+/// Exports every wrapper call needs.
+const ALLOCATORS: [&str; 2] = ["alloc_bytes", "free_bytes"];
+
+#[derive(Debug, Default, Serialize)]
+pub struct GlueReport {
+    /// Glue files that were scanned.
+    pub files: Vec<String>,
+    /// Exported functions the glue mentions.
+    pub referenced: Vec<String>,
+    /// Exported functions the glue never mentions.
+    pub unused: Vec<String>,
+    /// Wrapped kernels and allocators the glue calls that the module does
+    /// not export.
+    pub missing: Vec<String>,
+    /// Other names the glue references unconditionally that the module does
+    /// not export; only the glue helpers using them fail.
+    pub unavailable: Vec<String>,
+}
+
+/// JS files at `path`, recursing into directories except `node_modules`.
+fn collect(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if path.is_file() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            if entry.file_name().is_some_and(|n| n != "node_modules") {
+                collect(&entry, files)?;
+            }
+        } else if entry
+            .extension()
+            .is_some_and(|ext| EXTENSIONS.iter().any(|e| ext == *e))
+        {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+fn is_ident(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'$'
+}
+
+/// The identifier at the start of `s`.
+fn ident(s: &str) -> &str {
+    let end = s.bytes().position(|b| !is_ident(b)).unwrap_or(s.len());
+    &s[..end]
+}
+
+/// A quoted string literal at the start of `s`, without the quotes.
+fn quoted(s: &str) -> Option<&str> {
+    let quote = *s.as_bytes().first()?;
+    if !matches!(quote, b'"' | b'\'' | b'`') {
+        return None;
+    }
+    let end = s[1..].bytes().position(|b| b == quote)?;
+    Some(&s[1..1 + end])
+}
+
+/// Whether what follows a reference makes it a feature check rather than a
+/// use: `?.`, a ternary, or a truthiness test.
+fn is_optional(rest: &str) -> bool {
+    let rest = rest.trim_start();
+    ["?", ")", "&&", "||"].iter().any(|p| rest.starts_with(p))
+}
+
+/// Every whole word in `text`.
+fn words(text: &str) -> BTreeSet<&str> {
+    let mut words = BTreeSet::new();
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if is_ident(bytes[i]) {
+            let word = ident(&text[i..]);
+            words.insert(word);
+            i += word.len();
+        } else {
+            i += 1;
+        }
+    }
+    words
+}
+
+/// Names `text` references as `exports.name` or `exports["name"]` outside
+/// feature checks.
+fn direct(text: &str) -> BTreeSet<&str> {
+    let mut names = BTreeSet::new();
+    for (at, _) in text.match_indices("exports") {
+        let before = &text[..at];
+        if before.ends_with(|c: char| c.is_ascii() && is_ident(c as u8))
+            || before.ends_with("module.")
+        {
+            continue;
+        }
+        let after = &text[at + "exports".len()..];
+        let (name, rest) = if let Some(rest) = after.strip_prefix('.') {
+            let name = ident(rest);
+            (name, &rest[name.len()..])
+        } else if let Some(name) = after.strip_prefix('[').and_then(quoted) {
+            // Skip the quotes and the closing bracket.
+            (name, &after[name.len() + 4..])
+        } else {
+            continue;
+        };
+        if !name.is_empty() && !is_optional(rest) {
+            names.insert(name);
+        }
+    }
+    names
+}
+
+/// Names `text` passes as the first argument of the glue's callers.
+fn wrapped(text: &str) -> BTreeSet<&str> {
+    let mut names = BTreeSet::new();
+    for caller in CALLERS {
+        for (at, _) in text.match_indices(caller) {
+            let after = &text[at + caller.len()..];
+            if let Some(name) = after.strip_prefix('(').and_then(quoted) {
+                names.insert(name);
+            }
+        }
+    }
+    names
+}
+
+/// Scan the glue at `paths` against the module's exported `functions`. The
+/// glue also requires `memory`, which is not a function and is never
+/// reported as missing.
+pub fn check(paths: &[PathBuf], functions: &[String]) -> std::io::Result<GlueReport> {
+    let mut files = Vec::new();
+    for path in paths {
+        collect(path, &mut files)?;
+    }
+    let texts = files
+        .iter()
+        .map(fs::read_to_string)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut mentioned = BTreeSet::new();
+    let mut kernels = BTreeSet::new();
+    let mut referenced = BTreeSet::new();
+    for text in &texts {
+        mentioned.extend(words(text));
+        kernels.extend(wrapped(text));
+        referenced.extend(direct(text));
+    }
+
+    let mut report = GlueReport {
+        files: files.iter().map(|f| f.display().to_string()).collect(),
+        ..Default::default()
+    };
+    for name in functions {
+        if mentioned.contains(name.as_str()) {
+            report.referenced.push(name.clone());
+        } else {
+            report.unused.push(name.clone());
+        }
+    }
+    let absent = |name: &&&str| **name != "memory" && !functions.iter().any(|f| f == *name);
+    if !kernels.is_empty() {
+        kernels.extend(ALLOCATORS);
+    }
+    report.missing = kernels
+        .iter()
+        .filter(absent)
+        .map(|n| n.to_string())
+        .collect();
+    report.unavailable = referenced
+        .difference(&kernels)
+        .filter(absent)
+        .map(|n| n.to_string())
+        .collect();
+    report.referenced.sort();
+    report.unused.sort();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{self, Module, I32};
+
+    /// Names of the exported functions of a module with the allocators,
+    /// `process_bytes` and an `extra_kernel` the glue never wraps.
+    fn functions() -> Vec<String> {
+        let mut module = Module::default();
+        let body = fixture::i32_const(0);
+        let alloc = module.func(&[I32], &[I32], 0, &body);
+        let free = module.func(&[I32; 2], &[], 0, &[]);
+        let kernel = module.func(&[I32; 4], &[I32], 0, &body);
+        module
+            .export("alloc_bytes", alloc)
+            .export("free_bytes", free)
+            .export("process_bytes", kernel)
+            .export("extra_kernel", kernel);
+        crate::abi::exported_functions(&module.build())
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    const GLUE: &str = r#"
+const memory = () => exports.memory;
+function alloc(len) { return exports.alloc_bytes(len); }
+function free(ptr, len) { exports.free_bytes(ptr, len); }
+function grow(ptr, a, b) { return exports.realloc_bytes(ptr, a, b); }
+const code = exports.last_error_code?.() ?? 0;
+const clear = exports.last_error_clear ? 1 : 0;
+if (exports["abi_version"]) check();
+module.exports = { process };
+export function process(input) { return callWasm("process_bytes", input); }
+export function lines(input) { return callCallee('find_lines', input); }
+"#;
+
+    #[test]
+    fn reports_missing_and_unused_exports() {
+        let dir = fixture::temp_path("glue");
+        fs::create_dir_all(dir.join("node_modules/dep")).unwrap();
+        fs::write(dir.join("core.js"), GLUE).unwrap();
+        fs::write(
+            dir.join("types.d.ts"),
+            "export function extra_kernel(): void;",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("node_modules/dep/index.js"),
+            "callWasm('extra_kernel'); exports.vendored();",
+        )
+        .unwrap();
+
+        let report = check(std::slice::from_ref(&dir), &functions()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(report.files, [dir.join("core.js").display().to_string()]);
+        assert_eq!(
+            report.referenced,
+            ["alloc_bytes", "free_bytes", "process_bytes"]
+        );
+        assert_eq!(report.unused, ["extra_kernel"]);
+        assert_eq!(report.missing, ["find_lines"]);
+        // Feature checks (`?.`, `?`, `)`) and `module.exports` are skipped.
+        assert_eq!(report.unavailable, ["realloc_bytes"]);
+    }
+
+    #[test]
+    fn allocators_are_only_required_with_a_wrapped_kernel() {
+        let path = fixture::temp_path("helpers.js");
+        fs::write(&path, "const n = exports.handle_count();").unwrap();
+        let report = check(std::slice::from_ref(&path), &[]).unwrap();
+        assert!(report.missing.is_empty());
+        assert_eq!(report.unavailable, ["handle_count"]);
+
+        fs::write(&path, "callWasm(\"process_bytes\", input);").unwrap();
+        let report = check(std::slice::from_ref(&path), &[]).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            report.missing,
+            ["alloc_bytes", "free_bytes", "process_bytes"]
+        );
+    }
+}
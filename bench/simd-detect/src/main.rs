@@ -4,23 +4,35 @@
 //! instructions back to Rust source code using DWARF debug info.
 
 use addr2line::Context;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use gimli::{EndianSlice, LittleEndian};
 use object::{Object, ObjectSection};
 use serde::Serialize;
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::PathBuf;
-use wasmparser::{BinaryReaderError, Operator, Parser as WasmParser, Payload};
+use std::path::{Path, PathBuf};
+use wasmparser::{BinaryReaderError, Operator, Parser as WasmParser, Payload, TypeRef};
+
+mod abi;
+mod debug_build;
+#[cfg(test)]
+mod fixture;
+mod glue;
+mod stride;
+mod trend;
 
 #[derive(Parser, Debug)]
 #[command(name = "simd-detect")]
 #[command(about = "Detect SIMD instructions in WebAssembly and map to source")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to the .wasm file to analyze
     #[arg(required = true)]
-    wasm_file: PathBuf,
+    wasm_file: Option<PathBuf>,
 
     /// Variant name (for report labeling)
     #[arg(short, long, default_value = "unknown")]
@@ -33,6 +45,49 @@ struct Args {
     /// Print verbose output
     #[arg(short = 'V', long)]
     verbose: bool,
+
+    /// Exports the JS glue wraps (comma-separated); each must have the
+    /// standard `(i32, i32, i32, i32) -> i32` kernel signature
+    #[arg(long, value_delimiter = ',')]
+    abi_exports: Option<Vec<String>>,
+
+    /// Exit with an error when the ABI check finds violations
+    #[arg(long)]
+    abi_strict: bool,
+
+    /// Generated JS glue files or package directories to check against the
+    /// exports (repeatable)
+    #[arg(long)]
+    glue: Vec<PathBuf>,
+
+    /// Append this run's metrics to a JSONL trend database
+    #[arg(long)]
+    db: Option<PathBuf>,
+
+    /// Label for the run in the trend database, e.g. a git sha
+    #[arg(long, requires = "db")]
+    label: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print per-variant and per-function history from a trend database
+    Trend {
+        /// Trend database written with --db
+        db: PathBuf,
+
+        /// Only show this variant
+        #[arg(short, long)]
+        variant: Option<String>,
+
+        /// Only show functions whose name contains this
+        #[arg(short, long)]
+        function: Option<String>,
+
+        /// Number of functions to show per variant
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+    },
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -52,6 +107,48 @@ struct FunctionInfo {
     total_ops: u32,
     simd_density: f64,
     op_breakdown: HashMap<String, u32>,
+    branches: BranchStats,
+    /// Branch instructions per op; high values next to SIMD suggest control
+    /// flow that keeps the vector units waiting.
+    branch_density: f64,
+    /// Alignment and stride patterns of the v128 loads and stores.
+    v128_access: stride::V128Access,
+}
+
+/// Conditional control flow in a function.
+#[derive(Debug, Clone, Default, Serialize)]
+struct BranchStats {
+    if_count: u32,
+    br_if: u32,
+    br_table: u32,
+    /// Targets across all `br_table`s, default included.
+    br_table_targets: u32,
+    /// McCabe complexity: one plus a decision per `if`/`br_if` and per
+    /// extra `br_table` target.
+    cyclomatic_complexity: u32,
+}
+
+impl BranchStats {
+    fn count(&mut self, op: &Operator) {
+        match op {
+            Operator::If { .. } => self.if_count += 1,
+            Operator::BrIf { .. } => self.br_if += 1,
+            Operator::BrTable { targets } => {
+                self.br_table += 1;
+                self.br_table_targets += targets.len() + 1;
+            }
+            _ => {}
+        }
+    }
+
+    fn branches(&self) -> u32 {
+        self.if_count + self.br_if + self.br_table
+    }
+
+    fn finish(&mut self) {
+        self.cyclomatic_complexity =
+            1 + self.if_count + self.br_if + self.br_table_targets - self.br_table;
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -71,9 +168,17 @@ struct SimdReport {
     total_simd_ops: u32,
     total_ops: u32,
     overall_simd_density: f64,
+    /// v128 loads and stores across the module by declared alignment in
+    /// bytes.
+    v128_alignment: BTreeMap<u32, u32>,
     opcode_summary: HashMap<String, u32>,
     functions: Vec<FunctionInfo>,
     lines: Vec<LineInfo>,
+    abi: abi::AbiReport,
+    /// Signs of an unoptimized build; empty for a release build.
+    debug_indicators: Vec<debug_build::DebugIndicator>,
+    /// Glue/export cross-check; `None` without `--glue`.
+    glue: Option<glue::GlueReport>,
 }
 
 /// Categorize WASM operator as SIMD or not, return opcode name if SIMD
@@ -352,10 +457,8 @@ fn parse_name_section(data: &[u8]) -> HashMap<u32, String> {
                 let name_reader = wasmparser::NameSectionReader::new(reader);
                 for name in name_reader {
                     if let Ok(wasmparser::Name::Function(fnames)) = name {
-                        for fname in fnames {
-                            if let Ok(naming) = fname {
-                                names.insert(naming.index, naming.name.to_string());
-                            }
+                        for naming in fnames.into_iter().flatten() {
+                            names.insert(naming.index, naming.name.to_string());
                         }
                     }
                 }
@@ -367,18 +470,32 @@ fn parse_name_section(data: &[u8]) -> HashMap<u32, String> {
 }
 
 /// Analyze a single function's code
+/// Op count, SIMD op count, SIMD opcode breakdown, branches and v128 memory
+/// access of one function.
+type FunctionOps = (
+    u32,
+    u32,
+    HashMap<String, u32>,
+    BranchStats,
+    stride::V128Access,
+);
+
 fn analyze_function(
     _func_index: u32,
     code: &wasmparser::FunctionBody,
-) -> Result<(u32, u32, HashMap<String, u32>), BinaryReaderError> {
+) -> Result<FunctionOps, BinaryReaderError> {
     let mut total_ops = 0u32;
     let mut simd_ops = 0u32;
     let mut breakdown: HashMap<String, u32> = HashMap::new();
+    let mut branches = BranchStats::default();
+    let mut v128_access = stride::V128Access::default();
 
     let mut reader = code.get_operators_reader()?;
     while !reader.eof() {
         let op = reader.read()?;
         total_ops += 1;
+        branches.count(&op);
+        v128_access.count(&op);
 
         if let Some(opcode_name) = classify_simd_op(&op) {
             simd_ops += 1;
@@ -386,7 +503,9 @@ fn analyze_function(
         }
     }
 
-    Ok((total_ops, simd_ops, breakdown))
+    branches.finish();
+    v128_access.finish();
+    Ok((total_ops, simd_ops, breakdown, branches, v128_access))
 }
 
 /// Try to get source location from DWARF
@@ -404,8 +523,8 @@ fn get_source_location(
     (None, None)
 }
 
-fn analyze_wasm(args: &Args) -> Result<SimdReport, Box<dyn std::error::Error>> {
-    let wasm_bytes = fs::read(&args.wasm_file)?;
+fn analyze_wasm(args: &Args, wasm_file: &Path) -> Result<SimdReport, Box<dyn std::error::Error>> {
+    let wasm_bytes = fs::read(wasm_file)?;
     let wasm_hash = hex::encode(&Sha256::digest(&wasm_bytes)[..8]);
 
     // Parse name section for function names
@@ -442,16 +561,25 @@ fn analyze_wasm(args: &Args) -> Result<SimdReport, Box<dyn std::error::Error>> {
     let mut total_simd_ops = 0u32;
     let mut total_ops = 0u32;
 
+    // Imported functions come first in the function index space.
     let mut func_index = 0u32;
     let mut code_section_offset = 0u64;
 
     for payload in WasmParser::new(0).parse_all(&wasm_bytes) {
         match payload? {
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    if let TypeRef::Func(_) = import?.ty {
+                        func_index += 1;
+                    }
+                }
+            }
             Payload::CodeSectionStart { range, .. } => {
                 code_section_offset = range.start as u64;
             }
             Payload::CodeSectionEntry(code) => {
-                let (ops, simd, breakdown) = analyze_function(func_index, &code)?;
+                let (ops, simd, breakdown, branches, v128_access) =
+                    analyze_function(func_index, &code)?;
 
                 total_ops += ops;
                 total_simd_ops += simd;
@@ -474,10 +602,13 @@ fn analyze_wasm(args: &Args) -> Result<SimdReport, Box<dyn std::error::Error>> {
                     }
                 }
 
-                let density = if ops > 0 {
-                    simd as f64 / ops as f64
+                let (density, branch_density) = if ops > 0 {
+                    (
+                        simd as f64 / ops as f64,
+                        branches.branches() as f64 / ops as f64,
+                    )
                 } else {
-                    0.0
+                    (0.0, 0.0)
                 };
 
                 functions.push(FunctionInfo {
@@ -489,6 +620,9 @@ fn analyze_wasm(args: &Args) -> Result<SimdReport, Box<dyn std::error::Error>> {
                     total_ops: ops,
                     simd_density: density,
                     op_breakdown: breakdown,
+                    branches,
+                    branch_density,
+                    v128_access,
                 });
 
                 func_index += 1;
@@ -519,6 +653,23 @@ fn analyze_wasm(args: &Args) -> Result<SimdReport, Box<dyn std::error::Error>> {
         .collect();
     simd_functions.sort_by(|a, b| b.simd_density.partial_cmp(&a.simd_density).unwrap());
 
+    let mut v128_alignment = BTreeMap::new();
+    for f in &simd_functions {
+        for (align, count) in &f.v128_access.alignment {
+            *v128_alignment.entry(*align).or_insert(0) += count;
+        }
+    }
+
+    let exports = abi::exported_functions(&wasm_bytes)?;
+    let abi = abi::check(&exports, args.abi_exports.as_deref());
+    let debug_indicators = debug_build::indicators(&wasm_bytes, &func_names, total_simd_ops);
+    let glue = if args.glue.is_empty() {
+        None
+    } else {
+        let names: Vec<String> = exports.iter().map(|(name, _)| name.clone()).collect();
+        Some(glue::check(&args.glue, &names)?)
+    };
+
     let overall_density = if total_ops > 0 {
         total_simd_ops as f64 / total_ops as f64
     } else {
@@ -527,26 +678,69 @@ fn analyze_wasm(args: &Args) -> Result<SimdReport, Box<dyn std::error::Error>> {
 
     Ok(SimdReport {
         variant: args.variant.clone(),
-        wasm_path: args.wasm_file.display().to_string(),
+        wasm_path: wasm_file.display().to_string(),
         wasm_hash,
         wasm_size: wasm_bytes.len(),
         total_simd_ops,
         total_ops,
         overall_simd_density: overall_density,
+        v128_alignment,
         opcode_summary,
         functions: simd_functions,
         lines,
+        abi,
+        debug_indicators,
+        glue,
     })
 }
 
+/// The metrics of `report` kept in the trend database.
+fn trend_record(report: &SimdReport, label: Option<String>) -> trend::TrendRecord {
+    trend::TrendRecord {
+        timestamp: trend::now(),
+        label,
+        variant: report.variant.clone(),
+        wasm_hash: report.wasm_hash.clone(),
+        wasm_size: report.wasm_size,
+        total_ops: report.total_ops,
+        total_simd_ops: report.total_simd_ops,
+        simd_density: report.overall_simd_density,
+        functions: report
+            .functions
+            .iter()
+            .map(|f| trend::FunctionMetrics {
+                name: f.name.clone().unwrap_or_else(|| format!("#{}", f.index)),
+                simd_ops: f.simd_ops_total,
+                total_ops: f.total_ops,
+            })
+            .collect(),
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
 
+    if let Some(Command::Trend {
+        db,
+        variant,
+        function,
+        limit,
+    }) = &args.command
+    {
+        let records = trend::load(db)?;
+        if records.is_empty() {
+            return Err(format!("{} has no runs", db.display()).into());
+        }
+        trend::print(&records, variant.as_deref(), function.as_deref(), *limit);
+        return Ok(());
+    }
+    let wasm_file = args.wasm_file.as_deref().expect("required by clap");
+
     if args.verbose {
-        eprintln!("Analyzing: {}", args.wasm_file.display());
+        eprintln!("Analyzing: {}", wasm_file.display());
     }
 
-    let report = analyze_wasm(&args)?;
+    let report = analyze_wasm(&args, wasm_file)?;
 
     let json = serde_json::to_string_pretty(&report)?;
 
@@ -557,6 +751,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("{}", json);
     }
 
+    if let Some(db) = &args.db {
+        trend::append(db, &trend_record(&report, args.label.clone()))?;
+        eprintln!("Appended run to: {}", db.display());
+    }
+
+    if !report.debug_indicators.is_empty() {
+        eprintln!("\n{}", "!".repeat(72));
+        eprintln!("  WARNING: this looks like a debug build; its SIMD density and opcode");
+        eprintln!("  counts will not match the optimized binary. Rebuild with --release.");
+        for indicator in &report.debug_indicators {
+            eprintln!("    - {}", indicator.detail);
+        }
+        eprintln!("{}", "!".repeat(72));
+    }
+
     // Print summary to stderr
     eprintln!("\nSIMD Analysis Summary:");
     eprintln!("  Variant: {}", report.variant);
@@ -578,5 +787,157 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    // SIMD functions where branching is densest, i.e. where control flow
+    // most likely limits vector throughput.
+    let mut branchy: Vec<_> = report
+        .functions
+        .iter()
+        .filter(|f| f.branches.branches() > 0)
+        .collect();
+    branchy.sort_by(|a, b| b.branch_density.total_cmp(&a.branch_density));
+    if !branchy.is_empty() {
+        eprintln!("\n  Branchiest SIMD functions:");
+        for f in branchy.iter().take(5) {
+            eprintln!(
+                "    {}: {:.1}% SIMD, {:.1}% branches, complexity {}",
+                f.name.as_deref().unwrap_or("<unnamed>"),
+                f.simd_density * 100.0,
+                f.branch_density * 100.0,
+                f.branches.cyclomatic_complexity
+            );
+        }
+    }
+
+    if !report.v128_alignment.is_empty() {
+        let alignment: Vec<String> = report
+            .v128_alignment
+            .iter()
+            .map(|(align, count)| format!("{align}B: {count}"))
+            .collect();
+        eprintln!("\n  v128 memory ops by alignment: {}", alignment.join(", "));
+        let mut heavy: Vec<_> = report
+            .functions
+            .iter()
+            .filter(|f| f.v128_access.accesses() > 0)
+            .collect();
+        heavy.sort_by_key(|f| std::cmp::Reverse(f.v128_access.accesses()));
+        let peel = heavy
+            .iter()
+            .filter(|f| f.v128_access.peel_candidate)
+            .count();
+        eprintln!("  Alignment-peeling candidates: {peel} functions");
+        for f in heavy.iter().take(5) {
+            let access = &f.v128_access;
+            let strides: Vec<String> = access
+                .offset_strides
+                .iter()
+                .map(|s| format!("{}x{}B", s.offsets, s.stride))
+                .chain(
+                    access
+                        .pointer_steps
+                        .iter()
+                        .flat_map(|p| p.steps.iter().map(|step| format!("+{step}B/iter"))),
+                )
+                .collect();
+            eprintln!(
+                "    {}: {} loads, {} stores, {} under-aligned{}{}",
+                f.name.as_deref().unwrap_or("<unnamed>"),
+                access.loads,
+                access.stores,
+                access.underaligned_full_width,
+                if access.peel_candidate { " (peel)" } else { "" },
+                if strides.is_empty() {
+                    String::new()
+                } else {
+                    format!(", strides {}", strides.join(" "))
+                }
+            );
+        }
+    }
+
+    eprintln!(
+        "\n  ABI: {} kernel exports, {} violations",
+        report.abi.kernels.len(),
+        report.abi.violations.len()
+    );
+    for violation in &report.abi.violations {
+        eprintln!(
+            "    {}: {}, expected {}",
+            violation.export,
+            violation.signature.as_deref().unwrap_or("not exported"),
+            violation.expected
+        );
+    }
+    if let Some(glue) = &report.glue {
+        eprintln!(
+            "\n  Glue ({} files): {} exports referenced, {} unused, {} missing",
+            glue.files.len(),
+            glue.referenced.len(),
+            glue.unused.len(),
+            glue.missing.len()
+        );
+        for name in &glue.missing {
+            eprintln!("    missing: {name} is called by the glue but not exported");
+        }
+        if !glue.unavailable.is_empty() {
+            eprintln!(
+                "    helpers unavailable in this module: {}",
+                glue.unavailable.join(", ")
+            );
+        }
+        for name in glue.unused.iter().take(20) {
+            eprintln!("    unused: {name}");
+        }
+        if glue.unused.len() > 20 {
+            eprintln!("    ... {} more in the report", glue.unused.len() - 20);
+        }
+    }
+    if args.abi_strict && !report.abi.violations.is_empty() {
+        return Err("exports do not follow the wasm-bindgen-lite ABI".into());
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{Module, I32};
+
+    #[test]
+    fn report_runs_the_abi_check_on_the_wrapped_exports() {
+        let mut module = Module::default();
+        let kernel = module.func(&[I32; 4], &[I32], 0, &fixture::i32_const(0));
+        let alloc = module.func(&[I32], &[I32], 0, &fixture::i32_const(0));
+        let wrong = module.func(&[I32; 2], &[I32], 0, &fixture::i32_const(0));
+        module
+            .export("kernel", kernel)
+            .export("alloc_bytes", alloc)
+            .export("wrong", wrong);
+        let path = fixture::temp_path("abi.wasm");
+        fs::write(&path, module.build()).unwrap();
+
+        let args = Args::parse_from([
+            "simd-detect".as_ref(),
+            path.as_os_str(),
+            "--abi-exports".as_ref(),
+            "kernel,wrong".as_ref(),
+        ]);
+        let report = analyze_wasm(&args, &path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(report.abi.kernels, ["kernel"]);
+        let violations: Vec<_> = report
+            .abi
+            .violations
+            .iter()
+            .map(|v| (v.export.as_str(), v.signature.as_deref()))
+            .collect();
+        assert_eq!(
+            violations,
+            [("wrong", Some("(i32, i32) -> (i32)")), ("free_bytes", None)]
+        );
+        assert!(report.debug_indicators.is_empty());
+        assert!(report.glue.is_none());
+    }
+}
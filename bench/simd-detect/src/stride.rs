@@ -0,0 +1,300 @@
+//! Memory-access shape of v128 loads and stores.
+//!
+//! The `memarg` of every v128 memory op records its declared alignment and
+//! constant offset. Declared alignment is what the compiler could prove:
+//! full-width loads declared below 16 bytes mean the kernel never peels its
+//! head to an aligned boundary, which is what the alignment-peeling redesign
+//! would add. Offsets and pointer bumps approximate the access pattern, as a
+//! proxy for cache behavior:
+//!
+//! - `offset_strides`: loads with the same base at evenly spaced offsets,
+//!   i.e. an unrolled loop reading `stride` bytes apart.
+//! - `pointer_steps`: locals in a load base advanced by a constant
+//!   (`local.get p; i32.const n; i32.add; local.set p`), i.e. the per-
+//!   iteration stride of a pointer or index.
+//!
+//! A base is the local (`local.get p`) or sum of two locals (`local.get p;
+//! local.get i; i32.add`) computed just before the load. Only loads are
+//! attributed: stores and lane loads push their vector after the address,
+//! so they count towards alignment but not towards patterns.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use wasmparser::{MemArg, Operator};
+
+/// The operators a pointer bump is made of.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Recent {
+    LocalGet(u32),
+    I32Const(i32),
+    I32Add,
+    Other,
+}
+
+/// Loads from one base at evenly spaced offsets.
+#[derive(Debug, Clone, Serialize)]
+pub struct OffsetStride {
+    /// Locals summed to form the base.
+    pub locals: Vec<u32>,
+    /// Lowest offset, in bytes.
+    pub first_offset: u64,
+    /// Bytes between consecutive offsets.
+    pub stride: u64,
+    /// Distinct offsets in the run.
+    pub offsets: u32,
+}
+
+/// A load base advanced by constant steps.
+#[derive(Debug, Clone, Serialize)]
+pub struct PointerStep {
+    pub local: u32,
+    /// Distinct constants added to the local, in bytes.
+    pub steps: Vec<i32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct V128Access {
+    pub loads: u32,
+    pub stores: u32,
+    /// Accesses by declared alignment in bytes.
+    pub alignment: BTreeMap<u32, u32>,
+    /// Full-width `v128.load`/`v128.store` declared below 16 bytes.
+    pub underaligned_full_width: u32,
+    /// Every full-width access is under-aligned: the kernel would gain from
+    /// peeling to an aligned boundary.
+    pub peel_candidate: bool,
+    pub offset_strides: Vec<OffsetStride>,
+    pub pointer_steps: Vec<PointerStep>,
+    #[serde(skip)]
+    recent: [Option<Recent>; 3],
+    #[serde(skip)]
+    load_offsets: BTreeMap<Vec<u32>, BTreeSet<u64>>,
+    #[serde(skip)]
+    bumps: BTreeMap<u32, BTreeSet<i32>>,
+    #[serde(skip)]
+    full_width: u32,
+}
+
+/// `(memarg, is_load, full_width, addressed_first)` of a v128 memory op.
+fn classify(op: &Operator) -> Option<(MemArg, bool, bool, bool)> {
+    Some(match *op {
+        Operator::V128Load { memarg } => (memarg, true, true, true),
+        Operator::V128Store { memarg } => (memarg, false, true, false),
+        Operator::V128Load8x8S { memarg }
+        | Operator::V128Load8x8U { memarg }
+        | Operator::V128Load16x4S { memarg }
+        | Operator::V128Load16x4U { memarg }
+        | Operator::V128Load32x2S { memarg }
+        | Operator::V128Load32x2U { memarg }
+        | Operator::V128Load8Splat { memarg }
+        | Operator::V128Load16Splat { memarg }
+        | Operator::V128Load32Splat { memarg }
+        | Operator::V128Load64Splat { memarg }
+        | Operator::V128Load32Zero { memarg }
+        | Operator::V128Load64Zero { memarg } => (memarg, true, false, true),
+        Operator::V128Load8Lane { memarg, .. }
+        | Operator::V128Load16Lane { memarg, .. }
+        | Operator::V128Load32Lane { memarg, .. }
+        | Operator::V128Load64Lane { memarg, .. } => (memarg, true, false, false),
+        Operator::V128Store8Lane { memarg, .. }
+        | Operator::V128Store16Lane { memarg, .. }
+        | Operator::V128Store32Lane { memarg, .. }
+        | Operator::V128Store64Lane { memarg, .. } => (memarg, false, false, false),
+        _ => return None,
+    })
+}
+
+impl V128Access {
+    pub fn count(&mut self, op: &Operator) {
+        if let Some((memarg, load, full_width, addressed_first)) = classify(op) {
+            if load {
+                self.loads += 1;
+            } else {
+                self.stores += 1;
+            }
+            let align = 1u32 << memarg.align;
+            *self.alignment.entry(align).or_insert(0) += 1;
+            if full_width {
+                self.full_width += 1;
+                if align < 16 {
+                    self.underaligned_full_width += 1;
+                }
+            }
+            let base = match self.recent {
+                [_, _, Some(Recent::LocalGet(p))] => Some(vec![p]),
+                [Some(Recent::LocalGet(p)), Some(Recent::LocalGet(i)), Some(Recent::I32Add)] => {
+                    Some(vec![p, i])
+                }
+                _ => None,
+            };
+            if let (true, Some(base)) = (addressed_first, base) {
+                self.load_offsets
+                    .entry(base)
+                    .or_default()
+                    .insert(memarg.offset);
+            }
+        }
+
+        if let Operator::LocalSet { local_index } | Operator::LocalTee { local_index } = *op {
+            if let [Some(Recent::LocalGet(base)), Some(Recent::I32Const(step)), Some(Recent::I32Add)] =
+                self.recent
+            {
+                if base == local_index {
+                    self.bumps.entry(base).or_default().insert(step);
+                }
+            }
+        }
+
+        let recent = match *op {
+            Operator::LocalGet { local_index } => Recent::LocalGet(local_index),
+            Operator::I32Const { value } => Recent::I32Const(value),
+            Operator::I32Add => Recent::I32Add,
+            _ => Recent::Other,
+        };
+        self.recent = [self.recent[1], self.recent[2], Some(recent)];
+    }
+
+    pub fn accesses(&self) -> u32 {
+        self.loads + self.stores
+    }
+
+    pub fn finish(&mut self) {
+        self.peel_candidate =
+            self.full_width > 0 && self.underaligned_full_width == self.full_width;
+        for (locals, offsets) in &self.load_offsets {
+            let offsets: Vec<u64> = offsets.iter().copied().collect();
+            if offsets.len() < 2 {
+                continue;
+            }
+            let stride = offsets[1] - offsets[0];
+            if offsets.windows(2).all(|w| w[1] - w[0] == stride) {
+                self.offset_strides.push(OffsetStride {
+                    locals: locals.clone(),
+                    first_offset: offsets[0],
+                    stride,
+                    offsets: offsets.len() as u32,
+                });
+            }
+        }
+        for (&local, steps) in &self.bumps {
+            if self.load_offsets.keys().any(|base| base.contains(&local)) {
+                self.pointer_steps.push(PointerStep {
+                    local,
+                    steps: steps.iter().copied().collect(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{self, Module, DROP, I32, I32_ADD};
+    use wasmparser::{Parser, Payload};
+
+    /// The v128 access report of each function in `module`.
+    fn analyze(module: &Module) -> Vec<V128Access> {
+        let wasm = module.build();
+        Parser::new(0)
+            .parse_all(&wasm)
+            .filter_map(|payload| match payload.unwrap() {
+                Payload::CodeSectionEntry(body) => {
+                    Some(crate::analyze_function(0, &body).unwrap().4)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// `local.get p; [local.get i; i32.add;] v128.load; drop`
+    fn load(base: &[u32], align: u32, offset: u64) -> Vec<u8> {
+        let mut code = fixture::local_get(base[0]);
+        if let Some(&i) = base.get(1) {
+            code.extend(fixture::local_get(i));
+            code.push(I32_ADD);
+        }
+        code.extend(fixture::v128_load(align, offset));
+        code.push(DROP);
+        code
+    }
+
+    /// `local.get l; i32.const step; i32.add; local.set l`
+    fn bump(local: u32, step: i32) -> Vec<u8> {
+        let mut code = fixture::local_get(local);
+        code.extend(fixture::i32_const(step));
+        code.push(I32_ADD);
+        code.extend(fixture::local_set(local));
+        code
+    }
+
+    #[test]
+    fn reports_alignment_offsets_and_steps() {
+        // An aligned head, a 4x unrolled body at 16-byte offsets from `p`,
+        // two 8-byte-apart loads from `p + i`, a store, and both bumps.
+        let mut code = load(&[0], 16, 0);
+        for offset in [16, 32, 48] {
+            code.extend(load(&[0], 1, offset));
+        }
+        code.extend(load(&[0, 1], 4, 0));
+        code.extend(load(&[0, 1], 4, 8));
+        code.extend(fixture::local_get(0));
+        code.extend(fixture::local_get(0));
+        code.extend(fixture::v128_load(16, 0));
+        code.extend(fixture::v128_store(8, 0));
+        code.extend(bump(0, 64));
+        code.extend(bump(1, 16));
+        let mut module = Module::default();
+        module.func(&[I32, I32], &[], 0, &code);
+
+        let access = analyze(&module).remove(0);
+        assert_eq!((access.loads, access.stores), (7, 1));
+        assert_eq!(
+            access.alignment,
+            BTreeMap::from([(1, 3), (4, 2), (8, 1), (16, 2)])
+        );
+        assert_eq!(access.underaligned_full_width, 6);
+        assert!(!access.peel_candidate);
+
+        let strides: Vec<_> = access
+            .offset_strides
+            .iter()
+            .map(|s| (s.locals.clone(), s.first_offset, s.stride, s.offsets))
+            .collect();
+        assert_eq!(strides, [(vec![0], 0, 16, 4), (vec![0, 1], 0, 8, 2)]);
+        let steps: Vec<_> = access
+            .pointer_steps
+            .iter()
+            .map(|p| (p.local, p.steps.clone()))
+            .collect();
+        assert_eq!(steps, [(0, vec![64]), (1, vec![16])]);
+    }
+
+    #[test]
+    fn uneven_offsets_and_unrelated_bumps_are_not_patterns() {
+        let mut code = Vec::new();
+        for offset in [0, 16, 48] {
+            code.extend(load(&[0], 8, offset));
+        }
+        // Local 2 is never a load base; local 0 is bumped by a variable.
+        code.extend(bump(2, 4));
+        code.extend(fixture::local_get(0));
+        code.extend(fixture::local_get(1));
+        code.push(I32_ADD);
+        code.extend(fixture::local_set(0));
+        let mut module = Module::default();
+        module.func(&[I32, I32], &[], 1, &code);
+        module.func(&[I32], &[], 0, &[]);
+
+        let mut reports = analyze(&module);
+        let access = reports.remove(0);
+        assert_eq!(access.alignment, BTreeMap::from([(8, 3)]));
+        assert!(access.peel_candidate, "every full-width load under 16");
+        assert!(access.offset_strides.is_empty());
+        assert!(access.pointer_steps.is_empty());
+
+        let empty = reports.remove(0);
+        assert_eq!(empty.accesses(), 0);
+        assert!(!empty.peel_candidate, "nothing to peel");
+    }
+}
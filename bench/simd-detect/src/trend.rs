@@ -0,0 +1,305 @@
+//! Historical trend database.
+//!
+//! With `--db trends.jsonl` every run appends one JSON line of keyed
+//! metrics (variant, `--label` such as a git sha, module size, SIMD counts
+//! and per-function SIMD ops). `simd-detect trend trends.jsonl` prints that
+//! history as sparklines, so a regression shows up as a step in a trend
+//! rather than as one noisy number, and lists the metrics whose latest run
+//! fell below the run before it.
+
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TrendRecord {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub label: Option<String>,
+    pub variant: String,
+    pub wasm_hash: String,
+    pub wasm_size: usize,
+    pub total_ops: u32,
+    pub total_simd_ops: u32,
+    pub simd_density: f64,
+    pub functions: Vec<FunctionMetrics>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct FunctionMetrics {
+    pub name: String,
+    pub simd_ops: u32,
+    pub total_ops: u32,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Append `record` to the JSONL database at `path`, creating it if needed.
+pub fn append(path: &Path, record: &TrendRecord) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+}
+
+/// Read every record from the database, in the order they were appended.
+pub fn load(path: &Path) -> Result<Vec<TrendRecord>, Box<dyn std::error::Error>> {
+    let mut records = Vec::new();
+    for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record =
+            serde_json::from_str(line).map_err(|e| format!("{}:{}: {e}", path.display(), i + 1))?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// A metric that dropped between the last two runs of a variant.
+#[derive(Debug, PartialEq)]
+pub struct Regression {
+    pub variant: String,
+    /// `SIMD ops`, `SIMD density`, or the name of a function whose SIMD ops
+    /// dropped.
+    pub metric: String,
+    pub before: f64,
+    pub after: f64,
+}
+
+/// Variants in the order they first appear.
+fn variants(records: &[TrendRecord]) -> Vec<&str> {
+    let mut variants: Vec<&str> = Vec::new();
+    for record in records {
+        if !variants.contains(&record.variant.as_str()) {
+            variants.push(&record.variant);
+        }
+    }
+    variants
+}
+
+/// Compare each variant's latest run with the one before it: total SIMD
+/// ops, SIMD density, and the SIMD ops of every function in both runs.
+pub fn regressions(records: &[TrendRecord]) -> Vec<Regression> {
+    let mut found = Vec::new();
+    for variant in variants(records) {
+        let mut runs = records.iter().rev().filter(|r| r.variant == variant);
+        let (Some(after), Some(before)) = (runs.next(), runs.next()) else {
+            continue;
+        };
+        let mut check = |metric: &str, before: f64, after: f64| {
+            if after < before {
+                found.push(Regression {
+                    variant: variant.to_string(),
+                    metric: metric.to_string(),
+                    before,
+                    after,
+                });
+            }
+        };
+        check(
+            "SIMD ops",
+            before.total_simd_ops as f64,
+            after.total_simd_ops as f64,
+        );
+        check("SIMD density", before.simd_density, after.simd_density);
+        for f in &after.functions {
+            if let Some(old) = before.functions.iter().find(|g| g.name == f.name) {
+                check(&f.name, old.simd_ops as f64, f.simd_ops as f64);
+            }
+        }
+    }
+    found
+}
+
+/// One bar per value, scaled between the series' minimum and maximum.
+fn sparkline(values: &[f64]) -> String {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .map(|&v| {
+            if max > min {
+                BARS[((v - min) / (max - min) * (BARS.len() - 1) as f64).round() as usize]
+            } else {
+                BARS[BARS.len() / 2]
+            }
+        })
+        .collect()
+}
+
+fn line(label: &str, values: &[f64], format: impl Fn(f64) -> String) {
+    let (first, last) = (values[0], values[values.len() - 1]);
+    eprintln!(
+        "  {label:<36} {}  {} -> {}",
+        sparkline(values),
+        format(first),
+        format(last)
+    );
+}
+
+/// Print the history of each variant (or just `variant`), with the `limit`
+/// functions that have the most SIMD ops in the latest run, optionally only
+/// those whose name contains `function`.
+pub fn print(records: &[TrendRecord], variant: Option<&str>, function: Option<&str>, limit: usize) {
+    let regressions = regressions(records);
+    for name in variants(records) {
+        if variant.is_some_and(|v| v != name) {
+            continue;
+        }
+        let runs: Vec<&TrendRecord> = records.iter().filter(|r| r.variant == name).collect();
+        let label = |r: &TrendRecord| r.label.clone().unwrap_or_else(|| r.wasm_hash.clone());
+        eprintln!(
+            "\n{name}: {} runs, {} .. {}",
+            runs.len(),
+            label(runs[0]),
+            label(runs[runs.len() - 1])
+        );
+        let series =
+            |f: &dyn Fn(&TrendRecord) -> f64| runs.iter().map(|r| f(r)).collect::<Vec<_>>();
+        line("SIMD density", &series(&|r| r.simd_density), |v| {
+            format!("{:.2}%", v * 100.0)
+        });
+        line("SIMD ops", &series(&|r| r.total_simd_ops as f64), |v| {
+            format!("{v}")
+        });
+        line("Total ops", &series(&|r| r.total_ops as f64), |v| {
+            format!("{v}")
+        });
+        line("Size (bytes)", &series(&|r| r.wasm_size as f64), |v| {
+            format!("{v}")
+        });
+
+        let latest = runs[runs.len() - 1];
+        let mut functions: Vec<&FunctionMetrics> = latest
+            .functions
+            .iter()
+            .filter(|f| function.is_none_or(|pattern| f.name.contains(pattern)))
+            .collect();
+        functions.sort_by_key(|f| std::cmp::Reverse(f.simd_ops));
+        if !functions.is_empty() {
+            eprintln!("  Functions (SIMD ops):");
+        }
+        for f in functions.into_iter().take(limit) {
+            let history: Vec<f64> = runs
+                .iter()
+                .map(|r| {
+                    r.functions
+                        .iter()
+                        .find(|g| g.name == f.name)
+                        .map_or(0.0, |g| g.simd_ops as f64)
+                })
+                .collect();
+            let short: String = f.name.chars().take(34).collect();
+            line(&format!("  {short}"), &history, |v| format!("{v}"));
+        }
+
+        let dropped: Vec<&Regression> = regressions.iter().filter(|r| r.variant == name).collect();
+        if !dropped.is_empty() {
+            eprintln!("  Regressed since {}:", label(runs[runs.len() - 2]));
+            for r in dropped {
+                eprintln!("    {:<36} {} -> {}", r.metric, r.before, r.after);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+
+    fn record(variant: &str, label: &str, functions: &[(&str, u32)]) -> TrendRecord {
+        let total_simd_ops = functions.iter().map(|(_, ops)| ops).sum();
+        TrendRecord {
+            timestamp: 1_700_000_000,
+            label: Some(label.to_string()),
+            variant: variant.to_string(),
+            wasm_hash: format!("{label}-hash"),
+            wasm_size: 1000,
+            total_ops: 1000,
+            total_simd_ops,
+            simd_density: total_simd_ops as f64 / 1000.0,
+            functions: functions
+                .iter()
+                .map(|&(name, simd_ops)| FunctionMetrics {
+                    name: name.to_string(),
+                    simd_ops,
+                    total_ops: 100,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn append_then_load_keeps_order() {
+        let path = fixture::temp_path("trends.jsonl");
+        let runs = [
+            record("simd", "a1", &[("sum", 10)]),
+            record("base", "a1", &[]),
+            record("simd", "b2", &[("sum", 12)]),
+        ];
+        for run in &runs {
+            append(&path, run).unwrap();
+        }
+        let loaded = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, runs);
+    }
+
+    #[test]
+    fn load_skips_blank_lines_and_names_bad_ones() {
+        let path = fixture::temp_path("bad.jsonl");
+        let good = serde_json::to_string(&record("simd", "a1", &[])).unwrap();
+        fs::write(&path, format!("{good}\n\n{{\"variant\": 1}}\n")).unwrap();
+        let err = load(&path).unwrap_err().to_string();
+        assert!(err.starts_with(&format!("{}:3: ", path.display())), "{err}");
+
+        fs::write(&path, format!("{good}\n\n{good}\n")).unwrap();
+        assert_eq!(load(&path).unwrap().len(), 2);
+        fs::remove_file(&path).unwrap();
+        assert!(load(&path).is_err());
+    }
+
+    #[test]
+    fn regressions_compare_the_last_two_runs_per_variant() {
+        let path = fixture::temp_path("regressions.jsonl");
+        let runs = [
+            record("simd", "a1", &[("sum", 10), ("split", 8)]),
+            record("base", "a1", &[]),
+            // An older drop that was already recovered is not reported.
+            record("simd", "b2", &[("sum", 4), ("split", 8)]),
+            record("simd", "c3", &[("sum", 10), ("split", 8)]),
+            record("simd", "d4", &[("sum", 10), ("split", 5), ("new", 1)]),
+            record("base", "d4", &[]),
+        ];
+        for run in &runs {
+            append(&path, run).unwrap();
+        }
+        let records = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let found: Vec<_> = regressions(&records)
+            .into_iter()
+            .map(|r| (r.variant, r.metric, r.before, r.after))
+            .collect();
+        let simd =
+            |metric: &str, before, after| ("simd".to_string(), metric.to_string(), before, after);
+        assert_eq!(
+            found,
+            [
+                simd("SIMD ops", 18.0, 16.0),
+                simd("SIMD density", 0.018, 0.016),
+                simd("split", 8.0, 5.0),
+            ]
+        );
+        assert!(regressions(&records[..2]).is_empty(), "one run per variant");
+    }
+}
@@ -0,0 +1,44 @@
+import { init, process, memoryU8, alloc } from '../wasm-dist/browser.js'
+
+const statusEl = document.querySelector('#status')
+
+function wasmFetches() {
+  return performance
+    .getEntriesByType('resource')
+    .filter((entry) => entry.name.endsWith('.wasm')).length
+}
+
+// Warmup leaves a marker block in the heap. After a reload the snapshot
+// must restore both the block and the allocator state around it, so the
+// next allocation does not hand the same block out again.
+async function run() {
+  let warmups = 0
+  await init(
+    {},
+    {
+      cache: true,
+      snapshot: true,
+      warmup: (exports) => {
+        warmups++
+        const ptr = exports.alloc_bytes(16)
+        new Uint8Array(exports.memory.buffer).fill(42, ptr, ptr + 16)
+        localStorage.setItem('marker', String(ptr))
+      },
+    }
+  )
+  const marker = Number(localStorage.getItem('marker'))
+  const fresh = alloc(16)
+  const output = await process(new Uint8Array([1, 2, 3]))
+  statusEl.textContent = JSON.stringify({
+    fetches: wasmFetches(),
+    warmups,
+    restored: memoryU8()[marker] === 42,
+    reused: fresh === marker,
+    output: [...output],
+  })
+}
+
+run().catch((err) => {
+  console.error(err)
+  statusEl.textContent = `Error: ${err.message}`
+})
@@ -0,0 +1,29 @@
+import { test, expect } from '@playwright/test'
+
+async function readStatus(page) {
+  const status = page.locator('#status')
+  await expect(status).toContainText('"output"')
+  return JSON.parse(await status.textContent())
+}
+
+test('cached module and memory snapshot survive a reload', async ({ page }) => {
+  await page.goto('/cache.html')
+  expect(await readStatus(page)).toEqual({
+    fetches: 1,
+    warmups: 1,
+    restored: true,
+    reused: false,
+    output: [2, 4, 6],
+  })
+
+  // The bytes and the snapshot now come from IndexedDB: no wasm request
+  // and no warmup, with the warmed-up heap restored.
+  await page.reload()
+  expect(await readStatus(page)).toEqual({
+    fetches: 0,
+    warmups: 0,
+    restored: true,
+    reused: false,
+    output: [2, 4, 6],
+  })
+})
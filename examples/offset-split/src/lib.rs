@@ -1,7 +1,10 @@
-#![cfg(target_arch = "wasm32")]
-
 use std::alloc::{alloc, dealloc, Layout};
 use std::mem;
+use std::ptr;
+
+#[allow(dead_code)]
+#[path = "../../../src/ffi/view.rs"]
+mod ffi;
 
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
@@ -10,13 +13,121 @@ pub unsafe extern "C" fn alloc_bytes(len: usize) -> *mut u8 {
     alloc(layout)
 }
 
+/// Also releases the output of `find_line_offsets_alloc`. Zero-length
+/// blocks were never allocated and are ignored.
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn free_bytes(ptr: *mut u8, len: usize) {
+    if len == 0 {
+        return;
+    }
     let layout = Layout::from_size_align(len, mem::align_of::<u8>()).unwrap();
     dealloc(ptr, layout);
 }
 
+/// Scalar offset scan for builds without simd128 that stops once
+/// `max_offsets` is reached, returning the number of offsets written.
+#[cfg(not(target_feature = "simd128"))]
+#[inline]
+unsafe fn scan_scalar(input: &[u8], out_ptr: *mut u32, max_offsets: usize) -> usize {
+    let mut i = 0;
+    let mut count = 0;
+    while i < input.len() && count < max_offsets {
+        let b = input[i];
+        if b == b'\n' {
+            *out_ptr.add(count) = i as u32;
+            count += 1;
+        } else if b == b'\r' {
+            if i + 1 < input.len() && input[i + 1] == b'\n' {
+                *out_ptr.add(count) = i as u32;
+                count += 1;
+                i += 1; // skip \n
+            } else {
+                *out_ptr.add(count) = i as u32;
+                count += 1;
+            }
+        }
+        i += 1;
+    }
+    count
+}
+
+/// Bytes examined per outer iteration of the SIMD loop: the loop first ORs
+/// the newline masks of `STRIDE / 16` vectors and skips the whole stride
+/// when none match. Override at build time with `WBL_KERNEL_STRIDE`
+/// (16, 32, 64 or 128) to compare granularities in the bench matrix.
+#[cfg(target_feature = "simd128")]
+const STRIDE: usize = match option_env!("WBL_KERNEL_STRIDE") {
+    Some(s) => parse_stride(s),
+    None => 32,
+};
+
+#[cfg(target_feature = "simd128")]
+const fn parse_stride(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut n = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        assert!(
+            bytes[i].is_ascii_digit(),
+            "WBL_KERNEL_STRIDE must be a number"
+        );
+        n = n * 10 + (bytes[i] - b'0') as usize;
+        i += 1;
+    }
+    assert!(
+        matches!(n, 16 | 32 | 64 | 128),
+        "WBL_KERNEL_STRIDE must be 16, 32, 64 or 128"
+    );
+    n
+}
+
+/// Whether any of the `STRIDE` bytes at `ptr` is a CR or LF.
+#[cfg(target_feature = "simd128")]
+#[inline(always)]
+unsafe fn stride_has_newline(ptr: *const u8) -> bool {
+    use core::arch::wasm32::*;
+    let n_splat = i8x16_splat(b'\n' as i8);
+    let r_splat = i8x16_splat(b'\r' as i8);
+    let mut any = i8x16_splat(0);
+    let mut k = 0;
+    while k < STRIDE {
+        let v = v128_load(ptr.add(k) as *const v128);
+        any = v128_or(any, v128_or(i8x16_eq(v, n_splat), i8x16_eq(v, r_splat)));
+        k += 16;
+    }
+    v128_any_true(any)
+}
+
+/// Emit the break offsets of the 16-byte block at `block`, which starts at
+/// input offset `i`, returning the updated count and the CR carry for the
+/// next block.
+#[cfg(target_feature = "simd128")]
+#[inline(always)]
+unsafe fn scan_block(
+    block: *const u8,
+    i: usize,
+    carry_cr: u32,
+    out_ptr: *mut u32,
+    mut count: usize,
+    max_offsets: usize,
+) -> (usize, u32) {
+    use core::arch::wasm32::*;
+    let v = v128_load(block as *const v128);
+    let n_bits = i8x16_bitmask(i8x16_eq(v, i8x16_splat(b'\n' as i8))) as u32;
+    let r_bits = i8x16_bitmask(i8x16_eq(v, i8x16_splat(b'\r' as i8))) as u32;
+
+    // Every CR starts a break; an LF only does when not preceded by CR.
+    let mut breaks = r_bits | (n_bits & !((r_bits << 1) | carry_cr));
+    while breaks != 0 && count < max_offsets {
+        *out_ptr.add(count) = (i + breaks.trailing_zeros() as usize) as u32;
+        count += 1;
+        breaks &= breaks - 1;
+    }
+
+    (count, r_bits >> 15)
+}
+
 /// Find line break offsets and write them as u32s to out_ptr.
 /// Returns number of bytes written to out_ptr (count * 4).
 #[no_mangle]
@@ -27,66 +138,145 @@ pub unsafe extern "C" fn find_line_offsets(
     out_ptr: *mut u32,
     out_len_bytes: usize,
 ) -> isize {
-    let input = std::slice::from_raw_parts(in_ptr, in_len);
+    let input = ffi::slice(in_ptr, in_len);
     let max_offsets = out_len_bytes / 4;
-    let mut count = 0;
-    let mut i = 0;
+    ffi::check_range(out_ptr, max_offsets);
 
     #[cfg(target_feature = "simd128")]
-    {
-        use core::arch::wasm32::*;
-        let n_splat = i8x16_splat(b'\n' as i8);
-        let r_splat = i8x16_splat(b'\r' as i8);
+    let count = {
+        let mut count = 0;
+        let mut i = 0;
 
-        while i + 16 <= in_len && count < max_offsets {
-            let v = v128_load(in_ptr.add(i) as *const v128);
-            let mask = i8x16_bitmask(v128_or(i8x16_eq(v, n_splat), i8x16_eq(v, r_splat)));
+        // Lane 15 of the previous block was a CR, so a leading LF in the
+        // current block belongs to a CRLF pair that already has its offset.
+        let mut carry_cr = 0u32;
 
-            if mask == 0 {
+        while i + STRIDE <= in_len && count < max_offsets {
+            if !stride_has_newline(in_ptr.add(i)) {
+                carry_cr = 0;
+                i += STRIDE;
+                continue;
+            }
+            let end = i + STRIDE;
+            while i < end && count < max_offsets {
+                (count, carry_cr) =
+                    scan_block(in_ptr.add(i), i, carry_cr, out_ptr, count, max_offsets);
                 i += 16;
-            } else {
-                // Process these 16 bytes
-                for _ in 0..16 {
-                    if i >= in_len || count >= max_offsets {
-                        break;
-                    }
-                    let b = input[i];
-                    if b == b'\n' {
-                        *out_ptr.add(count) = i as u32;
-                        count += 1;
-                    } else if b == b'\r' {
-                        if i + 1 < in_len && input[i + 1] == b'\n' {
-                            *out_ptr.add(count) = i as u32;
-                            count += 1;
-                            i += 1; // skip \n
-                        } else {
-                            *out_ptr.add(count) = i as u32;
-                            count += 1;
-                        }
-                    }
-                    i += 1;
-                }
             }
         }
+
+        while i + 16 <= in_len && count < max_offsets {
+            (count, carry_cr) = scan_block(in_ptr.add(i), i, carry_cr, out_ptr, count, max_offsets);
+            i += 16;
+        }
+
+        // Masked tail: run the final partial block through `scan_block` on
+        // a zero-padded copy. Padding is never CR or LF, so it adds no
+        // offsets.
+        let rem = in_len - i;
+        if rem > 0 && count < max_offsets {
+            let mut tail = [0u8; 16];
+            tail[..rem].copy_from_slice(&input[i..]);
+            (count, _) = scan_block(tail.as_ptr(), i, carry_cr, out_ptr, count, max_offsets);
+        }
+        count
+    };
+
+    #[cfg(not(target_feature = "simd128"))]
+    let count = scan_scalar(input, out_ptr, max_offsets);
+
+    (count * 4) as isize
+}
+
+/// Number of offsets `find_line_offsets` reports for `input`: every CR, and
+/// every LF not preceded by a CR.
+fn count_line_offsets(input: &[u8]) -> usize {
+    let lf_after_cr = input.windows(2).filter(|w| w == b"\r\n").count();
+    input.iter().filter(|&&b| b == b'\n' || b == b'\r').count() - lf_after_cr
+}
+
+/// Runs `find_line_offsets` into an exactly-sized buffer from `alloc_bytes`,
+/// returning it with its byte length, or `None` when the allocation fails.
+/// Without line breaks the length is `0` and the pointer dangling.
+unsafe fn line_offsets_alloc(in_ptr: *const u8, in_len: usize) -> Option<(*mut u32, usize)> {
+    let len = count_line_offsets(ffi::slice(in_ptr, in_len)) * 4;
+    if len == 0 {
+        return Some((ptr::NonNull::dangling().as_ptr(), 0));
+    }
+    let out_ptr = alloc_bytes(len) as *mut u32;
+    if out_ptr.is_null() {
+        return None;
     }
+    let written = find_line_offsets(in_ptr, in_len, out_ptr, len);
+    debug_assert_eq!(written as usize, len);
+    Some((out_ptr, len))
+}
 
-    while i < in_len && count < max_offsets {
-        let b = input[i];
-        if b == b'\n' {
-            *out_ptr.add(count) = i as u32;
-            count += 1;
-        } else if b == b'\r' {
-            if i + 1 < in_len && input[i + 1] == b'\n' {
-                *out_ptr.add(count) = i as u32;
-                count += 1;
-                i += 1;
-            } else {
-                *out_ptr.add(count) = i as u32;
-                count += 1;
-            }
+/// Callee-allocated form of `find_line_offsets`: the kernel sizes and
+/// allocates its own output, so the caller never has to guess a capacity.
+///
+/// Returns the buffer packed into one `u64`, pointer in the low 32 bits and
+/// byte length in the high 32. The caller owns it and must release it with
+/// `free_bytes(ptr, len)`. Returns `0` when the allocation fails. Input
+/// without line breaks gives length `0` and a dangling pointer, which
+/// `free_bytes` ignores.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn find_line_offsets_alloc(in_ptr: *const u8, in_len: usize) -> u64 {
+    match line_offsets_alloc(in_ptr, in_len) {
+        Some((ptr, len)) => ptr as usize as u64 & 0xffff_ffff | (len as u64) << 32,
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offsets(input: &[u8], cap: usize) -> Vec<u32> {
+        let mut out = vec![u32::MAX; cap];
+        let written =
+            unsafe { find_line_offsets(input.as_ptr(), input.len(), out.as_mut_ptr(), cap * 4) };
+        assert_eq!(written % 4, 0);
+        out.truncate(written as usize / 4);
+        out
+    }
+
+    #[test]
+    fn finds_every_line_ending() {
+        assert_eq!(offsets(b"line1\nline2\r\nline3\rlast", 8), [5, 11, 18]);
+        assert_eq!(offsets(b"\r\n\n\r", 8), [0, 2, 3]);
+        assert_eq!(offsets(b"", 8), []);
+    }
+
+    #[test]
+    fn stops_at_output_capacity() {
+        assert_eq!(offsets(b"a\nb\nc\nd\n", 2), [1, 3]);
+        assert_eq!(offsets(b"a\nb\n", 0), []);
+    }
+
+    #[test]
+    fn callee_allocated_offsets_are_exactly_sized() {
+        for input in [
+            &b"line1\nline2\r\nline3\rlast"[..],
+            b"\r\n\n\r\r",
+            b"",
+            b"none",
+        ] {
+            let (ptr, len) = unsafe { line_offsets_alloc(input.as_ptr(), input.len()) }.unwrap();
+            let out = unsafe { std::slice::from_raw_parts(ptr, len / 4) };
+            assert_eq!(out, offsets(input, 16));
+            unsafe { free_bytes(ptr as *mut u8, len) };
         }
-        i += 1;
     }
 
-    (count * 4) as isize
+    #[test]
+    fn crlf_straddling_blocks_yields_one_offset() {
+        for at in [15, 31, 47, 63] {
+            let mut input = vec![b'x'; 80];
+            input[at] = b'\r';
+            input[at + 1] = b'\n';
+            assert_eq!(offsets(&input, 4), [at as u32], "CRLF at {at}");
+        }
+    }
 }
@@ -1,5 +1,120 @@
 import assert from 'node:assert'
+import { readFileSync } from 'node:fs'
+import { fileURLToPath } from 'node:url'
 import { init, getLines } from './dist/node.js'
+import {
+  callKernel,
+  instantiateVariants,
+  seededBytes,
+} from '../../scripts/kernel-variants.js'
+
+const CR = 13
+const LF = 10
+
+/** Scalar reference: every CR, and every LF not preceded by a CR. */
+function offsetsReference(input) {
+  const offsets = []
+  for (let i = 0; i < input.length; i++) {
+    if (input[i] === CR || (input[i] === LF && input[i - 1] !== CR)) {
+      offsets.push(i)
+    }
+  }
+  return offsets
+}
+
+/**
+ * Inputs with a lone CR, a lone LF or a CRLF pair at every position next to
+ * a 16-byte block boundary, over lengths ending one block short of, at and
+ * just past one and two of each configured stride.
+ */
+function* boundaryInputs(strides) {
+  const lengths = new Set([0, 1, 15, 16, 17, 31, 32, 33])
+  for (const stride of strides) {
+    for (const end of [stride, 2 * stride]) {
+      for (const delta of [-16, -1, 0, 1, 15]) lengths.add(end + delta)
+    }
+  }
+  for (const len of lengths) {
+    yield new Uint8Array(len).fill(0x78)
+    for (let at = 0; at < len; at++) {
+      if (![14, 15, 0, 1].includes(at % 16)) continue
+      for (const marks of [[CR], [LF], [CR, LF]]) {
+        const input = new Uint8Array(len).fill(0x78)
+        input.set(marks.slice(0, len - at), at)
+        yield input
+      }
+    }
+  }
+}
+
+/**
+ * Blocks where most lanes are line breaks, so the bitmask loop emits many
+ * breaks per block: runs of one pattern repeated across several blocks,
+ * offset by each lane of the first block.
+ */
+function* denseInputs() {
+  const patterns = [[CR], [LF], [CR, LF], [LF, CR], [CR, CR, LF], [0x61, LF]]
+  for (const pattern of patterns) {
+    for (let shift = 0; shift < 16; shift++) {
+      const input = new Uint8Array(shift + 64 + 7).fill(0x78)
+      for (let i = shift; i < input.length; i++) {
+        input[i] = pattern[(i - shift) % pattern.length]
+      }
+      yield input
+    }
+  }
+}
+
+function* randomInputs() {
+  const next = seededBytes(0x0ff5e7, [0x61, 0x62, 0x63, 0x64, CR, LF])
+  for (let len = 0; len <= 300; len++) yield next(len)
+}
+
+/** `find_line_offsets` of every bench variant against the reference. */
+async function testVariants() {
+  const dir = fileURLToPath(new URL('.', import.meta.url))
+  const config = JSON.parse(
+    readFileSync(new URL('wasm-bindgen-lite.config.json', import.meta.url))
+  )
+  const variants = await instantiateVariants(dir)
+  const inputs = [
+    ...boundaryInputs(config.bench.strides),
+    ...denseInputs(),
+    ...randomInputs(),
+  ]
+
+  for (const { name, exports } of variants) {
+    for (const input of inputs) {
+      const expected = offsetsReference(input)
+      const { written, out } = callKernel(
+        exports,
+        'find_line_offsets',
+        input,
+        expected.length * 4
+      )
+      assert.strictEqual(written, expected.length * 4, `${name}: ${input}`)
+      const offsets = Array.from(new Uint32Array(out.buffer))
+      assert.deepStrictEqual(offsets, expected, `${name}: ${input}`)
+    }
+
+    // A full output stops the bitmask loop partway through a block.
+    for (const input of denseInputs()) {
+      const expected = offsetsReference(input)
+      for (const cap of [1, 7, 16, 17, expected.length - 1]) {
+        const { written, out } = callKernel(
+          exports,
+          'find_line_offsets',
+          input,
+          cap * 4
+        )
+        const offsets = Array.from(new Uint32Array(out.buffer))
+        assert.strictEqual(written, cap * 4, `${name}: cap ${cap}: ${input}`)
+        assert.deepStrictEqual(offsets, expected.slice(0, cap))
+      }
+    }
+    console.log(`✓ ${name}: ${inputs.length} inputs match the reference`)
+  }
+}
 
 async function main() {
   await init()
@@ -13,6 +128,8 @@ async function main() {
   console.log('Result:', lines)
 
   assert.deepStrictEqual(lines, ['line1', 'line2', 'line3', 'last'])
+
+  await testVariants()
   console.log('✓ offset-split test passed')
 }
 
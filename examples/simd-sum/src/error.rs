@@ -0,0 +1,70 @@
+//! Structured error codes behind the `-1` sentinel.
+//!
+//! The subset of the runtime crate's `ErrorCode` this example can report,
+//! with the same values, so the generated loader's `WasmError` reads it the
+//! same way. A code is only meaningful right after a call returned `-1`.
+
+use std::cell::Cell;
+
+use crate::ffi;
+
+/// Why the last failing call failed.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// Nothing recorded.
+    None = 0,
+    /// The input is not a whole number of elements.
+    BadLength = 3,
+    /// The output buffer is smaller than the result.
+    ShortOutput = 4,
+}
+
+impl ErrorCode {
+    fn message(self) -> &'static str {
+        match self {
+            ErrorCode::None => "no error recorded",
+            ErrorCode::BadLength => "input length is invalid",
+            ErrorCode::ShortOutput => "output buffer is too small",
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: Cell<ErrorCode> = const { Cell::new(ErrorCode::None) };
+}
+
+/// Record `code` and return the `-1` failure sentinel.
+pub fn fail(code: ErrorCode) -> isize {
+    LAST_ERROR.set(code);
+    -1
+}
+
+/// Code of the last recorded failure, or `0` after `last_error_clear`.
+#[no_mangle]
+pub extern "C" fn last_error_code() -> u32 {
+    LAST_ERROR.get() as u32
+}
+
+/// Write a short description of `last_error_code` as UTF-8. With
+/// `out_len == 0` returns the number of bytes needed; otherwise bytes
+/// written, or `-1` for a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn last_error_message(out_ptr: *mut u8, out_len: usize) -> isize {
+    let message = LAST_ERROR.get().message().as_bytes();
+    if out_len == 0 {
+        return message.len() as isize;
+    }
+    if out_len < message.len() {
+        return -1;
+    }
+    ffi::slice_mut(out_ptr, message.len()).copy_from_slice(message);
+    message.len() as isize
+}
+
+/// Forget the recorded failure.
+#[no_mangle]
+pub extern "C" fn last_error_clear() {
+    LAST_ERROR.set(ErrorCode::None);
+}
@@ -1,8 +1,13 @@
-#![cfg(target_arch = "wasm32")]
-
 use std::alloc::{alloc, dealloc, Layout};
 use std::mem;
 
+mod error;
+#[allow(dead_code)]
+#[path = "../../../src/ffi/view.rs"]
+mod ffi;
+
+use error::ErrorCode;
+
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn alloc_bytes(len: usize) -> *mut u8 {
@@ -17,6 +22,16 @@ pub unsafe extern "C" fn free_bytes(ptr: *mut u8, len: usize) {
     dealloc(ptr, layout);
 }
 
+/// Load a partial block (< 16 bytes) as a vector, zero-padding the missing
+/// lanes so it can be fed through the same accumulator as full chunks.
+#[cfg(target_feature = "simd128")]
+#[inline]
+unsafe fn load_tail(rem: &[u8]) -> core::arch::wasm32::v128 {
+    let mut tail = [0u8; 16];
+    tail[..rem.len()].copy_from_slice(rem);
+    core::arch::wasm32::v128_load(tail.as_ptr() as *const core::arch::wasm32::v128)
+}
+
 #[inline]
 unsafe fn sum_u8(buf: &[u8]) -> f32 {
     #[cfg(target_feature = "simd128")]
@@ -33,14 +48,16 @@ unsafe fn sum_u8(buf: &[u8]) -> f32 {
             acc_vec = i32x4_add(acc_vec, widened);
         }
 
+        // Zero lanes contribute nothing, so the tail needs no scalar loop.
+        if !remainder.is_empty() {
+            let v = load_tail(remainder);
+            let widened = i32x4_extadd_pairwise_u16x8(i16x8_extadd_pairwise_u8x16(v));
+            acc_vec = i32x4_add(acc_vec, widened);
+        }
+
         let mut tmp = [0i32; 4];
         v128_store(tmp.as_mut_ptr() as *mut v128, acc_vec);
-        let mut sum = tmp.iter().copied().map(|x| x as f32).sum::<f32>();
-
-        for &b in remainder {
-            sum += b as f32;
-        }
-        return sum;
+        tmp.iter().copied().map(|x| x as f32).sum::<f32>()
     }
 
     #[cfg(not(target_feature = "simd128"))]
@@ -64,16 +81,14 @@ unsafe fn sum_u16(buf: &[u8]) -> f32 {
             acc_vec = i32x4_add(acc_vec, widened);
         }
 
+        if !remainder.is_empty() {
+            let widened = i32x4_extadd_pairwise_u16x8(load_tail(remainder));
+            acc_vec = i32x4_add(acc_vec, widened);
+        }
+
         let mut tmp = [0i32; 4];
         v128_store(tmp.as_mut_ptr() as *mut v128, acc_vec);
-        let mut sum = tmp.iter().copied().map(|x| x as f32).sum::<f32>();
-
-        for chunk in remainder.chunks_exact(2) {
-            let mut bytes = [0u8; 2];
-            bytes.copy_from_slice(chunk);
-            sum += u16::from_le_bytes(bytes) as f32;
-        }
-        return sum;
+        tmp.iter().copied().map(|x| x as f32).sum::<f32>()
     }
 
     #[cfg(not(target_feature = "simd128"))]
@@ -104,15 +119,14 @@ unsafe fn sum_f32(buf: &[u8]) -> f32 {
             acc = f32x4_add(acc, v);
         }
 
+        // Zero bytes decode as +0.0, the additive identity.
+        if !remainder.is_empty() {
+            acc = f32x4_add(acc, load_tail(remainder));
+        }
+
         let mut tmp = [0f32; 4];
         v128_store(tmp.as_mut_ptr() as *mut v128, acc);
         sum += tmp.iter().copied().sum::<f32>();
-
-        for r in remainder.chunks_exact(4) {
-            let mut bytes = [0u8; 4];
-            bytes.copy_from_slice(r);
-            sum += f32::from_le_bytes(bytes);
-        }
     }
 
     #[cfg(not(target_feature = "simd128"))]
@@ -129,12 +143,10 @@ unsafe fn sum_f32(buf: &[u8]) -> f32 {
 
 fn write_f32(out_ptr: *mut u8, out_len: usize, value: f32) -> isize {
     if out_len < 4 {
-        return -1;
-    }
-    let bytes = value.to_le_bytes();
-    unsafe {
-        std::ptr::copy_nonoverlapping(bytes.as_ptr(), out_ptr, 4);
+        return error::fail(ErrorCode::ShortOutput);
     }
+    let out = unsafe { ffi::slice_mut(out_ptr, 4) };
+    out.copy_from_slice(&value.to_le_bytes());
     4
 }
 
@@ -147,14 +159,14 @@ pub unsafe extern "C" fn sum_u8_bytes(
     out_ptr: *mut u8,
     out_len: usize,
 ) -> isize {
-    let input = std::slice::from_raw_parts(in_ptr, in_len);
+    let input = ffi::slice(in_ptr, in_len);
     let sum = sum_u8(input);
     write_f32(out_ptr, out_len, sum)
 }
 
 /// Sum u16 array bytes -> f32
 #[no_mangle]
-#[allow(clippy::missing_safety_doc)]
+#[allow(clippy::missing_safety_doc, clippy::manual_is_multiple_of)]
 pub unsafe extern "C" fn sum_u16_bytes(
     in_ptr: *const u8,
     in_len: usize,
@@ -162,16 +174,16 @@ pub unsafe extern "C" fn sum_u16_bytes(
     out_len: usize,
 ) -> isize {
     if in_len % 2 != 0 {
-        return -1;
+        return error::fail(ErrorCode::BadLength);
     }
-    let input = std::slice::from_raw_parts(in_ptr, in_len);
+    let input = ffi::slice(in_ptr, in_len);
     let sum = sum_u16(input);
     write_f32(out_ptr, out_len, sum)
 }
 
 /// Sum f32 array bytes -> f32
 #[no_mangle]
-#[allow(clippy::missing_safety_doc)]
+#[allow(clippy::missing_safety_doc, clippy::manual_is_multiple_of)]
 pub unsafe extern "C" fn sum_f32_bytes(
     in_ptr: *const u8,
     in_len: usize,
@@ -179,9 +191,59 @@ pub unsafe extern "C" fn sum_f32_bytes(
     out_len: usize,
 ) -> isize {
     if in_len % 4 != 0 {
-        return -1;
+        return error::fail(ErrorCode::BadLength);
     }
-    let input = std::slice::from_raw_parts(in_ptr, in_len);
+    let input = ffi::slice(in_ptr, in_len);
     let sum = sum_f32(input);
     write_f32(out_ptr, out_len, sum)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(
+        f: unsafe extern "C" fn(*const u8, usize, *mut u8, usize) -> isize,
+        input: &[u8],
+    ) -> Option<f32> {
+        let mut out = [0u8; 4];
+        match unsafe { f(input.as_ptr(), input.len(), out.as_mut_ptr(), out.len()) } {
+            4 => Some(f32::from_le_bytes(out)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn sums_each_element_type() {
+        let bytes: Vec<u8> = (0..37).collect();
+        assert_eq!(call(sum_u8_bytes, &bytes), Some(666.0));
+
+        let words: Vec<u8> = [1u16, 2, 3, 1000]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        assert_eq!(call(sum_u16_bytes, &words), Some(1006.0));
+
+        let floats: Vec<u8> = [1.5f32, 2.5, 3.0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        assert_eq!(call(sum_f32_bytes, &floats), Some(7.0));
+    }
+
+    #[test]
+    fn rejects_misshapen_input() {
+        assert_eq!(call(sum_u16_bytes, &[1, 2, 3]), None);
+        assert_eq!(error::last_error_code(), ErrorCode::BadLength as u32);
+        assert_eq!(call(sum_f32_bytes, &[0; 6]), None);
+    }
+
+    #[test]
+    fn write_f32_checks_capacity() {
+        let mut out = [0u8; 4];
+        assert_eq!(write_f32(out.as_mut_ptr(), 3, 1.0), -1);
+        assert_eq!(error::last_error_code(), ErrorCode::ShortOutput as u32);
+        assert_eq!(write_f32(out.as_mut_ptr(), 4, 2.5), 4);
+        assert_eq!(f32::from_le_bytes(out), 2.5);
+    }
+}
@@ -1,8 +1,10 @@
-#![cfg(target_arch = "wasm32")]
-
 use std::alloc::{alloc, dealloc, Layout};
 use std::mem;
 
+#[allow(dead_code)]
+#[path = "../../../src/ffi/view.rs"]
+mod ffi;
+
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn alloc_bytes(len: usize) -> *mut u8 {
@@ -17,6 +19,174 @@ pub unsafe extern "C" fn free_bytes(ptr: *mut u8, len: usize) {
     dealloc(ptr, layout);
 }
 
+/// Scalar newline normalization for builds without simd128, returning the
+/// number of bytes written.
+#[cfg(not(target_feature = "simd128"))]
+#[inline]
+unsafe fn normalize_scalar(input: &[u8], out_ptr: *mut u8) -> usize {
+    let end = input.len();
+    let mut i = 0;
+    let mut written = 0;
+    while i < end {
+        let b = input[i];
+        if b == b'\r' {
+            if i + 1 < input.len() && input[i + 1] == b'\n' {
+                *out_ptr.add(written) = 0;
+                written += 1;
+                i += 2;
+            } else {
+                *out_ptr.add(written) = 0;
+                written += 1;
+                i += 1;
+            }
+        } else if b == b'\n' {
+            *out_ptr.add(written) = 0;
+            written += 1;
+            i += 1;
+        } else {
+            *out_ptr.add(written) = b;
+            written += 1;
+            i += 1;
+        }
+    }
+    written
+}
+
+/// For each 8-bit drop mask, the indices of the kept lanes packed to the
+/// front. Used to compact one half of a block with a single swizzle.
+#[cfg(target_feature = "simd128")]
+static COMPACT_LANES: [[u8; 8]; 256] = {
+    let mut table = [[0u8; 8]; 256];
+    let mut mask = 0;
+    while mask < 256 {
+        let mut kept = 0;
+        let mut lane = 0;
+        while lane < 8 {
+            if mask & (1 << lane) == 0 {
+                table[mask][kept] = lane as u8;
+                kept += 1;
+            }
+            lane += 1;
+        }
+        mask += 1;
+    }
+    table
+};
+
+/// Store the lanes of `v` whose bit is clear in `drop`, packed contiguously
+/// at `out_ptr + written`, and return the advanced `written` cursor. Each
+/// half is shuffled through `COMPACT_LANES` and written with an 8-byte lane
+/// store, so the write never extends past the 16 input bytes consumed.
+#[cfg(target_feature = "simd128")]
+#[inline]
+unsafe fn compact_store(
+    v: core::arch::wasm32::v128,
+    drop: u32,
+    out_ptr: *mut u8,
+    mut written: usize,
+) -> usize {
+    use core::arch::wasm32::*;
+    let lo = (drop & 0xFF) as usize;
+    let hi = (drop >> 8) as usize;
+
+    let lo_idx = v128_load64_zero(COMPACT_LANES[lo].as_ptr() as *const u64);
+    let packed = i8x16_swizzle(v, lo_idx);
+    v128_store64_lane::<0>(packed, out_ptr.add(written) as *mut u64);
+    written += 8 - lo.count_ones() as usize;
+
+    let hi_idx = i8x16_add(
+        v128_load64_zero(COMPACT_LANES[hi].as_ptr() as *const u64),
+        i8x16_splat(8),
+    );
+    let packed = i8x16_swizzle(v, hi_idx);
+    v128_store64_lane::<0>(packed, out_ptr.add(written) as *mut u64);
+    written + 8 - hi.count_ones() as usize
+}
+
+/// Bytes examined per outer iteration of the SIMD loop: the loop first ORs
+/// the newline masks of `STRIDE / 16` vectors and copies the whole stride
+/// through when none match. Override at build time with `WBL_KERNEL_STRIDE`
+/// (16, 32, 64 or 128) to compare granularities in the bench matrix.
+#[cfg(target_feature = "simd128")]
+const STRIDE: usize = match option_env!("WBL_KERNEL_STRIDE") {
+    Some(s) => parse_stride(s),
+    None => 32,
+};
+
+#[cfg(target_feature = "simd128")]
+const fn parse_stride(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut n = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        assert!(
+            bytes[i].is_ascii_digit(),
+            "WBL_KERNEL_STRIDE must be a number"
+        );
+        n = n * 10 + (bytes[i] - b'0') as usize;
+        i += 1;
+    }
+    assert!(
+        matches!(n, 16 | 32 | 64 | 128),
+        "WBL_KERNEL_STRIDE must be 16, 32, 64 or 128"
+    );
+    n
+}
+
+/// Whether any of the `STRIDE` bytes at `ptr` is a CR or LF.
+#[cfg(target_feature = "simd128")]
+#[inline(always)]
+unsafe fn stride_has_newline(ptr: *const u8) -> bool {
+    use core::arch::wasm32::*;
+    let n_splat = i8x16_splat(b'\n' as i8);
+    let r_splat = i8x16_splat(b'\r' as i8);
+    let mut any = i8x16_splat(0);
+    let mut k = 0;
+    while k < STRIDE {
+        let v = v128_load(ptr.add(k) as *const v128);
+        any = v128_or(any, v128_or(i8x16_eq(v, n_splat), i8x16_eq(v, r_splat)));
+        k += 16;
+    }
+    v128_any_true(any)
+}
+
+/// Normalize the 16-byte block at `block`, returning the advanced `written`
+/// cursor and the CR carry for the next block.
+#[cfg(target_feature = "simd128")]
+#[inline(always)]
+unsafe fn split_block(
+    block: *const u8,
+    carry_cr: u32,
+    out_ptr: *mut u8,
+    mut written: usize,
+) -> (usize, u32) {
+    use core::arch::wasm32::*;
+    let v = v128_load(block as *const v128);
+    let is_n = i8x16_eq(v, i8x16_splat(b'\n' as i8));
+    let is_r = i8x16_eq(v, i8x16_splat(b'\r' as i8));
+    let n_bits = i8x16_bitmask(is_n) as u32;
+    let r_bits = i8x16_bitmask(is_r) as u32;
+
+    if n_bits | r_bits == 0 {
+        v128_store(out_ptr.add(written) as *mut v128, v);
+        written += 16;
+    } else {
+        // Every CR/LF becomes a separator; an LF directly after a CR is
+        // dropped so CRLF collapses to a single '\0'.
+        let zeroed = v128_andnot(v, v128_or(is_n, is_r));
+        let drop = n_bits & ((r_bits << 1) | carry_cr) & 0xFFFF;
+
+        if drop == 0 {
+            v128_store(out_ptr.add(written) as *mut v128, zeroed);
+            written += 16;
+        } else {
+            written = compact_store(zeroed, drop, out_ptr, written);
+        }
+    }
+
+    (written, r_bits >> 15)
+}
+
 /// Normalize newlines and mark splits: convert CRLF/CR/LF to '\0' separators.
 /// Writes into out_ptr (same length budget), returns bytes written.
 #[no_mangle]
@@ -27,81 +197,120 @@ pub unsafe extern "C" fn split_lines_chunk(
     out_ptr: *mut u8,
     out_len: usize,
 ) -> isize {
-    let input = std::slice::from_raw_parts(in_ptr, in_len);
+    let input = ffi::slice(in_ptr, in_len);
     if out_len < in_len {
         return -1;
     }
-
-    let mut written = 0usize;
-    let mut i = 0usize;
+    ffi::check_range(out_ptr, out_len);
 
     #[cfg(target_feature = "simd128")]
-    {
+    let written = {
         use core::arch::wasm32::*;
-        let n_splat = i8x16_splat(b'\n' as i8);
-        let r_splat = i8x16_splat(b'\r' as i8);
+        let mut written = 0usize;
+        let mut i = 0usize;
 
-        while i + 16 <= in_len {
-            let v = v128_load(in_ptr.add(i) as *const v128);
-            // Check for both \n and \r
-            let mask = i8x16_bitmask(v128_or(i8x16_eq(v, n_splat), i8x16_eq(v, r_splat)));
-
-            if mask == 0 {
-                // Fast path: no newlines in these 16 bytes
-                v128_store(out_ptr.add(written) as *mut v128, v);
-                written += 16;
-                i += 16;
-            } else {
-                // Slow path: process byte-by-byte to handle normalization/splitting
-                for _ in 0..16 {
-                    let b = input[i];
-                    if b == b'\r' {
-                        if i + 1 < in_len && input[i + 1] == b'\n' {
-                            *out_ptr.add(written) = 0;
-                            written += 1;
-                            i += 2;
-                        } else {
-                            *out_ptr.add(written) = 0;
-                            written += 1;
-                            i += 1;
-                        }
-                    } else if b == b'\n' {
-                        *out_ptr.add(written) = 0;
-                        written += 1;
-                        i += 1;
-                    } else {
-                        *out_ptr.add(written) = b;
-                        written += 1;
-                        i += 1;
-                    }
+        // Lane 15 of the previous block was a CR, so a leading LF in the
+        // current block is the second half of a CRLF pair.
+        let mut carry_cr = 0u32;
+
+        while i + STRIDE <= in_len {
+            if !stride_has_newline(in_ptr.add(i)) {
+                // Fast path: no newlines anywhere in the stride
+                let mut k = 0;
+                while k < STRIDE {
+                    let v = v128_load(in_ptr.add(i + k) as *const v128);
+                    v128_store(out_ptr.add(written + k) as *mut v128, v);
+                    k += 16;
                 }
+                written += STRIDE;
+                carry_cr = 0;
+                i += STRIDE;
+                continue;
+            }
+            let end = i + STRIDE;
+            while i < end {
+                (written, carry_cr) = split_block(in_ptr.add(i), carry_cr, out_ptr, written);
+                i += 16;
             }
         }
+
+        while i + 16 <= in_len {
+            (written, carry_cr) = split_block(in_ptr.add(i), carry_cr, out_ptr, written);
+            i += 16;
+        }
+
+        // Masked tail: run the final partial block through `split_block` on
+        // a zero-padded copy. Padding is never CR or LF, so it is kept and
+        // compacted to the end of the staging buffer, where it is dropped.
+        let rem = in_len - i;
+        if rem > 0 {
+            let mut tail = [0u8; 16];
+            let mut staged = [0u8; 16];
+            tail[..rem].copy_from_slice(&input[i..]);
+            let (n, _) = split_block(tail.as_ptr(), carry_cr, staged.as_mut_ptr(), 0);
+            let n = n - (16 - rem);
+            std::ptr::copy_nonoverlapping(staged.as_ptr(), out_ptr.add(written), n);
+            written += n;
+        }
+        written
+    };
+
+    #[cfg(not(target_feature = "simd128"))]
+    let written = normalize_scalar(input, out_ptr);
+
+    written as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(input: &[u8]) -> Vec<u8> {
+        let mut out = vec![0xAA; input.len()];
+        let written =
+            unsafe { split_lines_chunk(input.as_ptr(), input.len(), out.as_mut_ptr(), out.len()) };
+        assert!(written >= 0);
+        out.truncate(written as usize);
+        out
     }
 
-    // Remainder
-    while i < in_len {
-        let b = input[i];
-        if b == b'\r' {
-            if i + 1 < in_len && input[i + 1] == b'\n' {
-                *out_ptr.add(written) = 0;
-                written += 1;
-                i += 2;
-            } else {
-                *out_ptr.add(written) = 0;
-                written += 1;
-                i += 1;
-            }
-        } else if b == b'\n' {
-            *out_ptr.add(written) = 0;
-            written += 1;
-            i += 1;
-        } else {
-            *out_ptr.add(written) = b;
-            written += 1;
-            i += 1;
+    #[test]
+    fn normalizes_all_line_endings() {
+        assert_eq!(split(b"a\nb\r\nc\rd"), b"a\0b\0c\0d");
+        assert_eq!(split(b"\r\n\r\n\n\r"), b"\0\0\0\0");
+        assert_eq!(split(b""), b"");
+    }
+
+    #[test]
+    fn crlf_straddling_blocks_collapses_once() {
+        for at in [14, 15, 16, 31, 32, 63, 64] {
+            let mut input = vec![b'x'; 80];
+            input[at] = b'\r';
+            input[at + 1] = b'\n';
+            let mut expected = input.clone();
+            expected[at] = 0;
+            expected.remove(at + 1);
+            assert_eq!(split(&input), expected, "CRLF at {at}");
         }
     }
 
-    written as isize
+    #[test]
+    fn rejects_short_output() {
+        let input = b"abc\n";
+        let mut out = [0u8; 3];
+        let written = unsafe { split_lines_chunk(input.as_ptr(), 4, out.as_mut_ptr(), 3) };
+        assert_eq!(written, -1);
+    }
+
+    #[test]
+    fn alloc_round_trip() {
+        unsafe {
+            let in_ptr = alloc_bytes(32);
+            let out_ptr = alloc_bytes(32);
+            in_ptr.write_bytes(b'\n', 32);
+            assert_eq!(split_lines_chunk(in_ptr, 32, out_ptr, 32), 32);
+            free_bytes(in_ptr, 32);
+            free_bytes(out_ptr, 32);
+        }
+    }
 }
@@ -0,0 +1,101 @@
+import assert from 'node:assert'
+import { fileURLToPath } from 'node:url'
+import {
+  callKernel,
+  instantiateVariants,
+  seededBytes,
+} from '../../scripts/kernel-variants.js'
+
+const CR = 13
+const LF = 10
+
+/** Scalar reference: every CRLF, CR or LF becomes one 0 separator. */
+function splitReference(input) {
+  const out = []
+  for (let i = 0; i < input.length; i++) {
+    if (input[i] === CR) {
+      out.push(0)
+      if (input[i + 1] === LF) i++
+    } else if (input[i] === LF) {
+      out.push(0)
+    } else {
+      out.push(input[i])
+    }
+  }
+  return Uint8Array.from(out)
+}
+
+/**
+ * Inputs with a lone CR, a lone LF or a CRLF pair at every position next to
+ * a 16-byte block boundary, over lengths that end inside, at and just past
+ * each stride.
+ */
+function* boundaryInputs() {
+  const lengths = [0, 1, 15, 16, 17, 31, 32, 33, 47, 63, 64, 65, 127]
+  lengths.push(128, 129, 143, 255, 256, 257, 271)
+  for (const len of lengths) {
+    yield new Uint8Array(len).fill(0x78)
+    for (let at = 0; at < len; at++) {
+      if (![14, 15, 0, 1].includes(at % 16)) continue
+      for (const marks of [[CR], [LF], [CR, LF]]) {
+        const input = new Uint8Array(len).fill(0x78)
+        input.set(marks.slice(0, len - at), at)
+        yield input
+      }
+    }
+  }
+}
+
+/**
+ * Blocks where most lanes are line breaks, so the bitmask loop emits many
+ * breaks per block: runs of one pattern repeated across several blocks,
+ * offset by each lane of the first block.
+ */
+function* denseInputs() {
+  const patterns = [[CR], [LF], [CR, LF], [LF, CR], [CR, CR, LF], [0x61, LF]]
+  for (const pattern of patterns) {
+    for (let shift = 0; shift < 16; shift++) {
+      const input = new Uint8Array(shift + 64 + 7).fill(0x78)
+      for (let i = shift; i < input.length; i++) {
+        input[i] = pattern[(i - shift) % pattern.length]
+      }
+      yield input
+    }
+  }
+}
+
+function* randomInputs() {
+  const next = seededBytes(0x5eed, [0x61, 0x62, 0x63, 0x64, CR, LF])
+  for (let len = 0; len <= 300; len++) yield next(len)
+}
+
+async function main() {
+  const variants = await instantiateVariants(
+    fileURLToPath(new URL('.', import.meta.url))
+  )
+  const inputs = [
+    ...boundaryInputs(),
+    ...denseInputs(),
+    ...randomInputs(),
+  ]
+
+  for (const { name, exports } of variants) {
+    for (const input of inputs) {
+      const expected = splitReference(input)
+      const { written, out } = callKernel(
+        exports,
+        'split_lines_chunk',
+        input,
+        input.length
+      )
+      assert.strictEqual(written, expected.length, `${name}: ${input}`)
+      assert.deepStrictEqual(out, expected, `${name}: ${input}`)
+    }
+    console.log(`✓ ${name}: ${inputs.length} inputs match the scalar splitter`)
+  }
+}
+
+main().catch((err) => {
+  console.error('Test failed:', err)
+  process.exit(1)
+})
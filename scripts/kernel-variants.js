@@ -0,0 +1,83 @@
+/**
+ * Differential-test helpers for example kernels.
+ *
+ * Host `cargo test` never compiles the simd128 paths, so the examples check
+ * them in wasm instead: every variant the bench matrix emits (scalar,
+ * autovec, explicit-* and stride-N) is built and instantiated, and its
+ * output compared against a JS reference.
+ */
+
+import { readFileSync } from 'node:fs'
+import { resolve } from 'node:path'
+import { loadConfigFromCli } from '../src/cli/config.js'
+import {
+  buildVariant,
+  cargoTargetDir,
+  generateVariants,
+} from '../src/cli/bench.js'
+
+/**
+ * Build each bench variant of the crate at `crateDir` and instantiate it.
+ * Returns `[{ name, exports }]` in bench order.
+ */
+export async function instantiateVariants(crateDir) {
+  const cfg = loadConfigFromCli({ crate: resolve(crateDir) })
+  const targetDir = cargoTargetDir(cfg.crateDir)
+  const variants = []
+  for (const variant of generateVariants(cfg.simd || {}, cfg.bench)) {
+    const wasmPath = buildVariant({
+      crateDir: cfg.crateDir,
+      targetDir,
+      wasmFileStem: cfg.wasmFileStem,
+      variant,
+      release: cfg.release,
+    })
+    // Read before the next build overwrites the artifact.
+    const { instance } = await WebAssembly.instantiate(readFileSync(wasmPath))
+    variants.push({ name: variant.name, exports: instance.exports })
+  }
+  return variants
+}
+
+/**
+ * Call a `(in_ptr, in_len, out_ptr, out_len) -> isize` kernel with `input`
+ * and an `outLen`-byte output buffer. Returns the status and a copy of the
+ * first `written` output bytes (empty on failure).
+ */
+export function callKernel(wasm, abi, input, outLen) {
+  // Zero-sized layouts are not allocated; keep one byte for each buffer.
+  const inCap = Math.max(input.length, 1)
+  const outCap = Math.max(outLen, 1)
+  const inPtr = wasm.alloc_bytes(inCap)
+  const outPtr = wasm.alloc_bytes(outCap)
+  try {
+    new Uint8Array(wasm.memory.buffer, inPtr, input.length).set(input)
+    const written = wasm[abi](inPtr, input.length, outPtr, outLen)
+    const out =
+      written > 0
+        ? new Uint8Array(wasm.memory.buffer, outPtr, written).slice()
+        : new Uint8Array(0)
+    return { written, out }
+  } finally {
+    wasm.free_bytes(inPtr, inCap)
+    wasm.free_bytes(outPtr, outCap)
+  }
+}
+
+/**
+ * Deterministic byte generator (xorshift32) so failures reproduce.
+ */
+export function seededBytes(seed, alphabet) {
+  let state = seed >>> 0 || 1
+  return (len) => {
+    const out = new Uint8Array(len)
+    for (let i = 0; i < len; i++) {
+      state ^= state << 13
+      state ^= state >>> 17
+      state ^= state << 5
+      state >>>= 0
+      out[i] = alphabet[state % alphabet.length]
+    }
+    return out
+  }
+}
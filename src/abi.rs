@@ -0,0 +1,152 @@
+//! ABI version and build metadata.
+//!
+//! `abi_version` lets the generated JS glue check, before the first call,
+//! that the wasm it loaded speaks the calling conventions it was generated
+//! for; a stale cached `.wasm` next to newer glue otherwise fails in
+//! confusing ways or writes through misread pointers. wasm32 builds also
+//! embed a `wasm-bindgen-lite` custom section with the same version, the
+//! crate version and the enabled Cargo features as JSON, readable without
+//! instantiating the module:
+//!
+//! ```js
+//! const [section] = WebAssembly.Module.customSections(module, 'wasm-bindgen-lite')
+//! const info = JSON.parse(new TextDecoder().decode(section))
+//! ```
+//!
+//! The `built_with_` exports report the wasm target features the binary was
+//! compiled with, for applications that ship several variants and pick one
+//! by probing the engine.
+
+/// Bump whenever an export changes signature or meaning in a way older glue
+/// cannot handle.
+pub const ABI_VERSION: u32 = 1;
+
+const FEATURES: &[(&str, bool)] = &[
+    ("aead", cfg!(feature = "aead")),
+    ("checked-ffi", cfg!(feature = "checked-ffi")),
+    ("ed25519", cfg!(feature = "ed25519")),
+    ("heap-stats", cfg!(feature = "heap-stats")),
+    ("kdf", cfg!(feature = "kdf")),
+    ("log", cfg!(feature = "log")),
+    ("multi-value", cfg!(feature = "multi-value")),
+    ("parquet", cfg!(feature = "parquet")),
+    ("progress", cfg!(feature = "progress")),
+    ("regex", cfg!(feature = "regex")),
+    ("strict-aliasing", cfg!(feature = "strict-aliasing")),
+    ("tokenizer", cfg!(feature = "tokenizer")),
+];
+
+const HEAD: &str = concat!(
+    "{\"abi\":",
+    // Must match `ABI_VERSION`; checked by the tests.
+    "1",
+    ",\"version\":\"",
+    env!("CARGO_PKG_VERSION"),
+    "\",\"features\":["
+);
+const TAIL: &str = "]}";
+
+/// Copy `s` into `out` at `at`, returning the end. A `const fn` stand-in for
+/// `copy_from_slice`.
+const fn put(out: &mut [u8; INFO_LEN], at: usize, s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        out[at + i] = bytes[i];
+        i += 1;
+    }
+    at + bytes.len()
+}
+
+const fn info_len() -> usize {
+    let mut len = HEAD.len() + TAIL.len();
+    let (mut i, mut first) = (0, true);
+    while i < FEATURES.len() {
+        if FEATURES[i].1 {
+            // Quotes, plus a comma before all but the first.
+            len += FEATURES[i].0.len() + 2 + if first { 0 } else { 1 };
+            first = false;
+        }
+        i += 1;
+    }
+    len
+}
+
+const INFO_LEN: usize = info_len();
+
+const fn info() -> [u8; INFO_LEN] {
+    let mut out = [0u8; INFO_LEN];
+    let mut at = put(&mut out, 0, HEAD);
+    let (mut i, mut first) = (0, true);
+    while i < FEATURES.len() {
+        if FEATURES[i].1 {
+            if !first {
+                at = put(&mut out, at, ",");
+            }
+            at = put(&mut out, at, "\"");
+            at = put(&mut out, at, FEATURES[i].0);
+            at = put(&mut out, at, "\"");
+            first = false;
+        }
+        i += 1;
+    }
+    put(&mut out, at, TAIL);
+    out
+}
+
+/// The build metadata as JSON, emitted as the `wasm-bindgen-lite` custom
+/// section on wasm32.
+#[cfg_attr(target_arch = "wasm32", link_section = "wasm-bindgen-lite")]
+#[used]
+static BUILD_INFO: [u8; INFO_LEN] = info();
+
+/// Version of the calling conventions this module was built with; the
+/// generated glue refuses to use a module that reports a different one.
+#[no_mangle]
+pub extern "C" fn abi_version() -> u32 {
+    ABI_VERSION
+}
+
+/// `1` if this binary was compiled with the `simd128` target feature, else
+/// `0`. Lets a loader that picked a variant by probing the engine confirm
+/// which one it actually got.
+#[no_mangle]
+pub extern "C" fn built_with_simd128() -> u32 {
+    cfg!(target_feature = "simd128") as u32
+}
+
+/// `1` if this binary was compiled with the `bulk-memory` target feature
+/// (`memory.copy`/`memory.fill`), else `0`.
+#[no_mangle]
+pub extern "C" fn built_with_bulk_memory() -> u32 {
+    cfg!(target_feature = "bulk-memory") as u32
+}
+
+/// `1` if this binary was compiled with the `atomics` target feature (shared
+/// memory for threads), else `0`.
+#[no_mangle]
+pub extern "C" fn built_with_atomics() -> u32 {
+    cfg!(target_feature = "atomics") as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_info_is_json_with_the_enabled_features() {
+        let info = std::str::from_utf8(&BUILD_INFO).unwrap();
+        let features: Vec<String> = FEATURES
+            .iter()
+            .filter(|(_, on)| *on)
+            .map(|(name, _)| format!("\"{name}\""))
+            .collect();
+        let expected = format!(
+            "{{\"abi\":{},\"version\":\"{}\",\"features\":[{}]}}",
+            abi_version(),
+            env!("CARGO_PKG_VERSION"),
+            features.join(",")
+        );
+        assert_eq!(info, expected);
+    }
+}
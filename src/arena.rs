@@ -0,0 +1,152 @@
+//! A bump allocator for request-scoped scratch buffers.
+//!
+//! `arena_alloc` hands out 16-byte aligned blocks carved from large chunks,
+//! and `arena_reset` reclaims every block at once, so JS can allocate a
+//! batch of temporaries for one call without tracking and freeing each of
+//! them. Chunks are kept across resets (wasm memory never shrinks anyway),
+//! so a steady workload stops allocating after its first batch. The
+//! generated `reset()` calls `arena_reset` in every instance that exports it.
+//!
+//! Blocks from the arena must never be passed to `free_bytes`.
+
+use std::cell::RefCell;
+use std::ptr;
+
+/// Alignment of every block: enough for `v128` loads and any typed view.
+const ALIGN: usize = 16;
+/// Size of the first chunk; later chunks double.
+const MIN_CHUNK: usize = 64 * 1024;
+
+struct Chunk {
+    ptr: *mut u8,
+    cap: usize,
+}
+
+#[derive(Default)]
+struct Arena {
+    chunks: Vec<Chunk>,
+    /// Index of the chunk being bumped; chunks after it are unused.
+    current: usize,
+    /// Bytes used in the current chunk.
+    used: usize,
+}
+
+impl Arena {
+    fn alloc(&mut self, len: usize) -> *mut u8 {
+        let Some(len) = len.checked_next_multiple_of(ALIGN) else {
+            crate::set_alloc_status(crate::ALLOC_BAD_LAYOUT);
+            return ptr::null_mut();
+        };
+        while let Some(chunk) = self.chunks.get(self.current) {
+            if chunk.cap - self.used >= len {
+                let block = unsafe { chunk.ptr.add(self.used) };
+                self.used += len;
+                crate::set_alloc_status(crate::ALLOC_OK);
+                return block;
+            }
+            if self.current + 1 == self.chunks.len() {
+                break;
+            }
+            self.current += 1;
+            self.used = 0;
+        }
+        let last = self.chunks.last().map_or(0, |c| c.cap);
+        let cap = len.max(last.saturating_mul(2)).max(MIN_CHUNK);
+        let ptr = unsafe { crate::alloc_with_align(cap, ALIGN, false) };
+        if ptr.is_null() {
+            return ptr::null_mut();
+        }
+        self.chunks.push(Chunk { ptr, cap });
+        self.current = self.chunks.len() - 1;
+        self.used = len;
+        ptr
+    }
+
+    fn reset(&mut self) {
+        self.current = 0;
+        self.used = 0;
+    }
+
+    fn capacity(&self) -> usize {
+        self.chunks.iter().map(|c| c.cap).sum()
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        for chunk in &self.chunks {
+            unsafe { crate::free_with_align(chunk.ptr, chunk.cap, ALIGN) };
+        }
+    }
+}
+
+thread_local! {
+    static ARENA: RefCell<Arena> = RefCell::default();
+}
+
+/// Allocate `len` bytes from the arena, 16-byte aligned. The block stays
+/// valid until the next `arena_reset`.
+///
+/// Returns a null pointer when a new chunk cannot be allocated;
+/// `alloc_last_error` then says why. A zero length still returns a valid,
+/// aligned pointer.
+#[no_mangle]
+pub extern "C" fn arena_alloc(len: usize) -> *mut u8 {
+    ARENA.with_borrow_mut(|arena| arena.alloc(len))
+}
+
+/// Reclaim every block handed out by `arena_alloc` at once, keeping the
+/// chunks for reuse. Pointers into the arena are dangling afterwards.
+#[no_mangle]
+pub extern "C" fn arena_reset() {
+    ARENA.with_borrow_mut(Arena::reset);
+}
+
+/// Total bytes the arena has reserved from the allocator, whether or not
+/// they are currently handed out.
+#[no_mangle]
+pub extern "C" fn arena_capacity() -> usize {
+    ARENA.with_borrow(Arena::capacity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bumps_resets_and_grows() {
+        let mut arena = Arena::default();
+        let a = arena.alloc(3);
+        let b = arena.alloc(40);
+        let empty = arena.alloc(0);
+        assert_eq!(a as usize % ALIGN, 0);
+        assert_eq!(b as usize - a as usize, 16);
+        assert_eq!(empty as usize - b as usize, 48);
+        assert_eq!(arena.capacity(), MIN_CHUNK);
+        unsafe { ptr::write_bytes(b, 0xAB, 40) };
+
+        // Too big for the rest of the first chunk: a new, doubled one.
+        let big = arena.alloc(MIN_CHUNK);
+        assert!(!big.is_null());
+        assert_eq!(arena.capacity(), 3 * MIN_CHUNK);
+
+        arena.reset();
+        assert_eq!(arena.alloc(8), a, "reset reuses the first chunk");
+        assert_eq!(arena.alloc(MIN_CHUNK), big, "and then the second");
+        assert_eq!(arena.capacity(), 3 * MIN_CHUNK);
+
+        assert!(arena.alloc(usize::MAX).is_null());
+        assert_eq!(crate::alloc_last_error(), crate::ALLOC_BAD_LAYOUT);
+    }
+
+    #[test]
+    fn exports_share_one_arena() {
+        let before = arena_capacity();
+        let ptr = arena_alloc(100);
+        assert!(!ptr.is_null());
+        assert!(arena_capacity() >= before.max(MIN_CHUNK));
+        arena_reset();
+        assert_eq!(arena_alloc(100), ptr);
+        arena_reset();
+    }
+}
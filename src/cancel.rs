@@ -0,0 +1,58 @@
+//! Cooperative cancellation of long-running kernels.
+//!
+//! The module owns one cancellation byte in linear memory, at the address
+//! `cancel_flag_ptr` returns. Kernels that report progress (key derivation,
+//! decompression) poll it at every report, about once per percent of their
+//! work, and give up with [`CANCELLED`] once it is non-zero. Setting the
+//! byte is the caller's job: another thread writes it with `Atomics.store`
+//! when the module runs in a worker on shared memory, which aborts the call
+//! without terminating the worker and losing the instance; on a single
+//! thread an `onProgress` listener can call `cancel_request`.
+//!
+//! The flag stays set until `cancel_reset`, so a request that arrives just
+//! before a call starts still cancels it. Reset it before starting new work.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::error::{self, ErrorCode};
+
+/// Status returned by a kernel that stopped because cancellation was
+/// requested.
+pub const CANCELLED: isize = -3;
+
+static FLAG: AtomicU8 = AtomicU8::new(0);
+
+/// Whether cancellation has been requested.
+pub fn requested() -> bool {
+    FLAG.load(Ordering::Relaxed) != 0
+}
+
+/// Status for a long-running kernel that gave up: [`CANCELLED`] (recording
+/// `ErrorCode::Cancelled`) when that is why, otherwise `-1`.
+pub fn failed() -> isize {
+    if !requested() {
+        return -1;
+    }
+    error::record(ErrorCode::Cancelled);
+    CANCELLED
+}
+
+/// Address of the cancellation byte. Any non-zero value written there
+/// cancels the running call.
+#[no_mangle]
+pub extern "C" fn cancel_flag_ptr() -> *mut u8 {
+    FLAG.as_ptr()
+}
+
+/// Request cancellation of the running call, and of any started before
+/// `cancel_reset`.
+#[no_mangle]
+pub extern "C" fn cancel_request() {
+    FLAG.store(1, Ordering::Relaxed);
+}
+
+/// Clear the cancellation byte.
+#[no_mangle]
+pub extern "C" fn cancel_reset() {
+    FLAG.store(0, Ordering::Relaxed);
+}
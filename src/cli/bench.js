@@ -23,12 +23,13 @@ const DEFAULT_BENCH_CONFIG = {
   samples: 60,
   outputDir: 'bench_out',
   dataSizes: [1024, 16384, 65536, 262144, 1048576], // 1KB, 16KB, 64KB, 256KB, 1MB
+  strides: [], // e.g. [16, 32, 64, 128] to add stride-N variants
 }
 
 /**
  * Build a single variant
  */
-function buildVariant({ crateDir, targetDir, wasmFileStem, variant, release }) {
+export function buildVariant({ crateDir, targetDir, wasmFileStem, variant, release }) {
   const { name, rustflags, features } = variant
   
   console.log(`  Building ${name}...`)
@@ -39,7 +40,7 @@ function buildVariant({ crateDir, targetDir, wasmFileStem, variant, release }) {
     args.push('--features', features.join(','))
   }
   
-  const env = { ...process.env, RUSTFLAGS: rustflags }
+  const env = { ...process.env, ...variant.env, RUSTFLAGS: rustflags }
   if (targetDir) env.CARGO_TARGET_DIR = targetDir
   
   try {
@@ -81,7 +82,7 @@ function runSimdDetect(wasmPath, variantName) {
 /**
  * Generate variant configurations from simd config
  */
-function generateVariants(simdConfig) {
+export function generateVariants(simdConfig, benchConfig) {
   const variants = []
   const features = simdConfig?.features || {}
   const featureNames = Object.keys(features)
@@ -132,6 +133,18 @@ function generateVariants(simdConfig) {
     })
   }
   
+  // Inner-loop stride matrix: kernels read WBL_KERNEL_STRIDE at build time
+  for (const stride of benchConfig?.strides || []) {
+    variants.push({
+      name: `stride-${stride}`,
+      description: `SIMD with ${stride}-byte inner stride`,
+      rustflags: '-C opt-level=3 -C target-feature=+simd128',
+      features: [],
+      env: { WBL_KERNEL_STRIDE: String(stride) },
+      simd: true,
+    })
+  }
+  
   return variants
 }
 
@@ -316,16 +329,45 @@ async function runBenchmarks({ distDir, manifest, exports, benchConfig }) {
           // Copy input data
           let mem = new Uint8Array(wasm.memory.buffer)
           mem.set(testData, inPtr)
+
+          // Scatter/gather exports read the input through a one-entry
+          // (ptr, len) list.
+          let iovPtr = 0
+          if (exp.input === 'iovec') {
+            iovPtr = wasm.alloc_bytes(8)
+            const iov = new DataView(wasm.memory.buffer, iovPtr, 8)
+            iov.setUint32(0, inPtr, true)
+            iov.setUint32(4, actualInputSize, true)
+          }
+
+          // Callee-allocated exports take only the input and return a packed
+          // (ptr, len) block to release each call; the output buffer above
+          // goes unused.
+          const call =
+            exp.input === 'iovec'
+              ? () => fn(iovPtr, 1, outPtr, outSize)
+              : exp.output === 'callee'
+              ? () => {
+                  const packed = fn(inPtr, actualInputSize)
+                  if (packed === 0n) return -1
+                  wasm.free_bytes(
+                    Number(packed & 0xffffffffn),
+                    Number(packed >> 32n)
+                  )
+                  return 0
+                }
+              : () => fn(inPtr, actualInputSize, outPtr, outSize)
           
           // Warmup and verify function works
           let workingResult = -1
           for (let i = 0; i < warmupRuns; i++) {
             mem = new Uint8Array(wasm.memory.buffer)
-            workingResult = fn(inPtr, actualInputSize, outPtr, outSize)
+            workingResult = call()
           }
           
           // Skip if function returns error
           if (workingResult < 0) {
+            if (iovPtr) wasm.free_bytes(iovPtr, 8)
             wasm.free_bytes(inPtr, actualInputSize)
             wasm.free_bytes(outPtr, outSize)
             continue
@@ -339,13 +381,14 @@ async function runBenchmarks({ distDir, manifest, exports, benchConfig }) {
             mem = new Uint8Array(wasm.memory.buffer)
             const start = hrtimeMs()
             for (let j = 0; j < batchSize; j++) {
-              fn(inPtr, actualInputSize, outPtr, outSize)
+              call()
             }
             const end = hrtimeMs()
             times.push((end - start) / batchSize)
           }
           
           // Free buffers
+          if (iovPtr) wasm.free_bytes(iovPtr, 8)
           wasm.free_bytes(inPtr, actualInputSize)
           wasm.free_bytes(outPtr, outSize)
           
@@ -455,26 +498,31 @@ async function runBenchmarks({ distDir, manifest, exports, benchConfig }) {
 }
 
 /**
- * Build all variants and run SIMD analysis
+ * Cargo target directory of the crate
  */
-export function buildVariantsAndAnalyze({ cfg, outputDir }) {
-  const simdConfig = cfg.simd || {}
-  const variants = generateVariants(simdConfig)
-  
-  console.log(`\nBuilding ${variants.length} WASM variants...`)
-  
-  // Get target directory
-  let targetDir
+export function cargoTargetDir(crateDir) {
   try {
     const raw = execSync('cargo metadata --format-version 1 --no-deps', {
-      cwd: cfg.crateDir,
+      cwd: crateDir,
       stdio: ['ignore', 'pipe', 'inherit'],
     }).toString()
     const meta = JSON.parse(raw)
-    targetDir = meta?.target_directory || join(cfg.crateDir, 'target')
+    return meta?.target_directory || join(crateDir, 'target')
   } catch {
-    targetDir = join(cfg.crateDir, 'target')
+    return join(crateDir, 'target')
   }
+}
+
+/**
+ * Build all variants and run SIMD analysis
+ */
+export function buildVariantsAndAnalyze({ cfg, outputDir }) {
+  const simdConfig = cfg.simd || {}
+  const variants = generateVariants(simdConfig, cfg.bench)
+  
+  console.log(`\nBuilding ${variants.length} WASM variants...`)
+  
+  const targetDir = cargoTargetDir(cfg.crateDir)
   
   // Create output directories
   const distDir = join(outputDir, 'dist')
@@ -516,6 +564,7 @@ export function buildVariantsAndAnalyze({ cfg, outputDir }) {
         hash,
         size: wasmData.length,
         features: variant.features,
+        env: variant.env || null,
         simd: variant.simd,
       })
       
@@ -1,6 +1,7 @@
 import { execSync } from 'node:child_process'
-import { copyFileSync, mkdirSync } from 'node:fs'
+import { copyFileSync, mkdirSync, readFileSync, writeFileSync } from 'node:fs'
 import { join } from 'node:path'
+import { applyMultiValue } from './multivalue.js'
 
 function exec(cmd, options = {}) {
   try {
@@ -69,6 +70,15 @@ function wasmPath({ targetDir, release, wasmFileStem }) {
   )
 }
 
+// Builds with the crate's `multi-value` feature list exports whose results
+// must be moved from a return pointer into wasm multi-value results.
+function maybeApplyMultiValue(wasmFile) {
+  const rewritten = applyMultiValue(readFileSync(wasmFile))
+  if (!rewritten) return false
+  writeFileSync(wasmFile, rewritten)
+  return true
+}
+
 function maybeRunWasmOpt(wasmFile, wasmOpt, release) {
   if (wasmOpt.mode === 'off') return
   if (wasmOpt.mode === 'auto') {
@@ -130,7 +140,14 @@ export function buildArtifacts({
     const dest = join(wasmOutDir, `${artifactBaseName}.${suffix}.wasm`)
 
     copyFileSync(built, dest)
-    maybeRunWasmOpt(dest, wasmOpt)
+    if (maybeApplyMultiValue(dest)) {
+      maybeRunWasmOpt(dest, {
+        ...wasmOpt,
+        args: ['--enable-multivalue', ...wasmOpt.args],
+      })
+    } else {
+      maybeRunWasmOpt(dest, wasmOpt)
+    }
     return dest
   }
 
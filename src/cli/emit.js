@@ -1,15 +1,20 @@
 import { readFileSync, writeFileSync, mkdirSync, existsSync } from 'node:fs'
 import { join, extname } from 'node:path'
 import { createRequire } from 'node:module'
+import { createHash } from 'node:crypto'
 import { fileURLToPath } from 'node:url'
 
 const UTIL_PATH = fileURLToPath(new URL('../js/util.js', import.meta.url))
 const require = createRequire(import.meta.url)
 const TS_EXTS = new Set(['.ts', '.tsx', '.cts', '.mts'])
+// Calling conventions the generated glue expects; must match `ABI_VERSION`
+// in src/abi.rs.
+export const ABI_VERSION = 1
 
 export function buildWrapperIR(exportsList) {
   return exportsList.map((entry) => {
-    const { abi, name, return: retType, reuseBuffer, outSize } = entry
+    const { abi, name, return: retType, reuseBuffer, outSize, output, input } =
+      entry
     const returnType = retType || 'bytes'
     const fnName = name || abi
     const outSizeExpr =
@@ -25,6 +30,12 @@ export function buildWrapperIR(exportsList) {
       returnType,
       reuseBuffer: !!reuseBuffer,
       outSizeExpr,
+      // `callee`: the kernel takes only the input, allocates its own output
+      // and returns it packed; see `callCallee`.
+      output: output === 'callee' ? 'callee' : 'caller',
+      // `iovec`: the wrapper takes a list of chunks and the kernel reads
+      // them as one stream; see `callIovec`.
+      input: input === 'iovec' ? 'iovec' : 'bytes',
     }
   })
 }
@@ -34,9 +45,16 @@ export function createCore({ exportsList, autoInit, stream }) {
   const wrappersIR = buildWrapperIR(exportsList)
   const b = code()
 
+  b.line(`const ABI_VERSION = ${ABI_VERSION};`)
   b.line('let _inst = null;')
   b.line('let _memU8 = null;')
   b.line('let _initFn = null;')
+  b.line('let _pool = [];')
+  b.line('let _bindings = [];')
+  b.line('const _reuse = new WeakMap();')
+  b.line('const _resetHooks = [];')
+  b.line('let _onProgress = null;')
+  b.line('let _onLog = null;')
   b.blank()
 
   b.line('function refreshViews() {')
@@ -46,14 +64,174 @@ export function createCore({ exportsList, autoInit, stream }) {
   b.line('}')
   b.blank()
 
-  b.line('export function setInstance(instance) {')
+  b.line('function useInstance(instance) {')
   b.indent(() => {
+    b.line('if (_inst === instance) return;')
     b.line('_inst = instance;')
     b.line('refreshViews();')
   })
   b.line('}')
   b.blank()
 
+  b.line('export function setInstance(instance) {')
+  b.indent(() => {
+    b.line('setInstances([instance]);')
+  })
+  b.line('}')
+  b.blank()
+
+  // Pooled instances each own a separate linear memory, allocations and
+  // handle table. The top-level wrappers, alloc/free, memoryU8 and
+  // wasmExports stay pinned to the first instance, so pointers and handles
+  // they return remain valid on the next call; `instances()` binds each
+  // pooled instance explicitly for callers that spread work across them.
+  b.line('export function setInstances(instances) {')
+  b.indent(() => {
+    b.line('for (const instance of instances) {')
+    b.indent(() => {
+      b.line('const abi = instance.exports.abi_version?.();')
+      b.line('if (abi !== undefined && abi !== ABI_VERSION) {')
+      b.indent(() => {
+        b.line(
+          'throw new Error("wasm module ABI version " + abi + " does not match the JS glue (" + ABI_VERSION + "); rebuild the package or clear cached modules");'
+        )
+      })
+      b.line('}')
+      b.line('instance.exports.install_panic_hook?.();')
+    })
+    b.line('}')
+    b.line('_pool = instances;')
+    b.line('_inst = null;')
+    b.line('useInstance(instances[0]);')
+    b.line('_bindings = instances.map(bindInstance);')
+  })
+  b.line('}')
+  b.blank()
+
+  b.line('export function poolSize() {')
+  b.indent(() => {
+    b.line('return _pool.length;')
+  })
+  b.line('}')
+  b.blank()
+
+  b.line('export function instances() {')
+  b.indent(() => {
+    b.line('return _bindings;')
+  })
+  b.line('}')
+  b.blank()
+
+  // Run `fn` against `instance`, then switch back to the pinned one.
+  b.line('function onInstance(instance, fn, args) {')
+  b.indent(() => {
+    b.line('const prev = _inst;')
+    b.line('useInstance(instance);')
+    b.line('try {')
+    b.indent(() => {
+      b.line('return fn(...args);')
+    })
+    b.line('} finally {')
+    b.indent(() => {
+      b.line('useInstance(prev);')
+    })
+    b.line('}')
+  })
+  b.line('}')
+  b.blank()
+
+  b.line('function reuseSlot(name) {')
+  b.indent(() => {
+    b.line('let slots = _reuse.get(_inst);')
+    b.line('if (!slots) _reuse.set(_inst, (slots = {}));')
+    b.line(
+      'return (slots[name] ??= { in: { ptr: 0, len: 0 }, out: { ptr: 0, len: 0 } });'
+    )
+  })
+  b.line('}')
+  b.blank()
+
+  b.line('export function onReset(fn) {')
+  b.indent(() => {
+    b.line('_resetHooks.push(fn);')
+  })
+  b.line('}')
+  b.blank()
+
+  // Modules built with the `progress` or `log` feature import
+  // env.wbl_progress / env.wbl_log. An import cannot be optional, so the
+  // loader always supplies these defaults (host-provided ones win), which
+  // forward to the listeners set with onProgress and onLog.
+  b.line('export function onProgress(fn) {')
+  b.indent(() => {
+    b.line('_onProgress = fn;')
+  })
+  b.line('}')
+  b.blank()
+
+  b.line('export function onLog(fn) {')
+  b.indent(() => {
+    b.line('_onLog = fn;')
+  })
+  b.line('}')
+  b.blank()
+
+  // Levels as in the `log` crate: 1 error, 2 warn, 3 info, 4 debug, 5 trace.
+  b.line('function writeLog(level, ptr, len) {')
+  b.indent(() => {
+    b.line(
+      'const message = new TextDecoder().decode(memoryU8().subarray(ptr, ptr + len));'
+    )
+    b.line('if (_onLog) return _onLog(level, message);')
+    b.line(
+      'const method = [, "error", "warn", "info", "debug", "debug"][level] ?? "log";'
+    )
+    b.line('console[method](message);')
+  })
+  b.line('}')
+  b.blank()
+
+  b.line('export function withRuntimeImports(imports = {}) {')
+  b.indent(() => {
+    b.line(
+      'const wbl_progress = (done, total) => _onProgress?.(done, total);'
+    )
+    b.line(
+      'return { ...imports, env: { wbl_progress, wbl_log: writeLog, ...imports.env } };'
+    )
+  })
+  b.line('}')
+  b.blank()
+
+  // Run registered cleanup hooks, then release Rust-side handles, reclaim
+  // the arena and scratch buffer, and free every reuse buffer in every
+  // pooled instance.
+  // The loader's reset() calls this before re-instantiating.
+  b.line('export function resetState() {')
+  b.indent(() => {
+    b.line('for (const fn of _resetHooks) fn();')
+    b.line('for (const instance of _pool) {')
+    b.indent(() => {
+      b.line('instance.exports.handle_clear_all?.();')
+      b.line('instance.exports.arena_reset?.();')
+      b.line('instance.exports.scratch_release?.();')
+      b.line('const slots = _reuse.get(instance);')
+      b.line('if (!slots) continue;')
+      b.line('useInstance(instance);')
+      b.line('for (const slot of Object.values(slots)) {')
+      b.indent(() => {
+        b.line('if (slot.in.ptr) free(slot.in.ptr, slot.in.len);')
+        b.line('if (slot.out.ptr) free(slot.out.ptr, slot.out.len);')
+      })
+      b.line('}')
+      b.line('_reuse.delete(instance);')
+    })
+    b.line('}')
+    b.line('if (_pool.length) useInstance(_pool[0]);')
+  })
+  b.line('}')
+  b.blank()
+
   b.line('export function wasmExports() {')
   b.indent(() => {
     b.line('return _inst.exports;')
@@ -88,9 +266,191 @@ export function createCore({ exportsList, autoInit, stream }) {
   b.line('}')
   b.blank()
 
+  // alloc_bytes returns null instead of trapping when memory cannot grow, so
+  // callers can catch WasmAllocError and retry with smaller chunks.
+  b.line('export class WasmAllocError extends RangeError {')
+  b.indent(() => {
+    b.line('constructor(len, code) {')
+    b.indent(() => {
+      b.line(
+        'super("alloc_bytes(" + len + ") failed" + (code ? " with code " + code : ""));'
+      )
+      b.line('this.name = "WasmAllocError";')
+      b.line('this.len = len;')
+      b.line('this.code = code;')
+    })
+    b.line('}')
+  })
+  b.line('}')
+  b.blank()
+
+  // A panicking kernel traps with a bare "unreachable"; when the module
+  // exports the panic hook, rethrow the trap with the recorded message.
+  b.line('export class WasmPanicError extends Error {')
+  b.indent(() => {
+    b.line('constructor(message, cause) {')
+    b.indent(() => {
+      b.line('super(message, { cause });')
+      b.line('this.name = "WasmPanicError";')
+    })
+    b.line('}')
+  })
+  b.line('}')
+  b.blank()
+
+  // Kernels that know why they failed record an error code; report it (and
+  // clear it) when the module exports one.
+  b.line('export class WasmError extends Error {')
+  b.indent(() => {
+    b.line('constructor(abi, status, code, detail) {')
+    b.indent(() => {
+      b.line(
+        'super(abi + " failed: " + (code ? detail + " (code " + code + ")" : status));'
+      )
+      b.line('this.name = "WasmError";')
+      b.line('this.abi = abi;')
+      b.line('this.status = status;')
+      b.line('this.code = code;')
+    })
+    b.line('}')
+  })
+  b.line('}')
+  b.blank()
+
+  // A packed (ptr, len) return, as a BigInt: pointer in the low 32 bits,
+  // length in the high 32. `0n` is a failure.
+  b.line('export function packedView(packed) {')
+  b.indent(() => {
+    b.line('if (packed === 0n) return null;')
+    b.line('const ptr = Number(packed & 0xffffffffn);')
+    b.line('return memoryU8().subarray(ptr, ptr + Number(packed >> 32n));')
+  })
+  b.line('}')
+  b.blank()
+
+  b.line('function wasmError(abi, status) {')
+  b.indent(() => {
+    b.line('const code = _inst.exports.last_error_code?.() ?? 0;')
+    b.line('if (!code) return new WasmError(abi, status, 0, "");')
+    b.line('let detail;')
+    b.line('if (_inst.exports.last_error_message_packed) {')
+    b.indent(() => {
+      b.line(
+        'const bytes = packedView(_inst.exports.last_error_message_packed());'
+      )
+      b.line('detail = new TextDecoder().decode(bytes);')
+    })
+    b.line('} else {')
+    b.indent(() => {
+      b.line('const len = _inst.exports.last_error_message(0, 0);')
+      b.line('const ptr = alloc(len);')
+      b.line('_inst.exports.last_error_message(ptr, len);')
+      b.line(
+        'detail = new TextDecoder().decode(memoryU8().subarray(ptr, ptr + len));'
+      )
+      b.line('free(ptr, len);')
+    })
+    b.line('}')
+    b.line('_inst.exports.last_error_clear();')
+    b.line('return new WasmError(abi, status, code, detail);')
+  })
+  b.line('}')
+  b.blank()
+
+  // Modules without `last_panic_packed`.
+  b.line('function panicBytes() {')
+  b.indent(() => {
+    b.line('const len = _inst.exports.last_panic_len?.() ?? 0;')
+    b.line('const ptr = len ? _inst.exports.last_panic_ptr() >>> 0 : 0;')
+    b.line('return memoryU8().subarray(ptr, ptr + len);')
+  })
+  b.line('}')
+  b.blank()
+
+  b.line('function panicError(err) {')
+  b.indent(() => {
+    b.line('if (!(err instanceof WebAssembly.RuntimeError)) return err;')
+    b.line('const bytes = _inst.exports.last_panic_packed')
+    b.line('  ? packedView(_inst.exports.last_panic_packed())')
+    b.line('  : panicBytes();')
+    b.line('if (!bytes?.length) return err;')
+    b.line('const message = new TextDecoder().decode(bytes);')
+    b.line('_inst.exports.last_panic_clear();')
+    b.line('return new WasmPanicError(message, err);')
+  })
+  b.line('}')
+  b.blank()
+
   b.line('export function alloc(len) {')
   b.indent(() => {
-    b.line('return _inst.exports.alloc_bytes(len) >>> 0;')
+    b.line('const ptr = _inst.exports.alloc_bytes(len) >>> 0;')
+    b.line('if (ptr === 0 && len > 0) {')
+    b.indent(() => {
+      b.line(
+        'throw new WasmAllocError(len, _inst.exports.alloc_last_error?.() ?? 0);'
+      )
+    })
+    b.line('}')
+    b.line('return ptr;')
+  })
+  b.line('}')
+  b.blank()
+
+  // Grows or shrinks a buffer inside the module, so streaming output can
+  // expand without a JS-side alloc/copy/free round trip. On failure the old
+  // buffer is still valid and owned by the caller.
+  b.line('export function realloc(ptr, oldLen, newLen) {')
+  b.indent(() => {
+    b.line(
+      'const next = _inst.exports.realloc_bytes(ptr >>> 0, oldLen >>> 0, newLen >>> 0) >>> 0;'
+    )
+    b.line('if (next === 0 && newLen > 0) {')
+    b.indent(() => {
+      b.line(
+        'throw new WasmAllocError(newLen, _inst.exports.alloc_last_error?.() ?? 0);'
+      )
+    })
+    b.line('}')
+    b.line('return next;')
+  })
+  b.line('}')
+  b.blank()
+
+  b.line('export function allocZeroed(len) {')
+  b.indent(() => {
+    b.line('const ptr = _inst.exports.alloc_zeroed_bytes(len) >>> 0;')
+    b.line('if (ptr === 0 && len > 0) {')
+    b.indent(() => {
+      b.line(
+        'throw new WasmAllocError(len, _inst.exports.alloc_last_error?.() ?? 0);'
+      )
+    })
+    b.line('}')
+    b.line('return ptr;')
+  })
+  b.line('}')
+  b.blank()
+
+  b.line('export function allocAligned(len, align) {')
+  b.indent(() => {
+    b.line(
+      'const ptr = _inst.exports.alloc_aligned(len >>> 0, align >>> 0) >>> 0;'
+    )
+    b.line('if (ptr === 0) {')
+    b.indent(() => {
+      b.line(
+        'throw new WasmAllocError(len, _inst.exports.alloc_last_error?.() ?? 0);'
+      )
+    })
+    b.line('}')
+    b.line('return ptr;')
+  })
+  b.line('}')
+  b.blank()
+
+  b.line('export function freeAligned(ptr, len, align) {')
+  b.indent(() => {
+    b.line('_inst.exports.free_aligned(ptr >>> 0, len >>> 0, align >>> 0);')
   })
   b.line('}')
   b.blank()
@@ -177,6 +537,7 @@ export function createCore({ exportsList, autoInit, stream }) {
       b.line('if (reuse.in.len < len) {')
       b.indent(() => {
         b.line('if (reuse.in.ptr) free(reuse.in.ptr, reuse.in.len);')
+        b.line('reuse.in.ptr = reuse.in.len = 0;')
         b.line('reuse.in.ptr = alloc(len);')
         b.line('reuse.in.len = len;')
       })
@@ -184,6 +545,7 @@ export function createCore({ exportsList, autoInit, stream }) {
       b.line('if (reuse.out.len < outLen) {')
       b.indent(() => {
         b.line('if (reuse.out.ptr) free(reuse.out.ptr, reuse.out.len);')
+        b.line('reuse.out.ptr = reuse.out.len = 0;')
         b.line('reuse.out.ptr = alloc(outLen);')
         b.line('reuse.out.len = outLen;')
       })
@@ -194,41 +556,200 @@ export function createCore({ exportsList, autoInit, stream }) {
     b.line('} else {')
     b.indent(() => {
       b.line('inPtr = alloc(len);')
-      b.line('outPtr = alloc(outLen);')
+      b.line('try {')
+      b.indent(() => {
+        b.line('outPtr = alloc(outLen);')
+      })
+      b.line('} catch (err) {')
+      b.indent(() => {
+        b.line('free(inPtr, len);')
+        b.line('throw err;')
+      })
+      b.line('}')
     })
     b.line('}')
     b.blank()
-    b.line('memoryU8().set(view, inPtr);')
-    b.line('const written = _inst.exports[abi](inPtr, len, outPtr, outLen);')
-    b.line('if (written < 0) {')
+    b.line('let written = -1;')
+    b.line('try {')
     b.indent(() => {
-      b.line('if (!reuse) { free(inPtr, len); free(outPtr, outLen); }')
-      b.line('throw new Error(abi + " failed: " + written);')
+      b.line('memoryU8().set(view, inPtr);')
+      b.line('written = _inst.exports[abi](inPtr, len, outPtr, outLen);')
+    })
+    b.line('} catch (err) {')
+    b.indent(() => {
+      b.line('throw panicError(err);')
+    })
+    b.line('} finally {')
+    b.indent(() => {
+      // On success the wrapper frees both after copying the result out.
+      b.line('if (written < 0 && !reuse) {')
+      b.indent(() => {
+        b.line('free(inPtr, len);')
+        b.line('free(outPtr, outLen);')
+      })
+      b.line('}')
     })
     b.line('}')
+    b.line('if (written < 0) throw wasmError(abi, written);')
     b.blank()
     b.line('return { inPtr, outPtr, len, outLen, written };')
   })
   b.line('}')
   b.blank()
 
-  // Wrappers
-  wrappersIR.forEach((w) => {
-    if (w.reuseBuffer) {
-      b.line(
-        `const _${w.fnName}_reuse = { in: { ptr: 0, len: 0 }, out: { ptr: 0, len: 0 } };`
-      )
-    }
-    const asyncPrefix = needsEnsure ? 'async ' : ''
-    b.line(`${asyncPrefix}function ${w.fnName}(input) {`)
+  if (wrappersIR.some((w) => w.output === 'callee')) {
+    // Kernels that allocate their output with `alloc_bytes` and return it
+    // packed like `packedView`. The caller owns the block and releases it
+    // with `free_bytes`; `0n` is a failure.
+    b.line('function callCallee(abi, input, reuse) {')
     b.indent(() => {
-      if (needsEnsure) b.line('await ensureReady();')
+      b.line('if (!_inst) throw new Error("WASM instance not initialized");')
       b.line('const view = toBytes(input);')
       b.line('const len = view.byteLength;')
-      b.line(`const outLen = ${w.outSizeExpr};`)
+      b.line('let inPtr;')
+      b.line('if (reuse) {')
+      b.indent(() => {
+        b.line('if (reuse.in.len < len) {')
+        b.indent(() => {
+          b.line('if (reuse.in.ptr) free(reuse.in.ptr, reuse.in.len);')
+          b.line('reuse.in.ptr = reuse.in.len = 0;')
+          b.line('reuse.in.ptr = alloc(len);')
+          b.line('reuse.in.len = len;')
+        })
+        b.line('}')
+        b.line('inPtr = reuse.in.ptr;')
+      })
+      b.line('} else {')
+      b.indent(() => {
+        b.line('inPtr = alloc(len);')
+      })
+      b.line('}')
+      b.blank()
+      b.line('memoryU8().set(view, inPtr);')
+      b.line('let packed;')
+      b.line('try {')
+      b.indent(() => {
+        b.line('packed = _inst.exports[abi](inPtr, len);')
+      })
+      b.line('} catch (err) {')
+      b.indent(() => {
+        b.line('if (!reuse) free(inPtr, len);')
+        b.line('throw panicError(err);')
+      })
+      b.line('}')
+      b.line('if (!reuse) free(inPtr, len);')
+      b.line('if (packed === 0n) throw wasmError(abi, -1);')
+      b.blank()
       b.line(
-        `const { outPtr, written, inPtr } = callWasm("${w.abi}", view, outLen, ${w.reuseBuffer ? `_${w.fnName}_reuse` : 'null'});`
+        'return { outPtr: Number(packed & 0xffffffffn), written: Number(packed >> 32n) };'
       )
+    })
+    b.line('}')
+    b.blank()
+  }
+
+  if (wrappersIR.some((w) => w.input === 'iovec')) {
+    // Scatter/gather kernels take `(iov_ptr, iov_count, out_ptr, out_len)`,
+    // where `iov_ptr` is an array of `(ptr, len)` u32 pairs. Each chunk gets
+    // its own allocation, so nothing is concatenated on either side.
+    b.line('function callIovec(abi, views, outLen) {')
+    b.indent(() => {
+      b.line('if (!_inst) throw new Error("WASM instance not initialized");')
+      b.line('const blocks = [];')
+      b.line('let iovPtr = 0, outPtr = 0;')
+      b.line('const release = () => {')
+      b.indent(() => {
+        b.line('for (const [ptr, len] of blocks) free(ptr, len);')
+        b.line('free(iovPtr, views.length * 8);')
+      })
+      b.line('};')
+      b.line('try {')
+      b.indent(() => {
+        b.line('iovPtr = alloc(views.length * 8);')
+        b.line('for (const view of views) {')
+        b.indent(() => {
+          b.line('const ptr = alloc(view.byteLength);')
+          b.line('blocks.push([ptr, view.byteLength]);')
+          b.line('memoryU8().set(view, ptr);')
+        })
+        b.line('}')
+        b.line('outPtr = alloc(outLen);')
+      })
+      b.line('} catch (err) {')
+      b.indent(() => {
+        b.line('release();')
+        b.line('throw err;')
+      })
+      b.line('}')
+      b.line(
+        'const iov = new DataView(memoryU8().buffer, iovPtr, views.length * 8);'
+      )
+      b.line('blocks.forEach(([ptr, len], i) => {')
+      b.indent(() => {
+        b.line('iov.setUint32(i * 8, ptr, true);')
+        b.line('iov.setUint32(i * 8 + 4, len, true);')
+      })
+      b.line('});')
+      b.blank()
+      b.line('let written = -1;')
+      b.line('try {')
+      b.indent(() => {
+        b.line(
+          'written = _inst.exports[abi](iovPtr, views.length, outPtr, outLen);'
+        )
+      })
+      b.line('} catch (err) {')
+      b.indent(() => {
+        b.line('throw panicError(err);')
+      })
+      b.line('} finally {')
+      b.indent(() => {
+        b.line('release();')
+        b.line('if (written < 0) free(outPtr, outLen);')
+      })
+      b.line('}')
+      b.line('if (written < 0) throw wasmError(abi, written);')
+      b.line('return { outPtr, written };')
+    })
+    b.line('}')
+    b.blank()
+  }
+
+  // Wrappers
+  wrappersIR.forEach((w) => {
+    // Lazy wrappers await init, then run the synchronous body, which
+    // `instances()` bindings also call directly.
+    if (needsEnsure) {
+      b.line(`async function ${w.fnName}(input) {`)
+      b.indent(() => {
+        b.line('await ensureReady();')
+        b.line(`return ${bodyName(w, needsEnsure)}(input);`)
+      })
+      b.line('}')
+      b.blank()
+    }
+    b.line(`function ${bodyName(w, needsEnsure)}(input) {`)
+    b.indent(() => {
+      if (w.input !== 'iovec') b.line('const view = toBytes(input);')
+      const reuse = w.reuseBuffer ? `reuseSlot("${w.fnName}")` : 'null'
+      if (w.input === 'iovec') {
+        b.line('const views = Array.from(input, toBytes);')
+        b.line('const len = views.reduce((n, v) => n + v.byteLength, 0);')
+        b.line(`const outLen = ${w.outSizeExpr};`)
+        b.line(
+          `const { outPtr, written } = callIovec("${w.abi}", views, outLen);`
+        )
+      } else if (w.output === 'callee') {
+        b.line(
+          `const { outPtr, written } = callCallee("${w.abi}", view, ${reuse});`
+        )
+      } else {
+        b.line('const len = view.byteLength;')
+        b.line(`const outLen = ${w.outSizeExpr};`)
+        b.line(
+          `const { outPtr, written, inPtr } = callWasm("${w.abi}", view, outLen, ${reuse});`
+        )
+      }
       b.blank()
       if (w.returnType === 'bytes') {
         b.line('const result = memoryU8().slice(outPtr, outPtr + written);')
@@ -239,7 +760,11 @@ export function createCore({ exportsList, autoInit, stream }) {
         b.line(`const result = decodeReturn(retView, "${w.returnType}");`)
       }
       b.blank()
-      if (!w.reuseBuffer) {
+      if (w.input === 'iovec') {
+        b.line('free(outPtr, outLen);')
+      } else if (w.output === 'callee') {
+        b.line('free(outPtr, written);')
+      } else if (!w.reuseBuffer) {
         b.line('free(inPtr, len);')
         b.line('free(outPtr, outLen);')
       }
@@ -250,6 +775,24 @@ export function createCore({ exportsList, autoInit, stream }) {
     b.blank()
   })
 
+  b.line('function bindInstance(instance) {')
+  b.indent(() => {
+    b.line('const on = (fn) => (...args) => onInstance(instance, fn, args);')
+    b.line('return Object.freeze({')
+    b.indent(() => {
+      b.line('exports: instance.exports,')
+      b.line('memoryU8: on(memoryU8),')
+      b.line('alloc: on(alloc),')
+      b.line('free: on(free),')
+      wrappersIR.forEach((w) => {
+        b.line(`${w.fnName}: on(${bodyName(w, needsEnsure)}),`)
+      })
+    })
+    b.line('});')
+  })
+  b.line('}')
+  b.blank()
+
   // Streaming
   if (stream?.enable) {
     b.line('const __exports = {')
@@ -370,6 +913,33 @@ export function createCore({ exportsList, autoInit, stream }) {
   return b.toString()
 }
 
+function bodyName(w, needsEnsure) {
+  return needsEnsure ? `_${w.fnName}` : w.fnName
+}
+
+function tsReturnType(returnType) {
+  switch (returnType) {
+    case 'f32':
+    case 'f64':
+    case 'i32':
+    case 'u32':
+    case 'i16':
+    case 'u16':
+    case 'i8':
+    case 'u8':
+      return 'number'
+    case 'u32_array':
+      return 'Uint32Array'
+    case 'i32_array':
+      return 'Int32Array'
+    case 'f32_array':
+      return 'Float32Array'
+    case 'bytes':
+    default:
+      return 'Uint8Array'
+  }
+}
+
 export function createCoreTypes({ exportsList, autoInit, stream }) {
   const needsEnsure = autoInit === 'lazy'
   const wrappersIR = buildWrapperIR(exportsList)
@@ -379,42 +949,82 @@ export function createCoreTypes({ exportsList, autoInit, stream }) {
   b.blank()
 
   b.line('export function setInstance(instance: WebAssembly.Instance): void;')
+  b.line(
+    'export function setInstances(instances: WebAssembly.Instance[]): void;'
+  )
+  b.line('export function poolSize(): number;')
+  b.line('export function onReset(fn: () => void): void;')
+  b.line(
+    'export function onProgress(fn: ((done: number, total: number) => void) | null): void;'
+  )
+  b.line(
+    'export function onLog(fn: ((level: number, message: string) => void) | null): void;'
+  )
+  b.line(
+    'export function withRuntimeImports(imports?: WebAssembly.Imports): WebAssembly.Imports;'
+  )
+  b.line('export function packedView(packed: bigint): Uint8Array | null;')
+  b.line('export function resetState(): void;')
   b.line('export function wasmExports(): WebAssembly.Exports;')
   b.line('export function memoryU8(): Uint8Array;')
+  b.line('export class WasmAllocError extends RangeError {')
+  b.indent(() => {
+    b.line('readonly len: number;')
+    b.line('readonly code: number;')
+    b.line('constructor(len: number, code: number);')
+  })
+  b.line('}')
+  b.line('export class WasmError extends Error {')
+  b.indent(() => {
+    b.line('readonly abi: string;')
+    b.line('readonly status: number;')
+    b.line('readonly code: number;')
+    b.line(
+      'constructor(abi: string, status: number, code: number, detail: string);'
+    )
+  })
+  b.line('}')
+  b.line('export class WasmPanicError extends Error {')
+  b.indent(() => {
+    b.line('constructor(message: string, cause: unknown);')
+  })
+  b.line('}')
   b.line('export function alloc(len: number): number;')
+  b.line(
+    'export function realloc(ptr: number, oldLen: number, newLen: number): number;'
+  )
+  b.line('export function allocZeroed(len: number): number;')
+  b.line('export function allocAligned(len: number, align: number): number;')
+  b.line(
+    'export function freeAligned(ptr: number, len: number, align: number): void;'
+  )
   b.line('export function free(ptr: number, len: number): void;')
   b.blank()
 
+  const inputType = (w) =>
+    w.input === 'iovec' ? 'Iterable<WasmInput>' : 'WasmInput'
   wrappersIR.forEach((w) => {
-    let tsRetType
-    switch (w.returnType) {
-      case 'f32':
-      case 'f64':
-      case 'i32':
-      case 'u32':
-      case 'i16':
-      case 'u16':
-      case 'i8':
-      case 'u8':
-        tsRetType = 'number'
-        break
-      case 'u32_array':
-        tsRetType = 'Uint32Array'
-        break
-      case 'i32_array':
-        tsRetType = 'Int32Array'
-        break
-      case 'f32_array':
-        tsRetType = 'Float32Array'
-        break
-      case 'bytes':
-      default:
-        tsRetType = 'Uint8Array'
-    }
-
+    const tsRetType = tsReturnType(w.returnType)
     const ret = needsEnsure ? `Promise<${tsRetType}>` : tsRetType
-    b.line(`export function ${w.fnName}(input: WasmInput): ${ret};`)
+    b.line(`export function ${w.fnName}(input: ${inputType(w)}): ${ret};`)
+  })
+  b.blank()
+
+  // Bindings only exist after init, so their wrappers are synchronous.
+  b.line('export interface PooledInstance {')
+  b.indent(() => {
+    b.line('readonly exports: WebAssembly.Exports;')
+    b.line('memoryU8(): Uint8Array;')
+    b.line('alloc(len: number): number;')
+    b.line('free(ptr: number, len: number): void;')
+    wrappersIR.forEach((w) => {
+      b.line(
+        `${w.fnName}(input: ${inputType(w)}): ${tsReturnType(w.returnType)};`
+      )
+    })
   })
+  b.line('}')
+  b.line('export function instances(): PooledInstance[];')
 
   if (stream?.enable) {
     b.blank()
@@ -454,39 +1064,91 @@ export function code() {
 export function createLoaderTypes({ exportFrom }) {
   return `export interface InitOptions {
   backend?: 'auto' | 'simd' | 'base';
+  pool?: number;
+  cache?: boolean;
+  snapshot?: boolean;
+  warmup?: (exports: WebAssembly.Exports) => void | Promise<void>;
+}
+export interface ResetOptions {
+  shrink?: boolean;
 }
 export function init(imports?: WebAssembly.Imports, opts?: InitOptions): Promise<void>;
+export function reset(opts?: ResetOptions): Promise<void>;
 export * from "${exportFrom}";
 `
 }
 
-export function createLoader({ exportFrom, autoInit, getBytesSrc }) {
+export function createLoader({
+  exportFrom,
+  autoInit,
+  getBytesSrc,
+  cacheKey = 'wasm',
+}) {
   const eager =
     autoInit === 'eager'
       ? '\nregisterInit(init);\ninit();'
       : '\nregisterInit(init);'
 
-  return `import { setInstance, registerInit } from "./core.js";
-import { instantiateWithBackend } from "./util.js";
+  return `import { setInstances, registerInit, resetState, withRuntimeImports } from "./core.js";
+import { instantiateWithBackend, instantiatePool, applySnapshot, snapshotInstance } from "./util.js";
 ${getBytesSrc}
+const cacheKey = ${JSON.stringify(cacheKey)};
 
 let _ready = null;
 let _backend = null;
+let _pool = 1;
+let _module = null;
+let _imports = {};
+let _snapshot = null;
 export function init(imports = {}, opts = {}) {
   const backend = opts.backend || 'auto';
-  if (_ready && _backend === backend) return _ready;
+  const pool = Math.max(1, opts.pool || 1);
+  if (_ready && _backend === backend && _pool === pool) return _ready;
   _backend = backend;
+  _pool = pool;
+  _imports = withRuntimeImports(imports);
   return (_ready = (async () => {
-    const { instance } = await instantiateWithBackend({ getSimdBytes, getBaseBytes, imports, backend });
-    setInstance(instance);
+    const { instance, module, backend: used } = await instantiateWithBackend({
+      getSimdBytes,
+      getBaseBytes,
+      imports: _imports,
+      backend,
+      cacheKey: opts.cache ? cacheKey : null,
+    });
+    _module = module;
+    _snapshot = opts.snapshot
+      ? await snapshotInstance(instance, cacheKey + ":" + used + ":memory", opts.warmup)
+      : null;
+    setInstances([instance, ...(await spawnInstances(pool - 1))]);
   })());
 }
+
+async function spawnInstances(count) {
+  const instances = await instantiatePool(_module, _imports, count);
+  if (_snapshot) instances.forEach((instance) => applySnapshot(instance, _snapshot));
+  return instances;
+}
+
+export async function reset(opts = {}) {
+  if (!_ready) return;
+  await _ready;
+  resetState();
+  if (opts.shrink) {
+    setInstances(await spawnInstances(_pool));
+  }
+}
 ${eager}
 export * from "${exportFrom}";
 `
 }
 
-function createBrowserLoader({ name, autoInit, customJs, wasmDelivery }) {
+function createBrowserLoader({
+  name,
+  cacheKey,
+  autoInit,
+  customJs,
+  wasmDelivery,
+}) {
   const exportFrom = customJs ? './custom.js' : './core.js'
 
   let simdUrl, baseUrl
@@ -514,10 +1176,10 @@ async function getBaseBytes() {
   return res.arrayBuffer();
 }
 `
-  return createLoader({ exportFrom, autoInit, getBytesSrc })
+  return createLoader({ exportFrom, autoInit, getBytesSrc, cacheKey })
 }
 
-function createNodeLoader({ name, autoInit, customJs }) {
+function createNodeLoader({ name, cacheKey, autoInit, customJs }) {
   const exportFrom = customJs ? './custom.js' : './core.js'
   const getBytesSrc = `
 import { readFile } from "node:fs/promises";
@@ -534,10 +1196,10 @@ async function getBaseBytes() {
   return readFile(basePath);
 }
 `
-  return createLoader({ exportFrom, autoInit, getBytesSrc })
+  return createLoader({ exportFrom, autoInit, getBytesSrc, cacheKey })
 }
 
-function createInlineLoader({ name, autoInit, customJs }) {
+function createInlineLoader({ name, cacheKey, autoInit, customJs }) {
   const exportFrom = customJs ? './custom.js' : './core.js'
   const getBytesSrc = `
 import { wasmBytes as _simdBytes } from "./wasm-inline/${name}.simd.wasm.js";
@@ -551,7 +1213,7 @@ async function getBaseBytes() {
   return _baseBytes;
 }
 `
-  return createLoader({ exportFrom, autoInit, getBytesSrc })
+  return createLoader({ exportFrom, autoInit, getBytesSrc, cacheKey })
 }
 
 function createInlineModule(bytes) {
@@ -614,6 +1276,16 @@ function writeInlineModules({
   )
 }
 
+// Cached modules and snapshots are keyed by the artifact contents, so a new
+// build never restores state compiled from an older one.
+export function createCacheKey(name, { baselinePath, simdPath }) {
+  const hash = createHash('sha256')
+  for (const path of [baselinePath, simdPath]) {
+    if (path && existsSync(path)) hash.update(readFileSync(path))
+  }
+  return `${name}@${hash.digest('hex').slice(0, 16)}`
+}
+
 export function emitRuntime({
   crateDir,
   outDir,
@@ -658,6 +1330,7 @@ export function emitRuntime({
   }
   writeFileSync(join(outDir, 'util.js'), readFileSync(UTIL_PATH, 'utf8'))
 
+  const cacheKey = createCacheKey(artifactBaseName, wasmPaths)
   const loaderTypes = emitTypes
     ? createLoaderTypes({
         exportFrom: customJs ? './custom.js' : './core.js',
@@ -669,6 +1342,7 @@ export function emitRuntime({
       join(outDir, 'browser.js'),
       createBrowserLoader({
         name: artifactBaseName,
+        cacheKey,
         autoInit,
         customJs,
         wasmDelivery,
@@ -680,7 +1354,12 @@ export function emitRuntime({
   if (emitNode) {
     writeFileSync(
       join(outDir, 'node.js'),
-      createNodeLoader({ name: artifactBaseName, autoInit, customJs })
+      createNodeLoader({
+        name: artifactBaseName,
+        cacheKey,
+        autoInit,
+        customJs,
+      })
     )
     if (emitTypes) writeFileSync(join(outDir, 'node.d.ts'), loaderTypes)
   }
@@ -688,13 +1367,23 @@ export function emitRuntime({
   if (emitInline && wasmPaths.baselinePath) {
     writeFileSync(
       join(outDir, 'browser-inline.js'),
-      createInlineLoader({ name: artifactBaseName, autoInit, customJs })
+      createInlineLoader({
+        name: artifactBaseName,
+        cacheKey,
+        autoInit,
+        customJs,
+      })
     )
     if (emitTypes)
       writeFileSync(join(outDir, 'browser-inline.d.ts'), loaderTypes)
     writeFileSync(
       join(outDir, 'node-inline.js'),
-      createInlineLoader({ name: artifactBaseName, autoInit, customJs })
+      createInlineLoader({
+        name: artifactBaseName,
+        cacheKey,
+        autoInit,
+        customJs,
+      })
     )
     if (emitTypes) writeFileSync(join(outDir, 'node-inline.d.ts'), loaderTypes)
     writeInlineModules({
@@ -0,0 +1,294 @@
+// Rewrites the exports a `multi-value` build lists in its `wbl.multi_value`
+// custom section. rustc returns their `#[repr(C)]` result struct through a
+// hidden pointer (first parameter, no results); each becomes a wrapper that
+// reserves the struct on the shadow stack, calls the original and returns
+// the fields as wasm multi-value results. See src/multi_value.rs.
+
+const SECTION = 'wbl.multi_value'
+
+const VALTYPES = { i32: 0x7f, i64: 0x7e, f32: 0x7d, f64: 0x7c }
+const SIZES = { i32: 4, i64: 8, f32: 4, f64: 8 }
+// i32.load, i64.load, f32.load, f64.load
+const LOADS = { i32: 0x28, i64: 0x29, f32: 0x2a, f64: 0x2b }
+
+class Reader {
+  constructor(bytes, pos = 0) {
+    this.bytes = bytes
+    this.pos = pos
+  }
+
+  byte() {
+    return this.bytes[this.pos++]
+  }
+
+  u32() {
+    let result = 0
+    let shift = 0
+    for (;;) {
+      const b = this.byte()
+      result |= (b & 0x7f) << shift
+      if (!(b & 0x80)) return result >>> 0
+      shift += 7
+    }
+  }
+
+  name() {
+    const len = this.u32()
+    const name = new TextDecoder().decode(
+      this.bytes.subarray(this.pos, this.pos + len)
+    )
+    this.pos += len
+    return name
+  }
+
+  limits() {
+    const flags = this.byte()
+    this.u32()
+    if (flags & 1) this.u32()
+  }
+}
+
+function u32(n) {
+  const out = []
+  do {
+    let b = n & 0x7f
+    n >>>= 7
+    if (n) b |= 0x80
+    out.push(b)
+  } while (n)
+  return out
+}
+
+function i32(n) {
+  const out = []
+  for (;;) {
+    const b = n & 0x7f
+    n >>= 7
+    if ((n === 0 && !(b & 0x40)) || (n === -1 && b & 0x40)) {
+      out.push(b)
+      return out
+    }
+    out.push(b | 0x80)
+  }
+}
+
+function vec(items) {
+  return [...u32(items.length), ...items.flat()]
+}
+
+function parseSections(bytes) {
+  const view = new Uint8Array(bytes)
+  const sections = []
+  const r = new Reader(view, 8)
+  while (r.pos < view.length) {
+    const id = r.byte()
+    const size = r.u32()
+    const start = r.pos
+    const content = view.subarray(start, start + size)
+    const name = id === 0 ? new Reader(content).name() : null
+    sections.push({ id, name, content })
+    r.pos = start + size
+  }
+  return { header: view.subarray(0, 8), sections }
+}
+
+// `export_name type...` lines; the first type is the i32 status.
+function parseManifest(content) {
+  const r = new Reader(content)
+  r.name()
+  const text = new TextDecoder().decode(content.subarray(r.pos))
+  const entries = new Map()
+  for (const line of text.split('\n')) {
+    const [name, ...types] = line.trim().split(/\s+/)
+    if (!name) continue
+    for (const type of types) {
+      if (!(type in VALTYPES)) {
+        throw new Error(`${SECTION}: unknown type ${type} for ${name}`)
+      }
+    }
+    entries.set(name, types)
+  }
+  return entries
+}
+
+// Offsets of each field under C layout, and the struct size.
+function layout(types) {
+  let size = 0
+  let align = 1
+  const offsets = types.map((type) => {
+    const n = SIZES[type]
+    size = Math.ceil(size / n) * n
+    align = Math.max(align, n)
+    const offset = size
+    size += n
+    return offset
+  })
+  return { offsets, size: Math.ceil(size / align) * align }
+}
+
+function parseTypes(content) {
+  const r = new Reader(content)
+  const types = []
+  for (let i = r.u32(); i > 0; i--) {
+    if (r.byte() !== 0x60) {
+      throw new Error('multi-value: only plain function types are supported')
+    }
+    const params = Array.from({ length: r.u32() }, () => r.byte())
+    const results = Array.from({ length: r.u32() }, () => r.byte())
+    types.push({ params, results })
+  }
+  return types
+}
+
+// Type indices of imported functions, and the types of imported globals.
+function parseImports(content) {
+  const r = new Reader(content)
+  const funcs = []
+  const globals = []
+  for (let i = r.u32(); i > 0; i--) {
+    r.name()
+    r.name()
+    const kind = r.byte()
+    if (kind === 0) funcs.push(r.u32())
+    else if (kind === 1) {
+      r.byte()
+      r.limits()
+    } else if (kind === 2) r.limits()
+    else if (kind === 3) globals.push({ type: r.byte(), mutable: r.byte() })
+    else if (kind === 4) {
+      r.byte()
+      r.u32()
+    }
+  }
+  return { funcs, globals }
+}
+
+function parseFunctions(content) {
+  const r = new Reader(content)
+  return Array.from({ length: r.u32() }, () => r.u32())
+}
+
+function firstGlobal(content) {
+  const r = new Reader(content)
+  return r.u32() > 0 ? { type: r.byte(), mutable: r.byte() } : null
+}
+
+function parseExports(content) {
+  const r = new Reader(content)
+  return Array.from({ length: r.u32() }, () => ({
+    name: r.name(),
+    kind: r.byte(),
+    index: r.u32(),
+  }))
+}
+
+function encodeExports(exports) {
+  const encoder = new TextEncoder()
+  return vec(
+    exports.map(({ name, kind, index }) => {
+      const bytes = [...encoder.encode(name)]
+      return [...u32(bytes.length), ...bytes, kind, ...u32(index)]
+    })
+  )
+}
+
+// Wrapper body: take a 16-byte aligned frame from the stack pointer (global
+// 0), call `callee` with it and the wrapper's own parameters, load each
+// field, then give the frame back.
+function wrapperBody(callee, paramCount, types) {
+  const { offsets, size } = layout(types)
+  const frame = Math.ceil(size / 16) * 16
+  const frameLocal = u32(paramCount)
+  const code = [
+    ...[0x01, 0x01, 0x7f], // one i32 local
+    ...[0x23, 0x00, 0x41, ...i32(frame), 0x6b],
+    ...[0x22, ...frameLocal, 0x24, 0x00],
+    ...[0x20, ...frameLocal],
+  ]
+  for (let i = 0; i < paramCount; i++) code.push(0x20, ...u32(i))
+  code.push(0x10, ...u32(callee))
+  types.forEach((type, i) => {
+    const align = Math.log2(SIZES[type])
+    code.push(0x20, ...frameLocal, LOADS[type], align, ...u32(offsets[i]))
+  })
+  code.push(0x20, ...frameLocal, 0x41, ...i32(frame), 0x6a, 0x24, 0x00, 0x0b)
+  return [...u32(code.length), ...code]
+}
+
+/**
+ * Apply the multi-value rewrite to a module's bytes. Returns the rewritten
+ * module, or `null` when it has no `wbl.multi_value` section.
+ */
+export function applyMultiValue(bytes) {
+  const { header, sections } = parseSections(bytes)
+  const manifest = sections.find((s) => s.name === SECTION)
+  if (!manifest) return null
+  const entries = parseManifest(manifest.content)
+
+  const find = (id) => sections.find((s) => s.id === id)
+  const types = parseTypes(find(1).content)
+  const imports = find(2)
+    ? parseImports(find(2).content)
+    : { funcs: [], globals: [] }
+  const funcs = parseFunctions(find(3).content)
+  const exports = parseExports(find(7).content)
+
+  const stackPointer =
+    imports.globals[0] ?? (find(6) && firstGlobal(find(6).content))
+  if (stackPointer?.type !== 0x7f || stackPointer.mutable !== 1) {
+    throw new Error('multi-value: global 0 is not a mutable i32 stack pointer')
+  }
+
+  const funcType = (index) =>
+    types[
+      index < imports.funcs.length
+        ? imports.funcs[index]
+        : funcs[index - imports.funcs.length]
+    ]
+  const bodies = []
+  for (const [name, results] of entries) {
+    const exp = exports.find((e) => e.kind === 0 && e.name === name)
+    if (!exp) throw new Error(`multi-value: ${name} is not exported`)
+    const { params, results: original } = funcType(exp.index)
+    if (params[0] !== 0x7f || original.length !== 0) {
+      throw new Error(
+        `multi-value: ${name} does not return through a pointer argument`
+      )
+    }
+    types.push({
+      params: params.slice(1),
+      results: results.map((t) => VALTYPES[t]),
+    })
+    funcs.push(types.length - 1)
+    bodies.push(wrapperBody(exp.index, params.length - 1, results))
+    exp.index = imports.funcs.length + funcs.length - 1
+  }
+
+  const code = find(10).content
+  const r = new Reader(code)
+  const count = r.u32()
+  const replaced = {
+    1: vec(types.map((t) => [0x60, ...vec(t.params), ...vec(t.results)])),
+    3: vec(funcs.map(u32)),
+    7: encodeExports(exports),
+    10: [
+      ...u32(count + bodies.length),
+      ...code.subarray(r.pos),
+      ...bodies.flat(),
+    ],
+  }
+  const parts = [header]
+  for (const s of sections) {
+    if (s === manifest) continue
+    const content =
+      s.id in replaced ? Uint8Array.from(replaced[s.id]) : s.content
+    parts.push(Uint8Array.from([s.id, ...u32(content.length)]), content)
+  }
+  const out = new Uint8Array(parts.reduce((n, p) => n + p.length, 0))
+  let at = 0
+  for (const p of parts) {
+    out.set(p, at)
+    at += p.length
+  }
+  return out
+}
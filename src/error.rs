@@ -0,0 +1,156 @@
+//! Structured error codes behind the `-1` sentinel.
+//!
+//! Kernels still return `-1` (or `ffi::ALIAS_ERROR`) on failure, but those
+//! that know why record an [`ErrorCode`] first, so JS can tell a short output
+//! buffer from a malformed input with `last_error_code` and show
+//! `last_error_message`. The code is only meaningful right after a call
+//! failed: successful calls leave it alone, and kernels that have not been
+//! converted yet fail without recording one (`last_error_code` then returns
+//! whatever was there before, `0` after `last_error_clear`). Allocation
+//! failures, aliased ranges and unknown handles are recorded centrally, so
+//! every kernel reports those.
+
+use std::cell::Cell;
+
+use crate::{ffi, packed};
+
+/// Why the last failing call failed.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// Nothing recorded.
+    None = 0,
+    /// An allocation length exceeds `isize::MAX` or its alignment is not a
+    /// power of two (same value as `ALLOC_BAD_LAYOUT`).
+    BadLayout = 1,
+    /// Linear memory could not grow (same value as `ALLOC_OUT_OF_MEMORY`).
+    OutOfMemory = 2,
+    /// An input length is not a whole number of elements, or input lengths
+    /// that must match do not.
+    BadLength = 3,
+    /// The output buffer is smaller than the result.
+    ShortOutput = 4,
+    /// A scalar argument (count, rate, kind, flag) is out of range.
+    InvalidArgument = 5,
+    /// The input data itself is malformed, e.g. not UTF-8 or an index past
+    /// the end.
+    InvalidInput = 6,
+    /// Input and output ranges overlap in a `strict-aliasing` build.
+    Aliased = 7,
+    /// A handle is unknown, dropped or of the wrong type.
+    UnknownHandle = 8,
+    /// A long-running kernel stopped because cancellation was requested
+    /// (returned as `cancel::CANCELLED`).
+    Cancelled = 9,
+}
+
+impl ErrorCode {
+    fn message(self) -> &'static str {
+        match self {
+            ErrorCode::None => "no error recorded",
+            ErrorCode::BadLayout => "allocation length or alignment is invalid",
+            ErrorCode::OutOfMemory => "out of memory",
+            ErrorCode::BadLength => "input length is invalid",
+            ErrorCode::ShortOutput => "output buffer is too small",
+            ErrorCode::InvalidArgument => "argument is out of range",
+            ErrorCode::InvalidInput => "input is malformed",
+            ErrorCode::Aliased => "input and output overlap",
+            ErrorCode::UnknownHandle => "handle is unknown or of the wrong type",
+            ErrorCode::Cancelled => "cancelled",
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: Cell<ErrorCode> = const { Cell::new(ErrorCode::None) };
+}
+
+/// Record `code` for `last_error_code`.
+pub fn record(code: ErrorCode) {
+    LAST_ERROR.set(code);
+}
+
+/// Record `code` and return the `-1` failure sentinel.
+pub fn fail(code: ErrorCode) -> isize {
+    record(code);
+    -1
+}
+
+/// Code of the last recorded failure (one of the [`ErrorCode`] values), or
+/// `0` when none was recorded since `last_error_clear`.
+#[no_mangle]
+pub extern "C" fn last_error_code() -> u32 {
+    LAST_ERROR.get() as u32
+}
+
+/// Write a short English description of `last_error_code` as UTF-8.
+///
+/// With `out_len == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for a
+/// short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn last_error_message(out_ptr: *mut u8, out_len: usize) -> isize {
+    let message = LAST_ERROR.get().message().as_bytes();
+    if out_len == 0 {
+        return message.len() as isize;
+    }
+    if out_len < message.len() {
+        return -1;
+    }
+    ffi::slice_mut(out_ptr, message.len()).copy_from_slice(message);
+    message.len() as isize
+}
+
+/// `last_error_message` in one call: the packed `(ptr, len)` of the
+/// description, which is static and never needs freeing.
+#[no_mangle]
+pub extern "C" fn last_error_message_packed() -> u64 {
+    let message = LAST_ERROR.get().message();
+    packed::pack_slice(message.as_ptr(), message.len())
+}
+
+/// Forget the recorded failure, so `last_error_code` returns `0`.
+#[no_mangle]
+pub extern "C" fn last_error_clear() {
+    LAST_ERROR.set(ErrorCode::None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message() -> String {
+        let needed = unsafe { last_error_message(std::ptr::null_mut(), 0) };
+        let mut out = vec![0u8; needed as usize];
+        assert_eq!(
+            unsafe { last_error_message(out.as_mut_ptr(), out.len()) },
+            needed
+        );
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn records_until_cleared() {
+        last_error_clear();
+        assert_eq!(last_error_code(), 0);
+        assert_eq!(message(), "no error recorded");
+
+        assert_eq!(fail(ErrorCode::ShortOutput), -1);
+        assert_eq!(last_error_code(), 4);
+        assert_eq!(message(), "output buffer is too small");
+        let mut short = [0u8; 4];
+        assert_eq!(unsafe { last_error_message(short.as_mut_ptr(), 4) }, -1);
+
+        assert_eq!(
+            unsafe { crate::alloc_bytes(usize::MAX) },
+            std::ptr::null_mut()
+        );
+        assert_eq!(last_error_code(), ErrorCode::BadLayout as u32);
+        assert_eq!(crate::handles::handle_drop(0), -1);
+        assert_eq!(last_error_code(), ErrorCode::UnknownHandle as u32);
+
+        last_error_clear();
+        assert_eq!(last_error_code(), 0);
+    }
+}
@@ -0,0 +1,44 @@
+//! Slice views and aliasing checks for the raw `(ptr, len)` pairs passed across the ABI.
+
+mod view;
+
+pub use view::*;
+
+/// Status returned by a kernel that cannot run in place when its input and
+/// output ranges overlap. Only reported by `strict-aliasing` builds.
+pub const ALIAS_ERROR: isize = -2;
+
+/// Whether the byte ranges `[a, a + a_len)` and `[b, b + b_len)` share any
+/// byte. Empty ranges never overlap.
+#[inline]
+pub fn overlaps(a: *const u8, a_len: usize, b: *const u8, b_len: usize) -> bool {
+    let (a, b) = (a as usize, b as usize);
+    a_len != 0 && b_len != 0 && a < b.saturating_add(b_len) && b < a.saturating_add(a_len)
+}
+
+/// True when strict mode is enabled and the input and output ranges overlap,
+/// recording `ErrorCode::Aliased`. Always false without `strict-aliasing`,
+/// so the check compiles away.
+#[inline]
+pub fn aliased(in_ptr: *const u8, in_len: usize, out_ptr: *const u8, out_len: usize) -> bool {
+    let aliased = cfg!(feature = "strict-aliasing") && overlaps(in_ptr, in_len, out_ptr, out_len);
+    if aliased {
+        crate::error::record(crate::error::ErrorCode::Aliased);
+    }
+    aliased
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlap_is_half_open() {
+        let base = 0x1000 as *const u8;
+        assert!(overlaps(base, 8, base, 8));
+        assert!(overlaps(base, 8, base.wrapping_add(7), 8));
+        assert!(!overlaps(base, 8, base.wrapping_add(8), 8));
+        assert!(!overlaps(base.wrapping_add(8), 8, base, 8));
+        assert!(!overlaps(base, 0, base, 8));
+    }
+}
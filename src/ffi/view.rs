@@ -0,0 +1,121 @@
+//! `(ptr, len)` views, validated under `checked-ffi`; shared with the examples by path.
+
+/// Borrow `len` elements starting at `ptr`.
+///
+/// # Safety
+/// `ptr` must point to `len` initialized values of `T` that stay valid and
+/// unmodified for `'a`.
+#[inline]
+pub unsafe fn slice<'a, T>(ptr: *const T, len: usize) -> &'a [T] {
+    if len == 0 {
+        return &[];
+    }
+    check_range(ptr, len);
+    std::slice::from_raw_parts(ptr, len)
+}
+
+/// Mutably borrow `len` elements starting at `ptr`.
+///
+/// # Safety
+/// `ptr` must point to `len` values of `T` that stay valid for `'a` and are
+/// not accessed through any other reference meanwhile.
+#[inline]
+pub unsafe fn slice_mut<'a, T>(ptr: *mut T, len: usize) -> &'a mut [T] {
+    if len == 0 {
+        return &mut [];
+    }
+    check_range(ptr, len);
+    std::slice::from_raw_parts_mut(ptr, len)
+}
+
+/// Validate that `len` elements of `T` at `ptr` form a usable range. Kernels
+/// that write through raw pointers call this on their output range up front.
+#[cfg(feature = "checked-ffi")]
+#[inline(never)]
+#[track_caller]
+pub fn check_range<T>(ptr: *const T, len: usize) {
+    let size = std::mem::size_of::<T>();
+    let start = ptr as usize;
+    let bytes = match len.checked_mul(size) {
+        Some(bytes) if bytes <= isize::MAX as usize => bytes,
+        _ => panic!("checked-ffi: length {len} x {size} bytes overflows"),
+    };
+    if bytes == 0 {
+        return;
+    }
+    if start == 0 {
+        panic!("checked-ffi: null pointer for {bytes} bytes");
+    }
+    if !start.is_multiple_of(std::mem::align_of::<T>()) {
+        panic!(
+            "checked-ffi: pointer {start:#x} is not {}-byte aligned",
+            std::mem::align_of::<T>()
+        );
+    }
+    let end = match start.checked_add(bytes) {
+        Some(end) => end,
+        None => panic!("checked-ffi: range {start:#x}+{bytes} wraps the address space"),
+    };
+    #[cfg(target_arch = "wasm32")]
+    {
+        let memory_end = (core::arch::wasm32::memory_size(0) as u64) << 16;
+        if end as u64 > memory_end {
+            panic!(
+                "checked-ffi: range {start:#x}..{end:#x} exceeds linear memory ({memory_end:#x})"
+            );
+        }
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    let _ = end;
+}
+
+#[cfg(not(feature = "checked-ffi"))]
+#[inline(always)]
+pub fn check_range<T>(_ptr: *const T, _len: usize) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_length_never_touches_pointer() {
+        unsafe {
+            assert!(slice::<u8>(std::ptr::null(), 0).is_empty());
+            assert!(slice_mut::<u32>(std::ptr::null_mut(), 0).is_empty());
+        }
+    }
+
+    #[test]
+    fn views_cover_requested_range() {
+        let mut data = [1u32, 2, 3, 4];
+        unsafe {
+            assert_eq!(slice(data.as_ptr(), 3), &[1, 2, 3]);
+            slice_mut(data.as_mut_ptr().add(1), 2).fill(9);
+        }
+        assert_eq!(data, [1, 9, 9, 4]);
+    }
+
+    #[cfg(feature = "checked-ffi")]
+    #[test]
+    #[should_panic(expected = "overflows")]
+    fn checked_rejects_overflowing_length() {
+        let data = [0u64; 1];
+        check_range(data.as_ptr(), usize::MAX / 4);
+    }
+
+    #[cfg(feature = "checked-ffi")]
+    #[test]
+    #[should_panic(expected = "null pointer")]
+    fn checked_rejects_null() {
+        check_range::<u8>(std::ptr::null(), 16);
+    }
+
+    #[cfg(feature = "checked-ffi")]
+    #[test]
+    #[should_panic(expected = "aligned")]
+    fn checked_rejects_misaligned() {
+        let data = [0u32; 2];
+        let ptr = (data.as_ptr() as *const u8).wrapping_add(1) as *const u32;
+        check_range(ptr, 1);
+    }
+}
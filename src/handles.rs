@@ -0,0 +1,217 @@
+//! Opaque handles for state that lives in wasm memory between calls.
+//!
+//! A kernel that builds something expensive (a compiled pattern, a sketch, a
+//! decoder) stores it here and hands JS a `u32` handle. Later calls look the
+//! value up by handle and type; a handle of the wrong type, a dropped handle
+//! and `0` are all rejected. JS releases a handle with `handle_drop`, and the
+//! generated `reset()` releases all of them with `handle_clear_all`.
+//!
+//! Each stateful kernel family has its own typed constructor (`hll_new`,
+//! `regex_compile`, `dataset_new`, ...) that builds the value and calls
+//! [`insert`]; `handle_create` in `lib.rs` is the generic one, holding bytes.
+//!
+//! A handle packs the slot index (plus one) in its low [`INDEX_BITS`] bits
+//! and the slot's generation above them. Dropping a value bumps its slot's
+//! generation before the slot is reused, so a stale handle kept after
+//! `handle_drop` no longer matches and is rejected instead of resolving to
+//! the slot's next value. A slot whose generation is used up is retired.
+//! Handles stay below `2^31`, so constructors can return them as `isize`.
+
+use std::any::Any;
+use std::cell::RefCell;
+
+use crate::error::{self, ErrorCode};
+
+/// Bits of a handle holding the slot index plus one.
+pub const INDEX_BITS: u32 = 24;
+const INDEX_MASK: u32 = (1 << INDEX_BITS) - 1;
+/// Generations a slot goes through before it is retired.
+const GENERATIONS: u32 = 1 << (31 - INDEX_BITS);
+
+#[derive(Default)]
+struct Slot {
+    generation: u32,
+    value: Option<Box<dyn Any>>,
+}
+
+#[derive(Default)]
+struct Registry {
+    slots: Vec<Slot>,
+    free: Vec<usize>,
+}
+
+impl Registry {
+    fn slot(&mut self, handle: u32) -> Option<&mut Slot> {
+        let index = ((handle & INDEX_MASK) as usize).checked_sub(1)?;
+        let slot = self.slots.get_mut(index)?;
+        (slot.generation == handle >> INDEX_BITS).then_some(slot)
+    }
+
+    /// Take the value out of `index`, bumping the generation and freeing
+    /// the slot unless that generation is used up.
+    fn release(&mut self, index: usize) -> Option<Box<dyn Any>> {
+        let slot = &mut self.slots[index];
+        let value = slot.value.take()?;
+        slot.generation += 1;
+        if slot.generation < GENERATIONS {
+            self.free.push(index);
+        }
+        Some(value)
+    }
+}
+
+thread_local! {
+    static REGISTRY: RefCell<Registry> = RefCell::default();
+}
+
+/// Store `value` and return its handle. The handle is never `0`, unless
+/// `2^24 - 1` slots are in use, which records `ErrorCode::OutOfMemory`.
+pub fn insert<T: Any>(value: T) -> u32 {
+    let handle = REGISTRY.with_borrow_mut(|r| {
+        let index = match r.free.pop() {
+            Some(index) => index,
+            None if r.slots.len() < INDEX_MASK as usize => {
+                r.slots.push(Slot::default());
+                r.slots.len() - 1
+            }
+            None => return None,
+        };
+        let slot = &mut r.slots[index];
+        slot.value = Some(Box::new(value));
+        Some(slot.generation << INDEX_BITS | (index as u32 + 1))
+    });
+    handle.unwrap_or_else(|| {
+        error::record(ErrorCode::OutOfMemory);
+        0
+    })
+}
+
+/// Run `f` on the value behind `handle`, or return `None` (recording
+/// `ErrorCode::UnknownHandle`) when the handle is unknown, stale or holds a
+/// different type. `f` must not create or drop handles.
+pub fn with<T: Any, R>(handle: u32, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+    let value = REGISTRY.with_borrow_mut(|r| {
+        let slot = r.slot(handle)?;
+        slot.value.as_mut()?.downcast_mut::<T>().map(f)
+    });
+    if value.is_none() {
+        error::record(ErrorCode::UnknownHandle);
+    }
+    value
+}
+
+/// Drop the value behind `handle`. Returns `false` for an unknown or stale
+/// handle.
+pub fn remove(handle: u32) -> bool {
+    let value = REGISTRY.with_borrow_mut(|r| {
+        r.slot(handle)?;
+        let index = (handle & INDEX_MASK) as usize - 1;
+        r.release(index)
+    });
+    // Dropped outside the borrow in case the value's destructor touches the
+    // registry itself.
+    value.is_some()
+}
+
+/// Release the state behind `handle`. Returns `0`, or `-1` for an unknown
+/// handle.
+#[no_mangle]
+pub extern "C" fn handle_drop(handle: u32) -> isize {
+    if remove(handle) {
+        0
+    } else {
+        error::fail(ErrorCode::UnknownHandle)
+    }
+}
+
+/// Release every live handle. Returns how many were dropped. Generations
+/// carry on, so handles from before the call stay invalid.
+#[no_mangle]
+pub extern "C" fn handle_clear_all() -> isize {
+    let values: Vec<_> = REGISTRY.with_borrow_mut(|r| {
+        (0..r.slots.len())
+            .filter_map(|index| r.release(index))
+            .collect()
+    });
+    values.len() as isize
+}
+
+/// Number of live handles, for spotting handles JS forgot to drop.
+#[no_mangle]
+pub extern "C" fn handle_count() -> isize {
+    REGISTRY.with_borrow(|r| r.slots.iter().filter(|s| s.value.is_some()).count() as isize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typed_lookup_and_reuse() {
+        let a = insert(41u32);
+        let b = insert(String::from("x"));
+        assert_ne!(a, 0);
+        assert_eq!(
+            with(a, |v: &mut u32| {
+                *v += 1;
+                *v
+            }),
+            Some(42)
+        );
+        assert_eq!(with(a, |_: &mut String| ()), None);
+        assert_eq!(with(0, |_: &mut u32| ()), None);
+
+        assert_eq!(handle_drop(a), 0);
+        assert_eq!(handle_drop(a), -1);
+        assert_eq!(with(a, |_: &mut u32| ()), None);
+        assert_eq!(handle_count(), 1);
+        let c = insert(7u8);
+        assert_eq!(c & INDEX_MASK, a & INDEX_MASK, "freed slots are reused");
+        assert_ne!(c, a);
+        assert_eq!(handle_count(), 2);
+        assert_eq!(with(b, |s: &mut String| s.clone()).as_deref(), Some("x"));
+
+        assert_eq!(handle_clear_all(), 2);
+        assert_eq!(handle_count(), 0);
+        assert_eq!(with(b, |_: &mut String| ()), None);
+    }
+
+    #[test]
+    fn stale_handles_do_not_reach_reused_slots() {
+        let stale = insert(1u32);
+        assert_eq!(handle_drop(stale), 0);
+        let fresh = insert(2u32);
+        assert_eq!(fresh & INDEX_MASK, stale & INDEX_MASK);
+
+        error::last_error_clear();
+        assert_eq!(with(stale, |v: &mut u32| *v), None);
+        assert_eq!(error::last_error_code(), ErrorCode::UnknownHandle as u32);
+        assert_eq!(handle_drop(stale), -1);
+        assert_eq!(with(fresh, |v: &mut u32| *v), Some(2));
+
+        handle_clear_all();
+        let after = insert(3u32);
+        assert_eq!(
+            with(fresh, |_: &mut u32| ()),
+            None,
+            "cleared handles stay stale"
+        );
+        assert!(after < 1 << 31);
+        assert_eq!(handle_drop(after), 0);
+    }
+
+    #[test]
+    fn exhausted_slots_are_retired() {
+        let mut handle = insert(0u8);
+        let index = handle & INDEX_MASK;
+        for _ in 1..GENERATIONS {
+            assert_eq!(handle_drop(handle), 0);
+            handle = insert(0u8);
+            assert_eq!(handle & INDEX_MASK, index);
+        }
+        assert_eq!(handle_drop(handle), 0);
+        let next = insert(0u8);
+        assert_ne!(next & INDEX_MASK, index, "retired instead of wrapping");
+        assert_eq!(handle_drop(next), 0);
+    }
+}
@@ -0,0 +1,148 @@
+//! Heap introspection (`heap-stats` feature).
+//!
+//! Wraps the system allocator in a counting layer so JS can watch memory
+//! pressure from inside the instance: how many bytes and blocks are live and
+//! how large linear memory has grown. Wasm memory never shrinks, so a page
+//! can compare `heap_used_bytes` against `heap_capacity_bytes` and tear down
+//! an instance whose heap is mostly fragmentation or leftover growth.
+//!
+//! The counters cost a few atomic adds per allocation, which is why the
+//! layer is opt-in.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+/// Counts live bytes and blocks on top of `System`.
+struct Counting {
+    used: AtomicUsize,
+    peak: AtomicUsize,
+    blocks: AtomicUsize,
+}
+
+impl Counting {
+    const fn new() -> Self {
+        Counting {
+            used: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            blocks: AtomicUsize::new(0),
+        }
+    }
+
+    fn grow(&self, bytes: usize) {
+        let used = self.used.fetch_add(bytes, Relaxed) + bytes;
+        self.peak.fetch_max(used, Relaxed);
+    }
+
+    fn shrink(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            self.grow(layout.size());
+            self.blocks.fetch_add(1, Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            self.grow(layout.size());
+            self.blocks.fetch_add(1, Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        self.shrink(layout.size());
+        self.blocks.fetch_sub(1, Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        // On failure the old block stays live and the counts stay as they are.
+        if !new.is_null() {
+            self.shrink(layout.size());
+            self.grow(new_size);
+        }
+        new
+    }
+}
+
+#[global_allocator]
+static HEAP: Counting = Counting::new();
+
+/// Bytes currently allocated through the global allocator, including blocks
+/// the runtime holds for handles, the arena and the scratch buffer.
+#[no_mangle]
+pub extern "C" fn heap_used_bytes() -> usize {
+    HEAP.used.load(Relaxed)
+}
+
+/// Size of linear memory in bytes: the ceiling `heap_used_bytes` can reach
+/// before the allocator has to call `memory.grow`. Hosts without linear
+/// memory (native tests) report the peak of `heap_used_bytes` instead.
+#[no_mangle]
+pub extern "C" fn heap_capacity_bytes() -> usize {
+    #[cfg(target_arch = "wasm32")]
+    {
+        core::arch::wasm32::memory_size(0) * 65536
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        HEAP.peak.load(Relaxed)
+    }
+}
+
+/// Number of live allocations. A count that keeps climbing across otherwise
+/// identical calls points at a leaked buffer or handle.
+#[no_mangle]
+pub extern "C" fn allocation_count() -> usize {
+    HEAP.blocks.load(Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Other tests allocate concurrently through the global instance, so
+    // exact counts are checked on a private one.
+    #[test]
+    fn counts_live_bytes_and_blocks() {
+        let heap = Counting::new();
+        let small = Layout::from_size_align(100, 8).unwrap();
+        let large = Layout::from_size_align(4096, 16).unwrap();
+        unsafe {
+            let a = heap.alloc(small);
+            let b = heap.alloc_zeroed(large);
+            assert_eq!(heap.used.load(Relaxed), 4196);
+            assert_eq!(heap.blocks.load(Relaxed), 2);
+
+            let a = heap.realloc(a, small, 300);
+            assert_eq!(heap.used.load(Relaxed), 4396);
+            assert_eq!(heap.blocks.load(Relaxed), 2);
+
+            heap.dealloc(b, large);
+            assert_eq!(heap.used.load(Relaxed), 300);
+            assert_eq!(heap.blocks.load(Relaxed), 1);
+            heap.dealloc(a, Layout::from_size_align(300, 8).unwrap());
+        }
+        assert_eq!(heap.used.load(Relaxed), 0);
+        assert_eq!(heap.blocks.load(Relaxed), 0);
+        assert_eq!(heap.peak.load(Relaxed), 4396);
+    }
+
+    #[test]
+    fn exports_track_the_global_allocator() {
+        let block = vec![0u8; 1 << 20];
+        assert!(heap_used_bytes() >= block.len());
+        assert!(heap_capacity_bytes() >= block.len());
+        assert!(allocation_count() >= 1);
+        drop(block);
+    }
+}
@@ -0,0 +1,98 @@
+//! Scatter/gather input: one logical stream spread over several buffers.
+//!
+//! Browser data often arrives as a list of `ArrayBuffer` chunks (a fetch
+//! body, `Blob` slices, WebSocket frames). Concatenating them in JS before
+//! the call briefly holds the data twice. `_iov` kernels instead take
+//! `(iov_ptr, iov_count)`: an array of `iov_count` [`IoVec`] entries, each a
+//! `(ptr, len)` pair of `u32`s on wasm32, describing chunks that may sit
+//! anywhere in linear memory. The kernel reads them in order as if they
+//! were one contiguous input, and its result is the same as for the
+//! concatenation.
+//!
+//! Chunks may be empty, and an `iov_count` of `0` is an empty stream.
+
+use crate::ffi;
+
+/// One chunk of a scatter/gather input.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct IoVec {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+/// The chunks of an input, in stream order.
+#[derive(Clone, Copy)]
+pub struct Stream<'a> {
+    chunks: &'a [IoVec],
+}
+
+impl<'a> Stream<'a> {
+    /// Borrow the `iov_count` entries at `iov_ptr`.
+    ///
+    /// # Safety
+    /// `iov_ptr` must point to `iov_count` entries, each describing `len`
+    /// readable bytes, all valid and unmodified for `'a`.
+    pub unsafe fn new(iov_ptr: *const IoVec, iov_count: usize) -> Self {
+        Stream {
+            chunks: ffi::slice(iov_ptr, iov_count),
+        }
+    }
+
+    /// The chunks as byte slices.
+    pub fn chunks(self) -> impl Iterator<Item = &'a [u8]> {
+        self.chunks
+            .iter()
+            .map(|iov| unsafe { ffi::slice(iov.ptr, iov.len) })
+    }
+
+    /// Total length of the stream, or `None` if it overflows `usize`.
+    pub fn len(self) -> Option<usize> {
+        self.chunks
+            .iter()
+            .try_fold(0usize, |total, iov| total.checked_add(iov.len))
+    }
+
+    /// Like [`ffi::aliased`] for the whole stream: true in `strict-aliasing`
+    /// builds when any chunk, or the entry array itself, overlaps the
+    /// `out_len` bytes at `out_ptr`.
+    pub fn aliased(self, out_ptr: *const u8, out_len: usize) -> bool {
+        let entries = std::mem::size_of_val(self.chunks);
+        ffi::aliased(self.chunks.as_ptr() as *const u8, entries, out_ptr, out_len)
+            || self
+                .chunks
+                .iter()
+                .any(|iov| ffi::aliased(iov.ptr, iov.len, out_ptr, out_len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_chunks_in_order() {
+        let (a, b) = (b"hello ".as_slice(), b"world".as_slice());
+        let iov = [
+            IoVec {
+                ptr: a.as_ptr(),
+                len: a.len(),
+            },
+            IoVec {
+                ptr: std::ptr::null(),
+                len: 0,
+            },
+            IoVec {
+                ptr: b.as_ptr(),
+                len: b.len(),
+            },
+        ];
+        let stream = unsafe { Stream::new(iov.as_ptr(), iov.len()) };
+        assert_eq!(stream.len(), Some(11));
+        assert_eq!(stream.chunks().collect::<Vec<_>>().concat(), b"hello world");
+
+        let empty = unsafe { Stream::new(std::ptr::null(), 0) };
+        assert_eq!(empty.len(), Some(0));
+        assert_eq!(empty.chunks().count(), 0);
+    }
+}
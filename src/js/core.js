@@ -17,7 +17,22 @@ export function memoryU8() {
 }
 
 export function alloc(len) {
-  return _inst.exports.alloc_bytes(len) >>> 0
+  const ptr = _inst.exports.alloc_bytes(len) >>> 0
+  if (ptr === 0 && len > 0) {
+    const code = _inst.exports.alloc_last_error?.() ?? 0
+    throw new RangeError(`alloc_bytes(${len}) failed with code ${code}`)
+  }
+  return ptr
+}
+
+export function realloc(ptr, oldLen, newLen) {
+  const next =
+    _inst.exports.realloc_bytes(ptr >>> 0, oldLen >>> 0, newLen >>> 0) >>> 0
+  if (next === 0 && newLen > 0) {
+    const code = _inst.exports.alloc_last_error?.() ?? 0
+    throw new RangeError(`realloc_bytes(${newLen}) failed with code ${code}`)
+  }
+  return next
 }
 
 export function free(ptr, len) {
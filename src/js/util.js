@@ -4,41 +4,186 @@ export async function instantiateWithFallback(
   imports
 ) {
   try {
-    const { instance } = await WebAssembly.instantiate(trySimdBytes, imports)
-    return { instance, backend: 'wasm-simd' }
+    const { instance, module } = await WebAssembly.instantiate(trySimdBytes, imports)
+    return { instance, module, backend: 'wasm-simd' }
   } catch {
     // If SIMD fails (not supported), try baseline
-    const { instance } = await WebAssembly.instantiate(baseBytes, imports)
-    return { instance, backend: 'wasm' }
+    const { instance, module } = await WebAssembly.instantiate(baseBytes, imports)
+    return { instance, module, backend: 'wasm' }
   }
 }
 
+async function instantiateBytes(getBytes, imports, cacheKey) {
+  if (!cacheKey) {
+    return WebAssembly.instantiate(await getBytes(), imports)
+  }
+  const module = await compileCached(cacheKey, getBytes)
+  const instance = await WebAssembly.instantiate(module, imports)
+  return { instance, module }
+}
+
 export async function instantiateWithBackend({
   getSimdBytes,
   getBaseBytes,
   imports,
   backend = 'auto',
+  cacheKey = null,
 }) {
+  const simdKey = cacheKey && cacheKey + ':simd'
+  const baseKey = cacheKey && cacheKey + ':base'
+
   if (backend === 'base') {
-    const baseBytes = await getBaseBytes()
-    const { instance } = await WebAssembly.instantiate(baseBytes, imports)
-    return { instance, backend: 'wasm' }
+    const { instance, module } = await instantiateBytes(
+      getBaseBytes,
+      imports,
+      baseKey
+    )
+    return { instance, module, backend: 'wasm' }
   }
 
   if (backend === 'simd') {
-    const simdBytes = await getSimdBytes()
-    const { instance } = await WebAssembly.instantiate(simdBytes, imports)
-    return { instance, backend: 'wasm-simd' }
+    const { instance, module } = await instantiateBytes(
+      getSimdBytes,
+      imports,
+      simdKey
+    )
+    return { instance, module, backend: 'wasm-simd' }
   }
 
   // auto: try simd first, then fallback to baseline
   try {
-    const simdBytes = await getSimdBytes()
-    const { instance } = await WebAssembly.instantiate(simdBytes, imports)
-    return { instance, backend: 'wasm-simd' }
+    const { instance, module } = await instantiateBytes(
+      getSimdBytes,
+      imports,
+      simdKey
+    )
+    return { instance, module, backend: 'wasm-simd' }
   } catch {
-    const baseBytes = await getBaseBytes()
-    const { instance } = await WebAssembly.instantiate(baseBytes, imports)
-    return { instance, backend: 'wasm' }
+    const { instance, module } = await instantiateBytes(
+      getBaseBytes,
+      imports,
+      baseKey
+    )
+    return { instance, module, backend: 'wasm' }
+  }
+}
+
+// Wasm memory never shrinks, so reclaiming it means starting over from the
+// compiled module with fresh instances.
+export function instantiatePool(module, imports, count) {
+  return Promise.all(
+    Array.from({ length: count }, () =>
+      WebAssembly.instantiate(module, imports)
+    )
+  )
+}
+
+// IndexedDB cache for wasm bytes and memory snapshots. Browsers cannot
+// structured-clone a WebAssembly.Module into IndexedDB, so the bytes are
+// stored and compiled on load; engines keep their own code cache keyed by
+// those bytes. Every helper degrades to a miss when IndexedDB is missing
+// (Node) or refuses a value.
+const CACHE_DB = 'wasm-bindgen-lite'
+const CACHE_STORE = 'cache'
+let _db = null
+
+function openCache() {
+  if (typeof indexedDB === 'undefined') return Promise.resolve(null)
+  return (_db ??= new Promise((resolve) => {
+    const req = indexedDB.open(CACHE_DB, 1)
+    req.onupgradeneeded = () => req.result.createObjectStore(CACHE_STORE)
+    req.onsuccess = () => resolve(req.result)
+    req.onerror = () => resolve(null)
+  }))
+}
+
+async function cacheGet(key) {
+  const db = await openCache()
+  if (!db) return null
+  return new Promise((resolve) => {
+    try {
+      const req = db.transaction(CACHE_STORE).objectStore(CACHE_STORE).get(key)
+      req.onsuccess = () => resolve(req.result ?? null)
+      req.onerror = () => resolve(null)
+    } catch {
+      resolve(null)
+    }
+  })
+}
+
+async function cachePut(key, value) {
+  const db = await openCache()
+  if (!db) return
+  return new Promise((resolve) => {
+    try {
+      const tx = db.transaction(CACHE_STORE, 'readwrite')
+      tx.objectStore(CACHE_STORE).put(value, key)
+      tx.oncomplete = tx.onerror = tx.onabort = () => resolve()
+    } catch {
+      resolve()
+    }
+  })
+}
+
+// A standalone ArrayBuffer holding `bytes`, which may be a view into a
+// larger buffer (Node's pooled Buffers).
+function ownedBuffer(bytes) {
+  if (bytes instanceof ArrayBuffer) return bytes
+  const view = new Uint8Array(bytes.buffer, bytes.byteOffset, bytes.byteLength)
+  return view.slice().buffer
+}
+
+export async function compileCached(cacheKey, getBytes) {
+  const cached = await cacheGet(cacheKey)
+  if (cached instanceof ArrayBuffer) return WebAssembly.compile(cached)
+  const bytes = await getBytes()
+  const module = await WebAssembly.compile(bytes)
+  await cachePut(cacheKey, ownedBuffer(bytes))
+  return module
+}
+
+// Exported mutable globals, found by writing each global's own value back:
+// immutable ones throw.
+function mutableGlobals(exports) {
+  return Object.entries(exports).filter(([, value]) => {
+    if (!(value instanceof WebAssembly.Global)) return false
+    try {
+      value.value = value.value
+      return true
+    } catch {
+      return false
+    }
+  })
+}
+
+export function applySnapshot(instance, snapshot) {
+  const memory = instance.exports.memory
+  const missing = snapshot.memory.byteLength - memory.buffer.byteLength
+  if (missing > 0) memory.grow(missing / 65536)
+  new Uint8Array(memory.buffer).set(new Uint8Array(snapshot.memory))
+  for (const [name, global] of mutableGlobals(instance.exports)) {
+    global.value = snapshot.globals[name]
+  }
+}
+
+// Restore the state saved after a previous init + warmup, or run the warmup
+// and save the result: linear memory, where Rust keeps its statics (the
+// handle table, arena, scratch buffer and error slot) and heap, plus every
+// exported mutable global. The stack pointer is not exported, but it is
+// back at its initial value whenever no call is running.
+export async function snapshotInstance(instance, cacheKey, warmup) {
+  const saved = await cacheGet(cacheKey)
+  if (saved?.memory instanceof ArrayBuffer) {
+    applySnapshot(instance, saved)
+    return saved
+  }
+  if (warmup) await warmup(instance.exports)
+  const snapshot = {
+    memory: instance.exports.memory.buffer.slice(0),
+    globals: Object.fromEntries(
+      mutableGlobals(instance.exports).map(([name, g]) => [name, g.value])
+    ),
   }
+  await cachePut(cacheKey, snapshot)
+  return snapshot
 }
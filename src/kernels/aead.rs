@@ -0,0 +1,740 @@
+//! Streaming authenticated decryption (`aead` feature).
+//!
+//! `aead_decrypt_new` sets up AES-GCM or ChaCha20-Poly1305 decryption behind
+//! a handle, `aead_decrypt_update` decrypts ciphertext chunk by chunk
+//! straight into wasm memory, and `aead_decrypt_finish` checks the 16-byte
+//! tag. Chunks may have any length; keystream and MAC blocks carry over
+//! between calls, so a dataset never has to be held whole as SubtleCrypto
+//! requires.
+//!
+//! Plaintext from `aead_decrypt_update` is not authenticated until
+//! `aead_decrypt_finish` returns `1`. Callers may parse it as it arrives but
+//! must discard the results if the tag does not match. AES uses lookup
+//! tables, so its timing depends on the key; this is meant for decrypting
+//! downloaded data, not for guarding keys from code in the same page.
+
+use crate::{ffi, handles};
+
+/// AES-GCM with a 16-, 24- or 32-byte key.
+pub const AEAD_AES_GCM: u32 = 0;
+/// ChaCha20-Poly1305 (RFC 8439) with a 32-byte key.
+pub const AEAD_CHACHA20_POLY1305: u32 = 1;
+
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+const fn xtime(b: u8) -> u8 {
+    (b << 1) ^ if b & 0x80 != 0 { 0x1b } else { 0 }
+}
+
+/// Combined SubBytes and MixColumns for row 0; rows 1 to 3 are rotations.
+const TE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let s = SBOX[i];
+        let s2 = xtime(s);
+        table[i] = u32::from_be_bytes([s2, s, s, s2 ^ s]);
+        i += 1;
+    }
+    table
+};
+
+struct Aes {
+    round_keys: Vec<u32>,
+}
+
+impl Aes {
+    fn new(key: &[u8]) -> Aes {
+        let nk = key.len() / 4;
+        let total = 4 * (nk + 7);
+        let mut w: Vec<u32> = key
+            .chunks_exact(4)
+            .map(|c| u32::from_be_bytes(c.try_into().unwrap()))
+            .collect();
+        let sub = |x: u32| u32::from_be_bytes(x.to_be_bytes().map(|b| SBOX[b as usize]));
+        let mut rcon = 1u8;
+        for i in nk..total {
+            let mut t = w[i - 1];
+            if i % nk == 0 {
+                t = sub(t.rotate_left(8)) ^ (rcon as u32) << 24;
+                rcon = xtime(rcon);
+            } else if nk > 6 && i % nk == 4 {
+                t = sub(t);
+            }
+            w.push(w[i - nk] ^ t);
+        }
+        Aes { round_keys: w }
+    }
+
+    fn encrypt(&self, block: &[u8; 16]) -> [u8; 16] {
+        let rk = &self.round_keys;
+        let mut s: [u32; 4] = std::array::from_fn(|c| {
+            u32::from_be_bytes(block[4 * c..][..4].try_into().unwrap()) ^ rk[c]
+        });
+        let rounds = rk.len() / 4 - 1;
+        let byte = |x: u32, row: usize| (x >> (24 - 8 * row)) as u8 as usize;
+        for round in 1..rounds {
+            s = std::array::from_fn(|c| {
+                (0..4).fold(rk[4 * round + c], |acc, row| {
+                    acc ^ TE[byte(s[(c + row) % 4], row)].rotate_right(8 * row as u32)
+                })
+            });
+        }
+        let mut out = [0u8; 16];
+        for c in 0..4 {
+            let column: [u8; 4] = std::array::from_fn(|row| SBOX[byte(s[(c + row) % 4], row)]);
+            let word = u32::from_be_bytes(column) ^ rk[4 * rounds + c];
+            out[4 * c..][..4].copy_from_slice(&word.to_be_bytes());
+        }
+        out
+    }
+}
+
+/// Multiply by `x` in GCM's bit-reflected GF(2^128).
+const fn times_x(v: u128) -> u128 {
+    (v >> 1) ^ if v & 1 == 1 { 0xE1 << 120 } else { 0 }
+}
+
+/// `r * x^4` for each 4-bit `r`: the reduction of the bits `>> 4` drops.
+const REDUCE4: [u128; 16] = {
+    let mut table = [0u128; 16];
+    let mut r = 0;
+    while r < 16 {
+        table[r] = times_x(times_x(times_x(times_x(r as u128))));
+        r += 1;
+    }
+    table
+};
+
+/// GHASH with a 4-bit table of multiples of `H` (Shoup's method).
+struct Ghash {
+    table: [u128; 16],
+    y: u128,
+}
+
+impl Ghash {
+    fn new(h: u128) -> Ghash {
+        let mut table = [0u128; 16];
+        // The top bit of a nibble is the lowest power of `x`.
+        table[8] = h;
+        table[4] = times_x(h);
+        table[2] = times_x(table[4]);
+        table[1] = times_x(table[2]);
+        for i in 2..16usize {
+            if !i.is_power_of_two() {
+                let low = 1 << i.trailing_zeros();
+                table[i] = table[low] ^ table[i ^ low];
+            }
+        }
+        Ghash { table, y: 0 }
+    }
+
+    fn block(&mut self, block: &[u8; 16]) {
+        let x = self.y ^ u128::from_be_bytes(*block);
+        let mut z = 0u128;
+        for k in (0..32).rev() {
+            z = (z >> 4) ^ REDUCE4[(z & 0xF) as usize];
+            z ^= self.table[(x >> (124 - 4 * k) & 0xF) as usize];
+        }
+        self.y = z;
+    }
+}
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+/// One 64-byte ChaCha20 block; `state[12]` is the block counter.
+fn chacha20_block(state: &[u32; 16]) -> [u8; 64] {
+    let mut s = *state;
+    for _ in 0..10 {
+        quarter_round(&mut s, 0, 4, 8, 12);
+        quarter_round(&mut s, 1, 5, 9, 13);
+        quarter_round(&mut s, 2, 6, 10, 14);
+        quarter_round(&mut s, 3, 7, 11, 15);
+        quarter_round(&mut s, 0, 5, 10, 15);
+        quarter_round(&mut s, 1, 6, 11, 12);
+        quarter_round(&mut s, 2, 7, 8, 13);
+        quarter_round(&mut s, 3, 4, 9, 14);
+    }
+    let mut out = [0u8; 64];
+    for (i, bytes) in out.chunks_exact_mut(4).enumerate() {
+        bytes.copy_from_slice(&s[i].wrapping_add(state[i]).to_le_bytes());
+    }
+    out
+}
+
+/// Poly1305 in 26-bit limbs (after poly1305-donna).
+struct Poly1305 {
+    r: [u64; 5],
+    h: [u64; 5],
+    pad: [u32; 4],
+}
+
+impl Poly1305 {
+    fn new(key: &[u8; 32]) -> Poly1305 {
+        let le = |i: usize| u32::from_le_bytes(key[i..i + 4].try_into().unwrap()) as u64;
+        Poly1305 {
+            r: [
+                le(0) & 0x3ffffff,
+                (le(3) >> 2) & 0x3ffff03,
+                (le(6) >> 4) & 0x3ffc0ff,
+                (le(9) >> 6) & 0x3f03fff,
+                (le(12) >> 8) & 0x00fffff,
+            ],
+            h: [0; 5],
+            pad: std::array::from_fn(|i| le(16 + 4 * i) as u32),
+        }
+    }
+
+    /// Absorb a full 16-byte block.
+    fn block(&mut self, m: &[u8; 16]) {
+        let le = |i: usize| u32::from_le_bytes(m[i..i + 4].try_into().unwrap()) as u64;
+        let [r0, r1, r2, r3, r4] = self.r;
+        let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+        let h = &mut self.h;
+        h[0] += le(0) & 0x3ffffff;
+        h[1] += (le(3) >> 2) & 0x3ffffff;
+        h[2] += (le(6) >> 4) & 0x3ffffff;
+        h[3] += (le(9) >> 6) & 0x3ffffff;
+        h[4] += (le(12) >> 8) | 1 << 24;
+        let [h0, h1, h2, h3, h4] = *h;
+        let mut d = [
+            h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1,
+            h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2,
+            h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3,
+            h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4,
+            h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0,
+        ];
+        for i in 0..4 {
+            d[i + 1] += d[i] >> 26;
+            d[i] &= 0x3ffffff;
+        }
+        d[0] += (d[4] >> 26) * 5;
+        d[4] &= 0x3ffffff;
+        d[1] += d[0] >> 26;
+        d[0] &= 0x3ffffff;
+        *h = d;
+    }
+
+    fn tag(&self) -> [u8; 16] {
+        let mut h = self.h;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 26;
+            h[i] &= 0x3ffffff;
+        }
+        h[0] += (h[4] >> 26) * 5;
+        h[4] &= 0x3ffffff;
+        h[1] += h[0] >> 26;
+        h[0] &= 0x3ffffff;
+        // Subtract p = 2^130 - 5 if h >= p.
+        let mut g = h;
+        g[0] += 5;
+        for i in 0..4 {
+            g[i + 1] += g[i] >> 26;
+            g[i] &= 0x3ffffff;
+        }
+        if g[4] >> 26 != 0 {
+            g[4] &= 0x3ffffff;
+            h = g;
+        }
+        let words = [
+            h[0] | h[1] << 26,
+            h[1] >> 6 | h[2] << 20,
+            h[2] >> 12 | h[3] << 14,
+            h[3] >> 18 | h[4] << 8,
+        ];
+        let mut out = [0u8; 16];
+        let mut carry = 0u64;
+        for (i, bytes) in out.chunks_exact_mut(4).enumerate() {
+            let f = (words[i] & 0xffff_ffff) + self.pad[i] as u64 + carry;
+            bytes.copy_from_slice(&(f as u32).to_le_bytes());
+            carry = f >> 32;
+        }
+        out
+    }
+}
+
+enum Cipher {
+    Gcm {
+        aes: Aes,
+        counter: [u8; 16],
+        ghash: Ghash,
+        mask: [u8; 16],
+    },
+    ChaCha {
+        state: [u32; 16],
+        poly: Poly1305,
+    },
+}
+
+impl Cipher {
+    /// Fill `buf` with the next keystream block, returning its length.
+    fn keystream(&mut self, buf: &mut [u8; 64]) -> usize {
+        match self {
+            Cipher::Gcm { aes, counter, .. } => {
+                let n = u32::from_be_bytes(counter[12..].try_into().unwrap()).wrapping_add(1);
+                counter[12..].copy_from_slice(&n.to_be_bytes());
+                buf[..16].copy_from_slice(&aes.encrypt(counter));
+                16
+            }
+            Cipher::ChaCha { state, .. } => {
+                *buf = chacha20_block(state);
+                state[12] = state[12].wrapping_add(1);
+                64
+            }
+        }
+    }
+
+    fn mac_block(&mut self, block: &[u8; 16]) {
+        match self {
+            Cipher::Gcm { ghash, .. } => ghash.block(block),
+            Cipher::ChaCha { poly, .. } => poly.block(block),
+        }
+    }
+
+    fn tag(&mut self, aad_len: u64, ct_len: u64) -> [u8; 16] {
+        let mut lengths = [0u8; 16];
+        match self {
+            Cipher::Gcm { ghash, mask, .. } => {
+                lengths[..8].copy_from_slice(&(aad_len * 8).to_be_bytes());
+                lengths[8..].copy_from_slice(&(ct_len * 8).to_be_bytes());
+                ghash.block(&lengths);
+                let tag = ghash.y ^ u128::from_be_bytes(*mask);
+                tag.to_be_bytes()
+            }
+            Cipher::ChaCha { poly, .. } => {
+                lengths[..8].copy_from_slice(&aad_len.to_le_bytes());
+                lengths[8..].copy_from_slice(&ct_len.to_le_bytes());
+                poly.block(&lengths);
+                poly.tag()
+            }
+        }
+    }
+}
+
+struct Decryptor {
+    cipher: Cipher,
+    keystream: [u8; 64],
+    /// Keystream bytes produced and consumed so far in `keystream`.
+    ks_len: usize,
+    ks_used: usize,
+    /// Ciphertext not yet fed to the MAC, short of a full block.
+    pending: [u8; 16],
+    pending_len: usize,
+    aad_len: u64,
+    ct_len: u64,
+    finished: bool,
+}
+
+impl Decryptor {
+    /// Feed `data` to the MAC, keeping a partial final block in `pending`.
+    fn absorb(&mut self, mut data: &[u8]) {
+        if self.pending_len > 0 {
+            let take = (16 - self.pending_len).min(data.len());
+            self.pending[self.pending_len..][..take].copy_from_slice(&data[..take]);
+            self.pending_len += take;
+            data = &data[take..];
+            if self.pending_len < 16 {
+                return;
+            }
+            let block = self.pending;
+            self.cipher.mac_block(&block);
+            self.pending_len = 0;
+        }
+        let mut blocks = data.chunks_exact(16);
+        for block in &mut blocks {
+            self.cipher.mac_block(block.try_into().unwrap());
+        }
+        let rest = blocks.remainder();
+        self.pending[..rest.len()].copy_from_slice(rest);
+        self.pending_len = rest.len();
+    }
+
+    /// Zero-pad and absorb any partial block.
+    fn pad(&mut self) {
+        if self.pending_len > 0 {
+            self.pending[self.pending_len..].fill(0);
+            let block = self.pending;
+            self.cipher.mac_block(&block);
+            self.pending_len = 0;
+        }
+    }
+
+    /// Authenticate the ciphertext in `data`, then decrypt it in place.
+    fn update(&mut self, data: &mut [u8]) {
+        self.absorb(data);
+        self.ct_len += data.len() as u64;
+        let mut i = 0;
+        while i < data.len() {
+            if self.ks_used == self.ks_len {
+                self.ks_len = self.cipher.keystream(&mut self.keystream);
+                self.ks_used = 0;
+            }
+            let n = (self.ks_len - self.ks_used).min(data.len() - i);
+            let keystream = &self.keystream[self.ks_used..][..n];
+            for (d, k) in data[i..i + n].iter_mut().zip(keystream) {
+                *d ^= k;
+            }
+            i += n;
+            self.ks_used += n;
+        }
+    }
+}
+
+/// Start decrypting a stream encrypted with `algorithm` (`AEAD_AES_GCM` or
+/// `AEAD_CHACHA20_POLY1305`) under the key at `key_ptr` and the 12-byte
+/// nonce at `nonce_ptr`. The additional authenticated data at `aad_ptr` is
+/// hashed up front; pass an empty range when there is none.
+///
+/// Returns a handle for `aead_decrypt_update` and `aead_decrypt_finish`,
+/// released with `handle_drop`, or `-1` for an unknown algorithm, a key of
+/// the wrong length or a nonce that is not 12 bytes.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn aead_decrypt_new(
+    algorithm: u32,
+    key_ptr: *const u8,
+    key_len: usize,
+    nonce_ptr: *const u8,
+    nonce_len: usize,
+    aad_ptr: *const u8,
+    aad_len: usize,
+) -> isize {
+    if nonce_len != NONCE_LEN {
+        return -1;
+    }
+    let key = ffi::slice(key_ptr, key_len);
+    let nonce = ffi::slice(nonce_ptr, nonce_len);
+    let cipher = match (algorithm, key_len) {
+        (AEAD_AES_GCM, 16 | 24 | 32) => {
+            let aes = Aes::new(key);
+            let h = u128::from_be_bytes(aes.encrypt(&[0; 16]));
+            let mut counter = [0u8; 16];
+            counter[..12].copy_from_slice(nonce);
+            counter[15] = 1;
+            let mask = aes.encrypt(&counter);
+            Cipher::Gcm {
+                aes,
+                counter,
+                ghash: Ghash::new(h),
+                mask,
+            }
+        }
+        (AEAD_CHACHA20_POLY1305, 32) => {
+            let mut state = [0u32; 16];
+            state[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+            for (word, bytes) in state[4..12].iter_mut().zip(key.chunks_exact(4)) {
+                *word = u32::from_le_bytes(bytes.try_into().unwrap());
+            }
+            for (word, bytes) in state[13..].iter_mut().zip(nonce.chunks_exact(4)) {
+                *word = u32::from_le_bytes(bytes.try_into().unwrap());
+            }
+            // Block 0 keys Poly1305; the message starts at block 1.
+            let block = chacha20_block(&state);
+            state[12] = 1;
+            Cipher::ChaCha {
+                state,
+                poly: Poly1305::new(block[..32].try_into().unwrap()),
+            }
+        }
+        _ => return -1,
+    };
+    let mut decryptor = Decryptor {
+        cipher,
+        keystream: [0; 64],
+        ks_len: 0,
+        ks_used: 0,
+        pending: [0; 16],
+        pending_len: 0,
+        aad_len: aad_len as u64,
+        ct_len: 0,
+        finished: false,
+    };
+    decryptor.absorb(ffi::slice(aad_ptr, aad_len));
+    decryptor.pad();
+    handles::insert(decryptor) as isize
+}
+
+/// Decrypt the next `in_len` bytes of ciphertext (without the tag) into
+/// `out_ptr`. Runs in place when `in_ptr == out_ptr`. The plaintext is
+/// unauthenticated until `aead_decrypt_finish` returns `1`.
+///
+/// Returns bytes written (`in_len`), or `-1` for an unknown or finished
+/// handle or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn aead_decrypt_update(
+    handle: u32,
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    if out_len < in_len {
+        return -1;
+    }
+    let in_place = std::ptr::eq(in_ptr, out_ptr);
+    if !in_place && ffi::aliased(in_ptr, in_len, out_ptr, in_len) {
+        return ffi::ALIAS_ERROR;
+    }
+    // The output is only touched once the handle is known to accept input.
+    handles::with(handle, |d: &mut Decryptor| {
+        if d.finished {
+            return -1;
+        }
+        if !in_place {
+            // `copy` handles a partially overlapping input like the byte kernels.
+            std::ptr::copy(in_ptr, out_ptr, in_len);
+        }
+        d.update(ffi::slice_mut(out_ptr, in_len));
+        in_len as isize
+    })
+    .unwrap_or(-1)
+}
+
+/// Check the 16-byte tag at `tag_ptr` once all ciphertext has been passed
+/// to `aead_decrypt_update`. The handle accepts no more input afterwards.
+///
+/// Returns `1` if the stream is authentic, `0` if it was tampered with or
+/// decrypted with the wrong key, nonce or additional data, or `-1` for an
+/// unknown or finished handle or a tag that is not 16 bytes.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn aead_decrypt_finish(
+    handle: u32,
+    tag_ptr: *const u8,
+    tag_len: usize,
+) -> isize {
+    if tag_len != TAG_LEN {
+        return -1;
+    }
+    let tag = ffi::slice(tag_ptr, tag_len);
+    handles::with(handle, |d: &mut Decryptor| {
+        if d.finished {
+            return -1;
+        }
+        d.finished = true;
+        d.pad();
+        let expected = d.cipher.tag(d.aad_len, d.ct_len);
+        let diff = expected
+            .iter()
+            .zip(tag)
+            .fold(0, |acc, (a, b)| acc | (a ^ b));
+        (diff == 0) as isize
+    })
+    .unwrap_or(-1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    const GCM_IV: &str = "cafebabefacedbaddecaf888";
+    const GCM_AAD: &str = "feedfacedeadbeeffeedfacedeadbeefabaddad2";
+    const GCM_PLAIN: &str = "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72\
+        1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39";
+
+    /// `(algorithm, key, nonce, aad, plaintext, ciphertext || tag)`.
+    type Vector = (u32, Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>);
+
+    /// The GCM spec's test case 4 with 128-, 192- and 256-bit keys, and RFC
+    /// 8439 section 2.8.2.
+    fn vectors() -> Vec<Vector> {
+        let gcm = |key: &str, sealed: &str| {
+            (
+                AEAD_AES_GCM,
+                unhex(key),
+                unhex(GCM_IV),
+                unhex(GCM_AAD),
+                unhex(GCM_PLAIN),
+                unhex(sealed),
+            )
+        };
+        vec![
+            gcm(
+                "feffe9928665731c6d6a8f9467308308",
+                "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e\
+                 21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091\
+                 5bc94fbc3221a5db94fae95ae7121a47",
+            ),
+            gcm(
+                "feffe9928665731c6d6a8f9467308308feffe9928665731c",
+                "3980ca0b3c00e841eb06fac4872a2757859e1ceaa6efd984628593b40ca1e19c\
+                 7d773d00c144c525ac619d18c84a3f4718e2448b2fe324d9ccda2710\
+                 2519498e80f1478f37ba55bd6d27618c",
+            ),
+            gcm(
+                "feffe9928665731c6d6a8f9467308308feffe9928665731c6d6a8f9467308308",
+                "522dc1f099567d07f47f37a32a84427d643a8cdcbfe5c0c97598a2bd2555d1aa\
+                 8cb08e48590dbb3da7b08b1056828838c5f61e6393ba7a0abcc9f662\
+                 76fc6ece0f4e1768cddf8853bb2d551b",
+            ),
+            (
+                AEAD_CHACHA20_POLY1305,
+                (0x80..0xa0).collect(),
+                unhex("070000004041424344454647"),
+                unhex("50515253c0c1c2c3c4c5c6c7"),
+                b"Ladies and Gentlemen of the class of '99: If I could offer you only \
+                  one tip for the future, sunscreen would be it."
+                    .to_vec(),
+                unhex(
+                    "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6\
+                     3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36\
+                     92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc\
+                     3ff4def08e4b7a9de576d26586cec64b61161ae10b594f09e26a7e902ecbd060\
+                     0691",
+                ),
+            ),
+        ]
+    }
+
+    fn open(algorithm: u32, key: &[u8], nonce: &[u8], aad: &[u8]) -> u32 {
+        let handle = unsafe {
+            aead_decrypt_new(
+                algorithm,
+                key.as_ptr(),
+                key.len(),
+                nonce.as_ptr(),
+                nonce.len(),
+                aad.as_ptr(),
+                aad.len(),
+            )
+        };
+        assert!(handle > 0);
+        handle as u32
+    }
+
+    /// Decrypt `sealed` in chunks of `chunk` bytes, returning the plaintext
+    /// and the tag check.
+    fn decrypt(handle: u32, sealed: &[u8], chunk: usize) -> (Vec<u8>, isize) {
+        let (ct, tag) = sealed.split_at(sealed.len() - 16);
+        let mut out = vec![0u8; ct.len()];
+        for (src, dst) in ct.chunks(chunk).zip(out.chunks_mut(chunk)) {
+            let written = unsafe {
+                aead_decrypt_update(handle, src.as_ptr(), src.len(), dst.as_mut_ptr(), dst.len())
+            };
+            assert_eq!(written, src.len() as isize);
+        }
+        let valid = unsafe { aead_decrypt_finish(handle, tag.as_ptr(), tag.len()) };
+        assert_eq!(handles::handle_drop(handle), 0);
+        (out, valid)
+    }
+
+    #[test]
+    fn aes_block_matches_fips_197() {
+        let key: Vec<u8> = (0..16).collect();
+        let block = unhex("00112233445566778899aabbccddeeff");
+        let out = Aes::new(&key).encrypt(block.as_slice().try_into().unwrap());
+        assert_eq!(out.to_vec(), unhex("69c4e0d86a7b0430d8cdb78070b4c55a"));
+    }
+
+    #[test]
+    fn decrypts_in_chunks_of_any_size() {
+        for (algorithm, key, nonce, aad, plain, sealed) in vectors() {
+            for chunk in [1, 5, 16, 17, 64, 1000] {
+                let handle = open(algorithm, &key, &nonce, &aad);
+                assert_eq!(
+                    decrypt(handle, &sealed, chunk),
+                    (plain.clone(), 1),
+                    "{chunk}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_tampered_streams() {
+        for (algorithm, key, nonce, aad, plain, sealed) in vectors() {
+            let mut flipped = sealed.clone();
+            flipped[3] ^= 0x40;
+            let (out, valid) = decrypt(open(algorithm, &key, &nonce, &aad), &flipped, 7);
+            assert_eq!(valid, 0);
+            assert_eq!(out[3], plain[3] ^ 0x40, "plaintext is released unverified");
+            let mut tag = sealed.clone();
+            *tag.last_mut().unwrap() ^= 1;
+            assert_eq!(decrypt(open(algorithm, &key, &nonce, &aad), &tag, 7).1, 0);
+            let handle = open(algorithm, &key, &nonce, &aad[1..]);
+            assert_eq!(decrypt(handle, &sealed, 7).1, 0, "different aad");
+        }
+    }
+
+    #[test]
+    fn in_place_and_bad_arguments() {
+        let (algorithm, key, nonce, aad, plain, sealed) = vectors().swap_remove(3);
+        let handle = open(algorithm, &key, &nonce, &aad);
+        let (mut buf, tag) = {
+            let (ct, tag) = sealed.split_at(sealed.len() - 16);
+            (ct.to_vec(), tag.to_vec())
+        };
+        let written = unsafe {
+            aead_decrypt_update(handle, buf.as_ptr(), buf.len(), buf.as_mut_ptr(), buf.len())
+        };
+        assert_eq!(written, buf.len() as isize);
+        assert_eq!(buf, plain);
+        let mut short = [0u8; 4];
+        let call = |handle, out: &mut [u8]| unsafe {
+            aead_decrypt_update(handle, tag.as_ptr(), 8, out.as_mut_ptr(), out.len())
+        };
+        assert_eq!(call(handle, &mut short), -1, "short output");
+        assert_eq!(unsafe { aead_decrypt_finish(handle, tag.as_ptr(), 15) }, -1);
+        assert_eq!(unsafe { aead_decrypt_finish(handle, tag.as_ptr(), 16) }, 1);
+        assert_eq!(unsafe { aead_decrypt_finish(handle, tag.as_ptr(), 16) }, -1);
+        let mut out = [0u8; 8];
+        assert_eq!(call(handle, &mut out), -1, "finished");
+        assert_eq!(out, [0; 8], "output untouched for a finished handle");
+        assert_eq!(handles::handle_drop(handle), 0);
+        assert_eq!(call(handle, &mut out), -1, "unknown");
+        assert_eq!(out, [0; 8], "output untouched for an unknown handle");
+
+        let bad = |algorithm, key: &[u8], nonce: &[u8]| unsafe {
+            aead_decrypt_new(
+                algorithm,
+                key.as_ptr(),
+                key.len(),
+                nonce.as_ptr(),
+                nonce.len(),
+                key.as_ptr(),
+                0,
+            )
+        };
+        assert_eq!(bad(AEAD_AES_GCM, &key[..20], &nonce), -1);
+        assert_eq!(bad(AEAD_CHACHA20_POLY1305, &key[..16], &nonce), -1);
+        assert_eq!(bad(AEAD_AES_GCM, &key[..16], &nonce[..8]), -1);
+        assert_eq!(bad(2, &key, &nonce), -1);
+    }
+}
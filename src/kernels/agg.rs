@@ -0,0 +1,200 @@
+//! Incremental aggregates maintained across appended chunks.
+//!
+//! A dashboard over streaming data creates one aggregator per statistic with
+//! `agg_ctx_new`, feeds each new chunk of `f64` values to `agg_ctx_update`
+//! and reads `agg_ctx_result` whenever it redraws, so the cost of an update
+//! is the chunk, not everything seen so far. `NaN` values (gaps) are
+//! skipped by every kind.
+
+use super::hll::{mix, Hll};
+use crate::error::{self, ErrorCode};
+use crate::{ffi, handles};
+
+/// Sum of the values.
+pub const AGG_SUM: u32 = 0;
+/// Smallest value.
+pub const AGG_MIN: u32 = 1;
+/// Largest value.
+pub const AGG_MAX: u32 = 2;
+/// Number of values.
+pub const AGG_COUNT: u32 = 3;
+/// Sample variance of the values.
+pub const AGG_VARIANCE: u32 = 4;
+/// Approximate number of distinct values (HyperLogLog, about 1% error).
+pub const AGG_DISTINCT: u32 = 5;
+
+enum Agg {
+    /// Neumaier-compensated, so long streams of small values do not drift.
+    Sum {
+        sum: f64,
+        compensation: f64,
+    },
+    Min(f64),
+    Max(f64),
+    Count(u64),
+    /// Welford's running mean and sum of squared deviations.
+    Variance {
+        count: u64,
+        mean: f64,
+        m2: f64,
+    },
+    Distinct(Hll),
+}
+
+impl Agg {
+    fn new(kind: u32) -> Option<Self> {
+        Some(match kind {
+            AGG_SUM => Agg::Sum {
+                sum: 0.0,
+                compensation: 0.0,
+            },
+            AGG_MIN => Agg::Min(f64::NAN),
+            AGG_MAX => Agg::Max(f64::NAN),
+            AGG_COUNT => Agg::Count(0),
+            AGG_VARIANCE => Agg::Variance {
+                count: 0,
+                mean: 0.0,
+                m2: 0.0,
+            },
+            AGG_DISTINCT => Agg::Distinct(Hll::new(14)),
+            _ => return None,
+        })
+    }
+
+    fn update(&mut self, v: f64) {
+        match self {
+            Agg::Sum { sum, compensation } => {
+                let t = *sum + v;
+                *compensation += if sum.abs() >= v.abs() {
+                    (*sum - t) + v
+                } else {
+                    (v - t) + *sum
+                };
+                *sum = t;
+            }
+            // `f64::min` skips the initial `NaN`.
+            Agg::Min(min) => *min = min.min(v),
+            Agg::Max(max) => *max = max.max(v),
+            Agg::Count(count) => *count += 1,
+            Agg::Variance { count, mean, m2 } => {
+                *count += 1;
+                let delta = v - *mean;
+                *mean += delta / *count as f64;
+                *m2 += delta * (v - *mean);
+            }
+            // `+ 0.0` folds -0.0 into 0.0 so both count once.
+            Agg::Distinct(hll) => hll.add(mix((v + 0.0).to_bits())),
+        }
+    }
+
+    fn result(&self) -> f64 {
+        match self {
+            Agg::Sum { sum, compensation } => sum + compensation,
+            Agg::Min(v) | Agg::Max(v) => *v,
+            Agg::Count(count) => *count as f64,
+            Agg::Variance { count, m2, .. } if *count > 1 => m2 / (*count - 1) as f64,
+            Agg::Variance { .. } => f64::NAN,
+            Agg::Distinct(hll) => hll.estimate().round(),
+        }
+    }
+}
+
+/// Create an empty aggregator of `kind`, one of the `AGG_` constants.
+///
+/// Returns a handle for the other `agg_ctx_` exports, released with
+/// `handle_drop`, or `-1` for an unknown kind.
+#[no_mangle]
+pub extern "C" fn agg_ctx_new(kind: u32) -> isize {
+    match Agg::new(kind) {
+        Some(agg) => handles::insert(agg) as isize,
+        None => error::fail(ErrorCode::InvalidArgument),
+    }
+}
+
+/// Fold the `f64` values at `values_ptr` into the aggregator behind
+/// `handle`. `NaN`s are skipped.
+///
+/// Returns `0`, or `-1` for an unknown handle or a partial value.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn agg_ctx_update(
+    handle: u32,
+    values_ptr: *const f64,
+    values_len_bytes: usize,
+) -> isize {
+    if !values_len_bytes.is_multiple_of(8) {
+        return error::fail(ErrorCode::BadLength);
+    }
+    let values = ffi::slice(values_ptr, values_len_bytes / 8);
+    handles::with(handle, |agg: &mut Agg| {
+        for &v in values.iter().filter(|v| !v.is_nan()) {
+            agg.update(v);
+        }
+        0
+    })
+    .unwrap_or(-1)
+}
+
+/// Current value of the aggregator behind `handle`. Min, max and variance
+/// are `NaN` until there are enough values; sum and count start at `0`.
+///
+/// Returns `NaN` for an unknown handle (any finite value could be a
+/// legitimate result); `last_error_code` then says so.
+#[no_mangle]
+pub extern "C" fn agg_ctx_result(handle: u32) -> f64 {
+    handles::with(handle, |agg: &mut Agg| agg.result()).unwrap_or(f64::NAN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(kind: u32, chunks: &[&[f64]]) -> f64 {
+        let handle = agg_ctx_new(kind) as u32;
+        for chunk in chunks {
+            let status = unsafe { agg_ctx_update(handle, chunk.as_ptr(), chunk.len() * 8) };
+            assert_eq!(status, 0);
+        }
+        let result = agg_ctx_result(handle);
+        assert_eq!(handles::handle_drop(handle), 0);
+        result
+    }
+
+    #[test]
+    fn chunked_updates_match_one_pass() {
+        let chunks: [&[f64]; 3] = [&[4.0, f64::NAN, -2.0], &[], &[7.5, 0.5, -2.0]];
+        assert_eq!(feed(AGG_SUM, &chunks), 8.0);
+        assert_eq!(feed(AGG_MIN, &chunks), -2.0);
+        assert_eq!(feed(AGG_MAX, &chunks), 7.5);
+        assert_eq!(feed(AGG_COUNT, &chunks), 5.0);
+        assert_eq!(feed(AGG_DISTINCT, &chunks), 4.0);
+
+        let values = [4.0, -2.0, 7.5, 0.5, -2.0];
+        let mean = values.iter().sum::<f64>() / 5.0;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / 4.0;
+        assert!((feed(AGG_VARIANCE, &chunks) - variance).abs() < 1e-12);
+    }
+
+    #[test]
+    fn empty_and_compensated_results() {
+        assert_eq!(feed(AGG_SUM, &[]), 0.0);
+        assert_eq!(feed(AGG_COUNT, &[&[f64::NAN]]), 0.0);
+        assert!(feed(AGG_MIN, &[]).is_nan());
+        assert!(feed(AGG_VARIANCE, &[&[1.0]]).is_nan());
+        // Naive summation loses every 1.0 against 1e16.
+        let ones = [1.0; 100];
+        assert_eq!(feed(AGG_SUM, &[&[1e16], &ones, &[-1e16]]), 100.0);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(agg_ctx_new(6), -1);
+        let handle = agg_ctx_new(AGG_SUM) as u32;
+        let values = [1.0f64];
+        assert_eq!(unsafe { agg_ctx_update(handle, values.as_ptr(), 4) }, -1);
+        assert_eq!(handles::handle_drop(handle), 0);
+        assert_eq!(unsafe { agg_ctx_update(handle, values.as_ptr(), 8) }, -1);
+        assert!(agg_ctx_result(handle).is_nan());
+        assert_eq!(error::last_error_code(), ErrorCode::UnknownHandle as u32);
+    }
+}
@@ -0,0 +1,662 @@
+//! Pairwise sequence alignment: Needleman–Wunsch (global) and
+//! Smith–Waterman (local) with linear gap costs and an optional band.
+//!
+//! The score matrix is filled one anti-diagonal at a time. Every cell on an
+//! anti-diagonal depends only on the two before it, so eight cells are
+//! scored at once in `i16x8` lanes with saturating adds. Scores are `i16`,
+//! which bounds the sequence lengths a scoring scheme can handle (see
+//! `align_score`). Both kernels keep three anti-diagonals of scores;
+//! `align_traceback` also stores a one-byte direction per cell it scores.
+
+use crate::ffi;
+
+/// Global alignment of both sequences end to end (Needleman–Wunsch).
+pub const ALIGN_GLOBAL: u32 = 0;
+/// Best-scoring local alignment of any two substrings (Smith–Waterman).
+pub const ALIGN_LOCAL: u32 = 1;
+
+/// `i32` words written by `align_score`: `[score, a_end, b_end]`.
+const SCORE_WORDS: usize = 3;
+/// `i32` words written by `align_traceback`:
+/// `[score, a_start, a_end, b_start, b_end]`.
+const TRACE_WORDS: usize = 5;
+/// Cap on the cells `align_traceback` stores directions for (128 MiB).
+const MAX_TRACE_CELLS: usize = 1 << 27;
+
+/// Score of cells outside the band or matrix. Saturating adds keep it at
+/// the bottom of the `i16` range.
+const NEG: i16 = i16::MIN;
+
+/// Traceback directions, one per cell.
+const STOP: u8 = 0;
+const DIAG: u8 = 1;
+/// From the cell above: `a[i - 1]` against a gap.
+const UP: u8 = 2;
+/// From the cell to the left: `b[j - 1]` against a gap.
+const LEFT: u8 = 3;
+
+struct Scoring {
+    matched: i16,
+    mismatched: i16,
+    gap: i16,
+    local: bool,
+    /// Largest `|i - j|` scored, or `usize::MAX` for the full matrix.
+    band: usize,
+}
+
+/// Score eight consecutive cells of an anti-diagonal from their diagonal,
+/// upper and left neighbours, returning the scores and directions.
+#[inline(always)]
+fn cells(
+    diag: &[i16],
+    up: &[i16],
+    left: &[i16],
+    a: [u8; 8],
+    b: [u8; 8],
+    s: &Scoring,
+) -> ([i16; 8], [i16; 8]) {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let load = |x: &[i16]| v128_load(x.as_ptr() as *const v128);
+        let bytes = |x: [u8; 8]| i16x8_extend_low_u8x16(u64x2_splat(u64::from_le_bytes(x)));
+        let same = i16x8_eq(bytes(a), bytes(b));
+        let sub = v128_bitselect(i16x8_splat(s.matched), i16x8_splat(s.mismatched), same);
+        let gap = i16x8_splat(s.gap);
+        let d = i16x8_add_sat(load(diag), sub);
+        let u = i16x8_add_sat(load(up), gap);
+        let l = i16x8_add_sat(load(left), gap);
+        let floor = i16x8_splat(if s.local { 0 } else { NEG });
+        let h = i16x8_max(i16x8_max(d, u), i16x8_max(l, floor));
+        let mut dir = i16x8_splat(LEFT as i16);
+        dir = v128_bitselect(i16x8_splat(UP as i16), dir, i16x8_eq(h, u));
+        dir = v128_bitselect(i16x8_splat(DIAG as i16), dir, i16x8_eq(h, d));
+        if s.local {
+            dir = v128_andnot(dir, i16x8_eq(h, i16x8_splat(0)));
+        }
+        let (mut scores, mut dirs) = ([0i16; 8], [0i16; 8]);
+        v128_store(scores.as_mut_ptr() as *mut v128, h);
+        v128_store(dirs.as_mut_ptr() as *mut v128, dir);
+        (scores, dirs)
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        let (mut scores, mut dirs) = ([0i16; 8], [0i16; 8]);
+        for lane in 0..8 {
+            let sub = if a[lane] == b[lane] {
+                s.matched
+            } else {
+                s.mismatched
+            };
+            let d = diag[lane].saturating_add(sub);
+            let u = up[lane].saturating_add(s.gap);
+            let l = left[lane].saturating_add(s.gap);
+            let floor = if s.local { 0 } else { NEG };
+            let h = d.max(u).max(l).max(floor);
+            scores[lane] = h;
+            dirs[lane] = if s.local && h == 0 {
+                STOP
+            } else if h == d {
+                DIAG
+            } else if h == u {
+                UP
+            } else {
+                LEFT
+            } as i16;
+        }
+        (scores, dirs)
+    }
+}
+
+/// Directions of the cells scored on each anti-diagonal.
+struct Trace {
+    dirs: Vec<u8>,
+    /// Per anti-diagonal: index of its first direction and its first row.
+    starts: Vec<(usize, usize)>,
+}
+
+impl Trace {
+    fn get(&self, i: usize, j: usize) -> u8 {
+        let (start, lo) = self.starts[i + j];
+        let end = self.starts.get(i + j + 1).map_or(self.dirs.len(), |s| s.0);
+        i.checked_sub(lo)
+            .map(|k| start + k)
+            .filter(|&k| k < end)
+            .map_or(STOP, |k| self.dirs[k])
+    }
+}
+
+/// Fill the score matrix of `a` against `b`. Returns the score and end cell
+/// of the alignment, or `None` when a global alignment's end lies outside
+/// the band.
+fn fill(
+    a: &[u8],
+    b: &[u8],
+    s: &Scoring,
+    mut trace: Option<&mut Trace>,
+) -> Option<(i16, usize, usize)> {
+    let (la, lb) = (a.len(), b.len());
+    if !s.local && la.abs_diff(lb) > s.band {
+        return None;
+    }
+    let b_rev: Vec<u8> = b.iter().rev().copied().collect();
+    let width = la + 9;
+    let mut rows = [vec![NEG; width], vec![NEG; width], vec![NEG; width]];
+    let mut best = (0i16, 0usize, 0usize);
+    let boundary = |k: usize| {
+        if s.local {
+            0
+        } else {
+            (k.min(i16::MAX as usize) as i16).saturating_mul(s.gap)
+        }
+    };
+
+    for d in 0..=la + lb {
+        // Rows on this anti-diagonal inside the matrix and the band.
+        let mut lo = d.saturating_sub(lb);
+        let mut hi = la.min(d);
+        if s.band != usize::MAX {
+            lo = lo.max(d.saturating_sub(s.band).div_ceil(2));
+            hi = hi.min((d + s.band) / 2);
+        }
+        let [prev2, prev1, cur] = &mut rows;
+        if let Some(trace) = trace.as_deref_mut() {
+            trace.starts.push((trace.dirs.len(), lo));
+        }
+        if lo <= hi {
+            // Interior cells, `i >= 1` and `j >= 1`, eight at a time.
+            let (first, last) = (lo.max(1), hi.min(d.saturating_sub(1)));
+            let mut i = first;
+            while i <= last {
+                let lane = |src: &[u8], at: usize| -> [u8; 8] {
+                    std::array::from_fn(|k| src.get(at + k).copied().unwrap_or(0))
+                };
+                let (scores, dirs) = cells(
+                    &prev2[i - 1..i + 7],
+                    &prev1[i - 1..i + 7],
+                    &prev1[i..i + 8],
+                    lane(a, i - 1),
+                    lane(&b_rev, lb + i - d),
+                    s,
+                );
+                cur[i..i + 8].copy_from_slice(&scores);
+                let lanes = (last + 1 - i).min(8);
+                if let Some(trace) = trace.as_deref_mut() {
+                    trace.dirs.extend(dirs[..lanes].iter().map(|&d| d as u8));
+                }
+                if s.local {
+                    for (k, &h) in scores[..lanes].iter().enumerate() {
+                        if h > best.0 {
+                            best = (h, i + k, d - i - k);
+                        }
+                    }
+                }
+                i += 8;
+            }
+            // Edge cells: the first row and column.
+            if lo == 0 {
+                cur[0] = boundary(d);
+                if let Some(trace) = trace.as_deref_mut() {
+                    let dir = if d == 0 || s.local { STOP } else { LEFT };
+                    trace.dirs.insert(trace.starts[d].0, dir);
+                }
+            }
+            if hi == d && d > 0 {
+                cur[d] = boundary(d);
+                if let Some(trace) = trace.as_deref_mut() {
+                    trace.dirs.push(if s.local { STOP } else { UP });
+                }
+            }
+        }
+        // Cells just outside this anti-diagonal's range are read by the
+        // next two; mark them as unreachable.
+        if lo >= 1 && lo - 1 < width {
+            cur[lo - 1] = NEG;
+        }
+        let from = if lo <= hi { hi + 1 } else { lo };
+        cur[from.min(width)..(from + 9).min(width)].fill(NEG);
+        rows.rotate_left(1);
+    }
+    if s.local {
+        Some(best)
+    } else {
+        // After the last rotation the final anti-diagonal is `rows[1]`.
+        Some((rows[1][la], la, lb))
+    }
+}
+
+/// Validate the shared arguments, returning the sequences and scoring.
+#[allow(clippy::too_many_arguments)]
+unsafe fn setup<'a>(
+    a_ptr: *const u8,
+    a_len: usize,
+    b_ptr: *const u8,
+    b_len: usize,
+    mode: u32,
+    match_score: i32,
+    mismatch_score: i32,
+    gap_score: i32,
+    band: u32,
+) -> Option<(&'a [u8], &'a [u8], Scoring)> {
+    let local = match mode {
+        ALIGN_GLOBAL => false,
+        ALIGN_LOCAL => true,
+        _ => return None,
+    };
+    // Every score along any path, and every path climbing out of `NEG`,
+    // must stay clear of the `i16` limits.
+    let largest = [match_score, mismatch_score, gap_score]
+        .iter()
+        .map(|s| s.unsigned_abs() as usize)
+        .max()
+        .unwrap();
+    let limit = if local { 32767 } else { 16383 };
+    if largest.checked_mul(a_len.checked_add(b_len)?)? > limit {
+        return None;
+    }
+    let scoring = Scoring {
+        matched: match_score as i16,
+        mismatched: mismatch_score as i16,
+        gap: gap_score as i16,
+        local,
+        band: if band == 0 { usize::MAX } else { band as usize },
+    };
+    Some((ffi::slice(a_ptr, a_len), ffi::slice(b_ptr, b_len), scoring))
+}
+
+/// Align `a` against `b` and write `[score, a_end, b_end]` as `i32`s: the
+/// alignment covers `a[..a_end]` and `b[..b_end]` (the whole sequences in
+/// `ALIGN_GLOBAL` mode). Bytes are compared exactly, so fold case first if
+/// needed. `match_score` and `mismatch_score` score aligned pairs,
+/// `gap_score` each base against a gap (usually negative). `band > 0`
+/// scores only cells within `band` of the main diagonal (`|i - j| <= band`),
+/// which must cover `|a_len - b_len|` for a global alignment; `band == 0`
+/// scores the full matrix.
+///
+/// Scores are `i16`: the largest of `|match_score|`, `|mismatch_score|` and
+/// `|gap_score|`, times `a_len + b_len`, may not exceed 16383 (global) or
+/// 32767 (local).
+///
+/// Returns bytes written (`12`), or `-1` for an unknown mode, scores that
+/// do not fit `i16`, a band too narrow for a global alignment, or a short
+/// output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn align_score(
+    a_ptr: *const u8,
+    a_len: usize,
+    b_ptr: *const u8,
+    b_len: usize,
+    mode: u32,
+    match_score: i32,
+    mismatch_score: i32,
+    gap_score: i32,
+    band: u32,
+    out_ptr: *mut i32,
+    out_len_bytes: usize,
+) -> isize {
+    if out_len_bytes < SCORE_WORDS * 4 {
+        return -1;
+    }
+    let Some((a, b, scoring)) = setup(
+        a_ptr,
+        a_len,
+        b_ptr,
+        b_len,
+        mode,
+        match_score,
+        mismatch_score,
+        gap_score,
+        band,
+    ) else {
+        return -1;
+    };
+    let out_bytes = out_ptr as *const u8;
+    if ffi::aliased(a_ptr, a_len, out_bytes, SCORE_WORDS * 4)
+        || ffi::aliased(b_ptr, b_len, out_bytes, SCORE_WORDS * 4)
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    let Some((score, a_end, b_end)) = fill(a, b, &scoring, None) else {
+        return -1;
+    };
+    let out = ffi::slice_mut(out_ptr, SCORE_WORDS);
+    out.copy_from_slice(&[score as i32, a_end as i32, b_end as i32]);
+    (SCORE_WORDS * 4) as isize
+}
+
+/// Align `a` against `b` as `align_score` does and also recover the
+/// alignment. Writes `[score, a_start, a_end, b_start, b_end]` as `i32`s to
+/// `out_ptr`, and the alignment as one byte per column to `ops_ptr`: `=`
+/// for a matching pair, `X` for a mismatch, `D` for a base of `a` against a
+/// gap and `I` for a base of `b` against a gap. An ops buffer of
+/// `a_len + b_len` bytes is always enough.
+///
+/// Directions are stored for every cell scored, so the full matrix is
+/// limited to 2^27 cells; use a band for longer sequences.
+///
+/// Returns the number of ops written, or `-1` under the conditions of
+/// `align_score`, for too many cells, or for a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn align_traceback(
+    a_ptr: *const u8,
+    a_len: usize,
+    b_ptr: *const u8,
+    b_len: usize,
+    mode: u32,
+    match_score: i32,
+    mismatch_score: i32,
+    gap_score: i32,
+    band: u32,
+    out_ptr: *mut i32,
+    out_len_bytes: usize,
+    ops_ptr: *mut u8,
+    ops_len: usize,
+) -> isize {
+    if out_len_bytes < TRACE_WORDS * 4 {
+        return -1;
+    }
+    let Some((a, b, scoring)) = setup(
+        a_ptr,
+        a_len,
+        b_ptr,
+        b_len,
+        mode,
+        match_score,
+        mismatch_score,
+        gap_score,
+        band,
+    ) else {
+        return -1;
+    };
+    let band_cells = (scoring.band.min(a_len.max(b_len)) * 2 + 1).saturating_mul(a_len + 1);
+    if band_cells.min((a_len + 1) * (b_len + 1)) > MAX_TRACE_CELLS {
+        return -1;
+    }
+    let out_bytes = out_ptr as *const u8;
+    if ffi::aliased(a_ptr, a_len, out_bytes, TRACE_WORDS * 4)
+        || ffi::aliased(b_ptr, b_len, out_bytes, TRACE_WORDS * 4)
+        || ffi::aliased(a_ptr, a_len, ops_ptr, ops_len)
+        || ffi::aliased(b_ptr, b_len, ops_ptr, ops_len)
+        || ffi::overlaps(out_bytes, TRACE_WORDS * 4, ops_ptr, ops_len)
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    let mut trace = Trace {
+        dirs: Vec::new(),
+        starts: Vec::with_capacity(a_len + b_len + 1),
+    };
+    let Some((score, a_end, b_end)) = fill(a, b, &scoring, Some(&mut trace)) else {
+        return -1;
+    };
+    let (mut i, mut j) = (a_end, b_end);
+    let mut ops = Vec::new();
+    loop {
+        match trace.get(i, j) {
+            DIAG => {
+                ops.push(if a[i - 1] == b[j - 1] { b'=' } else { b'X' });
+                (i, j) = (i - 1, j - 1);
+            }
+            UP => {
+                ops.push(b'D');
+                i -= 1;
+            }
+            LEFT => {
+                ops.push(b'I');
+                j -= 1;
+            }
+            _ => break,
+        }
+    }
+    if ops.len() > ops_len {
+        return -1;
+    }
+    ops.reverse();
+    ffi::slice_mut(ops_ptr, ops.len()).copy_from_slice(&ops);
+    let header = [score as i32, i as i32, a_end as i32, j as i32, b_end as i32];
+    ffi::slice_mut(out_ptr, TRACE_WORDS).copy_from_slice(&header);
+    ops.len() as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plain `i32` dynamic program over the full matrix, for comparison.
+    fn reference(
+        a: &[u8],
+        b: &[u8],
+        local: bool,
+        (m, x, g): (i32, i32, i32),
+    ) -> (i32, usize, usize) {
+        let mut h = vec![vec![0i32; b.len() + 1]; a.len() + 1];
+        let mut best = (0, 0, 0);
+        for i in 0..=a.len() {
+            for j in 0..=b.len() {
+                h[i][j] = if i == 0 || j == 0 {
+                    if local {
+                        0
+                    } else {
+                        g * (i + j) as i32
+                    }
+                } else {
+                    let sub = if a[i - 1] == b[j - 1] { m } else { x };
+                    let v = (h[i - 1][j - 1] + sub)
+                        .max(h[i - 1][j] + g)
+                        .max(h[i][j - 1] + g);
+                    if local {
+                        v.max(0)
+                    } else {
+                        v
+                    }
+                };
+            }
+        }
+        // Local: first maximum in anti-diagonal order, lowest row first.
+        for d in 0..=a.len() + b.len() {
+            for i in d.saturating_sub(b.len())..=a.len().min(d) {
+                if h[i][d - i] > best.0 {
+                    best = (h[i][d - i], i, d - i);
+                }
+            }
+        }
+        if local {
+            best
+        } else {
+            (h[a.len()][b.len()], a.len(), b.len())
+        }
+    }
+
+    fn score(a: &[u8], b: &[u8], mode: u32, band: u32) -> Option<[i32; 3]> {
+        let mut out = [0i32; 3];
+        let status = unsafe {
+            align_score(
+                a.as_ptr(),
+                a.len(),
+                b.as_ptr(),
+                b.len(),
+                mode,
+                2,
+                -3,
+                -5,
+                band,
+                out.as_mut_ptr(),
+                12,
+            )
+        };
+        (status == 12).then_some(out)
+    }
+
+    fn traceback(a: &[u8], b: &[u8], mode: u32, band: u32) -> Option<([i32; 5], String)> {
+        let mut out = [0i32; 5];
+        let mut ops = vec![0u8; a.len() + b.len()];
+        let n = unsafe {
+            align_traceback(
+                a.as_ptr(),
+                a.len(),
+                b.as_ptr(),
+                b.len(),
+                mode,
+                2,
+                -3,
+                -5,
+                band,
+                out.as_mut_ptr(),
+                20,
+                ops.as_mut_ptr(),
+                ops.len(),
+            )
+        };
+        ops.truncate(usize::try_from(n).ok()?);
+        Some((out, String::from_utf8(ops).unwrap()))
+    }
+
+    /// Score of `ops` applied to `a[a_start..]` and `b[b_start..]`.
+    fn rescore(a: &[u8], b: &[u8], header: [i32; 5], ops: &str) -> i32 {
+        let (mut i, mut j, mut total) = (header[1] as usize, header[3] as usize, 0);
+        for op in ops.bytes() {
+            match op {
+                b'=' | b'X' => {
+                    assert_eq!(a[i] == b[j], op == b'=');
+                    total += if op == b'=' { 2 } else { -3 };
+                    (i, j) = (i + 1, j + 1);
+                }
+                b'D' => (i, total) = (i + 1, total - 5),
+                _ => (j, total) = (j + 1, total - 5),
+            }
+        }
+        assert_eq!((i as i32, j as i32), (header[2], header[4]));
+        total
+    }
+
+    fn sequence(seed: u32, len: usize) -> Vec<u8> {
+        let mut x = seed;
+        (0..len)
+            .map(|_| {
+                x = x.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                b"ACGT"[(x >> 16) as usize % 4]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn aligns_small_examples() {
+        let (header, ops) = traceback(b"GATTACA", b"GCATGCT", ALIGN_GLOBAL, 0).unwrap();
+        assert_eq!(
+            header,
+            [
+                reference(b"GATTACA", b"GCATGCT", false, (2, -3, -5)).0,
+                0,
+                7,
+                0,
+                7
+            ]
+        );
+        assert_eq!(rescore(b"GATTACA", b"GCATGCT", header, &ops), header[0]);
+
+        let (header, ops) = traceback(b"TTTTACGTACGTTTTT", b"GGACGTACGGG", ALIGN_LOCAL, 0).unwrap();
+        assert_eq!(ops, "=======");
+        assert_eq!(header, [14, 4, 11, 2, 9]);
+        assert_eq!(
+            score(b"TTTTACGTACGTTTTT", b"GGACGTACGGG", ALIGN_LOCAL, 0),
+            Some([14, 11, 9])
+        );
+
+        // Gaps at either end of a global alignment.
+        let (header, ops) = traceback(b"ACGT", b"", ALIGN_GLOBAL, 0).unwrap();
+        assert_eq!((header, ops.as_str()), ([-20, 0, 4, 0, 0], "DDDD"));
+        let (header, ops) = traceback(b"", b"AC", ALIGN_GLOBAL, 0).unwrap();
+        assert_eq!((header, ops.as_str()), ([-10, 0, 0, 0, 2], "II"));
+        assert_eq!(
+            traceback(b"AAAA", b"CCCC", ALIGN_LOCAL, 0).unwrap(),
+            ([0; 5], String::new())
+        );
+    }
+
+    #[test]
+    fn matches_the_reference_dynamic_program() {
+        for seed in 0..40 {
+            let a = sequence(seed, 5 + (seed as usize * 7) % 60);
+            let mut b = sequence(seed + 1000, 3 + (seed as usize * 13) % 50);
+            // Share a stretch so local alignments are not trivial.
+            b.extend_from_slice(&a[..a.len() / 2]);
+            for (mode, local) in [(ALIGN_GLOBAL, false), (ALIGN_LOCAL, true)] {
+                let (want, a_end, b_end) = reference(&a, &b, local, (2, -3, -5));
+                assert_eq!(
+                    score(&a, &b, mode, 0),
+                    Some([want, a_end as i32, b_end as i32])
+                );
+                let (header, ops) = traceback(&a, &b, mode, 0).unwrap();
+                assert_eq!(header[0], want, "seed {seed}");
+                assert_eq!(rescore(&a, &b, header, &ops), want, "seed {seed}");
+            }
+        }
+    }
+
+    #[test]
+    fn bands_limit_the_scored_cells() {
+        let a = sequence(7, 200);
+        let mut b = a.clone();
+        b.remove(50);
+        b.insert(120, b'A');
+        b[160] = if b[160] == b'C' { b'G' } else { b'C' };
+        let full = score(&a, &b, ALIGN_GLOBAL, 0).unwrap();
+        // The best path stays within one cell of the diagonal.
+        assert_eq!(score(&a, &b, ALIGN_GLOBAL, 2), Some(full));
+        let (header, ops) = traceback(&a, &b, ALIGN_GLOBAL, 2).unwrap();
+        assert_eq!(header[0], full[0]);
+        assert_eq!(rescore(&a, &b, header, &ops), full[0]);
+        assert_eq!(ops.bytes().filter(|&op| op != b'=').count(), 3);
+
+        // A global alignment needs the band to reach the corner.
+        assert_eq!(score(&a, &b[..150], ALIGN_GLOBAL, 10), None);
+        assert!(score(&a, &b[..150], ALIGN_LOCAL, 10).is_some());
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(score(b"A", b"A", 2, 0), None, "unknown mode");
+        let long = vec![b'A'; 2000];
+        assert_eq!(score(&long, &long, ALIGN_GLOBAL, 0), None, "exceeds i16");
+        assert!(score(&long, &long, ALIGN_LOCAL, 0).is_some());
+        let mut out = [0i32; 2];
+        let status = unsafe {
+            align_score(
+                b"A".as_ptr(),
+                1,
+                b"A".as_ptr(),
+                1,
+                0,
+                1,
+                -1,
+                -1,
+                0,
+                out.as_mut_ptr(),
+                8,
+            )
+        };
+        assert_eq!(status, -1, "short output");
+        let mut out = [0i32; 5];
+        let mut ops = [0u8; 1];
+        let status = unsafe {
+            align_traceback(
+                b"AC".as_ptr(),
+                2,
+                b"AC".as_ptr(),
+                2,
+                0,
+                1,
+                -1,
+                -1,
+                0,
+                out.as_mut_ptr(),
+                20,
+                ops.as_mut_ptr(),
+                1,
+            )
+        };
+        assert_eq!(status, -1, "short ops");
+    }
+}
@@ -0,0 +1,386 @@
+//! Approximate nearest-neighbor search over `f32` vectors (HNSW).
+//!
+//! `ann_build` copies a batch of vectors into a hierarchical navigable
+//! small-world graph kept behind a handle, and `ann_query` walks it to find
+//! the `k` vectors closest to a query by squared Euclidean distance. For
+//! cosine similarity, normalize the vectors and queries with
+//! `l2_normalize_f32_rows` first: on unit vectors the two rankings agree.
+//!
+//! This is the plain algorithm from Malkov & Yashunin: nodes get a random top
+//! layer, each layer keeps the `m` nearest neighbors found during insertion
+//! (`2 * m` on layer 0), and lookups descend greedily before a best-first
+//! search on layer 0. Layers are drawn from a fixed seed, so a given input
+//! always builds the same graph.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use super::vector::squared_distance;
+use crate::{ffi, handles};
+
+const DEFAULT_M: usize = 16;
+const DEFAULT_EF_CONSTRUCTION: usize = 100;
+const DEFAULT_EF_SEARCH: usize = 64;
+/// Cap on `m` so adjacency lists stay small.
+const MAX_M: usize = 256;
+
+#[derive(Clone, Copy, PartialEq)]
+struct Candidate {
+    distance: f32,
+    id: u32,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.id.cmp(&other.id))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+pub(crate) struct Hnsw {
+    dim: usize,
+    data: Vec<f32>,
+    /// `links[node][layer]`: neighbor ids on each layer the node is part of.
+    links: Vec<Vec<Vec<u32>>>,
+    entry: u32,
+    m: usize,
+    ef_construction: usize,
+    /// `visited[node] == epoch` marks nodes seen by the current search.
+    visited: Vec<u32>,
+    epoch: u32,
+    rng: u64,
+}
+
+impl Hnsw {
+    fn new(dim: usize, m: usize, ef_construction: usize) -> Hnsw {
+        Hnsw {
+            dim,
+            data: Vec::new(),
+            links: Vec::new(),
+            entry: 0,
+            m,
+            ef_construction: ef_construction.max(m),
+            visited: Vec::new(),
+            epoch: 0,
+            rng: 0x853C_49E6_748F_EA9B,
+        }
+    }
+
+    fn vector(&self, id: u32) -> &[f32] {
+        &self.data[id as usize * self.dim..][..self.dim]
+    }
+
+    fn distance(&self, query: &[f32], id: u32) -> Candidate {
+        Candidate {
+            distance: squared_distance(query, self.vector(id)),
+            id,
+        }
+    }
+
+    fn top_layer(&self) -> usize {
+        self.links
+            .get(self.entry as usize)
+            .map_or(0, |l| l.len() - 1)
+    }
+
+    /// Draw a top layer with `P(layer >= l) = m^-l`.
+    fn random_layer(&mut self) -> usize {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let uniform = ((self.rng >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        ((-uniform.ln()) / (self.m as f64).ln()).min(16.0) as usize
+    }
+
+    /// Best-first search of one layer from `entry`, returning up to `ef`
+    /// nearest nodes, closest first.
+    fn search_layer(
+        &mut self,
+        query: &[f32],
+        entry: Candidate,
+        ef: usize,
+        layer: usize,
+    ) -> Vec<Candidate> {
+        self.epoch = self.epoch.wrapping_add(1);
+        if self.epoch == 0 {
+            self.visited.fill(0);
+            self.epoch = 1;
+        }
+        self.visited[entry.id as usize] = self.epoch;
+        let mut frontier = BinaryHeap::from([Reverse(entry)]);
+        let mut best = BinaryHeap::from([entry]);
+
+        while let Some(Reverse(current)) = frontier.pop() {
+            if best.len() >= ef && current > *best.peek().unwrap() {
+                break;
+            }
+            for i in 0..self.links[current.id as usize][layer].len() {
+                let id = self.links[current.id as usize][layer][i];
+                if std::mem::replace(&mut self.visited[id as usize], self.epoch) == self.epoch {
+                    continue;
+                }
+                let candidate = self.distance(query, id);
+                if best.len() < ef || candidate < *best.peek().unwrap() {
+                    frontier.push(Reverse(candidate));
+                    best.push(candidate);
+                    if best.len() > ef {
+                        best.pop();
+                    }
+                }
+            }
+        }
+        best.into_sorted_vec()
+    }
+
+    /// Walk from the entry point down to `layer + 1`, one greedy hop chain
+    /// per layer, and return the closest node found.
+    fn descend(&mut self, query: &[f32], layer: usize) -> Candidate {
+        let mut entry = self.distance(query, self.entry);
+        for l in (layer + 1..=self.top_layer()).rev() {
+            entry = self.search_layer(query, entry, 1, l)[0];
+        }
+        entry
+    }
+
+    fn insert(&mut self, vector: &[f32]) {
+        let id = self.links.len() as u32;
+        self.data.extend_from_slice(vector);
+        self.visited.push(0);
+        let layer = self.random_layer();
+        self.links.push(vec![Vec::new(); layer + 1]);
+        if id == 0 {
+            return;
+        }
+
+        let top = self.top_layer();
+        let mut entry = self.descend(vector, layer.min(top));
+        for l in (0..=layer.min(top)).rev() {
+            let found = self.search_layer(vector, entry, self.ef_construction, l);
+            entry = found[0];
+            let max_links = if l == 0 { 2 * self.m } else { self.m };
+            let neighbors: Vec<u32> = found.iter().take(self.m).map(|c| c.id).collect();
+            for &n in &neighbors {
+                self.links[n as usize][l].push(id);
+                if self.links[n as usize][l].len() > max_links {
+                    self.prune(n, l, max_links);
+                }
+            }
+            self.links[id as usize][l] = neighbors;
+        }
+        if layer > top {
+            self.entry = id;
+        }
+    }
+
+    /// Keep the `max_links` neighbors of `node` on `layer` closest to it.
+    fn prune(&mut self, node: u32, layer: usize, max_links: usize) {
+        let base = self.vector(node);
+        let mut ranked: Vec<Candidate> = self.links[node as usize][layer]
+            .iter()
+            .map(|&id| Candidate {
+                distance: squared_distance(base, self.vector(id)),
+                id,
+            })
+            .collect();
+        ranked.sort_unstable();
+        self.links[node as usize][layer] = ranked[..max_links].iter().map(|c| c.id).collect();
+    }
+
+    fn query(&mut self, query: &[f32], k: usize, ef: usize) -> Vec<Candidate> {
+        if self.links.is_empty() || k == 0 {
+            return Vec::new();
+        }
+        let entry = self.descend(query, 0);
+        let mut found = self.search_layer(query, entry, ef.max(k), 0);
+        found.truncate(k);
+        found
+    }
+}
+
+/// Build an HNSW index over the `dim`-dimensional vectors at `vectors_ptr`
+/// (`vectors_len_bytes / (dim * 4)` of them, row-major). `m` is the number
+/// of links kept per node and layer, `ef_construction` the candidate list
+/// size while inserting; pass `0` for the defaults (16 and 100). Larger
+/// values build slower but give better recall. An `m` of 1 is raised to 2.
+///
+/// The vectors are copied, so the input can be freed afterwards. Returns a
+/// handle for `ann_query`, released with `handle_drop`, or `-1` when `dim`
+/// is `0`, the input is not a whole number of vectors, or `m` exceeds 256.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn ann_build(
+    vectors_ptr: *const f32,
+    vectors_len_bytes: usize,
+    dim: usize,
+    m: u32,
+    ef_construction: u32,
+) -> isize {
+    let m = match m as usize {
+        0 => DEFAULT_M,
+        1 => 2,
+        m if m > MAX_M => return -1,
+        m => m,
+    };
+    let ef_construction = match ef_construction {
+        0 => DEFAULT_EF_CONSTRUCTION,
+        ef => ef as usize,
+    };
+    if dim == 0
+        || !vectors_len_bytes.is_multiple_of(4)
+        || !(vectors_len_bytes / 4).is_multiple_of(dim)
+    {
+        return -1;
+    }
+    let vectors = ffi::slice(vectors_ptr, vectors_len_bytes / 4);
+    let mut index = Hnsw::new(dim, m, ef_construction);
+    for vector in vectors.chunks_exact(dim) {
+        index.insert(vector);
+    }
+    handles::insert(index) as isize
+}
+
+/// Find the `k` indexed vectors nearest to the query at `query_ptr`, writing
+/// their `u32` ids (insertion order) to `out_ptr`, closest first. `ef` is the
+/// search breadth, at least `k`; pass `0` for the default of 64.
+///
+/// Returns bytes written (`4 * min(k, vectors)`), or `-1` for an unknown
+/// handle, a query of the wrong dimension or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn ann_query(
+    handle: u32,
+    query_ptr: *const f32,
+    query_len_bytes: usize,
+    k: u32,
+    ef: u32,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let ef = match ef {
+        0 => DEFAULT_EF_SEARCH,
+        ef => ef as usize,
+    };
+    let result = handles::with(handle, |index: &mut Hnsw| {
+        if query_len_bytes != index.dim * 4 {
+            return None;
+        }
+        let query = ffi::slice(query_ptr, index.dim);
+        let k = (k as usize).min(index.links.len());
+        if out_len_bytes / 4 < k {
+            return None;
+        }
+        Some(index.query(query, k, ef))
+    });
+    let Some(Some(found)) = result else {
+        return -1;
+    };
+    let out = ffi::slice_mut(out_ptr, found.len());
+    for (slot, candidate) in out.iter_mut().zip(&found) {
+        *slot = candidate.id;
+    }
+    (found.len() * 4) as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_vectors(count: usize, dim: usize, seed: &mut u32) -> Vec<f32> {
+        (0..count * dim)
+            .map(|_| {
+                *seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (*seed >> 8) as f32 / (1 << 24) as f32
+            })
+            .collect()
+    }
+
+    fn query(handle: u32, q: &[f32], k: usize) -> Vec<u32> {
+        let mut out = vec![u32::MAX; k];
+        let written = unsafe {
+            ann_query(
+                handle,
+                q.as_ptr(),
+                q.len() * 4,
+                k as u32,
+                0,
+                out.as_mut_ptr(),
+                k * 4,
+            )
+        };
+        assert!(written >= 0);
+        out.truncate(written as usize / 4);
+        out
+    }
+
+    #[test]
+    fn recall_against_brute_force() {
+        let (count, dim, k) = (300, 8, 10);
+        let mut seed = 7;
+        let data = random_vectors(count, dim, &mut seed);
+        let handle = unsafe { ann_build(data.as_ptr(), data.len() * 4, dim, 8, 0) } as u32;
+
+        let queries = random_vectors(20, dim, &mut seed);
+        let mut hits = 0;
+        for q in queries.chunks(dim) {
+            let mut exact: Vec<(f32, u32)> = data
+                .chunks(dim)
+                .enumerate()
+                .map(|(i, v)| (squared_distance(q, v), i as u32))
+                .collect();
+            exact.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let found = query(handle, q, k);
+            assert_eq!(found.len(), k);
+            hits += found
+                .iter()
+                .filter(|id| exact[..k].iter().any(|e| e.1 == **id))
+                .count();
+        }
+        assert!(hits * 100 >= 20 * k * 90, "recall {hits}/200");
+
+        // An indexed vector finds itself first.
+        assert_eq!(query(handle, &data[42 * dim..43 * dim], 1), [42]);
+        assert_eq!(handles::handle_drop(handle), 0);
+    }
+
+    #[test]
+    fn small_and_invalid_inputs() {
+        let data = [0.0f32, 0.0, 1.0, 1.0, 5.0, 5.0];
+        let handle = unsafe { ann_build(data.as_ptr(), 24, 2, 0, 0) } as u32;
+        assert_eq!(
+            query(handle, &[4.0, 4.0], 10),
+            [2, 1, 0],
+            "k capped at the index size"
+        );
+
+        let mut out = [0u32; 1];
+        let q = [0.0f32; 3];
+        assert_eq!(
+            unsafe { ann_query(handle, q.as_ptr(), 12, 1, 0, out.as_mut_ptr(), 4) },
+            -1,
+            "wrong dim"
+        );
+        assert_eq!(
+            unsafe { ann_query(handle, q.as_ptr(), 8, 2, 0, out.as_mut_ptr(), 4) },
+            -1,
+            "short output"
+        );
+        handles::handle_drop(handle);
+
+        assert_eq!(unsafe { ann_build(data.as_ptr(), 24, 4, 0, 0) }, -1);
+        assert_eq!(unsafe { ann_build(data.as_ptr(), 24, 0, 0, 0) }, -1);
+        assert_eq!(unsafe { ann_build(data.as_ptr(), 24, 2, 1000, 0) }, -1);
+
+        let empty = unsafe { ann_build(data.as_ptr(), 0, 2, 0, 0) } as u32;
+        assert_eq!(query(empty, &[0.0, 0.0], 3), []);
+        handles::handle_drop(empty);
+    }
+}
@@ -0,0 +1,267 @@
+//! Anomaly flags and smoothing over `f32` series.
+//!
+//! `zscore_flags_f32` and `mad_outliers_f32` flag values far from the
+//! centre of the whole series, measured in standard deviations or in
+//! median absolute deviations; the MAD form is not skewed by the outliers
+//! it is looking for. `ewma_f32` computes an exponentially weighted moving
+//! average for trend lines. `NaN` values (gaps in a series) are never
+//! flagged and are left out of every statistic.
+
+use crate::ffi;
+
+/// Scale that makes the MAD of normally distributed data estimate its
+/// standard deviation, so MAD thresholds read like z-score thresholds.
+const MAD_SCALE: f64 = 1.4826;
+
+/// Number of `f32` values in `len_bytes`, or `None` for a partial value.
+fn value_count(len_bytes: usize) -> Option<usize> {
+    len_bytes.is_multiple_of(4).then_some(len_bytes / 4)
+}
+
+/// Shared driver for the flag kernels: set bit `i` of the bitmap at `out_ptr`
+/// when `flag(values[i])` holds, with `flag` built from the whole series.
+unsafe fn flag_batch<F: Fn(f32) -> bool>(
+    in_ptr: *const f32,
+    in_len_bytes: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+    build: impl FnOnce(&[f32]) -> F,
+) -> isize {
+    let Some(n) = value_count(in_len_bytes) else {
+        return -1;
+    };
+    let bytes = n.div_ceil(8);
+    if out_len < bytes {
+        return -1;
+    }
+    if ffi::aliased(in_ptr as *const u8, in_len_bytes, out_ptr, bytes) {
+        return ffi::ALIAS_ERROR;
+    }
+    let values = ffi::slice(in_ptr, n);
+    let out = ffi::slice_mut(out_ptr, bytes);
+    out.fill(0);
+    let flag = build(values);
+    for (i, &v) in values.iter().enumerate() {
+        if flag(v) {
+            out[i / 8] |= 1 << (i % 8);
+        }
+    }
+    bytes as isize
+}
+
+/// Mean and population standard deviation of the non-`NaN` values, summed
+/// in `f64` over two passes.
+fn mean_std(values: &[f32]) -> Option<(f64, f64)> {
+    let present = || values.iter().filter(|v| !v.is_nan()).map(|&v| v as f64);
+    let n = present().count();
+    if n == 0 {
+        return None;
+    }
+    let mean = present().sum::<f64>() / n as f64;
+    let variance = present().map(|v| (v - mean) * (v - mean)).sum::<f64>() / n as f64;
+    Some((mean, variance.sqrt()))
+}
+
+/// Median of `values`, reordering them.
+fn median(values: &mut [f64]) -> f64 {
+    let (mid, odd) = (values.len() / 2, values.len() % 2 == 1);
+    let (low, &mut upper, _) = values.select_nth_unstable_by(mid, f64::total_cmp);
+    if odd {
+        return upper;
+    }
+    let lower = low.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    (lower + upper) / 2.0
+}
+
+/// Flag the values at `in_ptr` whose z-score, `|x - mean| / std` over the
+/// whole series, exceeds `threshold`. Bit `i % 8` of byte `i / 8` of the
+/// bitmap at `out_ptr` is set for value `i`. A constant series has no
+/// outliers.
+///
+/// Returns bytes written (`ceil(n / 8)`), or `-1` for a partial value or a
+/// short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn zscore_flags_f32(
+    in_ptr: *const f32,
+    in_len_bytes: usize,
+    threshold: f32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    flag_batch(in_ptr, in_len_bytes, out_ptr, out_len, |values| {
+        let (mean, std) = mean_std(values).unwrap_or((0.0, 0.0));
+        let limit = threshold as f64 * std;
+        move |v: f32| std > 0.0 && (v as f64 - mean).abs() > limit
+    })
+}
+
+/// Flag the values at `in_ptr` whose robust z-score, `|x - median| / (1.4826
+/// * MAD)`, exceeds `threshold`. The MAD is the median absolute deviation
+/// from the median, and the 1.4826 factor puts thresholds on the same scale
+/// as `zscore_flags_f32` (3.5 is a common choice). When more than half the
+/// values are equal the MAD is zero, and every value that differs from the
+/// median is flagged. The bitmap is laid out as in `zscore_flags_f32`.
+///
+/// Returns bytes written (`ceil(n / 8)`), or `-1` for a partial value or a
+/// short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn mad_outliers_f32(
+    in_ptr: *const f32,
+    in_len_bytes: usize,
+    threshold: f32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    flag_batch(in_ptr, in_len_bytes, out_ptr, out_len, |values| {
+        let mut present: Vec<f64> = values
+            .iter()
+            .filter(|v| !v.is_nan())
+            .map(|&v| v as f64)
+            .collect();
+        let (center, mad) = if present.is_empty() {
+            (0.0, 0.0)
+        } else {
+            let center = median(&mut present);
+            present.iter_mut().for_each(|v| *v = (*v - center).abs());
+            (center, median(&mut present))
+        };
+        let limit = threshold as f64 * MAD_SCALE * mad;
+        move |v: f32| {
+            let deviation = (v as f64 - center).abs();
+            deviation > limit && deviation > 0.0
+        }
+    })
+}
+
+/// Exponentially weighted moving average of the values at `in_ptr`:
+/// `out[0] = x[0]` and `out[i] = alpha * x[i] + (1 - alpha) * out[i - 1]`.
+/// `alpha` must lie in `(0, 1]`; larger values follow the series more
+/// closely. A `NaN` value repeats the previous average, and leading `NaN`s
+/// stay `NaN` until the first value. Runs in place when `in_ptr == out_ptr`.
+/// The average is carried in `f64` and rounded once per output.
+///
+/// Returns bytes written, or `-1` for a partial value, an `alpha` out of
+/// range or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn ewma_f32(
+    in_ptr: *const f32,
+    in_len_bytes: usize,
+    alpha: f32,
+    out_ptr: *mut f32,
+    out_len_bytes: usize,
+) -> isize {
+    let Some(n) = value_count(in_len_bytes) else {
+        return -1;
+    };
+    if !(alpha > 0.0 && alpha <= 1.0) || out_len_bytes < in_len_bytes {
+        return -1;
+    }
+    if !std::ptr::eq(in_ptr, out_ptr) {
+        if ffi::aliased(
+            in_ptr as *const u8,
+            in_len_bytes,
+            out_ptr as *const u8,
+            in_len_bytes,
+        ) {
+            return ffi::ALIAS_ERROR;
+        }
+        // `copy` handles a partially overlapping input like the byte kernels.
+        std::ptr::copy(in_ptr, out_ptr, n);
+    }
+    let alpha = alpha as f64;
+    let mut average = f64::NAN;
+    for v in ffi::slice_mut(out_ptr, n) {
+        if !v.is_nan() {
+            average = if average.is_nan() {
+                *v as f64
+            } else {
+                alpha * *v as f64 + (1.0 - alpha) * average
+            };
+        }
+        *v = average as f32;
+    }
+    in_len_bytes as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Flags = unsafe extern "C" fn(*const f32, usize, f32, *mut u8, usize) -> isize;
+
+    fn flagged(export: Flags, values: &[f32], threshold: f32) -> Vec<usize> {
+        let mut bitmap = vec![0xffu8; values.len().div_ceil(8)];
+        let written = unsafe {
+            export(
+                values.as_ptr(),
+                values.len() * 4,
+                threshold,
+                bitmap.as_mut_ptr(),
+                bitmap.len(),
+            )
+        };
+        assert_eq!(written, bitmap.len() as isize);
+        (0..values.len())
+            .filter(|&i| bitmap[i / 8] >> (i % 8) & 1 == 1)
+            .collect()
+    }
+
+    #[test]
+    fn flags_zscore_outliers() {
+        let mut values = vec![10.0f32; 20];
+        for (i, v) in values.iter_mut().enumerate() {
+            *v += (i % 3) as f32 - 1.0;
+        }
+        values[7] = 40.0;
+        values[15] = f32::NAN;
+        assert_eq!(flagged(zscore_flags_f32, &values, 3.0), [7]);
+        assert_eq!(flagged(zscore_flags_f32, &[5.0; 9], 0.0), []);
+        assert_eq!(flagged(zscore_flags_f32, &[], 1.0), []);
+
+        let status =
+            unsafe { zscore_flags_f32(values.as_ptr(), 79, 3.0, [0u8; 3].as_mut_ptr(), 3) };
+        assert_eq!(status, -1, "partial value");
+        let status =
+            unsafe { zscore_flags_f32(values.as_ptr(), 80, 3.0, [0u8; 2].as_mut_ptr(), 2) };
+        assert_eq!(status, -1, "short bitmap");
+    }
+
+    #[test]
+    fn mad_ignores_the_outliers_it_finds() {
+        // Two large outliers inflate the standard deviation enough to hide
+        // each other from the z-score, but not from the MAD.
+        let values = [1.0f32, 2.0, 3.0, 2.0, 1.0, 3.0, 2.0, 100.0, 100.0, 2.5];
+        assert_eq!(flagged(zscore_flags_f32, &values, 3.0), []);
+        assert_eq!(flagged(mad_outliers_f32, &values, 3.5), [7, 8]);
+        assert_eq!(median(&mut [4.0, 1.0, 3.0, 2.0]), 2.5);
+
+        // Zero MAD: anything off the median is an outlier.
+        let values = [5.0f32, 5.0, 5.0, 6.0, 5.0, f32::NAN];
+        assert_eq!(flagged(mad_outliers_f32, &values, 3.5), [3]);
+    }
+
+    #[test]
+    fn smooths_with_ewma() {
+        let values = [f32::NAN, 10.0, 20.0, f32::NAN, 0.0];
+        let mut out = [0f32; 5];
+        let written = unsafe { ewma_f32(values.as_ptr(), 20, 0.5, out.as_mut_ptr(), 20) };
+        assert_eq!(written, 20);
+        assert!(out[0].is_nan());
+        assert_eq!(&out[1..], [10.0, 15.0, 15.0, 7.5]);
+
+        let mut in_place = values;
+        let ptr = in_place.as_mut_ptr();
+        assert_eq!(unsafe { ewma_f32(ptr, 20, 0.5, ptr, 20) }, 20);
+        assert_eq!(in_place[1..], out[1..]);
+
+        for alpha in [0.0, 1.5, f32::NAN] {
+            let status = unsafe { ewma_f32(values.as_ptr(), 20, alpha, out.as_mut_ptr(), 20) };
+            assert_eq!(status, -1);
+        }
+        let status = unsafe { ewma_f32(values.as_ptr(), 20, 0.5, out.as_mut_ptr(), 16) };
+        assert_eq!(status, -1);
+    }
+}
@@ -0,0 +1,489 @@
+//! Arrow IPC stream snapshots of datasets.
+//!
+//! `dataset_to_arrow` serializes a dataset as an Arrow IPC stream: a schema
+//! message, one record batch holding every row, and the end-of-stream
+//! marker. Arrow JS reads it with `tableFromIPC`, DuckDB-wasm with
+//! `insertArrowFromIPCStream`, and it can be saved as an `.arrows` file.
+//! The format's flatbuffer metadata is written by hand: only a handful of
+//! tables are needed, which does not justify a flatbuffers dependency.
+//!
+//! Number columns become `Float64` and string columns `Utf8`, all nullable.
+//! A row is null when it is missing as in `describe_dataset`, so an `f64`
+//! `NaN` is exported as null (its value is kept in the data buffer).
+
+use super::dataset::{Column, Dataset, Values};
+use crate::error::{self, ErrorCode};
+use crate::{ffi, handles, packed};
+
+const CONTINUATION: u32 = 0xFFFF_FFFF;
+const METADATA_V5: i16 = 4;
+const ENDIANNESS_LITTLE: i16 = 0;
+const HEADER_SCHEMA: u8 = 1;
+const HEADER_RECORD_BATCH: u8 = 3;
+const TYPE_FLOATING_POINT: u8 = 3;
+const TYPE_UTF8: u8 = 5;
+const PRECISION_DOUBLE: i16 = 2;
+
+/// A flatbuffer field value.
+enum Fb<'a> {
+    U8(u8),
+    I16(i16),
+    I64(i64),
+    Str(&'a str),
+    Table(Table<'a>),
+    Tables(Vec<Table<'a>>),
+    /// A vector of two-`i64` structs (Arrow's `FieldNode` and `Buffer`).
+    Pairs(Vec<[i64; 2]>),
+}
+
+impl Fb<'_> {
+    /// Inline size, which is also the alignment; everything else is a
+    /// 4-byte offset to data written after the table.
+    fn size(&self) -> usize {
+        match self {
+            Fb::U8(_) => 1,
+            Fb::I16(_) => 2,
+            Fb::I64(_) => 8,
+            _ => 4,
+        }
+    }
+}
+
+/// A table's fields as `(vtable slot, value)`.
+struct Table<'a>(Vec<(usize, Fb<'a>)>);
+
+fn pad(buf: &mut Vec<u8>, align: usize) {
+    buf.resize(buf.len().next_multiple_of(align), 0);
+}
+
+/// Point the offset field at `at` to `target`, which follows it.
+fn patch(buf: &mut [u8], at: usize, target: usize) {
+    buf[at..at + 4].copy_from_slice(&((target - at) as u32).to_le_bytes());
+}
+
+/// Append `table` and everything it references, returning the table's
+/// position. Each table is preceded by its own vtable and starts on 8 bytes,
+/// and its fields are placed largest first, so every scalar is naturally
+/// aligned as long as `buf` itself is.
+fn write_table(buf: &mut Vec<u8>, table: &Table) -> usize {
+    let mut fields: Vec<&(usize, Fb)> = table.0.iter().collect();
+    fields.sort_by_key(|(_, value)| std::cmp::Reverse(value.size()));
+    let slots = table.0.iter().map(|&(slot, _)| slot + 1).max().unwrap_or(0);
+    let mut vtable = vec![0u16; 2 + slots];
+    // Inline positions, after the table's leading offset to its vtable.
+    let mut inline = 4usize;
+    let mut placed = Vec::with_capacity(fields.len());
+    for (slot, value) in &fields {
+        inline = inline.next_multiple_of(value.size());
+        vtable[2 + slot] = inline as u16;
+        placed.push(inline);
+        inline += value.size();
+    }
+    vtable[0] = (vtable.len() * 2) as u16;
+    vtable[1] = inline as u16;
+
+    pad(buf, 2);
+    let vtable_at = buf.len();
+    buf.extend(vtable.iter().flat_map(|v| v.to_le_bytes()));
+    pad(buf, 8);
+    let at = buf.len();
+    buf.resize(at + inline, 0);
+    buf[at..at + 4].copy_from_slice(&((at - vtable_at) as i32).to_le_bytes());
+    for ((_, value), &offset) in fields.iter().zip(&placed) {
+        let field = at + offset;
+        match value {
+            Fb::U8(v) => buf[field] = *v,
+            Fb::I16(v) => buf[field..field + 2].copy_from_slice(&v.to_le_bytes()),
+            Fb::I64(v) => buf[field..field + 8].copy_from_slice(&v.to_le_bytes()),
+            _ => {
+                let target = write_ref(buf, value);
+                patch(buf, field, target);
+            }
+        }
+    }
+    at
+}
+
+/// Append the out-of-line data of a string, table or vector field,
+/// returning where its offset must point.
+fn write_ref(buf: &mut Vec<u8>, value: &Fb) -> usize {
+    match value {
+        Fb::Str(s) => {
+            pad(buf, 4);
+            let at = buf.len();
+            buf.extend((s.len() as u32).to_le_bytes());
+            buf.extend(s.as_bytes());
+            buf.push(0);
+            at
+        }
+        Fb::Table(table) => write_table(buf, table),
+        Fb::Tables(tables) => {
+            pad(buf, 4);
+            let at = buf.len();
+            buf.extend((tables.len() as u32).to_le_bytes());
+            buf.resize(at + 4 + 4 * tables.len(), 0);
+            for (i, table) in tables.iter().enumerate() {
+                let target = write_table(buf, table);
+                patch(buf, at + 4 + 4 * i, target);
+            }
+            at
+        }
+        Fb::Pairs(pairs) => {
+            // The elements after the length must start on 8 bytes.
+            pad(buf, 8);
+            buf.extend([0; 4]);
+            let at = buf.len();
+            buf.extend((pairs.len() as u32).to_le_bytes());
+            buf.extend(pairs.iter().flatten().flat_map(|v| v.to_le_bytes()));
+            at
+        }
+        Fb::U8(_) | Fb::I16(_) | Fb::I64(_) => unreachable!("scalars are inline"),
+    }
+}
+
+/// The flatbuffer of a `Message` wrapping `header`, padded to 8 bytes.
+fn message(header_type: u8, header: Table, body_len: usize) -> Vec<u8> {
+    let root = Table(vec![
+        (0, Fb::I16(METADATA_V5)),
+        (1, Fb::U8(header_type)),
+        (2, Fb::Table(header)),
+        (3, Fb::I64(body_len as i64)),
+    ]);
+    let mut buf = vec![0; 4];
+    let at = write_table(&mut buf, &root);
+    patch(&mut buf, 0, at);
+    pad(&mut buf, 8);
+    buf
+}
+
+fn schema(dataset: &Dataset) -> Table<'_> {
+    let fields = dataset
+        .columns
+        .iter()
+        .map(|column| {
+            let (type_type, ty) = match column.values {
+                Values::F64(_) => (
+                    TYPE_FLOATING_POINT,
+                    Table(vec![(0, Fb::I16(PRECISION_DOUBLE))]),
+                ),
+                Values::Utf8 { .. } => (TYPE_UTF8, Table(vec![])),
+            };
+            // Readers expect a children vector even for flat types.
+            Table(vec![
+                (0, Fb::Str(&column.name)),
+                (1, Fb::U8(1)),
+                (2, Fb::U8(type_type)),
+                (3, Fb::Table(ty)),
+                (5, Fb::Tables(Vec::new())),
+            ])
+        })
+        .collect();
+    Table(vec![
+        (0, Fb::I16(ENDIANNESS_LITTLE)),
+        (1, Fb::Tables(fields)),
+    ])
+}
+
+/// Null count and body buffer lengths of one column: the validity bitmap
+/// (empty without nulls), then the values, or the offsets and the text.
+fn layout(column: &Column, rows: usize) -> (usize, Vec<usize>) {
+    let nulls = (0..rows).filter(|&row| !column.is_valid(row)).count();
+    let validity = if nulls == 0 { 0 } else { rows.div_ceil(8) };
+    let lens = match &column.values {
+        Values::F64(_) => vec![validity, rows * 8],
+        Values::Utf8 { text, .. } => vec![validity, (rows + 1) * 4, text.len()],
+    };
+    (nulls, lens)
+}
+
+/// Write one column's buffers, each padded to 8 bytes, into `out`, which is
+/// exactly as long as the padded lengths from `layout`.
+fn write_column(column: &Column, rows: usize, nulls: usize, mut out: &mut [u8]) {
+    out.fill(0);
+    let mut next = |len: usize| {
+        let (buffer, rest) = std::mem::take(&mut out).split_at_mut(len.next_multiple_of(8));
+        out = rest;
+        &mut buffer[..len]
+    };
+    let validity = next(if nulls == 0 { 0 } else { rows.div_ceil(8) });
+    if nulls != 0 {
+        for row in (0..rows).filter(|&row| column.is_valid(row)) {
+            validity[row / 8] |= 1 << (row % 8);
+        }
+    }
+    match &column.values {
+        Values::F64(values) => {
+            for (chunk, v) in next(rows * 8).chunks_exact_mut(8).zip(values) {
+                chunk.copy_from_slice(&v.to_le_bytes());
+            }
+        }
+        Values::Utf8 { text, offsets } => {
+            for (chunk, o) in next((rows + 1) * 4).chunks_exact_mut(4).zip(offsets) {
+                chunk.copy_from_slice(&o.to_le_bytes());
+            }
+            next(text.len()).copy_from_slice(text);
+        }
+    }
+}
+
+/// Serialize every column of the dataset behind `handle` as an Arrow IPC
+/// stream (schema, one record batch, end-of-stream marker), for Arrow JS,
+/// DuckDB-wasm or a file. Allocate the output with `alloc_aligned(len, 8)`
+/// so readers can view its buffers in place.
+///
+/// With `out_len == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for an
+/// unknown handle, a string column over 2 GiB (Arrow `Utf8` offsets are
+/// `i32`) or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn dataset_to_arrow(handle: u32, out_ptr: *mut u8, out_len: usize) -> isize {
+    handles::with(handle, |dataset: &mut Dataset| {
+        let rows = dataset.rows;
+        let too_long = |column: &Column| match &column.values {
+            Values::Utf8 { text, .. } => text.len() > i32::MAX as usize,
+            Values::F64(_) => false,
+        };
+        if dataset.columns.iter().any(too_long) {
+            return error::fail(ErrorCode::InvalidInput);
+        }
+        let layouts: Vec<(usize, Vec<usize>)> = dataset
+            .columns
+            .iter()
+            .map(|column| layout(column, rows))
+            .collect();
+        let (mut nodes, mut buffers, mut body_len) = (Vec::new(), Vec::new(), 0);
+        for (nulls, lens) in &layouts {
+            nodes.push([rows as i64, *nulls as i64]);
+            for &len in lens {
+                buffers.push([body_len as i64, len as i64]);
+                body_len += len.next_multiple_of(8);
+            }
+        }
+        let schema = message(HEADER_SCHEMA, schema(dataset), 0);
+        let batch = Table(vec![
+            (0, Fb::I64(rows as i64)),
+            (1, Fb::Pairs(nodes)),
+            (2, Fb::Pairs(buffers)),
+        ]);
+        let batch = message(HEADER_RECORD_BATCH, batch, body_len);
+        // Each message and the end marker start with the continuation word
+        // and a length.
+        let needed = 8 + schema.len() + 8 + batch.len() + body_len + 8;
+        if out_len == 0 {
+            return needed as isize;
+        }
+        if out_len < needed {
+            return error::fail(ErrorCode::ShortOutput);
+        }
+        let out = ffi::slice_mut(out_ptr, needed);
+        let mut at = 0;
+        for (len, bytes) in [(schema.len(), &schema), (batch.len(), &batch)] {
+            out[at..at + 4].copy_from_slice(&CONTINUATION.to_le_bytes());
+            out[at + 4..at + 8].copy_from_slice(&(len as u32).to_le_bytes());
+            out[at + 8..at + 8 + len].copy_from_slice(bytes);
+            at += 8 + len;
+        }
+        for (column, (nulls, lens)) in dataset.columns.iter().zip(&layouts) {
+            let len: usize = lens.iter().map(|len| len.next_multiple_of(8)).sum();
+            write_column(column, rows, *nulls, &mut out[at..at + len]);
+            at += len;
+        }
+        out[at..at + 4].copy_from_slice(&CONTINUATION.to_le_bytes());
+        out[at + 4..].fill(0);
+        needed as isize
+    })
+    .unwrap_or(-1)
+}
+
+/// `dataset_to_arrow` in one call: returns the packed `(ptr, len)` of the
+/// stream in the scratch buffer (16-byte aligned), or `0` on failure.
+#[no_mangle]
+pub extern "C" fn dataset_to_arrow_packed(handle: u32) -> u64 {
+    packed::into_scratch(|out_ptr, out_len| unsafe { dataset_to_arrow(handle, out_ptr, out_len) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::dataset::{dataset_add_f64, dataset_add_utf8, dataset_new};
+    use super::*;
+
+    /// Just enough of a flatbuffer reader to walk the metadata.
+    struct Fbr<'a>(&'a [u8]);
+
+    impl Fbr<'_> {
+        fn u32(&self, at: usize) -> usize {
+            u32::from_le_bytes(self.0[at..at + 4].try_into().unwrap()) as usize
+        }
+        fn i64(&self, at: usize) -> i64 {
+            i64::from_le_bytes(self.0[at..at + 8].try_into().unwrap())
+        }
+        fn deref(&self, at: usize) -> usize {
+            at + self.u32(at)
+        }
+        /// Position of field `slot` of the table at `table`, if present.
+        fn field(&self, table: usize, slot: usize) -> Option<usize> {
+            let soffset = i32::from_le_bytes(self.0[table..table + 4].try_into().unwrap());
+            let vtable = (table as isize - soffset as isize) as usize;
+            let read = |at: usize| u16::from_le_bytes([self.0[at], self.0[at + 1]]) as usize;
+            let entry = 4 + 2 * slot;
+            let offset = if entry < read(vtable) {
+                read(vtable + entry)
+            } else {
+                0
+            };
+            (offset != 0).then_some(table + offset)
+        }
+        fn table(&self, table: usize, slot: usize) -> usize {
+            self.deref(self.field(table, slot).unwrap())
+        }
+        fn str(&self, at: usize) -> &str {
+            std::str::from_utf8(&self.0[at + 4..at + 4 + self.u32(at)]).unwrap()
+        }
+        fn pairs(&self, at: usize) -> Vec<[i64; 2]> {
+            assert_eq!((at + 4) % 8, 0, "struct vector alignment");
+            (0..self.u32(at))
+                .map(|i| [self.i64(at + 4 + 16 * i), self.i64(at + 12 + 16 * i)])
+                .collect()
+        }
+    }
+
+    fn export(handle: u32) -> Vec<u8> {
+        let needed = unsafe { dataset_to_arrow(handle, std::ptr::null_mut(), 0) };
+        let mut out = vec![0xaau8; needed as usize];
+        let written = unsafe { dataset_to_arrow(handle, out.as_mut_ptr(), out.len()) };
+        assert_eq!(written, needed);
+        out
+    }
+
+    /// Split a stream into `(metadata, body)` messages, checking the framing
+    /// and the end marker.
+    fn messages(stream: &[u8]) -> Vec<(&[u8], &[u8])> {
+        let mut out = Vec::new();
+        let mut at = 0;
+        loop {
+            assert_eq!(stream[at..at + 4], CONTINUATION.to_le_bytes());
+            let len = u32::from_le_bytes(stream[at + 4..at + 8].try_into().unwrap()) as usize;
+            if len == 0 {
+                assert_eq!(at + 8, stream.len());
+                return out;
+            }
+            assert_eq!(len % 8, 0);
+            let meta = &stream[at + 8..at + 8 + len];
+            let fb = Fbr(meta);
+            let root = fb.deref(0);
+            let body_len = fb.i64(fb.field(root, 3).unwrap()) as usize;
+            out.push((meta, &stream[at + 8 + len..at + 8 + len + body_len]));
+            at += 8 + len + body_len;
+        }
+    }
+
+    #[test]
+    fn writes_schema_and_record_batch() {
+        let handle = dataset_new() as u32;
+        let (name, values) = ("x", [1.5, f64::NAN, -3.0]);
+        let status = unsafe {
+            dataset_add_f64(
+                handle,
+                name.as_ptr(),
+                1,
+                values.as_ptr(),
+                24,
+                std::ptr::null(),
+                0,
+            )
+        };
+        assert_eq!(status, 0);
+        let (name, text, offsets, validity) = ("label", "abccc", [0u32, 2, 2, 5], [0b101u8]);
+        let status = unsafe {
+            dataset_add_utf8(
+                handle,
+                name.as_ptr(),
+                name.len(),
+                text.as_ptr(),
+                text.len(),
+                offsets.as_ptr(),
+                4,
+                validity.as_ptr(),
+                1,
+            )
+        };
+        assert_eq!(status, 1);
+
+        let stream = export(handle);
+        let messages = messages(&stream);
+        assert_eq!(messages.len(), 2);
+
+        let (meta, body) = messages[0];
+        assert!(body.is_empty());
+        let fb = Fbr(meta);
+        let root = fb.deref(0);
+        assert_eq!(meta[fb.field(root, 0).unwrap()], METADATA_V5 as u8);
+        assert_eq!(meta[fb.field(root, 1).unwrap()], HEADER_SCHEMA);
+        let fields = fb.table(fb.table(root, 2), 1);
+        assert_eq!(fb.u32(fields), 2);
+        for (i, (name, type_type)) in [("x", TYPE_FLOATING_POINT), ("label", TYPE_UTF8)]
+            .into_iter()
+            .enumerate()
+        {
+            let field = fb.deref(fields + 4 + 4 * i);
+            assert_eq!(fb.str(fb.table(field, 0)), name);
+            assert_eq!(meta[fb.field(field, 1).unwrap()], 1, "nullable");
+            assert_eq!(meta[fb.field(field, 2).unwrap()], type_type);
+            assert_eq!(fb.u32(fb.table(field, 5)), 0, "no children");
+        }
+        let float = fb.table(fb.deref(fields + 4), 3);
+        assert_eq!(meta[fb.field(float, 0).unwrap()], PRECISION_DOUBLE as u8);
+
+        let (meta, body) = messages[1];
+        let fb = Fbr(meta);
+        let root = fb.deref(0);
+        assert_eq!(meta[fb.field(root, 1).unwrap()], HEADER_RECORD_BATCH);
+        let batch = fb.table(root, 2);
+        assert_eq!(fb.i64(fb.field(batch, 0).unwrap()), 3);
+        assert_eq!(fb.pairs(fb.table(batch, 1)), [[3, 1], [3, 1]]);
+        let buffers = fb.pairs(fb.table(batch, 2));
+        assert_eq!(buffers.len(), 5);
+        let buffer = |i: usize| {
+            let [offset, len] = buffers[i];
+            assert_eq!(offset % 8, 0);
+            &body[offset as usize..(offset + len) as usize]
+        };
+        assert_eq!(buffer(0), [0b101], "NaN is null");
+        assert_eq!(buffer(1)[..8], 1.5f64.to_le_bytes());
+        assert_eq!(buffer(1)[16..], (-3.0f64).to_le_bytes());
+        assert_eq!(buffer(2), [0b101]);
+        let offsets: Vec<u8> = [0u32, 2, 2, 5]
+            .iter()
+            .flat_map(|o| o.to_le_bytes())
+            .collect();
+        assert_eq!(buffer(3), offsets);
+        assert_eq!(buffer(4), b"abccc");
+        assert_eq!(handles::handle_drop(handle), 0);
+    }
+
+    #[test]
+    fn empty_dataset_and_errors() {
+        let handle = dataset_new() as u32;
+        let stream = export(handle);
+        let messages = messages(&stream);
+        let fb = Fbr(messages[0].0);
+        assert_eq!(
+            fb.u32(fb.table(fb.table(fb.deref(0), 2), 1)),
+            0,
+            "no fields"
+        );
+        let fb = Fbr(messages[1].0);
+        let batch = fb.table(fb.deref(0), 2);
+        assert_eq!(fb.i64(fb.field(batch, 0).unwrap()), 0);
+
+        let mut short = vec![0u8; stream.len() - 1];
+        let written = unsafe { dataset_to_arrow(handle, short.as_mut_ptr(), short.len()) };
+        assert_eq!(written, -1);
+        assert_eq!(handles::handle_drop(handle), 0);
+        assert_eq!(
+            unsafe { dataset_to_arrow(handle, short.as_mut_ptr(), 0) },
+            -1
+        );
+    }
+}
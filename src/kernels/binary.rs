@@ -0,0 +1,366 @@
+//! Structural indexes for binary JSON-like payloads (MessagePack, CBOR).
+//!
+//! Rather than decoding a whole document into JS objects, the index kernels
+//! walk its top-level map or array once and report the byte span of every
+//! key and value. JS can then decode only the entries it touches, slicing
+//! them straight out of the buffer with any MessagePack or CBOR decoder.
+//!
+//! Both kernels write a flat `u32` result: `[kind, count]`, then for an
+//! array (`INDEX_ARRAY`) `[start, end)` per element, or for a map
+//! (`INDEX_MAP`) `[key_start, key_end, value_start, value_end]` per entry.
+//! Nested values are skipped without recursion, so deep nesting cannot
+//! overflow the stack.
+
+use crate::ffi;
+
+pub const INDEX_ARRAY: u32 = 0;
+pub const INDEX_MAP: u32 = 1;
+
+/// Big-endian unsigned integer of `n` bytes at `pos`.
+fn read_be(buf: &[u8], pos: usize, n: usize) -> Option<u64> {
+    let bytes = buf.get(pos..pos.checked_add(n)?)?;
+    Some(bytes.iter().fold(0, |v, &b| (v << 8) | b as u64))
+}
+
+/// A value's head: header length, payload bytes after it, and how many
+/// nested items follow (`None` for CBOR's indefinite-length items, which
+/// end at a break byte).
+struct Head {
+    header: usize,
+    payload: u64,
+    children: Option<u64>,
+}
+
+fn msgpack_head(buf: &[u8], pos: usize) -> Option<Head> {
+    let b = *buf.get(pos)?;
+    let head = |header, payload, children| {
+        Some(Head {
+            header,
+            payload,
+            children: Some(children),
+        })
+    };
+    let sized = |n, extra| Some((read_be(buf, pos + 1, n)?, n + 1 + extra));
+    match b {
+        0x00..=0x7f | 0xc0 | 0xc2 | 0xc3 | 0xe0..=0xff => head(1, 0, 0),
+        0x80..=0x8f => head(1, 0, 2 * (b & 0x0f) as u64),
+        0x90..=0x9f => head(1, 0, (b & 0x0f) as u64),
+        0xa0..=0xbf => head(1, (b & 0x1f) as u64, 0),
+        0xc4..=0xc6 | 0xd9..=0xdb => {
+            let n = [1, 2, 4][(b - if b >= 0xd9 { 0xd9 } else { 0xc4 }) as usize];
+            let (len, header) = sized(n, 0)?;
+            head(header, len, 0)
+        }
+        // ext: length, then a type byte counted in the header.
+        0xc7..=0xc9 => {
+            let (len, header) = sized([1, 2, 4][(b - 0xc7) as usize], 1)?;
+            head(header, len, 0)
+        }
+        0xca | 0xcb => head(1, [4, 8][(b - 0xca) as usize], 0),
+        0xcc..=0xcf => head(1, 1 << (b - 0xcc), 0),
+        0xd0..=0xd3 => head(1, 1 << (b - 0xd0), 0),
+        // fixext: a type byte and 1, 2, 4, 8 or 16 data bytes.
+        0xd4..=0xd8 => head(2, 1 << (b - 0xd4), 0),
+        0xdc | 0xdd => {
+            let (len, header) = sized([2, 4][(b - 0xdc) as usize], 0)?;
+            head(header, 0, len)
+        }
+        0xde | 0xdf => {
+            let (len, header) = sized([2, 4][(b - 0xde) as usize], 0)?;
+            head(header, 0, 2 * len)
+        }
+        0xc1 => None,
+    }
+}
+
+fn cbor_head(buf: &[u8], pos: usize) -> Option<Head> {
+    let b = *buf.get(pos)?;
+    let (major, info) = (b >> 5, b & 0x1f);
+    let (arg, header) = match info {
+        0..=23 => (info as u64, 1),
+        24..=27 => {
+            let n = 1 << (info - 24);
+            (read_be(buf, pos + 1, n)?, n + 1)
+        }
+        31 if (2..=5).contains(&major) => {
+            return Some(Head {
+                header: 1,
+                payload: 0,
+                children: None,
+            })
+        }
+        _ => return None,
+    };
+    let (payload, children) = match major {
+        0 | 1 | 7 => (0, 0),
+        2 | 3 => (arg, 0),
+        4 => (0, arg),
+        5 => (0, arg.checked_mul(2)?),
+        // A tag wraps exactly one item.
+        _ => (0, 1),
+    };
+    Some(Head {
+        header,
+        payload,
+        children: Some(children),
+    })
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    MsgPack,
+    Cbor,
+}
+
+impl Format {
+    fn head(self, buf: &[u8], pos: usize) -> Option<Head> {
+        match self {
+            Format::MsgPack => msgpack_head(buf, pos),
+            Format::Cbor => cbor_head(buf, pos),
+        }
+    }
+
+    /// `INDEX_ARRAY` or `INDEX_MAP` for a container's first byte.
+    fn container(self, b: u8) -> Option<u32> {
+        match (self, b) {
+            (Format::MsgPack, 0x80..=0x8f | 0xde | 0xdf) => Some(INDEX_MAP),
+            (Format::MsgPack, 0x90..=0x9f | 0xdc | 0xdd) => Some(INDEX_ARRAY),
+            (Format::Cbor, _) if b >> 5 == 4 => Some(INDEX_ARRAY),
+            (Format::Cbor, _) if b >> 5 == 5 => Some(INDEX_MAP),
+            _ => None,
+        }
+    }
+}
+
+/// End of the value starting at `pos`, or `None` if it is malformed or runs
+/// past the buffer.
+fn skip(format: Format, buf: &[u8], mut pos: usize) -> Option<usize> {
+    // Items still expected by each enclosing container, innermost last;
+    // `None` waits for a break byte (0xff, CBOR only).
+    let mut open: Vec<Option<u64>> = Vec::new();
+    let mut pending = Some(1);
+    loop {
+        match pending {
+            Some(0) => match open.pop() {
+                Some(outer) => {
+                    pending = outer;
+                    continue;
+                }
+                None => return Some(pos),
+            },
+            None if buf.get(pos) == Some(&0xff) => {
+                pos += 1;
+                pending = open.pop()?;
+                continue;
+            }
+            _ => {}
+        }
+        let h = format.head(buf, pos)?;
+        if let Some(n) = &mut pending {
+            *n -= 1;
+        }
+        pos = pos
+            .checked_add(h.header)?
+            .checked_add(usize::try_from(h.payload).ok()?)?;
+        if pos > buf.len() {
+            return None;
+        }
+        if h.children != Some(0) {
+            // Every item takes at least a byte, which bounds both the claimed
+            // counts and the nesting depth.
+            if h.children.is_some_and(|n| n > (buf.len() - pos) as u64) {
+                return None;
+            }
+            open.push(pending);
+            pending = h.children;
+        }
+    }
+}
+
+/// Index the top-level container of `buf` as `(kind, spans)`, `spans`
+/// holding a `[start, end)` pair per key and per value.
+fn index(format: Format, buf: &[u8]) -> Option<(u32, Vec<u32>)> {
+    if u32::try_from(buf.len()).is_err() {
+        return None;
+    }
+    let mut pos = 0;
+    if let Format::Cbor = format {
+        // Look through tags (e.g. the self-describe tag) to the container.
+        while buf.get(pos).is_some_and(|b| b >> 5 == 6) {
+            pos += cbor_head(buf, pos)?.header;
+        }
+    }
+    let kind = format.container(*buf.get(pos)?)?;
+    let h = format.head(buf, pos)?;
+    pos += h.header;
+    let mut spans = Vec::new();
+    let mut items = 0u64;
+    loop {
+        match h.children {
+            Some(n) if items == n => break,
+            None if buf.get(pos) == Some(&0xff) => {
+                if kind == INDEX_MAP && items % 2 == 1 {
+                    return None;
+                }
+                pos += 1;
+                break;
+            }
+            _ => {}
+        }
+        let end = skip(format, buf, pos)?;
+        spans.extend([pos as u32, end as u32]);
+        pos = end;
+        items += 1;
+    }
+    // Trailing bytes mean the buffer is not a single value.
+    (pos == buf.len()).then_some((kind, spans))
+}
+
+/// Shared export body, using the same size protocol as `tokenize`.
+unsafe fn write_index(
+    format: Format,
+    buf_ptr: *const u8,
+    buf_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let buf = ffi::slice(buf_ptr, buf_len);
+    let Some((kind, spans)) = index(format, buf) else {
+        return -1;
+    };
+    let bytes = (spans.len() + 2) * 4;
+    if out_len_bytes == 0 {
+        return bytes as isize;
+    }
+    if out_len_bytes < bytes {
+        return -1;
+    }
+    if ffi::aliased(buf_ptr, buf_len, out_ptr as *const u8, bytes) {
+        return ffi::ALIAS_ERROR;
+    }
+    let per_entry = if kind == INDEX_MAP { 4 } else { 2 };
+    let out = ffi::slice_mut(out_ptr, spans.len() + 2);
+    out[0] = kind;
+    out[1] = (spans.len() / per_entry) as u32;
+    out[2..].copy_from_slice(&spans);
+    bytes as isize
+}
+
+/// Index the top-level map or array of the MessagePack value at `buf_ptr`
+/// (see the module docs for the layout).
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes the index needs. Otherwise returns bytes written, or `-1`
+/// when the buffer is not exactly one well-formed map or array, or the
+/// output is short.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn msgpack_index(
+    buf_ptr: *const u8,
+    buf_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    write_index(Format::MsgPack, buf_ptr, buf_len, out_ptr, out_len_bytes)
+}
+
+/// Index the top-level map or array of the CBOR value at `buf_ptr`, looking
+/// through any tags around it. Indefinite-length items are supported.
+/// Returns as for `msgpack_index`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn cbor_index(
+    buf_ptr: *const u8,
+    buf_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    write_index(Format::Cbor, buf_ptr, buf_len, out_ptr, out_len_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(
+        f: unsafe extern "C" fn(*const u8, usize, *mut u32, usize) -> isize,
+        buf: &[u8],
+    ) -> Option<Vec<u32>> {
+        let needed = unsafe { f(buf.as_ptr(), buf.len(), std::ptr::null_mut(), 0) };
+        if needed < 0 {
+            return None;
+        }
+        let mut out = vec![u32::MAX; needed as usize / 4];
+        let written = unsafe { f(buf.as_ptr(), buf.len(), out.as_mut_ptr(), needed as usize) };
+        assert_eq!(written, needed);
+        Some(out)
+    }
+
+    #[test]
+    fn indexes_msgpack_maps_and_arrays() {
+        // {"a": [1, {"b": nil}], "cd": 3.5f32, 7: bin8 "xy"}
+        let map = [
+            0x83, 0xa1, b'a', 0x92, 0x01, 0x81, 0xa1, b'b', 0xc0, //
+            0xa2, b'c', b'd', 0xca, 0x40, 0x60, 0, 0, //
+            0x07, 0xc4, 2, b'x', b'y',
+        ];
+        assert_eq!(
+            run(msgpack_index, &map).unwrap(),
+            [INDEX_MAP, 3, 1, 3, 3, 9, 9, 12, 12, 17, 17, 18, 18, 22]
+        );
+        // array16 of [uint16, fixext1, str8 ""]
+        let array = [0xdc, 0, 3, 0xcd, 1, 0, 0xd4, 5, 9, 0xd9, 0];
+        assert_eq!(
+            run(msgpack_index, &array).unwrap(),
+            [INDEX_ARRAY, 3, 3, 6, 6, 9, 9, 11]
+        );
+        assert_eq!(run(msgpack_index, &[0x90]).unwrap(), [INDEX_ARRAY, 0]);
+    }
+
+    #[test]
+    fn indexes_cbor_including_indefinite_items() {
+        // 55799({"a": [_ 1, "xy"], "b": (_ h'01', h'0203')}) with an
+        // indefinite array and byte string.
+        let map = [
+            0xd9, 0xd9, 0xf7, 0xa2, 0x61, b'a', 0x9f, 0x01, 0x62, b'x', b'y', 0xff, //
+            0x61, b'b', 0x5f, 0x41, 1, 0x42, 2, 3, 0xff,
+        ];
+        assert_eq!(
+            run(cbor_index, &map).unwrap(),
+            [INDEX_MAP, 2, 4, 6, 6, 12, 12, 14, 14, 21]
+        );
+        // [_ 1000, -1, 1.5f16] with an indefinite top level.
+        let array = [0x9f, 0x19, 0x03, 0xe8, 0x20, 0xf9, 0x3e, 0x00, 0xff];
+        assert_eq!(
+            run(cbor_index, &array).unwrap(),
+            [INDEX_ARRAY, 3, 1, 4, 4, 5, 5, 8]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_payloads() {
+        let msgpack: [&[u8]; 6] = [
+            &[],
+            &[0x01],                         // not a container
+            &[0x92, 0x01],                   // missing element
+            &[0x91, 0xc1],                   // reserved byte
+            &[0x91, 0x01, 0x01],             // trailing byte
+            &[0xdd, 0xff, 0xff, 0xff, 0xff], // count past the buffer
+        ];
+        for buf in msgpack {
+            assert_eq!(run(msgpack_index, buf), None, "{buf:02x?}");
+        }
+        let cbor: [&[u8]; 4] = [
+            &[0xbf, 0x61, b'a', 0xff], // key without value
+            &[0x9f, 0x01],             // missing break
+            &[0x81, 0xff],             // break in a definite array
+            &[0x81, 0x1c],             // reserved additional info
+        ];
+        for buf in cbor {
+            assert_eq!(run(cbor_index, buf), None, "{buf:02x?}");
+        }
+        let mut out = [0u32; 3];
+        let status = unsafe { msgpack_index([0x91, 0x01].as_ptr(), 2, out.as_mut_ptr(), 12) };
+        assert_eq!(status, -1, "short output");
+    }
+}
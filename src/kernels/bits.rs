@@ -0,0 +1,481 @@
+//! Bit-level primitives: popcount, bit reversal, rank/select and set
+//! operations over bitmaps, and Gray codes.
+//!
+//! Bitmaps use the layout of the batch match kernels (`glob_match_batch`,
+//! `ed25519_verify_batch`, ...): bit `i` is bit `i % 8` of byte `i / 8`, so
+//! their outputs can be combined, counted and indexed here directly.
+//! Morton (Z-order) keys live with the other space-filling curves in
+//! `spatial`.
+
+use super::bytes::map_bytes;
+use crate::ffi;
+
+/// Number of set bits in a 16-byte block.
+#[inline(always)]
+fn popcount_block(block: [u8; 16]) -> u32 {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let counts = u8x16_popcnt(v128_load(block.as_ptr() as *const v128));
+        let sums = u32x4_extadd_pairwise_u16x8(u16x8_extadd_pairwise_u8x16(counts));
+        u32x4_extract_lane::<0>(sums)
+            + u32x4_extract_lane::<1>(sums)
+            + u32x4_extract_lane::<2>(sums)
+            + u32x4_extract_lane::<3>(sums)
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        u128::from_le_bytes(block).count_ones()
+    }
+}
+
+/// Set bits in `bytes`, 16 at a time with the final partial block
+/// zero-padded.
+fn popcount(bytes: &[u8]) -> usize {
+    bytes
+        .chunks(16)
+        .map(|chunk| {
+            let mut block = [0u8; 16];
+            block[..chunk.len()].copy_from_slice(chunk);
+            popcount_block(block) as usize
+        })
+        .sum()
+}
+
+/// Reverse the bit order of every byte in a block: the reversed low nibble
+/// becomes the high nibble and vice versa.
+#[inline(always)]
+fn reverse_block(block: [u8; 16]) -> [u8; 16] {
+    const REV4: [u8; 16] = [0, 8, 4, 12, 2, 10, 6, 14, 1, 9, 5, 13, 3, 11, 7, 15];
+
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let v = v128_load(block.as_ptr() as *const v128);
+        let rev = v128_load(REV4.as_ptr() as *const v128);
+        let lo = i8x16_swizzle(rev, v128_and(v, u8x16_splat(0x0f)));
+        let hi = i8x16_swizzle(rev, u8x16_shr(v, 4));
+        let mut out = [0u8; 16];
+        v128_store(out.as_mut_ptr() as *mut v128, v128_or(u8x16_shl(lo, 4), hi));
+        out
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        block.map(|b| (REV4[(b & 15) as usize] << 4) | REV4[(b >> 4) as usize])
+    }
+}
+
+fn reverse_bits(input: &[u8], out: &mut [u8]) {
+    for (src, dst) in input.chunks(16).zip(out.chunks_mut(16)) {
+        let mut block = [0u8; 16];
+        block[..src.len()].copy_from_slice(src);
+        dst.copy_from_slice(&reverse_block(block)[..src.len()]);
+    }
+}
+
+fn reverse_bits_in_place(buf: &mut [u8]) {
+    for chunk in buf.chunks_mut(16) {
+        let mut block = [0u8; 16];
+        block[..chunk.len()].copy_from_slice(chunk);
+        let len = chunk.len();
+        chunk.copy_from_slice(&reverse_block(block)[..len]);
+    }
+}
+
+#[derive(Clone, Copy)]
+enum SetOp {
+    And,
+    Or,
+    Xor,
+    AndNot,
+}
+
+#[inline(always)]
+fn combine_block(a: [u8; 16], b: [u8; 16], op: SetOp) -> [u8; 16] {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let x = v128_load(a.as_ptr() as *const v128);
+        let y = v128_load(b.as_ptr() as *const v128);
+        let r = match op {
+            SetOp::And => v128_and(x, y),
+            SetOp::Or => v128_or(x, y),
+            SetOp::Xor => v128_xor(x, y),
+            SetOp::AndNot => v128_andnot(x, y),
+        };
+        let mut out = [0u8; 16];
+        v128_store(out.as_mut_ptr() as *mut v128, r);
+        out
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        let (x, y) = (u128::from_le_bytes(a), u128::from_le_bytes(b));
+        let r = match op {
+            SetOp::And => x & y,
+            SetOp::Or => x | y,
+            SetOp::Xor => x ^ y,
+            SetOp::AndNot => x & !y,
+        };
+        r.to_le_bytes()
+    }
+}
+
+/// Shared body of the bitmap set operations. The output may coincide with
+/// either input: each block is copied out of both inputs before the result
+/// is stored, so no slice ever views overlapping memory.
+unsafe fn combine(
+    a_ptr: *const u8,
+    a_len: usize,
+    b_ptr: *const u8,
+    b_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+    op: SetOp,
+) -> isize {
+    if a_len != b_len || out_len < a_len {
+        return -1;
+    }
+    for src in [a_ptr, b_ptr] {
+        if !std::ptr::eq(src, out_ptr) && ffi::aliased(src, a_len, out_ptr, a_len) {
+            return ffi::ALIAS_ERROR;
+        }
+    }
+    ffi::check_range(a_ptr, a_len);
+    ffi::check_range(b_ptr, b_len);
+    ffi::check_range(out_ptr, a_len);
+    for start in (0..a_len).step_by(16) {
+        let n = (a_len - start).min(16);
+        let (mut x, mut y) = ([0u8; 16], [0u8; 16]);
+        std::ptr::copy(a_ptr.add(start), x.as_mut_ptr(), n);
+        std::ptr::copy(b_ptr.add(start), y.as_mut_ptr(), n);
+        std::ptr::copy(combine_block(x, y, op).as_ptr(), out_ptr.add(start), n);
+    }
+    a_len as isize
+}
+
+/// Number of set bits in the `len` bytes at `ptr`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bitmap_popcount(ptr: *const u8, len: usize) -> isize {
+    popcount(ffi::slice(ptr, len)) as isize
+}
+
+/// Rank: the number of set bits before bit `pos`. `pos` may equal the bit
+/// length, giving the total. Returns the count, or `-1` when `pos` is past
+/// the end of the bitmap.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bitmap_rank(ptr: *const u8, len: usize, pos: u32) -> isize {
+    let pos = pos as usize;
+    if pos > len.saturating_mul(8) {
+        return -1;
+    }
+    let bitmap = ffi::slice(ptr, len);
+    let (whole, rest) = (pos / 8, pos % 8);
+    let partial = match bitmap.get(whole) {
+        Some(&byte) if rest > 0 => (byte & ((1u8 << rest) - 1)).count_ones() as usize,
+        _ => 0,
+    };
+    (popcount(&bitmap[..whole]) + partial) as isize
+}
+
+/// Select: the position of the set bit with rank `k`, i.e. the `k + 1`-th
+/// set bit counting from zero. Returns the bit position, or `-1` when the
+/// bitmap has `k` or fewer set bits.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bitmap_select(ptr: *const u8, len: usize, k: u32) -> isize {
+    let mut remaining = k as usize;
+    let bitmap = ffi::slice(ptr, len);
+    for (c, chunk) in bitmap.chunks(16).enumerate() {
+        let mut block = [0u8; 16];
+        block[..chunk.len()].copy_from_slice(chunk);
+        let ones = popcount_block(block) as usize;
+        if remaining >= ones {
+            remaining -= ones;
+            continue;
+        }
+        for (i, &byte) in chunk.iter().enumerate() {
+            let ones = byte.count_ones() as usize;
+            if remaining >= ones {
+                remaining -= ones;
+                continue;
+            }
+            let mut byte = byte;
+            for _ in 0..remaining {
+                byte &= byte - 1;
+            }
+            return ((c * 16 + i) * 8 + byte.trailing_zeros() as usize) as isize;
+        }
+    }
+    -1
+}
+
+/// Reverse the bit order within each byte, e.g. to convert an LSB-first
+/// bitmap to MSB-first. Runs in place like the `bytes` kernels. Returns
+/// `in_len`, or `-1` for a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bit_reverse_bytes(
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    map_bytes(
+        in_ptr,
+        in_len,
+        out_ptr,
+        out_len,
+        reverse_bits,
+        reverse_bits_in_place,
+    )
+}
+
+/// In-place form of `bit_reverse_bytes`. Returns `len`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bit_reverse_bytes_in_place(ptr: *mut u8, len: usize) -> isize {
+    bit_reverse_bytes(ptr, len, ptr, len)
+}
+
+/// `out = a & b` over two bitmaps of equal length. `out_ptr` may equal
+/// either input. Returns bytes written, or `-1` for mismatched lengths or a
+/// short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bitmap_and(
+    a_ptr: *const u8,
+    a_len: usize,
+    b_ptr: *const u8,
+    b_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    combine(a_ptr, a_len, b_ptr, b_len, out_ptr, out_len, SetOp::And)
+}
+
+/// `out = a | b`; see `bitmap_and`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bitmap_or(
+    a_ptr: *const u8,
+    a_len: usize,
+    b_ptr: *const u8,
+    b_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    combine(a_ptr, a_len, b_ptr, b_len, out_ptr, out_len, SetOp::Or)
+}
+
+/// `out = a ^ b`; see `bitmap_and`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bitmap_xor(
+    a_ptr: *const u8,
+    a_len: usize,
+    b_ptr: *const u8,
+    b_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    combine(a_ptr, a_len, b_ptr, b_len, out_ptr, out_len, SetOp::Xor)
+}
+
+/// `out = a & !b`, the rows selected by `a` but not `b`; see `bitmap_and`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bitmap_andnot(
+    a_ptr: *const u8,
+    a_len: usize,
+    b_ptr: *const u8,
+    b_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    combine(a_ptr, a_len, b_ptr, b_len, out_ptr, out_len, SetOp::AndNot)
+}
+
+/// Shared body of the Gray code exports: moves the input into the output
+/// range, then maps every value in place.
+unsafe fn map_u32(
+    in_ptr: *const u32,
+    in_len_bytes: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+    map: impl Fn(u32) -> u32,
+) -> isize {
+    if !in_len_bytes.is_multiple_of(4) || out_len_bytes < in_len_bytes {
+        return -1;
+    }
+    let n = in_len_bytes / 4;
+    if !std::ptr::eq(in_ptr, out_ptr) {
+        if ffi::aliased(
+            in_ptr as *const u8,
+            in_len_bytes,
+            out_ptr as *const u8,
+            in_len_bytes,
+        ) {
+            return ffi::ALIAS_ERROR;
+        }
+        // `copy` handles a partially overlapping input like the byte kernels.
+        std::ptr::copy(in_ptr, out_ptr, n);
+    }
+    for v in ffi::slice_mut(out_ptr, n) {
+        *v = map(*v);
+    }
+    in_len_bytes as isize
+}
+
+/// Reflected binary Gray code of each `u32`: `x ^ (x >> 1)`, so consecutive
+/// values differ in exactly one bit. Runs in place when `in_ptr == out_ptr`.
+/// Returns bytes written, or `-1` for a partial value or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn gray_encode(
+    in_ptr: *const u32,
+    in_len_bytes: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    map_u32(in_ptr, in_len_bytes, out_ptr, out_len_bytes, |x| {
+        x ^ (x >> 1)
+    })
+}
+
+/// Inverse of `gray_encode`: a prefix XOR over each value's bits. Runs in
+/// place when `in_ptr == out_ptr`. Returns bytes written, or `-1` for a
+/// partial value or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn gray_decode(
+    in_ptr: *const u32,
+    in_len_bytes: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    map_u32(in_ptr, in_len_bytes, out_ptr, out_len_bytes, |x| {
+        [1, 2, 4, 8, 16]
+            .iter()
+            .fold(x, |x, &shift| x ^ (x >> shift))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bitmap(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i as u8).wrapping_mul(73) ^ 0x5a)
+            .collect()
+    }
+
+    fn is_set(bitmap: &[u8], i: usize) -> bool {
+        bitmap[i / 8] >> (i % 8) & 1 == 1
+    }
+
+    #[test]
+    fn popcount_rank_and_select_agree() {
+        let bits = bitmap(37);
+        let ones: Vec<usize> = (0..bits.len() * 8).filter(|&i| is_set(&bits, i)).collect();
+        unsafe {
+            assert_eq!(
+                bitmap_popcount(bits.as_ptr(), bits.len()),
+                ones.len() as isize
+            );
+            for pos in 0..=bits.len() * 8 {
+                let expected = ones.iter().filter(|&&i| i < pos).count();
+                assert_eq!(
+                    bitmap_rank(bits.as_ptr(), bits.len(), pos as u32),
+                    expected as isize
+                );
+            }
+            assert_eq!(bitmap_rank(bits.as_ptr(), bits.len(), 37 * 8 + 1), -1);
+            for (k, &pos) in ones.iter().enumerate() {
+                assert_eq!(
+                    bitmap_select(bits.as_ptr(), bits.len(), k as u32),
+                    pos as isize
+                );
+            }
+            assert_eq!(
+                bitmap_select(bits.as_ptr(), bits.len(), ones.len() as u32),
+                -1
+            );
+            assert_eq!(bitmap_select(bits.as_ptr(), 0, 0), -1);
+        }
+    }
+
+    #[test]
+    fn reverses_bits_per_byte() {
+        let input: Vec<u8> = (0..=255).collect();
+        let mut out = vec![0u8; 256];
+        unsafe {
+            assert_eq!(
+                bit_reverse_bytes(input.as_ptr(), 256, out.as_mut_ptr(), 256),
+                256
+            );
+        }
+        assert!(input.iter().zip(&out).all(|(a, b)| a.reverse_bits() == *b));
+        unsafe {
+            bit_reverse_bytes_in_place(out.as_mut_ptr(), 256);
+        }
+        assert_eq!(out, input);
+    }
+
+    #[test]
+    fn set_operations_run_in_place() {
+        let a = bitmap(21);
+        let b: Vec<u8> = a.iter().map(|x| x.rotate_left(3)).collect();
+        type Op = unsafe extern "C" fn(*const u8, usize, *const u8, usize, *mut u8, usize) -> isize;
+        type Reference = fn(u8, u8) -> u8;
+        let ops: [(Op, Reference); 4] = [
+            (bitmap_and, |x, y| x & y),
+            (bitmap_or, |x, y| x | y),
+            (bitmap_xor, |x, y| x ^ y),
+            (bitmap_andnot, |x, y| x & !y),
+        ];
+        for (op, reference) in ops {
+            let expected: Vec<u8> = a.iter().zip(&b).map(|(&x, &y)| reference(x, y)).collect();
+            let mut out = vec![0u8; 21];
+            let (mut in_a, mut in_b) = (a.clone(), b.clone());
+            unsafe {
+                assert_eq!(op(a.as_ptr(), 21, b.as_ptr(), 21, out.as_mut_ptr(), 21), 21);
+                op(in_a.as_ptr(), 21, b.as_ptr(), 21, in_a.as_mut_ptr(), 21);
+                op(a.as_ptr(), 21, in_b.as_ptr(), 21, in_b.as_mut_ptr(), 21);
+                assert_eq!(op(a.as_ptr(), 21, b.as_ptr(), 20, out.as_mut_ptr(), 21), -1);
+            }
+            assert_eq!(out, expected);
+            assert_eq!(in_a, expected);
+            assert_eq!(in_b, expected);
+        }
+    }
+
+    #[test]
+    fn gray_codes_round_trip() {
+        let values: Vec<u32> = (0..64).chain([u32::MAX, 0x8000_0000]).collect();
+        let mut codes = vec![0u32; values.len()];
+        let bytes = values.len() * 4;
+        unsafe {
+            assert_eq!(
+                gray_encode(values.as_ptr(), bytes, codes.as_mut_ptr(), bytes),
+                bytes as isize
+            );
+        }
+        assert!(codes[..64]
+            .windows(2)
+            .all(|w| (w[0] ^ w[1]).count_ones() == 1));
+        unsafe {
+            gray_decode(codes.as_ptr(), bytes, codes.as_mut_ptr(), bytes);
+            assert_eq!(
+                gray_encode(values.as_ptr(), 3, codes.as_mut_ptr(), bytes),
+                -1
+            );
+        }
+        assert_eq!(codes, values);
+    }
+}
@@ -0,0 +1,250 @@
+//! Byte-wise transforms: table translation and ASCII case conversion.
+//!
+//! These are pure per-byte maps, so every kernel here runs in place: pass the
+//! same pointer for input and output, or call the `_in_place` export. A
+//! partial overlap is handled by moving the input into the output range
+//! first, except in `strict-aliasing` builds, which reject it with
+//! `ALIAS_ERROR` like every other kernel.
+
+use crate::ffi;
+
+/// Shared driver for the out-of-place exports: validates the output length
+/// and dispatches to the in-place body when the ranges coincide or overlap.
+pub(crate) unsafe fn map_bytes(
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+    map: impl Fn(&[u8], &mut [u8]),
+    map_in_place: impl Fn(&mut [u8]),
+) -> isize {
+    if out_len < in_len {
+        return -1;
+    }
+    if std::ptr::eq(in_ptr, out_ptr) {
+        map_in_place(ffi::slice_mut(out_ptr, in_len));
+    } else if ffi::overlaps(in_ptr, in_len, out_ptr, in_len) {
+        if ffi::aliased(in_ptr, in_len, out_ptr, in_len) {
+            return ffi::ALIAS_ERROR;
+        }
+        // A per-byte map gives the same result after a memmove.
+        std::ptr::copy(in_ptr, out_ptr, in_len);
+        map_in_place(ffi::slice_mut(out_ptr, in_len));
+    } else {
+        map(ffi::slice(in_ptr, in_len), ffi::slice_mut(out_ptr, in_len));
+    }
+    in_len as isize
+}
+
+fn translate(input: &[u8], out: &mut [u8], table: &[u8; 256]) {
+    for (dst, &src) in out.iter_mut().zip(input) {
+        *dst = table[src as usize];
+    }
+}
+
+fn translate_in_place(buf: &mut [u8], table: &[u8; 256]) {
+    for b in buf {
+        *b = table[*b as usize];
+    }
+}
+
+/// Flip bit 0x20 of every byte in `lo..=hi`, i.e. swap ASCII case for one
+/// alphabet range.
+#[inline(always)]
+fn flip_block(block: [u8; 16], lo: u8, hi: u8) -> [u8; 16] {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let v = v128_load(block.as_ptr() as *const v128);
+        let in_range = v128_and(u8x16_ge(v, u8x16_splat(lo)), u8x16_le(v, u8x16_splat(hi)));
+        let flipped = v128_xor(v, v128_and(in_range, u8x16_splat(0x20)));
+        let mut out = [0u8; 16];
+        v128_store(out.as_mut_ptr() as *mut v128, flipped);
+        out
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        block.map(|b| if (lo..=hi).contains(&b) { b ^ 0x20 } else { b })
+    }
+}
+
+/// Run `flip_block` over `input` 16 bytes at a time; the final partial block
+/// is zero-padded rather than handled by a scalar loop.
+fn flip_case(input: &[u8], out: &mut [u8], lo: u8, hi: u8) {
+    for (src, dst) in input.chunks(16).zip(out.chunks_mut(16)) {
+        let mut block = [0u8; 16];
+        block[..src.len()].copy_from_slice(src);
+        dst.copy_from_slice(&flip_block(block, lo, hi)[..src.len()]);
+    }
+}
+
+fn flip_case_in_place(buf: &mut [u8], lo: u8, hi: u8) {
+    for chunk in buf.chunks_mut(16) {
+        let mut block = [0u8; 16];
+        block[..chunk.len()].copy_from_slice(chunk);
+        chunk.copy_from_slice(&flip_block(block, lo, hi)[..chunk.len()]);
+    }
+}
+
+/// Map every input byte through a 256-byte lookup table at `table_ptr`.
+/// Runs in place when `in_ptr == out_ptr`. Returns bytes written.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn translate_bytes(
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+    table_ptr: *const u8,
+) -> isize {
+    let table = &*(ffi::slice(table_ptr, 256).as_ptr() as *const [u8; 256]);
+    map_bytes(
+        in_ptr,
+        in_len,
+        out_ptr,
+        out_len,
+        |input, out| translate(input, out, table),
+        |buf| translate_in_place(buf, table),
+    )
+}
+
+/// In-place form of `translate_bytes`. Returns `len`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn translate_bytes_in_place(
+    ptr: *mut u8,
+    len: usize,
+    table_ptr: *const u8,
+) -> isize {
+    translate_bytes(ptr, len, ptr, len, table_ptr)
+}
+
+/// ASCII uppercase; non-ASCII bytes pass through. Runs in place when
+/// `in_ptr == out_ptr`. Returns bytes written.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn ascii_upper(
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    map_bytes(
+        in_ptr,
+        in_len,
+        out_ptr,
+        out_len,
+        |input, out| flip_case(input, out, b'a', b'z'),
+        |buf| flip_case_in_place(buf, b'a', b'z'),
+    )
+}
+
+/// ASCII lowercase; non-ASCII bytes pass through. Runs in place when
+/// `in_ptr == out_ptr`. Returns bytes written.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn ascii_lower(
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    map_bytes(
+        in_ptr,
+        in_len,
+        out_ptr,
+        out_len,
+        |input, out| flip_case(input, out, b'A', b'Z'),
+        |buf| flip_case_in_place(buf, b'A', b'Z'),
+    )
+}
+
+/// In-place form of `ascii_upper`. Returns `len`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn ascii_upper_in_place(ptr: *mut u8, len: usize) -> isize {
+    ascii_upper(ptr, len, ptr, len)
+}
+
+/// In-place form of `ascii_lower`. Returns `len`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn ascii_lower_in_place(ptr: *mut u8, len: usize) -> isize {
+    ascii_lower(ptr, len, ptr, len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &[u8] = b"Hello, World! 0123 \xc3\xa9 [mixed_Case] zZaA{}@`";
+
+    #[test]
+    fn case_conversion_out_of_place() {
+        let mut out = vec![0u8; TEXT.len()];
+        unsafe {
+            assert_eq!(
+                ascii_upper(TEXT.as_ptr(), TEXT.len(), out.as_mut_ptr(), out.len()),
+                TEXT.len() as isize
+            );
+            assert_eq!(out, TEXT.to_ascii_uppercase());
+            ascii_lower(TEXT.as_ptr(), TEXT.len(), out.as_mut_ptr(), out.len());
+            assert_eq!(out, TEXT.to_ascii_lowercase());
+        }
+    }
+
+    #[test]
+    fn case_conversion_in_place() {
+        let mut buf = TEXT.to_vec();
+        unsafe {
+            ascii_upper_in_place(buf.as_mut_ptr(), buf.len());
+            assert_eq!(buf, TEXT.to_ascii_uppercase());
+            ascii_lower(buf.as_ptr(), buf.len(), buf.as_mut_ptr(), buf.len());
+            assert_eq!(buf, TEXT.to_ascii_lowercase());
+        }
+    }
+
+    #[test]
+    fn translate_matches_table() {
+        let table: Vec<u8> = (0..=255u8).map(|b| b.wrapping_mul(3)).collect();
+        let expected: Vec<u8> = TEXT.iter().map(|b| b.wrapping_mul(3)).collect();
+        let mut out = vec![0u8; TEXT.len()];
+        let mut buf = TEXT.to_vec();
+        unsafe {
+            translate_bytes(
+                TEXT.as_ptr(),
+                TEXT.len(),
+                out.as_mut_ptr(),
+                out.len(),
+                table.as_ptr(),
+            );
+            translate_bytes_in_place(buf.as_mut_ptr(), buf.len(), table.as_ptr());
+        }
+        assert_eq!(out, expected);
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn partial_overlap_follows_policy() {
+        let mut buf = vec![0u8; TEXT.len() + 5];
+        buf[..TEXT.len()].copy_from_slice(TEXT);
+        let base = buf.as_mut_ptr();
+        let status = unsafe { ascii_upper(base, TEXT.len(), base.add(5), TEXT.len()) };
+        if cfg!(feature = "strict-aliasing") {
+            assert_eq!(status, ffi::ALIAS_ERROR);
+        } else {
+            assert_eq!(status, TEXT.len() as isize);
+            assert_eq!(&buf[5..], TEXT.to_ascii_uppercase());
+        }
+    }
+
+    #[test]
+    fn short_output_is_rejected() {
+        let mut out = [0u8; 2];
+        assert_eq!(
+            unsafe { ascii_upper(TEXT.as_ptr(), 3, out.as_mut_ptr(), 2) },
+            -1
+        );
+    }
+}
@@ -0,0 +1,452 @@
+//! Record indexes for network captures: libpcap files and HAR archives.
+//!
+//! Both index kernels report where each record lies plus its timing, so a
+//! capture viewer can page through a large file and decode only the records
+//! on screen.
+//!
+//! - `pcap_header` reads a classic libpcap file header (not pcapng) and
+//!   `pcap_records` lists the packet records that follow. Records can be
+//!   indexed a slice of the file at a time, so a capture larger than wasm
+//!   memory can be read in pieces.
+//! - `har_entries` finds the `log.entries` array of a HAR (HTTP Archive)
+//!   JSON file and lists each entry's span, start time and duration. The
+//!   JSON is skipped over rather than parsed, without recursion.
+
+use super::search::candidates;
+use super::time::{parse_date, DATE_ISO8601};
+use crate::ffi;
+#[cfg(feature = "multi-value")]
+use crate::multi_value::{multi_value_export, Status};
+
+/// `pcap_header` flag: the file's integers are big-endian.
+pub const PCAP_BIG_ENDIAN: u32 = 1;
+/// `pcap_header` flag: timestamp fractions are nanoseconds, not microseconds.
+pub const PCAP_NANOSECONDS: u32 = 2;
+
+/// `u32` words per record in `pcap_records` output.
+const RECORD_WORDS: usize = 5;
+/// `f64`s per entry in `har_entries` output.
+const ENTRY_WORDS: usize = 4;
+
+fn u32_at(buf: &[u8], pos: usize, big_endian: bool) -> Option<u32> {
+    let bytes = buf.get(pos..pos + 4)?.try_into().unwrap();
+    Some(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+/// Read the 24-byte libpcap file header at `in_ptr` and write `[link_type,
+/// snaplen, flags]`. `flags` combines `PCAP_BIG_ENDIAN` and
+/// `PCAP_NANOSECONDS` and is passed on to `pcap_records`; `link_type` names
+/// the packet format (`1` for Ethernet).
+///
+/// Returns bytes written (`12`), or `-1` for a buffer that does not start
+/// with a libpcap header (pcapng files included) or a short output.
+#[cfg_attr(not(feature = "multi-value"), no_mangle)]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn pcap_header(
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let buf = ffi::slice(in_ptr, in_len);
+    if in_len < 24 || out_len_bytes < 12 {
+        return -1;
+    }
+    let flags = match u32_at(buf, 0, false).unwrap() {
+        0xa1b2_c3d4 => 0,
+        0xa1b2_3c4d => PCAP_NANOSECONDS,
+        0xd4c3_b2a1 => PCAP_BIG_ENDIAN,
+        0x4d3c_b2a1 => PCAP_BIG_ENDIAN | PCAP_NANOSECONDS,
+        _ => return -1,
+    };
+    let big_endian = flags & PCAP_BIG_ENDIAN != 0;
+    let snaplen = u32_at(buf, 16, big_endian).unwrap();
+    // The link type shares its word with FCS flags in the top bits.
+    let link_type = u32_at(buf, 20, big_endian).unwrap() & 0x0fff_ffff;
+    if ffi::aliased(in_ptr, in_len, out_ptr as *const u8, 12) {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(out_ptr, 3).copy_from_slice(&[link_type, snaplen, flags]);
+    12
+}
+
+/// `pcap_header` in `multi-value` builds: takes only the input and returns
+/// `(status, link_type, snaplen, flags)`.
+#[cfg(feature = "multi-value")]
+#[export_name = "pcap_header"]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn pcap_header_multi_value(
+    in_ptr: *const u8,
+    in_len: usize,
+) -> Status<[u32; 3]> {
+    multi_value_export!(pcap_header, "i32 i32 i32 i32");
+    let mut fields = [0u32; 3];
+    let status = pcap_header(in_ptr, in_len, fields.as_mut_ptr(), 12);
+    Status::new(status, fields)
+}
+
+/// List the packet records in `in_ptr`, which must start at a record: byte
+/// 24 of the file, or where the previous call's last record ended. Writes
+/// five `u32`s per record: `[data_start, captured_len, original_len,
+/// seconds, nanoseconds]`, with `data_start` relative to `in_ptr` and the
+/// timestamp in UTC. `flags` is the value `pcap_header` reported.
+///
+/// Stops at the first record that does not fit in the buffer or when the
+/// output is full; the next record then starts at the last one's
+/// `data_start + captured_len`. Returns bytes written.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn pcap_records(
+    in_ptr: *const u8,
+    in_len: usize,
+    flags: u32,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let buf = ffi::slice(in_ptr, in_len);
+    let big_endian = flags & PCAP_BIG_ENDIAN != 0;
+    let fraction_scale = if flags & PCAP_NANOSECONDS != 0 {
+        1
+    } else {
+        1000
+    };
+    let mut records = Vec::new();
+    let mut pos = 0;
+    while records.len() + RECORD_WORDS <= out_len_bytes / 4 {
+        let (Some(seconds), Some(fraction), Some(captured), Some(original)) = (
+            u32_at(buf, pos, big_endian),
+            u32_at(buf, pos + 4, big_endian),
+            u32_at(buf, pos + 8, big_endian),
+            u32_at(buf, pos + 12, big_endian),
+        ) else {
+            break;
+        };
+        let data_start = pos + 16;
+        if buf.len() - data_start < captured as usize {
+            break;
+        }
+        let record: [u32; RECORD_WORDS] = [
+            data_start as u32,
+            captured,
+            original,
+            seconds,
+            fraction.wrapping_mul(fraction_scale),
+        ];
+        records.extend(record);
+        pos = data_start + captured as usize;
+    }
+    let bytes = records.len() * 4;
+    if ffi::aliased(in_ptr, in_len, out_ptr as *const u8, bytes) {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(out_ptr, records.len()).copy_from_slice(&records);
+    bytes as isize
+}
+
+fn skip_ws(json: &[u8], mut pos: usize) -> usize {
+    while json.get(pos).is_some_and(|b| b.is_ascii_whitespace()) {
+        pos += 1;
+    }
+    pos
+}
+
+/// End of the string whose opening quote is at `pos`, after its closing
+/// quote. Quotes and backslashes are found 16 bytes at a time.
+fn string_end(json: &[u8], pos: usize) -> Option<usize> {
+    let mut i = pos + 1;
+    while i < json.len() {
+        let chunk = &json[i..(i + 16).min(json.len())];
+        let mut block = [0u8; 16];
+        block[..chunk.len()].copy_from_slice(chunk);
+        let mask = candidates(block, b'"', b'\\') & (u16::MAX >> (16 - chunk.len()));
+        if mask == 0 {
+            i += chunk.len();
+            continue;
+        }
+        let hit = i + mask.trailing_zeros() as usize;
+        if json[hit] == b'"' {
+            return Some(hit + 1);
+        }
+        // Skip the escaped character.
+        i = hit + 2;
+    }
+    None
+}
+
+/// End of the JSON value starting at `pos` (after leading whitespace).
+/// Containers are skipped by counting brackets, without recursion.
+fn value_end(json: &[u8], pos: usize) -> Option<usize> {
+    let mut i = skip_ws(json, pos);
+    let mut depth = 0usize;
+    loop {
+        match *json.get(i)? {
+            b'"' => i = string_end(json, i)?,
+            b'{' | b'[' => {
+                depth += 1;
+                i += 1;
+            }
+            b'}' | b']' => {
+                depth = depth.checked_sub(1)?;
+                i += 1;
+            }
+            _ if depth == 0 => {
+                // A scalar: runs to the next delimiter.
+                let len = json[i..]
+                    .iter()
+                    .position(|b| matches!(b, b',' | b'}' | b']') || b.is_ascii_whitespace())
+                    .unwrap_or(json.len() - i);
+                return (len > 0).then_some(i + len);
+            }
+            _ => i += 1,
+        }
+        if depth == 0 {
+            return Some(i);
+        }
+    }
+}
+
+/// Call `member(key, value_start, value_end)` for each member of the object
+/// at `pos`, with `key` still escaped. Returns the end of the object.
+fn members(json: &[u8], pos: usize, mut member: impl FnMut(&[u8], usize, usize)) -> Option<usize> {
+    let mut i = skip_ws(json, pos);
+    if json.get(i) != Some(&b'{') {
+        return None;
+    }
+    i = skip_ws(json, i + 1);
+    if json.get(i) == Some(&b'}') {
+        return Some(i + 1);
+    }
+    loop {
+        if json.get(i) != Some(&b'"') {
+            return None;
+        }
+        let key_end = string_end(json, i)?;
+        let key = &json[i + 1..key_end - 1];
+        i = skip_ws(json, key_end);
+        if json.get(i) != Some(&b':') {
+            return None;
+        }
+        let start = skip_ws(json, i + 1);
+        let end = value_end(json, start)?;
+        member(key, start, end);
+        i = skip_ws(json, end);
+        match json.get(i)? {
+            b',' => i = skip_ws(json, i + 1),
+            b'}' => return Some(i + 1),
+            _ => return None,
+        }
+    }
+}
+
+/// Span of member `key` of the object at `pos`.
+fn member(json: &[u8], pos: usize, key: &[u8]) -> Option<(usize, usize)> {
+    let mut found = None;
+    members(json, pos, |k, start, end| {
+        if k == key && found.is_none() {
+            found = Some((start, end));
+        }
+    })?;
+    found
+}
+
+/// Collect `[start, end, started_ms, time_ms]` per entry of `log.entries`.
+fn har_index(json: &[u8], out: &mut Vec<f64>) -> Option<()> {
+    let (log, _) = member(json, 0, b"log")?;
+    let (entries, _) = member(json, log, b"entries")?;
+    if json.get(entries) != Some(&b'[') {
+        return None;
+    }
+    let mut i = skip_ws(json, entries + 1);
+    if json.get(i) == Some(&b']') {
+        return Some(());
+    }
+    loop {
+        let (mut started, mut time) = (f64::NAN, f64::NAN);
+        let end = members(json, i, |key, start, end| match key {
+            b"startedDateTime" if end - start >= 2 => {
+                if let Some(ms) = parse_date(&json[start + 1..end - 1], DATE_ISO8601) {
+                    started = ms as f64;
+                }
+            }
+            b"time" => {
+                time = std::str::from_utf8(&json[start..end])
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(f64::NAN);
+            }
+            _ => {}
+        })?;
+        out.extend([i as f64, end as f64, started, time]);
+        i = skip_ws(json, end);
+        match json.get(i)? {
+            b',' => i = skip_ws(json, i + 1),
+            b']' => return Some(()),
+            _ => return None,
+        }
+    }
+}
+
+/// Index the entries of the HAR file at `json_ptr`: four `f64`s per entry
+/// of `log.entries`, in order: `[start, end, started_ms, time_ms]`. The
+/// entry object is `json[start..end]`, ready for `JSON.parse` on its own;
+/// `started_ms` is its `startedDateTime` in epoch milliseconds and
+/// `time_ms` its total `time`, each `NaN` when missing or malformed.
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` when the
+/// file has no `log.entries` array, the JSON around the entries is
+/// malformed, or the output is short.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn har_entries(
+    json_ptr: *const u8,
+    json_len: usize,
+    out_ptr: *mut f64,
+    out_len_bytes: usize,
+) -> isize {
+    let json = ffi::slice(json_ptr, json_len);
+    let mut entries = Vec::new();
+    if har_index(json, &mut entries).is_none() {
+        return -1;
+    }
+    debug_assert_eq!(entries.len() % ENTRY_WORDS, 0);
+    let needed = entries.len() * 8;
+    if out_len_bytes == 0 {
+        return needed as isize;
+    }
+    if out_len_bytes < needed {
+        return -1;
+    }
+    if ffi::aliased(json_ptr, json_len, out_ptr as *const u8, needed) {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(out_ptr, entries.len()).copy_from_slice(&entries);
+    needed as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A little-endian microsecond capture holding `packets`.
+    fn capture(packets: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        let mut file = Vec::new();
+        for word in [0xa1b2_c3d4u32, 0x0004_0002, 0, 0, 65535, 1] {
+            file.extend(word.to_le_bytes());
+        }
+        for &(seconds, micros, data) in packets {
+            for word in [seconds, micros, data.len() as u32, data.len() as u32 + 10] {
+                file.extend(word.to_le_bytes());
+            }
+            file.extend(data);
+        }
+        file
+    }
+
+    fn records(buf: &[u8], flags: u32, max: usize) -> Vec<u32> {
+        let mut out = vec![0u32; max * RECORD_WORDS];
+        let written = unsafe {
+            pcap_records(
+                buf.as_ptr(),
+                buf.len(),
+                flags,
+                out.as_mut_ptr(),
+                out.len() * 4,
+            )
+        };
+        out.truncate(written as usize / 4);
+        out
+    }
+
+    #[test]
+    fn indexes_pcap_records() {
+        let file = capture(&[(100, 5, b"abc"), (101, 999_999, b""), (102, 0, b"xy")]);
+        let mut header = [0u32; 3];
+        let status = unsafe { pcap_header(file.as_ptr(), file.len(), header.as_mut_ptr(), 12) };
+        assert_eq!((status, header), (12, [1, 65535, 0]));
+
+        let body = &file[24..];
+        assert_eq!(
+            records(body, 0, 8),
+            [
+                16,
+                3,
+                13,
+                100,
+                5000,
+                35,
+                0,
+                10,
+                101,
+                999_999_000,
+                51,
+                2,
+                12,
+                102,
+                0
+            ]
+        );
+        // Paging: one record per call, then resume after it.
+        assert_eq!(records(body, 0, 1), [16, 3, 13, 100, 5000]);
+        assert_eq!(records(&body[19..], 0, 1), [16, 0, 10, 101, 999_999_000]);
+        // A record cut off by the end of the slice is left for the next one.
+        assert_eq!(records(&body[..52], 0, 8).len(), 2 * RECORD_WORDS);
+    }
+
+    #[test]
+    fn reads_big_endian_nanosecond_headers() {
+        let mut file = Vec::new();
+        for word in [0xa1b2_3c4du32, 0x0002_0004, 0, 0, 262_144, 0x1000_0071] {
+            file.extend(word.to_be_bytes());
+        }
+        let mut header = [0u32; 3];
+        let status = unsafe { pcap_header(file.as_ptr(), 24, header.as_mut_ptr(), 12) };
+        assert_eq!(status, 12);
+        assert_eq!(header, [113, 262_144, PCAP_BIG_ENDIAN | PCAP_NANOSECONDS]);
+        let packet = [7u32, 42, 0, 0].map(u32::to_be_bytes).concat();
+        assert_eq!(records(&packet, header[2], 1), [16, 0, 0, 7, 42]);
+
+        let pcapng = [0x0a, 0x0d, 0x0d, 0x0a].repeat(6);
+        let status = unsafe { pcap_header(pcapng.as_ptr(), 24, header.as_mut_ptr(), 12) };
+        assert_eq!(status, -1);
+    }
+
+    fn har(json: &str) -> Option<Vec<f64>> {
+        let call = |out: &mut [f64]| unsafe {
+            har_entries(json.as_ptr(), json.len(), out.as_mut_ptr(), out.len() * 8)
+        };
+        let needed = call(&mut []);
+        let mut out = vec![0f64; (needed.max(0) / 8) as usize];
+        (needed >= 0 && call(&mut out) == needed).then_some(out)
+    }
+
+    #[test]
+    fn indexes_har_entries() {
+        let json = r#"{"log": {"version": "1.2", "pages": [{"id": "p\"]{"}],
+  "entries": [
+    {"startedDateTime": "2024-01-02T03:04:05.250Z", "time": 12.5,
+     "request": {"url": "https://a/?q=[1,{2}]", "headers": []}},
+    {"time": 3, "startedDateTime": "bad", "response": {"content": {"text": "\\\""}}},
+    {}
+  ]}}"#;
+        let entries = har(json).unwrap();
+        assert_eq!(entries.len(), 3 * ENTRY_WORDS);
+        let span = |e: &[f64]| &json[e[0] as usize..e[1] as usize];
+        assert!(span(&entries[..4]).starts_with(r#"{"startedDateTime""#));
+        assert!(span(&entries[..4]).ends_with("]}}"));
+        assert_eq!(entries[2], 1_704_164_645_250.0);
+        assert_eq!(entries[3], 12.5);
+        assert!(span(&entries[4..8]).ends_with(r#""\\\""}}}"#));
+        assert!(entries[6].is_nan());
+        assert_eq!(entries[7], 3.0);
+        assert_eq!(span(&entries[8..]), "{}");
+
+        assert_eq!(har(r#"{"log": {"entries": []}}"#).unwrap(), []);
+        assert_eq!(har(r#"{"log": {"pages": []}}"#), None);
+        assert_eq!(har(r#"{"log": {"entries": [{"time": 1}"#), None);
+    }
+}
@@ -0,0 +1,199 @@
+//! Content-defined chunking (FastCDC) for deduplication and sync.
+//!
+//! Chunk ends are placed where a gear rolling hash of the last 64 bytes
+//! hits a mask, so they depend only on nearby content: inserting or removing
+//! bytes shifts the boundaries around the edit and leaves the rest where
+//! they were, and unchanged chunks keep their hashes. Normalized chunking
+//! uses a stricter mask before the average size and a looser one after it,
+//! keeping chunk sizes close to the average.
+
+use crate::ffi;
+
+/// Smallest and largest accepted `avg_size`.
+const MIN_AVG: u32 = 64;
+const MAX_AVG: u32 = 1 << 28;
+
+/// Per-byte gear values: splitmix64 outputs, fixed so boundaries are stable
+/// across builds and platforms.
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// Mask of the top `bits` bits, which depend on the last 64 bytes hashed.
+fn mask(bits: u32) -> u64 {
+    !0 << (64 - bits)
+}
+
+/// Length of the chunk at the start of `data`.
+fn cut(data: &[u8], min: usize, avg: usize, max: usize, bits: u32) -> usize {
+    let n = data.len();
+    if n <= min {
+        return n;
+    }
+    let (strict, loose) = (mask(bits + 1), mask(bits - 1));
+    let (normal, end) = (avg.min(n), max.min(n));
+    let mut h = 0u64;
+    for (i, &b) in data.iter().enumerate().take(end).skip(min) {
+        h = (h << 1).wrapping_add(GEAR[b as usize]);
+        let mask = if i < normal { strict } else { loose };
+        if h & mask == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+/// Split the bytes at `in_ptr` into content-defined chunks of `min_size` to
+/// `max_size` bytes, averaging about `avg_size` (a power of two), and write
+/// the end offset of each chunk as a `u32`. The last chunk may be shorter
+/// than `min_size` and always ends at `in_len`. When streaming, keep the
+/// bytes after the second-to-last offset and put them in front of the next
+/// input, since the last chunk may grow with more data.
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for an
+/// `avg_size` that is not a power of two in `64..=2^28`, sizes that do not
+/// satisfy `0 < min_size <= avg_size <= max_size`, input over 4 GiB, or a
+/// short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn cdc_chunks(
+    in_ptr: *const u8,
+    in_len: usize,
+    min_size: u32,
+    avg_size: u32,
+    max_size: u32,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    if !avg_size.is_power_of_two()
+        || !(MIN_AVG..=MAX_AVG).contains(&avg_size)
+        || min_size == 0
+        || min_size > avg_size
+        || avg_size > max_size
+        || u32::try_from(in_len).is_err()
+    {
+        return -1;
+    }
+    let data = ffi::slice(in_ptr, in_len);
+    let bits = avg_size.trailing_zeros();
+    let (min, avg, max) = (min_size as usize, avg_size as usize, max_size as usize);
+    let mut ends = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        start += cut(&data[start..], min, avg, max, bits);
+        ends.push(start as u32);
+    }
+    let needed = ends.len() * 4;
+    if out_len_bytes == 0 {
+        return needed as isize;
+    }
+    if out_len_bytes < needed {
+        return -1;
+    }
+    if ffi::aliased(in_ptr, in_len, out_ptr as *const u8, needed) {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(out_ptr, ends.len()).copy_from_slice(&ends);
+    needed as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random(n: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..n)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    fn chunks(data: &[u8], min: u32, avg: u32, max: u32) -> Option<Vec<u32>> {
+        let call = |out: &mut [u32]| unsafe {
+            cdc_chunks(
+                data.as_ptr(),
+                data.len(),
+                min,
+                avg,
+                max,
+                out.as_mut_ptr(),
+                out.len() * 4,
+            )
+        };
+        let needed = usize::try_from(call(&mut [])).ok()?;
+        let mut out = vec![0u32; needed / 4];
+        if needed > 0 {
+            assert_eq!(call(&mut out), needed as isize);
+        }
+        Some(out)
+    }
+
+    #[test]
+    fn chunk_sizes_stay_in_bounds() {
+        let data = random(1 << 20, 7);
+        let ends = chunks(&data, 2048, 8192, 65536).unwrap();
+        assert_eq!(*ends.last().unwrap(), data.len() as u32);
+        let sizes: Vec<u32> = std::iter::once(ends[0])
+            .chain(ends.windows(2).map(|w| w[1] - w[0]))
+            .collect();
+        let (last, full) = sizes.split_last().unwrap();
+        assert!(full.iter().all(|s| (2048..=65536).contains(s)));
+        assert!(*last <= 65536);
+        // Normalized chunking keeps the mean near the target.
+        let mean = data.len() / sizes.len();
+        assert!((6000..12000).contains(&mean), "{mean}");
+        // Uniform data never matches the mask, so every chunk is the max.
+        let flat = chunks(&[0u8; 10000], 100, 1024, 3000).unwrap();
+        assert_eq!(flat, [3000, 6000, 9000, 10000]);
+        assert_eq!(chunks(&data[..100], 2048, 8192, 65536).unwrap(), [100]);
+        assert_eq!(chunks(&[], 2048, 8192, 65536).unwrap(), []);
+    }
+
+    #[test]
+    fn boundaries_resynchronize_after_an_edit() {
+        let data = random(1 << 18, 99);
+        let mut edited = b"a few inserted bytes".to_vec();
+        edited.extend_from_slice(&data);
+        let shift = edited.len() as u32 - data.len() as u32;
+        let before = chunks(&data, 512, 2048, 16384).unwrap();
+        let after = chunks(&edited, 512, 2048, 16384).unwrap();
+        let shifted: Vec<u32> = after.iter().map(|&e| e - shift).collect();
+        let kept = before.iter().filter(|e| shifted.contains(e)).count();
+        assert!(kept + 3 >= before.len(), "{kept} of {}", before.len());
+    }
+
+    #[test]
+    fn rejects_bad_sizes() {
+        let data = random(100, 1);
+        assert!(
+            chunks(&data, 16, 1000, 4096).is_none(),
+            "not a power of two"
+        );
+        assert!(chunks(&data, 16, 32, 4096).is_none(), "average too small");
+        assert!(chunks(&data, 0, 1024, 4096).is_none());
+        assert!(chunks(&data, 2048, 1024, 4096).is_none());
+        assert!(chunks(&data, 16, 1024, 512).is_none());
+        let mut out = [0u32; 1];
+        let data = random(5000, 2);
+        let written =
+            unsafe { cdc_chunks(data.as_ptr(), data.len(), 64, 128, 256, out.as_mut_ptr(), 4) };
+        assert_eq!(written, -1, "short output");
+    }
+}
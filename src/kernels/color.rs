@@ -0,0 +1,583 @@
+//! Batch color space conversion for palette extraction and contrast checks.
+//!
+//! Pixels are packed `f32`s, three or four channels each; a fourth channel
+//! (alpha) is passed through untouched. Every conversion goes through
+//! linear-light sRGB. The sRGB transfer curve is evaluated with a
+//! `log2`/`exp2` polynomial pair, four channels at a time, accurate to about
+//! `1e-6` relative; the same operations run in SIMD and scalar builds, so
+//! both give identical results.
+
+use crate::ffi;
+
+/// Gamma-encoded sRGB, channels in `[0, 1]`.
+pub const COLOR_SRGB: u32 = 0;
+/// Linear-light sRGB, channels in `[0, 1]`.
+pub const COLOR_LINEAR: u32 = 1;
+/// `[hue in degrees, saturation, lightness]` of gamma-encoded sRGB.
+pub const COLOR_HSL: u32 = 2;
+/// CIELAB under D65, `L` in `[0, 100]`.
+pub const COLOR_LAB: u32 = 3;
+/// Oklab, `L` in `[0, 1]`.
+pub const COLOR_OKLAB: u32 = 4;
+/// Oklab in polar form: `[L, chroma, hue in degrees]`.
+pub const COLOR_OKLCH: u32 = 5;
+
+/// `x^p` for positive normal `x`, per lane: `exp2(p * log2(x))`.
+#[inline(always)]
+fn pow_block(x: [f32; 4], p: f32) -> [f32; 4] {
+    use std::f32::consts::{LN_2, LOG2_E, SQRT_2};
+
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let one = f32x4_splat(1.0);
+        let x = v128_load(x.as_ptr() as *const v128);
+        // x = m * 2^e with m in [sqrt(1/2), sqrt(2)).
+        let e = i32x4_sub(u32x4_shr(x, 23), i32x4_splat(127));
+        let m = v128_or(
+            v128_and(x, i32x4_splat(0x007F_FFFF)),
+            i32x4_splat(0x3F80_0000),
+        );
+        let big = f32x4_gt(m, f32x4_splat(SQRT_2));
+        let m = v128_bitselect(f32x4_mul(m, f32x4_splat(0.5)), m, big);
+        let e = i32x4_sub(e, big);
+        // ln(m) = 2 atanh(s), s = (m - 1) / (m + 1), |s| < 0.172.
+        let s = f32x4_div(f32x4_sub(m, one), f32x4_add(m, one));
+        let s2 = f32x4_mul(s, s);
+        let mut series = f32x4_splat(1.0 / 9.0);
+        for c in [1.0 / 7.0, 0.2, 1.0 / 3.0, 1.0] {
+            series = f32x4_add(f32x4_splat(c), f32x4_mul(s2, series));
+        }
+        let ln_m = f32x4_mul(f32x4_mul(f32x4_splat(2.0), s), series);
+        let log2 = f32x4_add(f32x4_convert_i32x4(e), f32x4_mul(ln_m, f32x4_splat(LOG2_E)));
+        let y = f32x4_mul(f32x4_splat(p), log2);
+        let y = f32x4_min(f32x4_max(y, f32x4_splat(-126.0)), f32x4_splat(126.0));
+        // 2^y = 2^n * e^(f ln 2) with |f| <= 1/2.
+        let n = f32x4_nearest(y);
+        let f = f32x4_mul(f32x4_sub(y, n), f32x4_splat(LN_2));
+        let mut taylor = f32x4_splat(1.0 / 5040.0);
+        for c in [
+            1.0 / 720.0,
+            1.0 / 120.0,
+            1.0 / 24.0,
+            1.0 / 6.0,
+            0.5,
+            1.0,
+            1.0,
+        ] {
+            taylor = f32x4_add(f32x4_splat(c), f32x4_mul(f, taylor));
+        }
+        let scale = i32x4_shl(i32x4_add(i32x4_trunc_sat_f32x4(n), i32x4_splat(127)), 23);
+        let mut out = [0f32; 4];
+        v128_store(out.as_mut_ptr() as *mut v128, f32x4_mul(scale, taylor));
+        out
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        x.map(|x| {
+            let bits = x.to_bits();
+            let mut e = (bits >> 23) as i32 - 127;
+            let mut m = f32::from_bits((bits & 0x007F_FFFF) | 0x3F80_0000);
+            if m > SQRT_2 {
+                m *= 0.5;
+                e += 1;
+            }
+            let s = (m - 1.0) / (m + 1.0);
+            let s2 = s * s;
+            let series = [1.0 / 7.0, 0.2, 1.0 / 3.0, 1.0]
+                .iter()
+                .fold(1.0 / 9.0, |acc, &c| c + s2 * acc);
+            let ln_m = 2.0 * s * series;
+            let y = (p * (e as f32 + ln_m * LOG2_E)).clamp(-126.0, 126.0);
+            let n = y.round_ties_even();
+            let f = (y - n) * LN_2;
+            let taylor = [
+                1.0 / 720.0,
+                1.0 / 120.0,
+                1.0 / 24.0,
+                1.0 / 6.0,
+                0.5,
+                1.0,
+                1.0,
+            ]
+            .iter()
+            .fold(1.0 / 5040.0, |acc, &c| c + f * acc);
+            f32::from_bits(((n as i32 + 127) as u32) << 23) * taylor
+        })
+    }
+}
+
+/// sRGB transfer curve to linear light, extended to negative values by
+/// symmetry as CSS Color 4 does. `NaN` and infinities pass through.
+fn decode(c: [f32; 3]) -> [f32; 3] {
+    let a = c.map(f32::abs);
+    let curve = pow_block(
+        [0, 1, 2, 3].map(|i| (a.get(i).unwrap_or(&1.0) + 0.055) / 1.055),
+        2.4,
+    );
+    std::array::from_fn(|i| {
+        let v = if a[i] <= 0.04045 {
+            a[i] / 12.92
+        } else if a[i] < f32::INFINITY {
+            curve[i]
+        } else {
+            a[i]
+        };
+        v.copysign(c[i])
+    })
+}
+
+/// Linear light to the sRGB transfer curve; the inverse of `decode`.
+fn encode(l: [f32; 3]) -> [f32; 3] {
+    let a = l.map(f32::abs);
+    let curve = pow_block([0, 1, 2, 3].map(|i| *a.get(i).unwrap_or(&1.0)), 1.0 / 2.4);
+    std::array::from_fn(|i| {
+        let v = if a[i] <= 0.003_130_8 {
+            a[i] * 12.92
+        } else if a[i] < f32::INFINITY {
+            1.055 * curve[i] - 0.055
+        } else {
+            a[i]
+        };
+        v.copysign(l[i])
+    })
+}
+
+fn mul(m: &[[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+    m.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+const RGB_TO_XYZ: [[f32; 3]; 3] = [
+    [0.412_390_8, 0.357_584_33, 0.180_480_8],
+    [0.212_639, 0.715_168_65, 0.072_192_32],
+    [0.019_330_818, 0.119_194_78, 0.950_532_14],
+];
+const XYZ_TO_RGB: [[f32; 3]; 3] = [
+    [3.240_97, -1.537_383_2, -0.498_610_76],
+    [-0.969_243_65, 1.875_967_5, 0.041_555_06],
+    [0.055_630_08, -0.203_976_96, 1.056_971_5],
+];
+/// D65 reference white.
+const WHITE: [f32; 3] = [0.950_455_9, 1.0, 1.089_057_8];
+/// CIE constants: 216 / 24389 and 24389 / 27.
+const EPSILON: f32 = 0.008_856_452;
+const KAPPA: f32 = 903.296_3;
+
+const RGB_TO_LMS: [[f32; 3]; 3] = [
+    [0.412_221_46, 0.536_332_55, 0.051_445_995],
+    [0.211_903_5, 0.680_699_5, 0.107_396_96],
+    [0.088_302_46, 0.281_718_85, 0.629_978_7],
+];
+const LMS_TO_OKLAB: [[f32; 3]; 3] = [
+    [0.210_454_26, 0.793_617_8, -0.004_072_047],
+    [1.977_998_5, -2.428_592_2, 0.450_593_7],
+    [0.025_904_037, 0.782_771_77, -0.808_675_77],
+];
+const OKLAB_TO_LMS: [[f32; 3]; 3] = [
+    [1.0, 0.396_337_78, 0.215_803_76],
+    [1.0, -0.105_561_346, -0.063_854_17],
+    [1.0, -0.089_484_18, -1.291_485_5],
+];
+const LMS_TO_RGB: [[f32; 3]; 3] = [
+    [4.076_741_7, -3.307_711_6, 0.230_969_94],
+    [-1.268_438, 2.609_757_4, -0.341_319_38],
+    [-0.004_196_086_3, -0.703_418_6, 1.707_614_7],
+];
+
+fn srgb_to_hsl([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let (chroma, l) = (max - min, (max + min) / 2.0);
+    if chroma == 0.0 {
+        return [0.0, 0.0, l];
+    }
+    let h = if max == r {
+        ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    [h * 60.0, chroma / (1.0 - (2.0 * l - 1.0).abs()), l]
+}
+
+fn hsl_to_srgb([h, s, l]: [f32; 3]) -> [f32; 3] {
+    let chroma = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    [0.0, 8.0, 4.0].map(|n: f32| {
+        let k = (n + h / 30.0).rem_euclid(12.0);
+        l - chroma / 2.0 * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+    })
+}
+
+fn linear_to_lab(rgb: [f32; 3]) -> [f32; 3] {
+    let xyz = mul(&RGB_TO_XYZ, rgb);
+    let [fx, fy, fz] = [0, 1, 2].map(|i| {
+        let t = xyz[i] / WHITE[i];
+        if t > EPSILON {
+            t.cbrt()
+        } else {
+            (KAPPA * t + 16.0) / 116.0
+        }
+    });
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn lab_to_linear([l, a, b]: [f32; 3]) -> [f32; 3] {
+    let fy = (l + 16.0) / 116.0;
+    let inverse = |f: f32| {
+        let cube = f * f * f;
+        if cube > EPSILON {
+            cube
+        } else {
+            (116.0 * f - 16.0) / KAPPA
+        }
+    };
+    let y = if l > KAPPA * EPSILON {
+        fy * fy * fy
+    } else {
+        l / KAPPA
+    };
+    let xyz = [inverse(a / 500.0 + fy), y, inverse(fy - b / 200.0)];
+    mul(&XYZ_TO_RGB, [0, 1, 2].map(|i| xyz[i] * WHITE[i]))
+}
+
+fn linear_to_oklab(rgb: [f32; 3]) -> [f32; 3] {
+    mul(&LMS_TO_OKLAB, mul(&RGB_TO_LMS, rgb).map(f32::cbrt))
+}
+
+fn oklab_to_linear(lab: [f32; 3]) -> [f32; 3] {
+    mul(&LMS_TO_RGB, mul(&OKLAB_TO_LMS, lab).map(|v| v * v * v))
+}
+
+fn to_polar([l, a, b]: [f32; 3]) -> [f32; 3] {
+    [l, a.hypot(b), b.atan2(a).to_degrees().rem_euclid(360.0)]
+}
+
+fn from_polar([l, c, h]: [f32; 3]) -> [f32; 3] {
+    let (sin, cos) = h.to_radians().sin_cos();
+    [l, c * cos, c * sin]
+}
+
+fn known(space: u32) -> bool {
+    matches!(
+        space,
+        COLOR_SRGB | COLOR_LINEAR | COLOR_HSL | COLOR_LAB | COLOR_OKLAB | COLOR_OKLCH
+    )
+}
+
+fn to_linear(space: u32, v: [f32; 3]) -> [f32; 3] {
+    match space {
+        COLOR_SRGB => decode(v),
+        COLOR_HSL => decode(hsl_to_srgb(v)),
+        COLOR_LAB => lab_to_linear(v),
+        COLOR_OKLAB => oklab_to_linear(v),
+        COLOR_OKLCH => oklab_to_linear(from_polar(v)),
+        _ => v,
+    }
+}
+
+fn from_linear(space: u32, v: [f32; 3]) -> [f32; 3] {
+    match space {
+        COLOR_SRGB => encode(v),
+        COLOR_HSL => srgb_to_hsl(encode(v)),
+        COLOR_LAB => linear_to_lab(v),
+        COLOR_OKLAB => linear_to_oklab(v),
+        COLOR_OKLCH => to_polar(linear_to_oklab(v)),
+        _ => v,
+    }
+}
+
+/// Convert the pixels at `in_ptr` from color space `from` to `to` (the
+/// `COLOR_*` constants), `channels` (3 or 4) `f32`s per pixel. Values
+/// outside the sRGB gamut convert without clamping. Hue is `0` for grays
+/// in HSL and may be any angle for near-grays in OKLCH. Runs in place when
+/// `in_ptr == out_ptr`.
+///
+/// Returns bytes written, or `-1` for an unknown space, `channels` other than
+/// 3 or 4, a partial pixel or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn color_convert_f32(
+    in_ptr: *const f32,
+    in_len_bytes: usize,
+    channels: u32,
+    from: u32,
+    to: u32,
+    out_ptr: *mut f32,
+    out_len_bytes: usize,
+) -> isize {
+    let channels = channels as usize;
+    if !(3..=4).contains(&channels)
+        || !known(from)
+        || !known(to)
+        || !in_len_bytes.is_multiple_of(channels * 4)
+        || out_len_bytes < in_len_bytes
+    {
+        return -1;
+    }
+    let n = in_len_bytes / 4;
+    if !std::ptr::eq(in_ptr, out_ptr) {
+        if ffi::aliased(
+            in_ptr as *const u8,
+            in_len_bytes,
+            out_ptr as *const u8,
+            in_len_bytes,
+        ) {
+            return ffi::ALIAS_ERROR;
+        }
+        std::ptr::copy(in_ptr, out_ptr, n);
+    }
+    if from != to {
+        for pixel in ffi::slice_mut(out_ptr, n).chunks_exact_mut(channels) {
+            let v = [pixel[0], pixel[1], pixel[2]];
+            pixel[..3].copy_from_slice(&from_linear(to, to_linear(from, v)));
+        }
+    }
+    in_len_bytes as isize
+}
+
+/// Decode 8-bit sRGB pixels (`channels` bytes each, 3 or 4, as in canvas
+/// `ImageData` with 4) to linear-light `f32`s in `[0, 1]`. Alpha is scaled to
+/// `[0, 1]` without decoding.
+///
+/// Returns bytes written (`4 * in_len`), or `-1` for bad `channels`, a
+/// partial pixel or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn srgb8_to_linear_f32(
+    in_ptr: *const u8,
+    in_len: usize,
+    channels: u32,
+    out_ptr: *mut f32,
+    out_len_bytes: usize,
+) -> isize {
+    let channels = channels as usize;
+    if !(3..=4).contains(&channels)
+        || !in_len.is_multiple_of(channels)
+        || out_len_bytes / 4 < in_len
+    {
+        return -1;
+    }
+    if ffi::aliased(in_ptr, in_len, out_ptr as *const u8, in_len * 4) {
+        return ffi::ALIAS_ERROR;
+    }
+    let table: [f32; 256] = std::array::from_fn(|i| decode([i as f32 / 255.0; 3])[0]);
+    let input = ffi::slice(in_ptr, in_len);
+    let out = ffi::slice_mut(out_ptr, in_len);
+    for (src, dst) in input
+        .chunks_exact(channels)
+        .zip(out.chunks_exact_mut(channels))
+    {
+        for k in 0..3 {
+            dst[k] = table[src[k] as usize];
+        }
+        if channels == 4 {
+            dst[3] = src[3] as f32 / 255.0;
+        }
+    }
+    (in_len * 4) as isize
+}
+
+/// Encode linear-light `f32` pixels (`channels` each, 3 or 4) to 8-bit sRGB,
+/// clamping to `[0, 1]` and rounding to nearest. Alpha is scaled without
+/// encoding.
+///
+/// Returns bytes written (`in_len_bytes / 4`), or `-1` for bad `channels`, a
+/// partial pixel or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn linear_f32_to_srgb8(
+    in_ptr: *const f32,
+    in_len_bytes: usize,
+    channels: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let channels = channels as usize;
+    let n = in_len_bytes / 4;
+    if !(3..=4).contains(&channels) || !in_len_bytes.is_multiple_of(channels * 4) || out_len < n {
+        return -1;
+    }
+    if ffi::aliased(in_ptr as *const u8, in_len_bytes, out_ptr, n) {
+        return ffi::ALIAS_ERROR;
+    }
+    let to_byte = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let input = ffi::slice(in_ptr, n);
+    let out = ffi::slice_mut(out_ptr, n);
+    for (src, dst) in input
+        .chunks_exact(channels)
+        .zip(out.chunks_exact_mut(channels))
+    {
+        let encoded = encode([src[0], src[1], src[2]]);
+        for k in 0..3 {
+            dst[k] = to_byte(encoded[k]);
+        }
+        if channels == 4 {
+            dst[3] = to_byte(src[3]);
+        }
+    }
+    n as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(pixels: &[f32], channels: u32, from: u32, to: u32) -> Vec<f32> {
+        let mut out = vec![0f32; pixels.len()];
+        let written = unsafe {
+            color_convert_f32(
+                pixels.as_ptr(),
+                pixels.len() * 4,
+                channels,
+                from,
+                to,
+                out.as_mut_ptr(),
+                out.len() * 4,
+            )
+        };
+        assert_eq!(written, pixels.len() as isize * 4);
+        out
+    }
+
+    fn assert_close(got: &[f32], want: &[f32], tolerance: f32) {
+        assert_eq!(got.len(), want.len());
+        for (g, w) in got.iter().zip(want) {
+            assert!((g - w).abs() <= tolerance, "{got:?} != {want:?}");
+        }
+    }
+
+    #[test]
+    fn transfer_curve_matches_powf() {
+        for k in 0..=10_000 {
+            let c = k as f32 / 10_000.0;
+            let exact = if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            };
+            let [l, _, _] = decode([c, 0.0, 0.0]);
+            assert!((l - exact).abs() <= 2e-6 * exact.max(1e-3), "{c}");
+            assert!((encode([l, 0.0, 0.0])[0] - c).abs() <= 2e-6, "{c}");
+        }
+        assert_eq!(decode([-0.5, 0.0, 1.0]), [-decode([0.5; 3])[0], 0.0, 1.0]);
+    }
+
+    #[test]
+    fn converts_known_colors() {
+        let red = [1.0, 0.0, 0.0];
+        assert_close(
+            &convert(&red, 3, COLOR_SRGB, COLOR_HSL),
+            &[0.0, 1.0, 0.5],
+            1e-6,
+        );
+        assert_close(
+            &convert(&red, 3, COLOR_SRGB, COLOR_LAB),
+            &[53.2371, 80.0901, 67.2033],
+            2e-3,
+        );
+        assert_close(
+            &convert(&red, 3, COLOR_SRGB, COLOR_OKLCH),
+            &[0.627_955, 0.257_683, 29.2339],
+            2e-4,
+        );
+        let white = [1.0, 1.0, 1.0, 0.25];
+        assert_close(
+            &convert(&white, 4, COLOR_SRGB, COLOR_LAB),
+            &[100.0, 0.0, 0.0, 0.25],
+            2e-3,
+        );
+        assert_close(
+            &convert(&white, 4, COLOR_LINEAR, COLOR_OKLAB),
+            &[1.0, 0.0, 0.0, 0.25],
+            1e-4,
+        );
+        assert_close(
+            &convert(&[0.5, 0.5, 0.5], 3, COLOR_SRGB, COLOR_LINEAR),
+            &[0.214_041; 3],
+            1e-6,
+        );
+    }
+
+    #[test]
+    fn round_trips_every_space() {
+        let mut seed = 3u32;
+        let pixels: Vec<f32> = (0..4 * 300)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 8) as f32 / (1 << 24) as f32
+            })
+            .collect();
+        for space in [COLOR_LINEAR, COLOR_HSL, COLOR_LAB, COLOR_OKLAB, COLOR_OKLCH] {
+            let there = convert(&pixels, 4, COLOR_SRGB, space);
+            assert_close(&convert(&there, 4, space, COLOR_SRGB), &pixels, 2e-4);
+        }
+
+        // In place.
+        let mut buffer = pixels.clone();
+        let ptr = buffer.as_mut_ptr();
+        let written =
+            unsafe { color_convert_f32(ptr, 4800, 4, COLOR_SRGB, COLOR_OKLAB, ptr, 4800) };
+        assert_eq!(written, 4800);
+        assert_eq!(buffer, convert(&pixels, 4, COLOR_SRGB, COLOR_OKLAB));
+    }
+
+    #[test]
+    fn round_trips_8_bit_pixels() {
+        let bytes: Vec<u8> = (0..=255).chain([7, 200]).collect();
+        let mut linear = vec![0f32; bytes.len()];
+        let written = unsafe {
+            srgb8_to_linear_f32(
+                bytes.as_ptr(),
+                bytes.len(),
+                3,
+                linear.as_mut_ptr(),
+                linear.len() * 4,
+            )
+        };
+        assert_eq!(written, 258 * 4);
+        let mut back = vec![0u8; bytes.len()];
+        let written = unsafe {
+            linear_f32_to_srgb8(
+                linear.as_ptr(),
+                linear.len() * 4,
+                3,
+                back.as_mut_ptr(),
+                back.len(),
+            )
+        };
+        assert_eq!(written, 258);
+        assert_eq!(back, bytes);
+
+        // Alpha is scaled, not decoded.
+        let rgba = [255u8, 128, 0, 128];
+        let mut out = [0f32; 4];
+        unsafe { srgb8_to_linear_f32(rgba.as_ptr(), 4, 4, out.as_mut_ptr(), 16) };
+        assert_close(&out, &[1.0, 0.215_861, 0.0, 128.0 / 255.0], 1e-6);
+        let mut bytes = [0u8; 4];
+        let hot = [2.0f32, -1.0, f32::NAN, 0.5];
+        unsafe { linear_f32_to_srgb8(hot.as_ptr(), 16, 4, bytes.as_mut_ptr(), 4) };
+        assert_eq!(bytes, [255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let pixels = [0f32; 6];
+        let mut out = [0f32; 6];
+        let call = |channels, from, to, len, out: &mut [f32]| unsafe {
+            color_convert_f32(
+                pixels.as_ptr(),
+                len,
+                channels,
+                from,
+                to,
+                out.as_mut_ptr(),
+                24,
+            )
+        };
+        assert_eq!(call(2, 0, 1, 24, &mut out), -1, "channels");
+        assert_eq!(call(3, 0, 6, 24, &mut out), -1, "unknown space");
+        assert_eq!(call(4, 0, 1, 24, &mut out), -1, "partial pixel");
+        assert_eq!(call(3, 0, 1, 24, &mut out), 24);
+    }
+}
@@ -0,0 +1,362 @@
+//! Delimited text (CSV, TSV) kernels.
+//!
+//! Fields follow RFC 4180: a field may be wrapped in double quotes, inside
+//! which delimiters and newlines are literal and `""` stands for one quote.
+//! Rows end in LF or CRLF.
+
+use super::time::{parse_date, DATE_ISO8601, DATE_ONLY};
+use crate::ffi;
+
+/// Skip the first row, treating it as column names.
+pub const SCHEMA_HEADER: u32 = 1;
+
+pub const COLUMN_STRING: u32 = 0;
+pub const COLUMN_INT: u32 = 1;
+pub const COLUMN_FLOAT: u32 = 2;
+pub const COLUMN_BOOL: u32 = 3;
+pub const COLUMN_DATE: u32 = 4;
+
+/// One field of a row. `bytes` excludes the surrounding quotes but still
+/// contains `escapes` doubled quotes.
+#[derive(Clone, Copy)]
+pub(crate) struct Field<'a> {
+    pub bytes: &'a [u8],
+    pub escapes: usize,
+}
+
+impl Field<'_> {
+    /// Length of the field once doubled quotes are collapsed.
+    pub fn width(&self) -> usize {
+        self.bytes.len() - self.escapes
+    }
+}
+
+/// Split `input` into rows of fields, calling `row` once per row that ends in
+/// a terminator. Returns the number of bytes consumed, i.e. the start of the
+/// trailing partial row (or `input.len()`).
+pub(crate) fn for_each_row<'a>(
+    input: &'a [u8],
+    delimiter: u8,
+    mut row: impl FnMut(&[Field<'a>]),
+) -> usize {
+    let mut fields = Vec::new();
+    let mut row_start = 0;
+    let mut i = 0;
+
+    while i < input.len() {
+        let field = if input[i] == b'"' {
+            let mut j = i + 1;
+            let mut escapes = 0;
+            loop {
+                match input[j..].iter().position(|&b| b == b'"') {
+                    // Unterminated quote: the row continues past the sample.
+                    None => return row_start,
+                    Some(q) if input.get(j + q + 1) == Some(&b'"') => {
+                        escapes += 1;
+                        j += q + 2;
+                    }
+                    Some(q) => {
+                        j += q;
+                        break;
+                    }
+                }
+            }
+            let field = Field {
+                bytes: &input[i + 1..j],
+                escapes,
+            };
+            // Anything between the closing quote and the delimiter is dropped.
+            i = j + 1;
+            while i < input.len() && !matches!(input[i], b'\n' | b'\r') && input[i] != delimiter {
+                i += 1;
+            }
+            field
+        } else {
+            let start = i;
+            while i < input.len() && !matches!(input[i], b'\n' | b'\r') && input[i] != delimiter {
+                i += 1;
+            }
+            Field {
+                bytes: &input[start..i],
+                escapes: 0,
+            }
+        };
+        fields.push(field);
+
+        match input.get(i) {
+            None => break,
+            Some(&b) if b == delimiter => i += 1,
+            Some(_) => {
+                i += if input[i] == b'\r' && input.get(i + 1) == Some(&b'\n') {
+                    2
+                } else {
+                    1
+                };
+                row(&fields);
+                fields.clear();
+                row_start = i;
+            }
+        }
+    }
+    row_start
+}
+
+fn is_null(v: &[u8]) -> bool {
+    v.is_empty() || v.eq_ignore_ascii_case(b"null")
+}
+
+fn is_bool(v: &[u8]) -> bool {
+    v.eq_ignore_ascii_case(b"true") || v.eq_ignore_ascii_case(b"false")
+}
+
+fn is_int(v: &[u8]) -> bool {
+    std::str::from_utf8(v).is_ok_and(|s| s.parse::<i64>().is_ok())
+}
+
+/// Decimal or exponent notation with at least one digit, so words such as
+/// `inf` or `NaN` stay strings.
+fn is_float(v: &[u8]) -> bool {
+    v.iter().any(u8::is_ascii_digit)
+        && v.iter().all(|b| b.is_ascii_digit() || b"+-.eE".contains(b))
+        && std::str::from_utf8(v).is_ok_and(|s| s.parse::<f64>().is_ok())
+}
+
+/// An ISO 8601 date or date-time, as accepted by `parse_iso8601_batch`.
+fn is_date(v: &[u8]) -> bool {
+    parse_date(v, DATE_ISO8601 | DATE_ONLY).is_some()
+}
+
+const CAN_INT: u8 = 1;
+const CAN_FLOAT: u8 = 2;
+const CAN_BOOL: u8 = 4;
+const CAN_DATE: u8 = 8;
+
+fn classify(v: &[u8]) -> u8 {
+    let mut can = 0;
+    if is_bool(v) {
+        can |= CAN_BOOL;
+    }
+    if is_int(v) {
+        can |= CAN_INT | CAN_FLOAT;
+    } else if is_float(v) {
+        can |= CAN_FLOAT;
+    }
+    if is_date(v) {
+        can |= CAN_DATE;
+    }
+    can
+}
+
+#[derive(Clone, Copy)]
+struct ColumnStats {
+    fields: u32,
+    nulls: u32,
+    max_width: u32,
+    values: u32,
+    can: u8,
+}
+
+impl ColumnStats {
+    const NEW: Self = ColumnStats {
+        fields: 0,
+        nulls: 0,
+        max_width: 0,
+        values: 0,
+        can: CAN_INT | CAN_FLOAT | CAN_BOOL | CAN_DATE,
+    };
+
+    fn kind(&self) -> u32 {
+        if self.values == 0 {
+            COLUMN_STRING
+        } else if self.can & CAN_BOOL != 0 {
+            COLUMN_BOOL
+        } else if self.can & CAN_INT != 0 {
+            COLUMN_INT
+        } else if self.can & CAN_FLOAT != 0 {
+            COLUMN_FLOAT
+        } else if self.can & CAN_DATE != 0 {
+            COLUMN_DATE
+        } else {
+            COLUMN_STRING
+        }
+    }
+}
+
+/// Infer per-column types from a sample of delimited text.
+///
+/// Writes a flat `u32` result to `out_ptr`: `[columns, rows]` followed by
+/// `[kind, nulls, max_width]` for each column, where `kind` is one of the
+/// `COLUMN_*` codes. Columns that do not fit in `out_len_bytes` are still
+/// counted in `columns` but their entries are omitted. Empty and `null`
+/// fields count as nulls, as do fields missing from short rows; `max_width`
+/// is in bytes with quotes removed.
+///
+/// The sample is usually cut from a larger stream, so a trailing row without
+/// a terminator is ignored unless it is the only row. With `SCHEMA_HEADER` in
+/// `flags` the first row is skipped.
+///
+/// Returns bytes written, or `-1` for a delimiter that is not a single byte
+/// other than a quote or newline.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn infer_schema(
+    in_ptr: *const u8,
+    in_len: usize,
+    delimiter: u32,
+    flags: u32,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let delimiter = match u8::try_from(delimiter) {
+        Ok(b'"' | b'\r' | b'\n') | Err(_) => return -1,
+        Ok(d) => d,
+    };
+    let input = ffi::slice(in_ptr, in_len);
+    let out = ffi::slice_mut(out_ptr, out_len_bytes / 4);
+
+    let mut columns: Vec<ColumnStats> = Vec::new();
+    let mut rows = 0u32;
+    let mut skip = flags & SCHEMA_HEADER != 0;
+    let mut add_row = |fields: &[Field]| {
+        if std::mem::take(&mut skip) {
+            return;
+        }
+        rows += 1;
+        if columns.len() < fields.len() {
+            columns.resize(fields.len(), ColumnStats::NEW);
+        }
+        for (stats, field) in columns.iter_mut().zip(fields) {
+            stats.fields += 1;
+            stats.max_width = stats.max_width.max(field.width() as u32);
+            if is_null(field.bytes) {
+                stats.nulls += 1;
+            } else {
+                stats.values += 1;
+                stats.can &= classify(field.bytes);
+            }
+        }
+    };
+
+    let consumed = for_each_row(input, delimiter, &mut add_row);
+    if consumed == 0 && !input.is_empty() {
+        // No complete row: treat the whole sample as one.
+        let mut terminated = input.to_vec();
+        terminated.push(b'\n');
+        for_each_row(&terminated, delimiter, &mut add_row);
+    }
+
+    let header = [columns.len() as u32, rows];
+    let mut written = 0;
+    for (slot, value) in out.iter_mut().zip(
+        header.into_iter().chain(
+            columns
+                .iter()
+                .flat_map(|c| [c.kind(), c.nulls + (rows - c.fields), c.max_width]),
+        ),
+    ) {
+        *slot = value;
+        written += 1;
+    }
+    // Only whole column entries count.
+    if written > 2 {
+        written -= (written - 2) % 3;
+    }
+    (written * 4) as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn infer(input: &[u8], delimiter: u8, flags: u32, slots: usize) -> Vec<u32> {
+        let mut out = vec![0u32; slots];
+        let written = unsafe {
+            infer_schema(
+                input.as_ptr(),
+                input.len(),
+                delimiter as u32,
+                flags,
+                out.as_mut_ptr(),
+                slots * 4,
+            )
+        };
+        assert!(written >= 0);
+        out.truncate(written as usize / 4);
+        out
+    }
+
+    #[test]
+    fn infers_column_kinds() {
+        let sample = b"id,price,ok,day,name\r\n\
+            1,2.5,true,2024-01-31,\"Smith, J\"\r\n\
+            -7,3,FALSE,2024-02-01T10:00:00Z,\"say \"\"hi\"\"\"\r\n\
+            ,1e3,,2024-12-25,null\r\n\
+            42,x";
+        let out = infer(sample, b',', SCHEMA_HEADER, 32);
+        assert_eq!(
+            out,
+            [
+                5,
+                3,
+                COLUMN_INT,
+                1,
+                2,
+                COLUMN_FLOAT,
+                0,
+                3,
+                COLUMN_BOOL,
+                1,
+                5,
+                COLUMN_DATE,
+                0,
+                20,
+                COLUMN_STRING,
+                1,
+                8,
+            ]
+        );
+    }
+
+    #[test]
+    fn mixed_and_missing_values() {
+        // Column 1 mixes ints and bools; column 2 only appears in row 2.
+        let out = infer(b"1\ttrue\n2\t3\tx\n", b'\t', 0, 11);
+        assert_eq!(
+            out,
+            [
+                3,
+                2,
+                COLUMN_INT,
+                0,
+                1,
+                COLUMN_STRING,
+                0,
+                4,
+                COLUMN_STRING,
+                1,
+                1
+            ]
+        );
+    }
+
+    #[test]
+    fn single_partial_row_and_truncation() {
+        assert_eq!(infer(b"1;2.5", b';', 0, 6), [2, 1, COLUMN_INT, 0, 1]);
+        assert_eq!(infer(b"", b',', 0, 8), [0, 0]);
+    }
+
+    #[test]
+    fn quoted_newlines_stay_in_field() {
+        let out = infer(b"\"a\nb\",1\n\"open", b',', 0, 8);
+        assert_eq!(out, [2, 1, COLUMN_STRING, 0, 3, COLUMN_INT, 0, 1]);
+    }
+
+    #[test]
+    fn rejects_bad_delimiter() {
+        let mut out = [0u32; 2];
+        let status = unsafe { infer_schema(b"a".as_ptr(), 1, b'"' as u32, 0, out.as_mut_ptr(), 8) };
+        assert_eq!(status, -1);
+        let status = unsafe { infer_schema(b"a".as_ptr(), 1, 300, 0, out.as_mut_ptr(), 8) };
+        assert_eq!(status, -1);
+    }
+}
@@ -0,0 +1,706 @@
+//! Datasets: named columns registered once and kept in wasm memory.
+//!
+//! A dataset is a handle holding columns of equal length, each `f64` values
+//! or UTF-8 strings (text plus Arrow-style offsets) with an optional
+//! validity bitmap (bit `i % 8` of byte `i / 8` set when row `i` is
+//! present). Columns are copied in, so JS can release its buffers right
+//! after registering them, and whole-table kernels such as
+//! `describe_dataset` then work from the handle alone. An `f64` row is also
+//! missing when it holds `NaN`.
+
+use super::dict::hash_bytes;
+use super::hll::{mix, Hll};
+use crate::error::{self, ErrorCode};
+use crate::{ffi, handles};
+
+/// Statistics per column written by `describe_dataset`.
+pub const DESCRIBE_STATS: usize = 7;
+/// `gather_rows` flag: read the row list as `[start, end)` pairs.
+pub const GATHER_RANGES: u32 = 1;
+
+pub(crate) enum Values {
+    F64(Vec<f64>),
+    /// `rows + 1` ascending offsets into `text`, starting at zero.
+    Utf8 {
+        text: Vec<u8>,
+        offsets: Vec<u32>,
+    },
+}
+
+pub(crate) struct Column {
+    pub(crate) name: String,
+    pub(crate) values: Values,
+    pub(crate) validity: Option<Vec<u8>>,
+}
+
+impl Column {
+    /// Whether row `row` holds a value.
+    pub(crate) fn is_valid(&self, row: usize) -> bool {
+        let present = self
+            .validity
+            .as_ref()
+            .is_none_or(|bits| bits[row / 8] >> (row % 8) & 1 == 1);
+        match &self.values {
+            Values::F64(values) => present && !values[row].is_nan(),
+            Values::Utf8 { .. } => present,
+        }
+    }
+
+    /// The bytes of string row `row`; empty for an `f64` column.
+    pub(crate) fn str(&self, row: usize) -> &[u8] {
+        match &self.values {
+            Values::F64(_) => &[],
+            Values::Utf8 { text, offsets } => {
+                &text[offsets[row] as usize..offsets[row + 1] as usize]
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Dataset {
+    pub(crate) rows: usize,
+    pub(crate) columns: Vec<Column>,
+}
+
+impl Dataset {
+    /// Append a column of `rows` values, returning its index, or `-1` when
+    /// the row count differs from the columns already registered.
+    fn push(&mut self, column: Column, rows: usize) -> isize {
+        if !self.columns.is_empty() && rows != self.rows {
+            return error::fail(ErrorCode::BadLength);
+        }
+        self.rows = rows;
+        self.columns.push(column);
+        self.columns.len() as isize - 1
+    }
+}
+
+/// Copy the name and validity bitmap shared by the `dataset_add_` exports,
+/// or `None` (with the error recorded) for a name that is not UTF-8 or a
+/// short bitmap.
+unsafe fn column_parts(
+    name_ptr: *const u8,
+    name_len: usize,
+    validity_ptr: *const u8,
+    validity_len: usize,
+    rows: usize,
+) -> Option<(String, Option<Vec<u8>>)> {
+    let Ok(name) = String::from_utf8(ffi::slice(name_ptr, name_len).to_vec()) else {
+        error::record(ErrorCode::InvalidInput);
+        return None;
+    };
+    let validity = match validity_len {
+        0 => None,
+        n if n < rows.div_ceil(8) => {
+            error::record(ErrorCode::BadLength);
+            return None;
+        }
+        _ => Some(ffi::slice(validity_ptr, rows.div_ceil(8)).to_vec()),
+    };
+    Some((name, validity))
+}
+
+/// Create an empty dataset.
+///
+/// Returns a handle for the `dataset_` exports, released with `handle_drop`.
+#[no_mangle]
+pub extern "C" fn dataset_new() -> isize {
+    handles::insert(Dataset::default()) as isize
+}
+
+/// Register a copy of the `f64` values at `values_ptr` as a column named by
+/// the UTF-8 bytes at `name_ptr`. With `validity_len == 0` every row is
+/// present (except `NaN`s); otherwise the bitmap must cover every row.
+///
+/// Returns the new column's index, or `-1` for an unknown handle, a partial
+/// value, a name that is not UTF-8, a short bitmap, or a row count that
+/// differs from the dataset's other columns.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn dataset_add_f64(
+    handle: u32,
+    name_ptr: *const u8,
+    name_len: usize,
+    values_ptr: *const f64,
+    values_len_bytes: usize,
+    validity_ptr: *const u8,
+    validity_len: usize,
+) -> isize {
+    if !values_len_bytes.is_multiple_of(8) {
+        return error::fail(ErrorCode::BadLength);
+    }
+    let rows = values_len_bytes / 8;
+    let Some((name, validity)) = column_parts(name_ptr, name_len, validity_ptr, validity_len, rows)
+    else {
+        return -1;
+    };
+    let values = Values::F64(ffi::slice(values_ptr, rows).to_vec());
+    let column = Column {
+        name,
+        values,
+        validity,
+    };
+    handles::with(handle, |dataset: &mut Dataset| dataset.push(column, rows)).unwrap_or(-1)
+}
+
+/// Register a copy of a UTF-8 string column: `offsets_len` ascending
+/// Arrow-style offsets (`rows + 1`) into the text at `text_ptr`. Validity
+/// works as in `dataset_add_f64`.
+///
+/// Returns the new column's index, or `-1` for an unknown handle, offsets
+/// that are empty, descending or past the text, text or a name that is not
+/// UTF-8, a short bitmap, or a mismatched row count.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn dataset_add_utf8(
+    handle: u32,
+    name_ptr: *const u8,
+    name_len: usize,
+    text_ptr: *const u8,
+    text_len: usize,
+    offsets_ptr: *const u32,
+    offsets_len: usize,
+    validity_ptr: *const u8,
+    validity_len: usize,
+) -> isize {
+    let offsets = ffi::slice(offsets_ptr, offsets_len);
+    let (Some(&first), Some(&last)) = (offsets.first(), offsets.last()) else {
+        return error::fail(ErrorCode::BadLength);
+    };
+    if last as usize > text_len || offsets.windows(2).any(|w| w[0] > w[1]) {
+        return error::fail(ErrorCode::InvalidInput);
+    }
+    let rows = offsets_len - 1;
+    let Some((name, validity)) = column_parts(name_ptr, name_len, validity_ptr, validity_len, rows)
+    else {
+        return -1;
+    };
+    let text = &ffi::slice(text_ptr, text_len)[first as usize..last as usize];
+    if std::str::from_utf8(text).is_err() {
+        return error::fail(ErrorCode::InvalidInput);
+    }
+    let values = Values::Utf8 {
+        text: text.to_vec(),
+        offsets: offsets.iter().map(|&o| o - first).collect(),
+    };
+    let column = Column {
+        name,
+        values,
+        validity,
+    };
+    handles::with(handle, |dataset: &mut Dataset| dataset.push(column, rows)).unwrap_or(-1)
+}
+
+/// Number of rows in the dataset behind `handle` (`0` before any column is
+/// registered), or `-1` for an unknown handle.
+#[no_mangle]
+pub extern "C" fn dataset_rows(handle: u32) -> isize {
+    handles::with(handle, |dataset: &mut Dataset| dataset.rows as isize).unwrap_or(-1)
+}
+
+/// Write the name of column `index` as UTF-8.
+///
+/// With `out_len == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for an
+/// unknown handle or column or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn dataset_column_name(
+    handle: u32,
+    index: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    handles::with(handle, |dataset: &mut Dataset| {
+        let Some(column) = dataset.columns.get(index as usize) else {
+            return error::fail(ErrorCode::InvalidArgument);
+        };
+        let name = column.name.as_bytes();
+        if out_len == 0 {
+            return name.len() as isize;
+        }
+        if out_len < name.len() {
+            return error::fail(ErrorCode::ShortOutput);
+        }
+        ffi::slice_mut(out_ptr, name.len()).copy_from_slice(name);
+        name.len() as isize
+    })
+    .unwrap_or(-1)
+}
+
+/// One pass over a column: `[count, nulls, min, max, mean, stddev,
+/// distinct]`.
+fn describe(column: &Column, rows: usize) -> [f64; DESCRIBE_STATS] {
+    let mut distinct = Hll::new(14);
+    let (mut count, mut mean, mut m2) = (0usize, 0.0f64, 0.0f64);
+    let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+    for row in (0..rows).filter(|&row| column.is_valid(row)) {
+        count += 1;
+        match &column.values {
+            Values::F64(values) => {
+                let v = values[row];
+                // Welford's update keeps the variance stable in one pass.
+                let delta = v - mean;
+                mean += delta / count as f64;
+                m2 += delta * (v - mean);
+                min = min.min(v);
+                max = max.max(v);
+                // `+ 0.0` folds -0.0 into 0.0 so both count once.
+                distinct.add(mix((v + 0.0).to_bits()));
+            }
+            Values::Utf8 { .. } => distinct.add(mix(hash_bytes(column.str(row)))),
+        }
+    }
+    let numeric = matches!(column.values, Values::F64(_)) && count > 0;
+    let stddev = if numeric && count > 1 {
+        (m2 / (count - 1) as f64).sqrt()
+    } else {
+        f64::NAN
+    };
+    let or_nan = |v: f64| if numeric { v } else { f64::NAN };
+    [
+        count as f64,
+        (rows - count) as f64,
+        or_nan(min),
+        or_nan(max),
+        or_nan(mean),
+        stddev,
+        distinct.estimate().round(),
+    ]
+}
+
+/// Summarize every column of the dataset behind `handle` in one call.
+///
+/// Writes `DESCRIBE_STATS` `f64`s per column, in registration order:
+/// non-null count, null count, min, max, mean, sample standard deviation
+/// and approximate distinct count (HyperLogLog, about 1% error). Min, max,
+/// mean and standard deviation are `NaN` for string columns and for columns
+/// without enough values.
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for an
+/// unknown handle or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn describe_dataset(
+    handle: u32,
+    out_ptr: *mut f64,
+    out_len_bytes: usize,
+) -> isize {
+    handles::with(handle, |dataset: &mut Dataset| {
+        let needed = dataset.columns.len() * DESCRIBE_STATS * 8;
+        if out_len_bytes == 0 {
+            return needed as isize;
+        }
+        if out_len_bytes < needed {
+            return error::fail(ErrorCode::ShortOutput);
+        }
+        let out = ffi::slice_mut(out_ptr, needed / 8);
+        for (column, stats) in dataset
+            .columns
+            .iter()
+            .zip(out.chunks_exact_mut(DESCRIBE_STATS))
+        {
+            stats.copy_from_slice(&describe(column, dataset.rows));
+        }
+        needed as isize
+    })
+    .unwrap_or(-1)
+}
+
+/// Expand the row list passed to `gather_rows`, or fail with a row past
+/// the end or a malformed range.
+fn selected_rows(list: &[u32], flags: u32, rows: usize) -> Result<Vec<usize>, ErrorCode> {
+    if flags & GATHER_RANGES == 0 {
+        if list.iter().any(|&row| row as usize >= rows) {
+            return Err(ErrorCode::InvalidInput);
+        }
+        return Ok(list.iter().map(|&row| row as usize).collect());
+    }
+    if !list.len().is_multiple_of(2) {
+        return Err(ErrorCode::BadLength);
+    }
+    let mut selected = Vec::new();
+    for range in list.chunks_exact(2) {
+        let (start, end) = (range[0] as usize, range[1] as usize);
+        if start > end || end > rows {
+            return Err(ErrorCode::InvalidInput);
+        }
+        selected.extend(start..end);
+    }
+    Ok(selected)
+}
+
+/// Bytes of one column's section in the `gather_rows` block, padding
+/// included.
+fn section_len(column: &Column, selected: &[usize]) -> usize {
+    let k = selected.len();
+    let len = match &column.values {
+        Values::F64(_) => k * 8 + k.div_ceil(8),
+        Values::Utf8 { .. } => {
+            let text: usize = selected.iter().map(|&row| column.str(row).len()).sum();
+            (k + 1) * 4 + k.div_ceil(8) + text
+        }
+    };
+    len.next_multiple_of(8)
+}
+
+/// Write one column's section for `gather_rows` into `out`, which is
+/// exactly `section_len` bytes.
+fn write_section(column: &Column, selected: &[usize], out: &mut [u8]) {
+    let k = selected.len();
+    out.fill(0);
+    let (data, rest) = match &column.values {
+        Values::F64(_) => out.split_at_mut(k * 8),
+        Values::Utf8 { .. } => out.split_at_mut((k + 1) * 4),
+    };
+    let (validity, text) = rest.split_at_mut(k.div_ceil(8));
+    for (i, &row) in selected.iter().enumerate() {
+        if column.is_valid(row) {
+            validity[i / 8] |= 1 << (i % 8);
+        }
+    }
+    match &column.values {
+        Values::F64(values) => {
+            for (chunk, &row) in data.chunks_exact_mut(8).zip(selected) {
+                chunk.copy_from_slice(&values[row].to_le_bytes());
+            }
+        }
+        Values::Utf8 { .. } => {
+            let mut end = 0;
+            for (i, &row) in selected.iter().enumerate() {
+                let s = column.str(row);
+                text[end..end + s.len()].copy_from_slice(s);
+                end += s.len();
+                data[(i + 1) * 4..(i + 2) * 4].copy_from_slice(&(end as u32).to_le_bytes());
+            }
+        }
+    }
+}
+
+/// Gather the rows listed at `rows_ptr` (`u32` row indices in any order,
+/// repeats allowed, or `[start, end)` pairs with `GATHER_RANGES`) from every
+/// column of the dataset behind `handle` into one compact block, for
+/// example the rows of a scrolled viewport.
+///
+/// The block holds one section per column in registration order, each
+/// starting at a multiple of 8 bytes and zero-padded to one. For `k` rows an
+/// `f64` column's section is `k` values followed by a `k`-bit validity
+/// bitmap; a string column's is `k + 1` `u32` offsets from zero, the bitmap,
+/// then the text. A row is valid as in `describe_dataset`. Allocate the
+/// block with `alloc_aligned(len, 8)` to view the values as a
+/// `Float64Array`.
+///
+/// With `out_len == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for an
+/// unknown handle, a row past the end, a malformed range, a partial index
+/// or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn gather_rows(
+    handle: u32,
+    rows_ptr: *const u32,
+    rows_len_bytes: usize,
+    flags: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    if !rows_len_bytes.is_multiple_of(4) {
+        return error::fail(ErrorCode::BadLength);
+    }
+    handles::with(handle, |dataset: &mut Dataset| {
+        let list = ffi::slice(rows_ptr, rows_len_bytes / 4);
+        let selected = match selected_rows(list, flags, dataset.rows) {
+            Ok(selected) => selected,
+            Err(code) => return error::fail(code),
+        };
+        let lens: Vec<usize> = dataset
+            .columns
+            .iter()
+            .map(|column| section_len(column, &selected))
+            .collect();
+        let needed: usize = lens.iter().sum();
+        if out_len == 0 {
+            return needed as isize;
+        }
+        if out_len < needed {
+            return error::fail(ErrorCode::ShortOutput);
+        }
+        if ffi::aliased(rows_ptr as *const u8, rows_len_bytes, out_ptr, needed) {
+            return ffi::ALIAS_ERROR;
+        }
+        let mut out = ffi::slice_mut(out_ptr, needed);
+        for (column, len) in dataset.columns.iter().zip(lens) {
+            let (section, rest) = out.split_at_mut(len);
+            write_section(column, &selected, section);
+            out = rest;
+        }
+        needed as isize
+    })
+    .unwrap_or(-1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_f64(handle: u32, name: &str, values: &[f64], validity: &[u8]) -> isize {
+        unsafe {
+            dataset_add_f64(
+                handle,
+                name.as_ptr(),
+                name.len(),
+                values.as_ptr(),
+                values.len() * 8,
+                validity.as_ptr(),
+                validity.len(),
+            )
+        }
+    }
+
+    fn add_utf8(handle: u32, name: &str, values: &[&str], validity: &[u8]) -> isize {
+        let text = values.concat();
+        let mut offsets = vec![0u32];
+        for v in values {
+            offsets.push(offsets.last().unwrap() + v.len() as u32);
+        }
+        unsafe {
+            dataset_add_utf8(
+                handle,
+                name.as_ptr(),
+                name.len(),
+                text.as_ptr(),
+                text.len(),
+                offsets.as_ptr(),
+                offsets.len(),
+                validity.as_ptr(),
+                validity.len(),
+            )
+        }
+    }
+
+    fn describe_all(handle: u32) -> Vec<f64> {
+        let needed = unsafe { describe_dataset(handle, std::ptr::null_mut(), 0) };
+        let mut out = vec![0f64; needed as usize / 8];
+        assert_eq!(
+            unsafe { describe_dataset(handle, out.as_mut_ptr(), needed as usize) },
+            needed
+        );
+        out
+    }
+
+    #[test]
+    fn describes_numeric_and_string_columns() {
+        let handle = dataset_new() as u32;
+        // Row 4 is masked out and row 5 is NaN.
+        let values = [2.0, 4.0, 4.0, -0.0, 100.0, f64::NAN, 0.0, 10.0];
+        assert_eq!(add_f64(handle, "score", &values, &[0b1110_1111]), 0);
+        let names = ["ann", "bob", "ann", "", "cy", "bob", "dee", "x"];
+        assert_eq!(add_utf8(handle, "name", &names, &[0b0111_1111]), 1);
+        assert_eq!(dataset_rows(handle), 8);
+        let mut name = [0u8; 8];
+        assert_eq!(
+            unsafe { dataset_column_name(handle, 1, name.as_mut_ptr(), 0) },
+            4
+        );
+        assert_eq!(
+            unsafe { dataset_column_name(handle, 1, name.as_mut_ptr(), 8) },
+            4
+        );
+        assert_eq!(&name[..4], b"name");
+        assert_eq!(
+            unsafe { dataset_column_name(handle, 2, name.as_mut_ptr(), 8) },
+            -1
+        );
+
+        let stats = describe_all(handle);
+        let (score, name) = stats.split_at(DESCRIBE_STATS);
+        let present = [2.0, 4.0, 4.0, 0.0, 0.0, 10.0];
+        let mean = present.iter().sum::<f64>() / 6.0;
+        let var = present.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / 5.0;
+        assert_eq!(score[..4], [6.0, 2.0, 0.0, 10.0]);
+        assert!((score[4] - mean).abs() < 1e-12);
+        assert!((score[5] - var.sqrt()).abs() < 1e-12);
+        assert_eq!(score[6], 4.0, "2, 4, 0 and 10 with -0.0 folded into 0.0");
+
+        assert_eq!(name[..2], [7.0, 1.0]);
+        assert!(name[2..6].iter().all(|v| v.is_nan()));
+        assert_eq!(name[6], 5.0, "ann, bob, empty, cy, dee");
+        assert_eq!(handles::handle_drop(handle), 0);
+    }
+
+    #[test]
+    fn approximates_large_distinct_counts() {
+        let handle = dataset_new() as u32;
+        let values: Vec<f64> = (0..100_000).map(|i| (i % 20_000) as f64 * 0.5).collect();
+        add_f64(handle, "x", &values, &[]);
+        let stats = describe_all(handle);
+        assert!((stats[6] - 20_000.0).abs() < 600.0, "{}", stats[6]);
+        assert_eq!(stats[..4], [100_000.0, 0.0, 0.0, 9_999.5]);
+    }
+
+    fn gather(handle: u32, list: &[u32], flags: u32) -> Option<Vec<u8>> {
+        let gather_into = |out: &mut [u8]| unsafe {
+            gather_rows(
+                handle,
+                list.as_ptr(),
+                list.len() * 4,
+                flags,
+                out.as_mut_ptr(),
+                out.len(),
+            )
+        };
+        let needed = gather_into(&mut []);
+        if needed < 0 {
+            return None;
+        }
+        let mut out = vec![0xaa; needed as usize];
+        assert_eq!(gather_into(&mut out), needed);
+        Some(out)
+    }
+
+    fn f64s(bytes: &[u8]) -> Vec<f64> {
+        let chunks = bytes.chunks_exact(8);
+        chunks
+            .map(|c| f64::from_le_bytes(c.try_into().unwrap()))
+            .collect()
+    }
+
+    fn u32s(bytes: &[u8]) -> Vec<u32> {
+        let chunks = bytes.chunks_exact(4);
+        chunks
+            .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn gathers_rows_from_every_column() {
+        let handle = dataset_new() as u32;
+        add_f64(handle, "x", &[0.5, 1.5, f64::NAN, 3.5, 4.5], &[0b1_1110]);
+        add_utf8(handle, "s", &["a", "bb", "", "dddd", "é"], &[0b1_0111]);
+
+        // Rows 4, 0 and 2: f64 section is 24 + 1 bytes padded to 32, string
+        // section 16 offsets + 1 bitmap + 3 text bytes padded to 24.
+        let block = gather(handle, &[4, 0, 2], 0).unwrap();
+        assert_eq!(block.len(), 32 + 24);
+        let (x, s) = block.split_at(32);
+        assert_eq!(f64s(&x[..24])[..2], [4.5, 0.5]);
+        assert!(f64s(&x[..24])[2].is_nan());
+        assert_eq!(x[24], 0b001, "row 0 masked out, row 2 NaN");
+        assert!(x[25..].iter().all(|&b| b == 0), "zero padding");
+        assert_eq!(u32s(&s[..16]), [0, 2, 3, 3]);
+        assert_eq!(s[16], 0b111);
+        assert_eq!(&s[17..20], "éa".as_bytes());
+
+        let ranges = gather(handle, &[3, 5, 1, 2], GATHER_RANGES).unwrap();
+        let (x, s) = ranges.split_at(32);
+        assert_eq!(f64s(&x[..24]), [3.5, 4.5, 1.5]);
+        assert_eq!(x[24], 0b111);
+        assert_eq!(u32s(&s[..16]), [0, 4, 6, 8]);
+        assert_eq!(s[16], 0b110, "row 3 masked out");
+        assert_eq!(&s[17..25], "ddddébb".as_bytes());
+
+        assert_eq!(gather(handle, &[], 0).unwrap(), [0; 8], "one string offset");
+        assert_eq!(handles::handle_drop(handle), 0);
+    }
+
+    #[test]
+    fn gather_rejects_bad_rows() {
+        let handle = dataset_new() as u32;
+        add_f64(handle, "x", &[1.0, 2.0], &[]);
+        assert!(gather(handle, &[2], 0).is_none(), "row past the end");
+        assert_eq!(error::last_error_code(), ErrorCode::InvalidInput as u32);
+        assert!(
+            gather(handle, &[1, 0], GATHER_RANGES).is_none(),
+            "descending range"
+        );
+        assert!(
+            gather(handle, &[0, 3], GATHER_RANGES).is_none(),
+            "range past the end"
+        );
+        assert!(
+            gather(handle, &[0], GATHER_RANGES).is_none(),
+            "half a range"
+        );
+        assert_eq!(error::last_error_code(), ErrorCode::BadLength as u32);
+        let mut short = [0u8; 8];
+        let rows = [0u32, 1];
+        let status = unsafe { gather_rows(handle, rows.as_ptr(), 8, 0, short.as_mut_ptr(), 8) };
+        assert_eq!(status, -1, "short output");
+        assert_eq!(error::last_error_code(), ErrorCode::ShortOutput as u32);
+        assert_eq!(
+            unsafe { gather_rows(handle, rows.as_ptr(), 6, 0, short.as_mut_ptr(), 8) },
+            -1
+        );
+        assert_eq!(handles::handle_drop(handle), 0);
+        assert!(gather(handle, &[0], 0).is_none(), "dropped handle");
+        assert_eq!(error::last_error_code(), ErrorCode::UnknownHandle as u32);
+    }
+
+    #[test]
+    fn rejects_bad_columns() {
+        let handle = dataset_new() as u32;
+        assert_eq!(add_f64(handle, "a", &[1.0, 2.0], &[]), 0);
+        assert_eq!(add_f64(handle, "b", &[1.0], &[]), -1, "row count");
+        assert_eq!(
+            add_f64(handle, "\u{fffd}", &[1.0; 9], &[0xff]),
+            -1,
+            "short bitmap"
+        );
+        let bad_name = [0xffu8];
+        let status = unsafe {
+            dataset_add_f64(
+                handle,
+                bad_name.as_ptr(),
+                1,
+                [1.0f64, 2.0].as_ptr(),
+                16,
+                std::ptr::null(),
+                0,
+            )
+        };
+        assert_eq!(status, -1, "name not UTF-8");
+        let offsets = [0u32, 3, 2];
+        let status = unsafe {
+            dataset_add_utf8(
+                handle,
+                "s".as_ptr(),
+                1,
+                "abc".as_ptr(),
+                3,
+                offsets.as_ptr(),
+                3,
+                std::ptr::null(),
+                0,
+            )
+        };
+        assert_eq!(status, -1, "descending offsets");
+        let bad_text = [0x61u8, 0xc3];
+        let offsets = [0u32, 1, 2];
+        let status = unsafe {
+            dataset_add_utf8(
+                handle,
+                "s".as_ptr(),
+                1,
+                bad_text.as_ptr(),
+                2,
+                offsets.as_ptr(),
+                3,
+                std::ptr::null(),
+                0,
+            )
+        };
+        assert_eq!(status, -1, "text not UTF-8");
+        assert_eq!(add_utf8(handle, "s", &["é", ""], &[]), 1);
+
+        let mut out = [0f64; 13];
+        assert_eq!(
+            unsafe { describe_dataset(handle, out.as_mut_ptr(), 13 * 8) },
+            -1
+        );
+        assert_eq!(handles::handle_drop(handle), 0);
+        assert_eq!(dataset_rows(handle), -1);
+        assert_eq!(unsafe { describe_dataset(handle, out.as_mut_ptr(), 0) }, -1);
+    }
+}
@@ -0,0 +1,183 @@
+//! Dictionary encoding of string batches.
+//!
+//! `dict_build` interns every value of a batch in an open-addressing hash
+//! table held in wasm memory and returns the distinct values plus one `u32`
+//! code per row, so categorical columns never round-trip through a JS `Map`.
+
+use super::spans;
+use crate::ffi;
+
+/// Multiply-rotate hash over 8-byte words; the top bits index the table.
+pub(crate) fn hash_bytes(bytes: &[u8]) -> u64 {
+    const K: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut h = (bytes.len() as u64).wrapping_mul(K);
+    let mut words = bytes.chunks_exact(8);
+    for word in &mut words {
+        let w = u64::from_le_bytes(word.try_into().unwrap());
+        h = (h.rotate_left(5) ^ w).wrapping_mul(K);
+    }
+    let rest = words.remainder();
+    if !rest.is_empty() {
+        let mut tail = [0u8; 8];
+        tail[..rest.len()].copy_from_slice(rest);
+        h = (h.rotate_left(5) ^ u64::from_le_bytes(tail)).wrapping_mul(K);
+    }
+    h ^ (h >> 29)
+}
+
+/// Assign codes in first-seen order. `unique` receives the `[start, end)`
+/// span of each distinct value's first occurrence. Returns the number of
+/// distinct values, or `None` for a bad span or a full `unique` buffer.
+fn build(text: &[u8], offsets: &[u32], unique: &mut [u32], codes: &mut [u32]) -> Option<usize> {
+    let rows = codes.len();
+    // Keep the load factor at or below one half.
+    let bits = (rows.max(1) * 2).next_power_of_two().trailing_zeros();
+    let mask = (1usize << bits) - 1;
+    let mut slots = vec![0u32; mask + 1];
+    let mut hashes: Vec<u64> = Vec::new();
+    let mut count = 0;
+
+    for ((value, code), w) in spans(text, offsets)
+        .zip(codes.iter_mut())
+        .zip(offsets.windows(2))
+    {
+        let value = value?;
+        let h = hash_bytes(value);
+        let mut i = (h >> (64 - bits)) as usize;
+        loop {
+            match slots[i] {
+                0 => {
+                    let pair = unique.get_mut(2 * count..2 * count + 2)?;
+                    pair.copy_from_slice(w);
+                    hashes.push(h);
+                    count += 1;
+                    slots[i] = count as u32;
+                    *code = count as u32 - 1;
+                    break;
+                }
+                slot => {
+                    let c = slot as usize - 1;
+                    let (start, end) = (unique[2 * c] as usize, unique[2 * c + 1] as usize);
+                    if hashes[c] == h && &text[start..end] == value {
+                        *code = c as u32;
+                        break;
+                    }
+                    i = (i + 1) & mask;
+                }
+            }
+        }
+    }
+    Some(count)
+}
+
+/// Dictionary-encode the values described by `offsets_ptr` (`rows + 1`
+/// Arrow-style offsets into the text blob).
+///
+/// Writes one `u32` code per row to `codes_ptr`, numbering distinct values in
+/// first-seen order, and the `[start, end)` text span of each distinct value
+/// as a `u32` pair to `unique_ptr`. `rows * 8` bytes of unique output always
+/// suffice.
+///
+/// Returns bytes written to `unique_ptr` (`8` per distinct value), or `-1`
+/// for a bad span or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn dict_build(
+    text_ptr: *const u8,
+    text_len: usize,
+    offsets_ptr: *const u32,
+    offsets_len: usize,
+    unique_ptr: *mut u32,
+    unique_len_bytes: usize,
+    codes_ptr: *mut u32,
+    codes_len_bytes: usize,
+) -> isize {
+    let rows = offsets_len.saturating_sub(1);
+    if codes_len_bytes / 4 < rows {
+        return -1;
+    }
+    let text = ffi::slice(text_ptr, text_len);
+    let offsets = ffi::slice(offsets_ptr, offsets_len);
+    let unique = ffi::slice_mut(unique_ptr, unique_len_bytes / 8 * 2);
+    let codes = ffi::slice_mut(codes_ptr, rows);
+    match build(text, offsets, unique, codes) {
+        Some(count) => (count * 8) as isize,
+        None => -1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(values: &[&str], unique_slots: usize) -> (isize, Vec<u32>, Vec<u32>) {
+        let text: String = values.concat();
+        let mut offsets = vec![0u32];
+        for v in values {
+            offsets.push(offsets.last().unwrap() + v.len() as u32);
+        }
+        let mut unique = vec![0u32; unique_slots];
+        let mut codes = vec![u32::MAX; values.len()];
+        let written = unsafe {
+            dict_build(
+                text.as_ptr(),
+                text.len(),
+                offsets.as_ptr(),
+                offsets.len(),
+                unique.as_mut_ptr(),
+                unique.len() * 4,
+                codes.as_mut_ptr(),
+                codes.len() * 4,
+            )
+        };
+        if written >= 0 {
+            unique.truncate(written as usize / 4);
+        }
+        (written, unique, codes)
+    }
+
+    #[test]
+    fn codes_follow_first_occurrence() {
+        let (written, unique, codes) =
+            encode(&["red", "green", "red", "", "blue", "green", ""], 14);
+        assert_eq!(written, 32);
+        assert_eq!(codes, [0, 1, 0, 2, 3, 1, 2]);
+        assert_eq!(unique, [0, 3, 3, 8, 11, 11, 11, 15]);
+    }
+
+    #[test]
+    fn many_values_survive_collisions() {
+        let values: Vec<String> = (0..5000)
+            .map(|i| format!("category-{}", i % 1234))
+            .collect();
+        let refs: Vec<&str> = values.iter().map(String::as_str).collect();
+        let (written, _, codes) = encode(&refs, refs.len() * 2);
+        assert_eq!(written, 1234 * 8);
+        for (i, &code) in codes.iter().enumerate() {
+            assert_eq!(code as usize, i % 1234);
+        }
+    }
+
+    #[test]
+    fn rejects_short_outputs_and_bad_spans() {
+        assert_eq!(encode(&["a", "b", "c"], 4).0, -1);
+        assert_eq!(encode(&[], 0).0, 0);
+
+        let offsets = [0u32, 4, 2];
+        let mut unique = [0u32; 4];
+        let mut codes = [0u32; 2];
+        let status = unsafe {
+            dict_build(
+                b"abcd".as_ptr(),
+                4,
+                offsets.as_ptr(),
+                3,
+                unique.as_mut_ptr(),
+                16,
+                codes.as_mut_ptr(),
+                8,
+            )
+        };
+        assert_eq!(status, -1);
+    }
+}
@@ -0,0 +1,454 @@
+//! Shortest edit scripts between byte strings or line batches.
+//!
+//! Both kernels run Myers' O(ND) algorithm in its linear-space form: common
+//! prefixes and suffixes are trimmed, then the middle snake of the remaining
+//! range splits it in two, recursively. Memory stays proportional to the
+//! input, and time to the input size times the number of edits, so similar
+//! documents diff quickly however large they are. For the line diff, equal
+//! lines are first interned to the same `u32`, so the search compares
+//! integers rather than text.
+
+use std::collections::HashMap;
+
+use super::spans;
+use crate::ffi;
+
+/// Edit kinds in diff output.
+pub const DIFF_EQUAL: u32 = 0;
+pub const DIFF_DELETE: u32 = 1;
+pub const DIFF_INSERT: u32 = 2;
+
+/// Edit runs as `[kind, a_start, b_start, len]`.
+type Edits = Vec<[u32; 4]>;
+
+fn push(edits: &mut Edits, kind: u32, a: usize, b: usize, len: usize) {
+    if len == 0 {
+        return;
+    }
+    match edits.last_mut() {
+        Some(last) if last[0] == kind => last[3] += len as u32,
+        _ => edits.push([kind, a as u32, b as u32, len as u32]),
+    }
+}
+
+/// Append the edits turning `a` into `b`, which start at `a_off` and `b_off`
+/// in the full sequences.
+fn diff_range<T: PartialEq>(a: &[T], b: &[T], a_off: usize, b_off: usize, edits: &mut Edits) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    push(edits, DIFF_EQUAL, a_off, b_off, prefix);
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let (a_off, b_off) = (a_off + prefix, b_off + prefix);
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+
+    if a.is_empty() || b.is_empty() {
+        push(edits, DIFF_DELETE, a_off, b_off, a.len());
+        push(edits, DIFF_INSERT, a_off + a.len(), b_off, b.len());
+    } else {
+        let (x, y) = middle_snake(a, b);
+        diff_range(&a[..x], &b[..y], a_off, b_off, edits);
+        diff_range(&a[x..], &b[y..], a_off + x, b_off + y, edits);
+    }
+    push(edits, DIFF_EQUAL, a_off + a.len(), b_off + b.len(), suffix);
+}
+
+/// Point `(x, y)` where a shortest edit path from `(0, 0)` to the end
+/// crosses the middle of its edits, found by searching forward from the
+/// start and backward from the end until the two frontiers overlap. `a` and
+/// `b` are nonempty and differ in their first and last items, so the point
+/// splits the problem into two strictly smaller ones.
+fn middle_snake<T: PartialEq>(a: &[T], b: &[T]) -> (usize, usize) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max_d = (n + m + 1) / 2;
+    let offset = max_d;
+    let len = 2 * max_d + 2;
+    // Furthest x reached on each diagonal k = x - y, forward and backward.
+    let mut forward = vec![-1isize; len as usize];
+    let mut backward = vec![-1isize; len as usize];
+    forward[offset as usize + 1] = 0;
+    backward[offset as usize + 1] = 0;
+    let delta = n - m;
+    // With an odd delta the paths meet on a forward step, otherwise on a
+    // backward one.
+    let odd = delta % 2 != 0;
+    let (mut k1_start, mut k1_end, mut k2_start, mut k2_end) = (0, 0, 0, 0);
+    for d in 0..max_d {
+        let mut k1 = -d + k1_start;
+        while k1 <= d - k1_end {
+            let i = (offset + k1) as usize;
+            let mut x = if k1 == -d || (k1 != d && forward[i - 1] < forward[i + 1]) {
+                forward[i + 1]
+            } else {
+                forward[i - 1] + 1
+            };
+            let mut y = x - k1;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[i] = x;
+            if x > n {
+                k1_end += 2;
+            } else if y > m {
+                k1_start += 2;
+            } else if odd {
+                let j = offset + delta - k1;
+                if (0..len).contains(&j)
+                    && backward[j as usize] != -1
+                    && x >= n - backward[j as usize]
+                {
+                    return (x as usize, y as usize);
+                }
+            }
+            k1 += 2;
+        }
+
+        let mut k2 = -d + k2_start;
+        while k2 <= d - k2_end {
+            let i = (offset + k2) as usize;
+            let mut x = if k2 == -d || (k2 != d && backward[i - 1] < backward[i + 1]) {
+                backward[i + 1]
+            } else {
+                backward[i - 1] + 1
+            };
+            let mut y = x - k2;
+            while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[i] = x;
+            if x > n {
+                k2_end += 2;
+            } else if y > m {
+                k2_start += 2;
+            } else if !odd {
+                let j = offset + delta - k2;
+                if (0..len).contains(&j) && forward[j as usize] != -1 {
+                    let x1 = forward[j as usize];
+                    if x1 >= n - x {
+                        return (x1 as usize, (x1 - (j - offset)) as usize);
+                    }
+                }
+            }
+            k2 += 2;
+        }
+    }
+    // Unreachable for nonempty inputs; replacing everything is still a
+    // valid, if long, script.
+    (a.len(), 0)
+}
+
+/// Append a changed region as one delete then one insert. Both start at
+/// the region's start in `b`, and the insert where the delete ends in `a`.
+fn flush(edits: &mut Edits, deleted: &mut Option<[u32; 4]>, inserted: &mut Option<[u32; 4]>) {
+    if let (Some(d), Some(i)) = (deleted.as_mut(), inserted.as_mut()) {
+        d[2] = d[2].min(i[2]);
+        i[1] = d[1] + d[3];
+    }
+    edits.extend(deleted.take());
+    edits.extend(inserted.take());
+}
+
+/// Shortest edit script from `a` to `b`, with each changed region listed as
+/// one delete followed by one insert.
+fn diff<T: PartialEq>(a: &[T], b: &[T]) -> Edits {
+    let mut raw = Vec::new();
+    diff_range(a, b, 0, 0, &mut raw);
+    let mut edits = Vec::with_capacity(raw.len());
+    let (mut deleted, mut inserted) = (None::<[u32; 4]>, None::<[u32; 4]>);
+    for edit in raw {
+        let pending = match edit[0] {
+            DIFF_DELETE => &mut deleted,
+            DIFF_INSERT => &mut inserted,
+            _ => {
+                flush(&mut edits, &mut deleted, &mut inserted);
+                edits.push(edit);
+                continue;
+            }
+        };
+        match pending {
+            Some(run) => run[3] += edit[3],
+            None => *pending = Some(edit),
+        }
+    }
+    flush(&mut edits, &mut deleted, &mut inserted);
+    edits
+}
+
+/// Copy `edits` to the output, or report the bytes needed.
+unsafe fn write(
+    edits: &Edits,
+    inputs: &[(*const u8, usize)],
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let needed = edits.len() * 16;
+    if out_len_bytes == 0 {
+        return needed as isize;
+    }
+    if out_len_bytes < needed {
+        return -1;
+    }
+    if inputs
+        .iter()
+        .any(|&(ptr, len)| ffi::aliased(ptr, len, out_ptr as *const u8, needed))
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(out_ptr, edits.len() * 4).copy_from_slice(edits.as_flattened());
+    needed as isize
+}
+
+/// Diff the bytes at `a_ptr` against those at `b_ptr`. Writes the shortest
+/// edit script as runs of four `u32`s, `[kind, a_start, b_start, len]`, with
+/// `kind` one of `DIFF_EQUAL`, `DIFF_DELETE` and `DIFF_INSERT`. Runs cover
+/// both inputs in order; each changed region is one delete (of
+/// `a[a_start..a_start + len]`) then one insert (of `b[b_start..b_start +
+/// len]`), either of which may be absent. Time grows with the input size
+/// times the number of edits.
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for an
+/// input over 4 GiB or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn diff_bytes(
+    a_ptr: *const u8,
+    a_len: usize,
+    b_ptr: *const u8,
+    b_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    if u32::try_from(a_len.max(b_len)).is_err() {
+        return -1;
+    }
+    let edits = diff(ffi::slice(a_ptr, a_len), ffi::slice(b_ptr, b_len));
+    write(
+        &edits,
+        &[(a_ptr, a_len), (b_ptr, b_len)],
+        out_ptr,
+        out_len_bytes,
+    )
+}
+
+/// Diff two line batches, each given as text plus `lines + 1` Arrow-style
+/// offsets, as `diff_bytes` does with lines in place of bytes: `a_start`,
+/// `b_start` and `len` count lines. Lines compare by content, so how they
+/// were split (with or without terminators) is up to the caller.
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for
+/// empty offsets, a bad span, or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn diff_lines(
+    a_text_ptr: *const u8,
+    a_text_len: usize,
+    a_offsets_ptr: *const u32,
+    a_offsets_len: usize,
+    b_text_ptr: *const u8,
+    b_text_len: usize,
+    b_offsets_ptr: *const u32,
+    b_offsets_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    if a_offsets_len == 0 || b_offsets_len == 0 {
+        return -1;
+    }
+    let a_text = ffi::slice(a_text_ptr, a_text_len);
+    let b_text = ffi::slice(b_text_ptr, b_text_len);
+    let a_offsets = ffi::slice(a_offsets_ptr, a_offsets_len);
+    let b_offsets = ffi::slice(b_offsets_ptr, b_offsets_len);
+    let mut ids: HashMap<&[u8], u32> = HashMap::new();
+    let mut intern = |text, offsets| -> Option<Vec<u32>> {
+        spans(text, offsets)
+            .map(|line| {
+                let next = ids.len() as u32;
+                Some(*ids.entry(line?).or_insert(next))
+            })
+            .collect()
+    };
+    let (Some(a), Some(b)) = (intern(a_text, a_offsets), intern(b_text, b_offsets)) else {
+        return -1;
+    };
+    let inputs = [
+        (a_text_ptr, a_text_len),
+        (a_offsets_ptr as *const u8, a_offsets_len * 4),
+        (b_text_ptr, b_text_len),
+        (b_offsets_ptr as *const u8, b_offsets_len * 4),
+    ];
+    write(&diff(&a, &b), &inputs, out_ptr, out_len_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edits(a: &[u8], b: &[u8]) -> Vec<[u32; 4]> {
+        let call = |out: &mut [u32]| unsafe {
+            diff_bytes(
+                a.as_ptr(),
+                a.len(),
+                b.as_ptr(),
+                b.len(),
+                out.as_mut_ptr(),
+                out.len() * 4,
+            )
+        };
+        let needed = call(&mut []);
+        let mut out = vec![0u32; needed as usize / 4];
+        assert_eq!(call(&mut out), needed);
+        out.chunks(4).map(|e| [e[0], e[1], e[2], e[3]]).collect()
+    }
+
+    /// Replay `edits` over `a`, checking each run's positions, and return
+    /// the result and the number of equal items.
+    fn apply<T: Clone + PartialEq + std::fmt::Debug>(
+        a: &[T],
+        b: &[T],
+        edits: &[[u32; 4]],
+    ) -> (Vec<T>, usize) {
+        let (mut i, mut j, mut out, mut same) = (0, 0, Vec::new(), 0);
+        for (n, &[kind, a_start, b_start, len]) in edits.iter().enumerate() {
+            let (a_start, b_start, len) = (a_start as usize, b_start as usize, len as usize);
+            assert!(len > 0);
+            assert_eq!((a_start, b_start), (i, j), "edit {n}");
+            match kind {
+                DIFF_EQUAL => {
+                    assert_eq!(a[i..i + len], b[j..j + len]);
+                    out.extend_from_slice(&a[i..i + len]);
+                    (i, j, same) = (i + len, j + len, same + len);
+                }
+                DIFF_DELETE => i += len,
+                _ => {
+                    out.extend_from_slice(&b[j..j + len]);
+                    j += len;
+                }
+            }
+        }
+        assert_eq!((i, j), (a.len(), b.len()));
+        (out, same)
+    }
+
+    fn lcs(a: &[u8], b: &[u8]) -> usize {
+        let mut row = vec![0usize; b.len() + 1];
+        for &x in a {
+            let mut diag = 0;
+            for (j, &y) in b.iter().enumerate() {
+                let up = row[j + 1];
+                row[j + 1] = if x == y { diag + 1 } else { up.max(row[j]) };
+                diag = up;
+            }
+        }
+        row[b.len()]
+    }
+
+    #[test]
+    fn diffs_bytes_minimally() {
+        assert_eq!(
+            edits(b"kitten", b"sitting"),
+            [
+                [DIFF_DELETE, 0, 0, 1],
+                [DIFF_INSERT, 1, 0, 1],
+                [DIFF_EQUAL, 1, 1, 3],
+                [DIFF_DELETE, 4, 4, 1],
+                [DIFF_INSERT, 5, 4, 1],
+                [DIFF_EQUAL, 5, 5, 1],
+                [DIFF_INSERT, 6, 6, 1],
+            ]
+        );
+        assert!(edits(b"", b"").is_empty());
+        assert_eq!(edits(b"abc", b""), [[DIFF_DELETE, 0, 0, 3]]);
+        assert_eq!(edits(b"", b"xy"), [[DIFF_INSERT, 0, 0, 2]]);
+        assert_eq!(edits(b"same", b"same"), [[DIFF_EQUAL, 0, 0, 4]]);
+
+        // Random strings over a small alphabet: the script must rebuild `b`
+        // and keep a longest common subsequence.
+        let mut state = 0x9E37_79B9u32;
+        let mut next = |n: u32| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state % n
+        };
+        for _ in 0..300 {
+            let (la, lb) = (next(40) as usize, next(40) as usize);
+            let a: Vec<u8> = (0..la).map(|_| b'a' + next(4) as u8).collect();
+            let b: Vec<u8> = (0..lb).map(|_| b'a' + next(4) as u8).collect();
+            let (rebuilt, same) = apply(&a, &b, &edits(&a, &b));
+            assert_eq!(rebuilt, b);
+            assert_eq!(same, lcs(&a, &b), "{a:?} {b:?}");
+        }
+    }
+
+    #[test]
+    fn diffs_line_batches() {
+        let batch = |lines: &[&str]| {
+            let mut offsets = vec![0u32];
+            for l in lines {
+                offsets.push(offsets.last().unwrap() + l.len() as u32);
+            }
+            (lines.concat().into_bytes(), offsets)
+        };
+        let (a, a_off) = batch(&["fn main() {\n", "    a();\n", "    b();\n", "}\n"]);
+        let (b, b_off) = batch(&[
+            "fn main() {\n",
+            "    b();\n",
+            "    c();\n",
+            "    d();\n",
+            "}\n",
+        ]);
+        let call = |b_off: &[u32], out: &mut [u32]| unsafe {
+            diff_lines(
+                a.as_ptr(),
+                a.len(),
+                a_off.as_ptr(),
+                a_off.len(),
+                b.as_ptr(),
+                b.len(),
+                b_off.as_ptr(),
+                b_off.len(),
+                out.as_mut_ptr(),
+                out.len() * 4,
+            )
+        };
+        assert_eq!(call(&b_off, &mut []), 5 * 16);
+        let mut out = [0u32; 20];
+        assert_eq!(call(&b_off, &mut out), 80);
+        assert_eq!(
+            out,
+            [
+                DIFF_EQUAL,
+                0,
+                0,
+                1, //
+                DIFF_DELETE,
+                1,
+                1,
+                1, //
+                DIFF_EQUAL,
+                2,
+                1,
+                1, //
+                DIFF_INSERT,
+                3,
+                2,
+                2, //
+                DIFF_EQUAL,
+                3,
+                4,
+                1,
+            ]
+        );
+        assert_eq!(call(&b_off, &mut out[..19]), -1, "short output");
+        assert_eq!(call(&[0, 999], &mut out), -1, "bad span");
+        assert_eq!(call(&[], &mut out), -1, "empty offsets");
+    }
+}
@@ -0,0 +1,746 @@
+//! Ed25519 signature verification (`ed25519` feature).
+//!
+//! Verifies RFC 8032 signatures over a batch of messages so signed records
+//! (log entries, update manifests) can be checked without a separate crypto
+//! bundle. Only verification is provided: no secret keys ever enter wasm.
+//!
+//! Field elements use five 51-bit limbs with `u128` products, and points use
+//! extended twisted Edwards coordinates with the complete addition law, so
+//! there are no special cases. A signature is accepted when
+//! `[S]B - [k]A` encodes to the signature's `R`, as in RFC 8032 and most
+//! libraries. Public keys and `y` coordinates must be canonical and `S` must
+//! be below the group order, which rules out malleated signatures. The code
+//! is not constant-time, which is fine for verifying public data.
+
+use super::spans;
+use crate::ffi;
+
+const MASK: u64 = (1 << 51) - 1;
+
+/// An element of GF(2^255 - 19) in radix 2^51. Limbs may exceed 51 bits
+/// slightly between operations; `to_bytes` fully reduces.
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+const ZERO: Fe = Fe([0; 5]);
+const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+/// The curve constant `d = -121665 / 121666`.
+const D: Fe = Fe([
+    0x34dca135978a3,
+    0x1a8283b156ebd,
+    0x5e7a26001c029,
+    0x739c663a03cbb,
+    0x52036cee2b6ff,
+]);
+const D2: Fe = Fe([
+    0x69b9426b2f159,
+    0x35050762add7a,
+    0x3cf44c0038052,
+    0x6738cc7407977,
+    0x2406d9dc56dff,
+]);
+const SQRT_M1: Fe = Fe([
+    0x61b274a0ea0b0,
+    0x0d5a5fc8f189d,
+    0x7ef5e9cbd0c60,
+    0x78595a6804c9e,
+    0x2b8324804fc1d,
+]);
+/// `(p - 5) / 8`, little-endian, for square roots.
+const P58: [u8; 32] = {
+    let mut e = [0xFF; 32];
+    e[0] = 0xFD;
+    e[31] = 0x0F;
+    e
+};
+/// `p - 2`, little-endian, for inversion.
+const P2: [u8; 32] = {
+    let mut e = [0xFF; 32];
+    e[0] = 0xEB;
+    e[31] = 0x7F;
+    e
+};
+
+/// The base point `B`.
+const BASE: Point = Point {
+    x: Fe([
+        0x62d608f25d51a,
+        0x412a4b4f6592a,
+        0x75b7171a4b31d,
+        0x1ff60527118fe,
+        0x216936d3cd6e5,
+    ]),
+    y: Fe([
+        0x6666666666658,
+        0x4cccccccccccc,
+        0x1999999999999,
+        0x3333333333333,
+        0x6666666666666,
+    ]),
+    z: ONE,
+    t: Fe([
+        0x68ab3a5b7dda3,
+        0x00eea2a5eadbb,
+        0x2af8df483c27e,
+        0x332b375274732,
+        0x67875f0fd78b7,
+    ]),
+};
+
+/// The group order `L = 2^252 + 27742317777372353535851937790883648493` in
+/// little-endian 64-bit limbs.
+const L: [u64; 4] = [0x5812631a5cf5d3ed, 0x14def9dea2f79cd6, 0, 1 << 60];
+
+impl Fe {
+    /// Propagate carries so limbs 1..5 fit in 51 bits.
+    fn carry(mut l: [u64; 5]) -> Fe {
+        for i in 0..4 {
+            l[i + 1] += l[i] >> 51;
+            l[i] &= MASK;
+        }
+        l[0] += 19 * (l[4] >> 51);
+        l[4] &= MASK;
+        Fe(l)
+    }
+
+    fn add(self, rhs: Fe) -> Fe {
+        Fe::carry(std::array::from_fn(|i| self.0[i] + rhs.0[i]))
+    }
+
+    fn sub(self, rhs: Fe) -> Fe {
+        // Add 4p first so no limb underflows.
+        let four_p = |i: usize| {
+            if i == 0 {
+                0x1F_FFFF_FFFF_FFB4
+            } else {
+                0x1F_FFFF_FFFF_FFFC
+            }
+        };
+        Fe::carry(std::array::from_fn(|i| self.0[i] + four_p(i) - rhs.0[i]))
+    }
+
+    fn neg(self) -> Fe {
+        ZERO.sub(self)
+    }
+
+    fn mul(self, rhs: Fe) -> Fe {
+        let a = self.0.map(u128::from);
+        let b = rhs.0.map(u128::from);
+        let c = b.map(|x| x * 19);
+        let mut r = [
+            a[0] * b[0] + a[1] * c[4] + a[2] * c[3] + a[3] * c[2] + a[4] * c[1],
+            a[0] * b[1] + a[1] * b[0] + a[2] * c[4] + a[3] * c[3] + a[4] * c[2],
+            a[0] * b[2] + a[1] * b[1] + a[2] * b[0] + a[3] * c[4] + a[4] * c[3],
+            a[0] * b[3] + a[1] * b[2] + a[2] * b[1] + a[3] * b[0] + a[4] * c[4],
+            a[0] * b[4] + a[1] * b[3] + a[2] * b[2] + a[3] * b[1] + a[4] * b[0],
+        ];
+        for i in 0..4 {
+            r[i + 1] += r[i] >> 51;
+            r[i] &= MASK as u128;
+        }
+        r[0] += 19 * (r[4] >> 51);
+        r[4] &= MASK as u128;
+        Fe::carry(r.map(|x| x as u64))
+    }
+
+    fn square(self) -> Fe {
+        self.mul(self)
+    }
+
+    /// `self` raised to the little-endian exponent `e`.
+    fn pow(self, e: &[u8; 32]) -> Fe {
+        let mut r = ONE;
+        for bit in (0..256).rev() {
+            r = r.square();
+            if e[bit / 8] >> (bit % 8) & 1 == 1 {
+                r = r.mul(self);
+            }
+        }
+        r
+    }
+
+    /// Read 255 bits, ignoring the top bit of the last byte.
+    fn from_bytes(bytes: &[u8; 32]) -> Fe {
+        let mut l = [0u64; 5];
+        let (mut acc, mut bits, mut i) = (0u128, 0, 0);
+        for &b in bytes {
+            acc |= (b as u128) << bits;
+            bits += 8;
+            if bits >= 51 && i < 5 {
+                l[i] = acc as u64 & MASK;
+                acc >>= 51;
+                bits -= 51;
+                i += 1;
+            }
+        }
+        Fe(l)
+    }
+
+    /// The canonical little-endian encoding, fully reduced mod p.
+    fn to_bytes(self) -> [u8; 32] {
+        let mut l = Fe::carry(Fe::carry(self.0).0).0;
+        // The value is below 2p; subtract p once if adding 19 carries past
+        // bit 255.
+        let mut q = (l[0] + 19) >> 51;
+        for limb in &l[1..] {
+            q = (limb + q) >> 51;
+        }
+        l[0] += 19 * q;
+        for i in 0..4 {
+            l[i + 1] += l[i] >> 51;
+            l[i] &= MASK;
+        }
+        l[4] &= MASK;
+        let mut out = [0u8; 32];
+        let (mut acc, mut bits, mut k) = (0u128, 0, 0);
+        for limb in l {
+            acc |= (limb as u128) << bits;
+            bits += 51;
+            while bits >= 8 {
+                out[k] = acc as u8;
+                acc >>= 8;
+                bits -= 8;
+                k += 1;
+            }
+        }
+        out[31] = acc as u8;
+        out
+    }
+
+    fn is_negative(self) -> bool {
+        self.to_bytes()[0] & 1 == 1
+    }
+
+    fn equals(self, rhs: Fe) -> bool {
+        self.to_bytes() == rhs.to_bytes()
+    }
+}
+
+/// A point in extended coordinates: `x = X/Z`, `y = Y/Z`, `xy = T/Z`.
+#[derive(Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+const IDENTITY: Point = Point {
+    x: ZERO,
+    y: ONE,
+    z: ONE,
+    t: ZERO,
+};
+
+impl Point {
+    /// Decode a 32-byte point encoding (RFC 8032 5.1.3), rejecting a
+    /// non-canonical `y` or a point not on the curve.
+    fn decompress(bytes: &[u8; 32]) -> Option<Point> {
+        let y = Fe::from_bytes(bytes);
+        let mut canonical = *bytes;
+        canonical[31] &= 0x7F;
+        if y.to_bytes() != canonical {
+            return None;
+        }
+        let sign = bytes[31] >> 7 == 1;
+        let yy = y.square();
+        let u = yy.sub(ONE);
+        let v = D.mul(yy).add(ONE);
+        let v3 = v.square().mul(v);
+        let v7 = v3.square().mul(v);
+        let mut x = u.mul(v3).mul(u.mul(v7).pow(&P58));
+        let vxx = v.mul(x.square());
+        if !vxx.equals(u) {
+            if !vxx.equals(u.neg()) {
+                return None;
+            }
+            x = x.mul(SQRT_M1);
+        }
+        if sign && x.equals(ZERO) {
+            return None;
+        }
+        if x.is_negative() != sign {
+            x = x.neg();
+        }
+        Some(Point {
+            x,
+            y,
+            z: ONE,
+            t: x.mul(y),
+        })
+    }
+
+    fn compress(&self) -> [u8; 32] {
+        let zinv = self.z.pow(&P2);
+        let mut bytes = self.y.mul(zinv).to_bytes();
+        bytes[31] |= (self.x.mul(zinv).is_negative() as u8) << 7;
+        bytes
+    }
+
+    fn neg(&self) -> Point {
+        Point {
+            x: self.x.neg(),
+            t: self.t.neg(),
+            ..*self
+        }
+    }
+
+    /// Complete addition for `a = -1` (RFC 8032 5.1.4); also doubles.
+    fn add(&self, o: &Point) -> Point {
+        let a = self.y.sub(self.x).mul(o.y.sub(o.x));
+        let b = self.y.add(self.x).mul(o.y.add(o.x));
+        let c = self.t.mul(D2).mul(o.t);
+        let d = self.z.add(self.z).mul(o.z);
+        let (e, f, g, h) = (b.sub(a), d.sub(c), d.add(c), b.add(a));
+        Point {
+            x: e.mul(f),
+            y: g.mul(h),
+            z: f.mul(g),
+            t: e.mul(h),
+        }
+    }
+}
+
+/// `[a]P + [b]Q` for little-endian scalars below 2^253.
+fn double_mul(a: &[u8; 32], p: &Point, b: &[u8; 32], q: &Point) -> Point {
+    let mut r = IDENTITY;
+    for bit in (0..253).rev() {
+        r = r.add(&r);
+        if a[bit / 8] >> (bit % 8) & 1 == 1 {
+            r = r.add(p);
+        }
+        if b[bit / 8] >> (bit % 8) & 1 == 1 {
+            r = r.add(q);
+        }
+    }
+    r
+}
+
+fn less_than_l(x: &[u64; 4]) -> bool {
+    for i in (0..4).rev() {
+        if x[i] != L[i] {
+            return x[i] < L[i];
+        }
+    }
+    false
+}
+
+fn limbs(bytes: &[u8]) -> [u64; 4] {
+    std::array::from_fn(|i| u64::from_le_bytes(bytes[i * 8..][..8].try_into().unwrap()))
+}
+
+/// Reduce a 512-bit little-endian number mod `L`, one bit at a time.
+fn reduce(wide: &[u8; 64]) -> [u8; 32] {
+    let mut r = [0u64; 4];
+    for bit in (0..512).rev() {
+        let mut carry = (wide[bit / 8] >> (bit % 8) & 1) as u64;
+        for limb in r.iter_mut() {
+            let top = *limb >> 63;
+            *limb = *limb << 1 | carry;
+            carry = top;
+        }
+        if !less_than_l(&r) {
+            let mut borrow = 0;
+            for (limb, l) in r.iter_mut().zip(L) {
+                let (d, b1) = limb.overflowing_sub(l);
+                let (d, b2) = d.overflowing_sub(borrow);
+                *limb = d;
+                borrow = (b1 || b2) as u64;
+            }
+        }
+    }
+    let mut out = [0u8; 32];
+    for (bytes, limb) in out.chunks_exact_mut(8).zip(r) {
+        bytes.copy_from_slice(&limb.to_le_bytes());
+    }
+    out
+}
+
+const K512: [u64; 80] = [
+    0x428a2f98d728ae22,
+    0x7137449123ef65cd,
+    0xb5c0fbcfec4d3b2f,
+    0xe9b5dba58189dbbc,
+    0x3956c25bf348b538,
+    0x59f111f1b605d019,
+    0x923f82a4af194f9b,
+    0xab1c5ed5da6d8118,
+    0xd807aa98a3030242,
+    0x12835b0145706fbe,
+    0x243185be4ee4b28c,
+    0x550c7dc3d5ffb4e2,
+    0x72be5d74f27b896f,
+    0x80deb1fe3b1696b1,
+    0x9bdc06a725c71235,
+    0xc19bf174cf692694,
+    0xe49b69c19ef14ad2,
+    0xefbe4786384f25e3,
+    0x0fc19dc68b8cd5b5,
+    0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275,
+    0x4a7484aa6ea6e483,
+    0x5cb0a9dcbd41fbd4,
+    0x76f988da831153b5,
+    0x983e5152ee66dfab,
+    0xa831c66d2db43210,
+    0xb00327c898fb213f,
+    0xbf597fc7beef0ee4,
+    0xc6e00bf33da88fc2,
+    0xd5a79147930aa725,
+    0x06ca6351e003826f,
+    0x142929670a0e6e70,
+    0x27b70a8546d22ffc,
+    0x2e1b21385c26c926,
+    0x4d2c6dfc5ac42aed,
+    0x53380d139d95b3df,
+    0x650a73548baf63de,
+    0x766a0abb3c77b2a8,
+    0x81c2c92e47edaee6,
+    0x92722c851482353b,
+    0xa2bfe8a14cf10364,
+    0xa81a664bbc423001,
+    0xc24b8b70d0f89791,
+    0xc76c51a30654be30,
+    0xd192e819d6ef5218,
+    0xd69906245565a910,
+    0xf40e35855771202a,
+    0x106aa07032bbd1b8,
+    0x19a4c116b8d2d0c8,
+    0x1e376c085141ab53,
+    0x2748774cdf8eeb99,
+    0x34b0bcb5e19b48a8,
+    0x391c0cb3c5c95a63,
+    0x4ed8aa4ae3418acb,
+    0x5b9cca4f7763e373,
+    0x682e6ff3d6b2b8a3,
+    0x748f82ee5defb2fc,
+    0x78a5636f43172f60,
+    0x84c87814a1f0ab72,
+    0x8cc702081a6439ec,
+    0x90befffa23631e28,
+    0xa4506cebde82bde9,
+    0xbef9a3f7b2c67915,
+    0xc67178f2e372532b,
+    0xca273eceea26619c,
+    0xd186b8c721c0c207,
+    0xeada7dd6cde0eb1e,
+    0xf57d4f7fee6ed178,
+    0x06f067aa72176fba,
+    0x0a637dc5a2c898a6,
+    0x113f9804bef90dae,
+    0x1b710b35131c471b,
+    0x28db77f523047d84,
+    0x32caab7b40c72493,
+    0x3c9ebe0a15c9bebc,
+    0x431d67c49c100d4c,
+    0x4cc5d4becb3e42b6,
+    0x597f299cfc657e2a,
+    0x5fcb6fab3ad6faec,
+    0x6c44198c4a475817,
+];
+
+fn compress512(state: &mut [u64; 8], block: &[u8]) {
+    let mut w = [0u64; 80];
+    for (word, bytes) in w.iter_mut().zip(block.chunks_exact(8)) {
+        *word = u64::from_be_bytes(bytes.try_into().unwrap());
+    }
+    for i in 16..80 {
+        let s0 = w[i - 15].rotate_right(1) ^ w[i - 15].rotate_right(8) ^ (w[i - 15] >> 7);
+        let s1 = w[i - 2].rotate_right(19) ^ w[i - 2].rotate_right(61) ^ (w[i - 2] >> 6);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for i in 0..80 {
+        let s1 = e.rotate_right(14) ^ e.rotate_right(18) ^ e.rotate_right(41);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(K512[i])
+            .wrapping_add(w[i]);
+        let s0 = a.rotate_right(28) ^ a.rotate_right(34) ^ a.rotate_right(39);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        (h, g, f, e, d, c, b, a) = (g, f, e, d.wrapping_add(t1), c, b, a, t1.wrapping_add(t2));
+    }
+    for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *s = s.wrapping_add(v);
+    }
+}
+
+/// SHA-512 of the concatenation of `parts`.
+fn sha512(parts: &[&[u8]]) -> [u8; 64] {
+    let mut state: [u64; 8] = [
+        0x6a09e667f3bcc908,
+        0xbb67ae8584caa73b,
+        0x3c6ef372fe94f82b,
+        0xa54ff53a5f1d36f1,
+        0x510e527fade682d1,
+        0x9b05688c2b3e6c1f,
+        0x1f83d9abfb41bd6b,
+        0x5be0cd19137e2179,
+    ];
+    let total: usize = parts.iter().map(|p| p.len()).sum();
+    let mut block = [0u8; 128];
+    let mut filled = 0;
+    for &byte in parts.iter().flat_map(|p| p.iter()) {
+        block[filled] = byte;
+        filled += 1;
+        if filled == 128 {
+            compress512(&mut state, &block);
+            filled = 0;
+        }
+    }
+    block[filled] = 0x80;
+    block[filled + 1..].fill(0);
+    if filled >= 112 {
+        compress512(&mut state, &block);
+        block.fill(0);
+    }
+    block[112..].copy_from_slice(&(total as u128 * 8).to_be_bytes());
+    compress512(&mut state, &block);
+    let mut out = [0u8; 64];
+    for (bytes, s) in out.chunks_exact_mut(8).zip(state) {
+        bytes.copy_from_slice(&s.to_be_bytes());
+    }
+    out
+}
+
+/// Verify one signature over `message` with a 32-byte public key.
+fn verify(message: &[u8], sig: &[u8; 64], key: &[u8; 32]) -> bool {
+    let Some(a) = Point::decompress(key) else {
+        return false;
+    };
+    let (r, s) = sig.split_at(32);
+    if !less_than_l(&limbs(s)) {
+        return false;
+    }
+    let k = reduce(&sha512(&[r, key, message]));
+    let check = double_mul(s.try_into().unwrap(), &BASE, &k, &a.neg());
+    check.compress() == r
+}
+
+/// Verify a batch of Ed25519 signatures. Row `i` is the message
+/// `text[offsets[i]..offsets[i + 1]]`, the 64-byte signature at
+/// `sigs[64 * i..]` and the 32-byte public key at `keys[32 * i..]`; pass a
+/// single 32-byte key to check every row against it. Sets bit `i % 8` of
+/// byte `i / 8` when row `i`'s signature is valid, so a malformed key or
+/// signature simply leaves its bit clear.
+///
+/// Returns bytes written (`ceil(rows / 8)`), or `-1` for signature or key
+/// arrays of the wrong length, a bad span or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn ed25519_verify_batch(
+    text_ptr: *const u8,
+    text_len: usize,
+    offsets_ptr: *const u32,
+    offsets_len: usize,
+    sigs_ptr: *const u8,
+    sigs_len: usize,
+    keys_ptr: *const u8,
+    keys_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let rows = offsets_len.saturating_sub(1);
+    let bytes = rows.div_ceil(8);
+    if sigs_len != rows * 64 || (keys_len != rows * 32 && keys_len != 32) || out_len < bytes {
+        return -1;
+    }
+    let text = ffi::slice(text_ptr, text_len);
+    let offsets = ffi::slice(offsets_ptr, offsets_len);
+    let sigs = ffi::slice(sigs_ptr, sigs_len);
+    let keys = ffi::slice(keys_ptr, keys_len);
+    let out = ffi::slice_mut(out_ptr, bytes);
+    out.fill(0);
+    for (i, message) in spans(text, offsets).enumerate() {
+        let Some(message) = message else {
+            return -1;
+        };
+        let sig = sigs[i * 64..][..64].try_into().unwrap();
+        let key = keys[(i * 32) % keys_len..][..32].try_into().unwrap();
+        if verify(message, sig, key) {
+            out[i / 8] |= 1 << (i % 8);
+        }
+    }
+    bytes as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unhex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// RFC 8032 section 7.1, tests 1 to 3: `(public key, message, signature)`.
+    const VECTORS: [(&str, &str, &str); 3] = [
+        (
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            "",
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        ),
+        (
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            "72",
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        ),
+        (
+            "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+            "af82",
+            "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+        ),
+    ];
+
+    struct Batch {
+        text: Vec<u8>,
+        offsets: Vec<u32>,
+        sigs: Vec<u8>,
+        keys: Vec<u8>,
+    }
+
+    impl Batch {
+        fn new(rows: &[(Vec<u8>, Vec<u8>, Vec<u8>)]) -> Batch {
+            let mut batch = Batch {
+                text: Vec::new(),
+                offsets: vec![0],
+                sigs: Vec::new(),
+                keys: Vec::new(),
+            };
+            for (key, message, sig) in rows {
+                batch.text.extend_from_slice(message);
+                batch.offsets.push(batch.text.len() as u32);
+                batch.sigs.extend_from_slice(sig);
+                batch.keys.extend_from_slice(key);
+            }
+            batch
+        }
+
+        fn verify(&self, keys: &[u8]) -> Option<Vec<u8>> {
+            let mut out = vec![0u8; self.offsets.len().div_ceil(8)];
+            let written = unsafe {
+                ed25519_verify_batch(
+                    self.text.as_ptr(),
+                    self.text.len(),
+                    self.offsets.as_ptr(),
+                    self.offsets.len(),
+                    self.sigs.as_ptr(),
+                    self.sigs.len(),
+                    keys.as_ptr(),
+                    keys.len(),
+                    out.as_mut_ptr(),
+                    out.len(),
+                )
+            };
+            out.truncate(usize::try_from(written).ok()?);
+            Some(out)
+        }
+    }
+
+    fn vectors() -> Vec<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+        VECTORS
+            .iter()
+            .map(|(k, m, s)| (unhex(k), unhex(m), unhex(s)))
+            .collect()
+    }
+
+    #[test]
+    fn sha512_vectors() {
+        let hex = |h: [u8; 64]| h.iter().map(|b| format!("{b:02x}")).collect::<String>();
+        assert_eq!(
+            hex(sha512(&[b"a", b"bc"])),
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        );
+        // 112 bytes: the length no longer fits in the first padded block.
+        let long = b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmno\
+            ijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu";
+        assert_eq!(
+            hex(sha512(&[long])),
+            "8e959b75dae313da8cf4f72814fc143f8f7779c6eb9f7fa17299aeadb6889018\
+             501d289e4900f7e4331b99dec4b5433ac7d329eeb6dd26545e96e55b874be909"
+        );
+    }
+
+    #[test]
+    fn verifies_rfc_8032_vectors() {
+        let rows = vectors();
+        let batch = Batch::new(&rows);
+        assert_eq!(batch.verify(&batch.keys).unwrap(), [0b111]);
+        // Flip one bit of the message, signature and key in turn.
+        for part in 0..3 {
+            let mut rows = rows.clone();
+            match part {
+                0 => rows[2].1[0] ^= 1,
+                1 => rows[2].2[40] ^= 1,
+                _ => rows[2].0[5] ^= 1,
+            }
+            let batch = Batch::new(&rows);
+            assert_eq!(batch.verify(&batch.keys).unwrap(), [0b011], "part {part}");
+        }
+        // A signature checked against another row's key fails.
+        let mut swapped = rows.clone();
+        swapped.swap(0, 1);
+        let keys = [rows[0].0.clone(), rows[1].0.clone(), rows[2].0.clone()].concat();
+        assert_eq!(Batch::new(&swapped).verify(&keys).unwrap(), [0b100]);
+    }
+
+    #[test]
+    fn shared_key_and_malleability() {
+        let (key, message, sig) = vectors().swap_remove(1);
+        let mut rows = vec![(key.clone(), message.clone(), sig.clone()); 9];
+        // S + L verifies mathematically but is not canonical.
+        let mut s = limbs(&sig[32..]);
+        let mut carry = false;
+        for (limb, l) in s.iter_mut().zip(L) {
+            let (sum, c1) = limb.overflowing_add(l);
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = c1 || c2;
+        }
+        for (bytes, limb) in rows[4].2[32..].chunks_exact_mut(8).zip(s) {
+            bytes.copy_from_slice(&limb.to_le_bytes());
+        }
+        rows[8].1.push(0);
+        let batch = Batch::new(&rows);
+        assert_eq!(batch.verify(&key).unwrap(), [0b1110_1111, 0]);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let rows = vectors();
+        let batch = Batch::new(&rows);
+        assert!(batch.verify(&batch.keys[..64]).is_none());
+        let mut short = Batch::new(&rows);
+        short.sigs.pop();
+        assert!(short.verify(&batch.keys).is_none());
+        let mut reversed = Batch::new(&rows);
+        reversed.offsets[1] = 5;
+        assert!(reversed.verify(&batch.keys).is_none());
+        let mut out = [0u8; 1];
+        let written = unsafe {
+            ed25519_verify_batch(
+                batch.text.as_ptr(),
+                batch.text.len(),
+                batch.offsets.as_ptr(),
+                batch.offsets.len(),
+                batch.sigs.as_ptr(),
+                batch.sigs.len(),
+                batch.keys.as_ptr(),
+                batch.keys.len(),
+                out.as_mut_ptr(),
+                0,
+            )
+        };
+        assert_eq!(written, -1, "short output");
+        assert!(Batch::new(&[]).verify(&[0; 32]).unwrap().is_empty());
+    }
+}
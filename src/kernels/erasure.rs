@@ -0,0 +1,563 @@
+//! GF(2^8) erasure coding and secret sharing: systematic Reed–Solomon and
+//! Shamir's scheme.
+//!
+//! Both work byte-wise over GF(2^8) with the polynomial `0x11d`. The inner
+//! loop multiplies a whole region by a constant using split-nibble tables:
+//! `c * b = lo[b & 15] ^ hi[b >> 4]`, which maps onto one `i8x16_swizzle`
+//! per nibble under `simd128` and two lookups per byte otherwise.
+//!
+//! Reed–Solomon parity rows form a Cauchy matrix, so any `k` of the `k + m`
+//! shards recover the data. Shards are `data_len / k` bytes each and travel
+//! as one buffer, shard `i` at `i * shard_len`.
+
+use crate::ffi;
+
+const fn build_tables() -> ([u8; 256], [u8; 256]) {
+    let mut exp = [0u8; 256];
+    let mut log = [0u8; 256];
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= 0x11d;
+        }
+        i += 1;
+    }
+    exp[255] = exp[0];
+    (exp, log)
+}
+
+const TABLES: ([u8; 256], [u8; 256]) = build_tables();
+const EXP: [u8; 256] = TABLES.0;
+const LOG: [u8; 256] = TABLES.1;
+
+fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    EXP[(LOG[a as usize] as usize + LOG[b as usize] as usize) % 255]
+}
+
+/// Multiplicative inverse of a non-zero element.
+fn inv(a: u8) -> u8 {
+    EXP[(255 - LOG[a as usize] as usize) % 255]
+}
+
+/// Products of `c` with every low nibble and every high nibble.
+fn nibble_tables(c: u8) -> ([u8; 16], [u8; 16]) {
+    (
+        std::array::from_fn(|x| mul(c, x as u8)),
+        std::array::from_fn(|x| mul(c, (x as u8) << 4)),
+    )
+}
+
+/// `dst ^= c * src`, 16 bytes at a time with the final partial block
+/// zero-padded.
+fn mul_add_region(c: u8, src: &[u8], dst: &mut [u8]) {
+    if c == 0 {
+        return;
+    }
+    let (lo, hi) = nibble_tables(c);
+    for (s, d) in src.chunks(16).zip(dst.chunks_mut(16)) {
+        let mut block = [0u8; 16];
+        block[..s.len()].copy_from_slice(s);
+        let product = mul_block(block, &lo, &hi);
+        for (d, p) in d.iter_mut().zip(product) {
+            *d ^= p;
+        }
+    }
+}
+
+#[inline(always)]
+fn mul_block(block: [u8; 16], lo: &[u8; 16], hi: &[u8; 16]) -> [u8; 16] {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let v = v128_load(block.as_ptr() as *const v128);
+        let lo_t = v128_load(lo.as_ptr() as *const v128);
+        let hi_t = v128_load(hi.as_ptr() as *const v128);
+        let mask = u8x16_splat(0x0f);
+        let product = v128_xor(
+            i8x16_swizzle(lo_t, v128_and(v, mask)),
+            i8x16_swizzle(hi_t, u8x16_shr(v, 4)),
+        );
+        let mut out = [0u8; 16];
+        v128_store(out.as_mut_ptr() as *mut v128, product);
+        out
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        block.map(|b| lo[(b & 15) as usize] ^ hi[(b >> 4) as usize])
+    }
+}
+
+/// Row `i` of the `(k + m) x k` encoding matrix: the identity for data
+/// shards, then Cauchy rows `1 / (x_i ^ y_j)` with `x_i = i` and `y_j = j`
+/// drawn from disjoint ranges.
+fn encoding_row(i: usize, k: usize) -> Vec<u8> {
+    (0..k)
+        .map(|j| {
+            if i < k {
+                u8::from(i == j)
+            } else {
+                inv(i as u8 ^ j as u8)
+            }
+        })
+        .collect()
+}
+
+/// Invert a `k x k` matrix in place by Gauss–Jordan elimination, returning
+/// `false` if it is singular.
+fn invert(matrix: &mut [Vec<u8>]) -> bool {
+    let k = matrix.len();
+    let mut inverse: Vec<Vec<u8>> = (0..k)
+        .map(|i| (0..k).map(|j| u8::from(i == j)).collect())
+        .collect();
+    for col in 0..k {
+        let Some(pivot) = (col..k).find(|&r| matrix[r][col] != 0) else {
+            return false;
+        };
+        matrix.swap(col, pivot);
+        inverse.swap(col, pivot);
+        let scale = inv(matrix[col][col]);
+        for j in 0..k {
+            matrix[col][j] = mul(matrix[col][j], scale);
+            inverse[col][j] = mul(inverse[col][j], scale);
+        }
+        for r in 0..k {
+            let factor = matrix[r][col];
+            if r == col || factor == 0 {
+                continue;
+            }
+            for j in 0..k {
+                matrix[r][j] ^= mul(factor, matrix[col][j]);
+                inverse[r][j] ^= mul(factor, inverse[col][j]);
+            }
+        }
+    }
+    matrix.clone_from_slice(&inverse);
+    true
+}
+
+/// Compute `m` parity shards for the `k` data shards in `data`.
+///
+/// `data_len` must be a multiple of `k`, and `k + m` at most 256. Writes
+/// `m * data_len / k` bytes, parity shard `i` at `i * shard_len`. Returns
+/// bytes written, or `-1` for bad arguments or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn rs_encode(
+    data_ptr: *const u8,
+    data_len: usize,
+    k: u32,
+    m: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let (k, m) = (k as usize, m as usize);
+    if k == 0 || k + m > 256 || !data_len.is_multiple_of(k) {
+        return -1;
+    }
+    let shard = data_len / k;
+    let Some(needed) = shard.checked_mul(m) else {
+        return -1;
+    };
+    if out_len < needed {
+        return -1;
+    }
+    if ffi::aliased(data_ptr, data_len, out_ptr, needed) {
+        return ffi::ALIAS_ERROR;
+    }
+    let data = ffi::slice(data_ptr, data_len);
+    let out = ffi::slice_mut(out_ptr, needed);
+    out.fill(0);
+    for (p, parity) in out.chunks_exact_mut(shard.max(1)).enumerate() {
+        let row = encoding_row(k + p, k);
+        for (&c, src) in row.iter().zip(data.chunks_exact(shard)) {
+            mul_add_region(c, src, parity);
+        }
+    }
+    needed as isize
+}
+
+/// Recover the `k` data shards from any `k` of the `k + m` shards.
+///
+/// `shards` holds all `k + m` shards back to back (missing ones may contain
+/// anything) and `present` one byte per shard, non-zero where the shard is
+/// intact. Writes `k * shard_len` bytes of data. Returns bytes written, or
+/// `-1` for bad arguments, fewer than `k` present shards, or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn rs_reconstruct(
+    shards_ptr: *const u8,
+    shards_len: usize,
+    present_ptr: *const u8,
+    present_len: usize,
+    k: u32,
+    m: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let (k, m) = (k as usize, m as usize);
+    let total = k + m;
+    if k == 0 || total > 256 || present_len != total || !shards_len.is_multiple_of(total) {
+        return -1;
+    }
+    let shard = shards_len / total;
+    let needed = shard * k;
+    if out_len < needed {
+        return -1;
+    }
+    if ffi::aliased(shards_ptr, shards_len, out_ptr, needed) {
+        return ffi::ALIAS_ERROR;
+    }
+    let present = ffi::slice(present_ptr, present_len);
+    let chosen: Vec<usize> = (0..total).filter(|&i| present[i] != 0).take(k).collect();
+    if chosen.len() < k {
+        return -1;
+    }
+    let mut matrix: Vec<Vec<u8>> = chosen.iter().map(|&i| encoding_row(i, k)).collect();
+    if !invert(&mut matrix) {
+        return -1;
+    }
+    let shards = ffi::slice(shards_ptr, shards_len);
+    let out = ffi::slice_mut(out_ptr, needed);
+    for (j, dst) in out.chunks_exact_mut(shard.max(1)).enumerate() {
+        if present[j] != 0 {
+            dst.copy_from_slice(&shards[j * shard..(j + 1) * shard]);
+            continue;
+        }
+        dst.fill(0);
+        for (&c, &i) in matrix[j].iter().zip(&chosen) {
+            mul_add_region(c, &shards[i * shard..(i + 1) * shard], dst);
+        }
+    }
+    needed as isize
+}
+
+/// Split a secret into `n` Shamir shares, any `k` of which recover it.
+///
+/// `random` supplies the `k - 1` random polynomial coefficients for every
+/// secret byte (`(k - 1) * secret_len` bytes, coefficient `t` at
+/// `t * secret_len`); fill it from `crypto.getRandomValues` and discard it
+/// afterwards. Share `i` is `1 + secret_len` bytes: its x coordinate `i + 1`
+/// followed by the y bytes. Returns bytes written (`n * (1 + secret_len)`),
+/// or `-1` for bad arguments (`k` must be in `1..=n`, `n` at most 255) or a
+/// short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn shamir_split(
+    secret_ptr: *const u8,
+    secret_len: usize,
+    random_ptr: *const u8,
+    random_len: usize,
+    k: u32,
+    n: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let (k, n) = (k as usize, n as usize);
+    if k == 0 || k > n || n > 255 || Some(random_len) != (k - 1).checked_mul(secret_len) {
+        return -1;
+    }
+    let share = secret_len + 1;
+    let needed = share * n;
+    if out_len < needed {
+        return -1;
+    }
+    if ffi::aliased(secret_ptr, secret_len, out_ptr, needed)
+        || ffi::aliased(random_ptr, random_len, out_ptr, needed)
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    let secret = ffi::slice(secret_ptr, secret_len);
+    let random = ffi::slice(random_ptr, random_len);
+    let out = ffi::slice_mut(out_ptr, needed);
+    for (i, dst) in out.chunks_exact_mut(share).enumerate() {
+        let x = i as u8 + 1;
+        dst[0] = x;
+        let y = &mut dst[1..];
+        // Horner's rule from the highest coefficient down to the secret.
+        y.fill(0);
+        for coeff in random.chunks_exact(secret_len.max(1)).rev() {
+            mul_region(x, y);
+            xor_region(coeff, y);
+        }
+        mul_region(x, y);
+        xor_region(secret, y);
+    }
+    needed as isize
+}
+
+/// Recover a secret from `count` Shamir shares of equal length laid out back
+/// to back, as written by `shamir_split`. Writes `shares_len / count - 1`
+/// bytes. Returns bytes written, or `-1` for bad arguments (including a zero
+/// or repeated x coordinate) or a short output. Passing fewer shares than
+/// the split threshold yields garbage rather than an error.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn shamir_combine(
+    shares_ptr: *const u8,
+    shares_len: usize,
+    count: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let count = count as usize;
+    if count == 0 || !shares_len.is_multiple_of(count) || shares_len / count == 0 {
+        return -1;
+    }
+    let share = shares_len / count;
+    let needed = share - 1;
+    if out_len < needed {
+        return -1;
+    }
+    if ffi::aliased(shares_ptr, shares_len, out_ptr, needed) {
+        return ffi::ALIAS_ERROR;
+    }
+    let shares = ffi::slice(shares_ptr, shares_len);
+    let xs: Vec<u8> = shares.chunks_exact(share).map(|s| s[0]).collect();
+    let mut seen = [false; 256];
+    for &x in &xs {
+        if x == 0 || std::mem::replace(&mut seen[x as usize], true) {
+            return -1;
+        }
+    }
+    let out = ffi::slice_mut(out_ptr, needed);
+    out.fill(0);
+    for (i, s) in shares.chunks_exact(share).enumerate() {
+        // Lagrange basis polynomial for share i, evaluated at zero.
+        let basis = xs
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .fold(1, |acc, (_, &xj)| mul(acc, mul(xj, inv(xj ^ xs[i]))));
+        mul_add_region(basis, &s[1..], out);
+    }
+    needed as isize
+}
+
+/// `buf = c * buf`.
+fn mul_region(c: u8, buf: &mut [u8]) {
+    let (lo, hi) = nibble_tables(c);
+    for chunk in buf.chunks_mut(16) {
+        let mut block = [0u8; 16];
+        block[..chunk.len()].copy_from_slice(chunk);
+        let len = chunk.len();
+        chunk.copy_from_slice(&mul_block(block, &lo, &hi)[..len]);
+    }
+}
+
+fn xor_region(src: &[u8], dst: &mut [u8]) {
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d ^= s;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic filler bytes for test buffers.
+    fn bytes(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(2654435761).wrapping_add(1);
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn field_arithmetic() {
+        assert_eq!(mul(2, 0x80), 0x1d);
+        for a in 1..=255u8 {
+            assert_eq!(mul(a, inv(a)), 1);
+        }
+        let src = bytes(37, 1);
+        let mut dst = vec![0u8; 37];
+        mul_add_region(0x53, &src, &mut dst);
+        let expected: Vec<u8> = src.iter().map(|&b| mul(0x53, b)).collect();
+        assert_eq!(dst, expected);
+        mul_region(0x53, &mut dst);
+        assert_eq!(
+            dst,
+            src.iter()
+                .map(|&b| mul(0x53, mul(0x53, b)))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    fn encode(data: &[u8], k: u32, m: u32) -> Vec<u8> {
+        let mut parity = vec![0u8; data.len() / k as usize * m as usize];
+        let n = unsafe {
+            rs_encode(
+                data.as_ptr(),
+                data.len(),
+                k,
+                m,
+                parity.as_mut_ptr(),
+                parity.len(),
+            )
+        };
+        assert_eq!(n, parity.len() as isize);
+        parity
+    }
+
+    fn reconstruct(shards: &[u8], present: &[u8], k: u32, m: u32) -> (isize, Vec<u8>) {
+        let mut out = vec![0u8; shards.len() / (k + m) as usize * k as usize];
+        let n = unsafe {
+            rs_reconstruct(
+                shards.as_ptr(),
+                shards.len(),
+                present.as_ptr(),
+                present.len(),
+                k,
+                m,
+                out.as_mut_ptr(),
+                out.len(),
+            )
+        };
+        (n, out)
+    }
+
+    #[test]
+    fn reed_solomon_recovers_any_k_shards() {
+        let (k, m, shard) = (4u32, 3u32, 21usize);
+        let data = bytes(k as usize * shard, 7);
+        let parity = encode(&data, k, m);
+        let total = (k + m) as usize;
+        for lost in 0u32..1 << total {
+            if lost.count_ones() > m {
+                continue;
+            }
+            let mut shards = [data.as_slice(), parity.as_slice()].concat();
+            let present: Vec<u8> = (0..total).map(|i| u8::from(lost >> i & 1 == 0)).collect();
+            for i in (0..total).filter(|&i| present[i] == 0) {
+                shards[i * shard..(i + 1) * shard].fill(0xee);
+            }
+            let (n, out) = reconstruct(&shards, &present, k, m);
+            assert_eq!(n, data.len() as isize, "lost mask {lost:b}");
+            assert_eq!(out, data, "lost mask {lost:b}");
+        }
+    }
+
+    #[test]
+    fn reed_solomon_rejects_bad_arguments() {
+        let data = bytes(12, 3);
+        let mut out = vec![0u8; 64];
+        unsafe {
+            assert_eq!(rs_encode(data.as_ptr(), 12, 5, 2, out.as_mut_ptr(), 64), -1);
+            assert_eq!(rs_encode(data.as_ptr(), 12, 0, 2, out.as_mut_ptr(), 64), -1);
+            assert_eq!(
+                rs_encode(data.as_ptr(), 12, 200, 57, out.as_mut_ptr(), 64),
+                -1
+            );
+            assert_eq!(rs_encode(data.as_ptr(), 12, 3, 2, out.as_mut_ptr(), 7), -1);
+        }
+        let parity = encode(&data, 3, 2);
+        let shards = [data.as_slice(), parity.as_slice()].concat();
+        assert_eq!(reconstruct(&shards, &[1, 0, 0, 1, 0], 3, 2).0, -1);
+        assert_eq!(reconstruct(&shards, &[1, 1, 1, 1], 3, 2).0, -1);
+    }
+
+    fn split(secret: &[u8], random: &[u8], k: u32, n: u32) -> Vec<u8> {
+        let mut out = vec![0u8; (secret.len() + 1) * n as usize];
+        let written = unsafe {
+            shamir_split(
+                secret.as_ptr(),
+                secret.len(),
+                random.as_ptr(),
+                random.len(),
+                k,
+                n,
+                out.as_mut_ptr(),
+                out.len(),
+            )
+        };
+        assert_eq!(written, out.len() as isize);
+        out
+    }
+
+    fn combine(shares: &[u8], count: u32) -> (isize, Vec<u8>) {
+        let mut out = vec![0u8; shares.len() / count as usize - 1];
+        let n = unsafe {
+            shamir_combine(
+                shares.as_ptr(),
+                shares.len(),
+                count,
+                out.as_mut_ptr(),
+                out.len(),
+            )
+        };
+        (n, out)
+    }
+
+    #[test]
+    fn shamir_recovers_from_any_threshold_subset() {
+        let secret = b"correct horse battery staple".to_vec();
+        let (k, n) = (3u32, 5u32);
+        let random = bytes(secret.len() * (k as usize - 1), 11);
+        let shares = split(&secret, &random, k, n);
+        let share = secret.len() + 1;
+        for mask in 0u32..1 << n {
+            if mask.count_ones() != k {
+                continue;
+            }
+            let subset: Vec<u8> = shares
+                .chunks_exact(share)
+                .enumerate()
+                .filter(|&(i, _)| mask >> i & 1 == 1)
+                .flat_map(|(_, s)| s.iter().copied())
+                .collect();
+            assert_eq!(combine(&subset, k), (secret.len() as isize, secret.clone()));
+        }
+        let (_, short) = combine(&shares[..share * 2], 2);
+        assert_ne!(short, secret);
+    }
+
+    #[test]
+    fn shamir_rejects_bad_arguments() {
+        let secret = [1u8, 2, 3];
+        assert_eq!(split(&secret, &[], 1, 2)[1..4], secret);
+        let mut out = [0u8; 16];
+        unsafe {
+            assert_eq!(
+                shamir_split(
+                    secret.as_ptr(),
+                    3,
+                    secret.as_ptr(),
+                    3,
+                    3,
+                    2,
+                    out.as_mut_ptr(),
+                    16
+                ),
+                -1
+            );
+            assert_eq!(
+                shamir_split(
+                    secret.as_ptr(),
+                    3,
+                    secret.as_ptr(),
+                    2,
+                    2,
+                    2,
+                    out.as_mut_ptr(),
+                    16
+                ),
+                -1
+            );
+        }
+        let shares = split(&secret, &[9, 9, 9], 2, 3);
+        let repeated = [&shares[..4], &shares[..4]].concat();
+        assert_eq!(combine(&repeated, 2).0, -1);
+    }
+}
@@ -0,0 +1,332 @@
+//! CSV and NDJSON exports of datasets.
+//!
+//! The text is formatted straight into the output buffer, so a page can
+//! offer a transformed dataset for download as a `Blob` over wasm memory
+//! without building a JS string per cell or one giant string for the file.
+//! Numbers are written in their shortest round-trip form, switching to
+//! exponent notation at the same magnitudes as JS `String(number)`.
+
+use std::fmt::{self, Write};
+
+use super::dataset::{Dataset, Values};
+use crate::error::{self, ErrorCode};
+use crate::{ffi, handles, packed};
+
+/// `dataset_to_csv` flag: write the column names as the first row.
+pub const EXPORT_HEADER: u32 = 1;
+/// `dataset_to_csv` flag: end rows in CRLF instead of LF.
+pub const EXPORT_CRLF: u32 = 2;
+
+/// Output that counts every byte but only stores those that fit, so the
+/// sizing pass and the writing pass share one formatter.
+struct Sink<'a> {
+    out: &'a mut [u8],
+    len: usize,
+}
+
+impl Sink<'_> {
+    fn put(&mut self, bytes: &[u8]) {
+        if let Some(dst) = self.out.get_mut(self.len..self.len + bytes.len()) {
+            dst.copy_from_slice(bytes);
+        }
+        self.len += bytes.len();
+    }
+
+    fn number(&mut self, v: f64) {
+        if v.is_infinite() {
+            self.put(if v > 0.0 { b"Infinity" } else { b"-Infinity" });
+        } else if v != 0.0 && !(1e-6..1e21).contains(&v.abs()) {
+            let _ = write!(self, "{v:e}");
+        } else {
+            let _ = write!(self, "{v}");
+        }
+    }
+
+    /// A CSV field, quoted (with quotes doubled) when it contains the
+    /// delimiter, a quote or a newline. An empty string is written as `""`
+    /// so it stays distinct from a null.
+    fn csv_field(&mut self, field: &[u8], delimiter: u8) {
+        let quote = field.is_empty()
+            || field
+                .iter()
+                .any(|&b| b == delimiter || matches!(b, b'"' | b'\r' | b'\n'));
+        if !quote {
+            return self.put(field);
+        }
+        self.put(b"\"");
+        for (i, part) in field.split(|&b| b == b'"').enumerate() {
+            if i > 0 {
+                self.put(b"\"\"");
+            }
+            self.put(part);
+        }
+        self.put(b"\"");
+    }
+
+    fn json_string(&mut self, s: &[u8]) {
+        self.put(b"\"");
+        let mut start = 0;
+        for (i, &b) in s.iter().enumerate() {
+            let escape: &[u8] = match b {
+                b'"' => b"\\\"",
+                b'\\' => b"\\\\",
+                b'\n' => b"\\n",
+                b'\r' => b"\\r",
+                b'\t' => b"\\t",
+                0..=0x1f => b"",
+                _ => continue,
+            };
+            self.put(&s[start..i]);
+            if escape.is_empty() {
+                let _ = write!(self, "\\u{b:04x}");
+            } else {
+                self.put(escape);
+            }
+            start = i + 1;
+        }
+        self.put(&s[start..]);
+        self.put(b"\"");
+    }
+}
+
+impl Write for Sink<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.put(s.as_bytes());
+        Ok(())
+    }
+}
+
+fn write_csv(sink: &mut Sink, dataset: &Dataset, delimiter: u8, flags: u32) {
+    let newline: &[u8] = if flags & EXPORT_CRLF != 0 {
+        b"\r\n"
+    } else {
+        b"\n"
+    };
+    if flags & EXPORT_HEADER != 0 {
+        for (i, column) in dataset.columns.iter().enumerate() {
+            if i > 0 {
+                sink.put(&[delimiter]);
+            }
+            sink.csv_field(column.name.as_bytes(), delimiter);
+        }
+        sink.put(newline);
+    }
+    for row in 0..dataset.rows {
+        for (i, column) in dataset.columns.iter().enumerate() {
+            if i > 0 {
+                sink.put(&[delimiter]);
+            }
+            if !column.is_valid(row) {
+                continue;
+            }
+            match &column.values {
+                Values::F64(values) => sink.number(values[row]),
+                Values::Utf8 { .. } => sink.csv_field(column.str(row), delimiter),
+            }
+        }
+        sink.put(newline);
+    }
+}
+
+fn write_ndjson(sink: &mut Sink, dataset: &Dataset) {
+    for row in 0..dataset.rows {
+        sink.put(b"{");
+        for (i, column) in dataset.columns.iter().enumerate() {
+            if i > 0 {
+                sink.put(b",");
+            }
+            sink.json_string(column.name.as_bytes());
+            sink.put(b":");
+            match &column.values {
+                _ if !column.is_valid(row) => sink.put(b"null"),
+                // JSON has no infinities.
+                Values::F64(values) if values[row].is_infinite() => sink.put(b"null"),
+                Values::F64(values) => sink.number(values[row]),
+                Values::Utf8 { .. } => sink.json_string(column.str(row)),
+            }
+        }
+        sink.put(b"}\n");
+    }
+}
+
+/// Run `write` over a sink on the output and apply the two-phase sizing
+/// convention to the byte count.
+unsafe fn finish(out_ptr: *mut u8, out_len: usize, write: impl FnOnce(&mut Sink)) -> isize {
+    let mut sink = Sink {
+        out: ffi::slice_mut(out_ptr, out_len),
+        len: 0,
+    };
+    write(&mut sink);
+    if out_len != 0 && out_len < sink.len {
+        return error::fail(ErrorCode::ShortOutput);
+    }
+    sink.len as isize
+}
+
+/// Write the dataset behind `handle` as delimited text, one row per line
+/// with columns in registration order. Missing values are empty fields;
+/// fields are quoted as in RFC 4180 when needed, and empty strings always.
+/// Pass `EXPORT_HEADER` in `flags` for a row of column names and
+/// `EXPORT_CRLF` for CRLF line ends.
+///
+/// With `out_len == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for an
+/// unknown handle, a delimiter that is not a single byte other than a quote
+/// or newline, or a short output (which may be partially written).
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn dataset_to_csv(
+    handle: u32,
+    delimiter: u32,
+    flags: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let delimiter = match u8::try_from(delimiter) {
+        Ok(b'"' | b'\r' | b'\n') | Err(_) => return error::fail(ErrorCode::InvalidArgument),
+        Ok(d) => d,
+    };
+    handles::with(handle, |dataset: &mut Dataset| {
+        finish(out_ptr, out_len, |sink| {
+            write_csv(sink, dataset, delimiter, flags)
+        })
+    })
+    .unwrap_or(-1)
+}
+
+/// Write the dataset behind `handle` as newline-delimited JSON: one object
+/// per row keyed by column name, with `null` for missing values and
+/// infinities.
+///
+/// With `out_len == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for an
+/// unknown handle or a short output (which may be partially written).
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn dataset_to_ndjson(handle: u32, out_ptr: *mut u8, out_len: usize) -> isize {
+    handles::with(handle, |dataset: &mut Dataset| {
+        finish(out_ptr, out_len, |sink| write_ndjson(sink, dataset))
+    })
+    .unwrap_or(-1)
+}
+
+/// `dataset_to_csv` in one call: returns the packed `(ptr, len)` of the text
+/// in the scratch buffer, or `0` on failure.
+#[no_mangle]
+pub extern "C" fn dataset_to_csv_packed(handle: u32, delimiter: u32, flags: u32) -> u64 {
+    packed::into_scratch(|out_ptr, out_len| unsafe {
+        dataset_to_csv(handle, delimiter, flags, out_ptr, out_len)
+    })
+}
+
+/// `dataset_to_ndjson` in one call: returns the packed `(ptr, len)` of the
+/// text in the scratch buffer, or `0` on failure.
+#[no_mangle]
+pub extern "C" fn dataset_to_ndjson_packed(handle: u32) -> u64 {
+    packed::into_scratch(|out_ptr, out_len| unsafe { dataset_to_ndjson(handle, out_ptr, out_len) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::dataset::{dataset_add_f64, dataset_add_utf8, dataset_new};
+    use super::*;
+
+    fn dataset() -> u32 {
+        let handle = dataset_new() as u32;
+        let values = [3.0, f64::NAN, 0.5, 1e21, -1e-7, f64::INFINITY];
+        let status = unsafe {
+            dataset_add_f64(
+                handle,
+                b"n".as_ptr(),
+                1,
+                values.as_ptr(),
+                values.len() * 8,
+                std::ptr::null(),
+                0,
+            )
+        };
+        assert_eq!(status, 0);
+        let text = "plaina,b\"q\"\n\ttab";
+        let offsets = [0u32, 5, 8, 8, 11, 12, 16];
+        let validity = [0b11_1011u8];
+        let name = "s, \"t\"";
+        let status = unsafe {
+            dataset_add_utf8(
+                handle,
+                name.as_ptr(),
+                name.len(),
+                text.as_ptr(),
+                text.len(),
+                offsets.as_ptr(),
+                offsets.len(),
+                validity.as_ptr(),
+                1,
+            )
+        };
+        assert_eq!(status, 1);
+        handle
+    }
+
+    fn run(export: impl Fn(*mut u8, usize) -> isize) -> String {
+        let needed = export(std::ptr::null_mut(), 0);
+        let mut out = vec![0u8; needed as usize];
+        assert_eq!(export(out.as_mut_ptr(), out.len()), needed);
+        assert_eq!(export(out.as_mut_ptr(), out.len() - 1), -1, "short output");
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn writes_csv() {
+        let handle = dataset();
+        let csv =
+            run(|ptr, len| unsafe { dataset_to_csv(handle, b',' as u32, EXPORT_HEADER, ptr, len) });
+        assert_eq!(
+            csv,
+            "n,\"s, \"\"t\"\"\"\n\
+             3,plain\n\
+             ,\"a,b\"\n\
+             0.5,\n\
+             1e21,\"\"\"q\"\"\"\n\
+             -1e-7,\"\n\"\n\
+             Infinity,\ttab\n"
+        );
+        let tsv =
+            run(|ptr, len| unsafe { dataset_to_csv(handle, b'\t' as u32, EXPORT_CRLF, ptr, len) });
+        assert!(tsv.starts_with("3\tplain\r\n\ta,b\r\n"), "{tsv:?}");
+        assert!(tsv.ends_with("Infinity\t\"\ttab\"\r\n"), "{tsv:?}");
+
+        let csv =
+            |delimiter| unsafe { dataset_to_csv(handle, delimiter, 0, std::ptr::null_mut(), 0) };
+        assert_eq!(csv(b'"' as u32), -1);
+        assert_eq!(csv(0x100), -1);
+        assert_eq!(handles::handle_drop(handle), 0);
+        assert_eq!(csv(b',' as u32), -1);
+    }
+
+    #[test]
+    fn writes_ndjson() {
+        let handle = dataset();
+        let ndjson = run(|ptr, len| unsafe { dataset_to_ndjson(handle, ptr, len) });
+        let lines: Vec<&str> = ndjson.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                r#"{"n":3,"s, \"t\"":"plain"}"#,
+                r#"{"n":null,"s, \"t\"":"a,b"}"#,
+                r#"{"n":0.5,"s, \"t\"":null}"#,
+                r#"{"n":1e21,"s, \"t\"":"\"q\""}"#,
+                r#"{"n":-1e-7,"s, \"t\"":"\n"}"#,
+                r#"{"n":null,"s, \"t\"":"\ttab"}"#,
+            ]
+        );
+        assert!(ndjson.ends_with("}\n"));
+
+        let packed = dataset_to_ndjson_packed(handle);
+        assert_eq!(packed >> packed::PACKED_PTR_BITS, ndjson.len() as u64);
+        let scratch = crate::scratch::scratch_acquire(ndjson.len());
+        let text = unsafe { std::slice::from_raw_parts(scratch, ndjson.len()) };
+        assert_eq!(text, ndjson.as_bytes());
+        assert_eq!(handles::handle_drop(handle), 0);
+        assert_eq!(dataset_to_ndjson_packed(handle), packed::PACKED_NONE);
+    }
+}
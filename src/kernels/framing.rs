@@ -0,0 +1,448 @@
+//! Streaming decoders for server-sent events and chunked transfer coding.
+//!
+//! Both decoders live behind handles and take a response body one network
+//! chunk at a time, so frames split across chunks need no JS-side
+//! buffering:
+//!
+//! - `sse_feed` parses an `text/event-stream` body (the HTML EventSource
+//!   format) and queues each dispatched event; `sse_read` drains the queue
+//!   as a string batch. Line breaks are found with the search kernel's
+//!   16-byte scan.
+//! - `chunked_feed` strips HTTP/1.1 `Transfer-Encoding: chunked` framing
+//!   and passes the payload through.
+
+use super::search::candidates;
+use crate::{ffi, handles};
+
+#[derive(Default)]
+struct SseDecoder {
+    /// The unfinished line at the end of the last chunk.
+    line: Vec<u8>,
+    /// The last chunk ended in `\r`, so a `\n` starting the next one
+    /// belongs to the same line break.
+    skip_lf: bool,
+    /// Whether the first line (which may carry a BOM) has been read.
+    started: bool,
+    event: Vec<u8>,
+    data: Vec<u8>,
+    /// Dispatched events as a string batch: type, then data, per event.
+    text: Vec<u8>,
+    offsets: Vec<u32>,
+}
+
+impl SseDecoder {
+    fn feed(&mut self, mut chunk: &[u8]) {
+        if self.skip_lf && !chunk.is_empty() {
+            self.skip_lf = false;
+            chunk = chunk.strip_prefix(b"\n").unwrap_or(chunk);
+        }
+        let mut start = 0;
+        let mut block_start = 0;
+        while block_start < chunk.len() {
+            let part = &chunk[block_start..(block_start + 16).min(chunk.len())];
+            let mut block = [0u8; 16];
+            block[..part.len()].copy_from_slice(part);
+            let mut mask = candidates(block, b'\n', b'\r') & (u16::MAX >> (16 - part.len()));
+            while mask != 0 {
+                let i = block_start + mask.trailing_zeros() as usize;
+                mask &= mask - 1;
+                if i < start {
+                    // The `\n` of a CRLF already consumed.
+                    continue;
+                }
+                let mut line = std::mem::take(&mut self.line);
+                line.extend_from_slice(&chunk[start..i]);
+                self.line(&line);
+                line.clear();
+                self.line = line;
+                start = i + 1;
+                if chunk[i] == b'\r' {
+                    match chunk.get(i + 1) {
+                        Some(b'\n') => start += 1,
+                        Some(_) => {}
+                        None => self.skip_lf = true,
+                    }
+                }
+            }
+            block_start += 16;
+        }
+        self.line.extend_from_slice(&chunk[start..]);
+    }
+
+    fn line(&mut self, mut line: &[u8]) {
+        if !std::mem::replace(&mut self.started, true) {
+            line = line.strip_prefix("\u{feff}".as_bytes()).unwrap_or(line);
+        }
+        if line.is_empty() {
+            self.dispatch();
+            return;
+        }
+        if line[0] == b':' {
+            return;
+        }
+        let (field, value) = match line.iter().position(|&b| b == b':') {
+            Some(colon) => {
+                let value = &line[colon + 1..];
+                (&line[..colon], value.strip_prefix(b" ").unwrap_or(value))
+            }
+            None => (line, &b""[..]),
+        };
+        match field {
+            b"data" => {
+                self.data.extend_from_slice(value);
+                self.data.push(b'\n');
+            }
+            b"event" => {
+                self.event.clear();
+                self.event.extend_from_slice(value);
+            }
+            // `id` and `retry` only matter to a reconnecting client.
+            _ => {}
+        }
+    }
+
+    fn dispatch(&mut self) {
+        if self.data.is_empty() {
+            self.event.clear();
+            return;
+        }
+        self.data.pop();
+        if self.offsets.is_empty() {
+            self.offsets.push(0);
+        }
+        let event: &[u8] = if self.event.is_empty() {
+            b"message"
+        } else {
+            &self.event
+        };
+        self.text.extend_from_slice(event);
+        self.offsets.push(self.text.len() as u32);
+        self.text.append(&mut self.data);
+        self.offsets.push(self.text.len() as u32);
+        self.event.clear();
+    }
+
+    fn queued(&self) -> usize {
+        self.offsets.len().saturating_sub(1) / 2
+    }
+}
+
+/// Create a server-sent events decoder.
+///
+/// Returns a handle for `sse_feed` and `sse_read`, released with
+/// `handle_drop`.
+#[no_mangle]
+pub extern "C" fn sse_new() -> isize {
+    handles::insert(SseDecoder::default()) as isize
+}
+
+/// Feed the next chunk of an event stream to the decoder behind `handle`.
+/// Chunks may split lines, and CRLF pairs, anywhere.
+///
+/// Returns how many complete events are queued for `sse_read`, or `-1` for
+/// an unknown handle.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn sse_feed(handle: u32, chunk_ptr: *const u8, chunk_len: usize) -> isize {
+    let chunk = ffi::slice(chunk_ptr, chunk_len);
+    handles::with(handle, |d: &mut SseDecoder| {
+        d.feed(chunk);
+        d.queued() as isize
+    })
+    .unwrap_or(-1)
+}
+
+/// Drain the events queued behind `handle` into a string batch: two strings
+/// per event, its type (`message` unless an `event:` line set one) and its
+/// data (the `data:` lines joined with `\n`). Event `i`'s type is
+/// `text[offsets[2i]..offsets[2i + 1]]` and its data runs on to
+/// `offsets[2i + 2]`, so `n` events need `2n + 1` offsets.
+///
+/// With `text_len == 0` and `offsets_len_bytes == 0` nothing is drained
+/// and the return value is the number of text bytes needed. Otherwise
+/// returns text bytes written, or `-1` for an unknown handle or a short
+/// output, which leaves the queue in place.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn sse_read(
+    handle: u32,
+    text_ptr: *mut u8,
+    text_len: usize,
+    offsets_ptr: *mut u32,
+    offsets_len_bytes: usize,
+) -> isize {
+    handles::with(handle, |d: &mut SseDecoder| {
+        if text_len == 0 && offsets_len_bytes == 0 {
+            return d.text.len() as isize;
+        }
+        let offsets = d.offsets.len().max(1);
+        if text_len < d.text.len() || offsets_len_bytes / 4 < offsets {
+            return -1;
+        }
+        if ffi::aliased(
+            text_ptr,
+            d.text.len(),
+            offsets_ptr as *const u8,
+            offsets * 4,
+        ) {
+            return ffi::ALIAS_ERROR;
+        }
+        ffi::slice_mut(text_ptr, d.text.len()).copy_from_slice(&d.text);
+        let out = ffi::slice_mut(offsets_ptr, offsets);
+        out[0] = 0;
+        out[1..].copy_from_slice(d.offsets.get(1..).unwrap_or(&[]));
+        let written = d.text.len() as isize;
+        d.text.clear();
+        d.offsets.clear();
+        written
+    })
+    .unwrap_or(-1)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Chunked {
+    /// Reading the hex size; the count is of digits seen.
+    Size(u64, u32),
+    /// Skipping a chunk extension after the size.
+    Extension(u64),
+    SizeLf(u64),
+    /// Payload bytes left in the current chunk.
+    Data(u64),
+    DataCr,
+    DataLf,
+    /// Reading trailer fields after the last chunk; `true` at the start of
+    /// a line.
+    Trailer(bool),
+    TrailerLf(bool),
+    Done,
+    Failed,
+}
+
+impl Chunked {
+    fn after_size(size: u64) -> Self {
+        if size == 0 {
+            Chunked::Trailer(true)
+        } else {
+            Chunked::Data(size)
+        }
+    }
+
+    /// Decode `input`, appending the payload to `out`.
+    fn feed(&mut self, input: &[u8], out: &mut Vec<u8>) {
+        use Chunked::*;
+        let mut i = 0;
+        while i < input.len() {
+            if let Data(left) = *self {
+                let take = left.min((input.len() - i) as u64) as usize;
+                out.extend_from_slice(&input[i..i + take]);
+                i += take;
+                *self = if take as u64 == left {
+                    DataCr
+                } else {
+                    Data(left - take as u64)
+                };
+                continue;
+            }
+            let b = input[i];
+            i += 1;
+            *self = match (*self, b) {
+                (Size(size, digits), _) if b.is_ascii_hexdigit() && digits < 16 => Size(
+                    size << 4 | (b as char).to_digit(16).unwrap() as u64,
+                    digits + 1,
+                ),
+                (Size(size, 1..), b';' | b' ' | b'\t') => Extension(size),
+                (Size(size, 1..), b'\r') | (Extension(size), b'\r') => SizeLf(size),
+                (Size(size, 1..) | Extension(size) | SizeLf(size), b'\n') => {
+                    Chunked::after_size(size)
+                }
+                (Extension(size), _) => Extension(size),
+                (DataCr, b'\r') => DataLf,
+                (DataCr | DataLf, b'\n') => Size(0, 0),
+                (Trailer(start), b'\r') => TrailerLf(start),
+                (Trailer(true) | TrailerLf(true), b'\n') => Done,
+                (Trailer(false) | TrailerLf(false), b'\n') => Trailer(true),
+                (Trailer(_), _) => Trailer(false),
+                (Done, _) => return,
+                _ => Failed,
+            };
+            if *self == Failed {
+                return;
+            }
+        }
+    }
+}
+
+/// Create a decoder for a `Transfer-Encoding: chunked` body.
+///
+/// Returns a handle for `chunked_feed` and `chunked_finished`, released
+/// with `handle_drop`.
+#[no_mangle]
+pub extern "C" fn chunked_new() -> isize {
+    handles::insert(Chunked::Size(0, 0)) as isize
+}
+
+/// Feed the next piece of a chunked body to the decoder behind `handle` and
+/// write the payload bytes it contains to `out_ptr`. Pieces may split the
+/// framing anywhere. Chunk extensions and trailer fields are skipped, as is
+/// anything after the body ends.
+///
+/// Returns payload bytes written, or `-1` for an unknown handle, malformed
+/// framing (then and on every later call) or an output shorter than the
+/// input, which always suffices.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn chunked_feed(
+    handle: u32,
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    if out_len < in_len {
+        return -1;
+    }
+    if ffi::aliased(in_ptr, in_len, out_ptr, in_len) {
+        return ffi::ALIAS_ERROR;
+    }
+    let input = ffi::slice(in_ptr, in_len);
+    handles::with(handle, |state: &mut Chunked| {
+        let mut payload = Vec::with_capacity(in_len);
+        state.feed(input, &mut payload);
+        if *state == Chunked::Failed {
+            return -1;
+        }
+        ffi::slice_mut(out_ptr, payload.len()).copy_from_slice(&payload);
+        payload.len() as isize
+    })
+    .unwrap_or(-1)
+}
+
+/// Whether the chunked body behind `handle` is complete: `1` once the last
+/// chunk and the trailer section have been read, `0` before, and `-1` for
+/// an unknown handle or malformed framing.
+#[no_mangle]
+pub extern "C" fn chunked_finished(handle: u32) -> i32 {
+    handles::with(handle, |state: &mut Chunked| match *state {
+        Chunked::Done => 1,
+        Chunked::Failed => -1,
+        _ => 0,
+    })
+    .unwrap_or(-1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `stream` in `chunk`-byte pieces and return `(type, data)` pairs.
+    fn events(stream: &[u8], chunk: usize) -> Vec<(String, String)> {
+        let handle = sse_new() as u32;
+        let mut queued = 0;
+        for part in stream.chunks(chunk) {
+            queued = unsafe { sse_feed(handle, part.as_ptr(), part.len()) };
+        }
+        let needed = unsafe { sse_read(handle, std::ptr::null_mut(), 0, std::ptr::null_mut(), 0) };
+        let mut text = vec![0u8; needed as usize];
+        let mut offsets = vec![0u32; 2 * queued as usize + 1];
+        let written = unsafe {
+            sse_read(
+                handle,
+                text.as_mut_ptr(),
+                text.len(),
+                offsets.as_mut_ptr(),
+                offsets.len() * 4,
+            )
+        };
+        assert_eq!(written, needed);
+        assert_eq!(handles::handle_drop(handle), 0);
+        let s = |i: usize| {
+            let span = offsets[i] as usize..offsets[i + 1] as usize;
+            String::from_utf8(text[span].to_vec()).unwrap()
+        };
+        (0..queued as usize)
+            .map(|i| (s(2 * i), s(2 * i + 1)))
+            .collect()
+    }
+
+    #[test]
+    fn splits_events_across_chunks() {
+        let stream = "\u{feff}: comment\r\ndata: one\r\n\r\n\
+event: log\ndata:two\ndata:  three\nid: 7\n\n\
+data\rdata: x\r\rretry: 10\r\n\r\nevent: lost\n\ndata: partial";
+        let expected = [
+            ("message", "one"),
+            ("log", "two\n three"),
+            ("message", "\nx"),
+        ];
+        for chunk in [1, 2, 3, 7, 16, 17, stream.len()] {
+            let got = events(stream.as_bytes(), chunk);
+            let got: Vec<_> = got.iter().map(|(a, b)| (a.as_str(), b.as_str())).collect();
+            assert_eq!(got, expected, "chunk {chunk}");
+        }
+        assert_eq!(events(b"data:\n\n", 4), [("message".into(), "".into())]);
+    }
+
+    #[test]
+    fn rejects_unknown_handles_and_short_outputs() {
+        assert_eq!(unsafe { sse_feed(0, [].as_ptr(), 0) }, -1);
+        let handle = sse_new() as u32;
+        let stream = b"data: hello\n\n";
+        assert_eq!(
+            unsafe { sse_feed(handle, stream.as_ptr(), stream.len()) },
+            1
+        );
+        let mut text = [0u8; 4];
+        let mut offsets = [0u32; 3];
+        let status = unsafe { sse_read(handle, text.as_mut_ptr(), 4, offsets.as_mut_ptr(), 12) };
+        assert_eq!(status, -1);
+        let needed = unsafe { sse_read(handle, text.as_mut_ptr(), 0, offsets.as_mut_ptr(), 0) };
+        assert_eq!(needed, 12, "queue kept");
+        handles::handle_drop(handle);
+    }
+
+    fn dechunk(body: &[u8], piece: usize) -> (Option<Vec<u8>>, i32) {
+        let handle = chunked_new() as u32;
+        let mut payload = Vec::new();
+        let mut ok = true;
+        for part in body.chunks(piece) {
+            let mut out = vec![0u8; part.len()];
+            let written = unsafe {
+                chunked_feed(
+                    handle,
+                    part.as_ptr(),
+                    part.len(),
+                    out.as_mut_ptr(),
+                    out.len(),
+                )
+            };
+            ok &= written >= 0;
+            payload.extend_from_slice(&out[..written.max(0) as usize]);
+        }
+        let finished = chunked_finished(handle);
+        handles::handle_drop(handle);
+        (ok.then_some(payload), finished)
+    }
+
+    #[test]
+    fn strips_chunk_framing() {
+        let body = b"5\r\nhello\r\nB;name=val\r\n, chunked!!\r\n0\r\nX-Trailer: 1\r\n\r\nextra";
+        for piece in [1, 2, 5, 13, body.len()] {
+            assert_eq!(
+                dechunk(body, piece),
+                (Some(b"hello, chunked!!".to_vec()), 1),
+                "piece {piece}"
+            );
+        }
+        assert_eq!(dechunk(b"3\nabc\n0\n\n", 2), (Some(b"abc".to_vec()), 1));
+        assert_eq!(dechunk(b"a\r\n01234", 3), (Some(b"01234".to_vec()), 0));
+    }
+
+    #[test]
+    fn rejects_bad_framing() {
+        assert_eq!(dechunk(b"zz\r\n", 4), (None, -1));
+        assert_eq!(dechunk(b"3\r\nabcX\r\n", 4).1, -1, "missing CRLF");
+        assert_eq!(dechunk(b"\r\n", 4).1, -1, "missing size");
+        assert_eq!(dechunk(b"11111111111111111\r\n", 4).1, -1, "size overflow");
+        assert_eq!(chunked_finished(0), -1);
+    }
+}
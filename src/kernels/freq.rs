@@ -0,0 +1,295 @@
+//! Streaming frequency sketches: count-min counts and top-k heavy hitters.
+//!
+//! Both are fed 64-bit key hashes, like the HyperLogLog sketch in `hll`, and
+//! live behind handles so they can be updated chunk by chunk during ingest.
+//! The count-min sketch answers "how often did this key occur" for any key,
+//! overestimating by at most `2n / width` with high probability (`n` being
+//! the total count). The space-saving summary tracks the `k` most frequent
+//! keys in `O(k)` memory.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{ffi, handles};
+
+const MAX_DEPTH: u32 = 16;
+/// Caps the table at 64 MiB of counters.
+const MAX_CELLS: usize = 1 << 24;
+
+struct CountMin {
+    width: usize,
+    depth: usize,
+    counts: Vec<u32>,
+}
+
+impl CountMin {
+    /// Counter index in row `i`: column `h1 + i * h2`, from the two halves of
+    /// the hash.
+    fn cell(&self, hash: u64, i: usize) -> usize {
+        let (h1, h2) = (hash as u32 as usize, (hash >> 32) as usize | 1);
+        i * self.width + h1.wrapping_add(i.wrapping_mul(h2)) % self.width
+    }
+}
+
+/// Create a count-min sketch of `depth` rows by `width` counters.
+///
+/// Returns a handle for `cms_add_batch` and `cms_query_batch`, released with
+/// `handle_drop`, or `-1` when either size is `0`, `depth` exceeds 16, or the
+/// table would exceed 2^24 counters.
+#[no_mangle]
+pub extern "C" fn cms_new(width: u32, depth: u32) -> isize {
+    let (w, d) = (width as usize, depth as usize);
+    if w == 0 || d == 0 || depth > MAX_DEPTH || w.saturating_mul(d) > MAX_CELLS {
+        return -1;
+    }
+    let sketch = CountMin {
+        width: w,
+        depth: d,
+        counts: vec![0; w * d],
+    };
+    handles::insert(sketch) as isize
+}
+
+/// Count one occurrence of each `u64` hash at `hashes_ptr`. Counters
+/// saturate at `u32::MAX`.
+///
+/// Returns `0`, or `-1` for an unknown handle or an input that is not a
+/// whole number of hashes.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn cms_add_batch(
+    handle: u32,
+    hashes_ptr: *const u64,
+    hashes_len_bytes: usize,
+) -> isize {
+    if !hashes_len_bytes.is_multiple_of(8) {
+        return -1;
+    }
+    let hashes = ffi::slice(hashes_ptr, hashes_len_bytes / 8);
+    handles::with(handle, |cms: &mut CountMin| {
+        for &hash in hashes {
+            for i in 0..cms.depth {
+                let cell = cms.cell(hash, i);
+                cms.counts[cell] = cms.counts[cell].saturating_add(1);
+            }
+        }
+        0
+    })
+    .unwrap_or(-1)
+}
+
+/// Estimate the count of each `u64` hash at `hashes_ptr`, writing one `u32`
+/// per hash to `out_ptr`. Estimates never undercount.
+///
+/// Returns bytes written, or `-1` for an unknown handle, a partial hash or a
+/// short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn cms_query_batch(
+    handle: u32,
+    hashes_ptr: *const u64,
+    hashes_len_bytes: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let n = hashes_len_bytes / 8;
+    if !hashes_len_bytes.is_multiple_of(8) || out_len_bytes / 4 < n {
+        return -1;
+    }
+    if ffi::aliased(
+        hashes_ptr as *const u8,
+        hashes_len_bytes,
+        out_ptr as *const u8,
+        n * 4,
+    ) {
+        return ffi::ALIAS_ERROR;
+    }
+    let hashes = ffi::slice(hashes_ptr, n);
+    handles::with(handle, |cms: &mut CountMin| {
+        let out = ffi::slice_mut(out_ptr, n);
+        for (o, &hash) in out.iter_mut().zip(hashes) {
+            *o = (0..cms.depth)
+                .map(|i| cms.counts[cms.cell(hash, i)])
+                .min()
+                .unwrap_or(0);
+        }
+        (n * 4) as isize
+    })
+    .unwrap_or(-1)
+}
+
+/// Space-saving summary: at most `k` monitored keys, each with a count and
+/// the overestimate it inherited when it evicted another key.
+pub(crate) struct TopK {
+    k: usize,
+    entries: HashMap<u64, (u64, u64)>,
+    /// `(count, hash)` of every entry, so the minimum is the first.
+    order: BTreeSet<(u64, u64)>,
+}
+
+impl TopK {
+    pub(crate) fn new(k: usize) -> Self {
+        TopK {
+            k,
+            entries: HashMap::new(),
+            order: BTreeSet::new(),
+        }
+    }
+
+    pub(crate) fn add(&mut self, hash: u64) {
+        if let Some((count, _)) = self.entries.get_mut(&hash) {
+            self.order.remove(&(*count, hash));
+            *count += 1;
+            self.order.insert((*count, hash));
+        } else if self.entries.len() < self.k {
+            self.entries.insert(hash, (1, 0));
+            self.order.insert((1, hash));
+        } else {
+            // Replace the least frequent key; the newcomer may have occurred
+            // up to that many times unseen.
+            let (min, evicted) = self.order.pop_first().unwrap();
+            self.entries.remove(&evicted);
+            self.entries.insert(hash, (min + 1, min));
+            self.order.insert((min + 1, hash));
+        }
+    }
+
+    /// `[hash, count, error]` per monitored key, most frequent first (ties
+    /// by descending hash).
+    pub(crate) fn ranked(&self) -> impl ExactSizeIterator<Item = [u64; 3]> + '_ {
+        self.order
+            .iter()
+            .rev()
+            .map(|&(count, hash)| [hash, count, self.entries[&hash].1])
+    }
+}
+
+/// Create a space-saving summary tracking the `k` most frequent keys.
+///
+/// Returns a handle for `topk_add_batch` and `topk_read`, released with
+/// `handle_drop`, or `-1` when `k` is `0`.
+#[no_mangle]
+pub extern "C" fn topk_new(k: u32) -> isize {
+    if k == 0 {
+        return -1;
+    }
+    handles::insert(TopK::new(k as usize)) as isize
+}
+
+/// Feed the `u64` hashes at `hashes_ptr` to the summary behind `handle`.
+///
+/// Returns `0`, or `-1` for an unknown handle or an input that is not a
+/// whole number of hashes.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn topk_add_batch(
+    handle: u32,
+    hashes_ptr: *const u64,
+    hashes_len_bytes: usize,
+) -> isize {
+    if !hashes_len_bytes.is_multiple_of(8) {
+        return -1;
+    }
+    let hashes = ffi::slice(hashes_ptr, hashes_len_bytes / 8);
+    handles::with(handle, |summary: &mut TopK| {
+        for &hash in hashes {
+            summary.add(hash);
+        }
+        0
+    })
+    .unwrap_or(-1)
+}
+
+/// Write the monitored keys as `u64` triples `[hash, count, error]`, most
+/// frequent first (ties by descending hash). A key's true count lies in
+/// `count - error ..= count`; any key that occurred more than `n / k` times
+/// is guaranteed to be listed.
+///
+/// Returns bytes written (at most `k * 24`), or `-1` for an unknown handle or
+/// a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn topk_read(handle: u32, out_ptr: *mut u64, out_len_bytes: usize) -> isize {
+    handles::with(handle, |summary: &mut TopK| {
+        let n = summary.order.len();
+        if out_len_bytes / 24 < n {
+            return -1;
+        }
+        let out = ffi::slice_mut(out_ptr, n * 3);
+        for (triple, entry) in out.chunks_exact_mut(3).zip(summary.ranked()) {
+            triple.copy_from_slice(&entry);
+        }
+        (n * 24) as isize
+    })
+    .unwrap_or(-1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Key `i` repeated `i` times for `i` in `1..=n`, interleaved.
+    fn skewed(n: u64) -> Vec<u64> {
+        let mut stream = Vec::new();
+        for round in 1..=n {
+            stream.extend(round..=n);
+        }
+        stream
+    }
+
+    #[test]
+    fn count_min_never_undercounts() {
+        let handle = cms_new(256, 4) as u32;
+        let stream = skewed(100);
+        let status = unsafe { cms_add_batch(handle, stream.as_ptr(), stream.len() * 8) };
+        assert_eq!(status, 0);
+        let keys: Vec<u64> = (1..=100).chain([1_000]).collect();
+        let mut counts = vec![0u32; keys.len()];
+        let written = unsafe {
+            cms_query_batch(
+                handle,
+                keys.as_ptr(),
+                keys.len() * 8,
+                counts.as_mut_ptr(),
+                counts.len() * 4,
+            )
+        };
+        assert_eq!(written, 404);
+        // 5050 total, so each error is at most 2 * 5050 / 256 < 40 w.h.p.
+        for (&key, &count) in keys.iter().zip(&counts) {
+            let exact = if key <= 100 { key as u32 } else { 0 };
+            assert!((exact..exact + 40).contains(&count), "{key}: {count}");
+        }
+    }
+
+    #[test]
+    fn top_k_finds_heavy_hitters() {
+        let handle = topk_new(10) as u32;
+        let stream = skewed(100);
+        for chunk in stream.chunks(333) {
+            let status = unsafe { topk_add_batch(handle, chunk.as_ptr(), chunk.len() * 8) };
+            assert_eq!(status, 0);
+        }
+        let mut out = vec![0u64; 30];
+        let written = unsafe { topk_read(handle, out.as_mut_ptr(), out.len() * 8) };
+        assert_eq!(written, 240);
+        // Keys 100, 99, 98 each exceed 5050 / 10 occurrences.
+        let top: Vec<u64> = out.chunks(3).map(|t| t[0]).collect();
+        assert_eq!(&top[..3], [100, 99, 98]);
+        for t in out.chunks(3) {
+            assert!(t[1] - t[2] <= t[0] && t[0] <= t[1], "{t:?}");
+        }
+        assert_eq!(unsafe { topk_read(handle, out.as_mut_ptr(), 232) }, -1);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(cms_new(0, 4), -1);
+        assert_eq!(cms_new(16, 17), -1);
+        assert_eq!(cms_new(1 << 23, 4), -1);
+        assert_eq!(topk_new(0), -1);
+        let handle = cms_new(8, 2) as u32;
+        let hashes = [1u64];
+        assert_eq!(unsafe { cms_add_batch(handle, hashes.as_ptr(), 4) }, -1);
+        assert_eq!(unsafe { topk_add_batch(handle, hashes.as_ptr(), 8) }, -1);
+    }
+}
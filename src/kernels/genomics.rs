@@ -0,0 +1,894 @@
+//! FASTA/FASTQ record splitting, base counts and quality statistics.
+//!
+//! `fastq_records` and `fasta_records` report where each record's id,
+//! sequence and (for FASTQ) quality string lie, so a sequence viewer can
+//! slice reads out of an uploaded file without decoding it to JS strings.
+//! `base_counts` tallies nucleotides sixteen bytes at a time with the
+//! search kernel's compare-to-bitmask scan and a popcount, and
+//! `fastq_quality_stats` summarizes the Phred scores of each read.
+//! `dna_pack_2bit` and `dna_unpack_2bit` store sequences at four bases per
+//! byte, and `reverse_complement` flips a strand with a table lookup.
+//! `kmer_count` tallies k-mers chunk by chunk behind a handle, in the
+//! heavy-hitters summary from `freq`.
+
+use super::bytes::map_bytes;
+use super::freq::TopK;
+use super::search::candidates;
+use crate::iovec::{IoVec, Stream};
+use crate::{ffi, handles};
+
+/// `u32` words per record in `fastq_records` output.
+const FASTQ_WORDS: usize = 6;
+/// `u32` words per record in `fasta_records` output.
+const FASTA_WORDS: usize = 4;
+/// `u32` words written by `base_counts`.
+const BASE_WORDS: usize = 6;
+/// `f32`s per record in `fastq_quality_stats` output.
+const QUALITY_WORDS: usize = 4;
+
+/// End of the line starting at `pos` (excluding any `\r`) and the start of
+/// the next one, or `None` when the buffer ends before a line break.
+fn line(buf: &[u8], pos: usize) -> Option<(usize, usize)> {
+    let lf = pos + buf.get(pos..)?.iter().position(|&b| b == b'\n')?;
+    let end = if lf > pos && buf[lf - 1] == b'\r' {
+        lf - 1
+    } else {
+        lf
+    };
+    Some((end, lf + 1))
+}
+
+/// Like `line`, but a final line without a break ends the buffer.
+fn last_line(buf: &[u8], pos: usize) -> (usize, usize) {
+    line(buf, pos).unwrap_or_else(|| {
+        let end = buf.len() - buf.ends_with(b"\r") as usize;
+        (end.max(pos), buf.len())
+    })
+}
+
+/// Parse the four-line FASTQ record at `pos` into its six spans and the
+/// start of the next record. `Ok(None)` means the buffer ends inside it.
+fn fastq_record(buf: &[u8], pos: usize) -> Result<Option<([usize; FASTQ_WORDS], usize)>, ()> {
+    if buf[pos] != b'@' {
+        return Err(());
+    }
+    let Some((id_end, seq_start)) = line(buf, pos) else {
+        return Ok(None);
+    };
+    let Some((seq_end, plus)) = line(buf, seq_start) else {
+        return Ok(None);
+    };
+    match buf.get(plus) {
+        None => return Ok(None),
+        Some(b'+') => {}
+        Some(_) => return Err(()),
+    }
+    let Some((_, qual_start)) = line(buf, plus) else {
+        return Ok(None);
+    };
+    let (qual_end, next) = last_line(buf, qual_start);
+    let len = seq_end - seq_start;
+    let complete = match line(buf, qual_start) {
+        Some(_) => true,
+        // Without a line break the quality string may still be arriving,
+        // unless it already matches the sequence length.
+        None => qual_end - qual_start >= len,
+    };
+    if !complete {
+        return Ok(None);
+    }
+    if qual_end - qual_start != len {
+        return Err(());
+    }
+    let spans = [pos + 1, id_end, seq_start, seq_end, qual_start, qual_end];
+    Ok(Some((spans, next)))
+}
+
+/// List the four-line FASTQ records in `in_ptr`, which must start at a
+/// record. Writes six `u32`s per record: `[id_start, id_end, seq_start,
+/// seq_end, qual_start, qual_end]`; the id excludes its `@` and the spans
+/// exclude line breaks (LF or CRLF).
+///
+/// Stops at a record cut off by the end of the buffer, or when the output
+/// is full; the next record starts on the line after the last `qual_end`,
+/// so a large file can be indexed a slice at a time. Returns bytes written,
+/// or `-1` for a record without its `@` or `+` line or with a quality
+/// string that does not match its sequence's length.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn fastq_records(
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    if u32::try_from(in_len).is_err() {
+        return -1;
+    }
+    let buf = ffi::slice(in_ptr, in_len);
+    let mut records = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() && records.len() + FASTQ_WORDS <= out_len_bytes / 4 {
+        match fastq_record(buf, pos) {
+            Ok(Some((spans, next))) => {
+                records.extend(spans.map(|v| v as u32));
+                pos = next;
+            }
+            Ok(None) => break,
+            Err(()) => return -1,
+        }
+    }
+    let bytes = records.len() * 4;
+    if ffi::aliased(in_ptr, in_len, out_ptr as *const u8, bytes) {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(out_ptr, records.len()).copy_from_slice(&records);
+    bytes as isize
+}
+
+/// Collect `[id_start, id_end, seq_start, seq_end]` per FASTA record.
+fn fasta(buf: &[u8], out: &mut Vec<u32>) -> Option<()> {
+    let mut pos = 0;
+    // Blank lines and `;` comments may precede the first record.
+    while pos < buf.len() && buf[pos] != b'>' {
+        if !matches!(buf[pos], b';' | b'\r' | b'\n') {
+            return None;
+        }
+        pos = last_line(buf, pos).1;
+    }
+    while pos < buf.len() {
+        let (id_end, seq_start) = last_line(buf, pos);
+        // The sequence runs to the next header line.
+        let mut seq_end = seq_start;
+        let mut next = seq_start;
+        while next < buf.len() && buf[next] != b'>' {
+            let (end, after) = last_line(buf, next);
+            if end > next {
+                seq_end = end;
+            }
+            next = after;
+        }
+        let record: [usize; FASTA_WORDS] = [pos + 1, id_end, seq_start, seq_end.max(seq_start)];
+        out.extend(record.map(|v| v as u32));
+        pos = next;
+    }
+    Some(())
+}
+
+/// List the records of the FASTA file at `in_ptr`. Writes four `u32`s per
+/// record: `[id_start, id_end, seq_start, seq_end]`. The id line excludes
+/// its `>`; the sequence spans every line up to the next record, line
+/// breaks included, so pass it to `base_counts` or strip the breaks before
+/// display. Blank and `;` comment lines may precede the first record.
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for a
+/// file that does not start with a record or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn fasta_records(
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let buf = ffi::slice(in_ptr, in_len);
+    let mut records = Vec::new();
+    if u32::try_from(in_len).is_err() || fasta(buf, &mut records).is_none() {
+        return -1;
+    }
+    let needed = records.len() * 4;
+    if out_len_bytes == 0 {
+        return needed as isize;
+    }
+    if out_len_bytes < needed {
+        return -1;
+    }
+    if ffi::aliased(in_ptr, in_len, out_ptr as *const u8, needed) {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(out_ptr, records.len()).copy_from_slice(&records);
+    needed as isize
+}
+
+/// Count `[A, C, G, T, N, other]` in `seq`, either case; line breaks are
+/// not counted.
+fn count_bases(seq: &[u8]) -> [u32; BASE_WORDS] {
+    let mut counts = [0u32; BASE_WORDS];
+    let mut breaks = 0;
+    for chunk in seq.chunks(16) {
+        let mut block = [0u8; 16];
+        block[..chunk.len()].copy_from_slice(chunk);
+        let valid = u16::MAX >> (16 - chunk.len());
+        for (count, base) in counts.iter_mut().zip(*b"ACGTN") {
+            let mask = candidates(block, base, base.to_ascii_lowercase()) & valid;
+            *count += mask.count_ones();
+        }
+        breaks += (candidates(block, b'\n', b'\r') & valid).count_ones();
+    }
+    let bases: u32 = counts[..5].iter().sum();
+    counts[5] = seq.len() as u32 - bases - breaks;
+    counts
+}
+
+/// Count the nucleotides in the sequence at `in_ptr`, case-insensitively,
+/// and write six `u32`s: `[A, C, G, T, N, other]`. Line breaks are skipped,
+/// so a multi-line FASTA sequence can be passed whole; `other` counts IUPAC
+/// ambiguity codes, gaps and anything else. GC content is `(C + G) /
+/// (A + C + G + T)`.
+///
+/// Returns bytes written (`24`), or `-1` for a short output or an input of
+/// 4 GiB or more.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn base_counts(
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    if out_len_bytes < BASE_WORDS * 4 || u32::try_from(in_len).is_err() {
+        return -1;
+    }
+    if ffi::aliased(in_ptr, in_len, out_ptr as *const u8, BASE_WORDS * 4) {
+        return ffi::ALIAS_ERROR;
+    }
+    let counts = count_bases(ffi::slice(in_ptr, in_len));
+    ffi::slice_mut(out_ptr, BASE_WORDS).copy_from_slice(&counts);
+    (BASE_WORDS * 4) as isize
+}
+
+/// Scatter/gather form of `base_counts`: counts the sequence split across
+/// the `iov_count` chunks at `iov_ptr` (see `iovec`), e.g. a FASTA file read
+/// in pieces, without joining them first. Same output and errors, with the
+/// 4 GiB limit on the total length.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn base_counts_iov(
+    iov_ptr: *const IoVec,
+    iov_count: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let stream = Stream::new(iov_ptr, iov_count);
+    let total = stream.len().and_then(|len| u32::try_from(len).ok());
+    if out_len_bytes < BASE_WORDS * 4 || total.is_none() {
+        return -1;
+    }
+    if stream.aliased(out_ptr as *const u8, BASE_WORDS * 4) {
+        return ffi::ALIAS_ERROR;
+    }
+    // Every count is a plain sum over bytes, so chunks add up.
+    let mut counts = [0u32; BASE_WORDS];
+    for chunk in stream.chunks() {
+        for (total, count) in counts.iter_mut().zip(count_bases(chunk)) {
+            *total += count;
+        }
+    }
+    ffi::slice_mut(out_ptr, BASE_WORDS).copy_from_slice(&counts);
+    (BASE_WORDS * 4) as isize
+}
+
+/// `[mean, min, max, fraction >= Q30]` of one quality string, or `None` for
+/// a character below `offset`.
+fn quality(qual: &[u8], offset: u8) -> Option<[f32; QUALITY_WORDS]> {
+    if qual.is_empty() {
+        return Some([f32::NAN, f32::NAN, f32::NAN, f32::NAN]);
+    }
+    let (mut sum, mut min, mut max, mut q30) = (0u64, u8::MAX, 0u8, 0usize);
+    for &c in qual {
+        let q = c.checked_sub(offset)?;
+        sum += q as u64;
+        min = min.min(q);
+        max = max.max(q);
+        q30 += (q >= 30) as usize;
+    }
+    let n = qual.len() as f64;
+    Some([
+        (sum as f64 / n) as f32,
+        min as f32,
+        max as f32,
+        (q30 as f64 / n) as f32,
+    ])
+}
+
+/// Summarize the quality string of each FASTQ record listed at
+/// `records_ptr` (the `fastq_records` output for `in_ptr`). Writes four
+/// `f32`s per record: `[mean, min, max, q30_fraction]`, where scores are
+/// the quality characters minus `phred_offset` (`33` for Sanger and modern
+/// Illumina data, `64` for old Illumina) and `q30_fraction` is the share of
+/// bases scoring 30 or more. An empty read gives `NaN`s.
+///
+/// Returns bytes written, or `-1` for a partial record, spans outside the
+/// input, a quality character below the offset, or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn fastq_quality_stats(
+    in_ptr: *const u8,
+    in_len: usize,
+    records_ptr: *const u32,
+    records_len_bytes: usize,
+    phred_offset: u8,
+    out_ptr: *mut f32,
+    out_len_bytes: usize,
+) -> isize {
+    if !records_len_bytes.is_multiple_of(FASTQ_WORDS * 4) {
+        return -1;
+    }
+    let count = records_len_bytes / (FASTQ_WORDS * 4);
+    let bytes = count * QUALITY_WORDS * 4;
+    if out_len_bytes < bytes {
+        return -1;
+    }
+    if ffi::aliased(in_ptr, in_len, out_ptr as *const u8, bytes)
+        || ffi::aliased(
+            records_ptr as *const u8,
+            records_len_bytes,
+            out_ptr as *const u8,
+            bytes,
+        )
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    let buf = ffi::slice(in_ptr, in_len);
+    let records = ffi::slice(records_ptr, count * FASTQ_WORDS);
+    let mut stats = Vec::with_capacity(count * QUALITY_WORDS);
+    for record in records.chunks_exact(FASTQ_WORDS) {
+        let qual = buf.get(record[4] as usize..record[5] as usize);
+        let Some(s) = qual.and_then(|q| quality(q, phred_offset)) else {
+            return -1;
+        };
+        stats.extend(s);
+    }
+    ffi::slice_mut(out_ptr, stats.len()).copy_from_slice(&stats);
+    bytes as isize
+}
+
+/// 2-bit codes for `dna_pack_2bit`, either case; `0xff` marks bytes that
+/// are not `A`, `C`, `G` or `T`.
+const PACK: [u8; 256] = {
+    let mut table = [0xff; 256];
+    let mut i = 0;
+    while i < 4 {
+        table[b"ACGT"[i] as usize] = i as u8;
+        table[b"acgt"[i] as usize] = i as u8;
+        i += 1;
+    }
+    table
+};
+
+/// Complements of the uppercase bytes `0x40..=0x5f`, IUPAC codes included;
+/// bytes that are not nucleotides map to themselves. Lowercase letters use
+/// the same entries with the case bit put back.
+const COMPLEMENT: [u8; 32] = *b"@TVGHEFCDIJMLKNOPQYSAABWXRZ[\\]^_";
+
+fn complement(b: u8) -> u8 {
+    let upper = b & 0xdf;
+    if upper & 0xe0 == 0x40 {
+        COMPLEMENT[(upper & 0x1f) as usize] | (b & 0x20)
+    } else {
+        b
+    }
+}
+
+/// Complement every lane of `block` and reverse the lane order. With
+/// simd128 the table lookup is two 16-entry swizzles.
+#[inline(always)]
+fn reverse_complement_block(block: [u8; 16]) -> [u8; 16] {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let v = v128_load(block.as_ptr() as *const v128);
+        let lo_table = v128_load(COMPLEMENT.as_ptr() as *const v128);
+        let hi_table = v128_load(COMPLEMENT[16..].as_ptr() as *const v128);
+        let row = v128_and(v, u8x16_splat(0xd0));
+        let index = v128_and(v, u8x16_splat(0x0f));
+        let in_lo = u8x16_eq(row, u8x16_splat(0x40));
+        let in_hi = u8x16_eq(row, u8x16_splat(0x50));
+        let looked_up = v128_or(
+            v128_and(i8x16_swizzle(lo_table, index), in_lo),
+            v128_and(i8x16_swizzle(hi_table, index), in_hi),
+        );
+        let complemented = v128_bitselect(
+            v128_or(looked_up, v128_and(v, u8x16_splat(0x20))),
+            v,
+            v128_or(in_lo, in_hi),
+        );
+        let reversed = i8x16_shuffle::<15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0>(
+            complemented,
+            complemented,
+        );
+        let mut out = [0u8; 16];
+        v128_store(out.as_mut_ptr() as *mut v128, reversed);
+        out
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        std::array::from_fn(|i| complement(block[15 - i]))
+    }
+}
+
+fn reverse_complement_into(input: &[u8], out: &mut [u8]) {
+    let mut blocks = out.chunks_exact_mut(16);
+    // Output block `i` is the reversed complement of the input block that
+    // ends `16 * i` bytes from the input's end.
+    for (dst, src) in blocks.by_ref().zip(input.rchunks_exact(16)) {
+        dst.copy_from_slice(&reverse_complement_block(src.try_into().unwrap()));
+    }
+    let head = &input[..input.len() % 16];
+    for (dst, &src) in blocks.into_remainder().iter_mut().zip(head.iter().rev()) {
+        *dst = complement(src);
+    }
+}
+
+fn reverse_complement_in_place(buf: &mut [u8]) {
+    let mut blocks = buf.chunks_exact_mut(16);
+    for block in blocks.by_ref() {
+        let reversed = reverse_complement_block((&*block).try_into().unwrap());
+        block.copy_from_slice(&reversed);
+    }
+    for b in blocks.into_remainder() {
+        *b = complement(*b);
+    }
+    // Undo the per-block reversal, then reverse the whole buffer.
+    for block in buf.chunks_exact_mut(16) {
+        block.reverse();
+    }
+    buf.reverse();
+}
+
+/// Pack the nucleotides at `in_ptr` into 2-bit codes, four per byte with
+/// the first base in the high bits: `A = 0`, `C = 1`, `G = 2`, `T = 3`,
+/// either case. In this encoding a base's complement is `3 - code`. The last
+/// byte is padded with zero bits; keep the base count to unpack. `N` and
+/// other IUPAC codes cannot be packed: mask them out first, for example by
+/// recording their runs and substituting `A`.
+///
+/// Returns bytes written (`ceil(in_len / 4)`), or `-1` for a byte that is
+/// not `A`, `C`, `G` or `T`, or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn dna_pack_2bit(
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let bytes = in_len.div_ceil(4);
+    if out_len < bytes {
+        return -1;
+    }
+    if ffi::aliased(in_ptr, in_len, out_ptr, bytes) {
+        return ffi::ALIAS_ERROR;
+    }
+    let input = ffi::slice(in_ptr, in_len);
+    // Validate first so a bad byte leaves the output untouched.
+    if input.iter().any(|&b| PACK[b as usize] == 0xff) {
+        return -1;
+    }
+    let out = ffi::slice_mut(out_ptr, bytes);
+    for (dst, bases) in out.iter_mut().zip(input.chunks(4)) {
+        *dst = bases
+            .iter()
+            .enumerate()
+            .fold(0, |byte, (i, &b)| byte | PACK[b as usize] << (6 - 2 * i));
+    }
+    bytes as isize
+}
+
+/// Unpack `bases` 2-bit codes from `in_ptr` (as written by `dna_pack_2bit`)
+/// into uppercase `A`, `C`, `G` and `T`.
+///
+/// Returns bytes written (`bases`), or `-1` when the input holds fewer
+/// than `bases` codes or the output is short.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn dna_unpack_2bit(
+    in_ptr: *const u8,
+    in_len: usize,
+    bases: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    if in_len < bases.div_ceil(4) || out_len < bases {
+        return -1;
+    }
+    if ffi::aliased(in_ptr, bases.div_ceil(4), out_ptr, bases) {
+        return ffi::ALIAS_ERROR;
+    }
+    let input = ffi::slice(in_ptr, bases.div_ceil(4));
+    let out = ffi::slice_mut(out_ptr, bases);
+    for (dst, &byte) in out.chunks_mut(4).zip(input) {
+        for (i, base) in dst.iter_mut().enumerate() {
+            *base = b"ACGT"[(byte >> (6 - 2 * i) & 3) as usize];
+        }
+    }
+    bases as isize
+}
+
+/// Reverse-complement the nucleotide sequence at `in_ptr`: reverse it and
+/// swap `A`/`T` (`U` becomes `A`), `C`/`G` and the IUPAC ambiguity codes
+/// (`R`/`Y`, `K`/`M`, `B`/`V`, `D`/`H`), keeping each letter's case. Other
+/// bytes, `N`, `S`, `W` and line breaks included, are moved but not changed,
+/// so strip line breaks from multi-line FASTA sequences first. With simd128
+/// the complement is a table lookup of sixteen bytes at a time.
+///
+/// Runs in place when `in_ptr == out_ptr`. Returns bytes written, or `-1`
+/// for a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn reverse_complement(
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    map_bytes(
+        in_ptr,
+        in_len,
+        out_ptr,
+        out_len,
+        reverse_complement_into,
+        reverse_complement_in_place,
+    )
+}
+
+/// Longest k-mer that fits a `u64` at two bits per base.
+const MAX_K: u32 = 32;
+
+/// Rolling k-mer state carried across `kmer_count` chunks, feeding a
+/// space-saving summary keyed by 2-bit k-mer codes.
+struct KmerCounter {
+    /// Fixed by the first `kmer_count` call; `0` until then.
+    k: u32,
+    canonical: bool,
+    /// The last `filled` bases (up to `k`), forward and reverse-complemented.
+    forward: u64,
+    reverse: u64,
+    filled: u32,
+    counts: TopK,
+}
+
+impl KmerCounter {
+    /// Feed `seq`, returning the number of k-mers counted.
+    fn feed(&mut self, seq: &[u8]) -> usize {
+        let k = self.k;
+        let mask = u64::MAX >> (64 - 2 * k);
+        let mut counted = 0;
+        for &b in seq {
+            let code = PACK[b as usize];
+            if code == 0xff {
+                // Line breaks join the lines of a FASTA sequence; anything
+                // else (`N`, gaps) breaks the k-mers spanning it.
+                if !matches!(b, b'\n' | b'\r') {
+                    self.filled = 0;
+                }
+                continue;
+            }
+            self.forward = (self.forward << 2 | code as u64) & mask;
+            self.reverse = self.reverse >> 2 | ((3 - code) as u64) << (2 * (k - 1));
+            self.filled = (self.filled + 1).min(k);
+            if self.filled == k {
+                let kmer = if self.canonical {
+                    self.forward.min(self.reverse)
+                } else {
+                    self.forward
+                };
+                self.counts.add(kmer);
+                counted += 1;
+            }
+        }
+        counted
+    }
+}
+
+/// Create a k-mer counter that tracks up to `capacity` distinct k-mers.
+/// Counts are exact while the sequence holds at most `capacity` distinct
+/// k-mers; beyond that the counter keeps the most frequent ones with the
+/// space-saving guarantees of `topk_new`. With `canonical != 0` each k-mer
+/// is counted together with its reverse complement, under whichever of the
+/// two codes is smaller, as is usual for double-stranded reads.
+///
+/// Returns a handle for `kmer_count` and `kmer_top_n`, released with
+/// `handle_drop`, or `-1` when `capacity` is `0`.
+#[no_mangle]
+pub extern "C" fn kmer_new(capacity: u32, canonical: u32) -> isize {
+    if capacity == 0 {
+        return -1;
+    }
+    let counter = KmerCounter {
+        k: 0,
+        canonical: canonical != 0,
+        forward: 0,
+        reverse: 0,
+        filled: 0,
+        counts: TopK::new(capacity as usize),
+    };
+    handles::insert(counter) as isize
+}
+
+/// Count the `k`-mers of the sequence chunk at `seq_ptr`, either case.
+/// Chunks continue one another: k-mers spanning a chunk boundary are
+/// counted, and line breaks are skipped so FASTA sequence spans can be fed
+/// as they are. Any other byte that is not `A`, `C`, `G` or `T` (such as
+/// `N`) ends the k-mers running through it.
+///
+/// `k` (1 to 32) is fixed by the first call. Returns the number of k-mers
+/// counted, or `-1` for an unknown handle, a `k` out of range or different
+/// from the first call's.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn kmer_count(
+    handle: u32,
+    seq_ptr: *const u8,
+    seq_len: usize,
+    k: u32,
+) -> isize {
+    if !(1..=MAX_K).contains(&k) {
+        return -1;
+    }
+    let seq = ffi::slice(seq_ptr, seq_len);
+    handles::with(handle, |counter: &mut KmerCounter| {
+        if counter.k == 0 {
+            counter.k = k;
+        } else if counter.k != k {
+            return -1;
+        }
+        counter.feed(seq) as isize
+    })
+    .unwrap_or(-1)
+}
+
+/// Write the `n` most frequent k-mers as `u64` triples `[kmer, count,
+/// error]`, most frequent first. `kmer` holds the bases in the
+/// `dna_pack_2bit` codes, first base in the highest used bits, so base `i`
+/// of a `k`-mer is `(kmer >> (2 * (k - 1 - i))) & 3`. The true count lies in
+/// `count - error ..= count`; `error` is `0` while the counter is exact.
+///
+/// Returns bytes written (fewer than `n * 24` when fewer k-mers were seen),
+/// or `-1` for an unknown handle or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn kmer_top_n(
+    handle: u32,
+    n: u32,
+    out_ptr: *mut u64,
+    out_len_bytes: usize,
+) -> isize {
+    handles::with(handle, |counter: &mut KmerCounter| {
+        let ranked = counter.counts.ranked();
+        let count = ranked.len().min(n as usize);
+        if out_len_bytes / 24 < count {
+            return -1;
+        }
+        let out = ffi::slice_mut(out_ptr, count * 3);
+        for (triple, entry) in out.chunks_exact_mut(3).zip(ranked) {
+            triple.copy_from_slice(&entry);
+        }
+        (count * 24) as isize
+    })
+    .unwrap_or(-1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fastq(buf: &[u8], max: usize) -> Result<Vec<[&str; 3]>, isize> {
+        let mut out = vec![0u32; max * FASTQ_WORDS];
+        let written =
+            unsafe { fastq_records(buf.as_ptr(), buf.len(), out.as_mut_ptr(), out.len() * 4) };
+        if written < 0 {
+            return Err(written);
+        }
+        let s = |a: u32, b: u32| std::str::from_utf8(&buf[a as usize..b as usize]).unwrap();
+        Ok(out[..written as usize / 4]
+            .chunks(FASTQ_WORDS)
+            .map(|r| [s(r[0], r[1]), s(r[2], r[3]), s(r[4], r[5])])
+            .collect())
+    }
+
+    #[test]
+    fn splits_fastq_records() {
+        let buf = b"@r1 desc\nACGT\n+\nII#I\n@r2\r\nGG\r\n+r2\r\n@@\r\n@r3\nA\n+\n5";
+        assert_eq!(
+            fastq(buf, 8).unwrap(),
+            [
+                ["r1 desc", "ACGT", "II#I"],
+                ["r2", "GG", "@@"],
+                ["r3", "A", "5"]
+            ]
+        );
+        // A full output or a cut-off record ends the page.
+        assert_eq!(fastq(buf, 1).unwrap().len(), 1);
+        assert_eq!(fastq(&buf[..buf.len() - 1], 8).unwrap().len(), 2);
+        assert_eq!(fastq(&buf[..24], 8).unwrap().len(), 1);
+        // Resume after the first record.
+        assert_eq!(fastq(&buf[21..], 1).unwrap(), [["r2", "GG", "@@"]]);
+
+        assert_eq!(fastq(b"r1\nA\n+\nI\n", 8), Err(-1), "no @");
+        assert_eq!(fastq(b"@r1\nA\n-\nI\n", 8), Err(-1), "no +");
+        assert_eq!(fastq(b"@r1\nAC\n+\nI\n", 8), Err(-1), "short quality");
+        assert_eq!(fastq(b"@r1\nA\n+\nII", 8), Err(-1), "long quality");
+    }
+
+    #[test]
+    fn splits_fasta_records() {
+        let buf = b";comment\n\n>seq1 human\nACGT\nAC\n\n>seq2\r\nNNNN\r\n>empty\n";
+        let call = |out: &mut [u32]| unsafe {
+            fasta_records(buf.as_ptr(), buf.len(), out.as_mut_ptr(), out.len() * 4)
+        };
+        let needed = call(&mut []);
+        assert_eq!(needed, 48);
+        let mut out = vec![0u32; 12];
+        assert_eq!(call(&mut out), needed);
+        let s = |a: u32, b: u32| &buf[a as usize..b as usize];
+        let records: Vec<_> = out
+            .chunks(FASTA_WORDS)
+            .map(|r| (s(r[0], r[1]), s(r[2], r[3])))
+            .collect();
+        assert_eq!(
+            records,
+            [
+                (&b"seq1 human"[..], &b"ACGT\nAC"[..]),
+                (b"seq2", b"NNNN"),
+                (b"empty", b""),
+            ]
+        );
+        let bad = b"ACGT\n";
+        let status = unsafe { fasta_records(bad.as_ptr(), 5, out.as_mut_ptr(), 48) };
+        assert_eq!(status, -1);
+    }
+
+    #[test]
+    fn counts_bases_across_blocks() {
+        let seq = b"ACGTacgtNNnRY-\nGGGGGGGGGGCCCCCCCCCC\r\nAT";
+        let mut out = [0u32; BASE_WORDS];
+        let written = unsafe { base_counts(seq.as_ptr(), seq.len(), out.as_mut_ptr(), 24) };
+        assert_eq!(written, 24);
+        assert_eq!(out, [3, 12, 12, 3, 3, 3]);
+        let status = unsafe { base_counts(seq.as_ptr(), seq.len(), out.as_mut_ptr(), 20) };
+        assert_eq!(status, -1);
+
+        // Split mid-block and mid-CRLF, the chunks count the same.
+        let iov = [&seq[..5], &seq[5..36], &seq[36..]].map(|c| IoVec {
+            ptr: c.as_ptr(),
+            len: c.len(),
+        });
+        let mut split = [0u32; BASE_WORDS];
+        let written = unsafe { base_counts_iov(iov.as_ptr(), 3, split.as_mut_ptr(), 24) };
+        assert_eq!(written, 24);
+        assert_eq!(split, out);
+    }
+
+    #[test]
+    fn summarizes_quality_scores() {
+        let buf = b"@a\nACGT\n+\nI5+?\n@b\n\n+\n\n@c\nA\n+\n!\n";
+        let mut records = [0u32; 3 * FASTQ_WORDS];
+        let written = unsafe { fastq_records(buf.as_ptr(), buf.len(), records.as_mut_ptr(), 72) };
+        assert_eq!(written, 72);
+        let mut out = [0f32; 3 * QUALITY_WORDS];
+        let written = unsafe {
+            fastq_quality_stats(
+                buf.as_ptr(),
+                buf.len(),
+                records.as_ptr(),
+                72,
+                33,
+                out.as_mut_ptr(),
+                48,
+            )
+        };
+        assert_eq!(written, 48);
+        // I=40, 5=20, +=10, ?=30.
+        assert_eq!(out[..4], [25.0, 10.0, 40.0, 0.5]);
+        assert!(out[4..8].iter().all(|v| v.is_nan()));
+        assert_eq!(out[8..], [0.0, 0.0, 0.0, 0.0]);
+
+        let status = unsafe {
+            fastq_quality_stats(
+                buf.as_ptr(),
+                buf.len(),
+                records.as_ptr(),
+                72,
+                64,
+                out.as_mut_ptr(),
+                48,
+            )
+        };
+        assert_eq!(status, -1, "below the offset");
+    }
+
+    #[test]
+    fn packs_and_unpacks_two_bit_codes() {
+        let seq = b"ACGTtgcaA";
+        let mut packed = [0u8; 3];
+        let written = unsafe { dna_pack_2bit(seq.as_ptr(), 9, packed.as_mut_ptr(), 3) };
+        assert_eq!(written, 3);
+        assert_eq!(packed, [0b00_01_10_11, 0b11_10_01_00, 0b00_00_00_00]);
+        let mut out = [0u8; 9];
+        let written = unsafe { dna_unpack_2bit(packed.as_ptr(), 3, 9, out.as_mut_ptr(), 9) };
+        assert_eq!(written, 9);
+        assert_eq!(&out, b"ACGTTGCAA");
+
+        let status = unsafe { dna_pack_2bit(b"ACNT".as_ptr(), 4, packed.as_mut_ptr(), 3) };
+        assert_eq!(status, -1, "N cannot be packed");
+        let status = unsafe { dna_unpack_2bit(packed.as_ptr(), 2, 9, out.as_mut_ptr(), 9) };
+        assert_eq!(status, -1, "too few codes");
+    }
+
+    #[test]
+    fn reverse_complements_across_blocks() {
+        let mut out = [0u8; 20];
+        let written = unsafe {
+            reverse_complement(b"ACGTacgtNnRYKMBVDHSW".as_ptr(), 20, out.as_mut_ptr(), 20)
+        };
+        assert_eq!(written, 20);
+        assert_eq!(&out, b"WSDHBVKMRYnNacgtACGT");
+        assert_eq!(complement(b'u'), b'a');
+        assert_eq!(complement(b'`'), b'`');
+        assert_eq!(complement(0xc1), 0xc1);
+
+        let seq = b"ACGTacgtNnRYKMBVDHSW-\n0123456789ACCGGGTTTTaaaa\xff";
+        let expected: Vec<u8> = seq.iter().rev().map(|&b| complement(b)).collect();
+        for len in [0, 1, 15, 16, 17, 33, seq.len()] {
+            let input = &seq[seq.len() - len..];
+            let mut out = vec![0u8; len];
+            let written = unsafe { reverse_complement(input.as_ptr(), len, out.as_mut_ptr(), len) };
+            assert_eq!(written, len as isize);
+            assert_eq!(out, expected[..len], "len {len}");
+
+            let mut in_place = input.to_vec();
+            let ptr = in_place.as_mut_ptr();
+            unsafe { reverse_complement(ptr, len, ptr, len) };
+            assert_eq!(in_place, out);
+            // Applying it twice restores the input.
+            unsafe { reverse_complement(ptr, len, ptr, len) };
+            assert_eq!(in_place, input);
+        }
+    }
+
+    fn top(handle: u32, n: u32) -> Vec<[u64; 3]> {
+        let mut out = vec![0u64; n as usize * 3];
+        let written = unsafe { kmer_top_n(handle, n, out.as_mut_ptr(), out.len() * 8) };
+        out.truncate(written as usize / 8);
+        out.chunks(3).map(|t| t.try_into().unwrap()).collect()
+    }
+
+    #[test]
+    fn counts_kmers_across_chunks() {
+        let handle = kmer_new(64, 0) as u32;
+        // "ACGACGA" split mid-k-mer, with a line break and an N.
+        for chunk in [&b"AC"[..], b"ga\nCGA", b"NACG"] {
+            assert!(unsafe { kmer_count(handle, chunk.as_ptr(), chunk.len(), 3) } >= 0);
+        }
+        let (acg, cga, gac) = (0b00_01_10, 0b01_10_00, 0b10_00_01);
+        assert_eq!(top(handle, 8), [[acg, 3, 0], [cga, 2, 0], [gac, 1, 0]]);
+        assert_eq!(top(handle, 1), [[acg, 3, 0]]);
+        let status = unsafe { kmer_count(handle, b"ACGT".as_ptr(), 4, 4) };
+        assert_eq!(status, -1, "k is fixed by the first call");
+        let status = unsafe { kmer_top_n(handle, 2, [0u64; 3].as_mut_ptr(), 24) };
+        assert_eq!(status, -1, "short output");
+        assert_eq!(handles::handle_drop(handle), 0);
+    }
+
+    #[test]
+    fn canonical_kmers_merge_strands() {
+        let handle = kmer_new(16, 1) as u32;
+        // AAC and its reverse complement GTT are one canonical k-mer.
+        let seq = b"AACnGTTnAAC";
+        assert_eq!(unsafe { kmer_count(handle, seq.as_ptr(), seq.len(), 3) }, 3);
+        assert_eq!(top(handle, 4), [[0b00_00_01, 3, 0]]);
+        assert_eq!(handles::handle_drop(handle), 0);
+
+        // k = 32 uses every bit of the code.
+        let handle = kmer_new(4, 0) as u32;
+        let seq = [b'T'; 33];
+        assert_eq!(unsafe { kmer_count(handle, seq.as_ptr(), 33, 32) }, 2);
+        assert_eq!(top(handle, 1), [[u64::MAX, 2, 0]]);
+        assert_eq!(unsafe { kmer_count(handle, seq.as_ptr(), 33, 33) }, -1);
+        assert_eq!(kmer_new(0, 0), -1);
+        assert_eq!(handles::handle_drop(handle), 0);
+    }
+}
@@ -0,0 +1,235 @@
+//! Geohash encoding and decoding for batches of coordinates.
+//!
+//! A geohash of `p` characters names a cell of a `2^ceil(5p/2) x
+//! 2^floor(5p/2)` longitude/latitude grid: its bits alternate longitude and
+//! latitude halvings, starting with longitude, five bits per base-32
+//! character. Encoding quantizes both axes to 30 bits and interleaves them
+//! with the Z-order helper from `spatial`, so a 12-character hash is one
+//! spread and a shift rather than 60 bisection steps.
+
+use super::spans;
+use super::spatial::spread2;
+use crate::ffi;
+
+const ALPHABET: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+const MAX_PRECISION: usize = 12;
+
+/// `v` in `[min, min + span]` as a 30-bit cell index.
+fn quantize(v: f64, min: f64, span: f64) -> u32 {
+    let q = ((v - min) / span * (1u64 << 30) as f64) as u32;
+    q.min((1 << 30) - 1)
+}
+
+/// Decode one hash to its cell center `[lat, lon]`.
+fn decode(hash: &[u8]) -> Option<[f64; 2]> {
+    if hash.is_empty() || hash.len() > MAX_PRECISION {
+        return None;
+    }
+    // (cell index, bits) for longitude then latitude.
+    let mut axes = [(0u64, 0u32); 2];
+    let mut bit = 0;
+    for &c in hash {
+        let c = c.to_ascii_lowercase();
+        let value = ALPHABET.iter().position(|&a| a == c)?;
+        for shift in (0..5).rev() {
+            let axis = &mut axes[bit % 2];
+            axis.0 = (axis.0 << 1) | ((value >> shift) & 1) as u64;
+            axis.1 += 1;
+            bit += 1;
+        }
+    }
+    let center = |(index, bits): (u64, u32), min: f64, span: f64| {
+        min + (index as f64 + 0.5) * span / (1u64 << bits) as f64
+    };
+    Some([
+        center(axes[1], -90.0, 180.0),
+        center(axes[0], -180.0, 360.0),
+    ])
+}
+
+/// Encode `[lat, lon]` `f64` pairs as geohashes of `precision` characters,
+/// written back to back to `out_ptr` (hash `i` at `out[i * precision..]`).
+///
+/// Returns bytes written (`points * precision`), or `-1` when `precision` is
+/// outside `1..=12`, a coordinate is NaN or out of range, the input is not a
+/// whole number of pairs, or the output is short. Nothing is written on
+/// error.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn geohash_encode_batch(
+    coords_ptr: *const f64,
+    coords_len_bytes: usize,
+    precision: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let p = precision as usize;
+    let n = coords_len_bytes / 16;
+    if !(1..=MAX_PRECISION).contains(&p) || !coords_len_bytes.is_multiple_of(16) || out_len / p < n
+    {
+        return -1;
+    }
+    if ffi::aliased(coords_ptr as *const u8, coords_len_bytes, out_ptr, n * p) {
+        return ffi::ALIAS_ERROR;
+    }
+    let coords = ffi::slice(coords_ptr, n * 2);
+    let in_range =
+        |pair: &[f64]| (-90.0..=90.0).contains(&pair[0]) && (-180.0..=180.0).contains(&pair[1]);
+    if !coords.chunks_exact(2).all(in_range) {
+        return -1;
+    }
+    let out = ffi::slice_mut(out_ptr, n * p);
+    for (pairs, dst) in coords.chunks(4).zip(out.chunks_mut(2 * p)) {
+        let (mut lats, mut lons) = ([0u32; 2], [0u32; 2]);
+        for (j, pair) in pairs.chunks_exact(2).enumerate() {
+            lats[j] = quantize(pair[0], -90.0, 180.0);
+            lons[j] = quantize(pair[1], -180.0, 360.0);
+        }
+        let (lats, lons) = (spread2(lats), spread2(lons));
+        for (j, hash) in dst.chunks_exact_mut(p).enumerate() {
+            // 60 bits, longitude first, left-aligned under the top nibble.
+            let bits = (lons[j] << 1 | lats[j]) << 4;
+            for (k, c) in hash.iter_mut().enumerate() {
+                *c = ALPHABET[(bits >> (59 - 5 * k)) as usize & 31];
+            }
+        }
+    }
+    (n * p) as isize
+}
+
+/// Decode each value of a string batch (case-insensitive) to the `[lat,
+/// lon]` `f64` center of its cell.
+///
+/// Rows that are empty, longer than 12 characters or contain a character
+/// outside the geohash alphabet get `[NaN, NaN]` and their bit set in the
+/// error bitmap at `errors_ptr` (bit `i % 8` of byte `i / 8`), which must
+/// hold `errors_len >= ceil(rows / 8)` bytes.
+///
+/// Returns bytes written to `out_ptr` (`rows * 16`), or `-1` when either
+/// output is too small or the offsets are empty.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn geohash_decode_batch(
+    text_ptr: *const u8,
+    text_len: usize,
+    offsets_ptr: *const u32,
+    offsets_len: usize,
+    out_ptr: *mut f64,
+    out_len_bytes: usize,
+    errors_ptr: *mut u8,
+    errors_len: usize,
+) -> isize {
+    let Some(rows) = offsets_len.checked_sub(1) else {
+        return -1;
+    };
+    if out_len_bytes / 16 < rows || errors_len < rows.div_ceil(8) {
+        return -1;
+    }
+    let text = ffi::slice(text_ptr, text_len);
+    let offsets = ffi::slice(offsets_ptr, offsets_len);
+    let out = ffi::slice_mut(out_ptr, rows * 2);
+    let errors = ffi::slice_mut(errors_ptr, rows.div_ceil(8));
+    errors.fill(0);
+
+    for (i, (value, slot)) in spans(text, offsets)
+        .zip(out.chunks_exact_mut(2))
+        .enumerate()
+    {
+        match value.and_then(decode) {
+            Some(center) => slot.copy_from_slice(&center),
+            None => {
+                slot.fill(f64::NAN);
+                errors[i / 8] |= 1 << (i % 8);
+            }
+        }
+    }
+    (rows * 16) as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(coords: &[f64], precision: u32) -> Option<String> {
+        let mut out = vec![0u8; coords.len() / 2 * precision as usize];
+        let written = unsafe {
+            geohash_encode_batch(
+                coords.as_ptr(),
+                coords.len() * 8,
+                precision,
+                out.as_mut_ptr(),
+                out.len(),
+            )
+        };
+        (written >= 0).then(|| String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn encodes_known_hashes() {
+        // Odd counts exercise the half-filled SIMD pair.
+        let coords = [57.64911, 10.40744, 42.6, -5.6, -90.0, -180.0];
+        assert_eq!(
+            encode(&coords, 11).unwrap(),
+            "u4pruydqqvjezs42e44yx900000000000"
+        );
+        assert_eq!(encode(&coords[..4], 5).unwrap(), "u4pruezs42");
+        assert_eq!(encode(&[90.0, 180.0], 12).unwrap(), "zzzzzzzzzzzz");
+    }
+
+    #[test]
+    fn decodes_cell_centers() {
+        let text = b"u4pruydqqvjEZS42xaz";
+        let offsets = [0u32, 11, 16, 16, 19];
+        let mut out = [0f64; 8];
+        let mut errors = [0u8; 1];
+        let written = unsafe {
+            geohash_decode_batch(
+                text.as_ptr(),
+                text.len(),
+                offsets.as_ptr(),
+                offsets.len(),
+                out.as_mut_ptr(),
+                out.len() * 8,
+                errors.as_mut_ptr(),
+                1,
+            )
+        };
+        assert_eq!(written, 64);
+        assert!((out[0] - 57.64911).abs() < 1e-5 && (out[1] - 10.40744).abs() < 1e-5);
+        assert!((out[2] - 42.6).abs() < 0.03 && (out[3] + 5.6).abs() < 0.03);
+        // Row 2 is empty; row 3 contains `a`, which geohash skips.
+        assert_eq!(errors[0], 0b1100);
+        assert!(out[4..].iter().all(|v| v.is_nan()));
+    }
+
+    #[test]
+    fn round_trips_through_cell_centers() {
+        for precision in 1..=12u32 {
+            let coords = [37.7749, -122.4194, -33.8688, 151.2093];
+            let hashes = encode(&coords, precision).unwrap();
+            for (hash, pair) in hashes
+                .as_bytes()
+                .chunks(precision as usize)
+                .zip(coords.chunks(2))
+            {
+                let center = decode(hash).unwrap();
+                assert_eq!(encode(&center, precision).unwrap().as_bytes(), hash);
+                let bits = 5 * precision as i32;
+                let lat_cell = 180.0 / 2f64.powi(bits / 2);
+                assert!((center[0] - pair[0]).abs() <= lat_cell / 2.0);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(encode(&[91.0, 0.0], 5), None);
+        assert_eq!(encode(&[0.0, f64::NAN], 5), None);
+        assert_eq!(encode(&[0.0, 0.0], 0), None);
+        assert_eq!(encode(&[0.0, 0.0], 13), None);
+        let coords = [0.0f64, 0.0];
+        let mut out = [0u8; 4];
+        let status = unsafe { geohash_encode_batch(coords.as_ptr(), 16, 5, out.as_mut_ptr(), 4) };
+        assert_eq!(status, -1, "short output");
+    }
+}
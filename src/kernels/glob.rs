@@ -0,0 +1,397 @@
+//! Prefix, suffix and glob matching over string batches.
+//!
+//! These cover the common filters without the `regex` feature's parser and
+//! DFA. Each export writes a bitmap with bit `i % 8` of byte `i / 8` set when
+//! row `i` matches, and returns the bytes written (`ceil(rows / 8)`), or `-1`
+//! for a bad pattern, a bad span or a short output.
+//!
+//! Glob patterns use `*` for any run of bytes and `?` for any single byte;
+//! `\` makes the next byte literal. Matching is byte-wise, so `?` consumes one
+//! byte of a multi-byte UTF-8 character.
+
+use super::spans;
+use crate::ffi;
+
+/// Compare two 16-byte blocks, treating lanes where `any` is `0xFF` as
+/// equal. Zero-padded tails compare equal.
+#[inline(always)]
+fn block_eq(a: [u8; 16], b: [u8; 16], any: [u8; 16]) -> bool {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let a = v128_load(a.as_ptr() as *const v128);
+        let b = v128_load(b.as_ptr() as *const v128);
+        let any = v128_load(any.as_ptr() as *const v128);
+        u8x16_all_true(v128_or(u8x16_eq(a, b), any))
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        (0..16).all(|i| a[i] == b[i] || any[i] != 0)
+    }
+}
+
+/// A run of pattern bytes between stars; `any` flags the `?` positions.
+struct Segment {
+    bytes: Vec<u8>,
+    any: Vec<u8>,
+}
+
+impl Segment {
+    fn literal(bytes: &[u8]) -> Segment {
+        Segment {
+            bytes: bytes.to_vec(),
+            any: vec![0; bytes.len()],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// `value` equals the segment, 16 bytes at a time. `value` must be
+    /// exactly `self.len()` bytes.
+    fn eq(&self, value: &[u8]) -> bool {
+        let chunks = value.chunks(16).zip(self.bytes.chunks(16));
+        chunks.zip(self.any.chunks(16)).all(|((v, p), any)| {
+            let mut a = [0u8; 16];
+            let mut b = [0u8; 16];
+            let mut m = [0u8; 16];
+            a[..v.len()].copy_from_slice(v);
+            b[..p.len()].copy_from_slice(p);
+            m[..any.len()].copy_from_slice(any);
+            block_eq(a, b, m)
+        })
+    }
+
+    fn is_prefix_of(&self, value: &[u8]) -> bool {
+        value.len() >= self.len() && self.eq(&value[..self.len()])
+    }
+
+    fn is_suffix_of(&self, value: &[u8]) -> bool {
+        value.len() >= self.len() && self.eq(&value[value.len() - self.len()..])
+    }
+
+    /// Start of the leftmost occurrence in `value`.
+    fn find(&self, value: &[u8]) -> Option<usize> {
+        if value.len() < self.len() {
+            return None;
+        }
+        // Skip candidates on the first literal byte before the full compare.
+        let first = self.any.iter().position(|&a| a == 0);
+        (0..=value.len() - self.len()).find(|&i| {
+            first.is_none_or(|f| value[i + f] == self.bytes[f])
+                && self.eq(&value[i..i + self.len()])
+        })
+    }
+}
+
+/// A glob split on `*`: `segments[0]` is anchored at the start and, when
+/// there is at least one star, the last segment at the end.
+struct Glob {
+    segments: Vec<Segment>,
+}
+
+impl Glob {
+    fn parse(pattern: &[u8]) -> Option<Glob> {
+        let mut segments = vec![Segment::literal(&[])];
+        let mut bytes = pattern.iter();
+        while let Some(&b) = bytes.next() {
+            let segment = segments.last_mut().unwrap();
+            match b {
+                b'*' => segments.push(Segment::literal(&[])),
+                b'?' => {
+                    segment.bytes.push(0);
+                    segment.any.push(0xFF);
+                }
+                b'\\' => {
+                    segment.bytes.push(*bytes.next()?);
+                    segment.any.push(0);
+                }
+                b => {
+                    segment.bytes.push(b);
+                    segment.any.push(0);
+                }
+            }
+        }
+        Some(Glob { segments })
+    }
+
+    fn matches(&self, value: &[u8]) -> bool {
+        let (first, rest) = self.segments.split_first().unwrap();
+        let Some((last, middle)) = rest.split_last() else {
+            return value.len() == first.len() && first.eq(value);
+        };
+        if value.len() < first.len() + last.len()
+            || !first.is_prefix_of(value)
+            || !last.is_suffix_of(value)
+        {
+            return false;
+        }
+        // Greedy leftmost placement of the middle segments is optimal: each
+        // has a fixed length, so matching it earlier never hurts later ones.
+        let mut rest = &value[first.len()..value.len() - last.len()];
+        for segment in middle {
+            match segment.find(rest) {
+                Some(i) => rest = &rest[i + segment.len()..],
+                None => return false,
+            }
+        }
+        true
+    }
+}
+
+/// Shared driver: set one bitmap bit per row for which `test` holds.
+#[allow(clippy::too_many_arguments)]
+unsafe fn match_batch(
+    text_ptr: *const u8,
+    text_len: usize,
+    offsets_ptr: *const u32,
+    offsets_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+    test: impl Fn(&[u8]) -> bool,
+) -> isize {
+    let rows = offsets_len.saturating_sub(1);
+    let bytes = rows.div_ceil(8);
+    if out_len < bytes {
+        return -1;
+    }
+    let text = ffi::slice(text_ptr, text_len);
+    let offsets = ffi::slice(offsets_ptr, offsets_len);
+    let out = ffi::slice_mut(out_ptr, bytes);
+    out.fill(0);
+    for (i, value) in spans(text, offsets).enumerate() {
+        match value {
+            Some(value) if test(value) => out[i / 8] |= 1 << (i % 8),
+            Some(_) => {}
+            None => return -1,
+        }
+    }
+    bytes as isize
+}
+
+/// Flag the rows of a string batch that start with the bytes at
+/// `prefix_ptr`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn starts_with_batch(
+    prefix_ptr: *const u8,
+    prefix_len: usize,
+    text_ptr: *const u8,
+    text_len: usize,
+    offsets_ptr: *const u32,
+    offsets_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let prefix = Segment::literal(ffi::slice(prefix_ptr, prefix_len));
+    match_batch(
+        text_ptr,
+        text_len,
+        offsets_ptr,
+        offsets_len,
+        out_ptr,
+        out_len,
+        |v| prefix.is_prefix_of(v),
+    )
+}
+
+/// Flag the rows of a string batch that end with the bytes at `suffix_ptr`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn ends_with_batch(
+    suffix_ptr: *const u8,
+    suffix_len: usize,
+    text_ptr: *const u8,
+    text_len: usize,
+    offsets_ptr: *const u32,
+    offsets_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let suffix = Segment::literal(ffi::slice(suffix_ptr, suffix_len));
+    match_batch(
+        text_ptr,
+        text_len,
+        offsets_ptr,
+        offsets_len,
+        out_ptr,
+        out_len,
+        |v| suffix.is_suffix_of(v),
+    )
+}
+
+/// Flag the rows of a string batch that the glob at `pattern_ptr` matches
+/// in full. A pattern ending in a lone `\` is rejected with `-1`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn glob_match_batch(
+    pattern_ptr: *const u8,
+    pattern_len: usize,
+    text_ptr: *const u8,
+    text_len: usize,
+    offsets_ptr: *const u32,
+    offsets_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let Some(glob) = Glob::parse(ffi::slice(pattern_ptr, pattern_len)) else {
+        return -1;
+    };
+    match_batch(
+        text_ptr,
+        text_len,
+        offsets_ptr,
+        offsets_len,
+        out_ptr,
+        out_len,
+        |v| glob.matches(v),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Export = unsafe extern "C" fn(
+        *const u8,
+        usize,
+        *const u8,
+        usize,
+        *const u32,
+        usize,
+        *mut u8,
+        usize,
+    ) -> isize;
+
+    fn run(export: Export, pattern: &str, values: &[&str]) -> Vec<bool> {
+        let text: String = values.concat();
+        let mut offsets = vec![0u32];
+        for v in values {
+            offsets.push(offsets.last().unwrap() + v.len() as u32);
+        }
+        let mut out = vec![0xFFu8; values.len().div_ceil(8)];
+        let written = unsafe {
+            export(
+                pattern.as_ptr(),
+                pattern.len(),
+                text.as_ptr(),
+                text.len(),
+                offsets.as_ptr(),
+                offsets.len(),
+                out.as_mut_ptr(),
+                out.len(),
+            )
+        };
+        assert_eq!(written, out.len() as isize);
+        (0..values.len())
+            .map(|i| out[i / 8] & (1 << (i % 8)) != 0)
+            .collect()
+    }
+
+    #[test]
+    fn prefix_and_suffix() {
+        let values = ["image.png", "img", "", "image", "archive.tar.gz", "x.png"];
+        assert_eq!(
+            run(starts_with_batch, "image", &values),
+            [true, false, false, true, false, false]
+        );
+        assert_eq!(
+            run(ends_with_batch, ".png", &values),
+            [true, false, false, false, false, true]
+        );
+        assert_eq!(run(starts_with_batch, "", &values), [true; 6]);
+
+        // Longer than one SIMD block.
+        let long = "/var/log/service/2024/01/31/app.log";
+        assert_eq!(
+            run(
+                starts_with_batch,
+                "/var/log/service/2024/",
+                &[long, "/var/log/service/2025/x"]
+            ),
+            [true, false]
+        );
+    }
+
+    #[test]
+    fn glob_patterns() {
+        let cases = [
+            ("*.log", "app.log", true),
+            ("*.log", "app.log.1", false),
+            ("app-??.log", "app-01.log", true),
+            ("app-??.log", "app-1.log", false),
+            ("*", "", true),
+            ("", "", true),
+            ("", "a", false),
+            ("a*b*c", "abc", true),
+            ("a*b*c", "aXbYbZc", true),
+            ("a*b*c", "acb", false),
+            ("*ab*ab*", "abab", true),
+            ("*aba*", "abab", true),
+            ("a*a", "a", false),
+            ("*\\**", "a*b", true),
+            ("*\\**", "ab", false),
+            ("?", "é", false),
+            ("??", "é", true),
+            ("/var/*/2024-??-??/*.gz", "/var/log/2024-01-31/a.gz", true),
+        ];
+        for (pattern, value, expected) in cases {
+            assert_eq!(
+                run(glob_match_batch, pattern, &[value]),
+                [expected],
+                "{pattern:?} on {value:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let mut out = [0u8; 1];
+        let offsets = [0u32, 1];
+        let status = unsafe {
+            glob_match_batch(
+                b"a\\".as_ptr(),
+                2,
+                b"a".as_ptr(),
+                1,
+                offsets.as_ptr(),
+                2,
+                out.as_mut_ptr(),
+                1,
+            )
+        };
+        assert_eq!(status, -1, "trailing escape");
+
+        let offsets = [0u32, 5];
+        let status = unsafe {
+            starts_with_batch(
+                b"a".as_ptr(),
+                1,
+                b"a".as_ptr(),
+                1,
+                offsets.as_ptr(),
+                2,
+                out.as_mut_ptr(),
+                1,
+            )
+        };
+        assert_eq!(status, -1, "span past the text");
+
+        let offsets = [0u32; 10];
+        let status = unsafe {
+            ends_with_batch(
+                b"".as_ptr(),
+                0,
+                b"".as_ptr(),
+                0,
+                offsets.as_ptr(),
+                10,
+                out.as_mut_ptr(),
+                1,
+            )
+        };
+        assert_eq!(status, -1, "short bitmap");
+    }
+}
@@ -0,0 +1,217 @@
+//! Graph traversal over CSR adjacency arrays.
+//!
+//! A graph of `n` nodes uses the same layout as `sparse`: `indptr` holds
+//! `n + 1` ascending `u32` offsets into `indices`, and node `v`'s outgoing
+//! edges go to `indices[indptr[v]..indptr[v + 1]]`. Every kernel first checks
+//! that the offsets are ascending and that every target is a node.
+
+use std::collections::VecDeque;
+
+use crate::ffi;
+
+/// Whether `indptr` and `indices` describe a graph of `indptr.len() - 1`
+/// nodes.
+fn valid_csr(indptr: &[u32], indices: &[u32]) -> bool {
+    let nodes = indptr.len().saturating_sub(1);
+    indptr.windows(2).all(|w| w[0] <= w[1])
+        && indptr
+            .last()
+            .is_none_or(|&end| end as usize <= indices.len())
+        && indices.iter().all(|&v| (v as usize) < nodes)
+}
+
+fn neighbors<'a>(indptr: &[u32], indices: &'a [u32], v: usize) -> &'a [u32] {
+    &indices[indptr[v] as usize..indptr[v + 1] as usize]
+}
+
+/// Breadth-first search from `source`, writing each node's hop count as an
+/// `i32` to `out_ptr` (`0` for the source, `-1` when unreachable). Edges are
+/// followed in their stored direction.
+///
+/// Returns bytes written (`nodes * 4`), or `-1` for a malformed graph, a
+/// `source` that is not a node, or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bfs_levels(
+    indptr_ptr: *const u32,
+    indptr_len: usize,
+    indices_ptr: *const u32,
+    indices_len: usize,
+    source: u32,
+    out_ptr: *mut i32,
+    out_len_bytes: usize,
+) -> isize {
+    let nodes = indptr_len.saturating_sub(1);
+    let indptr = ffi::slice(indptr_ptr, indptr_len);
+    let indices = ffi::slice(indices_ptr, indices_len);
+    if source as usize >= nodes || out_len_bytes / 4 < nodes || !valid_csr(indptr, indices) {
+        return -1;
+    }
+    let levels = ffi::slice_mut(out_ptr, nodes);
+    levels.fill(-1);
+    levels[source as usize] = 0;
+    let mut queue = VecDeque::from([source as usize]);
+    while let Some(v) = queue.pop_front() {
+        let next = levels[v] + 1;
+        for &w in neighbors(indptr, indices, v) {
+            if levels[w as usize] < 0 {
+                levels[w as usize] = next;
+                queue.push_back(w as usize);
+            }
+        }
+    }
+    (nodes * 4) as isize
+}
+
+fn find(parent: &mut [u32], mut v: u32) -> u32 {
+    while parent[v as usize] != v {
+        // Path halving.
+        parent[v as usize] = parent[parent[v as usize] as usize];
+        v = parent[v as usize];
+    }
+    v
+}
+
+/// Label the weakly connected components of the graph (edge direction is
+/// ignored), writing one `u32` label per node to `out_ptr`. Labels are
+/// numbered from `0` in order of each component's lowest node, so the
+/// component count is the largest label plus one.
+///
+/// Returns bytes written (`nodes * 4`), or `-1` for a malformed graph or a
+/// short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn connected_components(
+    indptr_ptr: *const u32,
+    indptr_len: usize,
+    indices_ptr: *const u32,
+    indices_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let nodes = indptr_len.saturating_sub(1);
+    let indptr = ffi::slice(indptr_ptr, indptr_len);
+    let indices = ffi::slice(indices_ptr, indices_len);
+    if out_len_bytes / 4 < nodes || !valid_csr(indptr, indices) {
+        return -1;
+    }
+    // Union by smaller root keeps every root the lowest node of its set.
+    let mut parent: Vec<u32> = (0..nodes as u32).collect();
+    for v in 0..nodes {
+        for &w in neighbors(indptr, indices, v) {
+            let (a, b) = (find(&mut parent, v as u32), find(&mut parent, w));
+            parent[a.max(b) as usize] = a.min(b);
+        }
+    }
+    let labels = ffi::slice_mut(out_ptr, nodes);
+    let mut count = 0;
+    for v in 0..nodes {
+        let root = find(&mut parent, v as u32) as usize;
+        // Roots come first in node order, so their labels are already set.
+        labels[v] = if root == v {
+            count += 1;
+            count - 1
+        } else {
+            labels[root]
+        };
+    }
+    (nodes * 4) as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// CSR arrays for the directed edges `edges` over `nodes` nodes.
+    fn csr(nodes: usize, edges: &[(u32, u32)]) -> (Vec<u32>, Vec<u32>) {
+        let mut indptr = vec![0u32; nodes + 1];
+        for &(from, _) in edges {
+            indptr[from as usize + 1] += 1;
+        }
+        for i in 0..nodes {
+            indptr[i + 1] += indptr[i];
+        }
+        let mut fill = indptr.clone();
+        let mut indices = vec![0u32; edges.len()];
+        for &(from, to) in edges {
+            indices[fill[from as usize] as usize] = to;
+            fill[from as usize] += 1;
+        }
+        (indptr, indices)
+    }
+
+    #[test]
+    fn bfs_follows_edge_direction() {
+        // 0 -> 1 -> 2 -> 3, 0 -> 2, 4 -> 0.
+        let (indptr, indices) = csr(5, &[(0, 1), (1, 2), (2, 3), (0, 2), (4, 0)]);
+        let mut levels = [i32::MAX; 5];
+        let written = unsafe {
+            bfs_levels(
+                indptr.as_ptr(),
+                6,
+                indices.as_ptr(),
+                indices.len(),
+                0,
+                levels.as_mut_ptr(),
+                20,
+            )
+        };
+        assert_eq!(written, 20);
+        assert_eq!(levels, [0, 1, 1, 2, -1]);
+
+        let status = unsafe {
+            bfs_levels(
+                indptr.as_ptr(),
+                6,
+                indices.as_ptr(),
+                indices.len(),
+                5,
+                levels.as_mut_ptr(),
+                20,
+            )
+        };
+        assert_eq!(status, -1, "source out of range");
+    }
+
+    #[test]
+    fn components_ignore_direction() {
+        // {0, 3, 5} via 3 -> 0 and 5 -> 3; {1, 4}; {2} alone.
+        let (indptr, indices) = csr(6, &[(3, 0), (5, 3), (4, 1), (2, 2)]);
+        let mut labels = [u32::MAX; 6];
+        let written = unsafe {
+            connected_components(
+                indptr.as_ptr(),
+                7,
+                indices.as_ptr(),
+                indices.len(),
+                labels.as_mut_ptr(),
+                24,
+            )
+        };
+        assert_eq!(written, 24);
+        assert_eq!(labels, [0, 1, 2, 0, 1, 0]);
+    }
+
+    #[test]
+    fn rejects_malformed_graphs() {
+        let mut out = [0u32; 2];
+        let cases: [(&[u32], &[u32]); 3] = [
+            (&[0, 2, 1], &[0, 1]),
+            (&[0, 1, 3], &[0, 1]),
+            (&[0, 1, 1], &[2]),
+        ];
+        for (indptr, indices) in cases {
+            let status = unsafe {
+                connected_components(
+                    indptr.as_ptr(),
+                    indptr.len(),
+                    indices.as_ptr(),
+                    indices.len(),
+                    out.as_mut_ptr(),
+                    8,
+                )
+            };
+            assert_eq!(status, -1, "{indptr:?} {indices:?}");
+        }
+    }
+}
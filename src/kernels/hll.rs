@@ -0,0 +1,199 @@
+//! HyperLogLog distinct-count sketches.
+//!
+//! A sketch of precision `p` keeps `2^p` one-byte registers (16 KiB at the
+//! usual `p = 14`) and estimates the number of distinct values fed to it with
+//! a relative standard error of about `1.04 / sqrt(2^p)`. Callers feed 64-bit
+//! hashes rather than raw values, so any key type works as long as it is
+//! hashed the same way everywhere; sketches with the same precision merge
+//! losslessly.
+
+use crate::error::{self, ErrorCode};
+#[cfg(feature = "multi-value")]
+use crate::multi_value::{multi_value_export, Status};
+use crate::{ffi, handles};
+
+const MIN_PRECISION: u32 = 4;
+const MAX_PRECISION: u32 = 18;
+
+pub(crate) struct Hll {
+    p: u32,
+    registers: Vec<u8>,
+}
+
+impl Hll {
+    /// An empty sketch; `precision` must lie in `4..=18`.
+    pub(crate) fn new(precision: u32) -> Self {
+        Hll {
+            p: precision,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    pub(crate) fn add(&mut self, hash: u64) {
+        let index = (hash >> (64 - self.p)) as usize;
+        // Rank of the first set bit in the remaining bits; the sentinel bit
+        // caps it when they are all zero.
+        let rest = (hash << self.p) | (1 << (self.p - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        let register = &mut self.registers[index];
+        *register = (*register).max(rank);
+    }
+
+    pub(crate) fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let raw = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        // Linear counting is more accurate while many registers are empty.
+        if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        }
+    }
+}
+
+/// SplitMix64 finalizer: spreads a key (or a weak hash) over all 64 bits
+/// before it feeds a sketch.
+pub(crate) fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+/// Create an empty sketch with `2^precision` registers.
+///
+/// Returns a handle for the other `hll_` exports, released with
+/// `handle_drop`, or `-1` when `precision` is outside `4..=18`.
+#[no_mangle]
+pub extern "C" fn hll_new(precision: u32) -> isize {
+    if !(MIN_PRECISION..=MAX_PRECISION).contains(&precision) {
+        return error::fail(ErrorCode::InvalidArgument);
+    }
+    handles::insert(Hll::new(precision)) as isize
+}
+
+/// Add the `u64` hashes at `hashes_ptr` to the sketch behind `handle`.
+/// Adding a hash again does not change the sketch.
+///
+/// Returns `0`, or `-1` for an unknown handle or an input that is not a
+/// whole number of hashes.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn hll_add_batch(
+    handle: u32,
+    hashes_ptr: *const u64,
+    hashes_len_bytes: usize,
+) -> isize {
+    if !hashes_len_bytes.is_multiple_of(8) {
+        return error::fail(ErrorCode::BadLength);
+    }
+    let hashes = ffi::slice(hashes_ptr, hashes_len_bytes / 8);
+    handles::with(handle, |hll: &mut Hll| {
+        for &hash in hashes {
+            hll.add(hash);
+        }
+        0
+    })
+    .unwrap_or(-1)
+}
+
+/// Estimate how many distinct hashes the sketch behind `handle` has seen.
+///
+/// Returns the estimate as an `f64` (counts past `2^31` do not fit the usual
+/// `isize` return), or `-1` for an unknown handle.
+#[cfg_attr(not(feature = "multi-value"), no_mangle)]
+pub extern "C" fn hll_count(handle: u32) -> f64 {
+    handles::with(handle, |hll: &mut Hll| hll.estimate()).unwrap_or(-1.0)
+}
+
+/// `hll_count` in `multi-value` builds: returns `(status, estimate)`.
+#[cfg(feature = "multi-value")]
+#[export_name = "hll_count"]
+pub extern "C" fn hll_count_multi_value(handle: u32) -> Status<f64> {
+    multi_value_export!(hll_count, "i32 f64");
+    let count = hll_count(handle);
+    Status::new(if count < 0.0 { -1 } else { 0 }, count)
+}
+
+/// Merge the sketch behind `b` into the one behind `a`, which then counts
+/// the union of both inputs. `b` is left unchanged.
+///
+/// Returns `0`, or `-1` for an unknown handle or sketches of different
+/// precision.
+#[no_mangle]
+pub extern "C" fn hll_merge(a: u32, b: u32) -> isize {
+    // `handles::with` cannot nest, so copy `b`'s registers out first.
+    let Some(other) = handles::with(b, |hll: &mut Hll| hll.registers.clone()) else {
+        return -1;
+    };
+    handles::with(a, |hll: &mut Hll| {
+        if hll.registers.len() != other.len() {
+            return error::fail(ErrorCode::InvalidArgument);
+        }
+        for (r, &o) in hll.registers.iter_mut().zip(&other) {
+            *r = (*r).max(o);
+        }
+        0
+    })
+    .unwrap_or(-1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sketch(precision: u32, keys: impl Iterator<Item = u64>) -> u32 {
+        let handle = hll_new(precision) as u32;
+        let hashes: Vec<u64> = keys.map(mix).collect();
+        let status = unsafe { hll_add_batch(handle, hashes.as_ptr(), hashes.len() * 8) };
+        assert_eq!(status, 0);
+        handle
+    }
+
+    fn assert_close(estimate: f64, exact: f64, tolerance: f64) {
+        let error = (estimate - exact).abs() / exact;
+        assert!(error < tolerance, "estimate {estimate} for {exact}");
+    }
+
+    #[test]
+    fn estimates_small_and_large_counts() {
+        for n in [10, 1_000, 200_000] {
+            // Each key twice: duplicates must not count.
+            let handle = sketch(14, (0..n).chain(0..n));
+            assert_close(hll_count(handle), n as f64, 0.03);
+        }
+        assert_eq!(hll_count(hll_new(14) as u32), 0.0);
+    }
+
+    #[test]
+    fn merge_counts_the_union() {
+        let a = sketch(12, 0..60_000);
+        let b = sketch(12, 40_000..100_000);
+        assert_eq!(hll_merge(a, b), 0);
+        assert_close(hll_count(a), 100_000.0, 0.05);
+        assert_close(hll_count(b), 60_000.0, 0.05);
+
+        let c = sketch(13, 0..10);
+        assert_eq!(hll_merge(a, c), -1, "precision mismatch");
+        assert_eq!(hll_merge(a, 0), -1);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(hll_new(3), -1);
+        assert_eq!(hll_new(19), -1);
+        let handle = hll_new(4) as u32;
+        let hashes = [1u64];
+        assert_eq!(unsafe { hll_add_batch(handle, hashes.as_ptr(), 4) }, -1);
+        assert_eq!(handles::handle_drop(handle), 0);
+        assert_eq!(unsafe { hll_add_batch(handle, hashes.as_ptr(), 8) }, -1);
+        assert_eq!(hll_count(handle), -1.0);
+    }
+}
@@ -0,0 +1,271 @@
+//! HTTP/1.1 response head scanning.
+//!
+//! `http_scan_headers` reads the status line and header fields of a raw
+//! response (RFC 9112) and reports the status code, where the body starts
+//! and the name and value span of every field. Line feeds, carriage returns
+//! and colons are found together, sixteen bytes at a time, as `i8x16_eq`
+//! bitmasks under `simd128` like the line splitters, so an archive of
+//! responses can be indexed without decoding each head to a JS string.
+
+use crate::ffi;
+
+/// `u32` words per header field in `http_scan_headers` output, after the
+/// two-word `[status, body_start]` prefix.
+const FIELD_WORDS: usize = 4;
+
+/// Bitmasks of the line feeds, carriage returns and colons among the 16
+/// bytes of `buf` at `at`. Only the final partial block is copied, into a
+/// zero-padded buffer whose padding matches none of them.
+#[inline(always)]
+fn block_masks(buf: &[u8], at: usize) -> (u16, u16, u16) {
+    let chunk = &buf[at..(at + 16).min(buf.len())];
+
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let v = if chunk.len() == 16 {
+            v128_load(chunk.as_ptr() as *const v128)
+        } else {
+            let mut block = [0u8; 16];
+            block[..chunk.len()].copy_from_slice(chunk);
+            v128_load(block.as_ptr() as *const v128)
+        };
+        let bits = |b: u8| i8x16_bitmask(i8x16_eq(v, i8x16_splat(b as i8)));
+        (bits(b'\n'), bits(b'\r'), bits(b':'))
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        let bits = |b: u8| {
+            (chunk.iter().enumerate()).fold(0u16, |mask, (i, &c)| mask | ((c == b) as u16) << i)
+        };
+        (bits(b'\n'), bits(b'\r'), bits(b':'))
+    }
+}
+
+/// Call `line(start, end, colon)` for each line of `buf[from..]`, where
+/// `end` excludes the line break and `colon` is the first `:` in the line,
+/// until it returns `false`. Returns `None` if the buffer ends mid-line.
+fn for_each_line(
+    buf: &[u8],
+    from: usize,
+    mut line: impl FnMut(usize, usize, Option<usize>) -> bool,
+) -> Option<()> {
+    let (mut start, mut colon) = (from, None);
+    let mut block_start = from;
+    // Lane 15 of the previous block was a CR.
+    let mut carry_cr = 0u16;
+    while block_start < buf.len() {
+        let (lf, cr, colons) = block_masks(buf, block_start);
+        // Line feeds ending a CRLF pair, which excludes the CR from the line.
+        let crlf = lf & ((cr << 1) | carry_cr);
+        let mut mask = lf | colons;
+        while mask != 0 {
+            let lane = mask.trailing_zeros();
+            let i = block_start + lane as usize;
+            mask &= mask - 1;
+            if colons & (1 << lane) != 0 {
+                colon = colon.or(Some(i));
+                continue;
+            }
+            let end = if i > start && crlf & (1 << lane) != 0 {
+                i - 1
+            } else {
+                i
+            };
+            if !line(start, end, colon) {
+                return Some(());
+            }
+            (start, colon) = (i + 1, None);
+        }
+        carry_cr = cr >> 15;
+        block_start += 16;
+    }
+    None
+}
+
+fn is_token(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// Trim spaces and tabs from both ends of `buf[start..end]`.
+fn trim_ows(buf: &[u8], mut start: usize, mut end: usize) -> (usize, usize) {
+    while start < end && matches!(buf[start], b' ' | b'\t') {
+        start += 1;
+    }
+    while end > start && matches!(buf[end - 1], b' ' | b'\t') {
+        end -= 1;
+    }
+    (start, end)
+}
+
+/// Parse a response head into `[status, body_start]` and then
+/// `[name_start, name_end, value_start, value_end]` per field.
+fn scan(buf: &[u8], out: &mut Vec<u32>) -> Option<()> {
+    // Status line: `HTTP/1.1 200 Reason`.
+    let status = buf.get(..12).filter(|head| {
+        head.starts_with(b"HTTP/")
+            && head[5].is_ascii_digit()
+            && head[6] == b'.'
+            && head[7].is_ascii_digit()
+            && head[8] == b' '
+            && head[9..12].iter().all(u8::is_ascii_digit)
+    })?;
+    let code = status[9..12]
+        .iter()
+        .fold(0, |n, &b| n * 10 + (b - b'0') as u32);
+    out.extend([code, 0]);
+    let mut first = true;
+    let mut body_start = None;
+    let mut malformed = false;
+    for_each_line(buf, 0, |start, end, colon| {
+        if first {
+            first = false;
+            malformed = end > 12 && buf[12] != b' ';
+            return !malformed;
+        }
+        if start == end {
+            body_start = Some(end + if buf[end] == b'\r' { 2 } else { 1 });
+            return false;
+        }
+        if matches!(buf[start], b' ' | b'\t') {
+            // obs-fold: the line continues the previous field's value.
+            if out.len() == 2 {
+                malformed = true;
+                return false;
+            }
+            let (_, folded_end) = trim_ows(buf, start, end);
+            if folded_end > start {
+                *out.last_mut().unwrap() = folded_end as u32;
+            }
+            return true;
+        }
+        let Some(colon) =
+            colon.filter(|&c| c > start && buf[start..c].iter().all(|&b| is_token(b)))
+        else {
+            malformed = true;
+            return false;
+        };
+        let (value_start, value_end) = trim_ows(buf, colon + 1, end);
+        out.extend([start, colon, value_start, value_end].map(|v| v as u32));
+        true
+    })?;
+    out[1] = body_start? as u32;
+    (!malformed).then_some(())
+}
+
+/// Scan the HTTP/1.x response head at the start of `in_ptr`. Writes
+/// `[status, body_start]`, then four `u32`s per header field in order:
+/// `[name_start, name_end, value_start, value_end]`. The value excludes
+/// surrounding whitespace. A value continued on following lines (obs-fold)
+/// spans them, line breaks included; replace each `\r\n` and the whitespace
+/// after it with a single space. Lines may end in CRLF or bare LF.
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for a
+/// bad status line, a malformed field, a head with no terminating empty
+/// line, or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn http_scan_headers(
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let buf = ffi::slice(in_ptr, in_len);
+    let mut fields = Vec::new();
+    if u32::try_from(in_len).is_err() || scan(buf, &mut fields).is_none() {
+        return -1;
+    }
+    debug_assert_eq!((fields.len() - 2) % FIELD_WORDS, 0);
+    let needed = fields.len() * 4;
+    if out_len_bytes == 0 {
+        return needed as isize;
+    }
+    if out_len_bytes < needed {
+        return -1;
+    }
+    if ffi::aliased(in_ptr, in_len, out_ptr as *const u8, needed) {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(out_ptr, fields.len()).copy_from_slice(&fields);
+    needed as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Status, body start and `(name, value)` fields.
+    type Head<'a> = (u32, u32, Vec<(&'a str, &'a str)>);
+
+    fn head(buf: &[u8]) -> Option<Head<'_>> {
+        let call = |out: &mut [u32]| unsafe {
+            http_scan_headers(buf.as_ptr(), buf.len(), out.as_mut_ptr(), out.len() * 4)
+        };
+        let needed = call(&mut []);
+        let mut out = vec![0u32; (needed.max(0) / 4) as usize];
+        if needed < 0 || call(&mut out) != needed {
+            return None;
+        }
+        let text = |a: u32, b: u32| std::str::from_utf8(&buf[a as usize..b as usize]).unwrap();
+        let fields = out[2..]
+            .chunks(FIELD_WORDS)
+            .map(|f| (text(f[0], f[1]), text(f[2], f[3])))
+            .collect();
+        Some((out[0], out[1], fields))
+    }
+
+    #[test]
+    fn scans_fields() {
+        let buf = b"HTTP/1.1 404 Not Found\r\n\
+Content-Type:text/html; charset=utf-8\r\n\
+X-Long-Header-Name-To-Span-Blocks:   padded value \t\r\n\
+Empty:\r\n\
+Link: <a:b>; rel=x\r\n\r\nbody: text\r\n";
+        let (status, body, fields) = head(buf).unwrap();
+        assert_eq!(status, 404);
+        assert_eq!(&buf[body as usize..], b"body: text\r\n");
+        assert_eq!(
+            fields,
+            [
+                ("Content-Type", "text/html; charset=utf-8"),
+                ("X-Long-Header-Name-To-Span-Blocks", "padded value"),
+                ("Empty", ""),
+                ("Link", "<a:b>; rel=x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn handles_folds_and_bare_line_feeds() {
+        let buf = b"HTTP/1.0 200\nX-Folded: one\n  two\n\tthree \nA: b\n\n";
+        let (status, body, fields) = head(buf).unwrap();
+        assert_eq!((status, body as usize), (200, buf.len()));
+        assert_eq!(fields, [("X-Folded", "one\n  two\n\tthree"), ("A", "b")]);
+    }
+
+    #[test]
+    fn crlf_straddling_blocks_ends_the_line() {
+        // The status line ends with the CR in lane 15 and the LF in lane 0
+        // of the next block; the field's CRLF straddles the second boundary.
+        let buf = b"HTTP/1.1 200 OK\r\nX-Padding-Na:v\r\n\r\n";
+        assert_eq!(&buf[15..17], b"\r\n");
+        assert_eq!(&buf[31..33], b"\r\n");
+        let (status, body, fields) = head(buf).unwrap();
+        assert_eq!((status, body as usize), (200, buf.len()));
+        assert_eq!(fields, [("X-Padding-Na", "v")]);
+    }
+
+    #[test]
+    fn rejects_malformed_heads() {
+        assert_eq!(head(b"HTTP/1.1 200 OK\r\nA: b\r\n"), None, "no end");
+        assert_eq!(head(b"HTTP/1.1 200 OK\r\nA : b\r\n\r\n"), None);
+        assert_eq!(head(b"HTTP/1.1 200 OK\r\nno colon\r\n\r\n"), None);
+        assert_eq!(head(b"HTTP/1.1 200 OK\r\n folded\r\n\r\n"), None);
+        assert_eq!(head(b"HTTP/1.1 20x OK\r\n\r\n"), None);
+        assert_eq!(head(b"HTTP/1.1 2000\r\n\r\n"), None);
+        assert_eq!(head(b"GET / HTTP/1.1\r\n\r\n"), None);
+    }
+}
@@ -0,0 +1,716 @@
+//! Memory-hard key derivation: Argon2 and scrypt (`kdf` feature).
+//!
+//! Both derive encryption keys from passwords in the browser, where the JS
+//! implementations are several times slower. `argon2_kdf` follows RFC 9106
+//! (version 0x13) for Argon2d, Argon2i and Argon2id; lanes are filled one
+//! after another, since wasm has no threads here, so `parallelism` changes
+//! the result but not the speed. `scrypt_kdf` follows RFC 7914.
+//!
+//! The memory is allocated up front and a cost that does not fit returns
+//! `-1` instead of trapping. With the `progress` feature both report how
+//! far they are, in Argon2 segments or scrypt mixing steps.
+
+use super::merkle::sha256;
+use crate::progress::Progress;
+use crate::{cancel, ffi};
+
+/// Argon2 variants, numbered as in RFC 9106.
+pub const ARGON2_D: u32 = 0;
+pub const ARGON2_I: u32 = 1;
+pub const ARGON2_ID: u32 = 2;
+
+const ARGON2_VERSION: u32 = 0x13;
+/// 64-bit words in a 1 KiB Argon2 block.
+const WORDS: usize = 128;
+const SYNC_POINTS: usize = 4;
+
+type Block = [u64; WORDS];
+
+/// Reserve `n` elements, or `None` when the allocation fails.
+fn try_vec<T: Clone>(value: T, n: usize) -> Option<Vec<T>> {
+    let mut v = Vec::new();
+    v.try_reserve_exact(n).ok()?;
+    v.resize(n, value);
+    Some(v)
+}
+
+const BLAKE2B_IV: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+const SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+fn blake2b_compress(h: &mut [u64; 8], block: &[u8; 128], bytes: u128, last: bool) {
+    let m: [u64; 16] =
+        std::array::from_fn(|i| u64::from_le_bytes(block[8 * i..][..8].try_into().unwrap()));
+    let mut v = [0u64; 16];
+    v[..8].copy_from_slice(h);
+    v[8..].copy_from_slice(&BLAKE2B_IV);
+    v[12] ^= bytes as u64;
+    v[13] ^= (bytes >> 64) as u64;
+    if last {
+        v[14] = !v[14];
+    }
+    let g = |v: &mut [u64; 16], [a, b, c, d]: [usize; 4], x: u64, y: u64| {
+        v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+        v[d] = (v[d] ^ v[a]).rotate_right(32);
+        v[c] = v[c].wrapping_add(v[d]);
+        v[b] = (v[b] ^ v[c]).rotate_right(24);
+        v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+        v[d] = (v[d] ^ v[a]).rotate_right(16);
+        v[c] = v[c].wrapping_add(v[d]);
+        v[b] = (v[b] ^ v[c]).rotate_right(63);
+    };
+    for round in 0..12 {
+        let s = &SIGMA[round % 10];
+        let lanes = [
+            [0, 4, 8, 12],
+            [1, 5, 9, 13],
+            [2, 6, 10, 14],
+            [3, 7, 11, 15],
+            [0, 5, 10, 15],
+            [1, 6, 11, 12],
+            [2, 7, 8, 13],
+            [3, 4, 9, 14],
+        ];
+        for (i, lane) in lanes.into_iter().enumerate() {
+            g(&mut v, lane, m[s[2 * i]], m[s[2 * i + 1]]);
+        }
+    }
+    for i in 0..8 {
+        h[i] ^= v[i] ^ v[i + 8];
+    }
+}
+
+/// Unkeyed BLAKE2b of the concatenation of `parts`, with a 1- to 64-byte
+/// digest.
+fn blake2b(out_len: usize, parts: &[&[u8]]) -> Vec<u8> {
+    let data = parts.concat();
+    let mut h = BLAKE2B_IV;
+    h[0] ^= 0x0101_0000 ^ out_len as u64;
+    let mut block = [0u8; 128];
+    let mut chunks = data.chunks(128).peekable();
+    if chunks.peek().is_none() {
+        blake2b_compress(&mut h, &block, 0, true);
+    }
+    let mut done = 0u128;
+    while let Some(chunk) = chunks.next() {
+        block.fill(0);
+        block[..chunk.len()].copy_from_slice(chunk);
+        done += chunk.len() as u128;
+        blake2b_compress(&mut h, &block, done, chunks.peek().is_none());
+    }
+    let bytes: Vec<u8> = h.iter().flat_map(|w| w.to_le_bytes()).collect();
+    bytes[..out_len].to_vec()
+}
+
+/// Argon2's variable-length hash `H'`.
+fn blake2b_long(out_len: usize, input: &[&[u8]]) -> Vec<u8> {
+    let len = (out_len as u32).to_le_bytes();
+    let mut parts = vec![&len[..]];
+    parts.extend_from_slice(input);
+    if out_len <= 64 {
+        return blake2b(out_len, &parts);
+    }
+    let mut v = blake2b(64, &parts);
+    let mut out = v[..32].to_vec();
+    while out_len - out.len() > 64 {
+        v = blake2b(64, &[&v]);
+        out.extend_from_slice(&v[..32]);
+    }
+    out.extend(blake2b(out_len - out.len(), &[&v]));
+    out
+}
+
+/// The BlaMka mix: BLAKE2b's `G` with a multiplication added to each sum.
+fn blamka(v: &mut Block, a: usize, b: usize, c: usize, d: usize) {
+    let fma = |x: u64, y: u64| {
+        x.wrapping_add(y).wrapping_add(
+            2u64.wrapping_mul(x & 0xffff_ffff)
+                .wrapping_mul(y & 0xffff_ffff),
+        )
+    };
+    v[a] = fma(v[a], v[b]);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = fma(v[c], v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = fma(v[a], v[b]);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = fma(v[c], v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+/// The permutation `P` over the 16 words at `idx`.
+fn permute(v: &mut Block, idx: [usize; 16]) {
+    blamka(v, idx[0], idx[4], idx[8], idx[12]);
+    blamka(v, idx[1], idx[5], idx[9], idx[13]);
+    blamka(v, idx[2], idx[6], idx[10], idx[14]);
+    blamka(v, idx[3], idx[7], idx[11], idx[15]);
+    blamka(v, idx[0], idx[5], idx[10], idx[15]);
+    blamka(v, idx[1], idx[6], idx[11], idx[12]);
+    blamka(v, idx[2], idx[7], idx[8], idx[13]);
+    blamka(v, idx[3], idx[4], idx[9], idx[14]);
+}
+
+/// The compression function `G(x, y)`.
+fn compress(x: &Block, y: &Block) -> Block {
+    let r: Block = std::array::from_fn(|i| x[i] ^ y[i]);
+    let mut z = r;
+    for row in 0..8 {
+        permute(&mut z, std::array::from_fn(|i| 16 * row + i));
+    }
+    for col in 0..8 {
+        permute(
+            &mut z,
+            std::array::from_fn(|i| 16 * (i / 2) + 2 * col + i % 2),
+        );
+    }
+    std::array::from_fn(|i| z[i] ^ r[i])
+}
+
+struct Argon2 {
+    variant: u32,
+    passes: u32,
+    lanes: usize,
+    lane_len: usize,
+    memory: Vec<Block>,
+}
+
+impl Argon2 {
+    fn segment_len(&self) -> usize {
+        self.lane_len / SYNC_POINTS
+    }
+
+    /// The next block of data-independent addresses.
+    fn addresses(&self, pass: u32, lane: usize, slice: usize, counter: u64) -> Block {
+        let mut input = [0u64; WORDS];
+        input[..7].copy_from_slice(&[
+            pass as u64,
+            lane as u64,
+            slice as u64,
+            self.memory.len() as u64,
+            self.passes as u64,
+            self.variant as u64,
+            counter,
+        ]);
+        let zero = [0u64; WORDS];
+        compress(&zero, &compress(&zero, &input))
+    }
+
+    /// Column of the block referenced at `index` of a segment, given the
+    /// low 32 bits of its pseudo-random value.
+    fn reference(&self, pass: u32, slice: usize, index: usize, same_lane: bool, j1: u64) -> usize {
+        let seg = self.segment_len();
+        let finished = if pass == 0 {
+            slice * seg
+        } else {
+            self.lane_len - seg
+        };
+        let area = if same_lane {
+            finished + index - 1
+        } else {
+            finished - (index == 0) as usize
+        };
+        let x = (j1 * j1) >> 32;
+        let relative = area - 1 - ((area as u64 * x) >> 32) as usize;
+        let start = if pass == 0 || slice == SYNC_POINTS - 1 {
+            0
+        } else {
+            (slice + 1) * seg
+        };
+        (start + relative) % self.lane_len
+    }
+
+    fn fill_segment(&mut self, pass: u32, lane: usize, slice: usize) {
+        let seg = self.segment_len();
+        let independent =
+            self.variant == ARGON2_I || (self.variant == ARGON2_ID && pass == 0 && slice < 2);
+        let first = if pass == 0 && slice == 0 { 2 } else { 0 };
+        let mut addresses = [0u64; WORDS];
+        let mut counter = 0;
+        for index in first..seg {
+            let col = slice * seg + index;
+            let cur = lane * self.lane_len + col;
+            let prev = if col == 0 {
+                cur + self.lane_len - 1
+            } else {
+                cur - 1
+            };
+            let random = if independent {
+                if index % WORDS == 0 || index == first {
+                    counter += 1;
+                    addresses = self.addresses(pass, lane, slice, counter);
+                }
+                addresses[index % WORDS]
+            } else {
+                self.memory[prev][0]
+            };
+            let ref_lane = if pass == 0 && slice == 0 {
+                lane
+            } else {
+                (random >> 32) as usize % self.lanes
+            };
+            let ref_col =
+                self.reference(pass, slice, index, ref_lane == lane, random & 0xffff_ffff);
+            let mut block = compress(
+                &self.memory[prev],
+                &self.memory[ref_lane * self.lane_len + ref_col],
+            );
+            if pass > 0 {
+                for (b, old) in block.iter_mut().zip(&self.memory[cur]) {
+                    *b ^= old;
+                }
+            }
+            self.memory[cur] = block;
+        }
+    }
+}
+
+/// Argon2 with the optional `secret` and associated data of RFC 9106.
+#[allow(clippy::too_many_arguments)]
+fn argon2(
+    variant: u32,
+    password: &[u8],
+    salt: &[u8],
+    secret: &[u8],
+    ad: &[u8],
+    passes: u32,
+    memory_kib: u32,
+    lanes: u32,
+    out_len: usize,
+) -> Option<Vec<u8>> {
+    let le = |x: usize| (x as u32).to_le_bytes();
+    let h0 = blake2b(
+        64,
+        &[
+            &lanes.to_le_bytes(),
+            &le(out_len),
+            &memory_kib.to_le_bytes(),
+            &passes.to_le_bytes(),
+            &ARGON2_VERSION.to_le_bytes(),
+            &variant.to_le_bytes(),
+            &le(password.len()),
+            password,
+            &le(salt.len()),
+            salt,
+            &le(secret.len()),
+            secret,
+            &le(ad.len()),
+            ad,
+        ],
+    );
+    let lanes = lanes as usize;
+    let lane_len = memory_kib as usize / (SYNC_POINTS * lanes) * SYNC_POINTS;
+    let mut state = Argon2 {
+        variant,
+        passes,
+        lanes,
+        lane_len,
+        memory: try_vec([0u64; WORDS], lane_len * lanes)?,
+    };
+    for lane in 0..lanes {
+        for col in 0..2 {
+            let bytes = blake2b_long(1024, &[&h0, &le(col), &le(lane)]);
+            let block = &mut state.memory[lane * lane_len + col];
+            for (w, b) in block.iter_mut().zip(bytes.chunks_exact(8)) {
+                *w = u64::from_le_bytes(b.try_into().unwrap());
+            }
+        }
+    }
+    let mut progress = Progress::new(passes as usize * SYNC_POINTS);
+    for pass in 0..passes {
+        for slice in 0..SYNC_POINTS {
+            for lane in 0..lanes {
+                state.fill_segment(pass, lane, slice);
+            }
+            progress.update(pass as usize * SYNC_POINTS + slice + 1)?;
+        }
+    }
+    let mut last = [0u64; WORDS];
+    for lane in 0..lanes {
+        for (w, b) in last
+            .iter_mut()
+            .zip(&state.memory[(lane + 1) * lane_len - 1])
+        {
+            *w ^= b;
+        }
+    }
+    let bytes: Vec<u8> = last.iter().flat_map(|w| w.to_le_bytes()).collect();
+    Some(blake2b_long(out_len, &[&bytes]))
+}
+
+/// Derive `out_len` bytes from the password at `password_ptr` with Argon2
+/// (`ARGON2_D`, `ARGON2_I` or `ARGON2_ID`; Argon2id is the usual choice for
+/// passwords). `time_cost` is the number of passes and `memory_kib` the
+/// memory in KiB, rounded down to a multiple of `4 * parallelism`.
+///
+/// Returns bytes written (`out_len`), or `-1` for an unknown variant, a
+/// salt under 8 bytes, `time_cost == 0`, `parallelism` outside
+/// `1..2^24`, `memory_kib < 8 * parallelism`, `out_len < 4`, or memory
+/// that cannot be allocated, or `CANCELLED` (`-3`) when cancellation was
+/// requested.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn argon2_kdf(
+    variant: u32,
+    password_ptr: *const u8,
+    password_len: usize,
+    salt_ptr: *const u8,
+    salt_len: usize,
+    time_cost: u32,
+    memory_kib: u32,
+    parallelism: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    if !matches!(variant, ARGON2_D | ARGON2_I | ARGON2_ID)
+        || salt_len < 8
+        || time_cost == 0
+        || !(1..1 << 24).contains(&parallelism)
+        || memory_kib / 8 < parallelism
+        || out_len < 4
+        || u32::try_from(out_len).is_err()
+    {
+        return -1;
+    }
+    if ffi::aliased(password_ptr, password_len, out_ptr, out_len)
+        || ffi::aliased(salt_ptr, salt_len, out_ptr, out_len)
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    let password = ffi::slice(password_ptr, password_len);
+    let salt = ffi::slice(salt_ptr, salt_len);
+    let Some(key) = argon2(
+        variant,
+        password,
+        salt,
+        &[],
+        &[],
+        time_cost,
+        memory_kib,
+        parallelism,
+        out_len,
+    ) else {
+        return cancel::failed();
+    };
+    ffi::slice_mut(out_ptr, out_len).copy_from_slice(&key);
+    out_len as isize
+}
+
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > 64 {
+        block[..32].copy_from_slice(&sha256(&[key]));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let inner_pad = block.map(|b| b ^ 0x36);
+    let outer_pad = block.map(|b| b ^ 0x5c);
+    let mut inner = vec![&inner_pad[..]];
+    inner.extend_from_slice(parts);
+    sha256(&[&outer_pad, &sha256(&inner)])
+}
+
+/// PBKDF2-HMAC-SHA256 with one iteration, as scrypt uses it.
+fn pbkdf2_sha256(password: &[u8], salt: &[u8], out: &mut [u8]) {
+    for (i, chunk) in out.chunks_mut(32).enumerate() {
+        let index = (i as u32 + 1).to_be_bytes();
+        let t = hmac_sha256(password, &[salt, &index]);
+        chunk.copy_from_slice(&t[..chunk.len()]);
+    }
+}
+
+/// The Salsa20/8 core over 16 words, in place.
+fn salsa20_8(b: &mut [u32; 16]) {
+    let mut x = *b;
+    let quarter = |x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize| {
+        x[b] ^= x[a].wrapping_add(x[d]).rotate_left(7);
+        x[c] ^= x[b].wrapping_add(x[a]).rotate_left(9);
+        x[d] ^= x[c].wrapping_add(x[b]).rotate_left(13);
+        x[a] ^= x[d].wrapping_add(x[c]).rotate_left(18);
+    };
+    for _ in 0..4 {
+        quarter(&mut x, 0, 4, 8, 12);
+        quarter(&mut x, 5, 9, 13, 1);
+        quarter(&mut x, 10, 14, 2, 6);
+        quarter(&mut x, 15, 3, 7, 11);
+        quarter(&mut x, 0, 1, 2, 3);
+        quarter(&mut x, 5, 6, 7, 4);
+        quarter(&mut x, 10, 11, 8, 9);
+        quarter(&mut x, 15, 12, 13, 14);
+    }
+    for (w, x) in b.iter_mut().zip(x) {
+        *w = w.wrapping_add(x);
+    }
+}
+
+/// scrypt's BlockMix over `2r` 64-byte blocks, from `input` into `out`.
+fn block_mix(input: &[[u32; 16]], out: &mut [[u32; 16]]) {
+    let half = input.len() / 2;
+    let mut x = input[input.len() - 1];
+    for (i, block) in input.iter().enumerate() {
+        for (w, b) in x.iter_mut().zip(block) {
+            *w ^= b;
+        }
+        salsa20_8(&mut x);
+        out[i / 2 + (i % 2) * half] = x;
+    }
+}
+
+/// scrypt's ROMix over one `128 * r`-byte chunk, using `v` as the `N`
+/// blocks of scratch memory. Reports its `2 * N` steps as `done..` on
+/// `progress`, and returns `None` if cancelled.
+fn ro_mix(
+    chunk: &mut [u8],
+    v: &mut [[u32; 16]],
+    n: usize,
+    progress: &mut Progress,
+    done: usize,
+) -> Option<()> {
+    let len = chunk.len() / 64;
+    let mut x: Vec<[u32; 16]> = chunk
+        .chunks_exact(64)
+        .map(|b| std::array::from_fn(|i| u32::from_le_bytes(b[4 * i..][..4].try_into().unwrap())))
+        .collect();
+    let mut y = x.clone();
+    for i in 0..n {
+        progress.update(done + i)?;
+        v[i * len..][..len].copy_from_slice(&x);
+        block_mix(&x, &mut y);
+        std::mem::swap(&mut x, &mut y);
+    }
+    for i in 0..n {
+        progress.update(done + n + i)?;
+        let last = &x[len - 1];
+        let j = ((last[0] as u64 | (last[1] as u64) << 32) % n as u64) as usize;
+        for (block, old) in x.iter_mut().zip(&v[j * len..][..len]) {
+            for (w, o) in block.iter_mut().zip(old) {
+                *w ^= o;
+            }
+        }
+        block_mix(&x, &mut y);
+        std::mem::swap(&mut x, &mut y);
+    }
+    for (bytes, w) in chunk.chunks_exact_mut(4).zip(x.iter().flatten()) {
+        bytes.copy_from_slice(&w.to_le_bytes());
+    }
+    Some(())
+}
+
+/// Derive `out_len` bytes from the password at `password_ptr` with scrypt,
+/// using `N = 2^log_n`, block size `r` and parallelism `p`. Memory use is
+/// `128 * r * N` bytes; the common interactive setting is `log_n = 15`,
+/// `r = 8`, `p = 1` (32 MiB).
+///
+/// Returns bytes written (`out_len`), or `-1` for `log_n` outside `1..=31`,
+/// `r` or `p` of `0`, `r * p >= 2^30`, an empty output, or memory that
+/// cannot be allocated, or `CANCELLED` (`-3`) when cancellation was
+/// requested.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn scrypt_kdf(
+    password_ptr: *const u8,
+    password_len: usize,
+    salt_ptr: *const u8,
+    salt_len: usize,
+    log_n: u32,
+    r: u32,
+    p: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    if !(1..=31).contains(&log_n)
+        || r == 0
+        || p == 0
+        || r as u64 * p as u64 >= 1 << 30
+        || out_len == 0
+    {
+        return -1;
+    }
+    if ffi::aliased(password_ptr, password_len, out_ptr, out_len)
+        || ffi::aliased(salt_ptr, salt_len, out_ptr, out_len)
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    let password = ffi::slice(password_ptr, password_len);
+    let salt = ffi::slice(salt_ptr, salt_len);
+    let n = 1usize << log_n;
+    let chunk = 128 * r as u64;
+    let (Ok(total), Ok(blocks)) = (
+        usize::try_from(chunk * p as u64),
+        usize::try_from(2 * r as u64 * n as u64),
+    ) else {
+        return -1;
+    };
+    let (Some(mut b), Some(mut v)) = (try_vec(0u8, total), try_vec([0u32; 16], blocks)) else {
+        return -1;
+    };
+    pbkdf2_sha256(password, salt, &mut b);
+    let mut progress = Progress::new(p as usize * 2 * n);
+    for (i, chunk) in b.chunks_exact_mut(chunk as usize).enumerate() {
+        if ro_mix(chunk, &mut v, n, &mut progress, i * 2 * n).is_none() {
+            return cancel::failed();
+        }
+    }
+    progress.update(p as usize * 2 * n);
+    pbkdf2_sha256(password, &b, ffi::slice_mut(out_ptr, out_len));
+    out_len as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    #[test]
+    fn blake2b_vectors() {
+        assert_eq!(
+            hex(&blake2b(64, &[b"a", b"bc"])),
+            "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+             7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+        );
+        assert_eq!(
+            hex(&blake2b(32, &[b""])),
+            "0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8"
+        );
+    }
+
+    #[test]
+    fn argon2_matches_rfc_9106() {
+        let run = |variant| {
+            let key = argon2(variant, &[1; 32], &[2; 16], &[3; 8], &[4; 12], 3, 32, 4, 32);
+            hex(&key.unwrap())
+        };
+        assert_eq!(
+            run(ARGON2_D),
+            "512b391b6f1162975371d30919734294f868e3be3984f3c1a13a4db9fabe4acb"
+        );
+        assert_eq!(
+            run(ARGON2_I),
+            "c814d9d1dc7f37aa13f0d77f2494bda1c8de6b016dd388d29952a4c4672b6ce8"
+        );
+        assert_eq!(
+            run(ARGON2_ID),
+            "0d640df58d78766c08c037a34a8b53c9d01ef0452d75b65eb52520e96b01e659"
+        );
+    }
+
+    fn argon2id(
+        password: &[u8],
+        salt: &[u8],
+        t: u32,
+        m: u32,
+        p: u32,
+        len: usize,
+    ) -> Option<String> {
+        let mut out = vec![0u8; len];
+        let written = unsafe {
+            argon2_kdf(
+                ARGON2_ID,
+                password.as_ptr(),
+                password.len(),
+                salt.as_ptr(),
+                salt.len(),
+                t,
+                m,
+                p,
+                out.as_mut_ptr(),
+                out.len(),
+            )
+        };
+        (written == len as isize).then(|| hex(&out))
+    }
+
+    #[test]
+    fn argon2id_export() {
+        assert_eq!(
+            argon2id(b"password", b"somesalt", 2, 256, 1, 24).unwrap(),
+            "efd08f041b452c025edf232a88a1ef7fbcf05c589e70b062"
+        );
+        // Three lanes round 100 KiB down to 96, and an 80-byte tag takes the
+        // long form of `H'`.
+        assert_eq!(
+            argon2id(b"pw", b"saltsaltsalt", 1, 100, 3, 80).unwrap(),
+            "30a59ffe16c3fbfe268ea8413bb291e49bc122832ffc90e7c607d1189bfcf80e\
+             b7dbeec1ee4c9a881af50e23015ce5c7cc73653fbfbdde61d42ac78e74044690\
+             055de28dd47f750103cf273d6663bd38"
+        );
+        assert!(argon2id(b"pw", b"short", 1, 64, 1, 32).is_none(), "salt");
+        assert!(
+            argon2id(b"pw", b"saltsalt", 0, 64, 1, 32).is_none(),
+            "passes"
+        );
+        assert!(
+            argon2id(b"pw", b"saltsalt", 1, 15, 2, 32).is_none(),
+            "memory"
+        );
+        assert!(
+            argon2id(b"pw", b"saltsalt", 1, 64, 0, 32).is_none(),
+            "lanes"
+        );
+        assert!(argon2id(b"pw", b"saltsalt", 1, 64, 1, 3).is_none(), "tag");
+    }
+
+    fn scrypt(
+        password: &[u8],
+        salt: &[u8],
+        log_n: u32,
+        r: u32,
+        p: u32,
+        len: usize,
+    ) -> Option<String> {
+        let mut out = vec![0u8; len];
+        let written = unsafe {
+            scrypt_kdf(
+                password.as_ptr(),
+                password.len(),
+                salt.as_ptr(),
+                salt.len(),
+                log_n,
+                r,
+                p,
+                out.as_mut_ptr(),
+                out.len(),
+            )
+        };
+        (written == len as isize).then(|| hex(&out))
+    }
+
+    #[test]
+    fn scrypt_matches_rfc_7914() {
+        assert_eq!(
+            scrypt(b"", b"", 4, 1, 1, 64).unwrap(),
+            "77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442\
+             fcd0069ded0948f8326a753a0fc81f17e8d3e0fb2e0d3628cf35e20c38d18906"
+        );
+        assert_eq!(
+            scrypt(b"password", b"NaCl", 10, 8, 16, 64).unwrap(),
+            "fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b373162\
+             2eaf30d92e22a3886ff109279d9830dac727afb94a83ee6d8360cbdfa2cc0640"
+        );
+        assert!(scrypt(b"pw", b"salt", 0, 8, 1, 32).is_none());
+        assert!(scrypt(b"pw", b"salt", 4, 0, 1, 32).is_none());
+        assert!(scrypt(b"pw", b"salt", 4, 8, 1, 0).is_none());
+    }
+}
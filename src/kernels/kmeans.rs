@@ -0,0 +1,290 @@
+//! K-means clustering of `f32` points, e.g. for color quantization.
+//!
+//! Centroids are seeded with k-means++ from a fixed seed, then refined with
+//! Lloyd iterations until no point changes cluster or the iteration budget
+//! runs out. Under `simd128` distances keep an `f32x4` accumulator in a
+//! register; otherwise they use `vector::squared_distance`. Both sum lanes in
+//! the same order, so results are the same in SIMD and scalar builds.
+
+use super::vector::{add_assign, scale};
+use crate::ffi;
+
+#[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+use super::vector::squared_distance as distance;
+
+/// Squared Euclidean distance between two equal-length vectors.
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+fn distance(a: &[f32], b: &[f32]) -> f32 {
+    use core::arch::wasm32::*;
+    unsafe {
+        let (mut a4, mut b4) = (a.chunks_exact(4), b.chunks_exact(4));
+        let mut acc = f32x4_splat(0.0);
+        for (a, b) in (&mut a4).zip(&mut b4) {
+            let d = f32x4_sub(
+                v128_load(a.as_ptr() as *const v128),
+                v128_load(b.as_ptr() as *const v128),
+            );
+            acc = f32x4_add(acc, f32x4_mul(d, d));
+        }
+        let rem = a4.remainder().len();
+        if rem > 0 {
+            // Zero-padded lanes add a difference of 0.
+            let (mut ta, mut tb) = ([0f32; 4], [0f32; 4]);
+            ta[..rem].copy_from_slice(a4.remainder());
+            tb[..rem].copy_from_slice(b4.remainder());
+            let d = f32x4_sub(
+                v128_load(ta.as_ptr() as *const v128),
+                v128_load(tb.as_ptr() as *const v128),
+            );
+            acc = f32x4_add(acc, f32x4_mul(d, d));
+        }
+        (f32x4_extract_lane::<0>(acc) + f32x4_extract_lane::<1>(acc))
+            + (f32x4_extract_lane::<2>(acc) + f32x4_extract_lane::<3>(acc))
+    }
+}
+
+struct Rng(u64);
+
+impl Rng {
+    /// Uniform in `[0, 1)`.
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// k-means++: each new centroid is a point drawn with probability
+/// proportional to its squared distance from the nearest centroid so far.
+fn seed(points: &[f32], dim: usize, centroids: &mut [f32]) {
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    let n = points.len() / dim;
+    let first = (rng.next() * n as f64) as usize;
+    centroids[..dim].copy_from_slice(&points[first * dim..][..dim]);
+
+    let mut nearest: Vec<f32> = points
+        .chunks_exact(dim)
+        .map(|p| distance(p, &centroids[..dim]))
+        .collect();
+    for c in 1..centroids.len() / dim {
+        let total: f64 = nearest.iter().map(|&d| d as f64).sum();
+        let mut target = rng.next() * total;
+        // Falls back to the last point when rounding leaves `target` > 0, and
+        // to point 0 when every point already coincides with a centroid.
+        let mut pick = if total > 0.0 { n - 1 } else { 0 };
+        for (i, &d) in nearest.iter().enumerate() {
+            target -= d as f64;
+            if d > 0.0 && target < 0.0 {
+                pick = i;
+                break;
+            }
+        }
+        let centroid = &mut centroids[c * dim..][..dim];
+        centroid.copy_from_slice(&points[pick * dim..][..dim]);
+        for (d, p) in nearest.iter_mut().zip(points.chunks_exact(dim)) {
+            *d = d.min(distance(p, centroid));
+        }
+    }
+}
+
+pub(crate) fn closest(point: &[f32], centroids: &[f32], dim: usize) -> u32 {
+    let mut best = (f32::INFINITY, 0);
+    for (c, centroid) in centroids.chunks_exact(dim).enumerate() {
+        let d = distance(point, centroid);
+        if d < best.0 {
+            best = (d, c as u32);
+        }
+    }
+    best.1
+}
+
+/// Cluster the `dim`-dimensional points at `points_ptr` (row-major,
+/// `points_len_bytes / (dim * 4)` of them) into `k` clusters.
+///
+/// Writes the `k x dim` centroids to `centroids_ptr` and one `u32` cluster
+/// index per point to `assignments_ptr`. Runs at most `iters` Lloyd
+/// iterations after seeding, stopping early once assignments settle; a
+/// cluster that loses all its points keeps its previous centroid. The same
+/// input always gives the same output.
+///
+/// Returns bytes written to `centroids_ptr` (`k * dim * 4`), or `-1` when
+/// `dim` is `0`, the input is not a whole number of points, `k` is `0` or
+/// more than the number of points, or an output is short.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn kmeans_f32(
+    points_ptr: *const f32,
+    points_len_bytes: usize,
+    dim: usize,
+    k: u32,
+    iters: u32,
+    centroids_ptr: *mut f32,
+    centroids_len_bytes: usize,
+    assignments_ptr: *mut u32,
+    assignments_len_bytes: usize,
+) -> isize {
+    let k = k as usize;
+    if dim == 0
+        || !points_len_bytes.is_multiple_of(4)
+        || !(points_len_bytes / 4).is_multiple_of(dim)
+    {
+        return -1;
+    }
+    let n = points_len_bytes / 4 / dim;
+    if k == 0 || k > n || centroids_len_bytes / 4 / dim < k || assignments_len_bytes / 4 < n {
+        return -1;
+    }
+    let centroids_bytes = k * dim * 4;
+    if ffi::aliased(
+        points_ptr as *const u8,
+        points_len_bytes,
+        centroids_ptr as *const u8,
+        centroids_bytes,
+    ) || ffi::aliased(
+        points_ptr as *const u8,
+        points_len_bytes,
+        assignments_ptr as *const u8,
+        n * 4,
+    ) {
+        return ffi::ALIAS_ERROR;
+    }
+    let points = ffi::slice(points_ptr, n * dim);
+    let centroids = ffi::slice_mut(centroids_ptr, k * dim);
+    let assignments = ffi::slice_mut(assignments_ptr, n);
+
+    seed(points, dim, centroids);
+    for (a, p) in assignments.iter_mut().zip(points.chunks_exact(dim)) {
+        *a = closest(p, centroids, dim);
+    }
+
+    let mut sums = vec![0f32; k * dim];
+    let mut counts = vec![0u32; k];
+    for _ in 0..iters {
+        sums.fill(0.0);
+        counts.fill(0);
+        for (&a, p) in assignments.iter().zip(points.chunks_exact(dim)) {
+            add_assign(&mut sums[a as usize * dim..][..dim], p);
+            counts[a as usize] += 1;
+        }
+        for ((centroid, sum), &count) in centroids
+            .chunks_exact_mut(dim)
+            .zip(sums.chunks_exact_mut(dim))
+            .zip(&counts)
+        {
+            if count > 0 {
+                scale(sum, 1.0 / count as f32);
+                centroid.copy_from_slice(sum);
+            }
+        }
+
+        let mut changed = false;
+        for (a, p) in assignments.iter_mut().zip(points.chunks_exact(dim)) {
+            let c = closest(p, centroids, dim);
+            changed |= c != *a;
+            *a = c;
+        }
+        if !changed {
+            break;
+        }
+    }
+    centroids_bytes as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(points: &[f32], dim: usize, k: usize, iters: u32) -> (isize, Vec<f32>, Vec<u32>) {
+        let mut centroids = vec![f32::NAN; k * dim];
+        let mut assignments = vec![u32::MAX; points.len() / dim.max(1)];
+        let written = unsafe {
+            kmeans_f32(
+                points.as_ptr(),
+                points.len() * 4,
+                dim,
+                k as u32,
+                iters,
+                centroids.as_mut_ptr(),
+                centroids.len() * 4,
+                assignments.as_mut_ptr(),
+                assignments.len() * 4,
+            )
+        };
+        (written, centroids, assignments)
+    }
+
+    #[test]
+    fn separates_well_spaced_blobs() {
+        // Three tight RGB-like clusters, interleaved.
+        let centers = [
+            [10.0f32, 10.0, 10.0],
+            [200.0, 20.0, 20.0],
+            [30.0, 40.0, 220.0],
+        ];
+        let mut points = Vec::new();
+        for i in 0..60 {
+            let c = centers[i % 3];
+            let jitter = (i / 3) as f32 * 0.1;
+            points.extend_from_slice(&[c[0] + jitter, c[1] - jitter, c[2] + jitter]);
+        }
+        let (written, centroids, assignments) = run(&points, 3, 3, 20);
+        assert_eq!(written, 36);
+        for i in 0..60 {
+            assert_eq!(
+                assignments[i],
+                assignments[i % 3],
+                "point {i} joins its blob"
+            );
+        }
+        // Each blob's jitter averages 0.95.
+        for (c, center) in centers.iter().enumerate() {
+            let centroid = &centroids[assignments[c] as usize * 3..][..3];
+            let mean = [center[0] + 0.95, center[1] - 0.95, center[2] + 0.95];
+            assert!(
+                distance(centroid, &mean) < 1e-6,
+                "{centroid:?} vs {center:?}"
+            );
+        }
+        assert_eq!(
+            run(&points, 3, 3, 20),
+            (written, centroids, assignments),
+            "deterministic"
+        );
+    }
+
+    #[test]
+    fn duplicate_points_and_k_equal_n() {
+        let points = [1.0f32, 1.0, 1.0, 1.0, 5.0, 5.0];
+        let (written, centroids, assignments) = run(&points, 2, 3, 5);
+        assert_eq!(written, 24);
+        assert_eq!(assignments[0], assignments[1]);
+        assert_ne!(assignments[0], assignments[2]);
+        assert!(centroids.iter().all(|v| v.is_finite()));
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let points = [0.0f32; 6];
+        assert_eq!(run(&points, 2, 4, 1).0, -1, "k > n");
+        assert_eq!(run(&points, 2, 0, 1).0, -1, "k = 0");
+        assert_eq!(run(&points, 4, 1, 1).0, -1, "partial point");
+        assert_eq!(run(&points, 0, 1, 1).0, -1, "dim = 0");
+        let mut centroids = [0f32; 2];
+        let mut assignments = [0u32; 2];
+        let status = unsafe {
+            kmeans_f32(
+                points.as_ptr(),
+                24,
+                2,
+                1,
+                1,
+                centroids.as_mut_ptr(),
+                8,
+                assignments.as_mut_ptr(),
+                8,
+            )
+        };
+        assert_eq!(status, -1, "short assignments");
+    }
+}
@@ -0,0 +1,496 @@
+//! Small dense linear algebra over row-major `f32` matrices.
+//!
+//! Inner products run through `vector::dot`, an `f32x4` loop under
+//! `simd128`. Data matrices are first transposed into contiguous columns so
+//! that every product is a straight SIMD dot product rather than a strided
+//! walk.
+
+use super::vector::{dot, scale};
+use crate::ffi;
+
+/// Fit an intercept term, reported after the `cols` slopes.
+pub const OLS_INTERCEPT: u32 = 1;
+
+/// Power-iteration steps per component before giving up on convergence.
+const MAX_POWER_ITERS: usize = 200;
+
+/// Mean of each column of `matrix` (`rows x cols`), accumulated in `f64`.
+fn column_means(matrix: &[f32], rows: usize, cols: usize) -> Vec<f32> {
+    let mut sums = vec![0f64; cols];
+    for row in matrix.chunks_exact(cols) {
+        for (sum, &v) in sums.iter_mut().zip(row) {
+            *sum += v as f64;
+        }
+    }
+    sums.iter().map(|&sum| (sum / rows as f64) as f32).collect()
+}
+
+/// Columns of `matrix` (`rows x cols`) minus `offsets[j]`, laid out
+/// contiguously: column `j` is `out[j * rows..][..rows]`.
+fn columns(matrix: &[f32], rows: usize, cols: usize, offsets: &[f32]) -> Vec<f32> {
+    let mut columns = vec![0f32; rows * cols];
+    for (r, row) in matrix.chunks_exact(cols).enumerate() {
+        for (c, (&v, &offset)) in row.iter().zip(offsets).enumerate() {
+            columns[c * rows + r] = v - offset;
+        }
+    }
+    columns
+}
+
+fn centered_columns(matrix: &[f32], rows: usize, cols: usize) -> Vec<f32> {
+    columns(matrix, rows, cols, &column_means(matrix, rows, cols))
+}
+
+/// Sample covariance (`n - 1` denominator) of the columns of a
+/// `rows x cols` matrix, as a `cols x cols` matrix.
+fn covariance(matrix: &[f32], rows: usize, cols: usize) -> Vec<f32> {
+    let columns = centered_columns(matrix, rows, cols);
+    let mut cov = vec![0f32; cols * cols];
+    let denominator = (rows - 1) as f32;
+    for i in 0..cols {
+        for j in i..cols {
+            let c = dot(&columns[i * rows..][..rows], &columns[j * rows..][..rows]) / denominator;
+            cov[i * cols + j] = c;
+            cov[j * cols + i] = c;
+        }
+    }
+    cov
+}
+
+/// Top `k` eigenvectors of the symmetric `n x n` matrix `m`, by power
+/// iteration with deflation. Each vector has unit length and its
+/// largest-magnitude entry positive, so the output is deterministic.
+fn top_eigenvectors(mut m: Vec<f32>, n: usize, k: usize) -> Vec<f32> {
+    let mut vectors = vec![0f32; k * n];
+    let mut next = vec![0f32; n];
+    for v in vectors.chunks_exact_mut(n) {
+        // Uneven start so it is unlikely to be orthogonal to the target.
+        for (i, x) in v.iter_mut().enumerate() {
+            *x = 1.0 + i as f32 / n as f32;
+        }
+        scale(v, 1.0 / dot(v, v).sqrt());
+        let mut eigenvalue = 0.0;
+        for _ in 0..MAX_POWER_ITERS {
+            for (x, row) in next.iter_mut().zip(m.chunks_exact(n)) {
+                *x = dot(row, v);
+            }
+            let norm = dot(&next, &next).sqrt();
+            if norm == 0.0 {
+                // Nothing left to explain: leave a zero component.
+                v.fill(0.0);
+                break;
+            }
+            scale(&mut next, 1.0 / norm);
+            let delta: f32 = next.iter().zip(v.iter()).map(|(a, b)| (a - b).abs()).sum();
+            v.copy_from_slice(&next);
+            eigenvalue = norm;
+            if delta < 1e-6 {
+                break;
+            }
+        }
+        let pivot = v
+            .iter()
+            .fold(0f32, |p, &x| if x.abs() > p.abs() { x } else { p });
+        if pivot < 0.0 {
+            scale(v, -1.0);
+        }
+        // Deflate: m -= eigenvalue * v v^T.
+        for (row, &vi) in m.chunks_exact_mut(n).zip(v.iter()) {
+            for (x, &vj) in row.iter_mut().zip(v.iter()) {
+                *x -= eigenvalue * vi * vj;
+            }
+        }
+    }
+    vectors
+}
+
+/// Solve `a x = b` for the symmetric positive definite `n x n` matrix `a`
+/// by Cholesky decomposition, in place (`a` becomes its factor, `b` the
+/// solution). Returns `None` when `a` is not positive definite.
+fn cholesky_solve(a: &mut [f64], b: &mut [f64], n: usize) -> Option<()> {
+    for j in 0..n {
+        let mut d = a[j * n + j];
+        for k in 0..j {
+            d -= a[j * n + k] * a[j * n + k];
+        }
+        if d <= 1e-12 * a[j * n + j].abs().max(f64::MIN_POSITIVE) {
+            return None;
+        }
+        let d = d.sqrt();
+        a[j * n + j] = d;
+        for i in j + 1..n {
+            let mut v = a[i * n + j];
+            for k in 0..j {
+                v -= a[i * n + k] * a[j * n + k];
+            }
+            a[i * n + j] = v / d;
+        }
+    }
+    // Forward substitution with L, then back substitution with L^T.
+    for i in 0..n {
+        for k in 0..i {
+            b[i] -= a[i * n + k] * b[k];
+        }
+        b[i] /= a[i * n + i];
+    }
+    for i in (0..n).rev() {
+        for k in i + 1..n {
+            b[i] -= a[k * n + i] * b[k];
+        }
+        b[i] /= a[i * n + i];
+    }
+    Some(())
+}
+
+/// `rows * cols` when it, and its size in bytes, fit in `usize`.
+fn matrix_len(rows: usize, cols: usize) -> Option<usize> {
+    rows.checked_mul(cols)
+        .filter(|len| len.checked_mul(4).is_some())
+}
+
+/// Write the `cols x cols` sample covariance matrix of the columns of the
+/// `rows x cols` matrix at `matrix_ptr` to `out_ptr`.
+///
+/// Returns bytes written (`cols * cols * 4`), or `-1` for fewer than two
+/// rows, no columns or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn covariance_f32(
+    matrix_ptr: *const f32,
+    rows: usize,
+    cols: usize,
+    out_ptr: *mut f32,
+    out_len_bytes: usize,
+) -> isize {
+    let (Some(len), Some(out_len)) = (matrix_len(rows, cols), matrix_len(cols, cols)) else {
+        return -1;
+    };
+    if rows < 2 || cols == 0 || out_len_bytes / 4 < out_len {
+        return -1;
+    }
+    if ffi::aliased(
+        matrix_ptr as *const u8,
+        len * 4,
+        out_ptr as *const u8,
+        out_len * 4,
+    ) {
+        return ffi::ALIAS_ERROR;
+    }
+    let cov = covariance(ffi::slice(matrix_ptr, len), rows, cols);
+    ffi::slice_mut(out_ptr, out_len).copy_from_slice(&cov);
+    (out_len * 4) as isize
+}
+
+/// Principal component analysis of the `rows x cols` matrix at
+/// `matrix_ptr`.
+///
+/// Writes the top `k` principal axes, unit `cols`-vectors ordered by
+/// explained variance, to `components_ptr` as a `k x cols` matrix. Each axis
+/// is oriented so that its largest-magnitude entry is positive. When
+/// `projection_len_bytes` is nonzero, it also writes each row's coordinates
+/// along those axes (centered data times the components) as a `rows x k`
+/// matrix, e.g. with `k = 2` for a scatter plot of an embedding set.
+///
+/// Returns bytes written to `components_ptr` (`k * cols * 4`), or `-1` for
+/// fewer than two rows, no columns, `k` of `0` or above `cols`, or a short
+/// output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn pca_f32(
+    matrix_ptr: *const f32,
+    rows: usize,
+    cols: usize,
+    k: u32,
+    components_ptr: *mut f32,
+    components_len_bytes: usize,
+    projection_ptr: *mut f32,
+    projection_len_bytes: usize,
+) -> isize {
+    let k = k as usize;
+    let (Some(len), Some(projection_len)) = (matrix_len(rows, cols), matrix_len(rows, k)) else {
+        return -1;
+    };
+    if rows < 2
+        || cols == 0
+        || k == 0
+        || k > cols
+        || components_len_bytes / 4 < k * cols
+        || (projection_len_bytes != 0 && projection_len_bytes / 4 < projection_len)
+    {
+        return -1;
+    }
+    let matrix_bytes = len * 4;
+    if ffi::aliased(
+        matrix_ptr as *const u8,
+        matrix_bytes,
+        components_ptr as *const u8,
+        k * cols * 4,
+    ) || (projection_len_bytes != 0
+        && ffi::aliased(
+            matrix_ptr as *const u8,
+            matrix_bytes,
+            projection_ptr as *const u8,
+            projection_len * 4,
+        ))
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    let matrix = ffi::slice(matrix_ptr, len);
+    let components = top_eigenvectors(covariance(matrix, rows, cols), cols, k);
+    ffi::slice_mut(components_ptr, k * cols).copy_from_slice(&components);
+
+    if projection_len_bytes != 0 {
+        let projection = ffi::slice_mut(projection_ptr, projection_len);
+        let mut centered = vec![0f32; cols];
+        let means = column_means(matrix, rows, cols);
+        for (row, out) in matrix
+            .chunks_exact(cols)
+            .zip(projection.chunks_exact_mut(k))
+        {
+            for ((c, &v), &mean) in centered.iter_mut().zip(row).zip(&means) {
+                *c = v - mean;
+            }
+            for (o, axis) in out.iter_mut().zip(components.chunks_exact(cols)) {
+                *o = dot(&centered, axis);
+            }
+        }
+    }
+    (k * cols * 4) as isize
+}
+
+/// Least-squares fit of `y` (`rows` values at `y_ptr`) against the
+/// `rows x cols` design matrix at `x_ptr`, by the normal equations
+/// `(X^T X + lambda I) b = X^T y`.
+///
+/// `lambda > 0` gives ridge regression. With `OLS_INTERCEPT` in `flags` the
+/// columns and `y` are centered first, so the intercept is not penalized,
+/// and the intercept is written after the slopes. The cross products use
+/// SIMD dot products; the solve runs in `f64`.
+///
+/// Returns bytes written (`4 * (cols + 1)` with an intercept, `4 * cols`
+/// without), or `-1` for no rows or columns, a short `y` or output, a
+/// negative or non-finite `lambda`, or a singular system (e.g. duplicate
+/// columns without ridge).
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn ols_fit(
+    x_ptr: *const f32,
+    rows: usize,
+    cols: usize,
+    y_ptr: *const f32,
+    y_len_bytes: usize,
+    lambda: f32,
+    flags: u32,
+    out_ptr: *mut f32,
+    out_len_bytes: usize,
+) -> isize {
+    let intercept = flags & OLS_INTERCEPT != 0;
+    let coefficients = cols + intercept as usize;
+    let Some(len) = matrix_len(rows, cols) else {
+        return -1;
+    };
+    if rows == 0
+        || cols == 0
+        || y_len_bytes / 4 < rows
+        || out_len_bytes / 4 < coefficients
+        || !(lambda >= 0.0 && lambda.is_finite())
+    {
+        return -1;
+    }
+    let x = ffi::slice(x_ptr, len);
+    let y = ffi::slice(y_ptr, rows);
+    let (x_means, y_mean) = if intercept {
+        let y_mean = (y.iter().map(|&v| v as f64).sum::<f64>() / rows as f64) as f32;
+        (column_means(x, rows, cols), y_mean)
+    } else {
+        (vec![0f32; cols], 0.0)
+    };
+    let x_columns = columns(x, rows, cols, &x_means);
+    let y_centered: Vec<f32> = y.iter().map(|&v| v - y_mean).collect();
+
+    let column = |j: usize| &x_columns[j * rows..][..rows];
+    let mut xtx = vec![0f64; cols * cols];
+    let mut b: Vec<f64> = (0..cols)
+        .map(|j| dot(column(j), &y_centered) as f64)
+        .collect();
+    for i in 0..cols {
+        for j in i..cols {
+            let v = dot(column(i), column(j)) as f64;
+            xtx[i * cols + j] = v;
+            xtx[j * cols + i] = v;
+        }
+        xtx[i * cols + i] += lambda as f64;
+    }
+    if cholesky_solve(&mut xtx, &mut b, cols).is_none() {
+        return -1;
+    }
+
+    let out = ffi::slice_mut(out_ptr, coefficients);
+    for (o, &v) in out.iter_mut().zip(&b) {
+        *o = v as f32;
+    }
+    if intercept {
+        let shift: f64 = b.iter().zip(&x_means).map(|(&b, &m)| b * m as f64).sum();
+        out[cols] = (y_mean as f64 - shift) as f32;
+    }
+    (coefficients * 4) as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn covariance_of_known_columns() {
+        // Column 1 = 2 * column 0, column 2 constant.
+        let m = [
+            1.0f32, 2.0, 5.0, 2.0, 4.0, 5.0, 3.0, 6.0, 5.0, 4.0, 8.0, 5.0,
+        ];
+        let mut out = [f32::NAN; 9];
+        let written = unsafe { covariance_f32(m.as_ptr(), 4, 3, out.as_mut_ptr(), 36) };
+        assert_eq!(written, 36);
+        let var = 5.0 / 3.0;
+        let expected = [
+            var,
+            2.0 * var,
+            0.0,
+            2.0 * var,
+            4.0 * var,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+        ];
+        for (got, want) in out.iter().zip(expected) {
+            assert!((got - want).abs() < 1e-5, "{out:?}");
+        }
+
+        assert_eq!(
+            unsafe { covariance_f32(m.as_ptr(), 1, 3, out.as_mut_ptr(), 36) },
+            -1
+        );
+        assert_eq!(
+            unsafe { covariance_f32(m.as_ptr(), 4, 3, out.as_mut_ptr(), 32) },
+            -1
+        );
+    }
+
+    #[test]
+    fn pca_recovers_dominant_axes() {
+        // Points spread mostly along (1, 1, 0), a little along (0, 0, 1).
+        let mut m = Vec::new();
+        for i in 0..40 {
+            let t = i as f32 - 19.5;
+            // +1 -1 -1 +1 repeating: zero mean and uncorrelated with t.
+            let s = if matches!(i % 4, 0 | 3) { 1.0 } else { -1.0 };
+            m.extend_from_slice(&[t + 3.0, t - 1.0, s]);
+        }
+        let mut components = [f32::NAN; 6];
+        let mut projection = vec![f32::NAN; 80];
+        let written = unsafe {
+            pca_f32(
+                m.as_ptr(),
+                40,
+                3,
+                2,
+                components.as_mut_ptr(),
+                24,
+                projection.as_mut_ptr(),
+                320,
+            )
+        };
+        assert_eq!(written, 24);
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+        for (got, want) in components.iter().zip([h, h, 0.0, 0.0, 0.0, 1.0]) {
+            assert!((got - want).abs() < 1e-3, "{components:?}");
+        }
+        // Row 0 sits at t = -19.5 along the first axis and s = 1 on the second.
+        assert!(
+            (projection[0] + 19.5 * 2f32.sqrt()).abs() < 1e-3,
+            "{:?}",
+            &projection[..2]
+        );
+        assert!((projection[1] - 1.0).abs() < 1e-3);
+
+        let status = unsafe {
+            pca_f32(
+                m.as_ptr(),
+                40,
+                3,
+                4,
+                components.as_mut_ptr(),
+                24,
+                std::ptr::null_mut(),
+                0,
+            )
+        };
+        assert_eq!(status, -1, "k > cols");
+        let status = unsafe {
+            pca_f32(
+                m.as_ptr(),
+                40,
+                3,
+                1,
+                components.as_mut_ptr(),
+                12,
+                std::ptr::null_mut(),
+                0,
+            )
+        };
+        assert_eq!(status, 12, "projection is optional");
+    }
+
+    fn fit(x: &[f32], y: &[f32], cols: usize, lambda: f32, flags: u32) -> (isize, Vec<f32>) {
+        let mut out = vec![f32::NAN; cols + 1];
+        let written = unsafe {
+            ols_fit(
+                x.as_ptr(),
+                y.len(),
+                cols,
+                y.as_ptr(),
+                y.len() * 4,
+                lambda,
+                flags,
+                out.as_mut_ptr(),
+                out.len() * 4,
+            )
+        };
+        (written, out)
+    }
+
+    #[test]
+    fn ols_recovers_exact_coefficients() {
+        // y = 3 x0 - 2 x1 + 7.
+        let mut x = Vec::new();
+        let mut y = Vec::new();
+        for i in 0..50 {
+            let (a, b) = (i as f32 * 0.5, ((i * 7) % 11) as f32);
+            x.extend_from_slice(&[a, b]);
+            y.push(3.0 * a - 2.0 * b + 7.0);
+        }
+        let (written, coeffs) = fit(&x, &y, 2, 0.0, OLS_INTERCEPT);
+        assert_eq!(written, 12);
+        for (got, want) in coeffs.iter().zip([3.0, -2.0, 7.0]) {
+            assert!((got - want).abs() < 1e-3, "{coeffs:?}");
+        }
+
+        // Through the origin, a single column gives sum(xy) / sum(x^2).
+        let (written, coeffs) = fit(&[1.0, 2.0, 3.0], &[2.0, 4.0, 7.0], 1, 0.0, 0);
+        assert_eq!(written, 4);
+        assert!((coeffs[0] - 31.0 / 14.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn ridge_shrinks_and_rescues_singular_systems() {
+        let x = [1.0f32, 1.0, 2.0, 2.0, 3.0, 3.0];
+        let y = [1.0f32, 2.0, 3.0];
+        assert_eq!(fit(&x, &y, 2, 0.0, 0).0, -1, "duplicate columns");
+        let (written, coeffs) = fit(&x, &y, 2, 1.0, 0);
+        assert_eq!(written, 8);
+        // Symmetric columns split the weight evenly: 2b * 14 + b = 14.
+        assert!((coeffs[0] - 14.0 / 29.0).abs() < 1e-5 && coeffs[0] == coeffs[1]);
+
+        assert_eq!(fit(&x, &y, 2, -1.0, 0).0, -1);
+        assert_eq!(fit(&x, &y, 2, f32::NAN, 0).0, -1);
+    }
+}
@@ -0,0 +1,571 @@
+//! Field extraction for common log line formats.
+//!
+//! Each kernel takes a batch of log lines (a text blob plus `n + 1`
+//! offsets, one line per value) and reports where each field lies in the
+//! text. A log explorer can then filter and sort on the fields it needs
+//! without decoding whole lines to JS strings. Line breaks at either end of a
+//! value are ignored, so the offsets can be line terminator positions (as
+//! `find_line_offsets` reports them) with `0` and the text length added.
+//!
+//! - `log_fields_clf`: Apache Common and Combined Log Format.
+//! - `log_fields_syslog`: RFC 5424 syslog.
+//! - `log_fields_logfmt`: logfmt `key=value` pairs.
+
+use super::spans;
+use crate::ffi;
+
+/// `[start, end]` spans per line in `log_fields_clf` output: host, ident,
+/// user, time, request, status, bytes, referer, user agent.
+const CLF_FIELDS: usize = 9;
+/// `[start, end]` spans per line in `log_fields_syslog` output: priority,
+/// version, timestamp, hostname, app name, process id, message id,
+/// structured data, message.
+const SYSLOG_FIELDS: usize = 9;
+/// `u32` words per pair in `log_fields_logfmt` output.
+const PAIR_WORDS: usize = 5;
+
+type Span = (usize, usize);
+
+/// A cursor over one line, with positions relative to its start.
+struct Line<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl Line<'_> {
+    fn at_end(&self) -> bool {
+        self.pos == self.buf.len()
+    }
+
+    fn byte(&mut self, b: u8) -> Option<()> {
+        (self.buf.get(self.pos) == Some(&b)).then(|| self.pos += 1)
+    }
+
+    /// A non-empty run of bytes up to the next space or the end of the line.
+    fn token(&mut self) -> Option<Span> {
+        let start = self.pos;
+        while self.buf.get(self.pos).is_some_and(|&b| b != b' ') {
+            self.pos += 1;
+        }
+        (self.pos > start).then_some((start, self.pos))
+    }
+
+    /// The bytes between `open` and `close`, where `\` escapes the next byte.
+    /// The span excludes the delimiters.
+    fn delimited(&mut self, open: u8, close: u8) -> Option<Span> {
+        self.byte(open)?;
+        let start = self.pos;
+        loop {
+            match *self.buf.get(self.pos)? {
+                b'\\' => self.pos += 2,
+                b if b == close => break,
+                _ => self.pos += 1,
+            }
+        }
+        let end = self.pos;
+        self.pos += 1;
+        Some((start, end))
+    }
+}
+
+fn all_digits(buf: &[u8], (start, end): Span) -> bool {
+    buf[start..end].iter().all(u8::is_ascii_digit)
+}
+
+/// `host ident user [time] "request" status bytes`, optionally followed by
+/// `"referer" "user-agent"` and further fields, which are ignored.
+fn clf(line: &[u8], fields: &mut [Span]) -> Option<()> {
+    let mut l = Line { buf: line, pos: 0 };
+    fields[0] = l.token()?;
+    l.byte(b' ')?;
+    fields[1] = l.token()?;
+    l.byte(b' ')?;
+    fields[2] = l.token()?;
+    l.byte(b' ')?;
+    fields[3] = l.delimited(b'[', b']')?;
+    l.byte(b' ')?;
+    fields[4] = l.delimited(b'"', b'"')?;
+    l.byte(b' ')?;
+    fields[5] = l
+        .token()
+        .filter(|&s| s.1 - s.0 == 3 && all_digits(line, s))?;
+    l.byte(b' ')?;
+    fields[6] = l
+        .token()
+        .filter(|&s| all_digits(line, s) || &line[s.0..s.1] == b"-")?;
+    if l.at_end() {
+        fields[7] = (l.pos, l.pos);
+        fields[8] = (l.pos, l.pos);
+        return Some(());
+    }
+    l.byte(b' ')?;
+    fields[7] = l.delimited(b'"', b'"')?;
+    l.byte(b' ')?;
+    fields[8] = l.delimited(b'"', b'"')?;
+    (l.at_end() || l.byte(b' ').is_some()).then_some(())
+}
+
+/// `<PRI>VERSION TIMESTAMP HOSTNAME APP-NAME PROCID MSGID SD [MSG]`.
+fn syslog(line: &[u8], fields: &mut [Span]) -> Option<()> {
+    let mut l = Line { buf: line, pos: 0 };
+    l.byte(b'<')?;
+    let pri_start = l.pos;
+    while line.get(l.pos).is_some_and(u8::is_ascii_digit) {
+        l.pos += 1;
+    }
+    fields[0] = (pri_start, l.pos);
+    if !(1..=3).contains(&(l.pos - pri_start)) {
+        return None;
+    }
+    l.byte(b'>')?;
+    fields[1] = l
+        .token()
+        .filter(|&s| s.1 - s.0 <= 3 && all_digits(line, s) && line[s.0] != b'0')?;
+    for field in &mut fields[2..7] {
+        l.byte(b' ')?;
+        *field = l.token()?;
+    }
+    l.byte(b' ')?;
+    // Structured data: `-`, or one or more `[id param="value" ...]`
+    // elements with `\"`, `\\` and `\]` escaped inside values.
+    let sd_start = l.pos;
+    if l.byte(b'-').is_none() {
+        while line.get(l.pos) == Some(&b'[') {
+            l.pos += 1;
+            loop {
+                match *line.get(l.pos)? {
+                    b'"' => {
+                        l.delimited(b'"', b'"')?;
+                    }
+                    b']' => break,
+                    _ => l.pos += 1,
+                }
+            }
+            l.pos += 1;
+        }
+    }
+    fields[7] = (sd_start, l.pos);
+    if l.pos == sd_start {
+        return None;
+    }
+    if l.at_end() {
+        fields[8] = (l.pos, l.pos);
+        return Some(());
+    }
+    l.byte(b' ')?;
+    fields[8] = (l.pos, line.len());
+    Some(())
+}
+
+/// The line without line breaks at either end, and the number of bytes
+/// dropped from its start.
+fn trim_breaks(mut line: &[u8]) -> (usize, &[u8]) {
+    let mut skipped = 0;
+    while let [b'\r' | b'\n', rest @ ..] = line {
+        (line, skipped) = (rest, skipped + 1);
+    }
+    while let [rest @ .., b'\r' | b'\n'] = line {
+        line = rest;
+    }
+    (skipped, line)
+}
+
+/// Shared driver for the fixed-width formats: write `[start, end]` per field
+/// for each line, or zeros and an error bit for lines `parse` rejects.
+#[allow(clippy::too_many_arguments)]
+unsafe fn fields_batch(
+    text_ptr: *const u8,
+    text_len: usize,
+    offsets_ptr: *const u32,
+    offsets_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+    errors_ptr: *mut u8,
+    errors_len: usize,
+    fields: usize,
+    parse: fn(&[u8], &mut [Span]) -> Option<()>,
+) -> isize {
+    let Some(rows) = offsets_len.checked_sub(1) else {
+        return -1;
+    };
+    let words = rows * fields * 2;
+    if out_len_bytes / 4 < words
+        || errors_len < rows.div_ceil(8)
+        || u32::try_from(text_len).is_err()
+    {
+        return -1;
+    }
+    let text = ffi::slice(text_ptr, text_len);
+    let offsets = ffi::slice(offsets_ptr, offsets_len);
+    if ffi::aliased(text_ptr, text_len, out_ptr as *const u8, words * 4)
+        || ffi::aliased(text_ptr, text_len, errors_ptr, rows.div_ceil(8))
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    let out = ffi::slice_mut(out_ptr, words);
+    let errors = ffi::slice_mut(errors_ptr, rows.div_ceil(8));
+    errors.fill(0);
+
+    let mut spans_buf = vec![(0, 0); fields];
+    for (i, (line, row)) in spans(text, offsets)
+        .zip(out.chunks_mut(fields * 2))
+        .enumerate()
+    {
+        let (skipped, line) = trim_breaks(line.unwrap_or_default());
+        if parse(line, &mut spans_buf).is_none() {
+            row.fill(0);
+            errors[i / 8] |= 1 << (i % 8);
+            continue;
+        }
+        let base = offsets[i] as usize + skipped;
+        for (slot, &(start, end)) in row.chunks_mut(2).zip(&spans_buf) {
+            slot.copy_from_slice(&[(base + start) as u32, (base + end) as u32]);
+        }
+    }
+    (words * 4) as isize
+}
+
+/// Split each line of a string batch in Apache Common or Combined Log
+/// Format into nine `[start, end]` `u32` pairs, positions in `text`:
+/// host, ident, user, time, request, status, bytes, referer, user agent.
+/// The time excludes its brackets and the quoted fields their quotes, with
+/// `\"` escapes left in place. Common-format lines get empty referer and
+/// user agent spans at the end of the line.
+///
+/// Lines that do not parse get zeros in `out_ptr` and their bit set in the
+/// error bitmap at `errors_ptr` (bit `i % 8` of byte `i / 8`), which must
+/// hold `errors_len >= ceil(rows / 8)` bytes.
+///
+/// Returns bytes written to `out_ptr` (`rows * 72`), or `-1` when either
+/// output is too small or the offsets are empty.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn log_fields_clf(
+    text_ptr: *const u8,
+    text_len: usize,
+    offsets_ptr: *const u32,
+    offsets_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+    errors_ptr: *mut u8,
+    errors_len: usize,
+) -> isize {
+    fields_batch(
+        text_ptr,
+        text_len,
+        offsets_ptr,
+        offsets_len,
+        out_ptr,
+        out_len_bytes,
+        errors_ptr,
+        errors_len,
+        CLF_FIELDS,
+        clf,
+    )
+}
+
+/// Split each RFC 5424 syslog line of a string batch into nine
+/// `[start, end]` `u32` pairs, positions in `text`: priority (without its
+/// angle brackets), version, timestamp, hostname, app name, process id,
+/// message id, structured data and message. Nil values (`-`) are reported
+/// as they appear; a line without a message gets an empty span at its end.
+///
+/// Errors are reported as in `log_fields_clf`. Returns bytes written to
+/// `out_ptr` (`rows * 72`), or `-1` when either output is too small or the
+/// offsets are empty.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn log_fields_syslog(
+    text_ptr: *const u8,
+    text_len: usize,
+    offsets_ptr: *const u32,
+    offsets_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+    errors_ptr: *mut u8,
+    errors_len: usize,
+) -> isize {
+    fields_batch(
+        text_ptr,
+        text_len,
+        offsets_ptr,
+        offsets_len,
+        out_ptr,
+        out_len_bytes,
+        errors_ptr,
+        errors_len,
+        SYSLOG_FIELDS,
+        syslog,
+    )
+}
+
+/// Append `[row, key_start, key_end, value_start, value_end]` for each pair
+/// of a logfmt line starting at `base` in the text.
+fn logfmt(line: &[u8], row: u32, base: usize, out: &mut Vec<u32>) {
+    let mut l = Line { buf: line, pos: 0 };
+    while !l.at_end() {
+        if matches!(line[l.pos], b' ' | b'\t' | b'"' | b'=') {
+            l.pos += 1;
+            continue;
+        }
+        let key_start = l.pos;
+        while line
+            .get(l.pos)
+            .is_some_and(|b| !matches!(b, b' ' | b'\t' | b'"' | b'='))
+        {
+            l.pos += 1;
+        }
+        let key_end = l.pos;
+        let value = if l.byte(b'=').is_none() {
+            // A bare key: an empty value.
+            (key_end, key_end)
+        } else if line.get(l.pos) == Some(&b'"') {
+            let open = l.pos;
+            l.delimited(b'"', b'"').unwrap_or_else(|| {
+                // An unterminated quote runs to the end of the line.
+                l.pos = line.len();
+                (open + 1, line.len())
+            })
+        } else {
+            let start = l.pos;
+            while line.get(l.pos).is_some_and(|b| !matches!(b, b' ' | b'\t')) {
+                l.pos += 1;
+            }
+            (start, l.pos)
+        };
+        let pair: [usize; PAIR_WORDS] = [
+            row as usize,
+            base + key_start,
+            base + key_end,
+            base + value.0,
+            base + value.1,
+        ];
+        out.extend(pair.map(|v| v as u32));
+    }
+}
+
+/// List the logfmt `key=value` pairs of each line of a string batch. Writes
+/// five `u32`s per pair, in line order: `[row, key_start, key_end,
+/// value_start, value_end]`, positions in `text`. Quoted values exclude
+/// their quotes, with `\"` escapes left in place; a key without `=` has an
+/// empty value; an unterminated quote runs to the end of the line.
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for
+/// inconsistent offsets or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn log_fields_logfmt(
+    text_ptr: *const u8,
+    text_len: usize,
+    offsets_ptr: *const u32,
+    offsets_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    if u32::try_from(text_len).is_err() {
+        return -1;
+    }
+    let text = ffi::slice(text_ptr, text_len);
+    let offsets = ffi::slice(offsets_ptr, offsets_len);
+    let mut pairs = Vec::new();
+    for (row, line) in spans(text, offsets).enumerate() {
+        let Some(line) = line else {
+            return -1;
+        };
+        let (skipped, line) = trim_breaks(line);
+        logfmt(
+            line,
+            row as u32,
+            offsets[row] as usize + skipped,
+            &mut pairs,
+        );
+    }
+    let needed = pairs.len() * 4;
+    if out_len_bytes == 0 {
+        return needed as isize;
+    }
+    if out_len_bytes < needed {
+        return -1;
+    }
+    if ffi::aliased(text_ptr, text_len, out_ptr as *const u8, needed) {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(out_ptr, pairs.len()).copy_from_slice(&pairs);
+    needed as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Export = unsafe extern "C" fn(
+        *const u8,
+        usize,
+        *const u32,
+        usize,
+        *mut u32,
+        usize,
+        *mut u8,
+        usize,
+    ) -> isize;
+
+    /// Join `lines` into a batch and return each line's fields as strings,
+    /// or `None` for lines flagged as errors.
+    fn fields(export: Export, lines: &[&str]) -> Vec<Option<Vec<String>>> {
+        let text = lines.concat();
+        let mut offsets = vec![0u32];
+        for line in lines {
+            offsets.push(offsets.last().unwrap() + line.len() as u32);
+        }
+        let mut out = vec![0u32; lines.len() * 18];
+        let mut errors = vec![0u8; lines.len().div_ceil(8)];
+        let written = unsafe {
+            export(
+                text.as_ptr(),
+                text.len(),
+                offsets.as_ptr(),
+                offsets.len(),
+                out.as_mut_ptr(),
+                out.len() * 4,
+                errors.as_mut_ptr(),
+                errors.len(),
+            )
+        };
+        assert_eq!(written, (out.len() * 4) as isize);
+        out.chunks(18)
+            .enumerate()
+            .map(|(i, row)| {
+                (errors[i / 8] >> (i % 8) & 1 == 0).then(|| {
+                    row.chunks(2)
+                        .map(|s| text[s[0] as usize..s[1] as usize].to_string())
+                        .collect()
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_clf_lines() {
+        let rows = fields(
+            log_fields_clf,
+            &[
+                "127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] \"GET /a.gif HTTP/1.0\" 200 2326\r",
+                // Split at the CR of a CRLF terminator.
+                "\n::1 - - [10/Oct/2000:13:55:36 -0700] \"GET / HTTP/1.1\" 304 - \
+\"http://x/\" \"Mozilla/5.0 \\\"q\\\"\" extra\n",
+                "10.0.0.1 - - [bad time \"GET /\" 200 1\n",
+                "10.0.0.1 - - [t] \"GET /\" 2000 1",
+            ],
+        );
+        assert_eq!(
+            rows[0].as_deref().unwrap(),
+            [
+                "127.0.0.1",
+                "-",
+                "frank",
+                "10/Oct/2000:13:55:36 -0700",
+                "GET /a.gif HTTP/1.0",
+                "200",
+                "2326",
+                "",
+                ""
+            ]
+        );
+        let combined = rows[1].as_deref().unwrap();
+        assert_eq!(combined[6], "-");
+        assert_eq!(combined[7], "http://x/");
+        assert_eq!(combined[8], "Mozilla/5.0 \\\"q\\\"");
+        assert_eq!(rows[2], None);
+        assert_eq!(rows[3], None);
+    }
+
+    #[test]
+    fn splits_syslog_lines() {
+        let rows = fields(
+            log_fields_syslog,
+            &[
+                "<34>1 2003-10-11T22:14:15.003Z mymachine.example.com su - ID47 - 'su root' failed\n",
+                "<165>1 2003-10-11T22:14:15Z host app 1234 - \
+[exampleSDID@32473 iut=\"3\" eventSource=\"App\\]\"][x@1 a=\"\"]\n",
+                "<13>0 2003-10-11T22:14:15Z h a - - - msg",
+                "<13>1 h a - - - msg",
+            ],
+        );
+        assert_eq!(
+            rows[0].as_deref().unwrap(),
+            [
+                "34",
+                "1",
+                "2003-10-11T22:14:15.003Z",
+                "mymachine.example.com",
+                "su",
+                "-",
+                "ID47",
+                "-",
+                "'su root' failed"
+            ]
+        );
+        let sd = rows[1].as_deref().unwrap();
+        assert_eq!(sd[5], "1234");
+        assert_eq!(
+            sd[7],
+            "[exampleSDID@32473 iut=\"3\" eventSource=\"App\\]\"][x@1 a=\"\"]"
+        );
+        assert_eq!(sd[8], "");
+        assert_eq!(rows[2], None, "version 0");
+        assert_eq!(rows[3], None, "missing field");
+    }
+
+    fn pairs(lines: &[&str]) -> Vec<(u32, String, String)> {
+        let text = lines.concat();
+        let mut offsets = vec![0u32];
+        for line in lines {
+            offsets.push(offsets.last().unwrap() + line.len() as u32);
+        }
+        let call = |out: &mut [u32]| unsafe {
+            log_fields_logfmt(
+                text.as_ptr(),
+                text.len(),
+                offsets.as_ptr(),
+                offsets.len(),
+                out.as_mut_ptr(),
+                out.len() * 4,
+            )
+        };
+        let needed = call(&mut []);
+        let mut out = vec![0u32; needed as usize / 4];
+        assert_eq!(call(&mut out), needed);
+        let s = |a: u32, b: u32| text[a as usize..b as usize].to_string();
+        out.chunks(PAIR_WORDS)
+            .map(|p| (p[0], s(p[1], p[2]), s(p[3], p[4])))
+            .collect()
+    }
+
+    #[test]
+    fn lists_logfmt_pairs() {
+        let got = pairs(&[
+            "level=info msg=\"hello \\\"world\\\"\" dur=1.5ms  flag\r\n",
+            "\n",
+            "at=error err=\"unterminated x=1",
+        ]);
+        let want = [
+            (0, "level", "info"),
+            (0, "msg", "hello \\\"world\\\""),
+            (0, "dur", "1.5ms"),
+            (0, "flag", ""),
+            (2, "at", "error"),
+            (2, "err", "unterminated x=1"),
+        ];
+        assert_eq!(got.len(), want.len());
+        for (got, want) in got.iter().zip(want) {
+            assert_eq!((got.0, got.1.as_str(), got.2.as_str()), want);
+        }
+        let mut out = [0u32; 4];
+        let text = b"a=1";
+        let status = unsafe {
+            log_fields_logfmt(text.as_ptr(), 3, [0, 3].as_ptr(), 2, out.as_mut_ptr(), 16)
+        };
+        assert_eq!(status, -1);
+    }
+}
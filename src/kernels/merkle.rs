@@ -0,0 +1,366 @@
+//! SHA-256 Merkle trees over precomputed leaf hashes.
+//!
+//! Trees follow RFC 6962 (Certificate Transparency): an interior node is
+//! `SHA-256(0x01 || left || right)`, and an odd node at the end of a level
+//! moves up unchanged. Leaves are 32-byte hashes supplied by the caller; per
+//! the RFC, hash each chunk as `SHA-256(0x00 || chunk)` so a leaf can never
+//! be mistaken for an interior node. Roots and audit paths therefore match
+//! other RFC 6962 implementations.
+
+use crate::ffi;
+
+const HASH: usize = 32;
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+fn compress(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes(bytes.try_into().unwrap());
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(K[i])
+            .wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        (h, g, f, e, d, c, b, a) = (g, f, e, d.wrapping_add(t1), c, b, a, t1.wrapping_add(t2));
+    }
+    for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *s = s.wrapping_add(v);
+    }
+}
+
+/// SHA-256 of the concatenation of `parts`.
+pub(crate) fn sha256(parts: &[&[u8]]) -> [u8; HASH] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let total: usize = parts.iter().map(|p| p.len()).sum();
+    let mut block = [0u8; 64];
+    let mut filled = 0;
+    for &byte in parts.iter().flat_map(|p| p.iter()) {
+        block[filled] = byte;
+        filled += 1;
+        if filled == 64 {
+            compress(&mut state, &block);
+            filled = 0;
+        }
+    }
+    block[filled] = 0x80;
+    block[filled + 1..].fill(0);
+    if filled >= 56 {
+        compress(&mut state, &block);
+        block.fill(0);
+    }
+    block[56..].copy_from_slice(&(total as u64 * 8).to_be_bytes());
+    compress(&mut state, &block);
+    let mut out = [0u8; HASH];
+    for (bytes, s) in out.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&s.to_be_bytes());
+    }
+    out
+}
+
+fn node(left: &[u8], right: &[u8]) -> [u8; HASH] {
+    sha256(&[&[0x01], left, right])
+}
+
+/// The level above `level`, promoting an odd last node.
+fn parent_level(level: &[[u8; HASH]]) -> Vec<[u8; HASH]> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node(left, right),
+            [single] => *single,
+            _ => [0; HASH],
+        })
+        .collect()
+}
+
+/// Validate a leaf hash array, returning the leaves.
+unsafe fn leaves(leaves_ptr: *const u8, leaves_len: usize) -> Option<Vec<[u8; HASH]>> {
+    if leaves_len == 0 || !leaves_len.is_multiple_of(HASH) {
+        return None;
+    }
+    let bytes = ffi::slice(leaves_ptr, leaves_len);
+    Some(
+        bytes
+            .chunks_exact(HASH)
+            .map(|h| h.try_into().unwrap())
+            .collect(),
+    )
+}
+
+/// Compute the Merkle root of the 32-byte leaf hashes at `leaves_ptr`,
+/// writing 32 bytes. A single leaf is its own root.
+///
+/// Returns bytes written (`32`), or `-1` for an empty or partial leaf array
+/// or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn merkle_root(
+    leaves_ptr: *const u8,
+    leaves_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let Some(mut level) = leaves(leaves_ptr, leaves_len) else {
+        return -1;
+    };
+    if out_len < HASH {
+        return -1;
+    }
+    if ffi::aliased(leaves_ptr, leaves_len, out_ptr, HASH) {
+        return ffi::ALIAS_ERROR;
+    }
+    while level.len() > 1 {
+        level = parent_level(&level);
+    }
+    ffi::slice_mut(out_ptr, HASH).copy_from_slice(&level[0]);
+    HASH as isize
+}
+
+/// Write the audit path of leaf `index`: the 32-byte sibling hashes from
+/// the leaf level up, skipping levels where the node is promoted without a
+/// sibling. `merkle_verify` checks it against the root.
+///
+/// With `out_len == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for an
+/// empty or partial leaf array, an `index` past the last leaf, or a short
+/// output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn merkle_proof(
+    leaves_ptr: *const u8,
+    leaves_len: usize,
+    index: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let Some(mut level) = leaves(leaves_ptr, leaves_len) else {
+        return -1;
+    };
+    let mut i = index as usize;
+    if i >= level.len() {
+        return -1;
+    }
+    let mut path = Vec::new();
+    while level.len() > 1 {
+        if let Some(sibling) = level.get(i ^ 1) {
+            path.extend_from_slice(sibling);
+        }
+        level = parent_level(&level);
+        i /= 2;
+    }
+    if out_len == 0 {
+        return path.len() as isize;
+    }
+    if out_len < path.len() {
+        return -1;
+    }
+    if ffi::aliased(leaves_ptr, leaves_len, out_ptr, path.len()) {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(out_ptr, path.len()).copy_from_slice(&path);
+    path.len() as isize
+}
+
+/// Check that the 32-byte `leaf` hash sits at `index` in a tree of `count`
+/// leaves with the 32-byte `root`, using the audit path from
+/// `merkle_proof`. Verifies one downloaded chunk without the other leaves.
+///
+/// Returns `1` if the path leads to the root, `0` if it does not or has the
+/// wrong length, or `-1` for a leaf or root that is not 32 bytes, a partial
+/// path, or an `index` not below `count`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn merkle_verify(
+    leaf_ptr: *const u8,
+    leaf_len: usize,
+    index: u32,
+    count: u32,
+    proof_ptr: *const u8,
+    proof_len: usize,
+    root_ptr: *const u8,
+    root_len: usize,
+) -> isize {
+    if leaf_len != HASH || root_len != HASH || !proof_len.is_multiple_of(HASH) || index >= count {
+        return -1;
+    }
+    let mut hash: [u8; HASH] = ffi::slice(leaf_ptr, HASH).try_into().unwrap();
+    let mut proof = ffi::slice(proof_ptr, proof_len).chunks_exact(HASH);
+    let (mut i, mut n) = (index as usize, count as usize);
+    while n > 1 {
+        if i % 2 == 1 || i + 1 < n {
+            let Some(sibling) = proof.next() else {
+                return 0;
+            };
+            hash = if i % 2 == 1 {
+                node(sibling, &hash)
+            } else {
+                node(&hash, sibling)
+            };
+        }
+        i /= 2;
+        n = n.div_ceil(2);
+    }
+    let valid = proof.next().is_none() && hash[..] == *ffi::slice(root_ptr, HASH);
+    valid as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    fn tree(n: usize) -> Vec<u8> {
+        (0..n)
+            .flat_map(|i| sha256(&[&[0x00], i.to_string().as_bytes()]))
+            .collect()
+    }
+
+    fn root(leaves: &[u8]) -> [u8; 32] {
+        let mut out = [0u8; 32];
+        let written = unsafe { merkle_root(leaves.as_ptr(), leaves.len(), out.as_mut_ptr(), 32) };
+        assert_eq!(written, 32);
+        out
+    }
+
+    fn proof(leaves: &[u8], index: u32) -> Vec<u8> {
+        let call = |out: &mut [u8]| unsafe {
+            merkle_proof(
+                leaves.as_ptr(),
+                leaves.len(),
+                index,
+                out.as_mut_ptr(),
+                out.len(),
+            )
+        };
+        let needed = call(&mut []);
+        let mut out = vec![0u8; needed as usize];
+        if needed > 0 {
+            assert_eq!(call(&mut out), needed);
+        }
+        out
+    }
+
+    fn verify(leaf: &[u8], index: u32, count: u32, proof: &[u8], root: &[u8]) -> isize {
+        unsafe {
+            merkle_verify(
+                leaf.as_ptr(),
+                leaf.len(),
+                index,
+                count,
+                proof.as_ptr(),
+                proof.len(),
+                root.as_ptr(),
+                root.len(),
+            )
+        }
+    }
+
+    #[test]
+    fn sha256_vectors() {
+        assert_eq!(
+            hex(&sha256(&[b""])),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(&sha256(&[b"a", b"bc"])),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // 56 bytes: the length no longer fits in the first padded block.
+        assert_eq!(
+            hex(&sha256(&[
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            ])),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            hex(&sha256(&[&[b'a'; 1000]])),
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+        );
+    }
+
+    #[test]
+    fn roots_and_proofs_match_rfc_6962() {
+        let leaves = tree(5);
+        let r = root(&leaves);
+        assert_eq!(
+            hex(&r),
+            "b6748f6ed7a99de7da84fd97e1a3bac6fab8999f4a43695cab9528a2de431147"
+        );
+        assert_eq!(root(&leaves[..32]), leaves[..32], "single leaf");
+        let short = |p: Vec<u8>| p.chunks(32).map(|h| hex(&h[..8])).collect::<Vec<_>>();
+        assert_eq!(
+            short(proof(&leaves, 0)),
+            ["2215e8ac4e2b871c", "d51f2dfecb59566d", "11e1f558223f4c71"]
+        );
+        assert_eq!(
+            short(proof(&leaves, 3)),
+            ["fa61e3dec3439589", "cb00989d94a569c0", "11e1f558223f4c71"]
+        );
+        assert_eq!(short(proof(&leaves, 4)), ["9f4a3fc20d4162dc"]);
+
+        for n in 1..=9u32 {
+            let leaves = tree(n as usize);
+            let r = root(&leaves);
+            for i in 0..n {
+                let leaf = &leaves[i as usize * 32..][..32];
+                let p = proof(&leaves, i);
+                assert_eq!(verify(leaf, i, n, &p, &r), 1, "{i} of {n}");
+                if n > 1 {
+                    let other = (i + 1) % n;
+                    assert_eq!(verify(leaf, other, n, &p, &r), 0, "{i} as {other} of {n}");
+                    assert_eq!(verify(leaf, i, n, &p[32..], &r), 0, "short path");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let leaves = tree(3);
+        let mut out = [0u8; 32];
+        let call = |len, out: &mut [u8]| unsafe {
+            merkle_root(leaves.as_ptr(), len, out.as_mut_ptr(), out.len())
+        };
+        assert_eq!(call(0, &mut out), -1);
+        assert_eq!(call(33, &mut out), -1);
+        assert_eq!(call(96, &mut out[..31]), -1);
+        let mut path = [0u8; 64];
+        let past = unsafe { merkle_proof(leaves.as_ptr(), 96, 3, path.as_mut_ptr(), 64) };
+        assert_eq!(past, -1);
+        let r = root(&leaves);
+        assert_eq!(verify(&leaves[..31], 0, 3, &[], &r), -1);
+        assert_eq!(verify(&leaves[..32], 3, 3, &[], &r), -1);
+    }
+}
@@ -0,0 +1,100 @@
+//! Batch kernels exported over the raw-buffer ABI, one module per family.
+//!
+//! Exports follow the same convention as `process_bytes`: inputs arrive as
+//! `(ptr, len)` pairs, outputs as `(out_ptr, out_len)`, and the return value
+//! is the number of bytes written or a negative status (`-1` for bad
+//! arguments or a short output buffer).
+//!
+//! Batches of strings arrive as one text blob plus Arrow-style offsets:
+//! `n + 1` ascending `u32` positions, value `i` being
+//! `text[offsets[i]..offsets[i + 1]]`.
+//!
+//! Typed inputs (`*const f32`, `*const u32`, ...) must be naturally aligned;
+//! allocate them with `alloc_aligned` rather than `alloc_bytes`. Byte
+//! kernels accept any address. Their SIMD paths may `v128_load` straight
+//! from a caller pointer as long as all 16 bytes are in bounds: wasm loads
+//! have no alignment requirement (the memarg alignment is only a hint), so
+//! 16-byte alignment from `alloc_aligned(len, 16)` can make loads faster on
+//! some engines but is never needed for soundness. Only a partial last block
+//! is copied into a local padded block first.
+
+#[cfg(feature = "aead")]
+mod aead;
+mod agg;
+mod align;
+mod ann;
+mod anomaly;
+mod arrow;
+mod binary;
+mod bits;
+mod bytes;
+mod capture;
+mod cdc;
+mod color;
+mod csv;
+mod dataset;
+mod dict;
+mod diff;
+#[cfg(feature = "ed25519")]
+mod ed25519;
+mod erasure;
+mod export;
+mod framing;
+mod freq;
+mod genomics;
+mod geohash;
+mod glob;
+mod graph;
+mod hll;
+mod http;
+pub(crate) mod inflate;
+#[cfg(feature = "kdf")]
+mod kdf;
+mod kmeans;
+mod linalg;
+mod logs;
+mod merkle;
+mod multipart;
+mod noise;
+mod order;
+mod palette;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod pdf;
+mod physics;
+mod pivot;
+mod placeholder;
+mod qr;
+#[cfg(feature = "regex")]
+mod regex;
+mod repair;
+mod resample;
+mod roaring;
+mod sample;
+mod search;
+mod signal;
+mod sparse;
+mod spatial;
+mod sqlite;
+mod time;
+#[cfg(feature = "tokenizer")]
+mod tokenizer;
+mod vcdiff;
+mod vector;
+mod vision;
+mod websocket;
+mod window;
+mod xlsx;
+mod zip;
+mod zonemap;
+
+/// Iterate the values described by `offsets`, yielding `None` for a span
+/// that is reversed or runs past the end of `text`.
+pub(crate) fn spans<'a>(
+    text: &'a [u8],
+    offsets: &'a [u32],
+) -> impl ExactSizeIterator<Item = Option<&'a [u8]>> + 'a {
+    offsets
+        .windows(2)
+        .map(move |w| text.get(w[0] as usize..w[1] as usize))
+}
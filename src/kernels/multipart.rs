@@ -0,0 +1,227 @@
+//! `multipart/form-data` (RFC 7578, RFC 2046) body splitting.
+//!
+//! `multipart_split` finds every `--boundary` delimiter line in an upload
+//! body and reports where each part's header block and body lie, so a
+//! service worker can hand file contents to other kernels as `subarray`s of
+//! the request body without decoding it to a JS string. Line breaks may be
+//! CRLF or bare LF.
+//!
+//! Both the delimiter and the line-end searches run on the search kernel's
+//! 16-byte first-byte scan, keyed on `\n`: boundaries commonly open with a
+//! run of dashes, so keying on the delimiter's own `-` would stop on nearly
+//! every byte of one.
+
+use super::search::for_each_match;
+use crate::ffi;
+
+/// `u32` words per part in `multipart_split` output.
+const PART_WORDS: usize = 4;
+
+/// Position after the line break ending the line that starts at `pos`.
+fn next_line(body: &[u8], pos: usize) -> Option<usize> {
+    let mut end = None;
+    for_each_match(body, b"\n", false, pos, |i| {
+        end = Some(i + 1);
+        false
+    });
+    end
+}
+
+/// Start of each delimiter: `--boundary` at the start of the body or of a
+/// line.
+fn delimiters(body: &[u8], boundary: &[u8]) -> Vec<usize> {
+    let mut needle = b"\n--".to_vec();
+    needle.extend_from_slice(boundary);
+    let mut found = Vec::new();
+    if body.starts_with(&needle[1..]) {
+        found.push(0);
+    }
+    for_each_match(body, &needle, false, 0, |i| {
+        found.push(i + 1);
+        true
+    });
+    found
+}
+
+/// Collect `[headers_start, headers_end, body_start, body_end]` per part.
+fn split(body: &[u8], boundary: &[u8], out: &mut Vec<u32>) -> Option<()> {
+    let delimiters = delimiters(body, boundary);
+    let after = |d: usize| d + 2 + boundary.len();
+    // Parts run up to the closing delimiter, `--boundary--`; anything after
+    // it is epilogue.
+    let last = delimiters
+        .iter()
+        .position(|&d| body[after(d)..].starts_with(b"--"))?;
+    for pair in delimiters[..=last].windows(2) {
+        let (open, close) = (pair[0], pair[1]);
+        // Only whitespace may follow the boundary on its line.
+        let line = next_line(body, after(open))?;
+        if !body[after(open)..line].trim_ascii().is_empty() {
+            return None;
+        }
+        let headers = line;
+        // Headers end at the first empty line.
+        let mut pos = headers;
+        let (headers_end, body_start) = loop {
+            let end = next_line(body, pos).filter(|&end| end <= close)?;
+            if body[pos..end].trim_ascii().is_empty() {
+                break (pos, end);
+            }
+            pos = end;
+        };
+        // The line break before the next delimiter belongs to it.
+        let mut body_end = close;
+        if body[..body_end].ends_with(b"\n") {
+            body_end -= 1;
+        }
+        if body[..body_end].ends_with(b"\r") {
+            body_end -= 1;
+        }
+        let part: [usize; PART_WORDS] = [headers, headers_end, body_start.min(body_end), body_end];
+        out.extend(part.map(|v| v as u32));
+    }
+    Some(())
+}
+
+/// Split the `multipart/form-data` body at `in_ptr` on the `boundary` from
+/// its `Content-Type` header (without the leading `--`). Writes four `u32`s
+/// per part: `[headers_start, headers_end, body_start, body_end]`. The
+/// header block (`Content-Disposition`, `Content-Type`, ...) is
+/// `in[headers_start..headers_end]`, one header per line; the part's content
+/// is `in[body_start..body_end]`, without the line break before the next
+/// delimiter. The preamble and epilogue are skipped.
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes the list needs. Otherwise returns bytes written, or `-1`
+/// for a boundary that is empty or over 70 bytes, a body with no closing
+/// `--boundary--` or a malformed part, or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn multipart_split(
+    in_ptr: *const u8,
+    in_len: usize,
+    boundary_ptr: *const u8,
+    boundary_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    if !(1..=70).contains(&boundary_len) || u32::try_from(in_len).is_err() {
+        return -1;
+    }
+    let body = ffi::slice(in_ptr, in_len);
+    let boundary = ffi::slice(boundary_ptr, boundary_len);
+    let mut parts = Vec::new();
+    if split(body, boundary, &mut parts).is_none() {
+        return -1;
+    }
+    let needed = parts.len() * 4;
+    if out_len_bytes == 0 {
+        return needed as isize;
+    }
+    if out_len_bytes < needed {
+        return -1;
+    }
+    if ffi::aliased(in_ptr, in_len, out_ptr as *const u8, needed)
+        || ffi::aliased(boundary_ptr, boundary_len, out_ptr as *const u8, needed)
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(out_ptr, parts.len()).copy_from_slice(&parts);
+    needed as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts<'a>(body: &'a [u8], boundary: &[u8]) -> Option<Vec<(&'a [u8], &'a [u8])>> {
+        let call = |out: &mut [u32]| unsafe {
+            multipart_split(
+                body.as_ptr(),
+                body.len(),
+                boundary.as_ptr(),
+                boundary.len(),
+                out.as_mut_ptr(),
+                out.len() * 4,
+            )
+        };
+        let needed = call(&mut []);
+        let mut out = vec![0u32; (needed.max(0) / 4) as usize];
+        if needed < 0 || call(&mut out) != needed {
+            return None;
+        }
+        let span = |a: u32, b: u32| &body[a as usize..b as usize];
+        Some(
+            out.chunks(PART_WORDS)
+                .map(|p| (span(p[0], p[1]), span(p[2], p[3])))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn splits_parts() {
+        let body = b"preamble\r\n--XyZ\r\n\
+Content-Disposition: form-data; name=\"a\"\r\n\r\n\
+one\r\n--XyZ  \r\n\
+Content-Disposition: form-data; name=\"f\"; filename=\"f.bin\"\r\n\
+Content-Type: application/octet-stream\r\n\r\n\
+bytes with --XyZ inside\r\n\r\n--XyZ\r\n\r\n\r\n--XyZ--\r\nepilogue";
+        let parts = parts(body, b"XyZ").unwrap();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(
+            parts[0].0,
+            b"Content-Disposition: form-data; name=\"a\"\r\n"
+        );
+        assert_eq!(parts[0].1, b"one");
+        assert!(parts[1]
+            .0
+            .ends_with(b"Content-Type: application/octet-stream\r\n"));
+        assert_eq!(parts[1].1, b"bytes with --XyZ inside\r\n");
+        // A part with no headers and an empty body.
+        assert_eq!(parts[2], (&b""[..], &b""[..]));
+    }
+
+    #[test]
+    fn accepts_bare_line_feeds() {
+        let body = b"--b\nContent-Type: text/plain\n\nhi\n--b--";
+        assert_eq!(
+            parts(body, b"b").unwrap(),
+            [(&b"Content-Type: text/plain\n"[..], &b"hi"[..])]
+        );
+    }
+
+    #[test]
+    fn dashed_boundaries_match_only_at_line_starts() {
+        let boundary = b"----WebKitFormBoundary7MA4YWxk";
+        let mut body = Vec::new();
+        for (i, value) in [&b"a"[..], b"x------WebKitFormBoundary7MA4YWxk"]
+            .iter()
+            .enumerate()
+        {
+            body.extend_from_slice(b"--");
+            body.extend_from_slice(boundary);
+            body.extend_from_slice(
+                format!("\r\nContent-Disposition: form-data; name=\"{i}\"\r\n\r\n").as_bytes(),
+            );
+            body.extend_from_slice(value);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(b"--");
+        body.extend_from_slice(boundary);
+        body.extend_from_slice(b"--\r\n");
+
+        let parts = parts(&body, boundary).unwrap();
+        let values: Vec<_> = parts.iter().map(|p| p.1).collect();
+        assert_eq!(values, [&b"a"[..], b"x------WebKitFormBoundary7MA4YWxk"]);
+    }
+
+    #[test]
+    fn rejects_malformed_bodies() {
+        assert_eq!(parts(b"--b\r\n\r\nhi\r\n--b\r\n", b"b"), None, "no close");
+        assert_eq!(parts(b"--b\r\nX: y\r\n--b--", b"b"), None, "no blank line");
+        assert_eq!(parts(b"--bx\r\n\r\nhi\r\n--b--", b"b"), None);
+        assert_eq!(parts(b"--b--", b""), None);
+        assert_eq!(parts(b"--b--", b"b").unwrap(), []);
+        assert_eq!(parts(b"--b--\r\n--b\r\n", b"b").unwrap(), [], "epilogue");
+    }
+}
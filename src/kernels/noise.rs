@@ -0,0 +1,260 @@
+//! Seeded 2D simplex noise for procedural textures and terrain.
+//!
+//! Gradients come from hashing the lattice corner with the seed, so there is
+//! no permutation table to build and any `u32` seed gives an independent,
+//! reproducible field. Values lie in `[-1, 1]`. Sample points are given as
+//! separate `x` and `y` arrays, so a grid is just the flattened coordinates
+//! and scattered points cost the same.
+
+use crate::ffi;
+
+const MAX_OCTAVES: u32 = 16;
+
+/// Skew and unskew factors between the square and simplex lattices.
+const F2: f32 = 0.366_025_42; // (sqrt(3) - 1) / 2
+const G2: f32 = 0.211_324_87; // (3 - sqrt(3)) / 6
+
+/// Scales the summed corner contributions to `[-1, 1]`.
+const SCALE: f32 = 70.0;
+
+const GRADIENTS: [(f32, f32); 8] = [
+    (1.0, 1.0),
+    (-1.0, 1.0),
+    (1.0, -1.0),
+    (-1.0, -1.0),
+    (1.0, 0.0),
+    (-1.0, 0.0),
+    (0.0, 1.0),
+    (0.0, -1.0),
+];
+
+/// Gradient at lattice corner `(i, j)`.
+fn gradient(i: i32, j: i32, seed: u32) -> (f32, f32) {
+    let mut h = (i as u32).wrapping_mul(0x27D4_EB2D)
+        ^ (j as u32).wrapping_mul(0x1656_67B1)
+        ^ seed.wrapping_mul(0x9E37_79B9);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x85EB_CA6B);
+    h ^= h >> 13;
+    GRADIENTS[(h >> 29) as usize]
+}
+
+/// Simplex noise at `(x, y)`.
+fn simplex(x: f32, y: f32, seed: u32) -> f32 {
+    let s = (x + y) * F2;
+    let (i, j) = ((x + s).floor(), (y + s).floor());
+    let t = (i + j) * G2;
+    let (x0, y0) = (x - (i - t), y - (j - t));
+    // The middle corner of the triangle containing the point.
+    let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+    let (i, j) = (i as i32, j as i32);
+    let corners = [
+        (x0, y0, 0, 0),
+        (x0 - i1 as f32 + G2, y0 - j1 as f32 + G2, i1, j1),
+        (x0 - 1.0 + 2.0 * G2, y0 - 1.0 + 2.0 * G2, 1, 1),
+    ];
+    let mut sum = 0.0;
+    for (dx, dy, di, dj) in corners {
+        let falloff = 0.5 - dx * dx - dy * dy;
+        if falloff > 0.0 {
+            let (gx, gy) = gradient(i.wrapping_add(di), j.wrapping_add(dj), seed);
+            sum += falloff.powi(4) * (gx * dx + gy * dy);
+        }
+    }
+    (sum * SCALE).clamp(-1.0, 1.0)
+}
+
+type Points<'a> = (&'a [f32], &'a [f32], &'a mut [f32]);
+
+/// Validate the coordinate arrays and output, returning them.
+unsafe fn points<'a>(
+    xs_ptr: *const f32,
+    xs_len_bytes: usize,
+    ys_ptr: *const f32,
+    ys_len_bytes: usize,
+    out_ptr: *mut f32,
+    out_len_bytes: usize,
+) -> Result<Points<'a>, isize> {
+    if xs_len_bytes != ys_len_bytes
+        || !xs_len_bytes.is_multiple_of(4)
+        || out_len_bytes < xs_len_bytes
+    {
+        return Err(-1);
+    }
+    let out_bytes = out_ptr as *const u8;
+    if ffi::aliased(xs_ptr as *const u8, xs_len_bytes, out_bytes, xs_len_bytes)
+        || ffi::aliased(ys_ptr as *const u8, ys_len_bytes, out_bytes, xs_len_bytes)
+    {
+        return Err(ffi::ALIAS_ERROR);
+    }
+    let n = xs_len_bytes / 4;
+    Ok((
+        ffi::slice(xs_ptr, n),
+        ffi::slice(ys_ptr, n),
+        ffi::slice_mut(out_ptr, n),
+    ))
+}
+
+/// Sample simplex noise at each point `(xs[k], ys[k])`, writing one `f32`
+/// in `[-1, 1]` per point. Features are about one unit across, so scale
+/// the coordinates to set the frequency. The same `seed` and point always
+/// give the same value.
+///
+/// Returns bytes written, or `-1` for mismatched or partial coordinates or a
+/// short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn noise2d_f32(
+    xs_ptr: *const f32,
+    xs_len_bytes: usize,
+    ys_ptr: *const f32,
+    ys_len_bytes: usize,
+    seed: u32,
+    out_ptr: *mut f32,
+    out_len_bytes: usize,
+) -> isize {
+    let (xs, ys, out) = match points(
+        xs_ptr,
+        xs_len_bytes,
+        ys_ptr,
+        ys_len_bytes,
+        out_ptr,
+        out_len_bytes,
+    ) {
+        Ok(p) => p,
+        Err(status) => return status,
+    };
+    for ((slot, &x), &y) in out.iter_mut().zip(xs).zip(ys) {
+        *slot = simplex(x, y, seed);
+    }
+    xs_len_bytes as isize
+}
+
+/// Fractal (fBm) noise: the sum of `octaves` layers of simplex noise, layer
+/// `k` sampled at `lacunarity^k` times the frequency with `gain^k` times the
+/// amplitude, and a seed derived from `seed` and `k`. The sum is divided by
+/// the total amplitude, so values stay in `[-1, 1]`. `lacunarity = 2` and
+/// `gain = 0.5` are typical for terrain.
+///
+/// Returns bytes written, or `-1` for `octaves` outside `1..=16`, a
+/// non-finite or non-positive `lacunarity` or `gain`, mismatched or partial
+/// coordinates, or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn fbm2d_f32(
+    xs_ptr: *const f32,
+    xs_len_bytes: usize,
+    ys_ptr: *const f32,
+    ys_len_bytes: usize,
+    seed: u32,
+    octaves: u32,
+    lacunarity: f32,
+    gain: f32,
+    out_ptr: *mut f32,
+    out_len_bytes: usize,
+) -> isize {
+    let valid = |v: f32| v.is_finite() && v > 0.0;
+    if !(1..=MAX_OCTAVES).contains(&octaves) || !valid(lacunarity) || !valid(gain) {
+        return -1;
+    }
+    let (xs, ys, out) = match points(
+        xs_ptr,
+        xs_len_bytes,
+        ys_ptr,
+        ys_len_bytes,
+        out_ptr,
+        out_len_bytes,
+    ) {
+        Ok(p) => p,
+        Err(status) => return status,
+    };
+    let layers: Vec<(f32, f32, u32)> = (0..octaves)
+        .map(|k| {
+            let octave_seed = seed.wrapping_add(k.wrapping_mul(0x6C8E_9CF5));
+            (lacunarity.powi(k as i32), gain.powi(k as i32), octave_seed)
+        })
+        .collect();
+    let total: f32 = layers.iter().map(|l| l.1).sum();
+    for ((slot, &x), &y) in out.iter_mut().zip(xs).zip(ys) {
+        let sum: f32 = layers
+            .iter()
+            .map(|&(frequency, amplitude, s)| amplitude * simplex(x * frequency, y * frequency, s))
+            .sum();
+        *slot = (sum / total).clamp(-1.0, 1.0);
+    }
+    xs_len_bytes as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(n: usize, step: f32) -> (Vec<f32>, Vec<f32>) {
+        (0..n * n)
+            .map(|k| ((k % n) as f32 * step, (k / n) as f32 * step))
+            .unzip()
+    }
+
+    fn noise(xs: &[f32], ys: &[f32], seed: u32) -> Vec<f32> {
+        let mut out = vec![0f32; xs.len()];
+        let written = unsafe {
+            noise2d_f32(
+                xs.as_ptr(),
+                xs.len() * 4,
+                ys.as_ptr(),
+                ys.len() * 4,
+                seed,
+                out.as_mut_ptr(),
+                out.len() * 4,
+            )
+        };
+        assert_eq!(written, xs.len() as isize * 4);
+        out
+    }
+
+    #[test]
+    fn noise_is_smooth_seeded_and_bounded() {
+        let (xs, ys) = grid(64, 0.137);
+        let a = noise(&xs, &ys, 1);
+        assert_eq!(a, noise(&xs, &ys, 1), "deterministic");
+        assert_ne!(a, noise(&xs, &ys, 2), "seeded");
+        let (min, max) = a
+            .iter()
+            .fold((1f32, -1f32), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        assert!((-1.0..-0.5).contains(&min), "{min}");
+        assert!((0.5..=1.0).contains(&max), "{max}");
+        // Zero at lattice points, and no jumps between neighbours.
+        assert_eq!(noise(&[0.0], &[0.0], 9), [0.0]);
+        let shifted: Vec<f32> = xs.iter().map(|x| x + 0.01).collect();
+        let b = noise(&shifted, &ys, 1);
+        assert!(a.iter().zip(&b).all(|(a, b)| (a - b).abs() < 0.1));
+    }
+
+    #[test]
+    fn fractal_noise_sums_octaves() {
+        let (xs, ys) = grid(32, 0.21);
+        let mut out = vec![0f32; xs.len()];
+        let fbm = |octaves: u32, gain: f32, out: &mut [f32]| unsafe {
+            fbm2d_f32(
+                xs.as_ptr(),
+                xs.len() * 4,
+                ys.as_ptr(),
+                ys.len() * 4,
+                5,
+                octaves,
+                2.0,
+                gain,
+                out.as_mut_ptr(),
+                out.len() * 4,
+            )
+        };
+        assert_eq!(fbm(1, 0.5, &mut out), 4096);
+        assert_eq!(out, noise(&xs, &ys, 5), "one octave is plain noise");
+        assert_eq!(fbm(6, 0.5, &mut out), 4096);
+        assert!(out.iter().all(|v| (-1.0..=1.0).contains(v)));
+        assert_eq!(fbm(0, 0.5, &mut out), -1);
+        assert_eq!(fbm(17, 0.5, &mut out), -1);
+        assert_eq!(fbm(4, f32::NAN, &mut out), -1);
+        assert_eq!(fbm(4, 0.5, &mut out[..1]), -1, "short output");
+    }
+}
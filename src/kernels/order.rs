@@ -0,0 +1,159 @@
+//! Approximate sort order from a sampled quantile sketch.
+//!
+//! Sorting a large column exactly takes long enough to stall a UI. A table
+//! view can instead call `approx_sort_order` first: it sorts a random sample,
+//! takes evenly spaced sample quantiles as bucket boundaries and tags every
+//! row with its bucket. Ordering rows by bucket id gives an immediate,
+//! roughly sorted view (exact between buckets, unordered within one) while
+//! the exact sort runs in a worker.
+
+use crate::error::{self, ErrorCode};
+use crate::ffi;
+
+/// Fixed xorshift seed, so the same column always gets the same buckets.
+const SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Assign each `f64` row to one of `buckets` ordered buckets, writing one
+/// `u32` bucket id per row: every value in bucket `b` is `<=` every value in
+/// bucket `b + 1`. Boundaries are quantiles of a random sample of about
+/// `sample_rate * rows` rows (at least `buckets` rows), so buckets hold
+/// roughly equal numbers of rows. `NaN` rows go to the last bucket.
+///
+/// `sample_rate` must lie in `(0, 1]` and `buckets` must be non-zero.
+/// Returns bytes written, or `-1` for a partial value, an argument out of
+/// range or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn approx_sort_order(
+    values_ptr: *const f64,
+    values_len_bytes: usize,
+    sample_rate: f64,
+    buckets: u32,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let n = values_len_bytes / 8;
+    let needed = n * 4;
+    if !values_len_bytes.is_multiple_of(8) {
+        return error::fail(ErrorCode::BadLength);
+    }
+    if !(sample_rate > 0.0 && sample_rate <= 1.0) || buckets == 0 {
+        return error::fail(ErrorCode::InvalidArgument);
+    }
+    if out_len_bytes < needed {
+        return error::fail(ErrorCode::ShortOutput);
+    }
+    if ffi::aliased(
+        values_ptr as *const u8,
+        values_len_bytes,
+        out_ptr as *const u8,
+        needed,
+    ) {
+        return ffi::ALIAS_ERROR;
+    }
+    let values = ffi::slice(values_ptr, n);
+    let out = ffi::slice_mut(out_ptr, n);
+
+    let rate = sample_rate.max(buckets as f64 / n.max(1) as f64);
+    let mut rng = SEED;
+    let mut sample: Vec<f64> = values
+        .iter()
+        .copied()
+        .filter(|_| {
+            rng ^= rng << 13;
+            rng ^= rng >> 7;
+            rng ^= rng << 17;
+            ((rng >> 11) as f64 / (1u64 << 53) as f64) < rate
+        })
+        .filter(|v| !v.is_nan())
+        .collect();
+    sample.sort_unstable_by(f64::total_cmp);
+
+    // Upper boundary of each bucket but the last.
+    let bounds: Vec<f64> = if sample.is_empty() {
+        Vec::new()
+    } else {
+        (1..buckets as usize)
+            .map(|b| sample[b * sample.len() / buckets as usize])
+            .collect()
+    };
+    for (id, &v) in out.iter_mut().zip(values) {
+        *id = if v.is_nan() {
+            buckets - 1
+        } else {
+            bounds.partition_point(|&bound| bound <= v) as u32
+        };
+    }
+    needed as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(values: &[f64], sample_rate: f64, buckets: u32) -> Vec<u32> {
+        let mut out = vec![u32::MAX; values.len()];
+        let written = unsafe {
+            approx_sort_order(
+                values.as_ptr(),
+                values.len() * 8,
+                sample_rate,
+                buckets,
+                out.as_mut_ptr(),
+                out.len() * 4,
+            )
+        };
+        assert_eq!(written, values.len() as isize * 4);
+        out
+    }
+
+    #[test]
+    fn buckets_are_ordered_and_balanced() {
+        // A shuffled permutation of 0..10_000.
+        let values: Vec<f64> = (0..10_000u64).map(|i| (i * 7919 % 10_000) as f64).collect();
+        let ids = order(&values, 0.05, 10);
+        let mut rows: Vec<(f64, u32)> = values.iter().copied().zip(ids.iter().copied()).collect();
+        rows.sort_by(|a, b| a.0.total_cmp(&b.0));
+        assert!(
+            rows.windows(2).all(|w| w[0].1 <= w[1].1),
+            "monotone in value"
+        );
+
+        let mut sizes = [0usize; 10];
+        for &id in &ids {
+            sizes[id as usize] += 1;
+        }
+        assert!(sizes.iter().all(|&s| (700..1300).contains(&s)), "{sizes:?}");
+    }
+
+    #[test]
+    fn nan_and_small_inputs() {
+        let ids = order(&[3.0, f64::NAN, 1.0, 2.0], 0.01, 4);
+        assert_eq!(ids[1], 3, "NaN in the last bucket");
+        assert!(ids[2] <= ids[3] && ids[3] <= ids[0]);
+        assert_eq!(order(&[f64::NAN, f64::NAN], 1.0, 3), [2, 2]);
+        assert_eq!(order(&[5.0, 5.0, 5.0], 1.0, 1), [0, 0, 0]);
+        assert_eq!(order(&[], 0.5, 8), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let values = [1.0, 2.0];
+        let mut out = [0u32; 2];
+        let call = |len, rate, buckets, out_len, out: &mut [u32; 2]| unsafe {
+            approx_sort_order(
+                values.as_ptr(),
+                len,
+                rate,
+                buckets,
+                out.as_mut_ptr(),
+                out_len,
+            )
+        };
+        assert_eq!(call(12, 0.5, 4, 8, &mut out), -1, "partial value");
+        assert_eq!(call(16, 0.0, 4, 8, &mut out), -1, "zero rate");
+        assert_eq!(call(16, f64::NAN, 4, 8, &mut out), -1, "NaN rate");
+        assert_eq!(call(16, 0.5, 0, 8, &mut out), -1, "no buckets");
+        assert_eq!(call(16, 0.5, 4, 4, &mut out), -1, "short output");
+    }
+}
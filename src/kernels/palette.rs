@@ -0,0 +1,264 @@
+//! Palette extraction from RGBA images: median cut refined by k-means.
+//!
+//! Pixels are first binned into a 32768-entry histogram (5 bits per channel)
+//! that keeps exact channel sums, so everything after that one pass scales
+//! with the number of distinct colors rather than the image size. Median cut
+//! splits the occupied bins into the requested number of boxes, then weighted
+//! Lloyd iterations, with the k-means kernel's nearest-centroid search, move
+//! each color to the mean of the pixels closest to it.
+
+use super::kmeans::closest;
+use crate::ffi;
+
+const MAX_COLORS: u32 = 256;
+/// Lloyd iterations after median cut, stopping early once bins settle.
+const ITERATIONS: usize = 16;
+/// Pixels with lower alpha are left out.
+const MIN_ALPHA: u8 = 128;
+
+/// Occupied histogram bin: pixel count and per-channel sums.
+struct Bin {
+    count: u64,
+    sums: [u64; 3],
+    mean: [f32; 3],
+}
+
+fn histogram(rgba: &[u8]) -> Vec<Bin> {
+    let mut counts = vec![0u64; 1 << 15];
+    let mut sums = vec![[0u64; 3]; 1 << 15];
+    for px in rgba.chunks_exact(4) {
+        if px[3] < MIN_ALPHA {
+            continue;
+        }
+        let bin = (px[0] as usize >> 3) << 10 | (px[1] as usize >> 3) << 5 | px[2] as usize >> 3;
+        counts[bin] += 1;
+        for c in 0..3 {
+            sums[bin][c] += px[c] as u64;
+        }
+    }
+    counts
+        .iter()
+        .zip(&sums)
+        .filter(|(&count, _)| count > 0)
+        .map(|(&count, &sums)| Bin {
+            count,
+            sums,
+            mean: sums.map(|s| (s as f64 / count as f64) as f32),
+        })
+        .collect()
+}
+
+/// Mean color of the bins in `members`.
+fn mean(bins: &[Bin], members: &[usize]) -> [f32; 3] {
+    let count: u64 = members.iter().map(|&b| bins[b].count).sum();
+    [0, 1, 2].map(|c| {
+        let sum: u64 = members.iter().map(|&b| bins[b].sums[c]).sum();
+        (sum as f64 / count as f64) as f32
+    })
+}
+
+/// Split the bins into at most `n` boxes, each time halving the most
+/// populous box at the weighted median of its widest channel.
+fn median_cut(bins: &[Bin], n: usize) -> Vec<Vec<usize>> {
+    let mut boxes = vec![(0..bins.len()).collect::<Vec<_>>()];
+    while boxes.len() < n {
+        let population = |b: &Vec<usize>| b.iter().map(|&i| bins[i].count).sum::<u64>();
+        let Some(widest) = (0..boxes.len())
+            .filter(|&i| boxes[i].len() > 1)
+            .max_by_key(|&i| population(&boxes[i]))
+        else {
+            break;
+        };
+        let mut members = std::mem::take(&mut boxes[widest]);
+        let range = |c: usize| {
+            let values = members.iter().map(|&b| bins[b].mean[c]);
+            values.clone().fold(f32::MIN, f32::max) - values.fold(f32::MAX, f32::min)
+        };
+        let channel = (0..3)
+            .max_by(|&a, &b| range(a).total_cmp(&range(b)))
+            .unwrap();
+        members.sort_by(|&a, &b| bins[a].mean[channel].total_cmp(&bins[b].mean[channel]));
+        let half = population(&members).div_ceil(2);
+        let mut seen = 0;
+        let split = members
+            .iter()
+            .position(|&b| {
+                seen += bins[b].count;
+                seen >= half
+            })
+            .map_or(1, |i| i + 1)
+            .clamp(1, members.len() - 1);
+        boxes.push(members.split_off(split));
+        boxes[widest] = members;
+    }
+    boxes
+}
+
+/// Extract up to `n_colors` representative colors from the RGBA pixels at
+/// `rgba_ptr`, skipping pixels with alpha below 128. Writes one `u32` pair
+/// per color, `[0xRRGGBB, pixels]`, most common first (ties by color). Fewer
+/// colors come back when the image has fewer distinct colors at 5 bits per
+/// channel. The same image always gives the same palette.
+///
+/// Returns bytes written, or `-1` for `n_colors` outside `1..=256`, a partial
+/// pixel, or an output shorter than `n_colors * 8` bytes.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn extract_palette(
+    rgba_ptr: *const u8,
+    rgba_len: usize,
+    n_colors: u32,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    if !(1..=MAX_COLORS).contains(&n_colors)
+        || !rgba_len.is_multiple_of(4)
+        || out_len_bytes / 8 < n_colors as usize
+    {
+        return -1;
+    }
+    if ffi::aliased(
+        rgba_ptr,
+        rgba_len,
+        out_ptr as *const u8,
+        n_colors as usize * 8,
+    ) {
+        return ffi::ALIAS_ERROR;
+    }
+    let bins = histogram(ffi::slice(rgba_ptr, rgba_len));
+    if bins.is_empty() {
+        return 0;
+    }
+    let boxes = median_cut(&bins, n_colors as usize);
+    let mut centroids: Vec<f32> = boxes.iter().flat_map(|b| mean(&bins, b)).collect();
+
+    let k = boxes.len();
+    let mut assignments = vec![u32::MAX; bins.len()];
+    let mut counts = vec![0u64; k];
+    for _ in 0..ITERATIONS {
+        let mut changed = false;
+        for (a, bin) in assignments.iter_mut().zip(&bins) {
+            let c = closest(&bin.mean, &centroids, 3);
+            changed |= c != *a;
+            *a = c;
+        }
+        if !changed {
+            break;
+        }
+        let mut sums = vec![[0u64; 3]; k];
+        counts.fill(0);
+        for (&a, bin) in assignments.iter().zip(&bins) {
+            counts[a as usize] += bin.count;
+            for (sum, s) in sums[a as usize].iter_mut().zip(bin.sums) {
+                *sum += s;
+            }
+        }
+        for ((centroid, sum), &count) in centroids.chunks_exact_mut(3).zip(&sums).zip(&counts) {
+            if count > 0 {
+                for c in 0..3 {
+                    centroid[c] = (sum[c] as f64 / count as f64) as f32;
+                }
+            }
+        }
+    }
+
+    let mut palette: Vec<(u64, u32)> = centroids
+        .chunks_exact(3)
+        .zip(&counts)
+        .filter(|(_, &count)| count > 0)
+        .map(|(centroid, &count)| {
+            let rgb = centroid.iter().fold(0u32, |rgb, &c| {
+                rgb << 8 | c.round().clamp(0.0, 255.0) as u32
+            });
+            (count, rgb)
+        })
+        .collect();
+    palette.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    let out = ffi::slice_mut(out_ptr, palette.len() * 2);
+    for (slot, &(count, rgb)) in out.chunks_exact_mut(2).zip(&palette) {
+        slot.copy_from_slice(&[rgb, count.min(u32::MAX as u64) as u32]);
+    }
+    (palette.len() * 8) as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palette(rgba: &[u8], n: u32) -> Vec<[u32; 2]> {
+        let mut out = vec![0u32; n as usize * 2];
+        let written = unsafe {
+            extract_palette(
+                rgba.as_ptr(),
+                rgba.len(),
+                n,
+                out.as_mut_ptr(),
+                out.len() * 4,
+            )
+        };
+        assert!(written >= 0, "{written}");
+        out.truncate(written as usize / 4);
+        out.chunks(2).map(|p| [p[0], p[1]]).collect()
+    }
+
+    fn image(colors: &[([u8; 4], usize)]) -> Vec<u8> {
+        colors
+            .iter()
+            .flat_map(|&(px, n)| std::iter::repeat_n(px, n).flatten())
+            .collect()
+    }
+
+    #[test]
+    fn recovers_flat_colors() {
+        let rgba = image(&[
+            ([200, 30, 40, 255], 50),
+            ([10, 120, 250, 255], 300),
+            ([250, 250, 250, 200], 120),
+            ([0, 255, 0, 0], 1000), // transparent
+        ]);
+        let want = [[0x0A78FA, 300], [0xFAFAFA, 120], [0xC81E28, 50]];
+        assert_eq!(palette(&rgba, 3), want);
+        assert_eq!(palette(&rgba, 8), want, "no more colors than exist");
+        assert_eq!(palette(&rgba, 1), [[0x5B90E4, 470]]);
+        assert!(palette(&image(&[([1, 2, 3, 0], 9)]), 4).is_empty());
+    }
+
+    #[test]
+    fn quantizes_a_gradient() {
+        // Two gradients: reds in 3/4 of the image, blues in the rest.
+        let mut rgba = Vec::new();
+        for i in 0..3000u32 {
+            let t = (i % 100) as u8;
+            rgba.extend(if i % 4 == 3 {
+                [t, t, 155 + t, 255]
+            } else {
+                [155 + t, t / 2, 0, 255]
+            });
+        }
+        let colors = palette(&rgba, 4);
+        assert_eq!(colors.len(), 4);
+        assert_eq!(colors.iter().map(|c| c[1]).sum::<u32>(), 3000);
+        assert!(colors.windows(2).all(|w| w[0][1] >= w[1][1]));
+        // Red-dominant colors hold the larger share.
+        let reds: u32 = colors
+            .iter()
+            .filter(|c| c[0] >> 16 > (c[0] & 0xFF))
+            .map(|c| c[1])
+            .sum();
+        assert_eq!(reds, 2250);
+        assert_eq!(palette(&rgba, 4), colors, "deterministic");
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let rgba = [0u8; 8];
+        let mut out = [0u32; 4];
+        let call = |len, n, out: &mut [u32]| unsafe {
+            extract_palette(rgba.as_ptr(), len, n, out.as_mut_ptr(), out.len() * 4)
+        };
+        assert_eq!(call(8, 0, &mut out), -1);
+        assert_eq!(call(8, 257, &mut out), -1);
+        assert_eq!(call(7, 1, &mut out), -1, "partial pixel");
+        assert_eq!(call(8, 3, &mut out), -1, "short output");
+    }
+}
@@ -0,0 +1,315 @@
+//! Parquet data-page value decoders.
+//!
+//! These cover the hot loops of a Parquet reader and nothing else: the JS
+//! side parses the Thrift footer and page headers, decompresses the page, and
+//! hands the value section to one of these kernels along with the counts
+//! from the header. All multi-byte values are little-endian, as in the file.
+//!
+//! - `parquet_plain_fixed`: PLAIN `INT32`, `INT64`, `INT96`, `FLOAT`,
+//!   `DOUBLE` and `FIXED_LEN_BYTE_ARRAY`, all fixed-width copies.
+//! - `parquet_plain_boolean`: PLAIN `BOOLEAN`, bit-packed LSB first.
+//! - `parquet_plain_byte_array`: PLAIN `BYTE_ARRAY`, into a string batch.
+//! - `parquet_rle_hybrid`: the RLE/bit-packed hybrid used for repetition and
+//!   definition levels and for dictionary indices.
+
+use crate::ffi;
+
+/// Copy `count` PLAIN values of `width` bytes each from the page at
+/// `page_ptr` to `out_ptr`. Returns bytes written (`count * width`), or `-1`
+/// when `width` is `0` or the page or output is short.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn parquet_plain_fixed(
+    page_ptr: *const u8,
+    page_len: usize,
+    width: usize,
+    count: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let Some(bytes) = width.checked_mul(count) else {
+        return -1;
+    };
+    if width == 0 || page_len < bytes || out_len < bytes {
+        return -1;
+    }
+    if ffi::aliased(page_ptr, bytes, out_ptr, bytes) {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(out_ptr, bytes).copy_from_slice(ffi::slice(page_ptr, bytes));
+    bytes as isize
+}
+
+/// Unpack `count` PLAIN booleans to one byte each (`0` or `1`). Returns bytes
+/// written (`count`), or `-1` when the page holds fewer than `count` bits or
+/// the output is short.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn parquet_plain_boolean(
+    page_ptr: *const u8,
+    page_len: usize,
+    count: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    if page_len < count.div_ceil(8) || out_len < count {
+        return -1;
+    }
+    if ffi::aliased(page_ptr, count.div_ceil(8), out_ptr, count) {
+        return ffi::ALIAS_ERROR;
+    }
+    let page = ffi::slice(page_ptr, count.div_ceil(8));
+    let out = ffi::slice_mut(out_ptr, count);
+    for (i, o) in out.iter_mut().enumerate() {
+        *o = (page[i / 8] >> (i % 8)) & 1;
+    }
+    count as isize
+}
+
+/// Decode `count` PLAIN byte arrays (each a `u32` length and its bytes) into
+/// a string batch: the bytes back to back at `text_ptr` and `count + 1`
+/// offsets at `offsets_ptr`. A text buffer of `page_len` bytes always
+/// suffices.
+///
+/// Returns bytes written to `text_ptr`, or `-1` when the page ends early or
+/// either output is short.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn parquet_plain_byte_array(
+    page_ptr: *const u8,
+    page_len: usize,
+    count: usize,
+    text_ptr: *mut u8,
+    text_len: usize,
+    offsets_ptr: *mut u32,
+    offsets_len_bytes: usize,
+) -> isize {
+    if offsets_len_bytes / 4 <= count {
+        return -1;
+    }
+    let page = ffi::slice(page_ptr, page_len);
+    // Validate every length before writing anything.
+    let mut values = Vec::with_capacity(count.min(page_len / 4));
+    let mut pos = 0;
+    for _ in 0..count {
+        let Some(len) = page.get(pos..pos + 4) else {
+            return -1;
+        };
+        let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+        let Some(value) = page.get(pos + 4..).and_then(|rest| rest.get(..len)) else {
+            return -1;
+        };
+        values.push(value);
+        pos += 4 + len;
+    }
+    let total = pos - 4 * count;
+    if text_len < total {
+        return -1;
+    }
+    if ffi::aliased(page_ptr, pos, text_ptr, total)
+        || ffi::aliased(page_ptr, pos, offsets_ptr as *const u8, (count + 1) * 4)
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    let text = ffi::slice_mut(text_ptr, total);
+    let offsets = ffi::slice_mut(offsets_ptr, count + 1);
+    let mut end = 0;
+    offsets[0] = 0;
+    for (value, offset) in values.iter().zip(&mut offsets[1..]) {
+        text[end..end + value.len()].copy_from_slice(value);
+        end += value.len();
+        *offset = end as u32;
+    }
+    total as isize
+}
+
+/// ULEB128 varint at `*pos`, advancing past it.
+fn varint(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let b = *data.get(*pos)?;
+        *pos += 1;
+        value |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Decode RLE/bit-packed hybrid runs of `bit_width`-bit values into `out`.
+fn rle_hybrid(data: &[u8], bit_width: u32, out: &mut [u32]) -> Option<()> {
+    let value_bytes = bit_width.div_ceil(8) as usize;
+    let mask = if bit_width == 32 {
+        u32::MAX
+    } else {
+        (1 << bit_width) - 1
+    };
+    let mut pos = 0;
+    let mut filled = 0;
+    while filled < out.len() {
+        let header = varint(data, &mut pos)?;
+        let rest = out.len() - filled;
+        if header & 1 == 0 {
+            // RLE run: one value repeated.
+            let run = usize::try_from(header >> 1).ok()?;
+            let bytes = data.get(pos..pos + value_bytes)?;
+            pos += value_bytes;
+            let value = bytes.iter().rev().fold(0u32, |v, &b| (v << 8) | b as u32);
+            let n = run.min(rest);
+            out[filled..filled + n].fill(value & mask);
+            filled += n;
+        } else {
+            // Bit-packed run of groups of 8 values, LSB first. The final
+            // group may be cut short when the page ends mid-group.
+            let groups = usize::try_from(header >> 1).ok()?;
+            let bits = data.get(pos..)?;
+            let values = groups.checked_mul(8)?;
+            let n = values.min(rest);
+            let needed = (n * bit_width as usize).div_ceil(8);
+            if bits.len() < needed {
+                return None;
+            }
+            for (i, o) in out[filled..filled + n].iter_mut().enumerate() {
+                let start = i * bit_width as usize;
+                // 32 bits starting anywhere in a byte span at most five bytes.
+                let mut word = 0u64;
+                for (k, &b) in bits[start / 8..].iter().take(5).enumerate() {
+                    word |= (b as u64) << (8 * k);
+                }
+                *o = (word >> (start % 8)) as u32 & mask;
+            }
+            pos = pos
+                .checked_add(groups.checked_mul(bit_width as usize)?)?
+                .min(data.len());
+            filled += n;
+        }
+    }
+    Some(())
+}
+
+/// Decode `count` values of `bit_width` bits (`0..=32`) from the
+/// RLE/bit-packed hybrid data at `data_ptr` to `u32`s at `out_ptr`.
+///
+/// Pass only the runs: strip the `u32` length prefix that data page v1
+/// levels carry, and the bit-width byte that starts a dictionary-index page
+/// (passing it as `bit_width` instead).
+///
+/// Returns bytes written (`count * 4`), or `-1` for a bit width over 32,
+/// data that ends before `count` values, or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn parquet_rle_hybrid(
+    data_ptr: *const u8,
+    data_len: usize,
+    bit_width: u32,
+    count: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    if bit_width > 32 || out_len_bytes / 4 < count {
+        return -1;
+    }
+    if ffi::aliased(data_ptr, data_len, out_ptr as *const u8, count * 4) {
+        return ffi::ALIAS_ERROR;
+    }
+    let data = ffi::slice(data_ptr, data_len);
+    let mut values = vec![0u32; count];
+    // Decoded into scratch so a truncated page leaves the output untouched.
+    if rle_hybrid(data, bit_width, &mut values).is_none() {
+        return -1;
+    }
+    ffi::slice_mut(out_ptr, count).copy_from_slice(&values);
+    (count * 4) as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rle(data: &[u8], bit_width: u32, count: usize) -> Option<Vec<u32>> {
+        let mut out = vec![u32::MAX; count];
+        let written = unsafe {
+            parquet_rle_hybrid(
+                data.as_ptr(),
+                data.len(),
+                bit_width,
+                count,
+                out.as_mut_ptr(),
+                count * 4,
+            )
+        };
+        (written >= 0).then_some(out)
+    }
+
+    #[test]
+    fn decodes_rle_and_bit_packed_runs() {
+        // The Parquet spec example: bit width 3, values 0..=7 bit-packed,
+        // then an RLE run of five 4s.
+        let data = [0x03, 0x88, 0xc6, 0xfa, 0x0a, 0x04];
+        assert_eq!(
+            rle(&data, 3, 13).unwrap(),
+            [0, 1, 2, 3, 4, 5, 6, 7, 4, 4, 4, 4, 4]
+        );
+        // A page may end inside a bit-packed group.
+        assert_eq!(rle(&data[..3], 3, 5).unwrap(), [0, 1, 2, 3, 4]);
+        // Width 0: every value is 0 and RLE values take no bytes.
+        assert_eq!(rle(&[0x08], 0, 4).unwrap(), [0; 4]);
+        // Width 32 reads four-byte RLE values.
+        assert_eq!(
+            rle(&[0x02, 0xff, 0xff, 0xff, 0xff], 32, 1).unwrap(),
+            [u32::MAX]
+        );
+        assert_eq!(rle(&data, 3, 14), None, "runs out");
+        assert_eq!(rle(&data, 33, 1), None);
+    }
+
+    #[test]
+    fn decodes_plain_values() {
+        let page = [0b1010_0101u8, 0b0000_0011];
+        let mut bools = [9u8; 10];
+        let written =
+            unsafe { parquet_plain_boolean(page.as_ptr(), 2, 10, bools.as_mut_ptr(), 10) };
+        assert_eq!(written, 10);
+        assert_eq!(bools, [1, 0, 1, 0, 0, 1, 0, 1, 1, 1]);
+
+        let ints = [7i32.to_le_bytes(), (-2i32).to_le_bytes()].concat();
+        let mut out = [0i32; 2];
+        let written =
+            unsafe { parquet_plain_fixed(ints.as_ptr(), 8, 4, 2, out.as_mut_ptr() as *mut u8, 8) };
+        assert_eq!((written, out), (8, [7, -2]));
+        let status =
+            unsafe { parquet_plain_fixed(ints.as_ptr(), 8, 4, 3, out.as_mut_ptr() as *mut u8, 12) };
+        assert_eq!(status, -1, "short page");
+    }
+
+    #[test]
+    fn decodes_plain_byte_arrays() {
+        let page = [
+            &2u32.to_le_bytes()[..],
+            b"hi",
+            &0u32.to_le_bytes(),
+            &3u32.to_le_bytes(),
+            b"abc",
+        ]
+        .concat();
+        let mut text = vec![0u8; page.len()];
+        let mut offsets = [u32::MAX; 4];
+        let decode = |count: usize, text: &mut [u8], offsets: &mut [u32]| unsafe {
+            parquet_plain_byte_array(
+                page.as_ptr(),
+                page.len(),
+                count,
+                text.as_mut_ptr(),
+                text.len(),
+                offsets.as_mut_ptr(),
+                offsets.len() * 4,
+            )
+        };
+        assert_eq!(decode(3, &mut text, &mut offsets), 5);
+        assert_eq!(&text[..5], b"hiabc");
+        assert_eq!(offsets, [0, 2, 2, 5]);
+        assert_eq!(decode(4, &mut text, &mut [0; 5]), -1, "page ends early");
+        assert_eq!(decode(3, &mut text[..4], &mut offsets), -1, "short text");
+    }
+}
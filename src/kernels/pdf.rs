@@ -0,0 +1,304 @@
+//! PDF content-stream tokenizing.
+//!
+//! A page's content stream (after decompression, see `inflate`) is a
+//! postfix program: operands such as numbers, names, strings and arrays,
+//! each followed by an operator like `Tf`, `Tj` or `cm`. `pdf_content_tokens`
+//! lexes a whole stream into token spans in one pass, leaving the operand
+//! stack and text state to JS. Comments are dropped, and the binary data of
+//! inline images (`BI ... ID <data> EI`) comes back as a single token so it
+//! cannot derail the lexer.
+
+use super::search::for_each_match;
+use crate::ffi;
+
+/// Token kinds reported by `pdf_content_tokens`.
+pub const PDF_NUMBER: u32 = 0;
+/// Any other run of regular characters: operators, and the keywords
+/// `true`, `false` and `null`.
+pub const PDF_OPERATOR: u32 = 1;
+/// `/Name`, slash included and `#xx` escapes left as written.
+pub const PDF_NAME: u32 = 2;
+/// `(literal)`, parentheses included and escapes left as written.
+pub const PDF_STRING: u32 = 3;
+/// `<hex>`, angle brackets included.
+pub const PDF_HEX_STRING: u32 = 4;
+pub const PDF_ARRAY_START: u32 = 5;
+pub const PDF_ARRAY_END: u32 = 6;
+pub const PDF_DICT_START: u32 = 7;
+pub const PDF_DICT_END: u32 = 8;
+/// The raw bytes between `ID` and `EI`, separators excluded.
+pub const PDF_INLINE_IMAGE: u32 = 9;
+
+fn is_space(b: u8) -> bool {
+    matches!(b, b'\0' | b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+fn is_delimiter(b: u8) -> bool {
+    matches!(
+        b,
+        b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%'
+    )
+}
+
+fn is_regular(b: u8) -> bool {
+    !is_space(b) && !is_delimiter(b)
+}
+
+/// Whether `token` is a PDF number: an optional sign, then digits with at
+/// most one decimal point.
+fn is_number(token: &[u8]) -> bool {
+    let digits = token
+        .strip_prefix(b"+")
+        .or(token.strip_prefix(b"-"))
+        .unwrap_or(token);
+    let points = digits.iter().filter(|&&b| b == b'.').count();
+    digits.iter().any(u8::is_ascii_digit)
+        && points <= 1
+        && digits.iter().all(|&b| b.is_ascii_digit() || b == b'.')
+}
+
+/// End of the literal string opening at `start`, after its `)`.
+fn literal_end(stream: &[u8], start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut i = start;
+    while i < stream.len() {
+        match stream[i] {
+            b'\\' => i += 1,
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Span of the inline image data starting at `from`, just after the single
+/// separator following `ID`: up to the first `EI` that has white space
+/// before it and white space, a delimiter or the end after it.
+fn inline_image(stream: &[u8], from: usize) -> Option<(usize, usize)> {
+    let mut end = None;
+    for_each_match(stream, b"EI", false, from, |i| {
+        let after = stream.get(i + 2).copied();
+        let ends = i > from
+            && is_space(stream[i - 1])
+            && after.is_none_or(|b| is_space(b) || is_delimiter(b));
+        if ends {
+            end = Some(i - 1);
+        }
+        !ends
+    });
+    end.map(|end| (from, end))
+}
+
+/// Lex `stream` into `[kind, start, len]` triples.
+fn tokens(stream: &[u8], out: &mut Vec<u32>) -> Option<()> {
+    let mut push = |kind: u32, start: usize, end: usize| {
+        out.extend([kind, start as u32, (end - start) as u32]);
+    };
+    let mut i = 0;
+    while i < stream.len() {
+        let start = i;
+        let (kind, end) = match stream[i] {
+            b if is_space(b) => {
+                i += 1;
+                continue;
+            }
+            b'%' => {
+                i += stream[i..]
+                    .iter()
+                    .position(|&b| b == b'\n' || b == b'\r')
+                    .unwrap_or(stream.len() - i);
+                continue;
+            }
+            b'(' => (PDF_STRING, literal_end(stream, i)?),
+            b'<' if stream.get(i + 1) == Some(&b'<') => (PDF_DICT_START, i + 2),
+            b'<' => {
+                let close = i + stream[i..].iter().position(|&b| b == b'>')?;
+                (PDF_HEX_STRING, close + 1)
+            }
+            b'>' if stream.get(i + 1) == Some(&b'>') => (PDF_DICT_END, i + 2),
+            b'[' => (PDF_ARRAY_START, i + 1),
+            b']' => (PDF_ARRAY_END, i + 1),
+            b'{' | b'}' => (PDF_OPERATOR, i + 1),
+            b'/' => {
+                let len = stream[i + 1..]
+                    .iter()
+                    .take_while(|&&b| is_regular(b))
+                    .count();
+                (PDF_NAME, i + 1 + len)
+            }
+            b')' | b'>' => return None,
+            _ => {
+                let len = stream[i..].iter().take_while(|&&b| is_regular(b)).count();
+                let token = &stream[i..i + len];
+                let kind = if is_number(token) {
+                    PDF_NUMBER
+                } else {
+                    PDF_OPERATOR
+                };
+                push(kind, i, i + len);
+                i += len;
+                if token == b"ID" {
+                    // One white-space byte separates `ID` from the data.
+                    let (data_start, data_end) = inline_image(stream, i + 1)?;
+                    push(PDF_INLINE_IMAGE, data_start, data_end);
+                    i = data_end;
+                }
+                continue;
+            }
+        };
+        push(kind, start, end);
+        i = end;
+    }
+    Some(())
+}
+
+/// Lex the decompressed PDF content stream at `stream_ptr` into tokens,
+/// written as three `u32`s each: `[kind, start, len]`, with `kind` one of
+/// the `PDF_*` constants and the token at `stream[start..start + len]`.
+/// Strings and names keep their delimiters and escapes, so
+/// `[(Hello) -250 (World)] TJ` is an array start, a string, a number, a
+/// string, an array end and an operator. Comments are skipped.
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for an
+/// unterminated string or inline image, a stray `)` or `>`, a stream over
+/// 4 GiB, or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn pdf_content_tokens(
+    stream_ptr: *const u8,
+    stream_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let stream = ffi::slice(stream_ptr, stream_len);
+    let mut out = Vec::new();
+    if u32::try_from(stream_len).is_err() || tokens(stream, &mut out).is_none() {
+        return -1;
+    }
+    let needed = out.len() * 4;
+    if out_len_bytes == 0 {
+        return needed as isize;
+    }
+    if out_len_bytes < needed {
+        return -1;
+    }
+    if ffi::aliased(stream_ptr, stream_len, out_ptr as *const u8, needed) {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(out_ptr, out.len()).copy_from_slice(&out);
+    needed as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(stream: &[u8]) -> Option<Vec<(u32, &[u8])>> {
+        let call = |out: &mut [u32]| unsafe {
+            pdf_content_tokens(
+                stream.as_ptr(),
+                stream.len(),
+                out.as_mut_ptr(),
+                out.len() * 4,
+            )
+        };
+        let needed = usize::try_from(call(&mut [])).ok()?;
+        let mut out = vec![0u32; needed / 4];
+        assert_eq!(call(&mut out), needed as isize);
+        Some(
+            out.chunks(3)
+                .map(|t| (t[0], &stream[t[1] as usize..][..t[2] as usize]))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn lexes_text_operators() {
+        let stream = b"BT /F1 12 Tf 72 712.5 Td (Hi \\(there\\) (nested)) Tj\n\
+            [(A) -120 (B)] TJ <48 65> Tj % a comment ] ) >\nET";
+        let tokens = lex(stream).unwrap();
+        let want: [(u32, &[u8]); 18] = [
+            (PDF_OPERATOR, b"BT"),
+            (PDF_NAME, b"/F1"),
+            (PDF_NUMBER, b"12"),
+            (PDF_OPERATOR, b"Tf"),
+            (PDF_NUMBER, b"72"),
+            (PDF_NUMBER, b"712.5"),
+            (PDF_OPERATOR, b"Td"),
+            (PDF_STRING, b"(Hi \\(there\\) (nested))"),
+            (PDF_OPERATOR, b"Tj"),
+            (PDF_ARRAY_START, b"["),
+            (PDF_STRING, b"(A)"),
+            (PDF_NUMBER, b"-120"),
+            (PDF_STRING, b"(B)"),
+            (PDF_ARRAY_END, b"]"),
+            (PDF_OPERATOR, b"TJ"),
+            (PDF_HEX_STRING, b"<48 65>"),
+            (PDF_OPERATOR, b"Tj"),
+            (PDF_OPERATOR, b"ET"),
+        ];
+        assert_eq!(tokens, want);
+    }
+
+    #[test]
+    fn classifies_numbers_names_and_dicts() {
+        let tokens = lex(b"/P <</MCID 3>> BDC .5 -.25 +7 1.2.3 - true/N#20x()EMC").unwrap();
+        let kinds: Vec<u32> = tokens.iter().map(|t| t.0).collect();
+        assert_eq!(
+            kinds,
+            [
+                PDF_NAME,
+                PDF_DICT_START,
+                PDF_NAME,
+                PDF_NUMBER,
+                PDF_DICT_END,
+                PDF_OPERATOR,
+                PDF_NUMBER,
+                PDF_NUMBER,
+                PDF_NUMBER,
+                PDF_OPERATOR,
+                PDF_OPERATOR,
+                PDF_OPERATOR,
+                PDF_NAME,
+                PDF_STRING,
+                PDF_OPERATOR,
+            ]
+        );
+        assert_eq!(tokens[12].1, b"/N#20x");
+    }
+
+    #[test]
+    fn skips_inline_image_data() {
+        let stream = b"q BI /W 2 /H 1 /BPC 8 /CS /G ID \xFFEI(\x00\n)>\nEI Q";
+        let tokens = lex(stream).unwrap();
+        let n = tokens.len();
+        assert_eq!(tokens[n - 4], (PDF_OPERATOR, &b"ID"[..]));
+        assert_eq!(tokens[n - 3], (PDF_INLINE_IMAGE, &b"\xFFEI(\x00\n)>"[..]));
+        assert_eq!(
+            tokens[n - 2..],
+            [(PDF_OPERATOR, &b"EI"[..]), (PDF_OPERATOR, &b"Q"[..])]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_streams() {
+        assert!(lex(b"(open Tj").is_none());
+        assert!(lex(b"<4865 Tj").is_none());
+        assert!(lex(b"1 2 ) Tj").is_none());
+        assert!(lex(b"BI ID \x01\x02").is_none());
+        assert_eq!(lex(b"").unwrap(), []);
+        let stream = b"1 0 0 1 0 0 cm";
+        let mut out = [0u32; 3];
+        let written =
+            unsafe { pdf_content_tokens(stream.as_ptr(), stream.len(), out.as_mut_ptr(), 12) };
+        assert_eq!(written, -1, "short output");
+    }
+}
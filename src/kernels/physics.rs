@@ -0,0 +1,255 @@
+//! Per-frame simulation passes: integration and broad-phase collision.
+//!
+//! State lives in packed `f32` arrays owned by the caller, so a game loop can
+//! keep its bodies in wasm memory and step them without copying. Positions,
+//! velocities and forces are interleaved components (`[x, y]` or
+//! `[x, y, z]` per body); the integrator treats every component alike, so
+//! any dimension works. Boxes are 2D, `[min_x, min_y, max_x, max_y]`.
+
+use crate::ffi;
+
+/// `f32`s per box: `[min_x, min_y, max_x, max_y]`.
+const BOX_WORDS: usize = 4;
+
+/// One step for four components: `v += a * dt / 2; x += v * dt; v += a *
+/// dt / 2`, returning the new `[x, v]`.
+#[inline(always)]
+fn step_block(x: [f32; 4], v: [f32; 4], a: [f32; 4], dt: f32) -> ([f32; 4], [f32; 4]) {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let load = |b: [f32; 4]| v128_load(b.as_ptr() as *const v128);
+        let kick = f32x4_mul(load(a), f32x4_splat(dt * 0.5));
+        let v = f32x4_add(load(v), kick);
+        let x = f32x4_add(load(x), f32x4_mul(v, f32x4_splat(dt)));
+        let (mut xs, mut vs) = ([0f32; 4], [0f32; 4]);
+        v128_store(xs.as_mut_ptr() as *mut v128, x);
+        v128_store(vs.as_mut_ptr() as *mut v128, f32x4_add(v, kick));
+        (xs, vs)
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        let kick = a.map(|a| a * (dt * 0.5));
+        let v: [f32; 4] = std::array::from_fn(|i| v[i] + kick[i]);
+        let x = std::array::from_fn(|i| x[i] + v[i] * dt);
+        (x, std::array::from_fn(|i| v[i] + kick[i]))
+    }
+}
+
+/// Advance the bodies by `dt` with a velocity Verlet step, updating the
+/// positions and velocities in place. `forces_ptr` holds the acceleration of
+/// each component (force divided by mass), taken as constant over the step:
+/// `x += v * dt + a * dt^2 / 2` and `v += a * dt`. All three arrays have the
+/// same length.
+///
+/// Returns bytes updated in each array, `-1` for mismatched or partial
+/// arrays or a non-finite `dt`, or `ALIAS_ERROR` if any two arrays overlap.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn verlet_step_f32(
+    positions_ptr: *mut f32,
+    positions_len_bytes: usize,
+    velocities_ptr: *mut f32,
+    velocities_len_bytes: usize,
+    forces_ptr: *const f32,
+    forces_len_bytes: usize,
+    dt: f32,
+) -> isize {
+    let len = positions_len_bytes;
+    if velocities_len_bytes != len
+        || forces_len_bytes != len
+        || !len.is_multiple_of(4)
+        || !dt.is_finite()
+    {
+        return -1;
+    }
+    let (x_bytes, v_bytes) = (positions_ptr as *const u8, velocities_ptr as *const u8);
+    if ffi::overlaps(x_bytes, len, v_bytes, len)
+        || ffi::aliased(forces_ptr as *const u8, len, x_bytes, len)
+        || ffi::aliased(forces_ptr as *const u8, len, v_bytes, len)
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    let n = len / 4;
+    let xs = ffi::slice_mut(positions_ptr, n);
+    let vs = ffi::slice_mut(velocities_ptr, n);
+    let accels = ffi::slice(forces_ptr, n);
+    for ((x, v), a) in xs.chunks_mut(4).zip(vs.chunks_mut(4)).zip(accels.chunks(4)) {
+        let lanes = |values: &[f32]| {
+            let mut block = [0f32; 4];
+            block[..values.len()].copy_from_slice(values);
+            block
+        };
+        let (new_x, new_v) = step_block(lanes(x), lanes(v), lanes(a), dt);
+        x.copy_from_slice(&new_x[..x.len()]);
+        v.copy_from_slice(&new_v[..v.len()]);
+    }
+    len as isize
+}
+
+/// Find every pair of overlapping boxes among the `[min_x, min_y, max_x,
+/// max_y]` boxes at `boxes_ptr`, writing `u32` index pairs `[i, j]` with
+/// `i < j`, sorted. Boxes sharing only an edge or corner overlap; empty boxes
+/// (`min > max` on either axis, or `NaN` bounds) overlap nothing. A
+/// sort-and-sweep along `x` keeps the cost near `O(n log n)` plus the pairs
+/// checked along `y`.
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for a
+/// partial box or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn aabb_overlap_pairs(
+    boxes_ptr: *const f32,
+    boxes_len_bytes: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    if !boxes_len_bytes.is_multiple_of(BOX_WORDS * 4) {
+        return -1;
+    }
+    let boxes = ffi::slice(boxes_ptr, boxes_len_bytes / 4);
+    let boxes: Vec<&[f32]> = boxes.chunks_exact(BOX_WORDS).collect();
+    let mut order: Vec<u32> = (0..boxes.len() as u32)
+        .filter(|&i| {
+            let b = boxes[i as usize];
+            b[0] <= b[2] && b[1] <= b[3]
+        })
+        .collect();
+    order.sort_unstable_by(|&i, &j| boxes[i as usize][0].total_cmp(&boxes[j as usize][0]));
+
+    let mut pairs = Vec::new();
+    let mut active: Vec<u32> = Vec::new();
+    for &i in &order {
+        let b = boxes[i as usize];
+        active.retain(|&j| boxes[j as usize][2] >= b[0]);
+        for &j in &active {
+            let other = boxes[j as usize];
+            if other[1] <= b[3] && b[1] <= other[3] {
+                pairs.push([i.min(j), i.max(j)]);
+            }
+        }
+        active.push(i);
+    }
+    pairs.sort_unstable();
+
+    let needed = pairs.len() * 8;
+    if out_len_bytes == 0 {
+        return needed as isize;
+    }
+    if out_len_bytes < needed {
+        return -1;
+    }
+    if ffi::aliased(
+        boxes_ptr as *const u8,
+        boxes_len_bytes,
+        out_ptr as *const u8,
+        needed,
+    ) {
+        return ffi::ALIAS_ERROR;
+    }
+    let out = ffi::slice_mut(out_ptr, pairs.len() * 2);
+    for (slot, pair) in out.chunks_exact_mut(2).zip(&pairs) {
+        slot.copy_from_slice(pair);
+    }
+    needed as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_positions_and_velocities() {
+        // Two 3D bodies; the second falls under gravity.
+        let mut x = [0.0f32, 0.0, 0.0, 1.0, 10.0, -2.0];
+        let mut v = [1.0f32, 2.0, 0.0, 0.0, 0.0, 4.0];
+        let a = [0.0f32, 0.0, 0.0, 0.0, -10.0, 0.0];
+        let status =
+            unsafe { verlet_step_f32(x.as_mut_ptr(), 24, v.as_mut_ptr(), 24, a.as_ptr(), 24, 0.5) };
+        assert_eq!(status, 24);
+        assert_eq!(x, [0.5, 1.0, 0.0, 1.0, 8.75, 0.0]);
+        assert_eq!(v, [1.0, 2.0, 0.0, 0.0, -5.0, 4.0]);
+
+        let status =
+            unsafe { verlet_step_f32(x.as_mut_ptr(), 24, v.as_mut_ptr(), 20, a.as_ptr(), 24, 0.5) };
+        assert_eq!(status, -1, "mismatched");
+        let status = unsafe {
+            verlet_step_f32(
+                x.as_mut_ptr(),
+                24,
+                v.as_mut_ptr(),
+                24,
+                a.as_ptr(),
+                24,
+                f32::NAN,
+            )
+        };
+        assert_eq!(status, -1, "NaN dt");
+        let ptr = x.as_mut_ptr();
+        let status = unsafe { verlet_step_f32(ptr, 24, ptr, 24, a.as_ptr(), 24, 0.5) };
+        assert_eq!(status, ffi::ALIAS_ERROR);
+    }
+
+    fn overlaps(boxes: &[f32]) -> Vec<[u32; 2]> {
+        let call = |out: &mut [u32]| unsafe {
+            aabb_overlap_pairs(
+                boxes.as_ptr(),
+                boxes.len() * 4,
+                out.as_mut_ptr(),
+                out.len() * 4,
+            )
+        };
+        let needed = call(&mut []);
+        let mut out = vec![0u32; needed as usize / 4];
+        assert_eq!(call(&mut out), needed);
+        out.chunks(2).map(|p| [p[0], p[1]]).collect()
+    }
+
+    #[test]
+    fn finds_overlapping_boxes() {
+        #[rustfmt::skip]
+        let boxes = [
+            0.0, 0.0, 2.0, 2.0,
+            1.0, 1.0, 3.0, 3.0,
+            5.0, 0.0, 6.0, 1.0,
+            2.0, 3.0, 4.0, 4.0, // touches box 1 at a corner
+            1.5, 10.0, 1.6, 11.0, // inside box 0 along x only
+            1.0, 1.0, 0.0, 0.0, // empty
+        ];
+        assert_eq!(overlaps(&boxes), [[0, 1], [1, 3]]);
+
+        // Matches checking every pair.
+        let mut seed = 7u32;
+        let mut next = || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) as f32 / 65536.0 * 100.0
+        };
+        let boxes: Vec<f32> = (0..200)
+            .flat_map(|_| {
+                let (x, y) = (next(), next());
+                [x, y, x + next() / 10.0, y + next() / 10.0]
+            })
+            .collect();
+        let b: Vec<&[f32]> = boxes.chunks(4).collect();
+        let mut want = Vec::new();
+        for i in 0..b.len() {
+            for j in i + 1..b.len() {
+                if b[i][0] <= b[j][2]
+                    && b[j][0] <= b[i][2]
+                    && b[i][1] <= b[j][3]
+                    && b[j][1] <= b[i][3]
+                {
+                    want.push([i as u32, j as u32]);
+                }
+            }
+        }
+        assert!(!want.is_empty());
+        assert_eq!(overlaps(&boxes), want);
+
+        let mut out = [0u32; 2];
+        let status = unsafe { aabb_overlap_pairs(boxes.as_ptr(), 12, out.as_mut_ptr(), 8) };
+        assert_eq!(status, -1, "partial box");
+    }
+}
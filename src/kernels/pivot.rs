@@ -0,0 +1,152 @@
+//! Pivot tables over dictionary-coded keys.
+//!
+//! Rows arrive as two `u32` code columns (for example the codes written by
+//! `dict_build`) and an optional `f64` measure. The result is a dense,
+//! row-major matrix with one extra column of row totals and one extra row of
+//! column totals, the bottom-right cell holding the grand total, which is
+//! exactly what a BI-style crosstab view renders.
+
+use crate::error::{self, ErrorCode};
+use crate::ffi;
+
+/// Cross-tabulate rows by `a` code (matrix row) and `b` code (matrix
+/// column), summing `values[i]` into cell `(a[i], b[i])`, or counting rows
+/// when `values_len_bytes == 0`. `NaN` values are skipped.
+///
+/// Codes must be below `a_count` and `b_count`. Writes `(a_count + 1) *
+/// (b_count + 1)` `f64`s: cell `(i, j)` at `i * (b_count + 1) + j`, row
+/// totals in the last column and column totals in the last row.
+///
+/// Returns bytes written, or `-1` for mismatched column lengths, a partial
+/// value, a code out of range or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn crosstab(
+    a_ptr: *const u32,
+    a_len_bytes: usize,
+    b_ptr: *const u32,
+    b_len_bytes: usize,
+    values_ptr: *const f64,
+    values_len_bytes: usize,
+    a_count: u32,
+    b_count: u32,
+    out_ptr: *mut f64,
+    out_len_bytes: usize,
+) -> isize {
+    let n = a_len_bytes / 4;
+    if !a_len_bytes.is_multiple_of(4)
+        || b_len_bytes != a_len_bytes
+        || (values_len_bytes != 0 && values_len_bytes != n * 8)
+    {
+        return error::fail(ErrorCode::BadLength);
+    }
+    let (rows, cols) = (a_count as usize + 1, b_count as usize + 1);
+    let Some(needed) = rows.checked_mul(cols).and_then(|c| c.checked_mul(8)) else {
+        return error::fail(ErrorCode::InvalidArgument);
+    };
+    if out_len_bytes < needed {
+        return error::fail(ErrorCode::ShortOutput);
+    }
+    let out_bytes = out_ptr as *const u8;
+    if ffi::aliased(a_ptr as *const u8, a_len_bytes, out_bytes, needed)
+        || ffi::aliased(b_ptr as *const u8, b_len_bytes, out_bytes, needed)
+        || ffi::aliased(values_ptr as *const u8, values_len_bytes, out_bytes, needed)
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    let a = ffi::slice(a_ptr, n);
+    let b = ffi::slice(b_ptr, n);
+    if a.iter().any(|&k| k >= a_count) || b.iter().any(|&k| k >= b_count) {
+        return error::fail(ErrorCode::InvalidInput);
+    }
+    let values = ffi::slice(values_ptr, values_len_bytes / 8);
+    let out = ffi::slice_mut(out_ptr, needed / 8);
+    out.fill(0.0);
+    for (i, (&ka, &kb)) in a.iter().zip(b).enumerate() {
+        let v = values.get(i).copied().unwrap_or(1.0);
+        if !v.is_nan() {
+            out[ka as usize * cols + kb as usize] += v;
+        }
+    }
+    // Marginals from the finished cells: row totals, then each column total
+    // (including the row-total column, which yields the grand total).
+    for row in out[..(rows - 1) * cols].chunks_exact_mut(cols) {
+        row[cols - 1] = row[..cols - 1].iter().sum();
+    }
+    for j in 0..cols {
+        out[(rows - 1) * cols + j] = (0..rows - 1).map(|i| out[i * cols + j]).sum();
+    }
+    needed as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(a: &[u32], b: &[u32], values: &[f64], a_count: u32, b_count: u32) -> (isize, Vec<f64>) {
+        let mut out = vec![f64::NAN; (a_count as usize + 1) * (b_count as usize + 1)];
+        let status = unsafe {
+            crosstab(
+                a.as_ptr(),
+                a.len() * 4,
+                b.as_ptr(),
+                b.len() * 4,
+                values.as_ptr(),
+                values.len() * 8,
+                a_count,
+                b_count,
+                out.as_mut_ptr(),
+                out.len() * 8,
+            )
+        };
+        (status, out)
+    }
+
+    #[test]
+    fn sums_cells_and_marginals() {
+        let a = [0, 1, 0, 1, 1, 0];
+        let b = [0, 0, 2, 2, 2, 1];
+        let values = [1.0, 2.0, 3.0, 4.0, f64::NAN, 5.0];
+        let (status, out) = run(&a, &b, &values, 2, 3);
+        assert_eq!(status, 12 * 8);
+        #[rustfmt::skip]
+        assert_eq!(out, [
+            1.0, 5.0, 3.0, 9.0,
+            2.0, 0.0, 4.0, 6.0,
+            3.0, 5.0, 7.0, 15.0,
+        ]);
+
+        let (_, counts) = run(&a, &b, &[], 2, 3);
+        #[rustfmt::skip]
+        assert_eq!(counts, [
+            1.0, 1.0, 1.0, 3.0,
+            1.0, 0.0, 2.0, 3.0,
+            2.0, 1.0, 3.0, 6.0,
+        ]);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(run(&[0, 2], &[0, 0], &[], 2, 1).0, -1, "code out of range");
+        assert_eq!(run(&[0, 1], &[0], &[], 2, 1).0, -1, "length mismatch");
+        assert_eq!(run(&[0, 1], &[0, 0], &[1.0], 2, 1).0, -1, "values length");
+        let (status, out) = run(&[], &[], &[], 0, 0);
+        assert_eq!((status, out), (8, vec![0.0]));
+        let mut short = [0f64; 3];
+        let status = unsafe {
+            crosstab(
+                [0u32].as_ptr(),
+                4,
+                [0u32].as_ptr(),
+                4,
+                std::ptr::null(),
+                0,
+                1,
+                1,
+                short.as_mut_ptr(),
+                24,
+            )
+        };
+        assert_eq!(status, -1, "short output");
+    }
+}
@@ -0,0 +1,783 @@
+//! QR code encoding to a module bitmap or RGBA pixels.
+//!
+//! The text is encoded as one segment in the densest mode that covers it
+//! (numeric, alphanumeric or bytes), in the smallest version (1 to 40) that
+//! fits at the requested error correction level. The mask is chosen by the
+//! standard's four penalty rules, so the output matches other conforming
+//! encoders given the same segment.
+
+use crate::ffi;
+
+pub const QR_ECC_LOW: u32 = 0;
+pub const QR_ECC_MEDIUM: u32 = 1;
+pub const QR_ECC_QUARTILE: u32 = 2;
+pub const QR_ECC_HIGH: u32 = 3;
+
+const ALPHANUMERIC: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Error correction codewords per block, by level then version.
+#[rustfmt::skip]
+const ECC_CODEWORDS_PER_BLOCK: [[u8; 41]; 4] = [
+    [0, 7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28, 30, 30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],
+    [0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28],
+    [0, 13, 22, 18, 26, 18, 24, 18, 22, 20, 24, 28, 26, 24, 20, 30, 24, 28, 28, 26, 30, 28, 30, 30, 30, 30, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],
+    [0, 17, 28, 22, 16, 22, 28, 26, 26, 24, 28, 24, 28, 22, 24, 24, 30, 28, 28, 26, 28, 30, 24, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],
+];
+
+/// Error correction blocks, by level then version.
+#[rustfmt::skip]
+const ECC_BLOCKS: [[u8; 41]; 4] = [
+    [0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13, 14, 15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25],
+    [0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23, 25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49],
+    [0, 1, 1, 2, 2, 4, 4, 6, 6, 8, 8, 8, 10, 12, 16, 12, 17, 16, 18, 21, 20, 23, 23, 25, 27, 29, 34, 34, 35, 38, 40, 43, 45, 48, 51, 53, 56, 59, 62, 65, 68],
+    [0, 1, 1, 2, 4, 4, 4, 5, 6, 8, 8, 11, 11, 16, 16, 18, 16, 19, 21, 25, 25, 25, 34, 30, 32, 35, 37, 40, 42, 45, 48, 51, 54, 57, 60, 63, 66, 70, 74, 77, 81],
+];
+
+/// Format information bits for each level (L, M, Q, H).
+const FORMAT_BITS: [u32; 4] = [1, 0, 3, 2];
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Numeric,
+    Alphanumeric,
+    Byte,
+}
+
+impl Mode {
+    fn of(text: &[u8]) -> Mode {
+        if text.iter().all(u8::is_ascii_digit) {
+            Mode::Numeric
+        } else if text.iter().all(|b| ALPHANUMERIC.contains(b)) {
+            Mode::Alphanumeric
+        } else {
+            Mode::Byte
+        }
+    }
+
+    fn indicator(self) -> u32 {
+        match self {
+            Mode::Numeric => 1,
+            Mode::Alphanumeric => 2,
+            Mode::Byte => 4,
+        }
+    }
+
+    /// Width of the character count field.
+    fn count_bits(self, version: usize) -> usize {
+        let band = match version {
+            1..=9 => 0,
+            10..=26 => 1,
+            _ => 2,
+        };
+        match self {
+            Mode::Numeric => [10, 12, 14][band],
+            Mode::Alphanumeric => [9, 11, 13][band],
+            Mode::Byte => [8, 16, 16][band],
+        }
+    }
+
+    fn data_bits(self, len: usize) -> usize {
+        match self {
+            Mode::Numeric => len / 3 * 10 + [0, 4, 7][len % 3],
+            Mode::Alphanumeric => len / 2 * 11 + len % 2 * 6,
+            Mode::Byte => len * 8,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Bits {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl Bits {
+    fn push(&mut self, value: u32, width: usize) {
+        for i in (0..width).rev() {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            self.bytes[self.len / 8] |= ((value >> i & 1) as u8) << (7 - self.len % 8);
+            self.len += 1;
+        }
+    }
+}
+
+/// Modules available for data and error correction in `version`.
+fn raw_modules(version: usize) -> usize {
+    let mut modules = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let align = version / 7 + 2;
+        modules -= (25 * align - 10) * align - 55;
+        if version >= 7 {
+            modules -= 36;
+        }
+    }
+    modules
+}
+
+fn data_codewords(version: usize, ecc: usize) -> usize {
+    raw_modules(version) / 8
+        - ECC_CODEWORDS_PER_BLOCK[ecc][version] as usize * ECC_BLOCKS[ecc][version] as usize
+}
+
+/// Multiplication in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1.
+fn gf_mul(x: u8, y: u8) -> u8 {
+    let mut z: u32 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11D);
+        z ^= ((y >> i) as u32 & 1) * x as u32;
+    }
+    z as u8
+}
+
+/// Reed-Solomon generator polynomial of `degree`, highest term dropped.
+fn rs_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0u8; degree];
+    result[degree - 1] = 1;
+    let mut root = 1u8;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_mul(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_mul(root, 0x02);
+    }
+    result
+}
+
+fn rs_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; divisor.len()];
+    for &b in data {
+        let factor = b ^ result.remove(0);
+        result.push(0);
+        for (r, &d) in result.iter_mut().zip(divisor) {
+            *r ^= gf_mul(d, factor);
+        }
+    }
+    result
+}
+
+/// Encode `text` into data codewords, returning the version and codewords.
+fn encode_data(text: &[u8], ecc: usize) -> Option<(usize, Vec<u8>)> {
+    let mode = Mode::of(text);
+    let (version, capacity) =
+        (1..=40)
+            .map(|v| (v, data_codewords(v, ecc) * 8))
+            .find(|&(v, capacity)| {
+                let count_bits = mode.count_bits(v);
+                text.len() < 1 << count_bits
+                    && 4 + count_bits + mode.data_bits(text.len()) <= capacity
+            })?;
+
+    let mut bits = Bits::default();
+    bits.push(mode.indicator(), 4);
+    bits.push(text.len() as u32, mode.count_bits(version));
+    match mode {
+        Mode::Numeric => {
+            for chunk in text.chunks(3) {
+                let value = chunk.iter().fold(0, |v, &d| v * 10 + (d - b'0') as u32);
+                bits.push(value, chunk.len() * 3 + 1);
+            }
+        }
+        Mode::Alphanumeric => {
+            let index = |b: &u8| ALPHANUMERIC.iter().position(|a| a == b).unwrap() as u32;
+            for pair in text.chunks(2) {
+                match pair.get(1) {
+                    Some(b) => bits.push(index(&pair[0]) * 45 + index(b), 11),
+                    None => bits.push(index(&pair[0]), 6),
+                }
+            }
+        }
+        Mode::Byte => {
+            for &b in text {
+                bits.push(b as u32, 8);
+            }
+        }
+    }
+    // Terminator, byte alignment, then alternating pad bytes.
+    bits.push(0, (capacity - bits.len).min(4));
+    bits.push(0, (8 - bits.len % 8) % 8);
+    let mut data = bits.bytes;
+    for pad in [0xEC, 0x11].into_iter().cycle() {
+        if data.len() * 8 >= capacity {
+            break;
+        }
+        data.push(pad);
+    }
+    Some((version, data))
+}
+
+/// Split the data into blocks, append error correction to each and
+/// interleave them.
+fn add_ecc_and_interleave(data: &[u8], version: usize, ecc: usize) -> Vec<u8> {
+    let blocks = ECC_BLOCKS[ecc][version] as usize;
+    let block_ecc = ECC_CODEWORDS_PER_BLOCK[ecc][version] as usize;
+    let raw = raw_modules(version) / 8;
+    let short_blocks = blocks - raw % blocks;
+    let short_len = raw / blocks;
+
+    let divisor = rs_divisor(block_ecc);
+    let mut split = Vec::with_capacity(blocks);
+    let mut k = 0;
+    for i in 0..blocks {
+        let len = short_len - block_ecc + usize::from(i >= short_blocks);
+        let mut block = data[k..k + len].to_vec();
+        k += len;
+        let ecc = rs_remainder(&block, &divisor);
+        if i < short_blocks {
+            // Placeholder so every block has the same length.
+            block.push(0);
+        }
+        block.extend(ecc);
+        split.push(block);
+    }
+    let mut result = Vec::with_capacity(raw);
+    for i in 0..split[0].len() {
+        for (j, block) in split.iter().enumerate() {
+            if i != short_len - block_ecc || j >= short_blocks {
+                result.push(block[i]);
+            }
+        }
+    }
+    result
+}
+
+struct Symbol {
+    size: usize,
+    dark: Vec<bool>,
+    function: Vec<bool>,
+}
+
+impl Symbol {
+    fn set(&mut self, x: usize, y: usize, dark: bool) {
+        self.dark[y * self.size + x] = dark;
+        self.function[y * self.size + x] = true;
+    }
+
+    fn finder(&mut self, cx: usize, cy: usize) {
+        for dy in -4isize..=4 {
+            for dx in -4isize..=4 {
+                let (x, y) = (cx as isize + dx, cy as isize + dy);
+                if (0..self.size as isize).contains(&x) && (0..self.size as isize).contains(&y) {
+                    let dist = dx.abs().max(dy.abs());
+                    self.set(x as usize, y as usize, dist != 2 && dist != 4);
+                }
+            }
+        }
+    }
+
+    fn alignment(&mut self, cx: usize, cy: usize) {
+        for dy in -2isize..=2 {
+            for dx in -2isize..=2 {
+                let dark = dx.abs().max(dy.abs()) != 1;
+                self.set(
+                    (cx as isize + dx) as usize,
+                    (cy as isize + dy) as usize,
+                    dark,
+                );
+            }
+        }
+    }
+
+    fn format(&mut self, ecc: usize, mask: usize) {
+        let data = FORMAT_BITS[ecc] << 3 | mask as u32;
+        let mut rem = data;
+        for _ in 0..10 {
+            rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+        }
+        let bits = (data << 10 | rem) ^ 0x5412;
+        let bit = |i: usize| bits >> i & 1 != 0;
+        let size = self.size;
+        for i in 0..6 {
+            self.set(8, i, bit(i));
+        }
+        self.set(8, 7, bit(6));
+        self.set(8, 8, bit(7));
+        self.set(7, 8, bit(8));
+        for i in 9..15 {
+            self.set(14 - i, 8, bit(i));
+        }
+        for i in 0..8 {
+            self.set(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set(8, size - 15 + i, bit(i));
+        }
+        self.set(8, size - 8, true);
+    }
+
+    fn version(&mut self, version: usize) {
+        if version < 7 {
+            return;
+        }
+        let mut rem = version as u32;
+        for _ in 0..12 {
+            rem = (rem << 1) ^ ((rem >> 11) * 0x1F25);
+        }
+        let bits = (version as u32) << 12 | rem;
+        for i in 0..18 {
+            let dark = bits >> i & 1 != 0;
+            let (a, b) = (self.size - 11 + i % 3, i / 3);
+            self.set(a, b, dark);
+            self.set(b, a, dark);
+        }
+    }
+
+    fn new(version: usize, ecc: usize) -> Symbol {
+        let size = version * 4 + 17;
+        let mut symbol = Symbol {
+            size,
+            dark: vec![false; size * size],
+            function: vec![false; size * size],
+        };
+        for i in 0..size {
+            symbol.set(6, i, i % 2 == 0);
+            symbol.set(i, 6, i % 2 == 0);
+        }
+        symbol.finder(3, 3);
+        symbol.finder(size - 4, 3);
+        symbol.finder(3, size - 4);
+        let positions = alignment_positions(version);
+        let last = positions.len().saturating_sub(1);
+        for (i, &x) in positions.iter().enumerate() {
+            for (j, &y) in positions.iter().enumerate() {
+                // The three corners taken by finder patterns.
+                let corner = [i, j].iter().all(|&k| k == 0 || k == last) && (i, j) != (last, last);
+                if !corner {
+                    symbol.alignment(x, y);
+                }
+            }
+        }
+        // Reserve the format areas; the real bits go in after masking.
+        symbol.format(ecc, 0);
+        symbol.version(version);
+        symbol
+    }
+
+    /// Place the codewords in the zigzag order, right to left in column
+    /// pairs, skipping function modules.
+    fn codewords(&mut self, data: &[u8]) {
+        let size = self.size;
+        let mut i = 0;
+        let mut right = size - 1;
+        loop {
+            if right == 6 {
+                right = 5;
+            }
+            for vert in 0..size {
+                for j in 0..2 {
+                    let x = right - j;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward { size - 1 - vert } else { vert };
+                    if !self.function[y * size + x] && i < data.len() * 8 {
+                        self.dark[y * size + x] = data[i >> 3] >> (7 - (i & 7)) & 1 != 0;
+                        i += 1;
+                    }
+                }
+            }
+            if right < 2 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: usize) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let k = y * self.size + x;
+                self.dark[k] ^= invert && !self.function[k];
+            }
+        }
+    }
+
+    fn penalty(&self) -> usize {
+        let size = self.size;
+        let at = |x: usize, y: usize| self.dark[y * size + x];
+        let mut score = 0;
+        // Runs of five or more and finder-like patterns, along rows then
+        // columns.
+        for transpose in [false, true] {
+            for a in 0..size {
+                let mut finder = FinderRuns::new(size);
+                let (mut color, mut run) = (false, 0);
+                for b in 0..size {
+                    let dark = if transpose { at(a, b) } else { at(b, a) };
+                    if dark == color {
+                        run += 1;
+                        if run == 5 {
+                            score += 3;
+                        } else if run > 5 {
+                            score += 1;
+                        }
+                    } else {
+                        finder.add(run);
+                        if !color {
+                            score += finder.count() * 40;
+                        }
+                        color = dark;
+                        run = 1;
+                    }
+                }
+                score += finder.terminate(color, run) * 40;
+            }
+        }
+        // 2x2 blocks of one color.
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let c = at(x, y);
+                if c == at(x + 1, y) && c == at(x, y + 1) && c == at(x + 1, y + 1) {
+                    score += 3;
+                }
+            }
+        }
+        // Balance of dark and light modules.
+        let total = size * size;
+        let dark = self.dark.iter().filter(|&&d| d).count();
+        let k = (dark * 20).abs_diff(total * 10).div_ceil(total) - 1;
+        score + k * 10
+    }
+}
+
+/// Run lengths for spotting 1:1:3:1:1 finder-like patterns with light
+/// space on one side.
+struct FinderRuns {
+    size: usize,
+    history: [usize; 7],
+}
+
+impl FinderRuns {
+    fn new(size: usize) -> Self {
+        FinderRuns {
+            size,
+            history: [0; 7],
+        }
+    }
+
+    fn add(&mut self, mut run: usize) {
+        if self.history[0] == 0 {
+            // The light border before the first run.
+            run += self.size;
+        }
+        self.history.copy_within(0..6, 1);
+        self.history[0] = run;
+    }
+
+    fn count(&self) -> usize {
+        let h = &self.history;
+        let n = h[1];
+        let core = n > 0 && h[2] == n && h[3] == n * 3 && h[4] == n && h[5] == n;
+        usize::from(core && h[0] >= n * 4 && h[6] >= n)
+            + usize::from(core && h[6] >= n * 4 && h[0] >= n)
+    }
+
+    fn terminate(&mut self, color: bool, mut run: usize) -> usize {
+        if color {
+            self.add(run);
+            run = 0;
+        }
+        self.add(run + self.size);
+        self.count()
+    }
+}
+
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+    let count = version / 7 + 2;
+    let step = if version == 32 {
+        26
+    } else {
+        (version * 4 + count * 2 + 1) / (count * 2 - 2) * 2
+    };
+    let size = version * 4 + 17;
+    let mut positions: Vec<usize> = (0..count - 1).map(|i| size - 7 - i * step).collect();
+    positions.push(6);
+    positions.reverse();
+    positions
+}
+
+/// Table index for a `QR_ECC_*` level.
+fn level(ecc_level: u32) -> Option<usize> {
+    matches!(
+        ecc_level,
+        QR_ECC_LOW | QR_ECC_MEDIUM | QR_ECC_QUARTILE | QR_ECC_HIGH
+    )
+    .then_some(ecc_level as usize)
+}
+
+/// Build the masked symbol for `text`.
+fn symbol(text: &[u8], ecc: usize) -> Option<Symbol> {
+    let (version, data) = encode_data(text, ecc)?;
+    let codewords = add_ecc_and_interleave(&data, version, ecc);
+    let mut symbol = Symbol::new(version, ecc);
+    symbol.codewords(&codewords);
+    let mut best = (usize::MAX, 0);
+    for mask in 0..8 {
+        symbol.apply_mask(mask);
+        symbol.format(ecc, mask);
+        best = best.min((symbol.penalty(), mask));
+        symbol.apply_mask(mask);
+    }
+    symbol.apply_mask(best.1);
+    symbol.format(ecc, best.1);
+    Some(symbol)
+}
+
+/// Encode `text` as a QR code at error correction level `ecc_level`
+/// (`QR_ECC_LOW` to `QR_ECC_HIGH`, recovering 7%, 15%, 25% or 30% damage),
+/// writing one byte per module, row by row: `1` dark, `0` light. The side
+/// is `sqrt` of the bytes written, `17 + 4 * version`; add a 4-module light
+/// quiet zone around it when drawing.
+///
+/// With `out_len == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for an
+/// unknown level, text too long for version 40 (2953 bytes at `QR_ECC_LOW`),
+/// or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn qr_encode(
+    text_ptr: *const u8,
+    text_len: usize,
+    ecc_level: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let Some(symbol) = level(ecc_level).and_then(|ecc| symbol(ffi::slice(text_ptr, text_len), ecc))
+    else {
+        return -1;
+    };
+    let needed = symbol.dark.len();
+    if out_len == 0 {
+        return needed as isize;
+    }
+    if out_len < needed {
+        return -1;
+    }
+    if ffi::aliased(text_ptr, text_len, out_ptr, needed) {
+        return ffi::ALIAS_ERROR;
+    }
+    for (o, &dark) in ffi::slice_mut(out_ptr, needed).iter_mut().zip(&symbol.dark) {
+        *o = dark as u8;
+    }
+    needed as isize
+}
+
+/// Encode `text` as `qr_encode` does and render it as opaque black-on-white
+/// RGBA pixels, `scale` pixels per module with a light quiet zone of
+/// `border` modules, ready for `ImageData`. The image is `(side + 2 *
+/// border) * scale` pixels square.
+///
+/// With `out_len == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` under
+/// the conditions of `qr_encode`, for a zero `scale`, or for an image over
+/// 2^31 bytes.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn qr_encode_rgba(
+    text_ptr: *const u8,
+    text_len: usize,
+    ecc_level: u32,
+    scale: u32,
+    border: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    if scale == 0 {
+        return -1;
+    }
+    let Some(symbol) = level(ecc_level).and_then(|ecc| symbol(ffi::slice(text_ptr, text_len), ecc))
+    else {
+        return -1;
+    };
+    let (scale, border) = (scale as usize, border as usize);
+    let Some(side) = border
+        .checked_mul(2)
+        .and_then(|b| b.checked_add(symbol.size))
+        .and_then(|s| s.checked_mul(scale))
+    else {
+        return -1;
+    };
+    let Some(needed) = side
+        .checked_mul(side)
+        .and_then(|n| n.checked_mul(4))
+        .filter(|&n| n <= 1 << 31)
+    else {
+        return -1;
+    };
+    if out_len == 0 {
+        return needed as isize;
+    }
+    if out_len < needed {
+        return -1;
+    }
+    if ffi::aliased(text_ptr, text_len, out_ptr, needed) {
+        return ffi::ALIAS_ERROR;
+    }
+    let out = ffi::slice_mut(out_ptr, needed);
+    for (py, row) in out.chunks_exact_mut(side * 4).enumerate() {
+        let y = (py / scale).wrapping_sub(border);
+        for (px, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let x = (px / scale).wrapping_sub(border);
+            let dark = x < symbol.size && y < symbol.size && symbol.dark[y * symbol.size + x];
+            let v = if dark { 0 } else { 255 };
+            pixel.copy_from_slice(&[v, v, v, 255]);
+        }
+    }
+    needed as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(text: &[u8], ecc: u32) -> Option<Vec<u8>> {
+        let call = |out: &mut [u8]| unsafe {
+            qr_encode(text.as_ptr(), text.len(), ecc, out.as_mut_ptr(), out.len())
+        };
+        let needed = usize::try_from(call(&mut [])).ok()?;
+        let mut out = vec![0u8; needed];
+        assert_eq!(call(&mut out), needed as isize);
+        Some(out)
+    }
+
+    #[test]
+    fn encodes_hello_world_codewords() {
+        // The worked example from the standard's tutorials: 1-Q,
+        // alphanumeric.
+        let (version, data) = encode_data(b"HELLO WORLD", 2).unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(
+            data,
+            [32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236]
+        );
+        let all = add_ecc_and_interleave(&data, 1, 2);
+        assert_eq!(
+            &all[13..],
+            [168, 72, 22, 82, 217, 54, 156, 0, 46, 15, 180, 122, 16]
+        );
+    }
+
+    #[test]
+    fn byte_capacities_match_the_standard() {
+        // Largest byte-mode text per version and level (L, M, Q, H).
+        let table: [(usize, [usize; 4]); 6] = [
+            (1, [17, 14, 11, 7]),
+            (2, [32, 26, 20, 14]),
+            (5, [106, 84, 60, 44]),
+            (10, [271, 213, 151, 119]),
+            (27, [1465, 1125, 805, 625]),
+            (40, [2953, 2331, 1663, 1273]),
+        ];
+        for (version, capacities) in table {
+            for (ecc, &capacity) in capacities.iter().enumerate() {
+                let fits = |len| encode_data(&vec![b'a'; len], ecc).map(|(v, _)| v);
+                assert!(fits(capacity).unwrap() <= version, "{version} {ecc}");
+                assert!(
+                    fits(capacity + 1).is_none_or(|v| v > version),
+                    "{version} {ecc}"
+                );
+            }
+        }
+        assert!(encode(&vec![b'a'; 2954], QR_ECC_LOW).is_none());
+        assert_eq!(encode_data(&[b'7'; 7089], 0).unwrap().0, 40, "numeric");
+    }
+
+    #[test]
+    fn draws_function_patterns() {
+        let bitmap = encode(b"https://example.com/a/b?c=d", QR_ECC_MEDIUM).unwrap();
+        let size = (bitmap.len() as f64).sqrt() as usize;
+        assert_eq!(size * size, bitmap.len());
+        assert_eq!(size, 29, "version 3");
+        let at = |x: usize, y: usize| bitmap[y * size + x] == 1;
+        // Finder patterns: 7x7 dark ring, light ring, 3x3 dark core.
+        for (cx, cy) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            for d in 0..7 {
+                let ring = |dx: usize, dy: usize| at(cx + dx - 3, cy + dy - 3);
+                assert!(ring(d, 0) && ring(d, 6) && ring(0, d) && ring(6, d));
+            }
+            assert!(!at(cx - 2, cy - 2) && at(cx, cy) && at(cx + 1, cy - 1));
+        }
+        // Timing patterns and the dark module.
+        assert!((8..size - 8).all(|i| at(i, 6) == (i % 2 == 0) && at(6, i) == (i % 2 == 0)));
+        assert!(at(8, size - 8));
+        // Both copies of the format bits agree and decode to level M.
+        let first: Vec<bool> = (0..6)
+            .map(|i| at(8, i))
+            .chain([at(8, 7), at(8, 8), at(7, 8)])
+            .chain((9..15).map(|i| at(14 - i, 8)))
+            .collect();
+        let second: Vec<bool> = (0..8)
+            .map(|i| at(size - 1 - i, 8))
+            .chain((8..15).map(|i| at(8, size - 15 + i)))
+            .collect();
+        assert_eq!(first, second);
+        let bits = first
+            .iter()
+            .enumerate()
+            .fold(0u32, |b, (i, &d)| b | (d as u32) << i)
+            ^ 0x5412;
+        assert_eq!(bits >> 13, FORMAT_BITS[1]);
+        assert_eq!(
+            encode(b"https://example.com/a/b?c=d", QR_ECC_MEDIUM).unwrap(),
+            bitmap
+        );
+    }
+
+    #[test]
+    fn renders_rgba_with_a_quiet_zone() {
+        let text = b"12345";
+        let call = |out: &mut [u8]| unsafe {
+            qr_encode_rgba(
+                text.as_ptr(),
+                text.len(),
+                QR_ECC_HIGH,
+                3,
+                4,
+                out.as_mut_ptr(),
+                out.len(),
+            )
+        };
+        let needed = call(&mut []);
+        let side = (21 + 8) * 3;
+        assert_eq!(needed, side as isize * side as isize * 4);
+        let mut rgba = vec![7u8; needed as usize];
+        assert_eq!(call(&mut rgba), needed);
+        let bitmap = encode(text, QR_ECC_HIGH).unwrap();
+        for (i, px) in rgba.chunks(4).enumerate() {
+            let (x, y) = ((i % side) / 3, (i / side) / 3);
+            let dark =
+                (4..25).contains(&x) && (4..25).contains(&y) && bitmap[(y - 4) * 21 + x - 4] == 1;
+            assert_eq!(px, if dark { [0, 0, 0, 255] } else { [255; 4] });
+        }
+        assert_eq!(call(&mut rgba[..100]), -1, "short output");
+    }
+
+    #[test]
+    fn version_information_for_large_symbols() {
+        let bitmap = encode(&[b'x'; 190], QR_ECC_LOW).unwrap();
+        let size = (bitmap.len() as f64).sqrt() as usize;
+        let version = (size - 17) / 4;
+        assert_eq!(version, 8);
+        // Version block bottom-left: bit i at (i / 3, size - 11 + i % 3).
+        let bits = (0..18).fold(0u32, |b, i| {
+            b | (bitmap[(size - 11 + i % 3) * size + i / 3] as u32) << i
+        });
+        assert_eq!(bits >> 12, 8);
+        assert_eq!(bits, 0x085BC, "standard version 8 block");
+    }
+}
@@ -0,0 +1,753 @@
+//! DFA-only pattern matching over string batches (`regex` feature).
+//!
+//! Patterns compile to a Thompson NFA, which is turned into a DFA lazily,
+//! one state per distinct set of NFA states actually reached. Matching is a
+//! single table lookup per byte with no backtracking, so a batch runs in time
+//! linear in its length whatever the pattern.
+//!
+//! The syntax is a byte-oriented subset of the usual one: literals, `.`
+//! (any byte but `\n`), classes such as `[a-z_]` and `[^0-9]`, the escapes
+//! `\d \w \s` (and their negations), `\n \t \r` and escaped metacharacters,
+//! groups `(...)` / `(?:...)`, alternation, and the quantifiers `* + ? {m}
+//! {m,} {m,n}` (a trailing lazy `?` is accepted and has no effect on whether a
+//! value matches). `^` and `$` are only allowed at the very start and end of
+//! the pattern, and then apply to all of it (so top-level alternation must be
+//! grouped, as in `^(?:a|b)$`). Non-ASCII literals match their UTF-8 bytes;
+//! classes must be ASCII.
+//!
+//! Patterns are untrusted input, so compiling is bounded too: syntax nested
+//! more than 64 levels deep (groups and stacked quantifiers), counted
+//! repetitions that expand past 100 000 nodes, and NFAs past 10 000 states
+//! are refused with `-1`.
+//!
+//! The engine is written here rather than embedding `regex-automata`:
+//! compiling patterns with that crate pulls in `regex-syntax` and its Unicode
+//! tables, while this subset adds about 20 KB to the module. It keeps the
+//! same guarantee, a DFA with no backtracking.
+
+use std::collections::HashMap;
+
+use super::spans;
+use crate::{ffi, handles};
+
+/// Match ASCII letters regardless of case.
+pub const REGEX_CASE_INSENSITIVE: u32 = 1;
+
+/// Upper bound on NFA size after counted repetitions are expanded.
+const MAX_NFA_STATES: usize = 10_000;
+/// Upper bound on the nodes compiled once counted repetitions are expanded.
+/// Checked before expanding, so repeats of groups that compile to nothing,
+/// as in `(){100000000}`, are refused too.
+const MAX_EXPANSION: u64 = 100_000;
+/// Deepest syntax tree a pattern may parse to. Parsing, compiling and
+/// dropping the tree recurse once per level, on wasm's 1 MB stack.
+const MAX_DEPTH: u32 = 64;
+/// Cached DFA states before the cache is flushed and rebuilt on demand.
+const MAX_DFA_STATES: usize = 4_096;
+const UNKNOWN: u32 = u32::MAX;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct ByteSet([u64; 4]);
+
+impl ByteSet {
+    const EMPTY: Self = ByteSet([0; 4]);
+
+    fn insert(&mut self, b: u8) {
+        self.0[b as usize / 64] |= 1 << (b % 64);
+    }
+
+    fn insert_range(&mut self, lo: u8, hi: u8) {
+        for b in lo..=hi {
+            self.insert(b);
+        }
+    }
+
+    fn contains(&self, b: u8) -> bool {
+        self.0[b as usize / 64] & (1 << (b % 64)) != 0
+    }
+
+    fn negate(mut self) -> Self {
+        for w in &mut self.0 {
+            *w = !*w;
+        }
+        self
+    }
+
+    fn union(mut self, other: Self) -> Self {
+        for (w, o) in self.0.iter_mut().zip(other.0) {
+            *w |= o;
+        }
+        self
+    }
+
+    fn fold_case(mut self) -> Self {
+        for b in b'a'..=b'z' {
+            if self.contains(b) || self.contains(b.to_ascii_uppercase()) {
+                self.insert(b);
+                self.insert(b.to_ascii_uppercase());
+            }
+        }
+        self
+    }
+}
+
+enum Node {
+    Set(ByteSet),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+    },
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    i: usize,
+    fold: bool,
+    /// Groups open at `i`.
+    depth: u32,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.i).copied()
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        let hit = self.peek() == Some(b);
+        self.i += hit as usize;
+        hit
+    }
+
+    fn set(&self, set: ByteSet) -> Node {
+        Node::Set(if self.fold { set.fold_case() } else { set })
+    }
+
+    /// Parse alternatives, returning the node and its height, at most
+    /// `MAX_DEPTH`. Every parse function does the same.
+    fn alt(&mut self) -> Option<(Node, u32)> {
+        let (first, mut height) = self.concat()?;
+        let mut branches = vec![first];
+        while self.eat(b'|') {
+            let (branch, h) = self.concat()?;
+            branches.push(branch);
+            height = height.max(h);
+        }
+        Some(if branches.len() == 1 {
+            (branches.pop().unwrap(), height)
+        } else {
+            (Node::Alt(branches), nested(height)?)
+        })
+    }
+
+    fn concat(&mut self) -> Option<(Node, u32)> {
+        let (mut items, mut height) = (Vec::new(), 0);
+        while !matches!(self.peek(), None | Some(b'|' | b')')) {
+            let atom = self.atom()?;
+            let (item, h) = self.quantifiers(atom)?;
+            items.push(item);
+            height = height.max(h);
+        }
+        Some((Node::Concat(items), nested(height)?))
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let start = self.i;
+        let mut n = 0u32;
+        while let Some(b @ b'0'..=b'9') = self.peek() {
+            n = n.checked_mul(10)?.checked_add((b - b'0') as u32)?;
+            self.i += 1;
+        }
+        (self.i > start).then_some(n)
+    }
+
+    fn quantifiers(&mut self, (mut node, mut height): (Node, u32)) -> Option<(Node, u32)> {
+        loop {
+            let (min, max) = match self.peek() {
+                Some(b'*') => (0, None),
+                Some(b'+') => (1, None),
+                Some(b'?') => (0, Some(1)),
+                Some(b'{') => {
+                    self.i += 1;
+                    let min = self.number()?;
+                    let max = if self.eat(b',') {
+                        if self.peek() == Some(b'}') {
+                            None
+                        } else {
+                            Some(self.number()?)
+                        }
+                    } else {
+                        Some(min)
+                    };
+                    if self.peek() != Some(b'}') || max.is_some_and(|max| max < min) {
+                        return None;
+                    }
+                    (min, max)
+                }
+                _ => return Some((node, height)),
+            };
+            self.i += 1;
+            self.eat(b'?');
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+            };
+            height = nested(height)?;
+        }
+    }
+
+    fn escape(&mut self) -> Option<ByteSet> {
+        let b = self.peek()?;
+        self.i += 1;
+        let mut set = ByteSet::EMPTY;
+        match b {
+            b'd' | b'D' => set.insert_range(b'0', b'9'),
+            b'w' | b'W' => {
+                set.insert_range(b'0', b'9');
+                set.insert_range(b'a', b'z');
+                set.insert_range(b'A', b'Z');
+                set.insert(b'_');
+            }
+            b's' | b'S' => b" \t\n\r\x0b\x0c".iter().for_each(|&b| set.insert(b)),
+            b'n' => set.insert(b'\n'),
+            b't' => set.insert(b'\t'),
+            b'r' => set.insert(b'\r'),
+            b if b.is_ascii_punctuation() => set.insert(b),
+            _ => return None,
+        }
+        Some(if b.is_ascii_uppercase() {
+            set.negate()
+        } else {
+            set
+        })
+    }
+
+    fn class(&mut self) -> Option<ByteSet> {
+        let negated = self.eat(b'^');
+        let mut set = ByteSet::EMPTY;
+        let mut first = true;
+        loop {
+            let b = self.peek()?;
+            if b == b']' && !first {
+                self.i += 1;
+                break;
+            }
+            first = false;
+            self.i += 1;
+            if !b.is_ascii() {
+                return None;
+            }
+            if b == b'\\' {
+                set = set.union(self.escape()?);
+                continue;
+            }
+            if self.peek() == Some(b'-') && self.s.get(self.i + 1).is_some_and(|&n| n != b']') {
+                let hi = self.s[self.i + 1];
+                if !hi.is_ascii() || hi < b || hi == b'\\' {
+                    return None;
+                }
+                set.insert_range(b, hi);
+                self.i += 2;
+            } else {
+                set.insert(b);
+            }
+        }
+        if self.fold {
+            set = set.fold_case();
+        }
+        Some(if negated { set.negate() } else { set })
+    }
+
+    fn atom(&mut self) -> Option<(Node, u32)> {
+        let b = self.peek()?;
+        self.i += 1;
+        let node = match b {
+            b'(' => {
+                if self.eat(b'?') && !self.eat(b':') {
+                    return None;
+                }
+                // Only groups recurse; stop before the stack runs out.
+                self.depth = nested(self.depth)?;
+                let group = self.alt()?;
+                self.depth -= 1;
+                return self.eat(b')').then_some(group);
+            }
+            b'[' => Node::Set(self.class()?),
+            b'.' => {
+                let mut newline = ByteSet::EMPTY;
+                newline.insert(b'\n');
+                Node::Set(newline.negate())
+            }
+            b'\\' => {
+                let set = self.escape()?;
+                self.set(set)
+            }
+            b'*' | b'+' | b'?' | b'{' | b'^' | b'$' => return None,
+            b => {
+                let mut set = ByteSet::EMPTY;
+                set.insert(b);
+                self.set(set)
+            }
+        };
+        Some((node, 1))
+    }
+}
+
+/// Height of a node over children of height `height`, if within `MAX_DEPTH`.
+fn nested(height: u32) -> Option<u32> {
+    (height < MAX_DEPTH).then_some(height + 1)
+}
+
+/// Nodes `Nfa::compile` visits for `node`, counting each expansion of a
+/// counted repetition, saturating past `MAX_EXPANSION`.
+fn expansion(node: &Node) -> u64 {
+    let visits = match node {
+        Node::Set(_) => 0,
+        Node::Concat(items) | Node::Alt(items) => items.iter().map(expansion).sum(),
+        Node::Repeat { node, min, max } => {
+            let copies = max.map_or(*min as u64 + 1, |max| max as u64);
+            copies.saturating_mul(expansion(node))
+        }
+    };
+    visits.saturating_add(1).min(MAX_EXPANSION + 1)
+}
+
+enum Inst {
+    Byte { set: usize, next: usize },
+    Split(usize, usize),
+    Match,
+}
+
+struct Nfa {
+    insts: Vec<Inst>,
+    sets: Vec<ByteSet>,
+}
+
+impl Nfa {
+    fn push(&mut self, inst: Inst) -> Option<usize> {
+        (self.insts.len() < MAX_NFA_STATES).then(|| {
+            self.insts.push(inst);
+            self.insts.len() - 1
+        })
+    }
+
+    /// Compile `node` so that it continues at `next`; returns its entry.
+    fn compile(&mut self, node: &Node, next: usize) -> Option<usize> {
+        match node {
+            Node::Set(set) => {
+                let index = match self.sets.iter().position(|s| s == set) {
+                    Some(index) => index,
+                    None => {
+                        self.sets.push(*set);
+                        self.sets.len() - 1
+                    }
+                };
+                self.push(Inst::Byte { set: index, next })
+            }
+            Node::Concat(items) => items
+                .iter()
+                .rev()
+                .try_fold(next, |next, item| self.compile(item, next)),
+            Node::Alt(branches) => {
+                let mut entry = self.compile(branches.last()?, next)?;
+                for branch in branches[..branches.len() - 1].iter().rev() {
+                    let start = self.compile(branch, next)?;
+                    entry = self.push(Inst::Split(start, entry))?;
+                }
+                Some(entry)
+            }
+            Node::Repeat { node, min, max } => {
+                let mut entry = match max {
+                    None => {
+                        let split = self.push(Inst::Split(0, next))?;
+                        let body = self.compile(node, split)?;
+                        self.insts[split] = Inst::Split(body, next);
+                        split
+                    }
+                    Some(max) => {
+                        let mut entry = next;
+                        for _ in *min..*max {
+                            let body = self.compile(node, entry)?;
+                            entry = self.push(Inst::Split(body, next))?;
+                        }
+                        entry
+                    }
+                };
+                for _ in 0..*min {
+                    entry = self.compile(node, entry)?;
+                }
+                Some(entry)
+            }
+        }
+    }
+}
+
+/// A compiled pattern plus its lazily built DFA.
+pub(crate) struct Regex {
+    nfa: Nfa,
+    start: usize,
+    anchored_start: bool,
+    anchored_end: bool,
+    /// Byte -> equivalence class; bytes in the same class behave identically
+    /// in every set, so transitions are stored per class.
+    classes: [u8; 256],
+    class_count: usize,
+    /// NFA state set (Byte and Match insts, sorted) of each DFA state. State
+    /// 0 is always the start state.
+    states: Vec<Vec<u32>>,
+    ids: HashMap<Vec<u32>, u32>,
+    trans: Vec<u32>,
+    start_set: Vec<u32>,
+}
+
+impl Regex {
+    pub fn new(pattern: &[u8], flags: u32) -> Option<Regex> {
+        let anchored_start = pattern.first() == Some(&b'^');
+        let anchored_end = pattern.last() == Some(&b'$')
+            && pattern.len() > anchored_start as usize
+            // `\$` is a literal dollar, `\\$` an anchor.
+            && pattern[..pattern.len() - 1]
+                .iter()
+                .rev()
+                .take_while(|&&b| b == b'\\')
+                .count()
+                % 2
+                == 0;
+        let body = &pattern[anchored_start as usize..pattern.len() - anchored_end as usize];
+        let mut parser = Parser {
+            s: body,
+            i: 0,
+            fold: flags & REGEX_CASE_INSENSITIVE != 0,
+            depth: 0,
+        };
+        let (node, _) = parser.alt()?;
+        if parser.i != body.len() || expansion(&node) > MAX_EXPANSION {
+            return None;
+        }
+        // `^a|b$` anchors each branch separately elsewhere; ask for a group.
+        if (anchored_start || anchored_end) && matches!(node, Node::Alt(_)) {
+            return None;
+        }
+
+        let mut nfa = Nfa {
+            insts: vec![Inst::Match],
+            sets: Vec::new(),
+        };
+        let start = nfa.compile(&node, 0)?;
+
+        let mut classes = [0u8; 256];
+        let mut signatures: Vec<Vec<bool>> = Vec::new();
+        for b in 0..=255u8 {
+            let signature: Vec<bool> = nfa.sets.iter().map(|s| s.contains(b)).collect();
+            classes[b as usize] = match signatures.iter().position(|s| *s == signature) {
+                Some(class) => class as u8,
+                None => {
+                    signatures.push(signature);
+                    (signatures.len() - 1) as u8
+                }
+            };
+        }
+
+        let mut regex = Regex {
+            nfa,
+            start,
+            anchored_start,
+            anchored_end,
+            classes,
+            class_count: signatures.len(),
+            states: Vec::new(),
+            ids: HashMap::new(),
+            trans: Vec::new(),
+            start_set: Vec::new(),
+        };
+        let mut start_set = Vec::new();
+        regex.closure(start, &mut start_set);
+        start_set.sort_unstable();
+        regex.start_set = start_set;
+        regex.flush();
+        Some(regex)
+    }
+
+    /// Add `inst` and everything reachable from it through splits.
+    fn closure(&self, inst: usize, set: &mut Vec<u32>) {
+        let mut stack = vec![inst];
+        while let Some(i) = stack.pop() {
+            if set.contains(&(i as u32)) {
+                continue;
+            }
+            set.push(i as u32);
+            if let Inst::Split(a, b) = self.nfa.insts[i] {
+                stack.push(b);
+                stack.push(a);
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        self.states.clear();
+        self.ids.clear();
+        self.trans.clear();
+        self.add_state(self.start_set.clone());
+    }
+
+    fn add_state(&mut self, set: Vec<u32>) -> u32 {
+        if let Some(&id) = self.ids.get(&set) {
+            return id;
+        }
+        let id = self.states.len() as u32;
+        self.ids.insert(set.clone(), id);
+        self.states.push(set);
+        self.trans
+            .extend(std::iter::repeat_n(UNKNOWN, self.class_count));
+        id
+    }
+
+    fn is_match(&self, state: u32) -> bool {
+        // Inst 0 is the only Match.
+        self.states[state as usize].first() == Some(&0)
+    }
+
+    fn step(&mut self, state: u32, byte: u8) -> u32 {
+        let class = self.classes[byte as usize] as usize;
+        let slot = state as usize * self.class_count + class;
+        let cached = self.trans[slot];
+        if cached != UNKNOWN {
+            return cached;
+        }
+
+        let mut next = Vec::new();
+        for &i in &self.states[state as usize] {
+            if let Inst::Byte { set, next: to } = self.nfa.insts[i as usize] {
+                if self.nfa.sets[set].contains(byte) {
+                    self.closure(to, &mut next);
+                }
+            }
+        }
+        if !self.anchored_start {
+            // Unanchored search: a match may begin at every position.
+            self.closure(self.start, &mut next);
+        }
+        next.sort_unstable();
+
+        if self.states.len() >= MAX_DFA_STATES && !self.ids.contains_key(&next) {
+            self.flush();
+            return self.add_state(next);
+        }
+        let id = self.add_state(next);
+        self.trans[slot] = id;
+        id
+    }
+
+    pub fn is_match_in(&mut self, value: &[u8]) -> bool {
+        let mut state = 0;
+        for &b in value {
+            if !self.anchored_end && self.is_match(state) {
+                return true;
+            }
+            state = self.step(state, b);
+            if self.anchored_start && self.states[state as usize].is_empty() {
+                return false;
+            }
+        }
+        self.is_match(state)
+    }
+}
+
+/// Compile `pattern` with `flags` (`REGEX_CASE_INSENSITIVE`). Returns a
+/// handle for `regex_match_batch`, released with `handle_drop`, or `-1` for
+/// an unsupported or malformed pattern.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn regex_compile(
+    pattern_ptr: *const u8,
+    pattern_len: usize,
+    flags: u32,
+) -> isize {
+    match Regex::new(ffi::slice(pattern_ptr, pattern_len), flags) {
+        Some(regex) => handles::insert(regex) as isize,
+        None => -1,
+    }
+}
+
+/// Test every value of a string batch against the pattern behind `handle`,
+/// setting bit `i % 8` of byte `i / 8` at `out_ptr` when value `i` contains a
+/// match. Returns bytes written (`ceil(rows / 8)`), or `-1` for an unknown
+/// handle, a bad span or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn regex_match_batch(
+    handle: u32,
+    text_ptr: *const u8,
+    text_len: usize,
+    offsets_ptr: *const u32,
+    offsets_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let rows = offsets_len.saturating_sub(1);
+    let bytes = rows.div_ceil(8);
+    if out_len < bytes {
+        return -1;
+    }
+    let text = ffi::slice(text_ptr, text_len);
+    let offsets = ffi::slice(offsets_ptr, offsets_len);
+    let out = ffi::slice_mut(out_ptr, bytes);
+    out.fill(0);
+
+    let result = handles::with(handle, |regex: &mut Regex| {
+        for (i, value) in spans(text, offsets).enumerate() {
+            if regex.is_match_in(value?) {
+                out[i / 8] |= 1 << (i % 8);
+            }
+        }
+        Some(())
+    });
+    match result {
+        Some(Some(())) => bytes as isize,
+        _ => -1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, flags: u32, value: &str) -> bool {
+        Regex::new(pattern.as_bytes(), flags)
+            .unwrap_or_else(|| panic!("pattern {pattern:?} should compile"))
+            .is_match_in(value.as_bytes())
+    }
+
+    #[test]
+    fn matches_like_a_search() {
+        let cases = [
+            ("abc", "xxabcxx", true),
+            ("abc", "ab", false),
+            ("^abc", "xabc", false),
+            ("^abc", "abcx", true),
+            ("abc$", "xabc", true),
+            ("abc$", "abcx", false),
+            ("^$", "", true),
+            ("^(?:a|b)$", "b", true),
+            ("colou?r", "color", true),
+            ("a.c", "a\nc", false),
+            ("[a-c]+x", "zzbcax", true),
+            ("[^0-9]", "123", false),
+            (r"\d{3}-\d{4}", "call 555-1234", true),
+            (r"\d{3}-\d{4}", "call 55-1234", false),
+            ("^(ab){2,3}$", "abab", true),
+            ("^(ab){2,3}$", "abababab", false),
+            ("^(?:a|b)*c$", "abbac", true),
+            (r"ERROR\s+\w+", "2024 ERROR  disk", true),
+            (r"\[warn\]", "[warn] x", true),
+            ("^(a*)*$", "aaaa", true),
+            ("x{2,}?", "xx", true),
+            ("é", "café", true),
+            (r"a\$", "a$b", true),
+            (r"a\\$", "a\\b", false),
+        ];
+        for (pattern, value, expected) in cases {
+            assert_eq!(
+                matches(pattern, 0, value),
+                expected,
+                "{pattern:?} on {value:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn case_insensitive_flag() {
+        assert!(matches("error", REGEX_CASE_INSENSITIVE, "An ERROR"));
+        assert!(matches("[a-c]+", REGEX_CASE_INSENSITIVE, "ABC"));
+        assert!(!matches("error", 0, "An ERROR"));
+    }
+
+    #[test]
+    fn rejects_unsupported_patterns() {
+        for pattern in [
+            "(", "a)", "*a", "a{3,1}", "[z-a]", "a^b", r"\b", "(?=a)", "[é]", "^a|b",
+        ] {
+            assert!(Regex::new(pattern.as_bytes(), 0).is_none(), "{pattern:?}");
+        }
+    }
+
+    #[test]
+    fn bounds_expansion_and_nesting() {
+        let deep = |n: usize| format!("{}a{}", "(".repeat(n), ")".repeat(n));
+        assert!(matches(&deep(16), 0, "a"));
+        assert!(matches("^(?:a{50}){100}$", 0, &"a".repeat(5_000)));
+        for pattern in [
+            "(){100000000}".to_string(),
+            "(?:(?:(){1000}){1000}){1000}".into(),
+            "(?:(?:(?:(){1000}){1000}){1000}){1000}".into(),
+            "(?:a{1000}){1000}".into(),
+            deep(200_000),
+            format!("a{}", "?".repeat(100_000)),
+        ] {
+            let head = &pattern[..pattern.len().min(40)];
+            assert!(Regex::new(pattern.as_bytes(), 0).is_none(), "{head:?}");
+        }
+    }
+
+    #[test]
+    fn cache_flush_keeps_results() {
+        // (a|b)*a(a|b){10} needs ~2^11 DFA states, well past one flush.
+        let mut regex = Regex::new(b"^(a|b)*a(a|b){10}$", 0).unwrap();
+        let mut seed = 12345u32;
+        for _ in 0..200 {
+            let value: Vec<u8> = (0..40)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    if seed >> 16 & 1 == 0 {
+                        b'a'
+                    } else {
+                        b'b'
+                    }
+                })
+                .collect();
+            assert_eq!(regex.is_match_in(&value), value[value.len() - 11] == b'a');
+        }
+    }
+
+    #[test]
+    fn batch_through_handle() {
+        let pattern = b"^GET /api/";
+        let handle = unsafe { regex_compile(pattern.as_ptr(), pattern.len(), 0) };
+        assert!(handle > 0);
+
+        let text = b"GET /api/usersPOST /api/xGET /index.htmlGET /api/";
+        let offsets = [0u32, 14, 25, 40, 49];
+        let mut out = [0xFFu8; 1];
+        let written = unsafe {
+            regex_match_batch(
+                handle as u32,
+                text.as_ptr(),
+                text.len(),
+                offsets.as_ptr(),
+                offsets.len(),
+                out.as_mut_ptr(),
+                1,
+            )
+        };
+        assert_eq!(written, 1);
+        assert_eq!(out, [0b1001]);
+
+        assert_eq!(handles::handle_drop(handle as u32), 0);
+        let status = unsafe {
+            regex_match_batch(
+                handle as u32,
+                text.as_ptr(),
+                text.len(),
+                offsets.as_ptr(),
+                5,
+                out.as_mut_ptr(),
+                1,
+            )
+        };
+        assert_eq!(status, -1);
+        assert_eq!(unsafe { regex_compile(b"(".as_ptr(), 1, 0) }, -1);
+    }
+}
@@ -0,0 +1,258 @@
+//! Record repair for line-delimited input (CSV rows, NDJSON documents).
+//!
+//! `repair_lines` takes the terminator offsets produced by `find_line_offsets`
+//! and a validity mask with one byte per record, and rebuilds the buffer
+//! without the bad records so ingest can continue past them in wasm. Record
+//! `k` spans from the end of terminator `k - 1` up to offset `k`; the bytes
+//! after the last offset form one final record, which may be empty.
+
+use crate::ffi;
+
+/// Drop malformed records along with their terminators.
+pub const REPAIR_DROP: u32 = 0;
+/// Replace the contents of malformed records with the patch bytes, keeping
+/// the terminator so record numbering stays aligned with the input.
+pub const REPAIR_PATCH: u32 = 1;
+
+/// Appends skipped ranges to a caller-provided `u32` buffer. Slot 0 holds the
+/// total number of ranges, followed by `[start, end)` pairs for as many as
+/// fit; a total larger than the stored pairs means the report was truncated.
+struct Report<'a> {
+    slots: &'a mut [u32],
+    total: u32,
+}
+
+impl Report<'_> {
+    fn push(&mut self, start: usize, end: usize) {
+        let at = 1 + 2 * self.total as usize;
+        if at + 2 <= self.slots.len() {
+            self.slots[at] = start as u32;
+            self.slots[at + 1] = end as u32;
+        }
+        self.total += 1;
+    }
+
+    fn finish(self) {
+        if let Some(count) = self.slots.first_mut() {
+            *count = self.total;
+        }
+    }
+}
+
+/// Bytes after the terminator at `offset`: 2 for CRLF, 1 otherwise.
+fn terminator_len(input: &[u8], offset: usize) -> usize {
+    if input[offset] == b'\r' && input.get(offset + 1) == Some(&b'\n') {
+        2
+    } else {
+        1
+    }
+}
+
+/// Append `bytes` at the output cursor, or fail if they do not fit.
+fn emit(out: &mut [u8], written: &mut usize, bytes: &[u8]) -> Option<()> {
+    let end = written.checked_add(bytes.len())?;
+    out.get_mut(*written..end)?.copy_from_slice(bytes);
+    *written = end;
+    Some(())
+}
+
+/// Rebuild `input` from its valid records. Runs of consecutive valid records
+/// are copied with a single memcpy. Returns bytes written, or `None` on
+/// inconsistent offsets or a short output buffer.
+fn repair(
+    input: &[u8],
+    offsets: &[u32],
+    mask: &[u8],
+    patch: Option<&[u8]>,
+    out: &mut [u8],
+    report: &mut Report,
+) -> Option<usize> {
+    let mut written = 0;
+    let mut run_start = 0;
+    let mut start = 0;
+
+    for (k, &valid) in mask.iter().enumerate() {
+        let (end, next) = match offsets.get(k) {
+            Some(&offset) => {
+                let offset = offset as usize;
+                if offset < start || offset >= input.len() {
+                    return None;
+                }
+                (offset, offset + terminator_len(input, offset))
+            }
+            None => (input.len(), input.len()),
+        };
+
+        if valid == 0 {
+            report.push(start, end);
+            emit(out, &mut written, &input[run_start..start])?;
+            match patch {
+                // The terminator stays in the next run.
+                Some(patch) => {
+                    emit(out, &mut written, patch)?;
+                    run_start = end;
+                }
+                None => run_start = next,
+            }
+        }
+        start = next;
+    }
+
+    emit(out, &mut written, &input[run_start..])?;
+    Some(written)
+}
+
+/// Drop or patch the records flagged invalid in `mask_ptr`.
+///
+/// `offsets_ptr` holds `offsets_len` terminator positions in ascending order
+/// (the output of `find_line_offsets`), and `mask_ptr` one byte per record,
+/// `offsets_len + 1` in total, nonzero for valid records. `mode` is
+/// `REPAIR_DROP` or `REPAIR_PATCH`; the patch bytes are ignored when
+/// dropping. Each bad record's `[start, end)` range, excluding its
+/// terminator, is appended to the report at `report_ptr` (`report_len` u32
+/// slots, may be 0).
+///
+/// Returns bytes written, `-1` for inconsistent offsets, an unknown mode or a
+/// short output buffer, and `ALIAS_ERROR` for overlapping input and output in
+/// `strict-aliasing` builds.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn repair_lines(
+    in_ptr: *const u8,
+    in_len: usize,
+    offsets_ptr: *const u32,
+    offsets_len: usize,
+    mask_ptr: *const u8,
+    mode: u32,
+    patch_ptr: *const u8,
+    patch_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+    report_ptr: *mut u32,
+    report_len: usize,
+) -> isize {
+    if ffi::aliased(in_ptr, in_len, out_ptr, out_len) {
+        return ffi::ALIAS_ERROR;
+    }
+    let patch = match mode {
+        REPAIR_DROP => None,
+        REPAIR_PATCH => Some(ffi::slice(patch_ptr, patch_len)),
+        _ => return -1,
+    };
+    let mut report = Report {
+        slots: ffi::slice_mut(report_ptr, report_len),
+        total: 0,
+    };
+
+    let written = repair(
+        ffi::slice(in_ptr, in_len),
+        ffi::slice(offsets_ptr, offsets_len),
+        ffi::slice(mask_ptr, offsets_len + 1),
+        patch,
+        ffi::slice_mut(out_ptr, out_len),
+        &mut report,
+    );
+    report.finish();
+    written.map_or(-1, |n| n as isize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &[u8] = b"{\"a\":1}\n{bad\r\n{\"b\":2}\n\xff\xfe\n{\"c\":3}";
+    const OFFSETS: [u32; 4] = [7, 12, 21, 24];
+    const MASK: [u8; 5] = [1, 0, 1, 0, 1];
+
+    fn run(
+        mode: u32,
+        patch: &[u8],
+        out_len: usize,
+        report_len: usize,
+    ) -> (isize, Vec<u8>, Vec<u32>) {
+        let mut out = vec![0u8; out_len];
+        let mut report = vec![0u32; report_len];
+        let written = unsafe {
+            repair_lines(
+                INPUT.as_ptr(),
+                INPUT.len(),
+                OFFSETS.as_ptr(),
+                OFFSETS.len(),
+                MASK.as_ptr(),
+                mode,
+                patch.as_ptr(),
+                patch.len(),
+                out.as_mut_ptr(),
+                out.len(),
+                report.as_mut_ptr(),
+                report.len(),
+            )
+        };
+        out.truncate(written.max(0) as usize);
+        (written, out, report)
+    }
+
+    #[test]
+    fn drop_removes_records_and_terminators() {
+        let (written, out, report) = run(REPAIR_DROP, b"", INPUT.len(), 5);
+        assert_eq!(written, out.len() as isize);
+        assert_eq!(out, b"{\"a\":1}\n{\"b\":2}\n{\"c\":3}");
+        assert_eq!(report, [2, 8, 12, 22, 24]);
+    }
+
+    #[test]
+    fn patch_keeps_record_numbering() {
+        let (_, out, report) = run(REPAIR_PATCH, b"null", 64, 1);
+        assert_eq!(out, b"{\"a\":1}\nnull\r\n{\"b\":2}\nnull\n{\"c\":3}");
+        // Only the count fits; the pairs are truncated.
+        assert_eq!(report, [2]);
+    }
+
+    #[test]
+    fn dropping_the_final_record() {
+        let input = b"ok\nbad";
+        let mut out = [0u8; 8];
+        let written = unsafe {
+            repair_lines(
+                input.as_ptr(),
+                input.len(),
+                [2u32].as_ptr(),
+                1,
+                [1u8, 0].as_ptr(),
+                REPAIR_DROP,
+                std::ptr::null(),
+                0,
+                out.as_mut_ptr(),
+                out.len(),
+                std::ptr::null_mut(),
+                0,
+            )
+        };
+        assert_eq!(&out[..written as usize], b"ok\n");
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(run(REPAIR_PATCH, b"null", INPUT.len(), 0).0, -1);
+        assert_eq!(run(7, b"", INPUT.len(), 0).0, -1);
+
+        let mut out = [0u8; 8];
+        let written = unsafe {
+            repair_lines(
+                b"a\nb".as_ptr(),
+                3,
+                [5u32].as_ptr(),
+                1,
+                [1u8, 1].as_ptr(),
+                REPAIR_DROP,
+                std::ptr::null(),
+                0,
+                out.as_mut_ptr(),
+                out.len(),
+                std::ptr::null_mut(),
+                0,
+            )
+        };
+        assert_eq!(written, -1);
+    }
+}
@@ -0,0 +1,232 @@
+//! Resampling and gap filling for `f64` time series.
+//!
+//! `resample_linear_f64` interpolates a series onto new timestamps, so
+//! several sensor streams can be aligned to one time base. `fill_forward_f64`
+//! carries the last valid value over gaps. Timestamps are `f64`s in any unit
+//! (epoch milliseconds, as JS `Date` values, are typical).
+
+use crate::ffi;
+
+/// Value of the series `(ts, values)` at `t`, interpolated linearly between
+/// its neighbours, or `NaN` outside `ts[0]..=ts[n - 1]`.
+fn interpolate(ts: &[f64], values: &[f64], t: f64) -> f64 {
+    // First sample at or after `t`.
+    let i = ts.partition_point(|&s| s < t);
+    match (ts.get(i), i.checked_sub(1)) {
+        (Some(&s), _) if s == t => values[i],
+        (Some(&right), Some(left)) => {
+            let weight = (t - ts[left]) / (right - ts[left]);
+            values[left] + (values[i] - values[left]) * weight
+        }
+        _ => f64::NAN,
+    }
+}
+
+/// Interpolate the series given by timestamps `ts_ptr` and values
+/// `values_ptr` (both `f64`, the same length) at each timestamp of
+/// `new_ts_ptr`, writing one value per new timestamp. `ts` must be strictly
+/// increasing; `new_ts` may be in any order. Timestamps before the first or
+/// after the last sample give `NaN`, as does a `NaN` timestamp. A `NaN`
+/// value spreads to the interpolations next to it.
+///
+/// Returns bytes written (`new_ts_len_bytes`), or `-1` for mismatched or
+/// partial inputs, timestamps out of order, or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn resample_linear_f64(
+    ts_ptr: *const f64,
+    ts_len_bytes: usize,
+    values_ptr: *const f64,
+    values_len_bytes: usize,
+    new_ts_ptr: *const f64,
+    new_ts_len_bytes: usize,
+    out_ptr: *mut f64,
+    out_len_bytes: usize,
+) -> isize {
+    if ts_len_bytes != values_len_bytes
+        || !ts_len_bytes.is_multiple_of(8)
+        || !new_ts_len_bytes.is_multiple_of(8)
+        || out_len_bytes < new_ts_len_bytes
+    {
+        return -1;
+    }
+    let out_bytes = out_ptr as *const u8;
+    if ffi::aliased(
+        ts_ptr as *const u8,
+        ts_len_bytes,
+        out_bytes,
+        new_ts_len_bytes,
+    ) || ffi::aliased(
+        values_ptr as *const u8,
+        values_len_bytes,
+        out_bytes,
+        new_ts_len_bytes,
+    ) || ffi::aliased(
+        new_ts_ptr as *const u8,
+        new_ts_len_bytes,
+        out_bytes,
+        new_ts_len_bytes,
+    ) {
+        return ffi::ALIAS_ERROR;
+    }
+    let ts = ffi::slice(ts_ptr, ts_len_bytes / 8);
+    let values = ffi::slice(values_ptr, values_len_bytes / 8);
+    if !ts.windows(2).all(|w| w[0] < w[1]) {
+        return -1;
+    }
+    let new_ts = ffi::slice(new_ts_ptr, new_ts_len_bytes / 8);
+    let out = ffi::slice_mut(out_ptr, new_ts.len());
+    for (slot, &t) in out.iter_mut().zip(new_ts) {
+        *slot = interpolate(ts, values, t);
+    }
+    new_ts_len_bytes as isize
+}
+
+/// Replace each missing value at `in_ptr` with the last value before it that
+/// is present. Value `i` is present when bit `i % 8` of byte `i / 8` of the
+/// validity bitmap at `validity_ptr` is set; with `validity_len == 0`,
+/// `NaN` marks the missing values instead. Missing values before the first
+/// present one become `NaN`. Runs in place when `in_ptr == out_ptr`.
+///
+/// Returns bytes written, or `-1` for a partial value, a short bitmap or a
+/// short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn fill_forward_f64(
+    in_ptr: *const f64,
+    in_len_bytes: usize,
+    validity_ptr: *const u8,
+    validity_len: usize,
+    out_ptr: *mut f64,
+    out_len_bytes: usize,
+) -> isize {
+    let n = in_len_bytes / 8;
+    if !in_len_bytes.is_multiple_of(8)
+        || out_len_bytes < in_len_bytes
+        || (validity_len != 0 && validity_len < n.div_ceil(8))
+    {
+        return -1;
+    }
+    let validity = ffi::slice(validity_ptr, validity_len);
+    if ffi::aliased(
+        validity_ptr,
+        validity_len,
+        out_ptr as *const u8,
+        in_len_bytes,
+    ) {
+        return ffi::ALIAS_ERROR;
+    }
+    if !std::ptr::eq(in_ptr, out_ptr) {
+        if ffi::aliased(
+            in_ptr as *const u8,
+            in_len_bytes,
+            out_ptr as *const u8,
+            in_len_bytes,
+        ) {
+            return ffi::ALIAS_ERROR;
+        }
+        std::ptr::copy(in_ptr, out_ptr, n);
+    }
+    let mut last = f64::NAN;
+    for (i, v) in ffi::slice_mut(out_ptr, n).iter_mut().enumerate() {
+        let present = if validity.is_empty() {
+            !v.is_nan()
+        } else {
+            validity[i / 8] & (1 << (i % 8)) != 0
+        };
+        if present {
+            last = *v;
+        } else {
+            *v = last;
+        }
+    }
+    in_len_bytes as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resample(ts: &[f64], values: &[f64], new_ts: &[f64]) -> Option<Vec<f64>> {
+        let mut out = vec![0f64; new_ts.len()];
+        let written = unsafe {
+            resample_linear_f64(
+                ts.as_ptr(),
+                ts.len() * 8,
+                values.as_ptr(),
+                values.len() * 8,
+                new_ts.as_ptr(),
+                new_ts.len() * 8,
+                out.as_mut_ptr(),
+                out.len() * 8,
+            )
+        };
+        (written >= 0).then_some(out)
+    }
+
+    #[test]
+    fn interpolates_onto_new_timestamps() {
+        let ts = [1000.0, 2000.0, 4000.0];
+        let values = [10.0, 20.0, 0.0];
+        let out = resample(
+            &ts,
+            &values,
+            &[3000.0, 1000.0, 1500.0, 4000.0, 999.0, 4001.0],
+        )
+        .unwrap();
+        assert_eq!(&out[..4], [10.0, 10.0, 15.0, 0.0]);
+        assert!(out[4].is_nan() && out[5].is_nan(), "no extrapolation");
+        assert!(resample(&ts, &values, &[f64::NAN]).unwrap()[0].is_nan());
+        assert!(resample(&[], &[], &[1.0]).unwrap()[0].is_nan());
+
+        assert_eq!(resample(&ts, &values[..2], &[1.0]), None, "mismatched");
+        assert_eq!(
+            resample(&[2.0, 1.0], &[0.0, 0.0], &[1.0]),
+            None,
+            "unordered"
+        );
+        assert_eq!(resample(&[1.0, 1.0], &[0.0, 0.0], &[1.0]), None, "repeated");
+    }
+
+    #[test]
+    fn fills_gaps_forward() {
+        let values = [f64::NAN, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0];
+        // Present: 1, 2, 5, 9.
+        let validity = [0b0010_0110, 0b10];
+        let mut out = [0f64; 10];
+        let written = unsafe {
+            fill_forward_f64(
+                values.as_ptr(),
+                80,
+                validity.as_ptr(),
+                2,
+                out.as_mut_ptr(),
+                80,
+            )
+        };
+        assert_eq!(written, 80);
+        assert!(out[0].is_nan());
+        assert_eq!(out[1..], [1.0, 2.0, 2.0, 2.0, 5.0, 5.0, 5.0, 5.0, 9.0]);
+
+        let mut in_place = [f64::NAN, 1.0, f64::NAN, f64::NAN, 4.0, f64::NAN];
+        let ptr = in_place.as_mut_ptr();
+        assert_eq!(
+            unsafe { fill_forward_f64(ptr, 48, std::ptr::null(), 0, ptr, 48) },
+            48
+        );
+        assert!(in_place[0].is_nan());
+        assert_eq!(in_place[1..], [1.0, 1.0, 1.0, 4.0, 4.0]);
+
+        let status = unsafe {
+            fill_forward_f64(
+                values.as_ptr(),
+                80,
+                validity.as_ptr(),
+                1,
+                out.as_mut_ptr(),
+                80,
+            )
+        };
+        assert_eq!(status, -1, "short bitmap");
+    }
+}
@@ -0,0 +1,629 @@
+//! Compressed `u32` sets in the style of Roaring bitmaps.
+//!
+//! Values are grouped by their high 16 bits into containers of up to 65 536
+//! values. A container holding at most 4096 values is a sorted `u16` array;
+//! a denser one is a 65 536-bit bitmap (8 KiB), so no container ever costs
+//! more than 8 KiB and sparse selections stay a few bytes per row. Sets live
+//! behind handles; the dense filter bitmaps produced by the batch kernels
+//! convert in and out with `roaring_from_bitmap` and `roaring_to_bitmap`.
+
+#[cfg(feature = "multi-value")]
+use crate::multi_value::{multi_value_export, Status};
+use crate::{ffi, handles};
+
+/// Largest array container; one more value and a bitmap is smaller.
+const ARRAY_MAX: usize = 4096;
+const WORDS: usize = 1024;
+
+#[derive(Clone)]
+enum Container {
+    Array(Vec<u16>),
+    /// The words and how many bits are set in them.
+    Bitmap(Box<[u64; WORDS]>, usize),
+}
+
+impl Container {
+    fn len(&self) -> usize {
+        match self {
+            Container::Array(values) => values.len(),
+            Container::Bitmap(_, len) => *len,
+        }
+    }
+
+    fn contains(&self, low: u16) -> bool {
+        match self {
+            Container::Array(values) => values.binary_search(&low).is_ok(),
+            Container::Bitmap(words, _) => words[low as usize / 64] >> (low % 64) & 1 == 1,
+        }
+    }
+
+    fn insert(&mut self, low: u16) {
+        match self {
+            Container::Array(values) => {
+                if let Err(at) = values.binary_search(&low) {
+                    values.insert(at, low);
+                    let len = values.len();
+                    if len > ARRAY_MAX {
+                        *self = Container::Bitmap(self.words(), len);
+                    }
+                }
+            }
+            Container::Bitmap(words, len) => {
+                let word = &mut words[low as usize / 64];
+                *len += (!*word >> (low % 64) & 1) as usize;
+                *word |= 1 << (low % 64);
+            }
+        }
+    }
+
+    fn remove(&mut self, low: u16) {
+        match self {
+            Container::Array(values) => {
+                if let Ok(at) = values.binary_search(&low) {
+                    values.remove(at);
+                }
+            }
+            Container::Bitmap(words, len) => {
+                let word = &mut words[low as usize / 64];
+                *len -= (*word >> (low % 64) & 1) as usize;
+                *word &= !(1 << (low % 64));
+                if *len <= ARRAY_MAX {
+                    *self = Container::Array(self.values());
+                }
+            }
+        }
+    }
+
+    fn words(&self) -> Box<[u64; WORDS]> {
+        match self {
+            Container::Array(values) => {
+                let mut words = Box::new([0u64; WORDS]);
+                for &v in values {
+                    words[v as usize / 64] |= 1 << (v % 64);
+                }
+                words
+            }
+            Container::Bitmap(words, _) => words.clone(),
+        }
+    }
+
+    fn values(&self) -> Vec<u16> {
+        let mut values = Vec::new();
+        self.for_each(|v| values.push(v));
+        values
+    }
+
+    /// Call `f` on every value in ascending order.
+    fn for_each(&self, mut f: impl FnMut(u16)) {
+        match self {
+            Container::Array(values) => values.iter().for_each(|&v| f(v)),
+            Container::Bitmap(words, _) => {
+                for (i, &word) in words.iter().enumerate() {
+                    let mut word = word;
+                    while word != 0 {
+                        f((i * 64) as u16 + word.trailing_zeros() as u16);
+                        word &= word - 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The cheaper representation of a bitmap, or `None` when it is empty.
+    fn from_words(words: Box<[u64; WORDS]>) -> Option<Container> {
+        let len = words.iter().map(|w| w.count_ones() as usize).sum();
+        let bitmap = Container::Bitmap(words, len);
+        match len {
+            0 => None,
+            n if n <= ARRAY_MAX => Some(Container::Array(bitmap.values())),
+            _ => Some(bitmap),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum SetOp {
+    And,
+    Or,
+    Xor,
+    AndNot,
+}
+
+impl SetOp {
+    fn keep(self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            SetOp::And => in_a && in_b,
+            SetOp::Or => in_a || in_b,
+            SetOp::Xor => in_a != in_b,
+            SetOp::AndNot => in_a && !in_b,
+        }
+    }
+
+    fn word(self, a: u64, b: u64) -> u64 {
+        match self {
+            SetOp::And => a & b,
+            SetOp::Or => a | b,
+            SetOp::Xor => a ^ b,
+            SetOp::AndNot => a & !b,
+        }
+    }
+}
+
+/// Combine two containers with the same key. Two arrays merge directly;
+/// anything involving a bitmap goes word by word.
+fn combine(a: Option<&Container>, b: Option<&Container>, op: SetOp) -> Option<Container> {
+    match (a, b) {
+        (None, None) => None,
+        (Some(c), None) | (None, Some(c)) => op.keep(a.is_some(), b.is_some()).then(|| c.clone()),
+        (Some(Container::Array(x)), Some(Container::Array(y))) => {
+            let (mut i, mut j) = (0, 0);
+            let mut out = Vec::new();
+            while i < x.len() || j < y.len() {
+                let (xv, yv) = (x.get(i), y.get(j));
+                let v = *xv.into_iter().chain(yv).min().unwrap_or(&0);
+                let (in_a, in_b) = (xv == Some(&v), yv == Some(&v));
+                i += usize::from(in_a);
+                j += usize::from(in_b);
+                if op.keep(in_a, in_b) {
+                    out.push(v);
+                }
+            }
+            match out.len() {
+                0 => None,
+                n if n <= ARRAY_MAX => Some(Container::Array(out)),
+                n => Some(Container::Bitmap(Container::Array(out).words(), n)),
+            }
+        }
+        (Some(x), Some(y)) => {
+            let (mut words, other) = (x.words(), y.words());
+            for (w, &o) in words.iter_mut().zip(other.iter()) {
+                *w = op.word(*w, o);
+            }
+            Container::from_words(words)
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct Roaring {
+    keys: Vec<u16>,
+    containers: Vec<Container>,
+}
+
+impl Roaring {
+    fn find(&self, value: u32) -> Option<&Container> {
+        let at = self.keys.binary_search(&((value >> 16) as u16)).ok()?;
+        Some(&self.containers[at])
+    }
+
+    fn contains(&self, value: u32) -> bool {
+        self.find(value).is_some_and(|c| c.contains(value as u16))
+    }
+
+    fn insert(&mut self, value: u32) {
+        let key = (value >> 16) as u16;
+        let at = match self.keys.binary_search(&key) {
+            Ok(at) => at,
+            Err(at) => {
+                self.keys.insert(at, key);
+                self.containers.insert(at, Container::Array(Vec::new()));
+                at
+            }
+        };
+        self.containers[at].insert(value as u16);
+    }
+
+    fn remove(&mut self, value: u32) {
+        let Ok(at) = self.keys.binary_search(&((value >> 16) as u16)) else {
+            return;
+        };
+        self.containers[at].remove(value as u16);
+        if self.containers[at].len() == 0 {
+            self.keys.remove(at);
+            self.containers.remove(at);
+        }
+    }
+
+    fn len(&self) -> u64 {
+        self.containers.iter().map(|c| c.len() as u64).sum()
+    }
+
+    /// Call `f` on every value in ascending order.
+    fn for_each(&self, mut f: impl FnMut(u32)) {
+        for (&key, container) in self.keys.iter().zip(&self.containers) {
+            container.for_each(|low| f((key as u32) << 16 | low as u32));
+        }
+    }
+
+    fn combine(&mut self, other: &Roaring, op: SetOp) {
+        let mut out = Roaring::default();
+        let (mut i, mut j) = (0, 0);
+        while i < self.keys.len() || j < other.keys.len() {
+            let (a, b) = (self.keys.get(i), other.keys.get(j));
+            let key = *a.into_iter().chain(b).min().unwrap_or(&0);
+            let x = (a == Some(&key)).then(|| &self.containers[i]);
+            let y = (b == Some(&key)).then(|| &other.containers[j]);
+            i += usize::from(x.is_some());
+            j += usize::from(y.is_some());
+            if let Some(container) = combine(x, y, op) {
+                out.keys.push(key);
+                out.containers.push(container);
+            }
+        }
+        *self = out;
+    }
+}
+
+/// Create an empty set.
+///
+/// Returns a handle for the other `roaring_` exports, released with
+/// `handle_drop`.
+#[no_mangle]
+pub extern "C" fn roaring_new() -> isize {
+    handles::insert(Roaring::default()) as isize
+}
+
+/// Build a set from a dense bitmap (bit `i % 8` of byte `i / 8`, as written
+/// by the batch match kernels): bit `i` set adds `offset + i`.
+///
+/// Returns a handle, released with `handle_drop`, or `-1` when the last
+/// position would pass `u32::MAX`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn roaring_from_bitmap(
+    bitmap_ptr: *const u8,
+    bitmap_len: usize,
+    offset: u32,
+) -> isize {
+    if offset as u64 + bitmap_len as u64 * 8 > 1 << 32 {
+        return -1;
+    }
+    let bitmap = ffi::slice(bitmap_ptr, bitmap_len);
+    let mut set = Roaring::default();
+    for (i, &byte) in bitmap.iter().enumerate() {
+        let mut byte = byte;
+        while byte != 0 {
+            set.insert(offset + (i * 8) as u32 + byte.trailing_zeros());
+            byte &= byte - 1;
+        }
+    }
+    handles::insert(set) as isize
+}
+
+/// Add the `u32` values at `values_ptr` to the set behind `handle`.
+///
+/// Returns `0`, or `-1` for an unknown handle or a partial value.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn roaring_add_batch(
+    handle: u32,
+    values_ptr: *const u32,
+    values_len_bytes: usize,
+) -> isize {
+    if !values_len_bytes.is_multiple_of(4) {
+        return -1;
+    }
+    let values = ffi::slice(values_ptr, values_len_bytes / 4);
+    handles::with(handle, |set: &mut Roaring| {
+        values.iter().for_each(|&v| set.insert(v));
+        0
+    })
+    .unwrap_or(-1)
+}
+
+/// Remove the `u32` values at `values_ptr` from the set behind `handle`;
+/// values not in the set are ignored.
+///
+/// Returns `0`, or `-1` for an unknown handle or a partial value.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn roaring_remove_batch(
+    handle: u32,
+    values_ptr: *const u32,
+    values_len_bytes: usize,
+) -> isize {
+    if !values_len_bytes.is_multiple_of(4) {
+        return -1;
+    }
+    let values = ffi::slice(values_ptr, values_len_bytes / 4);
+    handles::with(handle, |set: &mut Roaring| {
+        values.iter().for_each(|&v| set.remove(v));
+        0
+    })
+    .unwrap_or(-1)
+}
+
+/// Test each `u32` value at `values_ptr` for membership, writing a bitmap
+/// (bit `i % 8` of byte `i / 8` set when value `i` is in the set).
+///
+/// Returns bytes written (`ceil(values / 8)`), or `-1` for an unknown
+/// handle, a partial value or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn roaring_contains_batch(
+    handle: u32,
+    values_ptr: *const u32,
+    values_len_bytes: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    if !values_len_bytes.is_multiple_of(4) {
+        return -1;
+    }
+    let n = values_len_bytes / 4;
+    let needed = n.div_ceil(8);
+    if out_len < needed {
+        return -1;
+    }
+    if ffi::aliased(values_ptr as *const u8, values_len_bytes, out_ptr, needed) {
+        return ffi::ALIAS_ERROR;
+    }
+    let values = ffi::slice(values_ptr, n);
+    let out = ffi::slice_mut(out_ptr, needed);
+    handles::with(handle, |set: &mut Roaring| {
+        out.fill(0);
+        for (i, &v) in values.iter().enumerate() {
+            out[i / 8] |= u8::from(set.contains(v)) << (i % 8);
+        }
+        needed as isize
+    })
+    .unwrap_or(-1)
+}
+
+/// Number of values in the set behind `handle`.
+///
+/// Returns the count as an `f64` (a full set holds `2^32` values), or `-1`
+/// for an unknown handle.
+#[cfg_attr(not(feature = "multi-value"), no_mangle)]
+pub extern "C" fn roaring_count(handle: u32) -> f64 {
+    handles::with(handle, |set: &mut Roaring| set.len() as f64).unwrap_or(-1.0)
+}
+
+/// `roaring_count` in `multi-value` builds: returns `(status, count)`.
+#[cfg(feature = "multi-value")]
+#[export_name = "roaring_count"]
+pub extern "C" fn roaring_count_multi_value(handle: u32) -> Status<f64> {
+    multi_value_export!(roaring_count, "i32 f64");
+    let count = roaring_count(handle);
+    Status::new(if count < 0.0 { -1 } else { 0 }, count)
+}
+
+/// Write the values of the set behind `handle` as ascending `u32`s.
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for an
+/// unknown handle or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn roaring_to_array(
+    handle: u32,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    handles::with(handle, |set: &mut Roaring| {
+        let Ok(needed) = usize::try_from(set.len() * 4) else {
+            return -1;
+        };
+        if out_len_bytes == 0 {
+            return needed as isize;
+        }
+        if out_len_bytes < needed {
+            return -1;
+        }
+        let out = ffi::slice_mut(out_ptr, needed / 4);
+        let mut n = 0;
+        set.for_each(|v| {
+            out[n] = v;
+            n += 1;
+        });
+        needed as isize
+    })
+    .unwrap_or(-1)
+}
+
+/// Write the membership of `start..start + out_len * 8` as a dense bitmap:
+/// bit `i % 8` of byte `i / 8` is set when `start + i` is in the set, ready
+/// for the `bitmap_` kernels.
+///
+/// Returns bytes written (`out_len`), or `-1` for an unknown handle.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn roaring_to_bitmap(
+    handle: u32,
+    start: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let out = ffi::slice_mut(out_ptr, out_len);
+    handles::with(handle, |set: &mut Roaring| {
+        out.fill(0);
+        let start = start as u64;
+        let end = start + out_len as u64 * 8;
+        for (&key, container) in set.keys.iter().zip(&set.containers) {
+            let base = (key as u64) << 16;
+            if base + (1 << 16) <= start || base >= end {
+                continue;
+            }
+            container.for_each(|low| {
+                let v = base + low as u64;
+                if (start..end).contains(&v) {
+                    let i = (v - start) as usize;
+                    out[i / 8] |= 1 << (i % 8);
+                }
+            });
+        }
+        out_len as isize
+    })
+    .unwrap_or(-1)
+}
+
+/// Shared body of the set operations: replaces `a` with `a op b`.
+fn combine_handles(a: u32, b: u32, op: SetOp) -> isize {
+    // `handles::with` cannot nest, so copy `b` out first.
+    let Some(other) = handles::with(b, |set: &mut Roaring| set.clone()) else {
+        return -1;
+    };
+    handles::with(a, |set: &mut Roaring| {
+        set.combine(&other, op);
+        0
+    })
+    .unwrap_or(-1)
+}
+
+/// Replace the set behind `a` with its intersection with `b`; `b` is left
+/// unchanged and may be the same handle. Returns `0`, or `-1` for an
+/// unknown handle.
+#[no_mangle]
+pub extern "C" fn roaring_and(a: u32, b: u32) -> isize {
+    combine_handles(a, b, SetOp::And)
+}
+
+/// Replace the set behind `a` with its union with `b`; see `roaring_and`.
+#[no_mangle]
+pub extern "C" fn roaring_or(a: u32, b: u32) -> isize {
+    combine_handles(a, b, SetOp::Or)
+}
+
+/// Replace the set behind `a` with the values in exactly one of `a` and
+/// `b`; see `roaring_and`.
+#[no_mangle]
+pub extern "C" fn roaring_xor(a: u32, b: u32) -> isize {
+    combine_handles(a, b, SetOp::Xor)
+}
+
+/// Remove the values of `b` from the set behind `a`; see `roaring_and`.
+#[no_mangle]
+pub extern "C" fn roaring_andnot(a: u32, b: u32) -> isize {
+    combine_handles(a, b, SetOp::AndNot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// Sparse values across several containers plus one dense run that
+    /// forces a bitmap container.
+    fn sample(seed: u32) -> BTreeSet<u32> {
+        let mut state = seed.wrapping_mul(2654435761) | 1;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+        let mut values: BTreeSet<u32> = (0..3000).map(|_| next() % (1 << 19)).collect();
+        values.extend((0..9000).map(|_| (3 << 16) + next() % 20_000));
+        values.extend([u32::MAX, 0]);
+        values
+    }
+
+    fn build(values: &BTreeSet<u32>) -> u32 {
+        let handle = roaring_new() as u32;
+        let values: Vec<u32> = values.iter().copied().collect();
+        assert_eq!(
+            unsafe { roaring_add_batch(handle, values.as_ptr(), values.len() * 4) },
+            0
+        );
+        handle
+    }
+
+    fn to_vec(handle: u32) -> Vec<u32> {
+        let needed = unsafe { roaring_to_array(handle, std::ptr::null_mut(), 0) };
+        let mut out = vec![0u32; needed as usize / 4];
+        let written = unsafe { roaring_to_array(handle, out.as_mut_ptr(), needed as usize) };
+        assert_eq!(written, needed);
+        out
+    }
+
+    #[test]
+    fn tracks_membership_across_container_kinds() {
+        let mut expected = sample(1);
+        let handle = build(&expected);
+        assert_eq!(roaring_count(handle), expected.len() as f64);
+        assert_eq!(to_vec(handle), expected.iter().copied().collect::<Vec<_>>());
+
+        let removed: Vec<u32> = expected.iter().copied().step_by(2).collect();
+        unsafe { roaring_remove_batch(handle, removed.as_ptr(), removed.len() * 4) };
+        removed.iter().for_each(|v| {
+            expected.remove(v);
+        });
+        assert_eq!(to_vec(handle), expected.iter().copied().collect::<Vec<_>>());
+
+        let probes: Vec<u32> = (0..2000)
+            .map(|i| i * 997 % (1 << 19))
+            .chain(removed)
+            .collect();
+        let mut bits = vec![0u8; probes.len().div_ceil(8)];
+        let written = unsafe {
+            roaring_contains_batch(
+                handle,
+                probes.as_ptr(),
+                probes.len() * 4,
+                bits.as_mut_ptr(),
+                bits.len(),
+            )
+        };
+        assert_eq!(written, bits.len() as isize);
+        for (i, v) in probes.iter().enumerate() {
+            assert_eq!(
+                bits[i / 8] >> (i % 8) & 1 == 1,
+                expected.contains(v),
+                "value {v}"
+            );
+        }
+        assert_eq!(handles::handle_drop(handle), 0);
+    }
+
+    #[test]
+    fn set_operations_match_btreeset() {
+        let (x, y) = (sample(2), sample(3));
+        type Reference = fn(&BTreeSet<u32>, &BTreeSet<u32>) -> BTreeSet<u32>;
+        let ops: [(extern "C" fn(u32, u32) -> isize, Reference); 4] = [
+            (roaring_and, |a, b| a & b),
+            (roaring_or, |a, b| a | b),
+            (roaring_xor, |a, b| a ^ b),
+            (roaring_andnot, |a, b| a - b),
+        ];
+        for (op, reference) in ops {
+            let (a, b) = (build(&x), build(&y));
+            assert_eq!(op(a, b), 0);
+            assert_eq!(to_vec(a), reference(&x, &y).into_iter().collect::<Vec<_>>());
+            assert_eq!(to_vec(b), y.iter().copied().collect::<Vec<_>>());
+            assert_eq!(op(a, a), 0, "same handle");
+            assert_eq!(op(a, 0), -1);
+        }
+    }
+
+    #[test]
+    fn converts_to_and_from_dense_bitmaps() {
+        let bitmap: Vec<u8> = (0..20_000u32)
+            .map(|i| (i.wrapping_mul(37) >> 3) as u8)
+            .collect();
+        let handle = unsafe { roaring_from_bitmap(bitmap.as_ptr(), bitmap.len(), 100) } as u32;
+        let ones: u32 = bitmap.iter().map(|b| b.count_ones()).sum();
+        assert_eq!(roaring_count(handle), ones as f64);
+
+        let mut out = vec![0u8; bitmap.len()];
+        let written = unsafe { roaring_to_bitmap(handle, 100, out.as_mut_ptr(), out.len()) };
+        assert_eq!(written, out.len() as isize);
+        assert_eq!(out, bitmap);
+
+        // A window starting mid-byte shifts the bits.
+        let mut window = [0u8; 2];
+        unsafe { roaring_to_bitmap(handle, 104, window.as_mut_ptr(), 2) };
+        let expected = u16::from_le_bytes([bitmap[0], bitmap[1]]) >> 4 | (bitmap[2] as u16) << 12;
+        assert_eq!(u16::from_le_bytes(window), expected);
+
+        assert_eq!(
+            unsafe { roaring_from_bitmap(bitmap.as_ptr(), 1, u32::MAX - 6) },
+            -1
+        );
+        assert_eq!(handles::handle_drop(handle), 0);
+        assert_eq!(
+            unsafe { roaring_to_bitmap(handle, 0, out.as_mut_ptr(), 1) },
+            -1
+        );
+        assert_eq!(roaring_count(handle), -1.0);
+    }
+}
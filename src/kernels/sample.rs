@@ -0,0 +1,211 @@
+//! Uniform reservoir sampling over streams fed in chunks.
+//!
+//! `reservoir_new` creates a sampler behind a handle and
+//! `reservoir_sample_u32` feeds it one chunk at a time, so a bounded sample of
+//! a stream too large to hold in memory can be kept while it is read (e.g. to
+//! pick the rows `infer_schema` looks at). Sampling uses Algorithm L, which
+//! draws how many values to skip rather than a random number per value, so
+//! long streams cost little more than the copy. Skips are drawn from the
+//! handle's seed alone: the same stream gives the same sample however it is
+//! split into chunks.
+
+use crate::{ffi, handles};
+
+struct Reservoir {
+    k: usize,
+    sample: Vec<u32>,
+    /// Values fed so far.
+    seen: u64,
+    /// Stream position of the next value to take once the sample is full.
+    next: u64,
+    w: f64,
+    rng: u64,
+}
+
+impl Reservoir {
+    /// Uniform in `(0, 1)`, so its logarithm is finite.
+    fn uniform(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        ((self.rng >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+    }
+
+    /// Draw the position of the next value to take, `next` being the one just
+    /// taken (or the last one that filled the sample).
+    fn skip(&mut self) {
+        let gap = (self.uniform().ln() / (1.0 - self.w).ln()).floor();
+        // `as` saturates, pushing the next pick past any real stream.
+        self.next = self.next.saturating_add(gap as u64 + 1);
+    }
+
+    fn shrink_w(&mut self) {
+        self.w *= (self.uniform().ln() / self.k as f64).exp();
+    }
+
+    fn feed(&mut self, chunk: &[u32]) {
+        let mut rest = chunk;
+        if self.sample.len() < self.k {
+            let take = rest.len().min(self.k - self.sample.len());
+            self.sample.extend_from_slice(&rest[..take]);
+            self.seen += take as u64;
+            rest = &rest[take..];
+            if self.sample.len() < self.k {
+                return;
+            }
+            // Just filled: the last value placed counts as taken.
+            self.next = self.seen - 1;
+            self.shrink_w();
+            self.skip();
+        }
+        let end = self.seen + rest.len() as u64;
+        while self.next < end {
+            let value = rest[(self.next - self.seen) as usize];
+            let slot = (self.uniform() * self.k as f64) as usize;
+            self.sample[slot.min(self.k - 1)] = value;
+            self.shrink_w();
+            self.skip();
+        }
+        self.seen = end;
+    }
+}
+
+/// Create a sampler that keeps `k` values drawn uniformly from everything fed
+/// to it. `seed` fixes the random draws.
+///
+/// Returns a handle for `reservoir_sample_u32` and `reservoir_read`, released
+/// with `handle_drop`, or `-1` when `k` is `0`.
+#[no_mangle]
+pub extern "C" fn reservoir_new(k: u32, seed: u32) -> isize {
+    if k == 0 {
+        return -1;
+    }
+    let reservoir = Reservoir {
+        k: k as usize,
+        sample: Vec::new(),
+        seen: 0,
+        next: 0,
+        w: 1.0,
+        // Spread the seed over the state; xorshift must not start at zero.
+        rng: (seed as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15),
+    };
+    handles::insert(reservoir) as isize
+}
+
+/// Feed the `u32` values at `chunk_ptr` to the sampler behind `handle`.
+///
+/// Returns how many values the sample now holds (`k` once at least `k` have
+/// been fed), or `-1` for an unknown handle or a chunk that is not a whole
+/// number of values.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn reservoir_sample_u32(
+    handle: u32,
+    chunk_ptr: *const u32,
+    chunk_len_bytes: usize,
+) -> isize {
+    if !chunk_len_bytes.is_multiple_of(4) {
+        return -1;
+    }
+    let chunk = ffi::slice(chunk_ptr, chunk_len_bytes / 4);
+    handles::with(handle, |r: &mut Reservoir| {
+        r.feed(chunk);
+        r.sample.len() as isize
+    })
+    .unwrap_or(-1)
+}
+
+/// Write the current sample behind `handle` to `out_ptr` as `u32`s.
+///
+/// Returns bytes written, or `-1` for an unknown handle or an output shorter
+/// than the sample.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn reservoir_read(
+    handle: u32,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    handles::with(handle, |r: &mut Reservoir| {
+        let bytes = r.sample.len() * 4;
+        if out_len_bytes < bytes {
+            return -1;
+        }
+        ffi::slice_mut(out_ptr, r.sample.len()).copy_from_slice(&r.sample);
+        bytes as isize
+    })
+    .unwrap_or(-1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `stream` in chunks of `chunk` values and return the sample.
+    fn sample(stream: &[u32], k: u32, seed: u32, chunk: usize) -> Vec<u32> {
+        let handle = reservoir_new(k, seed) as u32;
+        for part in stream.chunks(chunk) {
+            let held = unsafe { reservoir_sample_u32(handle, part.as_ptr(), part.len() * 4) };
+            assert!(held > 0);
+        }
+        let mut out = vec![u32::MAX; k as usize];
+        let written = unsafe { reservoir_read(handle, out.as_mut_ptr(), out.len() * 4) };
+        assert_eq!(handles::handle_drop(handle), 0);
+        out.truncate(written as usize / 4);
+        out
+    }
+
+    #[test]
+    fn short_streams_are_kept_whole() {
+        assert_eq!(sample(&[5, 6, 7], 4, 1, 2), [5, 6, 7]);
+    }
+
+    #[test]
+    fn chunking_does_not_change_the_sample() {
+        let stream: Vec<u32> = (0..10_000).collect();
+        let whole = sample(&stream, 16, 7, stream.len());
+        assert_eq!(whole.len(), 16);
+        for chunk in [1, 3, 16, 17, 999] {
+            assert_eq!(sample(&stream, 16, 7, chunk), whole, "chunk {chunk}");
+        }
+        assert_ne!(sample(&stream, 16, 8, 100), whole, "seed matters");
+    }
+
+    #[test]
+    fn samples_are_roughly_uniform() {
+        // 1000 samples of 10 from 0..1000: each decile expects 1000 hits.
+        let stream: Vec<u32> = (0..1000).collect();
+        let mut deciles = [0u32; 10];
+        for seed in 0..1000 {
+            for v in sample(&stream, 10, seed, 64) {
+                deciles[v as usize / 100] += 1;
+            }
+        }
+        assert!(
+            deciles.iter().all(|&d| (880..1120).contains(&d)),
+            "{deciles:?}"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(reservoir_new(0, 0), -1);
+        let handle = reservoir_new(2, 0) as u32;
+        let values = [1u32, 2];
+        assert_eq!(
+            unsafe { reservoir_sample_u32(handle, values.as_ptr(), 7) },
+            -1
+        );
+        assert_eq!(
+            unsafe { reservoir_sample_u32(handle, values.as_ptr(), 8) },
+            2
+        );
+        let mut out = [0u32; 1];
+        assert_eq!(unsafe { reservoir_read(handle, out.as_mut_ptr(), 4) }, -1);
+        assert_eq!(handles::handle_drop(handle), 0);
+        assert_eq!(
+            unsafe { reservoir_sample_u32(handle, values.as_ptr(), 8) },
+            -1
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! Substring search for rendering highlights over large documents.
+//!
+//! `find_all_matches` reports every non-overlapping occurrence of a needle as
+//! a `(start, len)` pair. Output is capped by the caller's buffer, and a scan
+//! can be resumed from any byte offset, so JS can page through matches in a
+//! long document without rescanning the part it has already rendered.
+
+use crate::ffi;
+
+/// Bitmask of the lanes of the 16-byte `block` equal to `a` or `b`.
+#[inline(always)]
+pub(crate) fn candidates(block: [u8; 16], a: u8, b: u8) -> u16 {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let v = v128_load(block.as_ptr() as *const v128);
+        u8x16_bitmask(v128_or(
+            u8x16_eq(v, u8x16_splat(a)),
+            u8x16_eq(v, u8x16_splat(b)),
+        ))
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        (0..16).fold(0, |mask, i| {
+            mask | (((block[i] == a || block[i] == b) as u16) << i)
+        })
+    }
+}
+
+/// Call `hit` with the start of each non-overlapping match of `needle` in
+/// `haystack[from..]` until it returns `false`. Candidate positions come from
+/// a 16-byte scan for the needle's first byte (both cases when folding).
+pub(crate) fn for_each_match(
+    haystack: &[u8],
+    needle: &[u8],
+    fold: bool,
+    from: usize,
+    mut hit: impl FnMut(usize) -> bool,
+) {
+    let Some(&first) = needle.first() else {
+        return;
+    };
+    let (a, b) = if fold {
+        (first.to_ascii_lowercase(), first.to_ascii_uppercase())
+    } else {
+        (first, first)
+    };
+    let last_start = match haystack.len().checked_sub(needle.len()) {
+        Some(last) if from <= last => last,
+        _ => return,
+    };
+
+    let mut block_start = from;
+    // Matches may not overlap, so candidates before `next` are skipped.
+    let mut next = from;
+    while block_start <= last_start {
+        let chunk = &haystack[block_start..(block_start + 16).min(last_start + 1)];
+        let mut block = [0u8; 16];
+        block[..chunk.len()].copy_from_slice(chunk);
+        let live = u16::MAX >> (16 - chunk.len());
+        let mut mask = candidates(block, a, b) & live;
+        while mask != 0 {
+            let i = block_start + mask.trailing_zeros() as usize;
+            mask &= mask - 1;
+            if i < next {
+                continue;
+            }
+            let window = &haystack[i..i + needle.len()];
+            let found = if fold {
+                window.eq_ignore_ascii_case(needle)
+            } else {
+                window == needle
+            };
+            if found {
+                if !hit(i) {
+                    return;
+                }
+                next = i + needle.len();
+            }
+        }
+        block_start += 16;
+    }
+}
+
+/// Find the non-overlapping occurrences of the needle in
+/// `haystack[from..]`, matching ASCII letters in either case when
+/// `case_insensitive` is nonzero.
+///
+/// Writes `(start, len)` pairs of `u32` to `out_ptr`, with `start` counted
+/// from the beginning of the haystack, and stops once the buffer is full.
+/// When it comes back full, call again with `from` set to the end of the
+/// last pair to continue. An empty needle matches nothing.
+///
+/// Returns bytes written (`8` per match), or `-1` when `from` lies past the
+/// end of the haystack.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn find_all_matches(
+    haystack_ptr: *const u8,
+    haystack_len: usize,
+    needle_ptr: *const u8,
+    needle_len: usize,
+    case_insensitive: u32,
+    from: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    if from > haystack_len {
+        return -1;
+    }
+    let haystack = ffi::slice(haystack_ptr, haystack_len);
+    let needle = ffi::slice(needle_ptr, needle_len);
+    let out = ffi::slice_mut(out_ptr, out_len_bytes / 8 * 2);
+
+    let mut pairs = out.chunks_exact_mut(2);
+    let mut written = 0;
+    for_each_match(
+        haystack,
+        needle,
+        case_insensitive != 0,
+        from,
+        |start| match pairs.next() {
+            Some(pair) => {
+                pair[0] = start as u32;
+                pair[1] = needle.len() as u32;
+                written += 8;
+                true
+            }
+            None => false,
+        },
+    );
+    written as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(haystack: &[u8], needle: &[u8], fold: bool, from: usize, pairs: usize) -> Vec<u32> {
+        let mut out = vec![0u32; pairs * 2];
+        let written = unsafe {
+            find_all_matches(
+                haystack.as_ptr(),
+                haystack.len(),
+                needle.as_ptr(),
+                needle.len(),
+                fold as u32,
+                from,
+                out.as_mut_ptr(),
+                out.len() * 4,
+            )
+        };
+        assert!(written >= 0);
+        out.truncate(written as usize / 4);
+        out
+    }
+
+    #[test]
+    fn finds_non_overlapping_matches() {
+        assert_eq!(find(b"aaaa", b"aa", false, 0, 8), [0, 2, 2, 2]);
+        assert_eq!(find(b"abcabc", b"c", false, 0, 8), [2, 1, 5, 1]);
+        assert_eq!(find(b"abc", b"", false, 0, 8), []);
+        assert_eq!(find(b"ab", b"abc", false, 0, 8), []);
+        assert_eq!(find(b"", b"a", false, 0, 8), []);
+    }
+
+    #[test]
+    fn folds_ascii_case() {
+        let text = b"Error: error ERROR erroR";
+        assert_eq!(find(text, b"error", false, 0, 8), [7, 5]);
+        assert_eq!(find(text, b"error", true, 0, 8), [0, 5, 7, 5, 13, 5, 19, 5]);
+    }
+
+    #[test]
+    fn pages_through_a_long_document() {
+        // Matches straddle 16-byte block boundaries at varying offsets.
+        let text: Vec<u8> = (0..500)
+            .flat_map(|i| format!("line {i} needle;").into_bytes())
+            .collect();
+        let expected = find(&text, b"needle", false, 0, 1000);
+        assert_eq!(expected.len(), 1000);
+
+        let mut paged = Vec::new();
+        let mut from = 0;
+        loop {
+            let page = find(&text, b"needle", false, from, 7);
+            let Some(last) = page.chunks(2).last() else {
+                break;
+            };
+            from = (last[0] + last[1]) as usize;
+            paged.extend_from_slice(&page);
+        }
+        assert_eq!(paged, expected);
+    }
+
+    #[test]
+    fn rejects_start_past_end() {
+        let mut out = [0u32; 2];
+        let status = unsafe {
+            find_all_matches(
+                b"ab".as_ptr(),
+                2,
+                b"a".as_ptr(),
+                1,
+                0,
+                3,
+                out.as_mut_ptr(),
+                8,
+            )
+        };
+        assert_eq!(status, -1);
+        assert_eq!(find(b"ab", b"a", false, 2, 1), []);
+    }
+}
@@ -0,0 +1,194 @@
+//! Cross-correlation and autocorrelation of `f32` series.
+//!
+//! Each lag is one dot product over the overlapping part of the series,
+//! computed four lanes at a time with the vector kernels' helpers. The cost
+//! is `O(n * max_lag)`, which suits the lag windows used to align signals
+//! and find seasonal periods.
+
+use super::vector::dot;
+use crate::ffi;
+
+/// `sum(a[n + lag] * b[n])` over the `n` where both are in range.
+fn lagged_dot(a: &[f32], b: &[f32], lag: isize) -> f32 {
+    let (a, b) = if lag >= 0 {
+        (a.get(lag as usize..).unwrap_or_default(), b)
+    } else {
+        (a, b.get(lag.unsigned_abs()..).unwrap_or_default())
+    };
+    let len = a.len().min(b.len());
+    dot(&a[..len], &b[..len])
+}
+
+/// Cross-correlate the series at `a_ptr` and `b_ptr` over lags
+/// `-max_lag..=max_lag`, writing `2 * max_lag + 1` values: `out[max_lag + k]
+/// = sum(a[n + k] * b[n])`. A peak at lag `k` means `a` runs `k` samples
+/// behind `b`. The sums are not normalized; lags past the end of either
+/// series give `0`.
+///
+/// Returns bytes written, or `-1` for a partial value or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn xcorr_f32(
+    a_ptr: *const f32,
+    a_len_bytes: usize,
+    b_ptr: *const f32,
+    b_len_bytes: usize,
+    max_lag: usize,
+    out_ptr: *mut f32,
+    out_len_bytes: usize,
+) -> isize {
+    let Some(count) = max_lag
+        .checked_mul(2)
+        .and_then(|n| n.checked_add(1))
+        .filter(|&n| n <= isize::MAX as usize / 4)
+    else {
+        return -1;
+    };
+    if !a_len_bytes.is_multiple_of(4) || !b_len_bytes.is_multiple_of(4) || out_len_bytes / 4 < count
+    {
+        return -1;
+    }
+    let out_bytes = count * 4;
+    if ffi::aliased(
+        a_ptr as *const u8,
+        a_len_bytes,
+        out_ptr as *const u8,
+        out_bytes,
+    ) || ffi::aliased(
+        b_ptr as *const u8,
+        b_len_bytes,
+        out_ptr as *const u8,
+        out_bytes,
+    ) {
+        return ffi::ALIAS_ERROR;
+    }
+    let a = ffi::slice(a_ptr, a_len_bytes / 4);
+    let b = ffi::slice(b_ptr, b_len_bytes / 4);
+    let out = ffi::slice_mut(out_ptr, count);
+    for (i, slot) in out.iter_mut().enumerate() {
+        *slot = lagged_dot(a, b, i as isize - max_lag as isize);
+    }
+    out_bytes as isize
+}
+
+/// Autocorrelation of the series at `in_ptr` for lags `0..=max_lag`,
+/// writing `max_lag + 1` values: the autocovariance at each lag divided by
+/// the variance, so `out[0] == 1` and a seasonal period shows up as a peak
+/// at its lag. Lags past the end of the series give `0`; a constant series
+/// has no defined autocorrelation and gives `NaN` throughout.
+///
+/// Returns bytes written, or `-1` for a partial value or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn acf_f32(
+    in_ptr: *const f32,
+    in_len_bytes: usize,
+    max_lag: usize,
+    out_ptr: *mut f32,
+    out_len_bytes: usize,
+) -> isize {
+    let Some(count) = max_lag
+        .checked_add(1)
+        .filter(|&n| n <= isize::MAX as usize / 4)
+    else {
+        return -1;
+    };
+    if !in_len_bytes.is_multiple_of(4) || out_len_bytes / 4 < count {
+        return -1;
+    }
+    if ffi::aliased(
+        in_ptr as *const u8,
+        in_len_bytes,
+        out_ptr as *const u8,
+        count * 4,
+    ) {
+        return ffi::ALIAS_ERROR;
+    }
+    let values = ffi::slice(in_ptr, in_len_bytes / 4);
+    let mean = values.iter().map(|&v| v as f64).sum::<f64>() / values.len().max(1) as f64;
+    let centered: Vec<f32> = values.iter().map(|&v| (v as f64 - mean) as f32).collect();
+    let variance = dot(&centered, &centered);
+    let out = ffi::slice_mut(out_ptr, count);
+    for (lag, slot) in out.iter_mut().enumerate() {
+        *slot = if variance > 0.0 {
+            lagged_dot(&centered, &centered, lag as isize) / variance
+        } else {
+            f32::NAN
+        };
+    }
+    (count * 4) as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xcorr(a: &[f32], b: &[f32], max_lag: usize) -> Vec<f32> {
+        let mut out = vec![0f32; 2 * max_lag + 1];
+        let written = unsafe {
+            xcorr_f32(
+                a.as_ptr(),
+                a.len() * 4,
+                b.as_ptr(),
+                b.len() * 4,
+                max_lag,
+                out.as_mut_ptr(),
+                out.len() * 4,
+            )
+        };
+        assert_eq!(written, out.len() as isize * 4);
+        out
+    }
+
+    #[test]
+    fn cross_correlates_over_lags() {
+        // Matches numpy.correlate(a, b, "full") around the centre.
+        let a = [1.0f32, 2.0, 3.0];
+        let b = [0.0f32, 1.0, 0.5];
+        assert_eq!(xcorr(&a, &b, 2), [0.5, 2.0, 3.5, 3.0, 0.0]);
+        assert_eq!(xcorr(&a, &b, 4)[..2], [0.0, 0.0], "past the ends");
+
+        // `a` is `b` delayed by three samples: the peak sits at lag 3.
+        let b: Vec<f32> = (0..40).map(|i| ((i * 7) % 11) as f32 - 5.0).collect();
+        let mut a = vec![0f32; 3];
+        a.extend(&b[..37]);
+        let out = xcorr(&a, &b, 5);
+        let peak = (0..out.len()).max_by(|&i, &j| out[i].total_cmp(&out[j]));
+        assert_eq!(peak, Some(5 + 3));
+
+        let mut out = [0f32; 4];
+        let status = unsafe { xcorr_f32(a.as_ptr(), 12, b.as_ptr(), 12, 2, out.as_mut_ptr(), 16) };
+        assert_eq!(status, -1, "short output");
+        let status = unsafe {
+            xcorr_f32(
+                a.as_ptr(),
+                12,
+                b.as_ptr(),
+                12,
+                usize::MAX,
+                out.as_mut_ptr(),
+                16,
+            )
+        };
+        assert_eq!(status, -1, "lag overflow");
+    }
+
+    #[test]
+    fn autocorrelation_finds_the_period() {
+        let values: Vec<f32> = (0..48).map(|i| [1.0, 3.0, 2.0, -6.0][i % 4]).collect();
+        let mut out = [0f32; 9];
+        let written = unsafe { acf_f32(values.as_ptr(), 192, 8, out.as_mut_ptr(), 36) };
+        assert_eq!(written, 36);
+        assert_eq!(out[0], 1.0);
+        let peak = (1..9).max_by(|&i, &j| out[i].total_cmp(&out[j]));
+        assert_eq!(peak, Some(4));
+        assert!((out[4] - 44.0 / 48.0).abs() < 1e-6);
+
+        let constant = [2.0f32; 5];
+        let written = unsafe { acf_f32(constant.as_ptr(), 20, 8, out.as_mut_ptr(), 36) };
+        assert_eq!(written, 36);
+        assert!(out.iter().all(|v| v.is_nan()));
+        let status = unsafe { acf_f32(values.as_ptr(), 10, 1, out.as_mut_ptr(), 36) };
+        assert_eq!(status, -1, "partial value");
+    }
+}
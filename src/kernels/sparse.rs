@@ -0,0 +1,135 @@
+//! Sparse matrix kernels over CSR (compressed sparse row) arrays.
+//!
+//! A `rows x cols` matrix with `nnz` stored entries is three arrays:
+//! `indptr` (`rows + 1` ascending `u32`s, like the string-batch offsets),
+//! `indices` (`nnz` column numbers) and `values` (`nnz` `f32`s), row `r`
+//! owning entries `indptr[r]..indptr[r + 1]`. Kernels read the arrays in
+//! place, so a matrix allocated once in wasm memory can be scored against
+//! many vectors without copying it again.
+
+use super::vector::dot;
+use crate::ffi;
+
+/// Multiply the CSR matrix by the dense vector at `x_ptr`, writing one `f32`
+/// per row to `out_ptr`.
+///
+/// Each row gathers its `x` entries and then runs the shared SIMD dot
+/// product, so SIMD and scalar builds give the same sums.
+///
+/// Returns bytes written (`rows * 4`), or `-1` when `indptr` is not
+/// ascending or runs past the entries, `indices` and `values` differ in
+/// length, a column index is outside `x`, or the output is short.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn spmv_csr_f32(
+    indptr_ptr: *const u32,
+    indptr_len: usize,
+    indices_ptr: *const u32,
+    indices_len: usize,
+    values_ptr: *const f32,
+    values_len_bytes: usize,
+    x_ptr: *const f32,
+    x_len_bytes: usize,
+    out_ptr: *mut f32,
+    out_len_bytes: usize,
+) -> isize {
+    let rows = indptr_len.saturating_sub(1);
+    if values_len_bytes / 4 != indices_len || out_len_bytes / 4 < rows {
+        return -1;
+    }
+    if ffi::aliased(
+        x_ptr as *const u8,
+        x_len_bytes,
+        out_ptr as *const u8,
+        rows * 4,
+    ) {
+        return ffi::ALIAS_ERROR;
+    }
+    let indptr = ffi::slice(indptr_ptr, indptr_len);
+    let indices = ffi::slice(indices_ptr, indices_len);
+    let values = ffi::slice(values_ptr, indices_len);
+    let x = ffi::slice(x_ptr, x_len_bytes / 4);
+    if indices.iter().any(|&c| c as usize >= x.len()) {
+        return -1;
+    }
+    let mut gathered = Vec::new();
+    let mut sums = Vec::with_capacity(rows);
+    for w in indptr.windows(2) {
+        let Some(range) = indices.get(w[0] as usize..w[1] as usize) else {
+            return -1;
+        };
+        gathered.clear();
+        gathered.extend(range.iter().map(|&c| x[c as usize]));
+        sums.push(dot(&values[w[0] as usize..w[1] as usize], &gathered));
+    }
+    // Written only once every row has validated.
+    ffi::slice_mut(out_ptr, rows).copy_from_slice(&sums);
+    (rows * 4) as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spmv(
+        indptr: &[u32],
+        indices: &[u32],
+        values: &[f32],
+        x: &[f32],
+        out_len: usize,
+    ) -> (isize, Vec<f32>) {
+        let mut out = vec![f32::NAN; out_len];
+        let written = unsafe {
+            spmv_csr_f32(
+                indptr.as_ptr(),
+                indptr.len(),
+                indices.as_ptr(),
+                indices.len(),
+                values.as_ptr(),
+                values.len() * 4,
+                x.as_ptr(),
+                x.len() * 4,
+                out.as_mut_ptr(),
+                out.len() * 4,
+            )
+        };
+        (written, out)
+    }
+
+    #[test]
+    fn multiplies_rows_including_empty_ones() {
+        // [[1, 0, 2],
+        //  [0, 0, 0],
+        //  [0, 3, 0],
+        //  [4, 5, 6]]
+        let indptr = [0, 2, 2, 3, 6];
+        let indices = [0, 2, 1, 0, 1, 2];
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let (written, out) = spmv(&indptr, &indices, &values, &[1.0, 10.0, 100.0], 4);
+        assert_eq!(written, 16);
+        assert_eq!(out, [201.0, 0.0, 30.0, 654.0]);
+    }
+
+    #[test]
+    fn rejects_malformed_matrices() {
+        let x = [1.0f32; 2];
+        assert_eq!(
+            spmv(&[0, 2, 1], &[0, 1], &[1.0, 1.0], &x, 2).0,
+            -1,
+            "descending indptr"
+        );
+        assert_eq!(
+            spmv(&[0, 3], &[0, 1], &[1.0, 1.0], &x, 1).0,
+            -1,
+            "indptr past nnz"
+        );
+        assert_eq!(spmv(&[0, 1], &[2], &[1.0], &x, 1).0, -1, "column outside x");
+        assert_eq!(spmv(&[0, 2], &[0, 1], &[1.0], &x, 1).0, -1, "nnz mismatch");
+        assert_eq!(
+            spmv(&[0, 1, 2], &[0, 1], &[1.0, 1.0], &x, 1).0,
+            -1,
+            "short output"
+        );
+        assert_eq!(spmv(&[], &[], &[], &x, 0).0, 0);
+    }
+}
@@ -0,0 +1,323 @@
+//! Space-filling curve indices for sorting 2D points by locality.
+//!
+//! Both curves map `u32` grid coordinates to a `u64` key such that sorting
+//! by key keeps nearby points close together, which is what tiling and
+//! rendering passes want. Z-order (Morton) keys just interleave the bits of
+//! `x` and `y`; Hilbert keys cost a little more but never jump across the
+//! grid between neighboring keys. Scale float coordinates to the `u32` grid
+//! first; a coarser grid works too, with the same ordering.
+
+use crate::ffi;
+
+/// Spread the 32 bits of each lane over the even bits of a `u64`.
+#[inline(always)]
+pub(crate) fn spread2(v: [u32; 2]) -> [u64; 2] {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    {
+        use core::arch::wasm32::*;
+        let mut x = u64x2(v[0] as u64, v[1] as u64);
+        for (shift, mask) in SPREAD_STEPS {
+            x = v128_and(v128_or(x, u64x2_shl(x, shift)), u64x2_splat(mask));
+        }
+        [u64x2_extract_lane::<0>(x), u64x2_extract_lane::<1>(x)]
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        v.map(|x| {
+            SPREAD_STEPS
+                .iter()
+                .fold(x as u64, |x, &(shift, mask)| (x | (x << shift)) & mask)
+        })
+    }
+}
+
+const SPREAD_STEPS: [(u32, u64); 5] = [
+    (16, 0x0000_FFFF_0000_FFFF),
+    (8, 0x00FF_00FF_00FF_00FF),
+    (4, 0x0F0F_0F0F_0F0F_0F0F),
+    (2, 0x3333_3333_3333_3333),
+    (1, 0x5555_5555_5555_5555),
+];
+
+/// Morton keys for a pair of points: bit `i` of `x` lands on bit `2i`, bit
+/// `i` of `y` on bit `2i + 1`.
+fn zorder2(x: [u32; 2], y: [u32; 2]) -> [u64; 2] {
+    let (x, y) = (spread2(x), spread2(y));
+    [x[0] | (y[0] << 1), x[1] | (y[1] << 1)]
+}
+
+/// Inverse of `spread2` for one key: gather the even bits into a `u32`.
+fn compact(key: u64) -> u32 {
+    let mut x = key & SPREAD_STEPS[4].1;
+    // Undo each spread step, finest first: its shift pulls pairs back
+    // together and the previous step's mask keeps them.
+    for i in (0..SPREAD_STEPS.len()).rev() {
+        let mask = if i == 0 {
+            u32::MAX as u64
+        } else {
+            SPREAD_STEPS[i - 1].1
+        };
+        x = (x | (x >> SPREAD_STEPS[i].0)) & mask;
+    }
+    x as u32
+}
+
+/// Hilbert keys for a pair of points on the `2^32 x 2^32` grid.
+///
+/// Rather than walking the curve one level at a time, this computes every
+/// level's rotation state at once with a parallel prefix scan over the bits
+/// (after rawrunprotected's `hilbert_curves`), leaving two words whose
+/// interleaving is the key.
+fn hilbert2(x: [u32; 2], y: [u32; 2]) -> [u64; 2] {
+    let mut low = [0u32; 2];
+    let mut high = [0u32; 2];
+    for i in 0..2 {
+        let (x, y) = (x[i], y[i]);
+        let (mut a, mut b, mut c, mut d);
+        {
+            let a0 = x ^ y;
+            let b0 = !a0;
+            let c0 = !(x | y);
+            let d0 = x & !y;
+            a = a0 | (b0 >> 1);
+            b = (a0 >> 1) ^ a0;
+            c = ((c0 >> 1) ^ (b0 & (d0 >> 1))) ^ c0;
+            d = ((a0 & (c0 >> 1)) ^ (d0 >> 1)) ^ d0;
+        }
+        for shift in [2, 4, 8] {
+            let (a0, b0, c0, d0) = (a, b, c, d);
+            a = (a0 & (a0 >> shift)) ^ (b0 & (b0 >> shift));
+            b = (a0 & (b0 >> shift)) ^ (b0 & ((a0 ^ b0) >> shift));
+            c ^= (a0 & (c0 >> shift)) ^ (b0 & (d0 >> shift));
+            d ^= (b0 & (c0 >> shift)) ^ ((a0 ^ b0) & (d0 >> shift));
+        }
+        let (c0, d0) = (c, d);
+        c ^= (a & (c0 >> 16)) ^ (b & (d0 >> 16));
+        d ^= (b & (c0 >> 16)) ^ ((a ^ b) & (d0 >> 16));
+
+        let a = c ^ (c >> 1);
+        let b = d ^ (d >> 1);
+        low[i] = x ^ y;
+        high[i] = b | !(low[i] | a);
+    }
+    let (low, high) = (spread2(low), spread2(high));
+    [(high[0] << 1) | low[0], (high[1] << 1) | low[1]]
+}
+
+/// Shared driver: `xs` and `ys` hold the same number of `u32` coordinates,
+/// and one `u64` key per point goes to `out_ptr`.
+unsafe fn encode_2d(
+    xs_ptr: *const u32,
+    xs_len_bytes: usize,
+    ys_ptr: *const u32,
+    out_ptr: *mut u64,
+    out_len_bytes: usize,
+    key: impl Fn([u32; 2], [u32; 2]) -> [u64; 2],
+) -> isize {
+    let n = xs_len_bytes / 4;
+    if !xs_len_bytes.is_multiple_of(4) || out_len_bytes / 8 < n {
+        return -1;
+    }
+    if ffi::aliased(
+        xs_ptr as *const u8,
+        xs_len_bytes,
+        out_ptr as *const u8,
+        n * 8,
+    ) || ffi::aliased(
+        ys_ptr as *const u8,
+        xs_len_bytes,
+        out_ptr as *const u8,
+        n * 8,
+    ) {
+        return ffi::ALIAS_ERROR;
+    }
+    let xs = ffi::slice(xs_ptr, n);
+    let ys = ffi::slice(ys_ptr, n);
+    let out = ffi::slice_mut(out_ptr, n);
+    for ((x, y), dst) in xs.chunks(2).zip(ys.chunks(2)).zip(out.chunks_mut(2)) {
+        let (mut px, mut py) = ([0u32; 2], [0u32; 2]);
+        px[..x.len()].copy_from_slice(x);
+        py[..y.len()].copy_from_slice(y);
+        dst.copy_from_slice(&key(px, py)[..x.len()]);
+    }
+    (n * 8) as isize
+}
+
+/// Z-order (Morton) key of each point `(xs[i], ys[i])`, where `ys_ptr` holds
+/// as many values as `xs_ptr`. Returns bytes written (`8` per point).
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn zorder_encode_2d(
+    xs_ptr: *const u32,
+    xs_len_bytes: usize,
+    ys_ptr: *const u32,
+    out_ptr: *mut u64,
+    out_len_bytes: usize,
+) -> isize {
+    encode_2d(
+        xs_ptr,
+        xs_len_bytes,
+        ys_ptr,
+        out_ptr,
+        out_len_bytes,
+        zorder2,
+    )
+}
+
+/// Split each Z-order key back into its point, writing `x, y` pairs of
+/// `u32`. Returns bytes written (`8` per key), or `-1` for a partial key or
+/// a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn zorder_decode_2d(
+    keys_ptr: *const u64,
+    keys_len_bytes: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    if !keys_len_bytes.is_multiple_of(8) || out_len_bytes < keys_len_bytes {
+        return -1;
+    }
+    if ffi::aliased(
+        keys_ptr as *const u8,
+        keys_len_bytes,
+        out_ptr as *const u8,
+        keys_len_bytes,
+    ) {
+        return ffi::ALIAS_ERROR;
+    }
+    let n = keys_len_bytes / 8;
+    let keys = ffi::slice(keys_ptr, n);
+    let out = ffi::slice_mut(out_ptr, n * 2);
+    for (&key, point) in keys.iter().zip(out.chunks_exact_mut(2)) {
+        point[0] = compact(key);
+        point[1] = compact(key >> 1);
+    }
+    keys_len_bytes as isize
+}
+
+/// Hilbert curve key of each point `(xs[i], ys[i])` on the `2^32 x 2^32`
+/// grid, where `ys_ptr` holds as many values as `xs_ptr`. Returns bytes
+/// written (`8` per point).
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn hilbert_encode_2d(
+    xs_ptr: *const u32,
+    xs_len_bytes: usize,
+    ys_ptr: *const u32,
+    out_ptr: *mut u64,
+    out_len_bytes: usize,
+) -> isize {
+    encode_2d(
+        xs_ptr,
+        xs_len_bytes,
+        ys_ptr,
+        out_ptr,
+        out_len_bytes,
+        hilbert2,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Textbook level-by-level Hilbert index on a `2^bits` grid.
+    fn hilbert_reference(bits: u32, x: u32, y: u32) -> u64 {
+        let n = 1u64 << bits;
+        let (mut x, mut y) = (x as u64, y as u64);
+        let mut d = 0;
+        let mut s = n / 2;
+        while s > 0 {
+            let rx = (x & s > 0) as u64;
+            let ry = (y & s > 0) as u64;
+            d += s * s * ((3 * rx) ^ ry);
+            if ry == 0 {
+                if rx == 1 {
+                    x = n - 1 - x;
+                    y = n - 1 - y;
+                }
+                std::mem::swap(&mut x, &mut y);
+            }
+            s /= 2;
+        }
+        d
+    }
+
+    fn encode(
+        f: unsafe extern "C" fn(*const u32, usize, *const u32, *mut u64, usize) -> isize,
+        xs: &[u32],
+        ys: &[u32],
+    ) -> Vec<u64> {
+        let mut out = vec![u64::MAX; xs.len()];
+        let written = unsafe {
+            f(
+                xs.as_ptr(),
+                xs.len() * 4,
+                ys.as_ptr(),
+                out.as_mut_ptr(),
+                out.len() * 8,
+            )
+        };
+        assert_eq!(written, xs.len() as isize * 8);
+        out
+    }
+
+    #[test]
+    fn zorder_interleaves_bits() {
+        let keys = encode(zorder_encode_2d, &[0b11, 0, u32::MAX], &[0b01, 1, u32::MAX]);
+        assert_eq!(keys, [0b0111, 0b10, u64::MAX]);
+
+        let keys = encode(
+            zorder_encode_2d,
+            &[7, 0x8000_0001, 12345],
+            &[0, u32::MAX, 678],
+        );
+        let mut points = [0u32; 6];
+        let written = unsafe { zorder_decode_2d(keys.as_ptr(), 24, points.as_mut_ptr(), 24) };
+        assert_eq!(written, 24);
+        assert_eq!(points, [7, 0, 0x8000_0001, u32::MAX, 12345, 678]);
+    }
+
+    #[test]
+    fn hilbert_matches_reference() {
+        let mut seed = 0x2545_F491_4F6C_DD1Du64;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as u32
+        };
+        let (xs, ys): (Vec<u32>, Vec<u32>) = (0..1001).map(|_| (next(), next())).unzip();
+        let keys = encode(hilbert_encode_2d, &xs, &ys);
+        for ((&x, &y), &key) in xs.iter().zip(&ys).zip(&keys) {
+            assert_eq!(key, hilbert_reference(32, x, y), "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn hilbert_steps_between_neighbors() {
+        // Walking the keys of a 16 x 16 corner in order moves one cell at a
+        // time.
+        let (xs, ys): (Vec<u32>, Vec<u32>) = (0..256).map(|i| (i % 16, i / 16)).unzip();
+        let keys = encode(hilbert_encode_2d, &xs, &ys);
+        let mut order: Vec<usize> = (0..256).collect();
+        order.sort_by_key(|&i| keys[i]);
+        assert_eq!(keys[order[255]], 255);
+        for w in order.windows(2) {
+            let step = xs[w[0]].abs_diff(xs[w[1]]) + ys[w[0]].abs_diff(ys[w[1]]);
+            assert_eq!(step, 1);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let xs = [1u32, 2];
+        let mut out = [0u64; 1];
+        let status = unsafe { zorder_encode_2d(xs.as_ptr(), 8, xs.as_ptr(), out.as_mut_ptr(), 8) };
+        assert_eq!(status, -1, "short output");
+        let status = unsafe { hilbert_encode_2d(xs.as_ptr(), 6, xs.as_ptr(), out.as_mut_ptr(), 8) };
+        assert_eq!(status, -1, "partial coordinate");
+    }
+}
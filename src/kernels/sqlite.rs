@@ -0,0 +1,496 @@
+//! Read-only scanning of SQLite database files.
+//!
+//! Given the raw bytes of a `.sqlite` file, `sqlite_table_cells` walks one
+//! table B-tree and lists where each row's record payload lives in the
+//! buffer, `sqlite_payload` gathers a payload that spills onto overflow
+//! pages, and `sqlite_record` splits a record into column spans. Together
+//! they let a page read rows out of a user's database file without a full
+//! SQLite build. Page 1 holds the `sqlite_schema` table, whose rows give
+//! every other table's root page.
+//!
+//! Pages are addressed as in the file format: page `n` (from 1) starts at
+//! byte `(n - 1) * page_size`, and multi-byte integers are big-endian.
+
+use crate::ffi;
+
+/// `u32` words per row in `sqlite_table_cells` output.
+const CELL_WORDS: usize = 6;
+
+const INTERIOR_TABLE: u8 = 0x05;
+const LEAF_TABLE: u8 = 0x0d;
+
+fn be(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |v, &b| (v << 8) | b as u32)
+}
+
+/// SQLite varint at `*pos` (1 to 9 bytes, the ninth contributing all eight
+/// bits), advancing past it.
+fn varint(buf: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for i in 0..9 {
+        let b = *buf.get(*pos)?;
+        *pos += 1;
+        if i == 8 {
+            return Some((value << 8) | b as u64);
+        }
+        value = (value << 7) | (b & 0x7f) as u64;
+        if b & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+struct Db<'a> {
+    bytes: &'a [u8],
+    page_size: usize,
+    /// Page size minus the reserved bytes at the end of each page.
+    usable: usize,
+}
+
+impl<'a> Db<'a> {
+    fn open(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.get(..16)? != b"SQLite format 3\0" {
+            return None;
+        }
+        let page_size = match be(bytes.get(16..18)?) {
+            1 => 65536,
+            n if n >= 512 && n.is_power_of_two() => n as usize,
+            _ => return None,
+        };
+        let usable = page_size.checked_sub(*bytes.get(20)? as usize)?;
+        // The format requires at least 480 usable bytes.
+        (usable >= 480).then_some(Db {
+            bytes,
+            page_size,
+            usable,
+        })
+    }
+
+    fn page_count(&self) -> usize {
+        self.bytes.len() / self.page_size
+    }
+
+    /// Byte range of page `n`.
+    fn page(&self, n: u32) -> Option<std::ops::Range<usize>> {
+        let index = (n as usize).checked_sub(1)?;
+        (index < self.page_count()).then(|| index * self.page_size..(index + 1) * self.page_size)
+    }
+
+    /// Local bytes of a table-leaf payload of `total` bytes; the rest goes
+    /// to overflow pages.
+    fn local_len(&self, total: u64) -> usize {
+        let u = self.usable as u64;
+        let max_local = u - 35;
+        if total <= max_local {
+            return total as usize;
+        }
+        let min_local = (u - 12) * 32 / 255 - 23;
+        let k = min_local + (total - min_local) % (u - 4);
+        (if k <= max_local { k } else { min_local }) as usize
+    }
+
+    /// Append one `CELL_WORDS` entry per row of the table B-tree rooted at
+    /// `root`, in rowid order.
+    fn table_cells(&self, root: u32, out: &mut Vec<u32>) -> Option<()> {
+        let mut visited = vec![false; self.page_count() + 1];
+        // Pages still to visit, next on top, so children are pushed in
+        // reverse.
+        let mut stack = vec![root];
+        while let Some(n) = stack.pop() {
+            // A page reached twice means a corrupt (cyclic) tree.
+            if std::mem::replace(visited.get_mut(n as usize)?, true) {
+                return None;
+            }
+            let range = self.page(n)?;
+            let page = &self.bytes[range.clone()];
+            // Page 1 starts with the 100-byte file header.
+            let header = if n == 1 { 100 } else { 0 };
+            let kind = *page.get(header)?;
+            let cells = be(page.get(header + 3..header + 5)?) as usize;
+            let pointers = header + if kind == INTERIOR_TABLE { 12 } else { 8 };
+            let pointer_bytes = page.get(pointers..pointers + 2 * cells)?;
+            let offsets = pointer_bytes.chunks_exact(2).map(|p| be(p) as usize);
+            match kind {
+                INTERIOR_TABLE => {
+                    stack.push(be(page.get(header + 8..header + 12)?));
+                    let mut children = Vec::with_capacity(cells);
+                    for offset in offsets {
+                        children.push(be(page.get(offset..offset + 4)?));
+                    }
+                    stack.extend(children.iter().rev());
+                }
+                LEAF_TABLE => {
+                    for mut pos in offsets {
+                        let total = varint(page, &mut pos)?;
+                        let rowid = varint(page, &mut pos)?;
+                        let local = self.local_len(total);
+                        let overflow = if (local as u64) < total {
+                            be(page.get(pos + local..pos + local + 4)?)
+                        } else if pos + local > page.len() {
+                            return None;
+                        } else {
+                            0
+                        };
+                        let entry: [u32; CELL_WORDS] = [
+                            rowid as u32,
+                            (rowid >> 32) as u32,
+                            (range.start + pos) as u32,
+                            local as u32,
+                            u32::try_from(total).ok()?,
+                            overflow,
+                        ];
+                        out.extend(entry);
+                    }
+                }
+                _ => return None,
+            }
+        }
+        Some(())
+    }
+
+    /// Copy a payload's local bytes and then its overflow chain into `out`,
+    /// which holds exactly the payload's total size.
+    fn payload(&self, start: usize, local: usize, mut overflow: u32, out: &mut [u8]) -> Option<()> {
+        out.get_mut(..local)?
+            .copy_from_slice(self.bytes.get(start..start + local)?);
+        let mut filled = local;
+        // Each overflow page holds at least one byte, so a chain longer than
+        // the file has a cycle.
+        for _ in 0..self.page_count() {
+            if filled == out.len() {
+                return (overflow == 0).then_some(());
+            }
+            let page = &self.bytes[self.page(overflow)?];
+            let n = (out.len() - filled).min(self.usable - 4);
+            out[filled..filled + n].copy_from_slice(&page[4..4 + n]);
+            filled += n;
+            overflow = be(&page[..4]);
+        }
+        (filled == out.len() && overflow == 0).then_some(())
+    }
+}
+
+/// List the rows of the table B-tree whose root is page `root_page`, in
+/// rowid order, as six `u32`s each: `[rowid_lo, rowid_hi, start, local_len,
+/// total_len, overflow_page]`. The record payload's first `local_len` bytes
+/// sit at `db[start..]`; when `total_len` is larger, pass the entry to
+/// `sqlite_payload` to gather the rest from the chain starting at
+/// `overflow_page` (`0` when there is none).
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes the list needs. Otherwise returns bytes written, or `-1`
+/// for a buffer that is not a SQLite database, a root that is not a table
+/// B-tree, a corrupt tree, or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn sqlite_table_cells(
+    db_ptr: *const u8,
+    db_len: usize,
+    root_page: u32,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let bytes = ffi::slice(db_ptr, db_len);
+    let mut cells = Vec::new();
+    if u32::try_from(db_len).is_err()
+        || Db::open(bytes)
+            .and_then(|db| db.table_cells(root_page, &mut cells))
+            .is_none()
+    {
+        return -1;
+    }
+    let needed = cells.len() * 4;
+    if out_len_bytes == 0 {
+        return needed as isize;
+    }
+    if out_len_bytes < needed {
+        return -1;
+    }
+    if ffi::aliased(db_ptr, db_len, out_ptr as *const u8, needed) {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(out_ptr, cells.len()).copy_from_slice(&cells);
+    needed as isize
+}
+
+/// Gather a complete record payload described by one `sqlite_table_cells`
+/// entry (`start`, `local_len`, `total_len`, `overflow_page`) into `out_ptr`.
+///
+/// Returns bytes written (`total_len`), or `-1` for a bad database, a
+/// payload outside the buffer, a broken overflow chain or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn sqlite_payload(
+    db_ptr: *const u8,
+    db_len: usize,
+    start: u32,
+    local_len: u32,
+    total_len: u32,
+    overflow_page: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let total = total_len as usize;
+    if out_len < total || local_len > total_len {
+        return -1;
+    }
+    if ffi::aliased(db_ptr, db_len, out_ptr, total) {
+        return ffi::ALIAS_ERROR;
+    }
+    let Some(db) = Db::open(ffi::slice(db_ptr, db_len)) else {
+        return -1;
+    };
+    // Assembled in scratch so a broken chain leaves the output untouched.
+    let mut payload = vec![0u8; total];
+    if db
+        .payload(
+            start as usize,
+            local_len as usize,
+            overflow_page,
+            &mut payload,
+        )
+        .is_none()
+    {
+        return -1;
+    }
+    ffi::slice_mut(out_ptr, total).copy_from_slice(&payload);
+    total as isize
+}
+
+/// Content bytes of a column with serial type `t`, or `None` for the
+/// reserved types 10 and 11.
+fn serial_len(t: u64) -> Option<u64> {
+    Some(match t {
+        0 | 8 | 9 => 0,
+        1..=4 => t,
+        5 => 6,
+        6 | 7 => 8,
+        10 | 11 => return None,
+        _ => (t - 12) / 2,
+    })
+}
+
+/// Split the record payload at `payload_ptr` into columns, three `u32`s
+/// each: `[serial_type, start, len]`, with `start` relative to the payload.
+/// Serial types follow the file format: `0` NULL, `1..=6` big-endian
+/// integers of 1, 2, 3, 4, 6 and 8 bytes, `7` a big-endian `f64`, `8` and
+/// `9` the constants 0 and 1, even types from 12 BLOBs and odd types from 13
+/// text.
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for a
+/// malformed record or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn sqlite_record(
+    payload_ptr: *const u8,
+    payload_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let payload = ffi::slice(payload_ptr, payload_len);
+    let mut pos = 0;
+    let Some(header_len) = varint(payload, &mut pos) else {
+        return -1;
+    };
+    let Some(header) = usize::try_from(header_len)
+        .ok()
+        .and_then(|n| payload.get(..n))
+    else {
+        return -1;
+    };
+    let mut columns = Vec::new();
+    let mut body = header.len() as u64;
+    while pos < header.len() {
+        let Some((t, len)) = varint(header, &mut pos).and_then(|t| Some((t, serial_len(t)?)))
+        else {
+            return -1;
+        };
+        if body + len > payload_len as u64 || t > u32::MAX as u64 {
+            return -1;
+        }
+        columns.extend([t as u32, body as u32, len as u32]);
+        body += len;
+    }
+    let needed = columns.len() * 4;
+    if out_len_bytes == 0 {
+        return needed as isize;
+    }
+    if out_len_bytes < needed {
+        return -1;
+    }
+    if ffi::aliased(payload_ptr, payload_len, out_ptr as *const u8, needed) {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(out_ptr, columns.len()).copy_from_slice(&columns);
+    needed as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: usize = 512;
+
+    /// A record of one text column.
+    fn text_record(text: &[u8]) -> Vec<u8> {
+        let t = 13 + 2 * text.len();
+        assert!(t < 128 * 128);
+        let mut record = if t < 128 {
+            vec![2, t as u8]
+        } else {
+            vec![3, 0x80 | (t >> 7) as u8, (t & 0x7f) as u8]
+        };
+        record.extend_from_slice(text);
+        record
+    }
+
+    /// Write a table-leaf cell at the end of `page`, below `*top`, and
+    /// return its offset. Payloads must fit locally.
+    fn leaf_cell(page: &mut [u8], top: &mut usize, rowid: u8, record: &[u8]) -> u16 {
+        let mut cell = vec![];
+        let len = record.len();
+        if len < 128 {
+            cell.push(len as u8);
+        } else {
+            cell.extend([0x80 | (len >> 7) as u8, (len & 0x7f) as u8]);
+        }
+        cell.push(rowid);
+        cell.extend_from_slice(record);
+        *top -= cell.len();
+        page[*top..*top + cell.len()].copy_from_slice(&cell);
+        *top as u16
+    }
+
+    /// Pages: 1 empty schema leaf, 2 interior root (rows <= 2 on page 3,
+    /// the rest on page 4), 3 leaf with rows 1 and 2, 4 leaf with row 3
+    /// whose 1000-byte text overflows onto pages 5 and 6.
+    fn database() -> (Vec<u8>, Vec<u8>) {
+        let mut db = vec![0u8; 5 * PAGE];
+        db[..16].copy_from_slice(b"SQLite format 3\0");
+        db[16..18].copy_from_slice(&(PAGE as u16).to_be_bytes());
+        db[100] = LEAF_TABLE;
+
+        let p2 = PAGE;
+        db[p2] = INTERIOR_TABLE;
+        db[p2 + 3..p2 + 5].copy_from_slice(&1u16.to_be_bytes());
+        db[p2 + 8..p2 + 12].copy_from_slice(&4u32.to_be_bytes());
+        db[p2 + 12..p2 + 14].copy_from_slice(&500u16.to_be_bytes());
+        db[p2 + 500..p2 + 505].copy_from_slice(&[0, 0, 0, 3, 2]);
+
+        let p3 = 2 * PAGE;
+        let page = &mut db[p3..p3 + PAGE];
+        page[0] = LEAF_TABLE;
+        page[3..5].copy_from_slice(&2u16.to_be_bytes());
+        let mut top = PAGE;
+        let a = leaf_cell(page, &mut top, 1, &text_record(b"one"));
+        let b = leaf_cell(page, &mut top, 2, &text_record(b"two"));
+        page[8..10].copy_from_slice(&a.to_be_bytes());
+        page[10..12].copy_from_slice(&b.to_be_bytes());
+
+        // Row 3: a 1003-byte payload. With 512 usable bytes only 39 bytes
+        // stay local, and the other 964 fill overflow page 5 (508 bytes)
+        // and part of page 6.
+        let long: Vec<u8> = (0..1000).map(|i| b'a' + (i % 26) as u8).collect();
+        let record = text_record(&long);
+        db.resize(6 * PAGE, 0);
+        let p4 = 3 * PAGE;
+        db[p4] = LEAF_TABLE;
+        db[p4 + 3..p4 + 5].copy_from_slice(&1u16.to_be_bytes());
+        db[p4 + 8..p4 + 10].copy_from_slice(&12u16.to_be_bytes());
+        let cell = p4 + 12;
+        db[cell..cell + 3].copy_from_slice(&[0x87, 0x6b, 3]);
+        db[cell + 3..cell + 42].copy_from_slice(&record[..39]);
+        db[cell + 42..cell + 46].copy_from_slice(&5u32.to_be_bytes());
+        let p5 = 4 * PAGE;
+        db[p5..p5 + 4].copy_from_slice(&6u32.to_be_bytes());
+        db[p5 + 4..p5 + PAGE].copy_from_slice(&record[39..547]);
+        let p6 = 5 * PAGE;
+        db[p6 + 4..p6 + 460].copy_from_slice(&record[547..]);
+        (db, record)
+    }
+
+    fn cells(db: &[u8], root: u32) -> Option<Vec<u32>> {
+        let needed =
+            unsafe { sqlite_table_cells(db.as_ptr(), db.len(), root, std::ptr::null_mut(), 0) };
+        if needed < 0 {
+            return None;
+        }
+        let mut out = vec![0u32; needed as usize / 4];
+        let written = unsafe {
+            sqlite_table_cells(
+                db.as_ptr(),
+                db.len(),
+                root,
+                out.as_mut_ptr(),
+                needed as usize,
+            )
+        };
+        assert_eq!(written, needed);
+        Some(out)
+    }
+
+    #[test]
+    fn lists_rows_across_pages_in_rowid_order() {
+        let (db, record) = database();
+        let out = cells(&db, 2).unwrap();
+        assert_eq!(out.len(), 3 * CELL_WORDS);
+        let rows: Vec<&[u32]> = out.chunks(CELL_WORDS).collect();
+        assert_eq!(rows.iter().map(|r| r[0]).collect::<Vec<_>>(), [1, 2, 3]);
+        let first = rows[0];
+        assert_eq!(&first[3..], [5, 5, 0]);
+        let start = first[2] as usize;
+        assert_eq!(&db[start..start + 5], &text_record(b"one")[..]);
+        assert_eq!(&rows[2][2..], [(3 * PAGE + 15) as u32, 39, 1003, 5]);
+
+        let mut payload = vec![0u8; 1003];
+        let row = rows[2];
+        let written = unsafe {
+            sqlite_payload(
+                db.as_ptr(),
+                db.len(),
+                row[2],
+                row[3],
+                row[4],
+                row[5],
+                payload.as_mut_ptr(),
+                payload.len(),
+            )
+        };
+        assert_eq!(written, 1003);
+        assert_eq!(payload, record);
+
+        let mut columns = [0u32; 3];
+        let written =
+            unsafe { sqlite_record(payload.as_ptr(), payload.len(), columns.as_mut_ptr(), 12) };
+        assert_eq!(written, 12);
+        assert_eq!(columns, [2013, 3, 1000]);
+
+        assert_eq!(cells(&db, 1).unwrap(), [], "empty schema table");
+    }
+
+    #[test]
+    fn rejects_corrupt_files() {
+        let (mut db, _) = database();
+        assert_eq!(cells(&db[..PAGE], 2), None, "root past the end");
+        assert_eq!(cells(&db, 5), None, "overflow page is not a B-tree page");
+        // Point the root's right child back at the root.
+        db[PAGE + 8..PAGE + 12].copy_from_slice(&2u32.to_be_bytes());
+        assert_eq!(cells(&db, 2), None, "cycle");
+        db[0] = b's';
+        assert_eq!(cells(&db, 1), None, "bad magic");
+    }
+
+    #[test]
+    fn splits_records_by_serial_type() {
+        // NULL, int8 -3, constant 1, f64 1.5, blob [0xab]
+        let record = [6, 0, 1, 9, 7, 14, 0xfd, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0, 0xab];
+        let mut out = vec![0u32; 15];
+        let written = unsafe { sqlite_record(record.as_ptr(), record.len(), out.as_mut_ptr(), 60) };
+        assert_eq!(written, 60);
+        assert_eq!(out, [0, 6, 0, 1, 6, 1, 9, 7, 0, 7, 7, 8, 14, 15, 1]);
+        let status = unsafe { sqlite_record(record.as_ptr(), 15, out.as_mut_ptr(), 60) };
+        assert_eq!(status, -1, "blob past the end");
+    }
+}
@@ -0,0 +1,764 @@
+//! Date and time kernels over epoch milliseconds.
+//!
+//! Everything here is proleptic Gregorian and timezone-free: explicit UTC
+//! offsets in the input are applied, and values without one are read as UTC.
+//! Unlike `Date.parse`, the result never depends on the host's locale or
+//! local zone.
+
+use super::spans;
+use crate::error::{self, ErrorCode};
+use crate::ffi;
+
+/// `YYYY-MM-DD[T| ]HH:MM[:SS[.fff]][Z|±HH[:MM]]`.
+pub const DATE_ISO8601: u32 = 1;
+/// A bare `YYYY-MM-DD`, read as midnight UTC.
+pub const DATE_ONLY: u32 = 2;
+/// `[Tue, ]1 Jul 2003 10:52[:37] +0200`, including the obsolete named zones.
+pub const DATE_RFC2822: u32 = 4;
+
+pub(crate) const MS_PER_HOUR: i64 = 3_600_000;
+pub(crate) const MS_PER_DAY: i64 = 24 * MS_PER_HOUR;
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+pub(crate) fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (m as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Proleptic Gregorian `(year, month, day)` for days since 1970-01-01.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (yoe + era * 400 + (m <= 2) as i64, m, d)
+}
+
+fn days_in_month(y: i64, m: u32) -> u32 {
+    match m {
+        2 if y % 4 == 0 && (y % 100 != 0 || y % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+struct Cursor<'a> {
+    s: &'a [u8],
+    i: usize,
+}
+
+impl Cursor<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.i).copied()
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        let hit = self.peek() == Some(b);
+        self.i += hit as usize;
+        hit
+    }
+
+    /// Between `min` and `max` decimal digits.
+    fn digits(&mut self, min: usize, max: usize) -> Option<u32> {
+        let start = self.i;
+        let mut n = 0u32;
+        while self.i - start < max {
+            match self.peek() {
+                Some(b @ b'0'..=b'9') => n = n * 10 + (b - b'0') as u32,
+                _ => break,
+            }
+            self.i += 1;
+        }
+        (self.i - start >= min).then_some(n)
+    }
+
+    fn skip_spaces(&mut self) -> usize {
+        let start = self.i;
+        while self.peek() == Some(b' ') {
+            self.i += 1;
+        }
+        self.i - start
+    }
+
+    fn word(&mut self) -> &[u8] {
+        let start = self.i;
+        while self.peek().is_some_and(|b| b.is_ascii_alphabetic()) {
+            self.i += 1;
+        }
+        &self.s[start..self.i]
+    }
+
+    fn done(&self) -> bool {
+        self.i == self.s.len()
+    }
+}
+
+/// Epoch milliseconds for a validated civil date and time.
+fn to_millis(y: i64, mo: u32, d: u32, h: u32, mi: u32, s: u32, ms: u32) -> Option<i64> {
+    if !(1..=12).contains(&mo) || d == 0 || d > days_in_month(y, mo) || h > 23 || mi > 59 || s > 59
+    {
+        return None;
+    }
+    let secs = (h * 3600 + mi * 60 + s) as i64;
+    Some(days_from_civil(y, mo, d) * MS_PER_DAY + secs * 1000 + ms as i64)
+}
+
+/// `±HH`, `±HH:MM` or `±HHMM`, as minutes east of UTC.
+fn numeric_zone(c: &mut Cursor) -> Option<i64> {
+    let sign = match c.peek()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    c.i += 1;
+    let h = c.digits(2, 2)?;
+    let m = if c.eat(b':') {
+        c.digits(2, 2)?
+    } else {
+        c.digits(2, 2).unwrap_or(0)
+    };
+    (h <= 23 && m <= 59).then_some(sign * (h * 60 + m) as i64)
+}
+
+fn parse_iso(c: &mut Cursor, formats: u32) -> Option<i64> {
+    let y = c.digits(4, 4)? as i64;
+    let mo = (c.eat(b'-')).then(|| c.digits(2, 2))??;
+    let d = (c.eat(b'-')).then(|| c.digits(2, 2))??;
+    if c.done() {
+        return (formats & DATE_ONLY != 0).then(|| to_millis(y, mo, d, 0, 0, 0, 0))?;
+    }
+    if formats & DATE_ISO8601 == 0 || !(c.eat(b'T') || c.eat(b't') || c.eat(b' ')) {
+        return None;
+    }
+    let h = c.digits(2, 2)?;
+    let mi = c.eat(b':').then(|| c.digits(2, 2))??;
+    let mut s = 0;
+    let mut ms = 0;
+    if c.eat(b':') {
+        s = c.digits(2, 2)?;
+        if c.eat(b'.') || c.eat(b',') {
+            let start = c.i;
+            let frac = c.digits(1, 9)?;
+            // Keep millisecond precision, truncating any finer digits.
+            let len = (c.i - start) as u32;
+            ms = if len >= 3 {
+                frac / 10u32.pow(len - 3)
+            } else {
+                frac * 10u32.pow(3 - len)
+            };
+        }
+    }
+    let zone = if c.eat(b'Z') || c.eat(b'z') || c.done() {
+        0
+    } else {
+        numeric_zone(c)?
+    };
+    let local = to_millis(y, mo, d, h, mi, s, ms)?;
+    c.done().then_some(local - zone * 60_000)
+}
+
+const MONTHS: [&[u8]; 12] = [
+    b"jan", b"feb", b"mar", b"apr", b"may", b"jun", b"jul", b"aug", b"sep", b"oct", b"nov", b"dec",
+];
+
+fn parse_rfc2822(c: &mut Cursor) -> Option<i64> {
+    if c.peek()?.is_ascii_alphabetic() {
+        // Day-of-week names are not checked against the date.
+        c.word();
+        if !c.eat(b',') {
+            return None;
+        }
+        c.skip_spaces();
+    }
+    let d = c.digits(1, 2)?;
+    (c.skip_spaces() > 0).then_some(())?;
+    let month = c.word().to_ascii_lowercase();
+    let mo = MONTHS.iter().position(|m| *m == month.as_slice())? as u32 + 1;
+    (c.skip_spaces() > 0).then_some(())?;
+    let start = c.i;
+    let mut y = c.digits(2, 4)? as i64;
+    match c.i - start {
+        2 => y += if y < 50 { 2000 } else { 1900 },
+        3 => y += 1900,
+        _ => {}
+    }
+    (c.skip_spaces() > 0).then_some(())?;
+    let h = c.digits(2, 2)?;
+    let mi = c.eat(b':').then(|| c.digits(2, 2))??;
+    let s = if c.eat(b':') { c.digits(2, 2)? } else { 0 };
+    (c.skip_spaces() > 0).then_some(())?;
+    let zone = match c.peek()? {
+        b'+' | b'-' => numeric_zone(c)?,
+        _ => match c.word().to_ascii_uppercase().as_slice() {
+            b"UT" | b"GMT" | b"Z" => 0,
+            b"EDT" => -4 * 60,
+            b"EST" | b"CDT" => -5 * 60,
+            b"CST" | b"MDT" => -6 * 60,
+            b"MST" | b"PDT" => -7 * 60,
+            b"PST" => -8 * 60,
+            _ => return None,
+        },
+    };
+    let local = to_millis(y, mo, d, h, mi, s, 0)?;
+    c.done().then_some(local - zone * 60_000)
+}
+
+/// Parse one value in any of the enabled `formats`, ignoring surrounding
+/// ASCII whitespace.
+pub(crate) fn parse_date(value: &[u8], formats: u32) -> Option<i64> {
+    let value = value.trim_ascii();
+    if formats & (DATE_ISO8601 | DATE_ONLY) != 0 {
+        if let Some(ms) = parse_iso(&mut Cursor { s: value, i: 0 }, formats) {
+            return Some(ms);
+        }
+    }
+    if formats & DATE_RFC2822 != 0 {
+        return parse_rfc2822(&mut Cursor { s: value, i: 0 });
+    }
+    None
+}
+
+/// Parse each value of a string batch to epoch milliseconds.
+///
+/// `formats` is any combination of `DATE_ISO8601`, `DATE_ONLY` and
+/// `DATE_RFC2822`. Rows that fail to parse get `0` in `out_ptr` and their bit
+/// set in the error bitmap at `errors_ptr` (bit `i % 8` of byte `i / 8`),
+/// which must hold `errors_len >= ceil(rows / 8)` bytes.
+///
+/// Returns bytes written to `out_ptr` (`rows * 8`), or `-1` when either
+/// output is too small or the offsets are empty.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn parse_iso8601_batch(
+    text_ptr: *const u8,
+    text_len: usize,
+    offsets_ptr: *const u32,
+    offsets_len: usize,
+    formats: u32,
+    out_ptr: *mut i64,
+    out_len_bytes: usize,
+    errors_ptr: *mut u8,
+    errors_len: usize,
+) -> isize {
+    let Some(rows) = offsets_len.checked_sub(1) else {
+        return -1;
+    };
+    if out_len_bytes / 8 < rows || errors_len < rows.div_ceil(8) {
+        return -1;
+    }
+    let text = ffi::slice(text_ptr, text_len);
+    let offsets = ffi::slice(offsets_ptr, offsets_len);
+    let out = ffi::slice_mut(out_ptr, rows);
+    let errors = ffi::slice_mut(errors_ptr, rows.div_ceil(8));
+    errors.fill(0);
+
+    for (i, (value, slot)) in spans(text, offsets).zip(out.iter_mut()).enumerate() {
+        match value.and_then(|v| parse_date(v, formats)) {
+            Some(ms) => *slot = ms,
+            None => {
+                *slot = 0;
+                errors[i / 8] |= 1 << (i % 8);
+            }
+        }
+    }
+    (rows * 8) as isize
+}
+
+/// `floor(x / k)` for a pair of values.
+///
+/// With simd128 the quotient comes from an f64 division, which is exact
+/// after a one-step integer fix-up for any `|x| < 2^51` ms (about 71,000
+/// years either side of 1970); pairs outside that range take the scalar path.
+#[inline(always)]
+fn floor_div2(x: [i64; 2], k: i64) -> [i64; 2] {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        // Adding 2^52 + 2^51 moves an integer into the mantissa of a double
+        // with that exponent, so int <-> float is an add on the raw bits.
+        const MAGIC: f64 = 6_755_399_441_055_744.0;
+        const LIMIT: i64 = 1 << 51;
+        let v = v128_load(x.as_ptr() as *const v128);
+        let in_range = v128_and(
+            i64x2_lt(v, i64x2_splat(LIMIT)),
+            i64x2_gt(v, i64x2_splat(-LIMIT)),
+        );
+        if i64x2_all_true(in_range) {
+            let magic_bits = i64x2_splat(MAGIC.to_bits() as i64);
+            let xf = f64x2_sub(i64x2_add(v, magic_bits), f64x2_splat(MAGIC));
+            let q = f64x2_floor(f64x2_div(xf, f64x2_splat(k as f64)));
+            let mut q = i64x2_sub(f64x2_add(q, f64x2_splat(MAGIC)), magic_bits);
+            let r = i64x2_sub(v, i64x2_mul(q, i64x2_splat(k)));
+            // Comparison masks are -1 per true lane.
+            q = i64x2_add(q, i64x2_lt(r, i64x2_splat(0)));
+            q = i64x2_sub(q, i64x2_ge(r, i64x2_splat(k)));
+            let mut out = [0i64; 2];
+            v128_store(out.as_mut_ptr() as *mut v128, q);
+            return out;
+        }
+    }
+
+    x.map(|v| v.div_euclid(k))
+}
+
+/// Apply `f` to `input` two values at a time, zero-padding the last pair.
+/// `f` returns the outputs for both values, `N / 2` slots each, or `None`
+/// to stop with the output partly written.
+fn map_pairs<T: Copy, const N: usize>(
+    input: &[i64],
+    out: &mut [T],
+    f: impl Fn([i64; 2]) -> Option<[T; N]>,
+) -> Option<()> {
+    let per_value = N / 2;
+    for (src, dst) in input.chunks(2).zip(out.chunks_mut(N)) {
+        let mut pair = [0i64; 2];
+        pair[..src.len()].copy_from_slice(src);
+        dst.copy_from_slice(&f(pair)?[..src.len() * per_value]);
+    }
+    Some(())
+}
+
+/// `x` rounded down to multiples of `k`, or `None` when that is below
+/// `i64::MIN`.
+fn floor_to(x: [i64; 2], k: i64) -> Option<[i64; 2]> {
+    let [a, b] = floor_div2(x, k);
+    Some([a.checked_mul(k)?, b.checked_mul(k)?])
+}
+
+fn truncate_in_place(buf: &mut [i64], k: i64) -> Option<()> {
+    for chunk in buf.chunks_mut(2) {
+        let mut pair = [0i64; 2];
+        pair[..chunk.len()].copy_from_slice(chunk);
+        let len = chunk.len();
+        chunk.copy_from_slice(&floor_to(pair, k)?[..len]);
+    }
+    Some(())
+}
+
+/// Round every timestamp down to a multiple of `k` ms. Runs in place when the
+/// pointers are equal and, like the byte kernels, moves a partially
+/// overlapping input into place first unless `strict-aliasing` is on.
+///
+/// Fails with `InvalidInput` when a timestamp within `k` of `i64::MIN` has
+/// no multiple to round down to, leaving the output partly written.
+unsafe fn truncate_batch(
+    in_ptr: *const i64,
+    in_len_bytes: usize,
+    out_ptr: *mut i64,
+    out_len_bytes: usize,
+    k: i64,
+) -> isize {
+    if !in_len_bytes.is_multiple_of(8) || out_len_bytes < in_len_bytes {
+        return -1;
+    }
+    let n = in_len_bytes / 8;
+    let done = if std::ptr::eq(in_ptr, out_ptr) {
+        truncate_in_place(ffi::slice_mut(out_ptr, n), k)
+    } else if ffi::overlaps(
+        in_ptr as *const u8,
+        in_len_bytes,
+        out_ptr as *const u8,
+        in_len_bytes,
+    ) {
+        if ffi::aliased(
+            in_ptr as *const u8,
+            in_len_bytes,
+            out_ptr as *const u8,
+            in_len_bytes,
+        ) {
+            return ffi::ALIAS_ERROR;
+        }
+        std::ptr::copy(in_ptr, out_ptr, n);
+        truncate_in_place(ffi::slice_mut(out_ptr, n), k)
+    } else {
+        map_pairs(ffi::slice(in_ptr, n), ffi::slice_mut(out_ptr, n), |x| {
+            floor_to(x, k)
+        })
+    };
+    match done {
+        Some(()) => in_len_bytes as isize,
+        None => error::fail(ErrorCode::InvalidInput),
+    }
+}
+
+/// Truncate epoch milliseconds to the start of their UTC day. Runs in place
+/// when `in_ptr == out_ptr`. Returns bytes written, or `-1` for a value
+/// before the earliest whole day an `i64` can hold.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn truncate_to_day_batch(
+    in_ptr: *const i64,
+    in_len_bytes: usize,
+    out_ptr: *mut i64,
+    out_len_bytes: usize,
+) -> isize {
+    truncate_batch(in_ptr, in_len_bytes, out_ptr, out_len_bytes, MS_PER_DAY)
+}
+
+/// Truncate epoch milliseconds to the start of their hour. Runs in place
+/// when `in_ptr == out_ptr`. Returns bytes written, or `-1` for a value
+/// before the earliest whole hour an `i64` can hold.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn truncate_to_hour_batch(
+    in_ptr: *const i64,
+    in_len_bytes: usize,
+    out_ptr: *mut i64,
+    out_len_bytes: usize,
+) -> isize {
+    truncate_batch(in_ptr, in_len_bytes, out_ptr, out_len_bytes, MS_PER_HOUR)
+}
+
+/// Split epoch milliseconds into UTC `[year, month, day]` `i32` triples.
+/// Returns bytes written (`12` per input value), or `-1` if a year does not
+/// fit an `i32`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn epoch_to_ymd_batch(
+    in_ptr: *const i64,
+    in_len_bytes: usize,
+    out_ptr: *mut i32,
+    out_len_bytes: usize,
+) -> isize {
+    let n = in_len_bytes / 8;
+    if !in_len_bytes.is_multiple_of(8) || out_len_bytes / 12 < n {
+        return -1;
+    }
+    if ffi::aliased(
+        in_ptr as *const u8,
+        in_len_bytes,
+        out_ptr as *const u8,
+        n * 12,
+    ) {
+        return ffi::ALIAS_ERROR;
+    }
+    let done = map_pairs(ffi::slice(in_ptr, n), ffi::slice_mut(out_ptr, n * 3), |x| {
+        let [a, b] = floor_div2(x, MS_PER_DAY).map(civil_from_days);
+        Some([
+            i32::try_from(a.0).ok()?,
+            a.1 as i32,
+            a.2 as i32,
+            i32::try_from(b.0).ok()?,
+            b.1 as i32,
+            b.2 as i32,
+        ])
+    });
+    match done {
+        Some(()) => (n * 12) as isize,
+        None => error::fail(ErrorCode::InvalidInput),
+    }
+}
+
+/// Whole UTC calendar days from `a[i]` to `b[i]` as `i32`, where `b_ptr` holds
+/// as many values as `a_ptr`. Returns bytes written (`4` per pair), or `-1`
+/// if a difference does not fit an `i32` (about 5.9 million years).
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn diff_days_batch(
+    a_ptr: *const i64,
+    a_len_bytes: usize,
+    b_ptr: *const i64,
+    out_ptr: *mut i32,
+    out_len_bytes: usize,
+) -> isize {
+    let n = a_len_bytes / 8;
+    if !a_len_bytes.is_multiple_of(8) || out_len_bytes / 4 < n {
+        return -1;
+    }
+    if ffi::aliased(a_ptr as *const u8, a_len_bytes, out_ptr as *const u8, n * 4)
+        || ffi::aliased(b_ptr as *const u8, a_len_bytes, out_ptr as *const u8, n * 4)
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    let a = ffi::slice(a_ptr, n);
+    let b = ffi::slice(b_ptr, n);
+    let out = ffi::slice_mut(out_ptr, n);
+    for ((a, b), dst) in a.chunks(2).zip(b.chunks(2)).zip(out.chunks_mut(2)) {
+        let (mut x, mut y) = ([0i64; 2], [0i64; 2]);
+        x[..a.len()].copy_from_slice(a);
+        y[..b.len()].copy_from_slice(b);
+        let (dx, dy) = (floor_div2(x, MS_PER_DAY), floor_div2(y, MS_PER_DAY));
+        let (Ok(d0), Ok(d1)) = (i32::try_from(dy[0] - dx[0]), i32::try_from(dy[1] - dx[1])) else {
+            return error::fail(ErrorCode::InvalidInput);
+        };
+        dst.copy_from_slice(&[d0, d1][..a.len()]);
+    }
+    (n * 4) as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: u32 = DATE_ISO8601 | DATE_ONLY | DATE_RFC2822;
+
+    #[test]
+    fn civil_days_round_trip_known_dates() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        assert_eq!(days_from_civil(1600, 1, 1), -135_140);
+    }
+
+    #[test]
+    fn parses_iso_variants() {
+        let cases: &[(&[u8], i64)] = &[
+            (b"2024-02-29", 1_709_164_800_000),
+            (b"2024-02-29T12:34", 1_709_210_040_000),
+            (b"2024-02-29 12:34:56.789Z", 1_709_210_096_789),
+            (b"2024-02-29T12:34:56.7891234+02:00", 1_709_202_896_789),
+            (b"2024-02-29T12:34:56-0530", 1_709_229_896_000),
+            (b" 1969-12-31T23:59:59.999z ", -1),
+        ];
+        for &(text, ms) in cases {
+            assert_eq!(parse_date(text, ALL), Some(ms), "{}", text.escape_ascii());
+        }
+    }
+
+    #[test]
+    fn parses_rfc2822() {
+        assert_eq!(
+            parse_date(b"Tue, 1 Jul 2003 10:52:37 +0200", ALL),
+            Some(1_057_049_557_000)
+        );
+        assert_eq!(
+            parse_date(b"1 jul 03 08:52 GMT", ALL),
+            Some(1_057_049_520_000)
+        );
+        assert_eq!(
+            parse_date(b"01 Jul 2003 04:52:37 EDT", ALL),
+            Some(1_057_049_557_000)
+        );
+    }
+
+    #[test]
+    fn formats_gate_and_reject() {
+        assert_eq!(parse_date(b"2024-02-29", DATE_ISO8601), None);
+        assert_eq!(parse_date(b"2024-02-29T00:00", DATE_ONLY), None);
+        assert_eq!(parse_date(b"1 Jul 2003 10:52 +0200", DATE_ISO8601), None);
+        for bad in [
+            &b"2023-02-29"[..],
+            b"2024-13-01",
+            b"2024-01-01T24:00",
+            b"2024-01-01T10:00+2",
+            b"2024-01-01T10:00Zjunk",
+            b"1 Foo 2003 10:52 +0200",
+            b"1 Jul 2003 10:52",
+            b"",
+        ] {
+            assert_eq!(parse_date(bad, ALL), None, "{}", bad.escape_ascii());
+        }
+    }
+
+    #[test]
+    fn batch_sets_error_bits() {
+        let text = b"2024-01-01nope1 Jan 2024 00:00 Z";
+        let offsets = [0u32, 10, 14, 14, 32];
+        let mut out = [7i64; 4];
+        let mut errors = [0xFFu8; 1];
+        let written = unsafe {
+            parse_iso8601_batch(
+                text.as_ptr(),
+                text.len(),
+                offsets.as_ptr(),
+                offsets.len(),
+                ALL,
+                out.as_mut_ptr(),
+                32,
+                errors.as_mut_ptr(),
+                1,
+            )
+        };
+        assert_eq!(written, 32);
+        assert_eq!(out, [1_704_067_200_000, 0, 0, 1_704_067_200_000]);
+        assert_eq!(errors, [0b0110]);
+    }
+
+    #[test]
+    fn batch_rejects_short_outputs() {
+        let offsets = [0u32, 0, 0];
+        let mut out = [0i64; 2];
+        let mut errors = [0u8; 1];
+        let mut call = |out_len, errors_len| unsafe {
+            parse_iso8601_batch(
+                std::ptr::null(),
+                0,
+                offsets.as_ptr(),
+                offsets.len(),
+                ALL,
+                out.as_mut_ptr(),
+                out_len,
+                errors.as_mut_ptr(),
+                errors_len,
+            )
+        };
+        assert_eq!(call(8, 1), -1);
+        assert_eq!(call(16, 0), -1);
+        assert_eq!(call(16, 1), 16);
+        assert_eq!(errors, [0b11]);
+    }
+
+    #[test]
+    fn civil_from_days_inverts_days_from_civil() {
+        for days in (-800_000..800_000).step_by(997) {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m, d), days);
+        }
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+    }
+
+    #[test]
+    fn floor_div_matches_div_euclid() {
+        let edges = [
+            0,
+            1,
+            -1,
+            MS_PER_DAY - 1,
+            MS_PER_DAY,
+            -MS_PER_DAY,
+            -MS_PER_DAY - 1,
+            (1 << 51) - 1,
+            -(1 << 51) + 1,
+            i64::MAX,
+            i64::MIN,
+        ];
+        for &x in &edges {
+            for &k in &[MS_PER_HOUR, MS_PER_DAY] {
+                assert_eq!(
+                    floor_div2([x, x.saturating_sub(1)], k),
+                    [x.div_euclid(k), x.saturating_sub(1).div_euclid(k)]
+                );
+            }
+        }
+    }
+
+    const EPOCHS: [i64; 5] = [
+        1_709_210_096_789, // 2024-02-29T12:34:56.789Z
+        -1,
+        0,
+        -MS_PER_DAY - 1,
+        1_057_049_557_000, // 2003-07-01T08:52:37Z
+    ];
+
+    #[test]
+    fn truncates_in_and_out_of_place() {
+        let mut out = [0i64; 5];
+        let status = unsafe { truncate_to_day_batch(EPOCHS.as_ptr(), 40, out.as_mut_ptr(), 40) };
+        assert_eq!(status, 40);
+        assert_eq!(
+            out,
+            [
+                1_709_164_800_000,
+                -MS_PER_DAY,
+                0,
+                -2 * MS_PER_DAY,
+                1_057_017_600_000
+            ]
+        );
+
+        let mut buf = EPOCHS;
+        let ptr = buf.as_mut_ptr();
+        unsafe { truncate_to_hour_batch(ptr, 40, ptr, 40) };
+        assert_eq!(
+            buf,
+            [
+                1_709_208_000_000,
+                -MS_PER_HOUR,
+                0,
+                -MS_PER_DAY - MS_PER_HOUR,
+                1_057_046_400_000
+            ]
+        );
+
+        assert_eq!(
+            unsafe { truncate_to_day_batch(EPOCHS.as_ptr(), 12, out.as_mut_ptr(), 40) },
+            -1
+        );
+        assert_eq!(
+            unsafe { truncate_to_day_batch(EPOCHS.as_ptr(), 40, out.as_mut_ptr(), 32) },
+            -1
+        );
+
+        // The earliest whole day and hour still truncate; anything before
+        // them has nothing to round down to.
+        for (k, f) in [
+            (
+                MS_PER_DAY,
+                truncate_to_day_batch as unsafe extern "C" fn(_, _, _, _) -> _,
+            ),
+            (MS_PER_HOUR, truncate_to_hour_batch),
+        ] {
+            let earliest = i64::MIN / k * k;
+            let mut buf = [earliest, earliest + 1, i64::MAX];
+            let ptr = buf.as_mut_ptr();
+            assert_eq!(unsafe { f(ptr, 24, ptr, 24) }, 24);
+            assert_eq!(buf, [earliest, earliest, i64::MAX / k * k]);
+            let low = [0, earliest - 1, i64::MIN];
+            for (i, &x) in low[1..].iter().enumerate() {
+                let input = [low[0], x];
+                assert_eq!(unsafe { f(input.as_ptr(), 16, out.as_mut_ptr(), 40) }, -1);
+                let mut buf = [x];
+                let ptr = buf.as_mut_ptr();
+                assert_eq!(unsafe { f(ptr, 8, ptr, 8) }, -1, "in place {i}");
+            }
+        }
+        assert_eq!(error::last_error_code(), ErrorCode::InvalidInput as u32);
+    }
+
+    #[test]
+    fn splits_into_ymd() {
+        let mut out = [0i32; 15];
+        let status = unsafe { epoch_to_ymd_batch(EPOCHS.as_ptr(), 40, out.as_mut_ptr(), 60) };
+        assert_eq!(status, 60);
+        assert_eq!(
+            out,
+            [2024, 2, 29, 1969, 12, 31, 1970, 1, 1, 1969, 12, 30, 2003, 7, 1]
+        );
+
+        // Every i64 timestamp has a year that fits an i32.
+        let extremes = [i64::MIN, i64::MAX];
+        let status = unsafe { epoch_to_ymd_batch(extremes.as_ptr(), 16, out.as_mut_ptr(), 24) };
+        assert_eq!(status, 24);
+        assert_eq!(out[..6], [-292_275_055, 5, 16, 292_278_994, 8, 17]);
+    }
+
+    #[test]
+    fn diffs_calendar_days() {
+        let later = [
+            1_709_251_199_999, // 2024-02-29T23:59:59.999Z
+            0,
+            MS_PER_DAY * 366,
+            -1,
+            1_057_017_600_000,
+        ];
+        let mut out = [0i32; 5];
+        let status =
+            unsafe { diff_days_batch(EPOCHS.as_ptr(), 40, later.as_ptr(), out.as_mut_ptr(), 20) };
+        assert_eq!(status, 20);
+        assert_eq!(out, [0, 1, 366, 1, 0]);
+
+        // About 5.9 million years of days fit an i32; more fails the call.
+        let max_days = i32::MAX as i64 * MS_PER_DAY;
+        let (a, b) = ([0, 0], [max_days, -max_days - MS_PER_DAY]);
+        let status = unsafe { diff_days_batch(a.as_ptr(), 16, b.as_ptr(), out.as_mut_ptr(), 20) };
+        assert_eq!(status, 8);
+        assert_eq!(out[..2], [i32::MAX, i32::MIN]);
+        let (a, b) = ([i64::MIN, 0], [i64::MAX, 0]);
+        let status = unsafe { diff_days_batch(a.as_ptr(), 16, b.as_ptr(), out.as_mut_ptr(), 20) };
+        assert_eq!(status, -1);
+        let (a, b) = ([0], [max_days + MS_PER_DAY]);
+        let status = unsafe { diff_days_batch(a.as_ptr(), 8, b.as_ptr(), out.as_mut_ptr(), 20) };
+        assert_eq!(status, -1);
+    }
+}
@@ -0,0 +1,583 @@
+//! Subword tokenizers for LLM preprocessing (`tokenizer` feature).
+//!
+//! `tokenizer_load` parses a vocabulary once and keeps it behind a handle;
+//! `tokenize` then maps text to `u32` token ids. Two vocabulary formats are
+//! understood:
+//!
+//! - WordPiece (the default): a BERT-style `vocab.txt`, one token per line,
+//!   the id being the line number. Text is split on whitespace and ASCII
+//!   punctuation, then each word is matched greedily longest-first with `##`
+//!   marking continuation pieces. Words that cannot be covered become
+//!   `[UNK]`, which the vocabulary must contain.
+//! - Byte-level BPE (`TOKENIZER_BPE`): a tiktoken-style rank file, one
+//!   `<base64 token> <rank>` pair per line. Text is split into pieces by the
+//!   GPT-2 pre-tokenizer regex (r50k/p50k), or the cl100k_base one with
+//!   `TOKENIZER_CL100K`, and each piece is merged pairwise by lowest rank,
+//!   as tiktoken's `encode_ordinary` does (special tokens are not parsed).
+//!   `\p{L}` and `\p{N}` are read as `char::is_alphabetic` and
+//!   `char::is_numeric`, which differ only for the few marks and symbols
+//!   Unicode calls alphabetic but not letters (combining vowel signs,
+//!   circled letters). Pieces longer than 4 KiB, such as a long base64 run,
+//!   are merged 4 KiB at a time.
+//!
+//! `tokenize` sizes its output in two phases: pass `out_len_bytes == 0` to
+//! get the bytes needed, then call again with a buffer at least that large.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::{ffi, handles};
+
+/// Load a byte-level BPE rank file instead of a WordPiece vocabulary.
+pub const TOKENIZER_BPE: u32 = 1;
+/// Lowercase ASCII letters before WordPiece matching (uncased models).
+pub const TOKENIZER_LOWERCASE: u32 = 2;
+/// Split BPE input with the cl100k_base pre-tokenizer (GPT-3.5, GPT-4)
+/// instead of GPT-2's.
+pub const TOKENIZER_CL100K: u32 = 4;
+
+/// Words longer than this (in bytes) become `[UNK]`, as in BERT.
+const MAX_WORD_BYTES: usize = 200;
+/// Longest run of bytes merged as one BPE piece.
+const MAX_PIECE_BYTES: usize = 4096;
+
+pub(crate) struct Tokenizer {
+    vocab: HashMap<Vec<u8>, u32>,
+    bpe: bool,
+    lowercase: bool,
+    cl100k: bool,
+    unk: u32,
+}
+
+/// The char at byte `i`, and its length; invalid UTF-8 reads as one
+/// U+FFFD byte.
+fn char_at(text: &[u8], i: usize) -> Option<(char, usize)> {
+    let bytes = text.get(i..(i + 4).min(text.len()))?;
+    let valid = match std::str::from_utf8(bytes) {
+        Ok(valid) => valid,
+        Err(err) => std::str::from_utf8(&bytes[..err.valid_up_to()]).unwrap(),
+    };
+    match valid.chars().next() {
+        Some(c) => Some((c, c.len_utf8())),
+        None => (!bytes.is_empty()).then_some(('\u{FFFD}', 1)),
+    }
+}
+
+fn is_letter(c: char) -> bool {
+    c.is_alphabetic() && !c.is_numeric()
+}
+
+fn is_number(c: char) -> bool {
+    c.is_numeric()
+}
+
+/// `[^\s\p{L}\p{N}]`
+fn is_other(c: char) -> bool {
+    !c.is_whitespace() && !c.is_alphabetic() && !c.is_numeric()
+}
+
+fn is_newline(c: char) -> bool {
+    matches!(c, '\r' | '\n')
+}
+
+/// End of the run of chars matching `pred` from byte `i`, taking at most
+/// `max` chars, and the start of its last char.
+fn run(text: &[u8], mut i: usize, max: usize, pred: fn(char) -> bool) -> (usize, usize) {
+    let mut last = i;
+    for _ in 0..max {
+        match char_at(text, i) {
+            Some((c, n)) if pred(c) => {
+                last = i;
+                i += n;
+            }
+            _ => break,
+        }
+    }
+    (i, last)
+}
+
+/// Length of the contraction after a `'` at the start of `rest`: `s t re ve
+/// m ll d`, ignoring case (with the `ſ` that folds to `s`) for cl100k.
+fn contraction(rest: &[u8], cl100k: bool) -> Option<usize> {
+    let lower;
+    let rest = if cl100k {
+        if rest.starts_with("ſ".as_bytes()) {
+            return Some(2);
+        }
+        lower = rest[..rest.len().min(2)].to_ascii_lowercase();
+        &lower[..]
+    } else {
+        rest
+    };
+    [&b"s"[..], b"t", b"re", b"ve", b"m", b"ll", b"d"]
+        .into_iter()
+        .find(|c| rest.starts_with(c))
+        .map(<[u8]>::len)
+}
+
+/// End of the pre-tokenizer piece starting at byte `i` (not at the end of
+/// `text`): the first alternative of the GPT-2 split regex
+///
+/// `'s|'t|'re|'ve|'m|'ll|'d| ?\p{L}+| ?\p{N}+| ?[^\s\p{L}\p{N}]+|\s+(?!\S)|\s+`
+///
+/// or, with `cl100k`, of the cl100k_base one
+///
+/// `(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}|
+/// ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+`
+///
+/// that matches there.
+fn piece_end(text: &[u8], i: usize, cl100k: bool) -> usize {
+    let (c0, n0) = char_at(text, i).unwrap();
+    let c1 = char_at(text, i + n0).map(|(c, _)| c);
+    if c0 == '\'' {
+        if let Some(len) = contraction(&text[i + 1..], cl100k) {
+            return i + 1 + len;
+        }
+    }
+    if cl100k {
+        if is_letter(c0) {
+            return run(text, i, usize::MAX, is_letter).0;
+        }
+        if !is_newline(c0) && !is_number(c0) && c1.is_some_and(is_letter) {
+            return run(text, i + n0, usize::MAX, is_letter).0;
+        }
+        if is_number(c0) {
+            return run(text, i, 3, is_number).0;
+        }
+        let start = if c0 == ' ' && c1.is_some_and(is_other) {
+            i + n0
+        } else {
+            i
+        };
+        if start > i || is_other(c0) {
+            let (end, _) = run(text, start, usize::MAX, is_other);
+            return run(text, end, usize::MAX, is_newline).0;
+        }
+        // `\s*[\r\n]+` ends after the last line break of the space run.
+        let (end, _) = run(text, i, usize::MAX, char::is_whitespace);
+        if let Some(at) = text[i..end].iter().rposition(|&b| b == b'\r' || b == b'\n') {
+            return i + at + 1;
+        }
+    } else {
+        for pred in [is_letter, is_number, is_other] {
+            if pred(c0) {
+                return run(text, i, usize::MAX, pred).0;
+            }
+            if c0 == ' ' && c1.is_some_and(pred) {
+                return run(text, i + n0, usize::MAX, pred).0;
+            }
+        }
+    }
+    // `\s+(?!\S)` leaves the last space of a run for the word after it;
+    // `\s+` takes a lone one.
+    let (end, last) = run(text, i, usize::MAX, char::is_whitespace);
+    if end < text.len() && last > i {
+        last
+    } else {
+        end
+    }
+}
+
+fn base64_decode(input: &[u8]) -> Option<Vec<u8>> {
+    fn value(b: u8) -> Option<u32> {
+        Some(match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        } as u32)
+    }
+    let input = input
+        .strip_suffix(b"==")
+        .or(input.strip_suffix(b"="))
+        .unwrap_or(input);
+    if input.len() % 4 == 1 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    for chunk in input.chunks(4) {
+        let mut bits = 0u32;
+        for &b in chunk {
+            bits = bits << 6 | value(b)?;
+        }
+        bits <<= 6 * (4 - chunk.len()) as u32;
+        out.extend_from_slice(&bits.to_be_bytes()[1..chunk.len()]);
+    }
+    Some(out)
+}
+
+impl Tokenizer {
+    fn load(vocab: &[u8], flags: u32) -> Option<Tokenizer> {
+        let bpe = flags & TOKENIZER_BPE != 0;
+        let lines = vocab
+            .split(|&b| b == b'\n')
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+        let mut map = HashMap::new();
+        if bpe {
+            for line in lines.filter(|line| !line.is_empty()) {
+                let space = line.iter().position(|&b| b == b' ')?;
+                let rank = std::str::from_utf8(&line[space + 1..]).ok()?.parse().ok()?;
+                map.insert(base64_decode(&line[..space])?, rank);
+            }
+        } else {
+            // A trailing newline ends the last token rather than adding one.
+            let count = vocab.split(|&b| b == b'\n').count() - vocab.ends_with(b"\n") as usize;
+            for (id, line) in lines.take(count).enumerate() {
+                map.entry(line.to_vec()).or_insert(id as u32);
+            }
+        }
+        let unk = match map.get(&b"[UNK]"[..]) {
+            Some(&id) => id,
+            None if bpe => u32::MAX,
+            None => return None,
+        };
+        Some(Tokenizer {
+            vocab: map,
+            bpe,
+            lowercase: flags & TOKENIZER_LOWERCASE != 0,
+            cl100k: flags & TOKENIZER_CL100K != 0,
+            unk,
+        })
+    }
+
+    /// Greedy longest-match-first WordPiece over one word.
+    fn wordpiece(&self, word: &[u8], ids: &mut Vec<u32>) {
+        let mark = ids.len();
+        let mut key = Vec::with_capacity(word.len() + 2);
+        let mut start = 0;
+        while start < word.len() && word.len() <= MAX_WORD_BYTES {
+            let found = (start + 1..=word.len()).rev().find_map(|end| {
+                key.clear();
+                if start > 0 {
+                    key.extend_from_slice(b"##");
+                }
+                key.extend_from_slice(&word[start..end]);
+                self.vocab.get(&key).map(|&id| (id, end))
+            });
+            let Some((id, end)) = found else {
+                break;
+            };
+            ids.push(id);
+            start = end;
+        }
+        if start < word.len() {
+            ids.truncate(mark);
+            ids.push(self.unk);
+        }
+    }
+
+    /// Merge the lowest-ranked adjacent pair, leftmost first, until no pair
+    /// is in the vocab. As in tiktoken, the parts form a linked list and the
+    /// candidate pairs a min-heap, so a piece of `n` bytes costs
+    /// `O(n log n)`. Returns `None` when a single byte of `piece` has no rank.
+    fn bpe(&self, piece: &[u8], ids: &mut Vec<u32>) -> Option<()> {
+        if let Some(&id) = self.vocab.get(piece) {
+            ids.push(id);
+            return Some(());
+        }
+        let n = piece.len();
+        // The part starting at byte `i` ends at `next[i]`, and `prev[i]`
+        // starts the part before it (`usize::MAX` for none). `rank[i]` is the
+        // rank of the part merged with its successor, `None` for parts that
+        // have no ranked pair or were merged away.
+        let mut next: Vec<usize> = (1..=n).collect();
+        let mut prev: Vec<usize> = (0..n).map(|i| i.wrapping_sub(1)).collect();
+        let pair_rank = |i: usize, next: &[usize]| {
+            let end = *next.get(next[i])?;
+            self.vocab.get(&piece[i..end]).copied()
+        };
+        let mut rank: Vec<Option<u32>> = (0..n).map(|i| pair_rank(i, &next)).collect();
+        let mut heap: BinaryHeap<_> = (0..n)
+            .filter_map(|i| Some(Reverse((rank[i]?, i))))
+            .collect();
+        while let Some(Reverse((r, i))) = heap.pop() {
+            if rank[i] != Some(r) {
+                continue;
+            }
+            let gone = next[i];
+            next[i] = next[gone];
+            if next[i] < n {
+                prev[next[i]] = i;
+            }
+            rank[gone] = None;
+            for at in [i, prev[i]] {
+                if at < n {
+                    rank[at] = pair_rank(at, &next);
+                    heap.extend(rank[at].map(|r| Reverse((r, at))));
+                }
+            }
+        }
+        let mut i = 0;
+        while i < n {
+            ids.push(*self.vocab.get(&piece[i..next[i]])?);
+            i = next[i];
+        }
+        Some(())
+    }
+
+    fn encode(&self, text: &[u8]) -> Option<Vec<u32>> {
+        let mut ids = Vec::new();
+        if self.bpe {
+            let mut i = 0;
+            while i < text.len() {
+                let end = piece_end(text, i, self.cl100k);
+                let mut piece = &text[i..end];
+                while piece.len() > MAX_PIECE_BYTES {
+                    // Cut at a char boundary where there is one.
+                    let cut = (1..=MAX_PIECE_BYTES)
+                        .rev()
+                        .find(|&cut| piece[cut] & 0xc0 != 0x80)
+                        .unwrap_or(MAX_PIECE_BYTES);
+                    self.bpe(&piece[..cut], &mut ids)?;
+                    piece = &piece[cut..];
+                }
+                self.bpe(piece, &mut ids)?;
+                i = end;
+            }
+            return Some(ids);
+        }
+
+        let text = if self.lowercase {
+            text.to_ascii_lowercase()
+        } else {
+            text.to_vec()
+        };
+        for word in text.split(|b| b.is_ascii_whitespace()) {
+            let mut start = 0;
+            for (i, b) in word.iter().enumerate() {
+                if b.is_ascii_punctuation() {
+                    if start < i {
+                        self.wordpiece(&word[start..i], &mut ids);
+                    }
+                    self.wordpiece(&word[i..i + 1], &mut ids);
+                    start = i + 1;
+                }
+            }
+            if start < word.len() {
+                self.wordpiece(&word[start..], &mut ids);
+            }
+        }
+        Some(ids)
+    }
+}
+
+/// Parse a vocabulary (WordPiece by default, or a BPE rank file with
+/// `TOKENIZER_BPE` in `flags`) and return a handle for `tokenize`, released
+/// with `handle_drop`. Returns `-1` for a malformed vocabulary or a
+/// WordPiece vocabulary without `[UNK]`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn tokenizer_load(
+    vocab_ptr: *const u8,
+    vocab_len: usize,
+    flags: u32,
+) -> isize {
+    match Tokenizer::load(ffi::slice(vocab_ptr, vocab_len), flags) {
+        Some(tokenizer) => handles::insert(tokenizer) as isize,
+        None => -1,
+    }
+}
+
+/// Tokenize `text` with the vocabulary behind `handle`, writing `u32` ids to
+/// `out_ptr`.
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes the ids need. Otherwise returns bytes written, or `-1`
+/// for an unknown handle, a short output, or (BPE only) a byte the ranks do
+/// not cover.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn tokenize(
+    handle: u32,
+    text_ptr: *const u8,
+    text_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let text = ffi::slice(text_ptr, text_len);
+    let Some(Some(ids)) = handles::with(handle, |t: &mut Tokenizer| t.encode(text)) else {
+        return -1;
+    };
+    let bytes = ids.len() * 4;
+    if out_len_bytes == 0 {
+        return bytes as isize;
+    }
+    if out_len_bytes < bytes {
+        return -1;
+    }
+    ffi::slice_mut(out_ptr, ids.len()).copy_from_slice(&ids);
+    bytes as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(vocab: &[u8], flags: u32) -> u32 {
+        let handle = unsafe { tokenizer_load(vocab.as_ptr(), vocab.len(), flags) };
+        assert!(handle > 0, "vocabulary should load");
+        handle as u32
+    }
+
+    fn encode(handle: u32, text: &str) -> Vec<u32> {
+        let needed =
+            unsafe { tokenize(handle, text.as_ptr(), text.len(), std::ptr::null_mut(), 0) };
+        assert!(needed >= 0);
+        let mut ids = vec![0u32; needed as usize / 4];
+        let written = unsafe {
+            tokenize(
+                handle,
+                text.as_ptr(),
+                text.len(),
+                ids.as_mut_ptr(),
+                needed as usize,
+            )
+        };
+        assert_eq!(written, needed);
+        ids
+    }
+
+    #[test]
+    fn wordpiece_greedy_longest_match() {
+        let vocab = b"[PAD]\n[UNK]\nun\n##aff\n##able\nhello\n,\nworld\n!\n##s\nunaffable\n";
+        let handle = load(vocab, TOKENIZER_LOWERCASE);
+        assert_eq!(encode(handle, "Hello, worlds!"), [5, 6, 7, 9, 8]);
+        assert_eq!(encode(handle, "unaffable"), [10]);
+        assert_eq!(encode(handle, "unaffables xyz"), [10, 9, 1]);
+        assert_eq!(encode(handle, "  \t\n"), []);
+        assert_eq!(handles::handle_drop(handle), 0);
+
+        let cased = load(vocab, 0);
+        assert_eq!(encode(cased, "Hello"), [1]);
+        handles::handle_drop(cased);
+    }
+
+    #[test]
+    fn bpe_merges_by_rank() {
+        let mut vocab = String::new();
+        for b in 0..=255u8 {
+            vocab.push_str(&format!("{} {b}\n", base64(&[b])));
+        }
+        for (rank, token) in ["lo", "low", " l", " low", "er", "lower"]
+            .iter()
+            .enumerate()
+        {
+            vocab.push_str(&format!("{} {}\n", base64(token.as_bytes()), 256 + rank));
+        }
+        let handle = load(vocab.as_bytes(), TOKENIZER_BPE);
+        assert_eq!(encode(handle, "lower low"), [261, 259]);
+        assert_eq!(encode(handle, "lowx"), [257, b'x' as u32]);
+        assert_eq!(encode(handle, "  low"), [b' ' as u32, 259]);
+        assert_eq!(encode(handle, "low, low"), [257, b',' as u32, 259]);
+        handles::handle_drop(handle);
+
+        // Unspaced input is one piece; the heap keeps it fast, and pieces
+        // past the cap are merged in chunks.
+        let handle = load(vocab.as_bytes(), TOKENIZER_BPE | TOKENIZER_CL100K);
+        let long = "lowerlo".repeat(10_000);
+        let chunked: Vec<u32> = long
+            .as_bytes()
+            .chunks(MAX_PIECE_BYTES)
+            .flat_map(|chunk| encode(handle, std::str::from_utf8(chunk).unwrap()))
+            .collect();
+        assert_eq!(encode(handle, &long), chunked);
+        assert_eq!(chunked[..4], [261, 256, 261, 256]);
+        handles::handle_drop(handle);
+
+        // Without single-byte ranks some text is not encodable.
+        let partial = load(b"bG8= 0\nbA== 1\nbw== 2\n", TOKENIZER_BPE);
+        assert_eq!(encode(partial, "lo"), [0]);
+        assert_eq!(
+            unsafe { tokenize(partial, b"x".as_ptr(), 1, std::ptr::null_mut(), 0) },
+            -1
+        );
+        handles::handle_drop(partial);
+    }
+
+    fn pieces(text: &str, cl100k: bool) -> Vec<&str> {
+        let mut pieces = Vec::new();
+        let mut i = 0;
+        while i < text.len() {
+            let end = piece_end(text.as_bytes(), i, cl100k);
+            pieces.push(&text[i..end]);
+            i = end;
+        }
+        pieces
+    }
+
+    #[test]
+    fn pre_tokenizer_splits_like_the_gpt_regexes() {
+        let text = "Hello, world! It's 2024...  ok\n\nnew";
+        assert_eq!(
+            pieces(text, false),
+            [
+                "Hello", ",", " world", "!", " It", "'s", " 2024", "...", " ", " ok", "\n", "\n",
+                "new"
+            ]
+        );
+        assert_eq!(
+            pieces(text, true),
+            [
+                "Hello", ",", " world", "!", " It", "'s", " ", "202", "4", "...", " ", " ok",
+                "\n\n", "new"
+            ]
+        );
+        assert_eq!(
+            pieces("I'M 'quoted'", false),
+            ["I", "'", "M", " '", "quoted", "'"]
+        );
+        assert_eq!(
+            pieces("I'M 'quoted'", true),
+            ["I", "'M", " '", "quoted", "'"]
+        );
+        assert_eq!(pieces("x  \t", false), ["x", "  \t"]);
+        assert_eq!(pieces("a\t\nb", true), ["a", "\t\n", "b"]);
+        assert_eq!(pieces("naïve 東京12", false), ["naïve", " 東京", "12"]);
+        assert_eq!(pieces("!?\r\n", true), ["!?\r\n"]);
+    }
+
+    fn base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut out = String::new();
+        for chunk in bytes.chunks(3) {
+            let mut buf = [0u8; 3];
+            buf[..chunk.len()].copy_from_slice(chunk);
+            let n = u32::from_be_bytes([0, buf[0], buf[1], buf[2]]);
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+                } else {
+                    out.push('=');
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn rejects_bad_vocabularies_and_outputs() {
+        assert_eq!(
+            unsafe { tokenizer_load(b"a\nb\n".as_ptr(), 4, 0) },
+            -1,
+            "no [UNK]"
+        );
+        assert_eq!(
+            unsafe { tokenizer_load(b"!!! 1\n".as_ptr(), 6, TOKENIZER_BPE) },
+            -1
+        );
+        assert_eq!(
+            unsafe { tokenizer_load(b"YQ==\n".as_ptr(), 5, TOKENIZER_BPE) },
+            -1
+        );
+
+        let handle = load(b"[UNK]\na\n", 0);
+        let mut out = [0u32; 1];
+        let status = unsafe { tokenize(handle, b"a a".as_ptr(), 3, out.as_mut_ptr(), 4) };
+        assert_eq!(status, -1, "short output");
+        handles::handle_drop(handle);
+        let status = unsafe { tokenize(handle, b"a".as_ptr(), 1, out.as_mut_ptr(), 4) };
+        assert_eq!(status, -1, "dropped handle");
+    }
+}
@@ -0,0 +1,529 @@
+//! VCDIFF (RFC 3284) delta decoding.
+//!
+//! A delta is a series of windows, each rebuilding a stretch of the target
+//! from ADD (literal bytes), RUN (one byte repeated) and COPY instructions.
+//! COPY reads from a segment of the source, or of the target decoded so
+//! far, followed by the current target window itself, so a copy may overlap
+//! its own output. This is the format `xdelta3` writes; create deltas with
+//! `xdelta3 -e -S none -s old new patch.vcdiff`, since secondary compression
+//! and custom code tables are not supported.
+
+use crate::ffi;
+
+const MAGIC: [u8; 4] = [0xD6, 0xC3, 0xC4, 0x00];
+
+/// Header indicator bits.
+const VCD_DECOMPRESS: u8 = 0x01;
+const VCD_CODETABLE: u8 = 0x02;
+/// `xdelta3` application header (usually the file names).
+const VCD_APPHEADER: u8 = 0x04;
+
+/// Window indicator bits.
+const VCD_SOURCE: u8 = 0x01;
+const VCD_TARGET: u8 = 0x02;
+/// `xdelta3` Adler-32 checksum of the target window.
+const VCD_ADLER32: u8 = 0x04;
+
+const NOOP: u8 = 0;
+const ADD: u8 = 1;
+const RUN: u8 = 2;
+const COPY: u8 = 3;
+
+const NEAR_SLOTS: usize = 4;
+const SAME_SLOTS: usize = 3;
+
+/// One half of a code table entry: instruction type, size (0 means the
+/// size follows in the instruction section) and COPY address mode.
+#[derive(Clone, Copy, Default)]
+struct Inst {
+    kind: u8,
+    size: u8,
+    mode: u8,
+}
+
+/// The RFC's default code table.
+fn code_table() -> [[Inst; 2]; 256] {
+    let one = |kind, size, mode| [Inst { kind, size, mode }, Inst::default()];
+    let mut table = [[Inst::default(); 2]; 256];
+    let mut i = 0;
+    let mut put = |entry| {
+        table[i] = entry;
+        i += 1;
+    };
+    put(one(RUN, 0, 0));
+    for size in 0..=17 {
+        put(one(ADD, size, 0));
+    }
+    for mode in 0..9 {
+        put(one(COPY, 0, mode));
+        for size in 4..=18 {
+            put(one(COPY, size, mode));
+        }
+    }
+    for mode in 0..9 {
+        let copy_sizes = if mode < 6 { 4..=6 } else { 4..=4 };
+        for add in 1..=4 {
+            for size in copy_sizes.clone() {
+                put([
+                    Inst {
+                        kind: ADD,
+                        size: add,
+                        mode: 0,
+                    },
+                    Inst {
+                        kind: COPY,
+                        size,
+                        mode,
+                    },
+                ]);
+            }
+        }
+    }
+    for mode in 0..9 {
+        put([
+            Inst {
+                kind: COPY,
+                size: 4,
+                mode,
+            },
+            Inst {
+                kind: ADD,
+                size: 1,
+                mode: 0,
+            },
+        ]);
+    }
+    table
+}
+
+/// Forward-only reader over one section of the delta.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let b = *self.bytes.get(self.pos)?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    /// Big-endian base-128 integer, at most 32 bits.
+    fn int(&mut self) -> Option<usize> {
+        let mut value: u64 = 0;
+        loop {
+            let b = self.byte()?;
+            value = value << 7 | (b & 0x7F) as u64;
+            if value > u32::MAX as u64 {
+                return None;
+            }
+            if b & 0x80 == 0 {
+                return Some(value as usize);
+            }
+        }
+    }
+}
+
+/// Recently used COPY addresses, reset for each window.
+struct Cache {
+    near: [usize; NEAR_SLOTS],
+    next: usize,
+    same: [usize; SAME_SLOTS * 256],
+}
+
+impl Cache {
+    fn address(&mut self, mode: u8, here: usize, addrs: &mut Reader) -> Option<usize> {
+        let mode = mode as usize;
+        let addr = match mode {
+            0 => addrs.int()?,
+            1 => here.checked_sub(addrs.int()?)?,
+            m if m < 2 + NEAR_SLOTS => self.near[m - 2].checked_add(addrs.int()?)?,
+            m => *self
+                .same
+                .get((m - 2 - NEAR_SLOTS) * 256 + addrs.byte()? as usize)?,
+        };
+        self.near[self.next] = addr;
+        self.next = (self.next + 1) % NEAR_SLOTS;
+        self.same[addr % (SAME_SLOTS * 256)] = addr;
+        Some(addr)
+    }
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before `b` could overflow.
+    for chunk in bytes.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+/// Decode one window from `delta`, appending its target to `out`.
+fn window(
+    delta: &mut Reader,
+    table: &[[Inst; 2]; 256],
+    source: &[u8],
+    out: &mut Vec<u8>,
+    limit: usize,
+) -> Option<()> {
+    let indicator = delta.byte()?;
+    let from_source = indicator & VCD_SOURCE != 0;
+    let segment: &[u8] = match (from_source, indicator & VCD_TARGET != 0) {
+        (false, false) => &[],
+        (true, true) => return None,
+        _ => {
+            let (len, pos) = (delta.int()?, delta.int()?);
+            let from = if from_source { source } else { &out[..] };
+            from.get(pos..pos.checked_add(len)?)?
+        }
+    };
+    let encoding_len = delta.int()?;
+    let mut body = Reader {
+        bytes: delta.take(encoding_len)?,
+        pos: 0,
+    };
+    let target_len = body.int()?;
+    // Compressed sections need a secondary decompressor.
+    if body.byte()? != 0 {
+        return None;
+    }
+    let (data_len, inst_len, addr_len) = (body.int()?, body.int()?, body.int()?);
+    let checksum = if indicator & VCD_ADLER32 != 0 {
+        Some(u32::from_be_bytes(body.take(4)?.try_into().ok()?))
+    } else {
+        None
+    };
+    let mut data = Reader {
+        bytes: body.take(data_len)?,
+        pos: 0,
+    };
+    let mut insts = Reader {
+        bytes: body.take(inst_len)?,
+        pos: 0,
+    };
+    let mut addrs = Reader {
+        bytes: body.take(addr_len)?,
+        pos: 0,
+    };
+    if out.len().checked_add(target_len)? > limit {
+        return None;
+    }
+
+    // A segment taken from earlier target output is copied out, since the
+    // window appends to the same buffer.
+    let segment = segment.to_vec();
+    let start = out.len();
+    let mut cache = Cache {
+        near: [0; NEAR_SLOTS],
+        next: 0,
+        same: [0; SAME_SLOTS * 256],
+    };
+    while insts.pos < insts.bytes.len() {
+        for inst in table[insts.byte()? as usize] {
+            if inst.kind == NOOP {
+                continue;
+            }
+            let size = match inst.size {
+                0 => insts.int()?,
+                size => size as usize,
+            };
+            let written = out.len() - start;
+            if written + size > target_len {
+                return None;
+            }
+            match inst.kind {
+                ADD => out.extend_from_slice(data.take(size)?),
+                RUN => {
+                    let b = data.byte()?;
+                    out.resize(out.len() + size, b);
+                }
+                _ => {
+                    let here = segment.len() + written;
+                    let addr = cache.address(inst.mode, here, &mut addrs)?;
+                    if addr >= here {
+                        return None;
+                    }
+                    // Byte by byte: the copy may run from the segment into
+                    // the window, and into bytes it is itself writing.
+                    for k in addr..addr + size {
+                        let b = match segment.get(k) {
+                            Some(&b) => b,
+                            None => out[start + k - segment.len()],
+                        };
+                        out.push(b);
+                    }
+                }
+            }
+        }
+    }
+    if out.len() - start != target_len {
+        return None;
+    }
+    match checksum {
+        Some(sum) if adler32(&out[start..]) != sum => None,
+        _ => Some(()),
+    }
+}
+
+/// Apply a VCDIFF delta to `source`, returning the target.
+fn apply(source: &[u8], delta: &[u8], limit: usize) -> Option<Vec<u8>> {
+    let mut delta = Reader {
+        bytes: delta,
+        pos: 0,
+    };
+    if delta.take(4)? != MAGIC {
+        return None;
+    }
+    let header = delta.byte()?;
+    if header & (VCD_DECOMPRESS | VCD_CODETABLE) != 0 {
+        return None;
+    }
+    if header & VCD_APPHEADER != 0 {
+        let len = delta.int()?;
+        delta.take(len)?;
+    }
+    let table = code_table();
+    let mut out = Vec::new();
+    while delta.pos < delta.bytes.len() {
+        window(&mut delta, &table, source, &mut out, limit)?;
+    }
+    Some(out)
+}
+
+/// Apply the VCDIFF delta at `patch_ptr` to the source bytes at
+/// `source_ptr` (ignored by deltas that only reference their own output),
+/// writing the target.
+///
+/// With `out_len == 0` nothing is written and the return value is the
+/// number of bytes needed. Otherwise returns bytes written, or `-1` for a
+/// malformed or truncated delta, one using secondary compression or a custom
+/// code table, a window checksum mismatch, a copy outside its segment, or a
+/// short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn vcdiff_apply(
+    source_ptr: *const u8,
+    source_len: usize,
+    patch_ptr: *const u8,
+    patch_len: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let limit = if out_len == 0 { usize::MAX } else { out_len };
+    let source = ffi::slice(source_ptr, source_len);
+    let Some(target) = apply(source, ffi::slice(patch_ptr, patch_len), limit) else {
+        return -1;
+    };
+    if out_len == 0 {
+        return target.len() as isize;
+    }
+    if ffi::aliased(source_ptr, source_len, out_ptr, target.len())
+        || ffi::aliased(patch_ptr, patch_len, out_ptr, target.len())
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(out_ptr, target.len()).copy_from_slice(&target);
+    target.len() as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(mut v: usize) -> Vec<u8> {
+        let mut bytes = vec![(v & 0x7F) as u8];
+        v >>= 7;
+        while v > 0 {
+            bytes.insert(0, (v & 0x7F) as u8 | 0x80);
+            v >>= 7;
+        }
+        bytes
+    }
+
+    /// Encode one window: `segment` is `(len, pos)` for a source or target
+    /// segment.
+    fn window(
+        indicator: u8,
+        segment: Option<(usize, usize)>,
+        target_len: usize,
+        sections: [&[u8]; 3],
+        checksum: Option<u32>,
+    ) -> Vec<u8> {
+        let mut body = int(target_len);
+        body.push(0);
+        for s in sections {
+            body.extend(int(s.len()));
+        }
+        body.extend(checksum.map(u32::to_be_bytes).into_iter().flatten());
+        for s in sections {
+            body.extend_from_slice(s);
+        }
+        let mut out = vec![indicator];
+        if let Some((len, pos)) = segment {
+            out.extend(int(len));
+            out.extend(int(pos));
+        }
+        out.extend(int(body.len()));
+        out.extend(body);
+        out
+    }
+
+    fn delta(windows: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(0);
+        out.extend(windows.concat());
+        out
+    }
+
+    fn run(source: &[u8], patch: &[u8]) -> Option<Vec<u8>> {
+        let call = |out: &mut [u8]| unsafe {
+            vcdiff_apply(
+                source.as_ptr(),
+                source.len(),
+                patch.as_ptr(),
+                patch.len(),
+                out.as_mut_ptr(),
+                out.len(),
+            )
+        };
+        let needed = usize::try_from(call(&mut [])).ok()?;
+        let mut out = vec![0u8; needed];
+        if needed > 0 {
+            assert_eq!(call(&mut out), needed as isize);
+        }
+        Some(out)
+    }
+
+    #[test]
+    fn default_code_table_layout() {
+        let table = code_table();
+        let kinds = |i: usize| {
+            (
+                table[i][0].kind,
+                table[i][0].size,
+                table[i][0].mode,
+                table[i][1].kind,
+                table[i][1].size,
+                table[i][1].mode,
+            )
+        };
+        assert_eq!(kinds(0), (RUN, 0, 0, NOOP, 0, 0));
+        assert_eq!(kinds(18), (ADD, 17, 0, NOOP, 0, 0));
+        assert_eq!(kinds(19), (COPY, 0, 0, NOOP, 0, 0));
+        assert_eq!(kinds(162), (COPY, 18, 8, NOOP, 0, 0));
+        assert_eq!(kinds(163), (ADD, 1, 0, COPY, 4, 0));
+        assert_eq!(kinds(234), (ADD, 4, 0, COPY, 6, 5));
+        assert_eq!(kinds(246), (ADD, 4, 0, COPY, 4, 8));
+        assert_eq!(kinds(247), (COPY, 4, 0, ADD, 1, 0));
+        assert_eq!(kinds(255), (COPY, 4, 8, ADD, 1, 0));
+    }
+
+    #[test]
+    fn applies_source_copies_and_adds() {
+        let source = b"hello world";
+        // COPY 6 @0 (self mode), ADD 10, COPY 5 @6.
+        let w = window(
+            VCD_SOURCE,
+            Some((11, 0)),
+            21,
+            [b"brave new ", &[22, 11, 21], &[0, 6]],
+            None,
+        );
+        assert_eq!(run(source, &delta(&[w])).unwrap(), b"hello brave new world");
+    }
+
+    #[test]
+    fn applies_runs_and_overlapping_target_copies() {
+        let target = b"abcabcabcXXXX";
+        // ADD 3, COPY 6 from 3 back (here mode), RUN of 4 (size in the
+        // instruction section).
+        let w = window(
+            VCD_ADLER32,
+            None,
+            13,
+            [b"abcX", &[4, 38, 0, 4], &[3]],
+            Some(adler32(target)),
+        );
+        let patch = delta(&[w]);
+        assert_eq!(run(b"", &patch).unwrap(), target);
+        // A second window copying from the first window's output, using the
+        // ADD+COPY pair 163.
+        let w2 = window(VCD_TARGET, Some((13, 0)), 5, [b"!", &[163], &[9]], None);
+        let patch = delta(&[
+            window(0, None, 13, [b"abcX", &[4, 38, 0, 4], &[3]], None),
+            w2,
+        ]);
+        assert_eq!(run(b"", &patch).unwrap(), b"abcabcabcXXXX!XXXX");
+    }
+
+    #[test]
+    fn rejects_bad_deltas() {
+        let good = window(
+            VCD_SOURCE,
+            Some((11, 0)),
+            21,
+            [b"brave new ", &[22, 11, 21], &[0, 6]],
+            None,
+        );
+        let source = b"hello world";
+        assert!(run(source, &delta(std::slice::from_ref(&good))).is_some());
+        assert!(
+            run(b"hello", &delta(std::slice::from_ref(&good))).is_none(),
+            "segment past the source"
+        );
+        let mut patch = delta(&[good]);
+        patch.pop();
+        assert!(run(source, &patch).is_none(), "truncated");
+        assert!(
+            run(source, &[0xD6, 0xC3, 0xC4, 0x00, VCD_DECOMPRESS, 1]).is_none(),
+            "secondary compression"
+        );
+        assert!(run(source, b"BSDIFF40").is_none());
+        let target = b"abcabcabcXXXX";
+        let bad_sum = window(
+            VCD_ADLER32,
+            None,
+            13,
+            [b"abcX", &[4, 38, 0, 4], &[3]],
+            Some(adler32(target) ^ 1),
+        );
+        assert!(run(b"", &delta(&[bad_sum])).is_none(), "checksum");
+        let forward = window(0, None, 4, [b"", &[20], &[0]], None);
+        assert!(
+            run(b"", &delta(&[forward])).is_none(),
+            "copy from unwritten bytes"
+        );
+        let mut out = [0u8; 20];
+        let patch = delta(&[window(
+            VCD_SOURCE,
+            Some((11, 0)),
+            21,
+            [b"brave new ", &[22, 11, 21], &[0, 6]],
+            None,
+        )]);
+        let written = unsafe {
+            vcdiff_apply(
+                source.as_ptr(),
+                11,
+                patch.as_ptr(),
+                patch.len(),
+                out.as_mut_ptr(),
+                20,
+            )
+        };
+        assert_eq!(written, -1, "short output");
+        assert_eq!(run(b"", &delta(&[])).unwrap(), b"");
+    }
+}
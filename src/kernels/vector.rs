@@ -0,0 +1,281 @@
+//! Dense `f32` vector kernels for embedding post-processing.
+//!
+//! Matrices are row-major `f32` arrays, `rows * cols` values long. The
+//! helpers accumulate four lanes at a time and sum the lanes last, so SIMD
+//! and scalar builds produce identical results.
+
+use crate::ffi;
+
+#[inline(always)]
+fn lanes(values: &[f32]) -> [f32; 4] {
+    let mut block = [0f32; 4];
+    block[..values.len()].copy_from_slice(values);
+    block
+}
+
+/// `acc + a * b` per lane.
+#[inline(always)]
+fn mul_add_block(acc: [f32; 4], a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let acc = v128_load(acc.as_ptr() as *const v128);
+        let a = v128_load(a.as_ptr() as *const v128);
+        let b = v128_load(b.as_ptr() as *const v128);
+        let mut out = [0f32; 4];
+        v128_store(
+            out.as_mut_ptr() as *mut v128,
+            f32x4_add(acc, f32x4_mul(a, b)),
+        );
+        out
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        [0, 1, 2, 3].map(|i| acc[i] + a[i] * b[i])
+    }
+}
+
+/// `a + b` per lane.
+#[inline(always)]
+fn add_block(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let a = v128_load(a.as_ptr() as *const v128);
+        let b = v128_load(b.as_ptr() as *const v128);
+        let mut out = [0f32; 4];
+        v128_store(out.as_mut_ptr() as *mut v128, f32x4_add(a, b));
+        out
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        [0, 1, 2, 3].map(|i| a[i] + b[i])
+    }
+}
+
+/// `a - b` per lane.
+#[inline(always)]
+fn sub_block(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let a = v128_load(a.as_ptr() as *const v128);
+        let b = v128_load(b.as_ptr() as *const v128);
+        let mut out = [0f32; 4];
+        v128_store(out.as_mut_ptr() as *mut v128, f32x4_sub(a, b));
+        out
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        [0, 1, 2, 3].map(|i| a[i] - b[i])
+    }
+}
+
+fn horizontal_sum(acc: [f32; 4]) -> f32 {
+    (acc[0] + acc[1]) + (acc[2] + acc[3])
+}
+
+/// Dot product of two equal-length vectors. Under `simd128` the
+/// accumulator stays in an `f32x4` register across the whole vector.
+pub(crate) fn dot(a: &[f32], b: &[f32]) -> f32 {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let (mut a4, mut b4) = (a.chunks_exact(4), b.chunks_exact(4));
+        let mut acc = f32x4_splat(0.0);
+        for (a, b) in (&mut a4).zip(&mut b4) {
+            let a = v128_load(a.as_ptr() as *const v128);
+            let b = v128_load(b.as_ptr() as *const v128);
+            acc = f32x4_add(acc, f32x4_mul(a, b));
+        }
+        if !a4.remainder().is_empty() {
+            let (a, b) = (lanes(a4.remainder()), lanes(b4.remainder()));
+            let a = v128_load(a.as_ptr() as *const v128);
+            let b = v128_load(b.as_ptr() as *const v128);
+            acc = f32x4_add(acc, f32x4_mul(a, b));
+        }
+        let mut out = [0f32; 4];
+        v128_store(out.as_mut_ptr() as *mut v128, acc);
+        horizontal_sum(out)
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        let acc = a.chunks(4).zip(b.chunks(4)).fold([0f32; 4], |acc, (a, b)| {
+            mul_add_block(acc, lanes(a), lanes(b))
+        });
+        horizontal_sum(acc)
+    }
+}
+
+/// Squared Euclidean distance between two equal-length vectors.
+pub(crate) fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+    let acc = a.chunks(4).zip(b.chunks(4)).fold([0f32; 4], |acc, (a, b)| {
+        let d = sub_block(lanes(a), lanes(b));
+        mul_add_block(acc, d, d)
+    });
+    horizontal_sum(acc)
+}
+
+/// `acc += row`, element-wise.
+pub(crate) fn add_assign(acc: &mut [f32], row: &[f32]) {
+    for (acc, row) in acc.chunks_mut(4).zip(row.chunks(4)) {
+        let sum = add_block(lanes(acc), lanes(row));
+        acc.copy_from_slice(&sum[..acc.len()]);
+    }
+}
+
+/// `row *= factor`, element-wise.
+pub(crate) fn scale(row: &mut [f32], factor: f32) {
+    for chunk in row.chunks_mut(4) {
+        let scaled = mul_add_block([0f32; 4], lanes(chunk), [factor; 4]);
+        chunk.copy_from_slice(&scaled[..chunk.len()]);
+    }
+}
+
+/// Scale each row of the `rows x cols` matrix at `matrix_ptr` to unit L2
+/// norm, in place. All-zero rows are left as they are.
+///
+/// Returns bytes processed (`rows * cols * 4`), or `-1` when the size
+/// overflows.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn l2_normalize_f32_rows(
+    matrix_ptr: *mut f32,
+    rows: usize,
+    cols: usize,
+) -> isize {
+    let Some(len) = rows
+        .checked_mul(cols)
+        .filter(|len| len.checked_mul(4).is_some())
+    else {
+        return -1;
+    };
+    if cols == 0 {
+        return 0;
+    }
+    let matrix = ffi::slice_mut(matrix_ptr, len);
+    for row in matrix.chunks_exact_mut(cols) {
+        let norm = dot(row, row).sqrt();
+        if norm > 0.0 {
+            scale(row, 1.0 / norm);
+        }
+    }
+    (len * 4) as isize
+}
+
+/// Average the rows of the `rows x cols` matrix at `matrix_ptr` whose bit
+/// is set in `mask_ptr` (bit `i % 8` of byte `i / 8` for row `i`), writing
+/// `cols` values to `out_ptr`. This is the usual attention-mask mean pooling
+/// of token embeddings. A `mask_len` of `0` includes every row; when no row
+/// is included the output is all zeros.
+///
+/// Returns bytes written (`cols * 4`), or `-1` for a short mask or output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn mean_pool_f32(
+    matrix_ptr: *const f32,
+    rows: usize,
+    cols: usize,
+    mask_ptr: *const u8,
+    mask_len: usize,
+    out_ptr: *mut f32,
+    out_len_bytes: usize,
+) -> isize {
+    let Some(len) = rows
+        .checked_mul(cols)
+        .filter(|len| len.checked_mul(4).is_some())
+    else {
+        return -1;
+    };
+    if out_len_bytes / 4 < cols || (mask_len != 0 && mask_len < rows.div_ceil(8)) {
+        return -1;
+    }
+    if ffi::aliased(
+        matrix_ptr as *const u8,
+        len * 4,
+        out_ptr as *const u8,
+        cols * 4,
+    ) {
+        return ffi::ALIAS_ERROR;
+    }
+    if cols == 0 {
+        return 0;
+    }
+    let matrix = ffi::slice(matrix_ptr, len);
+    let mask = ffi::slice(mask_ptr, mask_len);
+    let mut sum = vec![0f32; cols];
+    let mut count = 0usize;
+    for (i, row) in matrix.chunks_exact(cols).enumerate() {
+        if mask.is_empty() || mask[i / 8] & (1 << (i % 8)) != 0 {
+            add_assign(&mut sum, row);
+            count += 1;
+        }
+    }
+    if count > 0 {
+        scale(&mut sum, 1.0 / count as f32);
+    }
+    ffi::slice_mut(out_ptr, cols).copy_from_slice(&sum);
+    (cols * 4) as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reductions_match_scalar_math() {
+        let a: Vec<f32> = (0..11).map(|i| i as f32 * 0.5).collect();
+        let b: Vec<f32> = (0..11).map(|i| 3.0 - i as f32).collect();
+        let expected_dot: f32 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+        let expected_dist: f32 = a.iter().zip(&b).map(|(x, y)| (x - y) * (x - y)).sum();
+        assert!((dot(&a, &b) - expected_dot).abs() < 1e-4);
+        assert!((squared_distance(&a, &b) - expected_dist).abs() < 1e-3);
+    }
+
+    #[test]
+    fn normalizes_rows_in_place() {
+        let mut m = [
+            3.0f32, 4.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0,
+        ];
+        let written = unsafe { l2_normalize_f32_rows(m.as_mut_ptr(), 3, 5) };
+        assert_eq!(written, 60);
+        assert_eq!(&m[..5], [0.6, 0.8, 0.0, 0.0, 0.0]);
+        assert_eq!(&m[5..10], [0.0; 5], "zero row untouched");
+        assert!((dot(&m[10..], &m[10..]) - 1.0).abs() < 1e-6);
+        assert_eq!(
+            unsafe { l2_normalize_f32_rows(m.as_mut_ptr(), usize::MAX, 2) },
+            -1
+        );
+    }
+
+    #[test]
+    fn mean_pools_masked_rows() {
+        let m = [1.0f32, 2.0, 3.0, 10.0, 20.0, 30.0, 100.0, 200.0, 300.0];
+        let mut out = [f32::NAN; 3];
+        let status =
+            unsafe { mean_pool_f32(m.as_ptr(), 3, 3, [0b101].as_ptr(), 1, out.as_mut_ptr(), 12) };
+        assert_eq!(status, 12);
+        assert_eq!(out, [50.5, 101.0, 151.5]);
+
+        let status =
+            unsafe { mean_pool_f32(m.as_ptr(), 3, 3, std::ptr::null(), 0, out.as_mut_ptr(), 12) };
+        assert_eq!(status, 12);
+        assert_eq!(out, [37.0, 74.0, 111.0]);
+
+        let status =
+            unsafe { mean_pool_f32(m.as_ptr(), 3, 3, [0].as_ptr(), 1, out.as_mut_ptr(), 12) };
+        assert_eq!(status, 12);
+        assert_eq!(out, [0.0; 3]);
+
+        let status =
+            unsafe { mean_pool_f32(m.as_ptr(), 3, 3, [0].as_ptr(), 1, out.as_mut_ptr(), 8) };
+        assert_eq!(status, -1, "short output");
+        let status =
+            unsafe { mean_pool_f32(m.as_ptr(), 9, 1, [0].as_ptr(), 1, out.as_mut_ptr(), 12) };
+        assert_eq!(status, -1, "short mask");
+    }
+}
@@ -0,0 +1,406 @@
+//! Per-pixel preprocessing for barcode and QR detectors: adaptive
+//! thresholding and Sobel gradients over 8-bit grayscale images.
+//!
+//! Images are `width * height` bytes, row by row. Both passes treat pixels
+//! past the border as copies of the nearest edge pixel, so the output is the
+//! same size as the input and flat regions stay flat up to the edge.
+
+use crate::ffi;
+
+/// Largest adaptive threshold radius; keeps every window sum below 2^32.
+const MAX_RADIUS: u32 = 1024;
+
+/// Validate the image arguments, returning the pixels.
+unsafe fn image<'a>(
+    gray_ptr: *const u8,
+    gray_len: usize,
+    width: u32,
+    height: u32,
+) -> Option<&'a [u8]> {
+    let len = (width as usize).checked_mul(height as usize)?;
+    (width > 0 && height > 0 && gray_len == len).then(|| ffi::slice(gray_ptr, gray_len))
+}
+
+/// Sobel gradients at column `c` with neighbour columns `l` and `r`.
+#[inline(always)]
+fn gradient(rows: [&[u8]; 3], l: usize, c: usize, r: usize) -> (i16, i16) {
+    let [up, mid, down] = rows.map(|row| [row[l], row[c], row[r]].map(i16::from));
+    let gx = (up[2] - up[0]) + 2 * (mid[2] - mid[0]) + (down[2] - down[0]);
+    let gy = (down[0] + 2 * down[1] + down[2]) - (up[0] + 2 * up[1] + up[2]);
+    (gx, gy)
+}
+
+/// Sobel gradients at the eight columns from `x`, which must have both
+/// neighbours in the row.
+#[inline(always)]
+fn gradients8(rows: [&[u8]; 3], x: usize) -> ([i16; 8], [i16; 8]) {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let load = |row: &[u8], at: usize| {
+            i16x8_extend_low_u8x16(v128_load64_zero(row[at..at + 8].as_ptr() as *const u64))
+        };
+        let [up, mid, down] = rows;
+        let diff = |row: &[u8]| i16x8_sub(load(row, x + 1), load(row, x - 1));
+        let sum = |row: &[u8]| {
+            let centre = load(row, x);
+            i16x8_add(
+                i16x8_add(load(row, x - 1), load(row, x + 1)),
+                i16x8_add(centre, centre),
+            )
+        };
+        let middle = diff(mid);
+        let gx = i16x8_add(i16x8_add(diff(up), diff(down)), i16x8_add(middle, middle));
+        let gy = i16x8_sub(sum(down), sum(up));
+        let (mut xs, mut ys) = ([0i16; 8], [0i16; 8]);
+        v128_store(xs.as_mut_ptr() as *mut v128, gx);
+        v128_store(ys.as_mut_ptr() as *mut v128, gy);
+        (xs, ys)
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        let (mut xs, mut ys) = ([0i16; 8], [0i16; 8]);
+        for lane in 0..8 {
+            let c = x + lane;
+            (xs[lane], ys[lane]) = gradient(rows, c - 1, c, c + 1);
+        }
+        (xs, ys)
+    }
+}
+
+/// Sobel gradients of row `y`, one `(gx, gy)` per pixel.
+fn row_gradients(
+    gray: &[u8],
+    width: usize,
+    height: usize,
+    y: usize,
+    mut emit: impl FnMut(usize, i16, i16),
+) {
+    let row = |y: usize| &gray[y * width..][..width];
+    let rows = [
+        row(y.saturating_sub(1)),
+        row(y),
+        row((y + 1).min(height - 1)),
+    ];
+    let last = width - 1;
+    let mut x = 0;
+    while x < width {
+        if x >= 1 && x + 9 <= width {
+            let (gx, gy) = gradients8(rows, x);
+            for lane in 0..8 {
+                emit(x + lane, gx[lane], gy[lane]);
+            }
+            x += 8;
+        } else {
+            let (gx, gy) = gradient(rows, x.saturating_sub(1), x, (x + 1).min(last));
+            emit(x, gx, gy);
+            x += 1;
+        }
+    }
+}
+
+/// Sobel gradient magnitude of the grayscale image at `gray_ptr`, as
+/// `|gx| + |gy|` saturated to 255, one byte per pixel. Edges of any
+/// orientation show up bright; feed it to a threshold or a line finder.
+///
+/// Returns bytes written, or `-1` for an image that is not `width * height`
+/// bytes, a zero dimension, or a short output. The output cannot overlap
+/// the image.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn sobel_magnitude_u8(
+    gray_ptr: *const u8,
+    gray_len: usize,
+    width: u32,
+    height: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let Some(gray) = image(gray_ptr, gray_len, width, height) else {
+        return -1;
+    };
+    if out_len < gray_len {
+        return -1;
+    }
+    if ffi::aliased(gray_ptr, gray_len, out_ptr, gray_len) {
+        return ffi::ALIAS_ERROR;
+    }
+    let (w, h) = (width as usize, height as usize);
+    let out = ffi::slice_mut(out_ptr, gray_len);
+    for (y, row) in out.chunks_exact_mut(w).enumerate() {
+        row_gradients(gray, w, h, y, |x, gx, gy| {
+            row[x] = (gx.unsigned_abs() + gy.unsigned_abs()).min(255) as u8;
+        });
+    }
+    gray_len as isize
+}
+
+/// Sobel gradients of the grayscale image at `gray_ptr`, as interleaved
+/// `i16` pairs `[gx, gy]` per pixel, each in `-1020..=1020`. `gx` is
+/// positive where brightness rises to the right and `gy` where it rises
+/// downward, so `atan2(gy, gx)` gives the edge normal for orientation
+/// voting.
+///
+/// Returns bytes written (`4 * width * height`), or `-1` for an image that
+/// is not `width * height` bytes, a zero dimension, or a short output. The
+/// output cannot overlap the image.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn sobel_gradients_i16(
+    gray_ptr: *const u8,
+    gray_len: usize,
+    width: u32,
+    height: u32,
+    out_ptr: *mut i16,
+    out_len_bytes: usize,
+) -> isize {
+    let Some(gray) = image(gray_ptr, gray_len, width, height) else {
+        return -1;
+    };
+    let needed = gray_len * 4;
+    if out_len_bytes < needed {
+        return -1;
+    }
+    if ffi::aliased(gray_ptr, gray_len, out_ptr as *const u8, needed) {
+        return ffi::ALIAS_ERROR;
+    }
+    let (w, h) = (width as usize, height as usize);
+    let out = ffi::slice_mut(out_ptr, gray_len * 2);
+    for (y, row) in out.chunks_exact_mut(w * 2).enumerate() {
+        row_gradients(gray, w, h, y, |x, gx, gy| {
+            row[x * 2] = gx;
+            row[x * 2 + 1] = gy;
+        });
+    }
+    needed as isize
+}
+
+/// Adaptive mean threshold: each pixel becomes `255` if it is brighter than
+/// the mean of the `(2 * radius + 1)`-square window around it minus
+/// `offset`, and `0` otherwise. Windows are clipped at the border. Unlike a
+/// global threshold this survives shadows and uneven lighting; a radius
+/// around an eighth of the expected code size and an offset of 5 to 10 suit
+/// barcodes. The window means come from an integral image, so the cost
+/// does not depend on the radius. Runs in place when `out_ptr == gray_ptr`.
+///
+/// Returns bytes written, or `-1` for an image that is not `width * height`
+/// bytes, a zero dimension, a `radius` outside `1..=1024`, or a short
+/// output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn adaptive_threshold_u8(
+    gray_ptr: *const u8,
+    gray_len: usize,
+    width: u32,
+    height: u32,
+    radius: u32,
+    offset: i32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let Some(gray) = image(gray_ptr, gray_len, width, height) else {
+        return -1;
+    };
+    if !(1..=MAX_RADIUS).contains(&radius) || out_len < gray_len {
+        return -1;
+    }
+    if !std::ptr::eq(gray_ptr, out_ptr) && ffi::aliased(gray_ptr, gray_len, out_ptr, gray_len) {
+        return ffi::ALIAS_ERROR;
+    }
+    let (w, h, r) = (width as usize, height as usize, radius as usize);
+    // Wrapping sums: a window sum is a difference of four entries, exact
+    // modulo 2^32, and never reaches 2^32 itself.
+    let stride = w + 1;
+    let mut integral = vec![0u32; stride * (h + 1)];
+    for y in 0..h {
+        let mut line = 0u32;
+        for x in 0..w {
+            line = line.wrapping_add(gray[y * w + x] as u32);
+            integral[(y + 1) * stride + x + 1] = integral[y * stride + x + 1].wrapping_add(line);
+        }
+    }
+    // The integral image holds everything needed, so the output may now
+    // replace the input.
+    std::ptr::copy(gray_ptr, out_ptr, gray_len);
+    let out = ffi::slice_mut(out_ptr, gray_len);
+    for y in 0..h {
+        let (top, bottom) = (y.saturating_sub(r), (y + r + 1).min(h));
+        for x in 0..w {
+            let (left, right) = (x.saturating_sub(r), (x + r + 1).min(w));
+            let sum = integral[bottom * stride + right]
+                .wrapping_sub(integral[top * stride + right])
+                .wrapping_sub(integral[bottom * stride + left])
+                .wrapping_add(integral[top * stride + left]);
+            let count = ((bottom - top) * (right - left)) as i64;
+            let pixel = &mut out[y * w + x];
+            let bright = *pixel as i64 * count > sum as i64 - offset as i64 * count;
+            *pixel = if bright { 255 } else { 0 };
+        }
+    }
+    gray_len as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noise(w: usize, h: usize) -> Vec<u8> {
+        let mut state = 0x2545_F491u32;
+        (0..w * h)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    fn gradients(gray: &[u8], w: usize, h: usize) -> Vec<i16> {
+        let mut out = vec![0i16; w * h * 2];
+        let written = unsafe {
+            sobel_gradients_i16(
+                gray.as_ptr(),
+                gray.len(),
+                w as u32,
+                h as u32,
+                out.as_mut_ptr(),
+                out.len() * 2,
+            )
+        };
+        assert_eq!(written, (w * h * 4) as isize);
+        out
+    }
+
+    #[test]
+    fn sobel_matches_the_direct_convolution() {
+        let (w, h) = (37, 6);
+        let gray = noise(w, h);
+        let at = |x: isize, y: isize| {
+            let (x, y) = (x.clamp(0, w as isize - 1), y.clamp(0, h as isize - 1));
+            gray[y as usize * w + x as usize] as i16
+        };
+        let mut want = Vec::new();
+        for y in 0..h as isize {
+            for x in 0..w as isize {
+                let gx = at(x + 1, y - 1) - at(x - 1, y - 1)
+                    + 2 * (at(x + 1, y) - at(x - 1, y))
+                    + at(x + 1, y + 1)
+                    - at(x - 1, y + 1);
+                let gy = at(x - 1, y + 1) + 2 * at(x, y + 1) + at(x + 1, y + 1)
+                    - at(x - 1, y - 1)
+                    - 2 * at(x, y - 1)
+                    - at(x + 1, y - 1);
+                want.extend([gx, gy]);
+            }
+        }
+        assert_eq!(gradients(&gray, w, h), want);
+
+        let mut magnitude = vec![0u8; w * h];
+        let written = unsafe {
+            sobel_magnitude_u8(
+                gray.as_ptr(),
+                gray.len(),
+                w as u32,
+                h as u32,
+                magnitude.as_mut_ptr(),
+                magnitude.len(),
+            )
+        };
+        assert_eq!(written, (w * h) as isize);
+        let expected: Vec<u8> = want
+            .chunks(2)
+            .map(|g| (g[0].abs() + g[1].abs()).min(255) as u8)
+            .collect();
+        assert_eq!(magnitude, expected);
+    }
+
+    #[test]
+    fn sobel_responds_to_ramps_and_edges() {
+        // Horizontal ramp of 10 per column: gx = 4 * 20 inside, half that
+        // at the clamped border columns.
+        let (w, h) = (20, 3);
+        let ramp: Vec<u8> = (0..w * h).map(|i| (i % w * 10) as u8).collect();
+        let g = gradients(&ramp, w, h);
+        for (x, pair) in g.chunks(2).take(w).enumerate() {
+            let gx = if x == 0 || x == w - 1 { 40 } else { 80 };
+            assert_eq!(pair, [gx, 0], "column {x}");
+        }
+        // A flat image has no gradient, a 1-pixel image included.
+        assert!(gradients(&[77; 30], 10, 3).iter().all(|&v| v == 0));
+        assert_eq!(gradients(&[5], 1, 1), [0, 0]);
+    }
+
+    #[test]
+    fn threshold_survives_uneven_lighting() {
+        // Lighting falls from 250 to 70 across the image; dark bars sit 50
+        // below it, darker at the left than the background at the right.
+        let (w, h) = (64, 16);
+        let lit = |x: usize| 250 - (x * 180 / w) as u8;
+        let bar = |x: usize| x % 8 < 2;
+        let gray: Vec<u8> = (0..w * h)
+            .map(|i| lit(i % w) - if bar(i % w) { 50 } else { 0 })
+            .collect();
+        let mut out = vec![0u8; w * h];
+        let threshold = |gray: &[u8], out: *mut u8| unsafe {
+            adaptive_threshold_u8(
+                gray.as_ptr(),
+                gray.len(),
+                w as u32,
+                h as u32,
+                4,
+                10,
+                out,
+                w * h,
+            )
+        };
+        assert_eq!(threshold(&gray, out.as_mut_ptr()), (w * h) as isize);
+        for (i, &v) in out.iter().enumerate() {
+            assert_eq!(v, if bar(i % w) { 0 } else { 255 }, "pixel {i}");
+        }
+        let mut copy = gray.clone();
+        assert_eq!(
+            threshold(&copy.clone(), copy.as_mut_ptr()),
+            (w * h) as isize
+        );
+        assert_eq!(copy, out, "in place");
+        // A flat image is all bright with a positive offset.
+        let flat = [9u8; 6];
+        let mut out = [1u8; 6];
+        let call = |radius, offset, out: &mut [u8]| unsafe {
+            adaptive_threshold_u8(
+                flat.as_ptr(),
+                6,
+                3,
+                2,
+                radius,
+                offset,
+                out.as_mut_ptr(),
+                out.len(),
+            )
+        };
+        assert_eq!(call(2, 1, &mut out), 6);
+        assert_eq!(out, [255; 6]);
+        assert_eq!(call(0, 1, &mut out), -1);
+        assert_eq!(call(1025, 1, &mut out), -1);
+        assert_eq!(call(1, 1, &mut out[..5]), -1, "short output");
+    }
+
+    #[test]
+    fn rejects_bad_images() {
+        let gray = [0u8; 12];
+        let mut out = [0u8; 64];
+        let call = |len, w, h, out: &mut [u8]| unsafe {
+            sobel_magnitude_u8(gray.as_ptr(), len, w, h, out.as_mut_ptr(), out.len())
+        };
+        assert_eq!(call(12, 4, 3, &mut out), 12);
+        assert_eq!(call(11, 4, 3, &mut out), -1, "wrong length");
+        assert_eq!(call(0, 0, 3, &mut out), -1, "zero width");
+        assert_eq!(call(12, 4, 3, &mut out[..11]), -1, "short output");
+        let mut pairs = [0i16; 24];
+        let written =
+            unsafe { sobel_gradients_i16(gray.as_ptr(), 12, 4, 3, pairs.as_mut_ptr(), 46) };
+        assert_eq!(written, -1, "short gradients");
+    }
+}
@@ -0,0 +1,253 @@
+//! WebSocket (RFC 6455) frame headers and payload masking.
+//!
+//! `ws_frame_header` decodes the variable-length header at the start of a
+//! frame, and `ws_mask` applies or removes the 4-byte XOR mask that clients
+//! put on every payload. Masking XORs a 16-byte repetition of the key into
+//! the payload one vector at a time. Because the mask repeats every four
+//! bytes, a payload can be processed in pieces by passing each piece's
+//! position in the payload as `offset`.
+
+use super::bytes::map_bytes;
+use crate::ffi;
+
+/// `u32` words written by `ws_frame_header`.
+const HEADER_WORDS: usize = 8;
+
+/// The mask key repeated over 16 bytes, rotated so lane 0 lines up with
+/// payload position `offset`.
+fn mask_pattern(mask_key: u32, offset: usize) -> [u8; 16] {
+    let key = mask_key.to_le_bytes();
+    std::array::from_fn(|i| key[(offset + i) % 4])
+}
+
+#[inline(always)]
+fn xor_block(block: [u8; 16], pattern: [u8; 16]) -> [u8; 16] {
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    unsafe {
+        use core::arch::wasm32::*;
+        let v = v128_load(block.as_ptr() as *const v128);
+        let p = v128_load(pattern.as_ptr() as *const v128);
+        let mut out = [0u8; 16];
+        v128_store(out.as_mut_ptr() as *mut v128, v128_xor(v, p));
+        out
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+    {
+        std::array::from_fn(|i| block[i] ^ pattern[i])
+    }
+}
+
+fn mask(input: &[u8], out: &mut [u8], pattern: [u8; 16]) {
+    for (src, dst) in input.chunks(16).zip(out.chunks_mut(16)) {
+        let mut block = [0u8; 16];
+        block[..src.len()].copy_from_slice(src);
+        dst.copy_from_slice(&xor_block(block, pattern)[..src.len()]);
+    }
+}
+
+fn mask_in_place(buf: &mut [u8], pattern: [u8; 16]) {
+    for chunk in buf.chunks_mut(16) {
+        let mut block = [0u8; 16];
+        block[..chunk.len()].copy_from_slice(chunk);
+        chunk.copy_from_slice(&xor_block(block, pattern)[..chunk.len()]);
+    }
+}
+
+/// XOR the payload bytes at `in_ptr` with a WebSocket mask, which both masks
+/// and unmasks. `mask_key` holds the four key bytes as they appear in the
+/// frame, read as a little-endian `u32` (the form `ws_frame_header`
+/// reports). `offset` is the position of `in[0]` within the frame's
+/// payload, for payloads processed in pieces; pass `0` for a whole payload.
+///
+/// Runs in place when `in_ptr == out_ptr`. Returns bytes written, or `-1`
+/// for a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn ws_mask(
+    in_ptr: *const u8,
+    in_len: usize,
+    mask_key: u32,
+    offset: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let pattern = mask_pattern(mask_key, offset);
+    map_bytes(
+        in_ptr,
+        in_len,
+        out_ptr,
+        out_len,
+        |input, out| mask(input, out, pattern),
+        |buf| mask_in_place(buf, pattern),
+    )
+}
+
+/// In-place form of `ws_mask`. Returns `len`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn ws_mask_in_place(
+    ptr: *mut u8,
+    len: usize,
+    mask_key: u32,
+    offset: usize,
+) -> isize {
+    ws_mask(ptr, len, mask_key, offset, ptr, len)
+}
+
+/// Decode a frame header into its eight fields, or `None` when more bytes
+/// are needed. `Err` marks a header no endpoint may send.
+fn frame_header(buf: &[u8]) -> Result<Option<[u32; HEADER_WORDS]>, ()> {
+    let (Some(&b0), Some(&b1)) = (buf.first(), buf.get(1)) else {
+        return Ok(None);
+    };
+    let (fin, rsv, opcode) = (b0 >> 7, (b0 >> 4) & 7, b0 & 0x0f);
+    let masked = b1 >> 7;
+    let (len, mut header) = match b1 & 0x7f {
+        126 => (
+            buf.get(2..4)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]) as u64),
+            4,
+        ),
+        127 => (
+            buf.get(2..10)
+                .map(|b| u64::from_be_bytes(b.try_into().unwrap())),
+            10,
+        ),
+        n => (Some(n as u64), 2),
+    };
+    let Some(len) = len else {
+        return Ok(None);
+    };
+    let control = opcode >= 8;
+    // Opcodes 3-7 and 11-15 are reserved; control frames are never
+    // fragmented and carry at most 125 bytes; the top length bit is zero.
+    if matches!(opcode, 3..=7 | 11..) || (control && (fin == 0 || len > 125)) || len >> 63 != 0 {
+        return Err(());
+    }
+    let mut mask_key = 0;
+    if masked == 1 {
+        let Some(key) = buf.get(header..header + 4) else {
+            return Ok(None);
+        };
+        mask_key = u32::from_le_bytes(key.try_into().unwrap());
+        header += 4;
+    }
+    Ok(Some([
+        fin as u32,
+        rsv as u32,
+        opcode as u32,
+        masked as u32,
+        header as u32,
+        len as u32,
+        (len >> 32) as u32,
+        mask_key,
+    ]))
+}
+
+/// Decode the WebSocket frame header at the start of `in_ptr` into eight
+/// `u32`s: `[fin, rsv, opcode, masked, header_len, payload_len_lo,
+/// payload_len_hi, mask_key]`. `rsv` holds the three reserved bits (RSV1 is
+/// set on `permessage-deflate` frames); the payload starts at `header_len`;
+/// `mask_key` is `0` for unmasked frames and otherwise ready for `ws_mask`.
+///
+/// Returns bytes written (`32`), `0` when the buffer ends inside the header
+/// (read more and call again), or `-1` for a reserved opcode, a fragmented
+/// or oversized control frame, a length with its top bit set, or a short
+/// output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn ws_frame_header(
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    if out_len_bytes < HEADER_WORDS * 4 {
+        return -1;
+    }
+    if ffi::aliased(in_ptr, in_len, out_ptr as *const u8, HEADER_WORDS * 4) {
+        return ffi::ALIAS_ERROR;
+    }
+    match frame_header(ffi::slice(in_ptr, in_len)) {
+        Ok(Some(fields)) => {
+            ffi::slice_mut(out_ptr, HEADER_WORDS).copy_from_slice(&fields);
+            (HEADER_WORDS * 4) as isize
+        }
+        Ok(None) => 0,
+        Err(()) => -1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(buf: &[u8]) -> (isize, [u32; HEADER_WORDS]) {
+        let mut out = [0u32; HEADER_WORDS];
+        let status = unsafe { ws_frame_header(buf.as_ptr(), buf.len(), out.as_mut_ptr(), 32) };
+        (status, out)
+    }
+
+    #[test]
+    fn decodes_frame_headers() {
+        // RFC 6455 section 5.7: a masked "Hello" text frame.
+        let frame = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        let (status, fields) = header(&frame);
+        assert_eq!(status, 32);
+        let key = u32::from_le_bytes([0x37, 0xfa, 0x21, 0x3d]);
+        assert_eq!(fields, [1, 0, 1, 1, 6, 5, 0, key]);
+        let mut payload = frame[6..].to_vec();
+        unsafe { ws_mask_in_place(payload.as_mut_ptr(), 5, key, 0) };
+        assert_eq!(payload, b"Hello");
+
+        // 64-bit length, unmasked binary frame with RSV1 set.
+        let mut frame = vec![0xc2, 127];
+        frame.extend(0x1_0000_0002u64.to_be_bytes());
+        assert_eq!(header(&frame).1, [1, 4, 2, 0, 10, 2, 1, 0]);
+        // 16-bit length, continuation frame without FIN.
+        assert_eq!(
+            header(&[0x00, 126, 0x01, 0x00]).1,
+            [0, 0, 0, 0, 4, 256, 0, 0]
+        );
+    }
+
+    #[test]
+    fn waits_for_whole_headers_and_rejects_bad_ones() {
+        assert_eq!(header(&[0x81]).0, 0);
+        assert_eq!(header(&[0x81, 126, 0x01]).0, 0);
+        assert_eq!(header(&[0x81, 0x80, 1, 2, 3]).0, 0, "mask key cut off");
+        assert_eq!(header(&[0x83, 0x00]).0, -1, "reserved opcode");
+        assert_eq!(header(&[0x09, 0x00]).0, -1, "fragmented ping");
+        assert_eq!(header(&[0x89, 126, 0, 126]).0, -1, "oversized ping");
+        let mut huge = vec![0x82, 127];
+        huge.extend(u64::MAX.to_be_bytes());
+        assert_eq!(header(&huge).0, -1);
+    }
+
+    #[test]
+    fn masking_in_pieces_matches_one_pass() {
+        let payload: Vec<u8> = (0..100u8).collect();
+        let key = 0xdead_beef;
+        let mut whole = vec![0u8; payload.len()];
+        let written = unsafe { ws_mask(payload.as_ptr(), 100, key, 0, whole.as_mut_ptr(), 100) };
+        assert_eq!(written, 100);
+        let bytes = key.to_le_bytes();
+        assert!(whole
+            .iter()
+            .zip(&payload)
+            .enumerate()
+            .all(|(i, (m, p))| m ^ p == bytes[i % 4]));
+
+        let mut pieces = payload.clone();
+        for (start, end) in [(0, 3), (3, 21), (21, 22), (22, 100)] {
+            let piece = &mut pieces[start..end];
+            unsafe { ws_mask_in_place(piece.as_mut_ptr(), piece.len(), key, start) };
+        }
+        assert_eq!(pieces, whole);
+        let status = unsafe { ws_mask(payload.as_ptr(), 100, key, 0, whole.as_mut_ptr(), 99) };
+        assert_eq!(status, -1);
+    }
+}
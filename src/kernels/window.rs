@@ -0,0 +1,215 @@
+//! Running aggregates partitioned by key, like SQL window functions.
+//!
+//! `window_agg` is the per-row counterpart of a `GROUP BY`: instead of one
+//! result per group it writes, for every row, the aggregate of its group over
+//! the rows seen so far in input order (`OVER (PARTITION BY key ROWS
+//! UNBOUNDED PRECEDING)`). Rows of different groups may interleave freely;
+//! sort by time first when the running order matters.
+
+use std::collections::HashMap;
+
+use crate::error::{self, ErrorCode};
+use crate::ffi;
+
+/// Running sum of the group's values.
+pub const WINDOW_SUM: u32 = 0;
+/// Running count of the group's non-`NaN` values.
+pub const WINDOW_COUNT: u32 = 1;
+/// Running mean of the group's values.
+pub const WINDOW_MEAN: u32 = 2;
+/// Running minimum of the group's values.
+pub const WINDOW_MIN: u32 = 3;
+/// Running maximum of the group's values.
+pub const WINDOW_MAX: u32 = 4;
+/// 1-based position of the row within its group, `NaN` rows included.
+pub const WINDOW_ROW_NUMBER: u32 = 5;
+
+/// Running state of one group.
+struct Group {
+    rows: u64,
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Group {
+    fn new() -> Self {
+        Group {
+            rows: 0,
+            count: 0,
+            sum: 0.0,
+            min: f64::NAN,
+            max: f64::NAN,
+        }
+    }
+
+    fn push(&mut self, v: f64) {
+        self.rows += 1;
+        if v.is_nan() {
+            return;
+        }
+        self.count += 1;
+        self.sum += v;
+        // `f64::min` ignores the initial `NaN`.
+        self.min = self.min.min(v);
+        self.max = self.max.max(v);
+    }
+
+    fn get(&self, kind: u32) -> f64 {
+        match kind {
+            WINDOW_SUM => self.sum,
+            WINDOW_COUNT => self.count as f64,
+            WINDOW_MEAN if self.count == 0 => f64::NAN,
+            WINDOW_MEAN => self.sum / self.count as f64,
+            WINDOW_MIN => self.min,
+            WINDOW_MAX => self.max,
+            _ => self.rows as f64,
+        }
+    }
+}
+
+/// For each row `i`, write to `out[i]` the `agg_kind` aggregate (one of the
+/// `WINDOW_` constants) of `values[j]` over the rows `j <= i` with
+/// `keys[j] == keys[i]`. Keys are arbitrary `u32`s, for example `dict_build`
+/// codes.
+///
+/// `NaN` values are left out of every aggregate; their row repeats the
+/// group's running result. Min, max and mean stay `NaN` until a group has a
+/// value. Runs in place when `values_ptr == out_ptr`.
+///
+/// Returns bytes written, or `-1` for mismatched lengths, a partial value,
+/// an unknown `agg_kind` or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn window_agg(
+    keys_ptr: *const u32,
+    keys_len_bytes: usize,
+    values_ptr: *const f64,
+    values_len_bytes: usize,
+    agg_kind: u32,
+    out_ptr: *mut f64,
+    out_len_bytes: usize,
+) -> isize {
+    let n = keys_len_bytes / 4;
+    if !keys_len_bytes.is_multiple_of(4) || values_len_bytes != n * 8 {
+        return error::fail(ErrorCode::BadLength);
+    }
+    if agg_kind > WINDOW_ROW_NUMBER {
+        return error::fail(ErrorCode::InvalidArgument);
+    }
+    if out_len_bytes < values_len_bytes {
+        return error::fail(ErrorCode::ShortOutput);
+    }
+    if ffi::aliased(
+        keys_ptr as *const u8,
+        keys_len_bytes,
+        out_ptr as *const u8,
+        values_len_bytes,
+    ) {
+        return ffi::ALIAS_ERROR;
+    }
+    if !std::ptr::eq(values_ptr, out_ptr) {
+        if ffi::aliased(
+            values_ptr as *const u8,
+            values_len_bytes,
+            out_ptr as *const u8,
+            values_len_bytes,
+        ) {
+            return ffi::ALIAS_ERROR;
+        }
+        // `copy` handles a partially overlapping input like the byte kernels.
+        std::ptr::copy(values_ptr, out_ptr, n);
+    }
+    let keys = ffi::slice(keys_ptr, n);
+    let mut groups: HashMap<u32, Group> = HashMap::new();
+    for (&key, v) in keys.iter().zip(ffi::slice_mut(out_ptr, n)) {
+        let group = groups.entry(key).or_insert_with(Group::new);
+        group.push(*v);
+        *v = group.get(agg_kind);
+    }
+    values_len_bytes as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(keys: &[u32], values: &[f64], kind: u32) -> Vec<f64> {
+        let mut out = vec![-7.0; values.len()];
+        let written = unsafe {
+            window_agg(
+                keys.as_ptr(),
+                keys.len() * 4,
+                values.as_ptr(),
+                values.len() * 8,
+                kind,
+                out.as_mut_ptr(),
+                out.len() * 8,
+            )
+        };
+        assert_eq!(written, values.len() as isize * 8);
+        out
+    }
+
+    /// Compare with `NaN == NaN`.
+    fn same(a: &[f64], b: &[f64]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.to_bits() == y.to_bits())
+    }
+
+    #[test]
+    fn running_aggregates_per_group() {
+        let keys = [7, 3, 7, 3, 7, 9];
+        let values = [1.0, f64::NAN, 4.0, 2.0, f64::NAN, -1.0];
+        let nan = f64::NAN;
+        let cases = [
+            (WINDOW_SUM, [1.0, 0.0, 5.0, 2.0, 5.0, -1.0]),
+            (WINDOW_COUNT, [1.0, 0.0, 2.0, 1.0, 2.0, 1.0]),
+            (WINDOW_MEAN, [1.0, nan, 2.5, 2.0, 2.5, -1.0]),
+            (WINDOW_MIN, [1.0, nan, 1.0, 2.0, 1.0, -1.0]),
+            (WINDOW_MAX, [1.0, nan, 4.0, 2.0, 4.0, -1.0]),
+            (WINDOW_ROW_NUMBER, [1.0, 1.0, 2.0, 2.0, 3.0, 1.0]),
+        ];
+        for (kind, expected) in cases {
+            let out = run(&keys, &values, kind);
+            assert!(same(&out, &expected), "kind {kind}: {out:?}");
+        }
+    }
+
+    #[test]
+    fn runs_in_place() {
+        let keys = [0, 1, 0, 1];
+        let mut values = [1.0, 10.0, 2.0, 20.0];
+        let ptr = values.as_mut_ptr();
+        let written = unsafe { window_agg(keys.as_ptr(), 16, ptr, 32, WINDOW_SUM, ptr, 32) };
+        assert_eq!(written, 32);
+        assert_eq!(values, [1.0, 10.0, 3.0, 30.0]);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let keys = [0u32, 1];
+        let values = [1.0, 2.0];
+        let mut out = [0f64; 2];
+        let call = |keys_len, values_len, kind, out_len, out: &mut [f64; 2]| unsafe {
+            window_agg(
+                keys.as_ptr(),
+                keys_len,
+                values.as_ptr(),
+                values_len,
+                kind,
+                out.as_mut_ptr(),
+                out_len,
+            )
+        };
+        let code = || crate::error::last_error_code();
+        assert_eq!(call(8, 8, WINDOW_SUM, 16, &mut out), -1, "length mismatch");
+        assert_eq!(code(), ErrorCode::BadLength as u32);
+        assert_eq!(call(6, 12, WINDOW_SUM, 16, &mut out), -1, "partial key");
+        assert_eq!(call(8, 16, 6, 16, &mut out), -1, "unknown kind");
+        assert_eq!(code(), ErrorCode::InvalidArgument as u32);
+        assert_eq!(call(8, 16, WINDOW_SUM, 8, &mut out), -1, "short output");
+        assert_eq!(code(), ErrorCode::ShortOutput as u32);
+        assert_eq!(call(0, 0, WINDOW_SUM, 0, &mut out), 0);
+    }
+}
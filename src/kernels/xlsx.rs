@@ -0,0 +1,506 @@
+//! XLSX worksheet and shared-string scanning.
+//!
+//! An `.xlsx` file is a ZIP archive (see `zip`) of XML parts. The cells of
+//! each sheet live in `xl/worksheets/sheetN.xml` as `<c r="B3" t="s"><v>12</v>
+//! </c>` elements, and text cells usually hold an index into the strings of
+//! `xl/sharedStrings.xml`. `xlsx_sheet_cells` lists where every cell's value
+//! sits in the sheet XML, `xlsx_cell_numbers` parses those values as `f64`s in
+//! one pass, and `xlsx_shared_strings` decodes the string table into a string
+//! batch. The XML is scanned tag to tag, with the search kernel's 16-byte scan
+//! finding each `<`; no DOM is built.
+
+use super::search::for_each_match;
+use crate::ffi;
+
+/// Cell kinds reported by `xlsx_sheet_cells`, from the cell's `t` attribute.
+pub const CELL_NUMBER: u32 = 0;
+pub const CELL_SHARED_STRING: u32 = 1;
+pub const CELL_BOOLEAN: u32 = 2;
+pub const CELL_ERROR: u32 = 3;
+/// `t="str"`: the text result of a formula.
+pub const CELL_STRING: u32 = 4;
+/// `t="inlineStr"`: the value span covers the `<is>` element's contents.
+pub const CELL_INLINE_STRING: u32 = 5;
+/// `t="d"`: an ISO 8601 date in the value text.
+pub const CELL_DATE: u32 = 6;
+
+/// `u32` words per cell in `xlsx_sheet_cells` output.
+const CELL_WORDS: usize = 5;
+
+/// Position of the first `needle` in `xml[from..]`.
+fn find(xml: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    let mut found = None;
+    for_each_match(xml, needle, false, from, |i| {
+        found = Some(i);
+        false
+    });
+    found
+}
+
+/// A tag starting at the `<` at `pos`: its local name (any namespace prefix
+/// dropped), whether it is a closing tag, its attribute bytes, whether it
+/// closes itself (`/>`), and the position after its `>`.
+struct Tag<'a> {
+    name: &'a [u8],
+    closing: bool,
+    attrs: &'a [u8],
+    empty: bool,
+    end: usize,
+}
+
+fn tag(xml: &[u8], pos: usize) -> Option<Tag<'_>> {
+    let close = pos + find(xml.get(pos..)?, b">", 0)?;
+    let inner = &xml[pos + 1..close];
+    let (inner, closing) = match inner.strip_prefix(b"/") {
+        Some(inner) => (inner, true),
+        None => (inner, false),
+    };
+    let (inner, empty) = match inner.strip_suffix(b"/") {
+        Some(inner) => (inner, true),
+        None => (inner, false),
+    };
+    let name_len = inner
+        .iter()
+        .position(|b| b.is_ascii_whitespace())
+        .unwrap_or(inner.len());
+    let qualified = &inner[..name_len];
+    let name = match qualified.iter().position(|&b| b == b':') {
+        Some(colon) => &qualified[colon + 1..],
+        None => qualified,
+    };
+    Some(Tag {
+        name,
+        closing,
+        attrs: &inner[name_len..],
+        empty,
+        end: close + 1,
+    })
+}
+
+/// Value of attribute `name` in `attrs`.
+fn attr<'a>(mut attrs: &'a [u8], name: &[u8]) -> Option<&'a [u8]> {
+    loop {
+        attrs = attrs.trim_ascii_start();
+        let eq = attrs.iter().position(|&b| b == b'=')?;
+        let key = attrs[..eq].trim_ascii_end();
+        let rest = attrs[eq + 1..].trim_ascii_start();
+        let quote = *rest.first()?;
+        let len = rest[1..].iter().position(|&b| b == quote)?;
+        if key == name {
+            return Some(&rest[1..1 + len]);
+        }
+        attrs = &rest[len + 2..];
+    }
+}
+
+/// Parse an A1 reference (`"AB12"`) to a 0-based `(row, column)`.
+fn cell_ref(r: &[u8]) -> Option<(u32, u32)> {
+    let letters = r.iter().take_while(|b| b.is_ascii_alphabetic()).count();
+    let (col, row) = r.split_at(letters);
+    if !(1..=3).contains(&letters) || row.is_empty() || row.len() > 7 {
+        return None;
+    }
+    let col = col.iter().fold(0u32, |c, &b| {
+        c * 26 + (b.to_ascii_uppercase() - b'A') as u32 + 1
+    });
+    let row = row.iter().try_fold(0u32, |n, &b| {
+        b.is_ascii_digit().then(|| n * 10 + (b - b'0') as u32)
+    })?;
+    Some((row.checked_sub(1)?, col - 1))
+}
+
+fn kind(t: Option<&[u8]>) -> Option<u32> {
+    Some(match t.unwrap_or(b"n") {
+        b"n" => CELL_NUMBER,
+        b"s" => CELL_SHARED_STRING,
+        b"b" => CELL_BOOLEAN,
+        b"e" => CELL_ERROR,
+        b"str" => CELL_STRING,
+        b"inlineStr" => CELL_INLINE_STRING,
+        b"d" => CELL_DATE,
+        _ => return None,
+    })
+}
+
+/// Collect `[row, col, kind, value_start, value_len]` for each cell that has
+/// a value.
+fn sheet_cells(xml: &[u8], out: &mut Vec<u32>) -> Option<()> {
+    // Rows and cells may omit `r`; they then follow the previous one.
+    let (mut row, mut next_col) = (u32::MAX, 0u32);
+    let mut pos = 0;
+    while let Some(lt) = find(xml, b"<", pos) {
+        let tag = tag(xml, lt)?;
+        pos = tag.end;
+        if tag.closing {
+            continue;
+        }
+        match tag.name {
+            b"row" => {
+                row = match attr(tag.attrs, b"r") {
+                    Some(r) => std::str::from_utf8(r)
+                        .ok()?
+                        .parse::<u32>()
+                        .ok()?
+                        .checked_sub(1)?,
+                    None => row.wrapping_add(1),
+                };
+                next_col = 0;
+            }
+            b"c" => {
+                let (r, c) = match attr(tag.attrs, b"r") {
+                    Some(r) => cell_ref(r)?,
+                    None => (row, next_col),
+                };
+                next_col = c + 1;
+                let kind = kind(attr(tag.attrs, b"t"))?;
+                if tag.empty {
+                    continue;
+                }
+                let end = find_close(xml, b"c", pos)?;
+                let value = if kind == CELL_INLINE_STRING {
+                    element(xml, b"is", pos, end)
+                } else {
+                    element(xml, b"v", pos, end)
+                };
+                if let Some((start, len)) = value {
+                    out.extend([r, c, kind, start as u32, len as u32]);
+                }
+                pos = end;
+            }
+            _ => {}
+        }
+    }
+    Some(())
+}
+
+/// Position of the `</name>` that ends the element whose content starts at
+/// `from`. None of the elements looked up this way nest, so the first
+/// closing tag with that name is it.
+fn find_close(xml: &[u8], name: &[u8], from: usize) -> Option<usize> {
+    let mut pos = from;
+    loop {
+        let lt = find(xml, b"</", pos)?;
+        let tag = tag(xml, lt)?;
+        if tag.name == name {
+            return Some(lt);
+        }
+        pos = tag.end;
+    }
+}
+
+/// Content span `(start, len)` of the first `<name>` element in
+/// `xml[from..to]`.
+fn element(xml: &[u8], name: &[u8], from: usize, to: usize) -> Option<(usize, usize)> {
+    let mut pos = from;
+    while let Some(lt) = find(&xml[..to], b"<", pos) {
+        let tag = tag(xml, lt)?;
+        if !tag.closing && tag.name == name {
+            if tag.empty {
+                return Some((tag.end, 0));
+            }
+            let close = find_close(&xml[..to], name, tag.end)?;
+            return Some((tag.end, close - tag.end));
+        }
+        pos = tag.end;
+    }
+    None
+}
+
+/// List the cells of the worksheet XML at `xml_ptr` that hold a value, in
+/// document order, as five `u32`s each: `[row, col, kind, value_start,
+/// value_len]`. `row` and `col` are 0-based (`B3` is row 2, column 1).
+/// `kind` is one of the `CELL_*` constants. The value text is
+/// `xml[value_start..value_start + value_len]`, still XML-escaped: the
+/// number, the shared-string index for `CELL_SHARED_STRING`, or the `<is>`
+/// contents for `CELL_INLINE_STRING`. Cells with only a style are skipped.
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes the list needs. Otherwise returns bytes written, or `-1`
+/// for malformed XML, a bad cell reference or type, or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn xlsx_sheet_cells(
+    xml_ptr: *const u8,
+    xml_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let xml = ffi::slice(xml_ptr, xml_len);
+    let mut cells = Vec::new();
+    if u32::try_from(xml_len).is_err() || sheet_cells(xml, &mut cells).is_none() {
+        return -1;
+    }
+    let needed = cells.len() * 4;
+    if out_len_bytes == 0 {
+        return needed as isize;
+    }
+    if out_len_bytes < needed {
+        return -1;
+    }
+    if ffi::aliased(xml_ptr, xml_len, out_ptr as *const u8, needed) {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(out_ptr, cells.len()).copy_from_slice(&cells);
+    needed as isize
+}
+
+/// Parse the value of each cell listed by `xlsx_sheet_cells` to an `f64`:
+/// numbers as written, shared-string indexes as the index, booleans as `0`
+/// or `1`. Other kinds and unparsable values become `NaN`.
+///
+/// Returns bytes written (`cells * 8`), or `-1` when the cell list is not a
+/// whole number of entries, a span lies outside the XML, or the output is
+/// short.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn xlsx_cell_numbers(
+    xml_ptr: *const u8,
+    xml_len: usize,
+    cells_ptr: *const u32,
+    cells_len_bytes: usize,
+    out_ptr: *mut f64,
+    out_len_bytes: usize,
+) -> isize {
+    let n = cells_len_bytes / (CELL_WORDS * 4);
+    if !cells_len_bytes.is_multiple_of(CELL_WORDS * 4) || out_len_bytes / 8 < n {
+        return -1;
+    }
+    if ffi::aliased(xml_ptr, xml_len, out_ptr as *const u8, n * 8)
+        || ffi::aliased(
+            cells_ptr as *const u8,
+            cells_len_bytes,
+            out_ptr as *const u8,
+            n * 8,
+        )
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    let xml = ffi::slice(xml_ptr, xml_len);
+    let cells = ffi::slice(cells_ptr, n * CELL_WORDS);
+    let spans: Option<Vec<_>> = cells
+        .chunks_exact(CELL_WORDS)
+        .map(|c| xml.get(c[3] as usize..)?.get(..c[4] as usize))
+        .collect();
+    let Some(spans) = spans else {
+        return -1;
+    };
+    let out = ffi::slice_mut(out_ptr, n);
+    for ((cell, value), o) in cells.chunks_exact(CELL_WORDS).zip(spans).zip(out) {
+        *o = match cell[2] {
+            CELL_NUMBER | CELL_SHARED_STRING | CELL_BOOLEAN => std::str::from_utf8(value)
+                .ok()
+                .and_then(|s| s.trim().parse().ok())
+                .unwrap_or(f64::NAN),
+            _ => f64::NAN,
+        };
+    }
+    (n * 8) as isize
+}
+
+/// Append `text` to `out` with XML character references and OOXML `_xHHHH_`
+/// escapes decoded.
+fn unescape(text: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        let decoded = match rest[0] {
+            b'&' => rest.iter().position(|&b| b == b';').and_then(|semi| {
+                let c = match &rest[1..semi] {
+                    b"amp" => '&',
+                    b"lt" => '<',
+                    b"gt" => '>',
+                    b"quot" => '"',
+                    b"apos" => '\'',
+                    [b'#', b'x' | b'X', hex @ ..] => hex_char(hex)?,
+                    [b'#', dec @ ..] => {
+                        char::from_u32(std::str::from_utf8(dec).ok()?.parse().ok()?)?
+                    }
+                    _ => return None,
+                };
+                Some((c, semi + 1))
+            }),
+            b'_' if rest.len() >= 7 && rest[1] == b'x' && rest[6] == b'_' => {
+                hex_char(&rest[2..6]).map(|c| (c, 7))
+            }
+            _ => None,
+        };
+        match decoded {
+            Some((c, len)) => {
+                out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                i += len;
+            }
+            None => {
+                out.push(rest[0]);
+                i += 1;
+            }
+        }
+    }
+}
+
+fn hex_char(hex: &[u8]) -> Option<char> {
+    char::from_u32(u32::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?)
+}
+
+/// Decode each `<si>` of a shared-string table: its `<t>` runs joined, minus
+/// phonetic (`<rPh>`) runs.
+fn shared_strings(xml: &[u8], text: &mut Vec<u8>, offsets: &mut Vec<u32>) -> Option<()> {
+    offsets.push(0);
+    let mut pos = 0;
+    // Some(phonetic) while inside an `<si>`.
+    let mut item: Option<bool> = None;
+    while let Some(lt) = find(xml, b"<", pos) {
+        let tag = tag(xml, lt)?;
+        pos = tag.end;
+        match (tag.closing, tag.name, item) {
+            (false, b"si", None) if tag.empty => offsets.push(text.len() as u32),
+            (false, b"si", None) => item = Some(false),
+            (true, b"si", Some(_)) => {
+                offsets.push(text.len() as u32);
+                item = None;
+            }
+            (false, b"rPh", Some(_)) if !tag.empty => item = Some(true),
+            (true, b"rPh", Some(_)) => item = Some(false),
+            (false, b"t", Some(false)) if !tag.empty => {
+                let close = find_close(xml, b"t", pos)?;
+                unescape(&xml[pos..close], text);
+                pos = close;
+            }
+            _ => {}
+        }
+    }
+    item.is_none().then_some(())
+}
+
+/// Decode the shared-string table XML at `xml_ptr` into a string batch: the
+/// UTF-8 strings back to back at `text_ptr` and `strings + 1` offsets at
+/// `offsets_ptr`, so string `i` (the index `CELL_SHARED_STRING` cells hold)
+/// is `text[offsets[i]..offsets[i + 1]]`. Entities are decoded; a text
+/// buffer of `xml_len` bytes always suffices.
+///
+/// With `offsets_len_bytes == 0` nothing is written and the return value is
+/// the number of bytes the offsets need. Otherwise returns bytes written to
+/// `offsets_ptr`, or `-1` for malformed XML or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn xlsx_shared_strings(
+    xml_ptr: *const u8,
+    xml_len: usize,
+    text_ptr: *mut u8,
+    text_len: usize,
+    offsets_ptr: *mut u32,
+    offsets_len_bytes: usize,
+) -> isize {
+    let xml = ffi::slice(xml_ptr, xml_len);
+    let (mut text, mut offsets) = (Vec::new(), Vec::new());
+    if u32::try_from(xml_len).is_err() || shared_strings(xml, &mut text, &mut offsets).is_none() {
+        return -1;
+    }
+    let needed = offsets.len() * 4;
+    if offsets_len_bytes == 0 {
+        return needed as isize;
+    }
+    if offsets_len_bytes < needed || text_len < text.len() {
+        return -1;
+    }
+    if ffi::aliased(xml_ptr, xml_len, text_ptr, text.len())
+        || ffi::aliased(xml_ptr, xml_len, offsets_ptr as *const u8, needed)
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    ffi::slice_mut(text_ptr, text.len()).copy_from_slice(&text);
+    ffi::slice_mut(offsets_ptr, offsets.len()).copy_from_slice(&offsets);
+    needed as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHEET: &[u8] = br#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>
+<row r="1" spans="1:3"><c r="A1" t="s"><v>0</v></c><c r="B1" s="2"/><c r="C1" t="b"><v>1</v></c></row>
+<row r="3"><c r="A3"><f>SUM(1,2)</f><v>3.5E2</v></c><c><v>-7</v></c>
+<c r="AA3" t="inlineStr"><is><t>a &amp; b</t></is></c><c r="D3" t="e"><v>#N/A</v></c></row>
+<row><c t="str"><v>x</v></c></row>
+</sheetData></worksheet>"#;
+
+    fn cells(xml: &[u8]) -> Option<Vec<u32>> {
+        let needed = unsafe { xlsx_sheet_cells(xml.as_ptr(), xml.len(), std::ptr::null_mut(), 0) };
+        let mut out = vec![0u32; (needed.max(0) / 4) as usize];
+        let written =
+            unsafe { xlsx_sheet_cells(xml.as_ptr(), xml.len(), out.as_mut_ptr(), needed as usize) };
+        (needed >= 0 && written == needed).then_some(out)
+    }
+
+    #[test]
+    fn lists_cells_with_values() {
+        let cells = cells(SHEET).unwrap();
+        let rows: Vec<_> = cells
+            .chunks(CELL_WORDS)
+            .map(|c| (c[0], c[1], c[2], &SHEET[c[3] as usize..][..c[4] as usize]))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (0, 0, CELL_SHARED_STRING, &b"0"[..]),
+                (0, 2, CELL_BOOLEAN, b"1"),
+                (2, 0, CELL_NUMBER, b"3.5E2"),
+                (2, 1, CELL_NUMBER, b"-7"),
+                (2, 26, CELL_INLINE_STRING, b"<t>a &amp; b</t>"),
+                (2, 3, CELL_ERROR, b"#N/A"),
+                (3, 0, CELL_STRING, b"x"),
+            ]
+        );
+
+        let mut numbers = vec![0f64; rows.len()];
+        let written = unsafe {
+            xlsx_cell_numbers(
+                SHEET.as_ptr(),
+                SHEET.len(),
+                cells.as_ptr(),
+                cells.len() * 4,
+                numbers.as_mut_ptr(),
+                numbers.len() * 8,
+            )
+        };
+        assert_eq!(written, 56);
+        assert_eq!(numbers[..4], [0.0, 1.0, 350.0, -7.0]);
+        assert!(numbers[4..].iter().all(|v| v.is_nan()));
+    }
+
+    #[test]
+    fn rejects_bad_sheets() {
+        assert_eq!(cells(br#"<c r="1A"><v>1</v></c>"#), None);
+        assert_eq!(cells(br#"<c r="A1" t="zz"><v>1</v></c>"#), None);
+        assert_eq!(cells(br#"<c r="A1"><v>1</v>"#), None, "unclosed cell");
+        assert_eq!(cells(br#"<c r="A1"#), None, "unclosed tag");
+        assert_eq!(cell_ref(b"XFD1048576"), Some((1_048_575, 16_383)));
+    }
+
+    #[test]
+    fn decodes_shared_strings() {
+        let xml = br#"<sst count="4" uniqueCount="4"><si><t>plain</t></si><si/>
+<si><r><rPr><b/></rPr><t>bold </t></r><r><t xml:space="preserve">&lt;run&gt;&#233;&#x1F600;_x000D_</t></r>
+<rPh sb="0" eb="1"><t>ignored</t></rPh></si><si><t/></si></sst>"#;
+        let mut text = vec![0u8; xml.len()];
+        let mut offsets = [0u32; 5];
+        let status = |offsets_len_bytes: usize, text: &mut [u8], offsets: &mut [u32]| unsafe {
+            xlsx_shared_strings(
+                xml.as_ptr(),
+                xml.len(),
+                text.as_mut_ptr(),
+                text.len(),
+                offsets.as_mut_ptr(),
+                offsets_len_bytes,
+            )
+        };
+        assert_eq!(status(0, &mut text, &mut offsets), 20);
+        assert_eq!(status(20, &mut text, &mut offsets), 20);
+        let strings: Vec<_> = offsets
+            .windows(2)
+            .map(|w| std::str::from_utf8(&text[w[0] as usize..w[1] as usize]).unwrap())
+            .collect();
+        assert_eq!(strings, ["plain", "", "bold <run>é😀\r", ""]);
+        assert_eq!(status(16, &mut text, &mut offsets), -1, "short offsets");
+    }
+}
@@ -0,0 +1,345 @@
+//! ZIP archive listing and extraction.
+//!
+//! `zip_list` reads the central directory at the end of an archive and
+//! `zip_extract` decompresses one entry, so a page can open a user's `.zip`,
+//! `.xlsx` or `.docx` where its bytes sit in wasm memory. Stored and DEFLATE
+//! entries are supported; the other methods, encryption, multi-disk archives
+//! and ZIP64 fields are rejected. All integers are little-endian.
+
+use super::inflate::inflate;
+use crate::{cancel, ffi};
+
+/// `u32` words per entry in `zip_list` output.
+const ENTRY_WORDS: usize = 6;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+fn u16_at(buf: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        buf.get(pos..pos + 2)?.try_into().unwrap(),
+    ))
+}
+
+fn u32_at(buf: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        buf.get(pos..pos + 4)?.try_into().unwrap(),
+    ))
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 (IEEE), as stored in ZIP headers.
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |c, &b| {
+        CRC_TABLE[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8)
+    })
+}
+
+/// One central directory record.
+struct Entry {
+    name_start: usize,
+    name_len: usize,
+    flags: u16,
+    method: u16,
+    crc: u32,
+    compressed: u32,
+    size: u32,
+    local_offset: u32,
+}
+
+/// Position of the end-of-central-directory record: the last signature that
+/// leaves room for its own comment.
+fn find_end(buf: &[u8]) -> Option<usize> {
+    let last = buf.len().checked_sub(22)?;
+    // The comment is at most 65535 bytes.
+    (last.saturating_sub(0xffff)..=last).rev().find(|&pos| {
+        u32_at(buf, pos) == Some(END_OF_DIRECTORY)
+            && pos + 22 + u16_at(buf, pos + 20).unwrap() as usize <= buf.len()
+    })
+}
+
+/// Parse the central directory.
+fn entries(buf: &[u8]) -> Option<Vec<Entry>> {
+    let end = find_end(buf)?;
+    let disk = u16_at(buf, end + 4)?;
+    let directory_disk = u16_at(buf, end + 6)?;
+    let count = u16_at(buf, end + 10)?;
+    let offset = u32_at(buf, end + 16)?;
+    if disk != 0 || directory_disk != 0 || offset == u32::MAX {
+        return None;
+    }
+    let mut pos = offset as usize;
+    let mut list = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        if u32_at(buf, pos)? != CENTRAL_HEADER {
+            return None;
+        }
+        let name_len = u16_at(buf, pos + 28)? as usize;
+        let extra_len = u16_at(buf, pos + 30)? as usize;
+        let comment_len = u16_at(buf, pos + 32)? as usize;
+        let entry = Entry {
+            name_start: pos + 46,
+            name_len,
+            flags: u16_at(buf, pos + 8)?,
+            method: u16_at(buf, pos + 10)?,
+            crc: u32_at(buf, pos + 16)?,
+            compressed: u32_at(buf, pos + 20)?,
+            size: u32_at(buf, pos + 24)?,
+            local_offset: u32_at(buf, pos + 42)?,
+        };
+        // All-ones sizes and offsets defer to a ZIP64 extra field.
+        if [entry.compressed, entry.size, entry.local_offset].contains(&u32::MAX) {
+            return None;
+        }
+        pos = entry.name_start + name_len + extra_len + comment_len;
+        if pos > end {
+            return None;
+        }
+        list.push(entry);
+    }
+    Some(list)
+}
+
+/// Decompress `entry` and check its CRC.
+fn extract(buf: &[u8], entry: &Entry) -> Option<Vec<u8>> {
+    // Bit 0 marks an encrypted entry.
+    if entry.flags & 1 != 0 {
+        return None;
+    }
+    let local = entry.local_offset as usize;
+    if u32_at(buf, local)? != LOCAL_HEADER {
+        return None;
+    }
+    // The local header's name and extra field may differ from the central
+    // ones; its sizes may be zero when a data descriptor follows the data, so
+    // the central sizes are used.
+    let start = local + 30 + u16_at(buf, local + 26)? as usize + u16_at(buf, local + 28)? as usize;
+    let data = buf.get(start..)?.get(..entry.compressed as usize)?;
+    let size = entry.size as usize;
+    let out = match entry.method {
+        STORED if data.len() == size => data.to_vec(),
+        DEFLATED => {
+            let mut out = Vec::with_capacity(size);
+            inflate(data, &mut out, size)?;
+            out
+        }
+        _ => return None,
+    };
+    (out.len() == size && crc32(&out) == entry.crc).then_some(out)
+}
+
+/// List the entries of the ZIP archive at `in_ptr` in central directory
+/// order, six `u32`s each: `[name_start, name_len, method, compressed_size,
+/// uncompressed_size, crc32]`. The name's bytes sit at
+/// `in[name_start..name_start + name_len]` (UTF-8 when general-purpose flag
+/// bit 11 is set, usually CP437 otherwise); directories end in `/`. Method
+/// `0` is stored and `8` DEFLATE, the two `zip_extract` handles.
+///
+/// With `out_len_bytes == 0` nothing is written and the return value is the
+/// number of bytes the list needs. Otherwise returns bytes written, or `-1`
+/// for a buffer without a readable central directory (including ZIP64 and
+/// multi-disk archives) or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn zip_list(
+    in_ptr: *const u8,
+    in_len: usize,
+    out_ptr: *mut u32,
+    out_len_bytes: usize,
+) -> isize {
+    let buf = ffi::slice(in_ptr, in_len);
+    let Some(list) = entries(buf).filter(|_| u32::try_from(in_len).is_ok()) else {
+        return -1;
+    };
+    let needed = list.len() * ENTRY_WORDS * 4;
+    if out_len_bytes == 0 {
+        return needed as isize;
+    }
+    if out_len_bytes < needed {
+        return -1;
+    }
+    if ffi::aliased(in_ptr, in_len, out_ptr as *const u8, needed) {
+        return ffi::ALIAS_ERROR;
+    }
+    let out = ffi::slice_mut(out_ptr, list.len() * ENTRY_WORDS);
+    for (e, words) in list.iter().zip(out.chunks_exact_mut(ENTRY_WORDS)) {
+        words.copy_from_slice(&[
+            e.name_start as u32,
+            e.name_len as u32,
+            e.method as u32,
+            e.compressed,
+            e.size,
+            e.crc,
+        ]);
+    }
+    needed as isize
+}
+
+/// Extract entry `entry_index` (its position in `zip_list` order) of the ZIP
+/// archive at `in_ptr` into `out_ptr`, which needs its `uncompressed_size`
+/// bytes.
+///
+/// Returns bytes written, or `-1` for a bad archive or index, an encrypted
+/// entry or one using another method, corrupt data, a CRC mismatch or a
+/// short output, or `CANCELLED` (`-3`) when cancellation was requested
+/// while inflating. Nothing is written on error.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn zip_extract(
+    in_ptr: *const u8,
+    in_len: usize,
+    entry_index: u32,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let buf = ffi::slice(in_ptr, in_len);
+    let Some(entry) = entries(buf).and_then(|mut list| {
+        let i = entry_index as usize;
+        (i < list.len()).then(|| list.swap_remove(i))
+    }) else {
+        return -1;
+    };
+    let size = entry.size as usize;
+    if out_len < size {
+        return -1;
+    }
+    if ffi::aliased(in_ptr, in_len, out_ptr, size) {
+        return ffi::ALIAS_ERROR;
+    }
+    let Some(data) = extract(buf, &entry) else {
+        return cancel::failed();
+    };
+    ffi::slice_mut(out_ptr, size).copy_from_slice(&data);
+    size as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build an archive of `(name, method, stored bytes, contents)` entries
+    /// behind a two-byte prefix and with an archive comment.
+    fn archive(files: &[(&str, u16, &[u8], &[u8])]) -> Vec<u8> {
+        let mut buf = b"MZ".to_vec();
+        let mut directory = Vec::new();
+        for &(name, method, data, contents) in files {
+            let offset = buf.len() as u32;
+            let fields = |sig: u32| {
+                [
+                    &sig.to_le_bytes()[..],
+                    &[20, 0, 20, 0][..2 + 2 * (sig == CENTRAL_HEADER) as usize],
+                    &[0, 0],
+                    &method.to_le_bytes(),
+                    &[0; 4],
+                    &crc32(contents).to_le_bytes(),
+                    &(data.len() as u32).to_le_bytes(),
+                    &(contents.len() as u32).to_le_bytes(),
+                    &(name.len() as u16).to_le_bytes(),
+                    &[0, 0],
+                ]
+                .concat()
+            };
+            buf.extend(fields(LOCAL_HEADER));
+            buf.extend(name.as_bytes());
+            buf.extend(data);
+            directory.extend(fields(CENTRAL_HEADER));
+            directory.extend([0; 10]);
+            directory.extend(offset.to_le_bytes());
+            directory.extend(name.as_bytes());
+        }
+        let offset = buf.len() as u32;
+        buf.extend(&directory);
+        buf.extend(END_OF_DIRECTORY.to_le_bytes());
+        buf.extend([0; 4]);
+        buf.extend([files.len() as u8, 0, files.len() as u8, 0]);
+        buf.extend((directory.len() as u32).to_le_bytes());
+        buf.extend(offset.to_le_bytes());
+        buf.extend([3, 0]);
+        buf.extend(b"end");
+        buf
+    }
+
+    fn list(buf: &[u8]) -> Option<Vec<u32>> {
+        let needed = unsafe { zip_list(buf.as_ptr(), buf.len(), std::ptr::null_mut(), 0) };
+        let mut out = vec![0u32; (needed.max(0) / 4) as usize];
+        let written =
+            unsafe { zip_list(buf.as_ptr(), buf.len(), out.as_mut_ptr(), needed as usize) };
+        (needed >= 0 && written == needed).then_some(out)
+    }
+
+    fn extract_entry(buf: &[u8], index: u32, out_len: usize) -> Option<Vec<u8>> {
+        let mut out = vec![0u8; out_len];
+        let written =
+            unsafe { zip_extract(buf.as_ptr(), buf.len(), index, out.as_mut_ptr(), out_len) };
+        (written >= 0).then(|| out[..written as usize].to_vec())
+    }
+
+    #[test]
+    fn computes_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn lists_and_extracts_entries() {
+        // Raw DEFLATE of "abcabcabc".
+        let deflated = [0x4b, 0x4c, 0x4a, 0x4e, 0x04, 0x23, 0x00];
+        let buf = archive(&[
+            ("docs/", STORED, b"", b""),
+            ("a.txt", STORED, b"hello", b"hello"),
+            ("b.txt", DEFLATED, &deflated, b"abcabcabc"),
+        ]);
+        let entries = list(&buf).unwrap();
+        assert_eq!(entries.len(), 3 * ENTRY_WORDS);
+        let b = &entries[2 * ENTRY_WORDS..];
+        assert_eq!(&buf[b[0] as usize..][..b[1] as usize], b"b.txt");
+        assert_eq!(&b[2..5], [8, 7, 9]);
+        assert_eq!(b[5], crc32(b"abcabcabc"));
+
+        assert_eq!(extract_entry(&buf, 0, 0).unwrap(), b"");
+        assert_eq!(extract_entry(&buf, 1, 5).unwrap(), b"hello");
+        assert_eq!(extract_entry(&buf, 2, 16).unwrap(), b"abcabcabc");
+        assert_eq!(extract_entry(&buf, 2, 8), None, "short output");
+        assert_eq!(extract_entry(&buf, 3, 16), None, "no such entry");
+    }
+
+    #[test]
+    fn rejects_bad_archives() {
+        let bad_crc = archive(&[("a", STORED, b"hello", b"hellO")]);
+        assert!(list(&bad_crc).is_some());
+        assert_eq!(extract_entry(&bad_crc, 0, 5), None);
+        let bad_method = archive(&[("a", 12, b"hello", b"hello")]);
+        assert_eq!(extract_entry(&bad_method, 0, 5), None);
+
+        let buf = archive(&[("a", STORED, b"hello", b"hello")]);
+        assert_eq!(list(&buf[..buf.len() - 10]), None, "truncated");
+        assert_eq!(list(b"not a zip"), None);
+        let mut encrypted = buf.clone();
+        let central = find_end(&buf).unwrap() - 47;
+        encrypted[central + 8] = 1;
+        assert_eq!(extract_entry(&encrypted, 0, 5), None);
+    }
+}
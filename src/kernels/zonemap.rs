@@ -0,0 +1,244 @@
+//! Min/max zone maps for skipping blocks in predicate scans.
+//!
+//! `build_zone_map` records the smallest and largest value of each
+//! fixed-size block of an `f64` column once. `filter_range_f64` then checks
+//! each block's range against the predicate before touching its rows: a
+//! block entirely outside is skipped, and one entirely inside is accepted
+//! without comparisons. On sorted or clustered data (timestamps, ids) most
+//! blocks fall in one of the two cases, so a scan only compares the rows of
+//! the few blocks straddling a bound.
+
+use crate::error::{self, ErrorCode};
+use crate::ffi;
+
+/// Write one `(min, max)` `f64` pair per `block_size` rows of the column at
+/// `values_ptr`, the last block possibly shorter. `NaN`s are ignored; a
+/// block of only `NaN`s gets `(NaN, NaN)` and never matches a predicate.
+///
+/// Returns bytes written, or `-1` for a partial value, a zero
+/// `block_size` or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn build_zone_map(
+    values_ptr: *const f64,
+    values_len_bytes: usize,
+    block_size: u32,
+    out_ptr: *mut f64,
+    out_len_bytes: usize,
+) -> isize {
+    if !values_len_bytes.is_multiple_of(8) {
+        return error::fail(ErrorCode::BadLength);
+    }
+    if block_size == 0 {
+        return error::fail(ErrorCode::InvalidArgument);
+    }
+    let n = values_len_bytes / 8;
+    let needed = n.div_ceil(block_size as usize) * 16;
+    if out_len_bytes < needed {
+        return error::fail(ErrorCode::ShortOutput);
+    }
+    if ffi::aliased(
+        values_ptr as *const u8,
+        values_len_bytes,
+        out_ptr as *const u8,
+        needed,
+    ) {
+        return ffi::ALIAS_ERROR;
+    }
+    let values = ffi::slice(values_ptr, n);
+    let out = ffi::slice_mut(out_ptr, needed / 8);
+    for (block, zone) in values
+        .chunks(block_size as usize)
+        .zip(out.chunks_exact_mut(2))
+    {
+        // `f64::min` skips `NaN` operands, so only an all-`NaN` block keeps
+        // the initial `NaN`s.
+        let (min, max) = block
+            .iter()
+            .fold((f64::NAN, f64::NAN), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        zone.copy_from_slice(&[min, max]);
+    }
+    needed as isize
+}
+
+/// Set bit `i` (bit `i % 8` of byte `i / 8`) of the bitmap at `out_ptr` for
+/// each row with `lo <= values[i] <= hi`; pass infinities for one-sided
+/// ranges. `NaN` rows never match.
+///
+/// With a zone map from `build_zone_map` at `zones_ptr` (built with the same
+/// `block_size` over the current values), blocks whose range misses
+/// `[lo, hi]` are skipped and blocks inside it are accepted without
+/// comparing each row. Pass `zones_len_bytes == 0` to scan every row.
+///
+/// Returns bytes written, or `-1` for a partial value, a zone map of the
+/// wrong length, a zero `block_size` or a short output.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub unsafe extern "C" fn filter_range_f64(
+    values_ptr: *const f64,
+    values_len_bytes: usize,
+    zones_ptr: *const f64,
+    zones_len_bytes: usize,
+    block_size: u32,
+    lo: f64,
+    hi: f64,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    if !values_len_bytes.is_multiple_of(8) {
+        return error::fail(ErrorCode::BadLength);
+    }
+    if block_size == 0 {
+        return error::fail(ErrorCode::InvalidArgument);
+    }
+    let n = values_len_bytes / 8;
+    let block = block_size as usize;
+    if zones_len_bytes != 0 && zones_len_bytes != n.div_ceil(block) * 16 {
+        return error::fail(ErrorCode::BadLength);
+    }
+    let bytes = n.div_ceil(8);
+    if out_len < bytes {
+        return error::fail(ErrorCode::ShortOutput);
+    }
+    if ffi::aliased(values_ptr as *const u8, values_len_bytes, out_ptr, bytes)
+        || ffi::aliased(zones_ptr as *const u8, zones_len_bytes, out_ptr, bytes)
+    {
+        return ffi::ALIAS_ERROR;
+    }
+    let values = ffi::slice(values_ptr, n);
+    let zones = ffi::slice(zones_ptr, zones_len_bytes / 8);
+    let out = ffi::slice_mut(out_ptr, bytes);
+    out.fill(0);
+    for (b, rows) in values.chunks(block).enumerate() {
+        let start = b * block;
+        let inside = match zones.get(2 * b..2 * b + 2) {
+            // `NaN` bounds fail both comparisons and fall through to a skip.
+            Some(&[min, max]) if !(max >= lo && min <= hi) => continue,
+            Some(&[min, max]) => min >= lo && max <= hi,
+            _ => false,
+        };
+        for (i, &v) in rows.iter().enumerate() {
+            // Inside a zone every value is in range; only `NaN`s are out.
+            let hit = if inside {
+                !v.is_nan()
+            } else {
+                v >= lo && v <= hi
+            };
+            if hit {
+                let row = start + i;
+                out[row / 8] |= 1 << (row % 8);
+            }
+        }
+    }
+    bytes as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone_map(values: &[f64], block_size: u32) -> Vec<f64> {
+        let mut out = vec![0f64; values.len().div_ceil(block_size as usize) * 2];
+        let written = unsafe {
+            build_zone_map(
+                values.as_ptr(),
+                values.len() * 8,
+                block_size,
+                out.as_mut_ptr(),
+                out.len() * 8,
+            )
+        };
+        assert_eq!(written, out.len() as isize * 8);
+        out
+    }
+
+    fn filter(values: &[f64], zones: &[f64], block_size: u32, lo: f64, hi: f64) -> Vec<usize> {
+        let mut out = vec![0xffu8; values.len().div_ceil(8)];
+        let written = unsafe {
+            filter_range_f64(
+                values.as_ptr(),
+                values.len() * 8,
+                zones.as_ptr(),
+                zones.len() * 8,
+                block_size,
+                lo,
+                hi,
+                out.as_mut_ptr(),
+                out.len(),
+            )
+        };
+        assert_eq!(written, out.len() as isize);
+        (0..values.len())
+            .filter(|&i| out[i / 8] >> (i % 8) & 1 == 1)
+            .collect()
+    }
+
+    #[test]
+    fn builds_min_max_per_block() {
+        let values = [3.0, -1.0, f64::NAN, 7.0, f64::NAN, f64::NAN, 2.5];
+        let zones = zone_map(&values, 2);
+        assert_eq!(zones[..4], [-1.0, 3.0, 7.0, 7.0]);
+        assert!(zones[4].is_nan() && zones[5].is_nan(), "all-NaN block");
+        assert_eq!(zones[6..], [2.5, 2.5]);
+    }
+
+    #[test]
+    fn zone_map_matches_a_full_scan() {
+        // Mostly ascending with some noise and gaps, like timestamps.
+        let values: Vec<f64> = (0..1000)
+            .map(|i| match i % 97 {
+                0 => f64::NAN,
+                r => i as f64 + (r % 5) as f64,
+            })
+            .collect();
+        let zones = zone_map(&values, 64);
+        for (lo, hi) in [
+            (100.0, 300.0),
+            (f64::NEG_INFINITY, 5.0),
+            (990.0, f64::INFINITY),
+            (2000.0, 3000.0),
+            (5.0, 4.0),
+        ] {
+            let expected: Vec<usize> = (0..values.len())
+                .filter(|&i| values[i] >= lo && values[i] <= hi)
+                .collect();
+            assert_eq!(
+                filter(&values, &zones, 64, lo, hi),
+                expected,
+                "[{lo}, {hi}]"
+            );
+            assert_eq!(filter(&values, &[], 64, lo, hi), expected, "no zone map");
+        }
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let values = [1.0, 2.0, 3.0];
+        let mut out = [0f64; 4];
+        let build = |len, block, out_len, out: &mut [f64; 4]| unsafe {
+            build_zone_map(values.as_ptr(), len, block, out.as_mut_ptr(), out_len)
+        };
+        assert_eq!(build(20, 2, 32, &mut out), -1, "partial value");
+        assert_eq!(build(24, 0, 32, &mut out), -1, "zero block size");
+        assert_eq!(build(24, 2, 16, &mut out), -1, "short output");
+        assert_eq!(build(24, 2, 32, &mut out), 32);
+
+        let mut bitmap = [0u8; 1];
+        let run = |zones_len, bitmap: &mut [u8; 1]| unsafe {
+            filter_range_f64(
+                values.as_ptr(),
+                24,
+                out.as_ptr(),
+                zones_len,
+                2,
+                0.0,
+                9.0,
+                bitmap.as_mut_ptr(),
+                1,
+            )
+        };
+        assert_eq!(run(16, &mut bitmap), -1, "zone map for another block size");
+        assert_eq!(run(32, &mut bitmap), 1);
+        assert_eq!(bitmap, [0b111]);
+    }
+}
@@ -1,22 +1,235 @@
-use std::alloc::{alloc, dealloc, Layout};
+use std::alloc::{alloc, alloc_zeroed, dealloc, realloc, Layout};
+use std::cell::Cell;
 use std::mem;
+use std::ptr::{self, NonNull};
+
+use error::ErrorCode;
+
+mod abi;
+mod arena;
+mod cancel;
+mod error;
+mod ffi;
+mod handles;
+#[cfg(feature = "heap-stats")]
+mod heap;
+mod iovec;
+mod kernels;
+#[cfg(feature = "log")]
+mod logging;
+#[cfg(feature = "multi-value")]
+mod multi_value;
+mod packed;
+mod panics;
+mod progress;
+mod scratch;
+
+/// `alloc_last_error` codes.
+pub const ALLOC_OK: u32 = 0;
+/// The requested length exceeds `isize::MAX`, or the alignment is not a
+/// power of two.
+pub const ALLOC_BAD_LAYOUT: u32 = ErrorCode::BadLayout as u32;
+/// The allocator could not grow linear memory.
+pub const ALLOC_OUT_OF_MEMORY: u32 = ErrorCode::OutOfMemory as u32;
+
+thread_local! {
+    static LAST_ALLOC_ERROR: Cell<u32> = const { Cell::new(ALLOC_OK) };
+}
+
+/// Record an allocation outcome for `alloc_last_error`; failures are also
+/// recorded for `last_error_code`, whose codes agree.
+fn set_alloc_status(status: u32) {
+    LAST_ALLOC_ERROR.set(status);
+    match status {
+        ALLOC_BAD_LAYOUT => error::record(ErrorCode::BadLayout),
+        ALLOC_OUT_OF_MEMORY => error::record(ErrorCode::OutOfMemory),
+        _ => {}
+    }
+}
 
 #[no_mangle]
 /// # Safety
 /// This function is unsafe because it allocates memory using the global allocator and returns a raw pointer.
 /// The caller must ensure that the memory is eventually deallocated using `free_bytes` with the same length.
+///
+/// Returns a null pointer instead of aborting when the length is too large
+/// or memory cannot grow; `alloc_last_error` then says which. A zero length
+/// returns a dangling non-null pointer that must not be read or written.
 pub unsafe extern "C" fn alloc_bytes(len: usize) -> *mut u8 {
-    let layout = Layout::from_size_align(len, mem::align_of::<u8>()).unwrap();
-    alloc(layout)
+    alloc_with_align(len, mem::align_of::<u8>(), false)
+}
+
+#[no_mangle]
+/// # Safety
+/// Same contract as `alloc_bytes`; release the block with `free_bytes`.
+///
+/// Like `alloc_bytes`, but the block is zero-filled. Fresh pages from
+/// `memory.grow` are already zero, so this is usually cheaper than clearing
+/// a typed array view from JS.
+pub unsafe extern "C" fn alloc_zeroed_bytes(len: usize) -> *mut u8 {
+    alloc_with_align(len, mem::align_of::<u8>(), true)
+}
+
+/// Shared body of the `alloc_` exports: records the outcome in
+/// `set_alloc_status` and returns null on failure.
+unsafe fn alloc_with_align(len: usize, align: usize, zeroed: bool) -> *mut u8 {
+    let (ptr, status) = match Layout::from_size_align(len, align) {
+        Err(_) => (ptr::null_mut(), ALLOC_BAD_LAYOUT),
+        // A well-aligned dangling pointer, like `NonNull::dangling` for `u8`.
+        Ok(_) if len == 0 => (ptr::without_provenance_mut(align), ALLOC_OK),
+        Ok(layout) => {
+            let ptr = if zeroed {
+                alloc_zeroed(layout)
+            } else {
+                alloc(layout)
+            };
+            let status = if ptr.is_null() {
+                ALLOC_OUT_OF_MEMORY
+            } else {
+                ALLOC_OK
+            };
+            (ptr, status)
+        }
+    };
+    set_alloc_status(status);
+    ptr
+}
+
+unsafe fn free_with_align(ptr: *mut u8, len: usize, align: usize) {
+    let Ok(layout) = Layout::from_size_align(len, align) else {
+        return;
+    };
+    if !ptr.is_null() && len > 0 {
+        dealloc(ptr, layout);
+    }
 }
 
 #[no_mangle]
 /// # Safety
 /// This function is unsafe because it deallocates memory using a raw pointer.
 /// The caller must ensure that `ptr` was previously allocated by `alloc_bytes` and that `len` is the same as when it was allocated.
+/// Null pointers and zero lengths, which `alloc_bytes` never backs with memory, are ignored.
 pub unsafe extern "C" fn free_bytes(ptr: *mut u8, len: usize) {
-    let layout = Layout::from_size_align(len, mem::align_of::<u8>()).unwrap();
-    dealloc(ptr, layout);
+    free_with_align(ptr, len, mem::align_of::<u8>());
+}
+
+#[no_mangle]
+/// # Safety
+/// The returned block must be released with `free_aligned` passing the same
+/// `len` and `align`, never with `free_bytes`.
+///
+/// Like `alloc_bytes`, but the block starts on a multiple of `align`, which
+/// must be a power of two: 16 lets SIMD kernels use aligned `v128` loads, 4
+/// or 8 lets JS view the block as `Uint32Array` or `Float64Array`. Returns
+/// null with `ALLOC_BAD_LAYOUT` for any other `align`.
+pub unsafe extern "C" fn alloc_aligned(len: usize, align: usize) -> *mut u8 {
+    alloc_with_align(len, align, false)
+}
+
+#[no_mangle]
+/// # Safety
+/// `ptr` must have come from `alloc_aligned` with the same `len` and `align`.
+/// Null pointers and zero lengths are ignored.
+pub unsafe extern "C" fn free_aligned(ptr: *mut u8, len: usize, align: usize) {
+    free_with_align(ptr, len, align);
+}
+
+#[no_mangle]
+/// # Safety
+/// `ptr` must have been returned by `alloc_bytes` or `realloc_bytes` with
+/// `old_len`, and must not be used again after this call succeeds.
+///
+/// Grows or shrinks the block, in place when the allocator can, and returns
+/// its new address; the first `min(old_len, new_len)` bytes are preserved. A
+/// null `ptr` or zero `old_len` behaves like `alloc_bytes(new_len)`, and a
+/// zero `new_len` frees the block. On failure the old block is left intact,
+/// null is returned and `alloc_last_error` says why.
+pub unsafe extern "C" fn realloc_bytes(ptr: *mut u8, old_len: usize, new_len: usize) -> *mut u8 {
+    if ptr.is_null() || old_len == 0 {
+        return alloc_bytes(new_len);
+    }
+    if new_len == 0 {
+        free_bytes(ptr, old_len);
+        set_alloc_status(ALLOC_OK);
+        return NonNull::dangling().as_ptr();
+    }
+    let (Ok(layout), Ok(_)) = (
+        Layout::from_size_align(old_len, mem::align_of::<u8>()),
+        Layout::from_size_align(new_len, mem::align_of::<u8>()),
+    ) else {
+        set_alloc_status(ALLOC_BAD_LAYOUT);
+        return ptr::null_mut();
+    };
+    let grown = realloc(ptr, layout, new_len);
+    set_alloc_status(if grown.is_null() {
+        ALLOC_OUT_OF_MEMORY
+    } else {
+        ALLOC_OK
+    });
+    grown
+}
+
+/// Why the last `alloc_bytes`, `alloc_zeroed_bytes`, `alloc_aligned` or
+/// `realloc_bytes` call returned null:
+/// `ALLOC_BAD_LAYOUT` or `ALLOC_OUT_OF_MEMORY`, or `ALLOC_OK` when it succeeded.
+#[no_mangle]
+pub extern "C" fn alloc_last_error() -> u32 {
+    LAST_ALLOC_ERROR.get()
+}
+
+/// Store a copy of the `in_len` bytes at `in_ptr` behind a new handle, the
+/// generic counterpart of the typed constructors in `handles`. Data that
+/// spans calls (a partial record, a dictionary, a key) then stays in wasm
+/// memory instead of being re-sent; Rust reads it with
+/// `handles::with(handle, |bytes: &mut Vec<u8>| ...)`.
+///
+/// Returns the handle, or `-1` when memory cannot grow. Release it with
+/// `handle_drop`.
+///
+/// # Safety
+/// `in_ptr` must point to `in_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn handle_create(in_ptr: *const u8, in_len: usize) -> isize {
+    let mut bytes = Vec::new();
+    if bytes.try_reserve_exact(in_len).is_err() {
+        return error::fail(ErrorCode::OutOfMemory);
+    }
+    bytes.extend_from_slice(ffi::slice(in_ptr, in_len));
+    match handles::insert(bytes) {
+        0 => -1,
+        handle => handle as isize,
+    }
+}
+
+/// Append the `in_len` bytes at `in_ptr` to a `handle_create` handle.
+/// Returns the new length, or `-1` for an unknown handle or when memory
+/// cannot grow.
+///
+/// # Safety
+/// `in_ptr` must point to `in_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn handle_append(handle: u32, in_ptr: *const u8, in_len: usize) -> isize {
+    let input = ffi::slice(in_ptr, in_len);
+    handles::with(handle, |bytes: &mut Vec<u8>| {
+        if bytes.try_reserve(in_len).is_err() {
+            return error::fail(ErrorCode::OutOfMemory);
+        }
+        bytes.extend_from_slice(input);
+        bytes.len() as isize
+    })
+    .unwrap_or(-1)
+}
+
+/// The bytes behind a `handle_create` handle, packed as in `packed`. The
+/// view is valid until the next `handle_append` or `handle_drop` on it.
+/// Returns `0` for an unknown handle.
+#[no_mangle]
+pub extern "C" fn handle_bytes_packed(handle: u32) -> u64 {
+    handles::with(handle, |bytes: &mut Vec<u8>| {
+        // A non-null pointer even when empty, so `0` stays the failure.
+        packed::pack_slice(bytes.as_ptr(), bytes.len())
+    })
+    .unwrap_or(packed::PACKED_NONE)
 }
 
 /// A simple example function that "processes" bytes.
@@ -27,16 +240,23 @@ pub unsafe extern "C" fn free_bytes(ptr: *mut u8, len: usize) {
 /// The caller must ensure that:
 /// - `in_ptr` points to at least `in_len` bytes of valid memory.
 /// - `out_ptr` points to at least `in_len` bytes of valid memory.
-/// - The memory ranges do not overlap, or if they do, the behavior is acceptable.
+/// - The memory ranges do not overlap. `strict-aliasing` builds return
+///   `ALIAS_ERROR` (-2) when they do.
 #[no_mangle]
 pub unsafe extern "C" fn process_bytes(
     in_ptr: *const u8,
     in_len: usize,
     out_ptr: *mut u8,
-    _out_len: usize,
+    out_len: usize,
 ) -> isize {
-    let input = std::slice::from_raw_parts(in_ptr, in_len);
-    let output = std::slice::from_raw_parts_mut(out_ptr, in_len);
+    if out_len < in_len {
+        return error::fail(ErrorCode::ShortOutput);
+    }
+    if ffi::aliased(in_ptr, in_len, out_ptr, in_len) {
+        return ffi::ALIAS_ERROR;
+    }
+    let input = ffi::slice(in_ptr, in_len);
+    let output = ffi::slice_mut(out_ptr, in_len);
 
     // Just a simple transformation for demonstration
     for i in 0..in_len {
@@ -46,6 +266,40 @@ pub unsafe extern "C" fn process_bytes(
     in_len as isize
 }
 
+/// Scatter/gather form of `process_bytes`: transforms the stream split across
+/// the `iov_count` chunks at `iov_ptr` (see `iovec`) into one contiguous
+/// output, as if the chunks had been concatenated first.
+///
+/// # Safety
+/// `iov_ptr` must point to `iov_count` `(ptr, len)` entries describing
+/// readable chunks, and `out_ptr` to `out_len` writable bytes that no chunk
+/// overlaps. `strict-aliasing` builds return `ALIAS_ERROR` (-2) when one
+/// does.
+#[no_mangle]
+pub unsafe extern "C" fn process_bytes_iov(
+    iov_ptr: *const iovec::IoVec,
+    iov_count: usize,
+    out_ptr: *mut u8,
+    out_len: usize,
+) -> isize {
+    let stream = iovec::Stream::new(iov_ptr, iov_count);
+    let Some(in_len) = stream.len().filter(|&len| len <= out_len) else {
+        return error::fail(ErrorCode::ShortOutput);
+    };
+    if stream.aliased(out_ptr, in_len) {
+        return ffi::ALIAS_ERROR;
+    }
+    let output = ffi::slice_mut(out_ptr, in_len);
+    let mut at = 0;
+    for chunk in stream.chunks() {
+        for (out, byte) in output[at..at + chunk.len()].iter_mut().zip(chunk) {
+            *out = byte.wrapping_add(1);
+        }
+        at += chunk.len();
+    }
+    in_len as isize
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -69,4 +323,144 @@ mod tests {
             free_bytes(out_ptr, in_len);
         }
     }
+
+    #[test]
+    fn test_process_bytes_iov() {
+        let chunks: [&[u8]; 3] = [b"he", b"", b"llo"];
+        let iov = chunks.map(|c| iovec::IoVec {
+            ptr: c.as_ptr(),
+            len: c.len(),
+        });
+        let mut out = [0u8; 5];
+        unsafe {
+            assert_eq!(process_bytes_iov(iov.as_ptr(), 3, out.as_mut_ptr(), 5), 5);
+            assert_eq!(&out, b"ifmmp");
+            assert_eq!(process_bytes_iov(iov.as_ptr(), 3, out.as_mut_ptr(), 4), -1);
+            assert_eq!(
+                process_bytes_iov(std::ptr::null(), 0, out.as_mut_ptr(), 0),
+                0
+            );
+        }
+    }
+
+    #[test]
+    fn test_handle_create() {
+        // Packed pointers are truncated on 64-bit hosts, so compare the
+        // packed value rather than reading through it.
+        let view = |handle| {
+            let (packed, bytes) = handles::with(handle, |b: &mut Vec<u8>| {
+                (packed::pack_slice(b.as_ptr(), b.len()), b.clone())
+            })
+            .unwrap();
+            assert_eq!(handle_bytes_packed(handle), packed);
+            assert_ne!(packed, packed::PACKED_NONE);
+            bytes
+        };
+        unsafe {
+            let handle = handle_create(b"ab".as_ptr(), 2);
+            assert!(handle > 0);
+            let handle = handle as u32;
+            assert_eq!(handle_append(handle, b"cd".as_ptr(), 2), 4);
+            assert_eq!(view(handle), b"abcd");
+
+            let empty = handle_create(std::ptr::null(), 0) as u32;
+            assert_eq!(view(empty), b"");
+
+            assert_eq!(handles::handle_drop(handle), 0);
+            assert_eq!(handle_append(handle, b"e".as_ptr(), 1), -1);
+            assert_eq!(handle_bytes_packed(handle), packed::PACKED_NONE);
+            assert_eq!(handles::handle_drop(empty), 0);
+        }
+    }
+
+    #[test]
+    fn test_alloc_failure_returns_null() {
+        unsafe {
+            assert!(alloc_bytes(usize::MAX).is_null());
+            assert_eq!(alloc_last_error(), ALLOC_BAD_LAYOUT);
+
+            let empty = alloc_bytes(0);
+            assert!(!empty.is_null());
+            assert_eq!(alloc_last_error(), ALLOC_OK);
+            free_bytes(empty, 0);
+
+            let ptr = alloc_bytes(16);
+            assert!(!ptr.is_null());
+            free_bytes(ptr, 16);
+            free_bytes(std::ptr::null_mut(), 16);
+        }
+    }
+
+    #[test]
+    fn test_alloc_zeroed_bytes() {
+        unsafe {
+            // Dirty a block first so the allocator may hand the same memory back.
+            let dirty = alloc_bytes(4096);
+            std::ptr::write_bytes(dirty, 0xAB, 4096);
+            free_bytes(dirty, 4096);
+
+            let ptr = alloc_zeroed_bytes(4096);
+            assert!(!ptr.is_null());
+            assert!(std::slice::from_raw_parts(ptr, 4096)
+                .iter()
+                .all(|&b| b == 0));
+            free_bytes(ptr, 4096);
+
+            assert!(alloc_zeroed_bytes(usize::MAX).is_null());
+            assert_eq!(alloc_last_error(), ALLOC_BAD_LAYOUT);
+        }
+    }
+
+    #[test]
+    fn test_alloc_aligned() {
+        unsafe {
+            for align in [1, 4, 8, 16, 64] {
+                let ptr = alloc_aligned(24, align);
+                assert!(!ptr.is_null());
+                assert_eq!(ptr as usize % align, 0);
+                free_aligned(ptr, 24, align);
+
+                let empty = alloc_aligned(0, align);
+                assert_eq!(empty as usize % align, 0);
+                free_aligned(empty, 0, align);
+            }
+            assert!(alloc_aligned(24, 12).is_null());
+            assert_eq!(alloc_last_error(), ALLOC_BAD_LAYOUT);
+            assert!(alloc_aligned(24, 0).is_null());
+        }
+    }
+
+    #[test]
+    fn test_realloc_preserves_prefix() {
+        unsafe {
+            let ptr = alloc_bytes(4);
+            std::ptr::copy_nonoverlapping(b"abcd".as_ptr(), ptr, 4);
+
+            let grown = realloc_bytes(ptr, 4, 4096);
+            assert!(!grown.is_null());
+            assert_eq!(std::slice::from_raw_parts(grown, 4), b"abcd");
+
+            let shrunk = realloc_bytes(grown, 4096, 2);
+            assert_eq!(std::slice::from_raw_parts(shrunk, 2), b"ab");
+
+            assert!(realloc_bytes(shrunk, 2, usize::MAX).is_null());
+            assert_eq!(alloc_last_error(), ALLOC_BAD_LAYOUT);
+            assert_eq!(std::slice::from_raw_parts(shrunk, 2), b"ab");
+
+            let empty = realloc_bytes(shrunk, 2, 0);
+            assert!(!empty.is_null());
+            let fresh = realloc_bytes(empty, 0, 3);
+            assert!(!fresh.is_null());
+            free_bytes(fresh, 3);
+        }
+    }
+
+    #[cfg(feature = "strict-aliasing")]
+    #[test]
+    fn test_process_bytes_rejects_overlap() {
+        let mut buf = *b"hello world";
+        let ptr = buf.as_mut_ptr();
+        let written = unsafe { process_bytes(ptr, 8, ptr.wrapping_add(3), 8) };
+        assert_eq!(written, ffi::ALIAS_ERROR);
+    }
 }
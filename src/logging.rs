@@ -0,0 +1,159 @@
+//! `log` crate adapter (`log` feature).
+//!
+//! Kernels log with the usual `log::debug!`/`log::warn!` macros, and the
+//! records go out through the imported `env.wbl_log(level, ptr, len)` as
+//! UTF-8 `target: message` text, where the generated loader prints them
+//! with the matching `console` method. That replaces debugging a SIMD kernel
+//! by writing probe bytes into its output buffer. Nothing is logged until JS
+//! calls `log_init`, and disabled levels cost one comparison per call site.
+//! Host builds print to stderr instead, and host tests record each call in
+//! place of the import. `inflate_raw` logs why it rejects a stream.
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::error::{self, ErrorCode};
+
+#[cfg(target_arch = "wasm32")]
+#[link(wasm_import_module = "env")]
+extern "C" {
+    fn wbl_log(level: u32, ptr: *const u8, len: usize);
+}
+
+struct WasmLogger;
+
+static LOGGER: WasmLogger = WasmLogger;
+
+impl Log for WasmLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            emit(record.level(), &message(record));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn message(record: &Record) -> String {
+    format!("{}: {}", record.target(), record.args())
+}
+
+/// Stands in for the `env.wbl_log` import in host tests.
+#[cfg(all(test, not(target_arch = "wasm32")))]
+unsafe fn wbl_log(level: u32, ptr: *const u8, len: usize) {
+    let text = String::from_utf8(std::slice::from_raw_parts(ptr, len).to_vec()).unwrap();
+    tests::LOGGED.with_borrow_mut(|logged| logged.push((level, text)));
+}
+
+#[cfg(any(target_arch = "wasm32", test))]
+fn emit(level: Level, message: &str) {
+    unsafe { wbl_log(level as u32, message.as_ptr(), message.len()) }
+}
+
+#[cfg(not(any(target_arch = "wasm32", test)))]
+fn emit(level: Level, message: &str) {
+    eprintln!("[{level}] {message}");
+}
+
+/// Route `log` records through `env.wbl_log` and keep those at or above
+/// `max_level`: `0` off, `1` error, `2` warn, `3` info, `4` debug, `5`
+/// trace (the numbers `wbl_log` receives). Call again to change the level.
+///
+/// Returns `0`, or `-1` for a level above `5`.
+#[no_mangle]
+pub extern "C" fn log_init(max_level: u32) -> isize {
+    let filter = match max_level {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        5 => LevelFilter::Trace,
+        _ => return error::fail(ErrorCode::InvalidArgument),
+    };
+    // Only the first call installs the logger; later ones just fail here.
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(filter);
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    thread_local! {
+        /// `(level, text)` of each `wbl_log` call on this thread.
+        pub(super) static LOGGED: RefCell<Vec<(u32, String)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// The level filter is global; tests that set it run one at a time.
+    static LEVEL: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn formats_and_filters_records() {
+        let _level = LEVEL.lock().unwrap();
+        let args = format_args!("lane {} of {}", 3, 4);
+        let record = Record::builder()
+            .level(Level::Debug)
+            .target("kernels::bytes")
+            .args(args)
+            .build();
+        assert_eq!(message(&record), "kernels::bytes: lane 3 of 4");
+
+        assert_eq!(log_init(6), -1);
+        assert_eq!(log_init(2), 0);
+        assert!(LOGGER.enabled(&Metadata::builder().level(Level::Warn).build()));
+        assert!(!LOGGER.enabled(&Metadata::builder().level(Level::Debug).build()));
+        assert_eq!(log_init(5), 0);
+        assert!(LOGGER.enabled(&Metadata::builder().level(Level::Trace).build()));
+        LOGGED.take();
+        log::trace!("reaches the import");
+        log::set_max_level(LevelFilter::Debug);
+        log::trace!("filtered out");
+        assert_eq!(
+            LOGGED.take(),
+            [(
+                5,
+                "wasm_bindgen_lite::logging::tests: reaches the import".into()
+            )]
+        );
+        assert_eq!(log_init(0), 0);
+        assert!(!LOGGER.enabled(&Metadata::builder().level(Level::Error).build()));
+    }
+
+    #[test]
+    fn inflate_logs_why_it_rejects_a_stream() {
+        let _level = LEVEL.lock().unwrap();
+        let mut out = [0u8; 16];
+        let mut inflate = |input: &[u8]| unsafe {
+            crate::kernels::inflate::inflate_raw(input.as_ptr(), input.len(), out.as_mut_ptr(), 16)
+        };
+        assert_eq!(log_init(0), 0);
+        LOGGED.take();
+        assert_eq!(inflate(&[0x07]), -1);
+        assert_eq!(LOGGED.take(), [], "off until log_init");
+
+        assert_eq!(log_init(4), 0);
+        assert_eq!(inflate(&[0x07]), -1);
+        assert_eq!(inflate(&[0x03, 0x02, 0x00]), -1);
+        assert_eq!(inflate(&[0x03, 0x00]), 0, "accepted streams log nothing");
+        assert_eq!(log_init(0), 0);
+        let target = "wasm_bindgen_lite::kernels::inflate";
+        assert_eq!(
+            LOGGED.take(),
+            [
+                (4, format!("{target}: reserved block type at input byte 1")),
+                (
+                    4,
+                    format!("{target}: distance reaches before the output at input byte 2")
+                ),
+            ]
+        );
+    }
+}
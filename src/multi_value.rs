@@ -0,0 +1,76 @@
+//! Multi-value returns (`multi-value` feature).
+//!
+//! The C ABI returns a single scalar, so a kernel with a small fixed result
+//! either writes it through an out-pointer (`pcap_header`) or folds failure
+//! into the value as a sentinel (`hll_count`). With this feature those
+//! kernels instead return `(status, values...)` as wasm multi-value
+//! results: status `0` on success, or the kernel's usual negative status.
+//!
+//! Rust cannot emit such a signature itself: an `extern "C"` function
+//! returning the `#[repr(C)]` [`Status`] takes a hidden pointer to it as
+//! its first parameter. Each of these exports is therefore listed, with the
+//! wasm types of its fields, in the `wbl.multi_value` custom section, and
+//! the CLI build rewrites it into a wrapper that reserves the struct on the
+//! shadow stack, calls the original and returns the loaded fields, then
+//! drops the section. A module that skips that step keeps the pointer
+//! signature, so build with the CLI when the feature is on.
+
+/// The result of a multi-value export: the status, then the fields of
+/// `value` in order.
+#[repr(C)]
+pub struct Status<T> {
+    pub status: i32,
+    pub value: T,
+}
+
+impl<T> Status<T> {
+    /// `value` with the status of a kernel's usual `isize` return: `0` for
+    /// success (a byte count or other non-negative result), or the failure
+    /// status itself.
+    pub fn new(status: isize, value: T) -> Self {
+        Status {
+            status: status.min(0) as i32,
+            value,
+        }
+    }
+}
+
+/// `s` as a byte array, for building a custom section entry in a `static`.
+pub const fn bytes<const N: usize>(s: &str) -> [u8; N] {
+    let src = s.as_bytes();
+    let mut out = [0u8; N];
+    let mut i = 0;
+    while i < N {
+        out[i] = src[i];
+        i += 1;
+    }
+    out
+}
+
+/// List export `$name` in the `wbl.multi_value` section as returning the
+/// space-separated wasm `$types`, which must match the fields of its
+/// [`Status`] in order, starting with the `i32` status.
+macro_rules! multi_value_export {
+    ($name:ident, $types:literal) => {
+        const _: () = {
+            const ENTRY: &str = concat!(stringify!($name), " ", $types, "\n");
+            #[cfg_attr(target_arch = "wasm32", link_section = "wbl.multi_value")]
+            #[used]
+            static MULTI_VALUE_ENTRY: [u8; ENTRY.len()] = $crate::multi_value::bytes(ENTRY);
+        };
+    };
+}
+
+pub(crate) use multi_value_export;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_kernel_statuses() {
+        assert_eq!(Status::new(12, ()).status, 0);
+        assert_eq!(Status::new(-2, ()).status, -2);
+        assert_eq!(bytes::<4>("hll\n"), *b"hll\n");
+    }
+}
@@ -0,0 +1,85 @@
+//! Packed `(ptr, len)` returns.
+//!
+//! A result that already sits in wasm memory can come back from one call as
+//! a single `u64`: the pointer in the low 32 bits and the length in the high
+//! 32 bits. JS receives it as a `BigInt`:
+//!
+//! ```js
+//! const ptr = Number(packed & 0xffffffffn)
+//! const len = Number(packed >> 32n)
+//! ```
+//!
+//! A valid slice never has a null pointer, so [`PACKED_NONE`] (`0`) marks a
+//! failure, with `last_error_code` saying why when the kernel recorded it.
+//!
+//! `_packed` variants of two-phase kernels return their output in the
+//! scratch buffer. It is only valid until the next call that uses the
+//! scratch buffer, so copy it out or consume it first. It must not be passed
+//! to `free_bytes`.
+
+use crate::scratch;
+
+/// Bits of the packed value below the length: the pointer.
+pub const PACKED_PTR_BITS: u32 = 32;
+/// Mask selecting the pointer from a packed value.
+pub const PACKED_PTR_MASK: u64 = (1 << PACKED_PTR_BITS) - 1;
+/// Packed value returned on failure.
+pub const PACKED_NONE: u64 = 0;
+
+/// Pack `ptr` and `len` into one `u64`. Both fit in 32 bits on wasm32;
+/// elsewhere the pointer is truncated.
+pub fn pack_slice(ptr: *const u8, len: usize) -> u64 {
+    (ptr as usize as u64 & PACKED_PTR_MASK) | (len as u64) << PACKED_PTR_BITS
+}
+
+/// Run a two-phase kernel `write(out_ptr, out_len)` once to size its output
+/// and once into the scratch buffer, and pack the result. Returns
+/// [`PACKED_NONE`] when either call fails or the buffer cannot grow.
+pub fn into_scratch(write: impl Fn(*mut u8, usize) -> isize) -> u64 {
+    let needed = write(std::ptr::null_mut(), 0);
+    if needed < 0 {
+        return PACKED_NONE;
+    }
+    // At least one byte, so even an empty result has a non-null pointer.
+    let ptr = scratch::scratch_acquire((needed as usize).max(1));
+    if ptr.is_null() {
+        return PACKED_NONE;
+    }
+    if needed == 0 {
+        return pack_slice(ptr, 0);
+    }
+    match write(ptr, needed as usize) {
+        written if written < 0 => PACKED_NONE,
+        written => pack_slice(ptr, written as usize),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_and_writes_into_scratch() {
+        assert_eq!(pack_slice(0x1234 as *const u8, 5), 5 << 32 | 0x1234);
+
+        let hello = |ptr: *mut u8, len: usize| {
+            if len != 0 {
+                unsafe { ptr.copy_from(b"hello".as_ptr(), 5) };
+            }
+            5
+        };
+        // Host pointers do not fit the packing, so compare with the scratch
+        // buffer directly.
+        assert_eq!(
+            into_scratch(hello),
+            pack_slice(scratch::scratch_acquire(5), 5)
+        );
+        let out = unsafe { std::slice::from_raw_parts(scratch::scratch_acquire(5), 5) };
+        assert_eq!(out, b"hello");
+        assert_ne!(into_scratch(|_, _| 0) & PACKED_PTR_MASK, 0);
+        assert_eq!(
+            into_scratch(|_, len| if len == 0 { 3 } else { -1 }),
+            PACKED_NONE
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Panic messages readable from JS.
+//!
+//! Under `panic = "abort"` a panicking kernel traps, and all the host sees is
+//! `RuntimeError: unreachable`. `install_panic_hook` records the message and
+//! source location of every panic in a fixed buffer before the trap, and the
+//! generated loader reads it back through `last_panic_ptr`/`last_panic_len`
+//! to throw a `WasmPanicError` carrying the real message. The buffer is
+//! static so recording works even when the panic came from a failed
+//! allocation.
+
+use std::cell::RefCell;
+use std::fmt::{self, Write};
+use std::panic::{self, PanicHookInfo};
+use std::sync::Once;
+
+use crate::packed;
+
+/// Longer messages are cut at a character boundary.
+const CAPACITY: usize = 1024;
+
+struct LastPanic {
+    buf: [u8; CAPACITY],
+    len: usize,
+}
+
+impl Write for LastPanic {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let room = CAPACITY - self.len;
+        let s = &s[..s.floor_char_boundary(room)];
+        self.buf[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+        self.len += s.len();
+        Ok(())
+    }
+}
+
+thread_local! {
+    static LAST_PANIC: RefCell<LastPanic> = const {
+        RefCell::new(LastPanic {
+            buf: [0; CAPACITY],
+            len: 0,
+        })
+    };
+}
+
+fn record(info: &PanicHookInfo) {
+    let _ = LAST_PANIC.try_with(|last| {
+        let Ok(mut last) = last.try_borrow_mut() else {
+            return;
+        };
+        last.len = 0;
+        let _ = write!(last, "{}", info.payload_as_str().unwrap_or("Box<dyn Any>"));
+        if let Some(location) = info.location() {
+            let _ = write!(last, " at {location}");
+        }
+    });
+}
+
+/// Record every later panic for `last_panic_ptr`/`last_panic_len`, then run
+/// the previously installed hook. Calling it again has no effect; the
+/// generated loader calls it once per instance.
+#[no_mangle]
+pub extern "C" fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            record(info);
+            previous(info);
+        }));
+    });
+}
+
+/// Address of the last panic message, UTF-8 formatted as
+/// `"<message> at <file>:<line>:<column>"`.
+#[no_mangle]
+pub extern "C" fn last_panic_ptr() -> *const u8 {
+    LAST_PANIC.with_borrow(|last| last.buf.as_ptr())
+}
+
+/// Length in bytes of the last panic message; `0` when nothing panicked
+/// since the last `last_panic_clear`.
+#[no_mangle]
+pub extern "C" fn last_panic_len() -> usize {
+    LAST_PANIC.with_borrow(|last| last.len)
+}
+
+/// `last_panic_ptr` and `last_panic_len` in one call, as a packed
+/// `(ptr, len)`.
+#[no_mangle]
+pub extern "C" fn last_panic_packed() -> u64 {
+    LAST_PANIC.with_borrow(|last| packed::pack_slice(last.buf.as_ptr(), last.len))
+}
+
+/// Forget the last panic message, so a later trap that is not a panic is
+/// not reported with a stale one.
+#[no_mangle]
+pub extern "C" fn last_panic_clear() {
+    LAST_PANIC.with_borrow_mut(|last| last.len = 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message() -> String {
+        let bytes = unsafe { std::slice::from_raw_parts(last_panic_ptr(), last_panic_len()) };
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn records_message_and_location() {
+        install_panic_hook();
+        install_panic_hook();
+        last_panic_clear();
+        assert_eq!(last_panic_len(), 0);
+
+        let line = line!() + 1;
+        let _ = panic::catch_unwind(|| panic!("bad length {}", 7));
+        assert!(message().starts_with(&format!("bad length 7 at src/panics.rs:{line}:")));
+
+        // Long messages are truncated without splitting a character.
+        let _ = panic::catch_unwind(|| panic!("{}", "é".repeat(CAPACITY)));
+        assert_eq!(last_panic_len(), CAPACITY);
+        assert_eq!(message(), "é".repeat(CAPACITY / 2));
+
+        last_panic_clear();
+        assert_eq!(last_panic_len(), 0);
+    }
+}
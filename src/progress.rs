@@ -0,0 +1,100 @@
+//! Progress reports from long-running kernels.
+//!
+//! With the `progress` feature, kernels that can run for seconds (key
+//! derivation, decompressing large inputs) call the imported function
+//! `env.wbl_progress(done, total)` as they go, in whatever unit suits the
+//! kernel. Reports are throttled to about one per percent, so the import
+//! costs nothing measurable. Without the feature, or outside wasm32, the
+//! report compiles to nothing and the module has no such import.
+//!
+//! A wasm import cannot be optional, so the generated loader always
+//! supplies `env.wbl_progress`, forwarding to the listener set with
+//! `onProgress` (and doing nothing without one). Hosts that instantiate the
+//! module themselves must provide it when the feature is on.
+//!
+//! Every throttled report, with or without the feature, is also where the
+//! kernel polls the `cancel` flag.
+
+use crate::cancel;
+
+#[cfg(all(target_arch = "wasm32", feature = "progress"))]
+#[link(wasm_import_module = "env")]
+extern "C" {
+    fn wbl_progress(done: f64, total: f64);
+}
+
+/// Throttled reporter for one kernel call; create it with the total amount
+/// of work and feed it the amount done so far.
+pub struct Progress {
+    total: usize,
+    step: usize,
+    next: usize,
+}
+
+impl Progress {
+    pub fn new(total: usize) -> Self {
+        Progress {
+            total,
+            step: (total / 100).max(1),
+            next: 0,
+        }
+    }
+
+    /// Report `done` if it reaches the next percent, or the end. Returns
+    /// `None` when cancellation was requested; the kernel then stops and
+    /// fails with `cancel::failed()`.
+    pub fn update(&mut self, done: usize) -> Option<()> {
+        if self.due(done) {
+            report(done, self.total);
+            if cancel::requested() {
+                return None;
+            }
+        }
+        Some(())
+    }
+
+    fn due(&mut self, done: usize) -> bool {
+        if done < self.next {
+            return false;
+        }
+        // Past the end nothing more is due.
+        self.next = if done >= self.total {
+            usize::MAX
+        } else {
+            (done + self.step).min(self.total)
+        };
+        true
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "progress"))]
+fn report(done: usize, total: usize) {
+    unsafe { wbl_progress(done as f64, total as f64) }
+}
+
+#[cfg(not(all(target_arch = "wasm32", feature = "progress")))]
+fn report(_done: usize, _total: usize) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reported(total: usize, updates: impl IntoIterator<Item = usize>) -> Vec<usize> {
+        let mut progress = Progress::new(total);
+        updates
+            .into_iter()
+            .filter(|&done| progress.due(done))
+            .collect()
+    }
+
+    #[test]
+    fn throttles_to_percent_steps_and_the_end() {
+        let done = reported(1000, 0..=1000);
+        assert_eq!(done.len(), 101);
+        assert_eq!(done[..3], [0, 10, 20]);
+        assert_eq!(done.last(), Some(&1000));
+        assert_eq!(reported(1000, [0, 995, 1000, 1000]), [0, 995, 1000]);
+        assert_eq!(reported(3, 0..=3), [0, 1, 2, 3]);
+        assert_eq!(reported(0, [0, 0]), [0]);
+    }
+}
@@ -0,0 +1,114 @@
+//! One persistent, growable scratch buffer per instance.
+//!
+//! Hot JS loops call `scratch_acquire(min_len)` before each kernel call and
+//! get the same 16-byte aligned region back until a larger request forces it
+//! to grow, so steady-state calls never touch the allocator. Growing does
+//! not preserve the contents, and any pointer from an earlier acquire is
+//! invalid afterwards. The generated `reset()` frees the buffer with
+//! `scratch_release`.
+
+use std::cell::RefCell;
+
+const ALIGN: usize = 16;
+
+struct Scratch {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Scratch {
+    fn acquire(&mut self, min_len: usize) -> *mut u8 {
+        if min_len <= self.len && !self.ptr.is_null() {
+            crate::set_alloc_status(crate::ALLOC_OK);
+            return self.ptr;
+        }
+        // Doubling keeps a slowly growing workload from reallocating on
+        // every call.
+        let len = min_len.max(self.len.saturating_mul(2));
+        let ptr = unsafe { crate::alloc_with_align(len, ALIGN, false) };
+        if ptr.is_null() {
+            return ptr;
+        }
+        self.release();
+        self.ptr = ptr;
+        self.len = len;
+        ptr
+    }
+
+    fn release(&mut self) {
+        unsafe { crate::free_with_align(self.ptr, self.len, ALIGN) };
+        self.ptr = std::ptr::null_mut();
+        self.len = 0;
+    }
+}
+
+impl Default for Scratch {
+    fn default() -> Self {
+        Scratch {
+            ptr: std::ptr::null_mut(),
+            len: 0,
+        }
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+thread_local! {
+    static SCRATCH: RefCell<Scratch> = RefCell::default();
+}
+
+/// Return the scratch buffer, grown to at least `min_len` bytes if needed.
+/// The contents survive only while no larger request comes in.
+///
+/// Returns a null pointer when the buffer cannot grow (the old buffer stays
+/// valid and `alloc_last_error` says why).
+#[no_mangle]
+pub extern "C" fn scratch_acquire(min_len: usize) -> *mut u8 {
+    SCRATCH.with_borrow_mut(|scratch| scratch.acquire(min_len))
+}
+
+/// Current size of the scratch buffer in bytes; `0` before the first
+/// `scratch_acquire` or after `scratch_release`.
+#[no_mangle]
+pub extern "C" fn scratch_len() -> usize {
+    SCRATCH.with_borrow(|scratch| scratch.len)
+}
+
+/// Free the scratch buffer. The next `scratch_acquire` allocates afresh.
+#[no_mangle]
+pub extern "C" fn scratch_release() {
+    SCRATCH.with_borrow_mut(Scratch::release);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_until_a_larger_request() {
+        let first = scratch_acquire(100);
+        assert!(!first.is_null());
+        assert_eq!(first as usize % ALIGN, 0);
+        assert_eq!(scratch_len(), 100);
+        assert_eq!(scratch_acquire(10), first);
+        assert_eq!(scratch_acquire(100), first);
+
+        assert!(!scratch_acquire(150).is_null());
+        assert_eq!(scratch_len(), 200, "grows by doubling");
+        let zero = scratch_acquire(0);
+        assert!(!zero.is_null());
+
+        assert!(scratch_acquire(usize::MAX).is_null());
+        assert_eq!(crate::alloc_last_error(), crate::ALLOC_BAD_LAYOUT);
+        assert_eq!(scratch_len(), 200, "failed growth keeps the old buffer");
+
+        scratch_release();
+        assert_eq!(scratch_len(), 0);
+        assert!(!scratch_acquire(0).is_null());
+        scratch_release();
+    }
+}
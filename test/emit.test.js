@@ -3,12 +3,14 @@ import assert from 'node:assert'
 import { mkdtempSync, mkdirSync, writeFileSync, readFileSync, rmSync } from 'node:fs'
 import { tmpdir } from 'node:os'
 import { join } from 'node:path'
+import { pathToFileURL } from 'node:url'
 import {
   code,
   buildWrapperIR,
   createCore,
   createLoader,
   emitRuntime,
+  ABI_VERSION,
 } from '../src/cli/emit.js'
 
 test('code builder should manage indentation and blank lines', () => {
@@ -42,10 +44,11 @@ test('buildWrapperIR should normalize export entries', () => {
   const exportsList = [
     { abi: 'add', name: 'plus', return: 'i32' },
     { abi: 'process', reuseBuffer: true, outSize: 'len * 2' },
+    { abi: 'split', return: 'u32_array', output: 'callee' },
   ]
   const ir = buildWrapperIR(exportsList)
 
-  assert.strictEqual(ir.length, 2)
+  assert.strictEqual(ir.length, 3)
 
   assert.deepStrictEqual(ir[0], {
     abi: 'add',
@@ -53,6 +56,8 @@ test('buildWrapperIR should normalize export entries', () => {
     returnType: 'i32',
     reuseBuffer: false,
     outSizeExpr: "(scalarSize('i32') || 4)",
+    output: 'caller',
+    input: 'bytes',
   })
 
   assert.deepStrictEqual(ir[1], {
@@ -61,7 +66,12 @@ test('buildWrapperIR should normalize export entries', () => {
     returnType: 'bytes',
     reuseBuffer: true,
     outSizeExpr: 'len * 2',
+    output: 'caller',
+    input: 'bytes',
   })
+
+  assert.strictEqual(ir[2].output, 'callee')
+  assert.strictEqual(buildWrapperIR([{ abi: 'a', input: 'iovec' }])[0].input, 'iovec')
 })
 
 test('createCore should generate expected boilerplate', () => {
@@ -96,19 +106,418 @@ async function getBaseBytes() { return new Uint8Array(); }
   })
 
   assert.ok(
-    loader.includes('import { setInstance, registerInit } from "./core.js"')
-  )
-  assert.ok(
-    loader.includes('import { instantiateWithBackend } from "./util.js"')
+    loader.includes(
+      'import { setInstances, registerInit, resetState, withRuntimeImports } from "./core.js"'
+    )
   )
   assert.ok(
-    loader.includes('await instantiateWithBackend({ getSimdBytes, getBaseBytes, imports, backend })')
+    loader.includes(
+      'import { instantiateWithBackend, instantiatePool, applySnapshot, snapshotInstance } from "./util.js"'
+    )
   )
+  assert.ok(loader.includes('await instantiateWithBackend({'))
+  assert.ok(loader.includes('_imports = withRuntimeImports(imports);'))
+  assert.ok(loader.includes('cacheKey: opts.cache ? cacheKey : null'))
+  assert.ok(loader.includes('const cacheKey = "wasm";'))
+  assert.ok(loader.includes('export async function reset(opts = {})'))
+  assert.ok(loader.includes('await spawnInstances(pool - 1)'))
   assert.ok(loader.includes('registerInit(init);'))
   assert.ok(loader.includes('init();'))
   assert.ok(loader.includes('export * from "./core.js"'))
 })
 
+function fakeInstance() {
+  const memory = new WebAssembly.Memory({ initial: 1 })
+  const live = new Map()
+  let next = 8
+  return {
+    live,
+    exports: {
+      memory,
+      alloc_bytes(len) {
+        const ptr = next
+        // Distinct pointers even for empty blocks, so `live` tracks each.
+        next += Math.max(len, 1)
+        live.set(ptr, len)
+        return ptr
+      },
+      free_bytes(ptr, len) {
+        assert.strictEqual(live.get(ptr), len)
+        live.delete(ptr)
+      },
+      copy(inPtr, len, outPtr) {
+        const mem = new Uint8Array(memory.buffer)
+        mem.copyWithin(outPtr, inPtr, inPtr + len)
+        return len
+      },
+    },
+  }
+}
+
+test('pooled core pins the first instance and binds the rest explicitly', async () => {
+  const tempRoot = mkdtempSync(join(tmpdir(), 'wbl-'))
+  const corePath = join(tempRoot, 'core.js')
+  writeFileSync(
+    corePath,
+    createCore({
+      exportsList: [{ abi: 'copy', reuseBuffer: true }],
+      autoInit: 'off',
+    })
+  )
+  const core = await import(pathToFileURL(corePath).href)
+
+  const a = fakeInstance()
+  const b = fakeInstance()
+  let cleared = 0
+  let arenas = 0
+  a.exports.handle_clear_all = () => cleared++
+  a.exports.arena_reset = () => arenas++
+  a.exports.scratch_release = () => arenas++
+  core.setInstances([a, b])
+  assert.strictEqual(core.poolSize(), 2)
+
+  assert.deepStrictEqual([...core.copy(new Uint8Array([1, 2]))], [1, 2])
+  const ptr = core.alloc(4)
+  assert.deepStrictEqual([...core.copy(new Uint8Array([3]))], [3])
+  assert.strictEqual(a.live.get(ptr), 4, 'alloc and wrappers share one instance')
+  core.free(ptr, 4)
+  assert.strictEqual(a.live.size, 2, 'reuse buffers stay in a')
+  assert.strictEqual(b.live.size, 0, 'b is never used implicitly')
+
+  const [first, second] = core.instances()
+  assert.strictEqual(first.exports, a.exports)
+  assert.strictEqual(second.exports, b.exports)
+  assert.deepStrictEqual([...second.copy(new Uint8Array([4, 5]))], [4, 5])
+  const bPtr = second.alloc(3)
+  second.memoryU8()[bPtr] = 7
+  assert.strictEqual(new Uint8Array(b.exports.memory.buffer)[bPtr], 7)
+  second.free(bPtr, 3)
+  assert.strictEqual(b.live.size, 2, 'bound call kept reuse buffers in b')
+  assert.strictEqual(core.wasmExports(), a.exports, 'binding switches back')
+
+  let hooked = 0
+  core.onReset(() => hooked++)
+  core.resetState()
+  assert.strictEqual(hooked, 1)
+  assert.strictEqual(cleared, 1, 'handles released in instances that export it')
+  assert.strictEqual(arenas, 2, 'arena and scratch reclaimed where exported')
+  assert.strictEqual(a.live.size, 0)
+  assert.strictEqual(b.live.size, 0)
+  assert.strictEqual(core.wasmExports(), a.exports)
+
+  rmSync(tempRoot, { recursive: true, force: true })
+})
+
+test('callWasm frees both buffers when the kernel traps or fails', async () => {
+  const tempRoot = mkdtempSync(join(tmpdir(), 'wbl-'))
+  const corePath = join(tempRoot, 'core.js')
+  writeFileSync(
+    corePath,
+    createCore({
+      exportsList: [{ abi: 'trap' }, { abi: 'fail' }],
+      autoInit: 'off',
+    })
+  )
+  const core = await import(pathToFileURL(corePath).href)
+
+  const inst = fakeInstance()
+  inst.exports.trap = () => {
+    throw new WebAssembly.RuntimeError('unreachable')
+  }
+  inst.exports.fail = () => -1
+  core.setInstance(inst)
+
+  assert.throws(() => core.trap(new Uint8Array([1, 2])), WebAssembly.RuntimeError)
+  assert.strictEqual(inst.live.size, 0, 'freed after a trap')
+  assert.throws(() => core.fail(new Uint8Array([1, 2])), core.WasmError)
+  assert.strictEqual(inst.live.size, 0, 'freed after an error status')
+
+  rmSync(tempRoot, { recursive: true, force: true })
+})
+
+test('callee-allocated outputs are copied out and freed', async () => {
+  const tempRoot = mkdtempSync(join(tmpdir(), 'wbl-'))
+  const corePath = join(tempRoot, 'core.js')
+  writeFileSync(
+    corePath,
+    createCore({
+      exportsList: [{ abi: 'split', return: 'u32_array', output: 'callee' }],
+      autoInit: 'off',
+    })
+  )
+  const core = await import(pathToFileURL(corePath).href)
+
+  const inst = fakeInstance()
+  // Offsets of every byte equal to 10, in an exactly-sized block.
+  inst.exports.split = (inPtr, len) => {
+    const input = new Uint8Array(inst.exports.memory.buffer, inPtr, len)
+    const offsets = [...input.keys()].filter((i) => input[i] === 10)
+    const ptr = inst.exports.alloc_bytes(offsets.length * 4)
+    const out = new DataView(inst.exports.memory.buffer, ptr)
+    offsets.forEach((offset, i) => out.setUint32(i * 4, offset, true))
+    return (BigInt(offsets.length * 4) << 32n) | BigInt(ptr)
+  }
+  core.setInstance(inst)
+
+  assert.deepStrictEqual(
+    core.split(new Uint8Array([1, 10, 2, 10, 3])),
+    new Uint32Array([1, 3])
+  )
+  assert.strictEqual(inst.live.size, 0, 'input and output freed')
+
+  inst.exports.split = () => 0n
+  assert.throws(
+    () => core.split(new Uint8Array([1])),
+    (err) => err instanceof core.WasmError && err.abi === 'split'
+  )
+  assert.strictEqual(inst.live.size, 0, 'input freed after failure')
+
+  rmSync(tempRoot, { recursive: true, force: true })
+})
+
+test('iovec inputs pass each chunk in its own allocation', async () => {
+  const tempRoot = mkdtempSync(join(tmpdir(), 'wbl-'))
+  const corePath = join(tempRoot, 'core.js')
+  writeFileSync(
+    corePath,
+    createCore({
+      exportsList: [{ abi: 'join', input: 'iovec', outSize: 'len' }],
+      autoInit: 'off',
+    })
+  )
+  const core = await import(pathToFileURL(corePath).href)
+
+  const inst = fakeInstance()
+  // Copies the chunks listed at `iovPtr` back to back into the output.
+  inst.exports.join = (iovPtr, count, outPtr) => {
+    const mem = new Uint8Array(inst.exports.memory.buffer)
+    const iov = new DataView(inst.exports.memory.buffer, iovPtr, count * 8)
+    let at = outPtr
+    for (let i = 0; i < count; i++) {
+      const ptr = iov.getUint32(i * 8, true)
+      const len = iov.getUint32(i * 8 + 4, true)
+      mem.copyWithin(at, ptr, ptr + len)
+      at += len
+    }
+    return at - outPtr
+  }
+  core.setInstance(inst)
+
+  const chunks = [
+    new Uint8Array([1, 2]),
+    new Uint8Array(0),
+    new Uint16Array([0x0403]).buffer,
+  ]
+  assert.deepStrictEqual(core.join(chunks), new Uint8Array([1, 2, 3, 4]))
+  assert.strictEqual(inst.live.size, 0, 'chunks, entries and output freed')
+
+  inst.exports.join = () => -1
+  assert.throws(
+    () => core.join(chunks),
+    (err) => err instanceof core.WasmError && err.abi === 'join'
+  )
+  assert.strictEqual(inst.live.size, 0, 'freed after failure')
+
+  rmSync(tempRoot, { recursive: true, force: true })
+})
+
+test('failed allocations throw WasmAllocError without leaking', async () => {
+  const tempRoot = mkdtempSync(join(tmpdir(), 'wbl-'))
+  const corePath = join(tempRoot, 'core.js')
+  writeFileSync(
+    corePath,
+    createCore({ exportsList: [{ abi: 'copy' }], autoInit: 'off' })
+  )
+  const core = await import(pathToFileURL(corePath).href)
+
+  const inst = fakeInstance()
+  const alloc = inst.exports.alloc_bytes
+  let budget = 1
+  inst.exports.alloc_bytes = (len) => (budget-- > 0 ? alloc(len) : 0)
+  inst.exports.alloc_last_error = () => 2
+  core.setInstance(inst)
+
+  assert.throws(
+    () => core.copy(new Uint8Array([1, 2, 3])),
+    (err) =>
+      err instanceof core.WasmAllocError &&
+      err instanceof RangeError &&
+      err.len === 4 &&
+      err.code === 2
+  )
+  assert.strictEqual(inst.live.size, 0, 'input buffer freed after failure')
+
+  rmSync(tempRoot, { recursive: true, force: true })
+})
+
+test('kernel panics surface as WasmPanicError with the message', async () => {
+  const tempRoot = mkdtempSync(join(tmpdir(), 'wbl-'))
+  const corePath = join(tempRoot, 'core.js')
+  writeFileSync(
+    corePath,
+    createCore({ exportsList: [{ abi: 'copy' }], autoInit: 'off' })
+  )
+  const core = await import(pathToFileURL(corePath).href)
+
+  const inst = fakeInstance()
+  const message = new TextEncoder().encode('bad length at src/lib.rs:1:1')
+  let installed = 0
+  let panicLen = 0
+  Object.assign(inst.exports, {
+    install_panic_hook: () => installed++,
+    last_panic_ptr: () => 60000,
+    last_panic_len: () => panicLen,
+    last_panic_clear: () => (panicLen = 0),
+    copy() {
+      new Uint8Array(inst.exports.memory.buffer).set(message, 60000)
+      panicLen = message.length
+      throw new WebAssembly.RuntimeError('unreachable')
+    },
+  })
+  core.setInstance(inst)
+  assert.strictEqual(installed, 1)
+
+  assert.throws(
+    () => core.copy(new Uint8Array([1])),
+    (err) =>
+      err instanceof core.WasmPanicError &&
+      err.message === 'bad length at src/lib.rs:1:1' &&
+      err.cause instanceof WebAssembly.RuntimeError
+  )
+  assert.strictEqual(panicLen, 0, 'message cleared after reading')
+
+  // A trap without a recorded panic is rethrown unchanged.
+  inst.exports.copy = () => {
+    throw new WebAssembly.RuntimeError('memory access out of bounds')
+  }
+  assert.throws(
+    () => core.copy(new Uint8Array([1])),
+    (err) => err instanceof WebAssembly.RuntimeError
+  )
+
+  rmSync(tempRoot, { recursive: true, force: true })
+})
+
+test('failed calls throw WasmError with the recorded code', async () => {
+  const tempRoot = mkdtempSync(join(tmpdir(), 'wbl-'))
+  const corePath = join(tempRoot, 'core.js')
+  writeFileSync(
+    corePath,
+    createCore({ exportsList: [{ abi: 'copy' }], autoInit: 'off' })
+  )
+  const core = await import(pathToFileURL(corePath).href)
+
+  const inst = fakeInstance()
+  const detail = new TextEncoder().encode('output buffer is too small')
+  let code = 0
+  Object.assign(inst.exports, {
+    last_error_code: () => code,
+    last_error_message(ptr, len) {
+      if (len === 0) return detail.length
+      new Uint8Array(inst.exports.memory.buffer).set(detail, ptr)
+      return detail.length
+    },
+    last_error_clear: () => (code = 0),
+    copy: () => {
+      code = 4
+      return -1
+    },
+  })
+  core.setInstance(inst)
+
+  assert.throws(
+    () => core.copy(new Uint8Array([1])),
+    (err) =>
+      err instanceof core.WasmError &&
+      err.abi === 'copy' &&
+      err.status === -1 &&
+      err.code === 4 &&
+      err.message === 'copy failed: output buffer is too small (code 4)'
+  )
+  assert.strictEqual(code, 0, 'code cleared after reading')
+  assert.strictEqual(inst.live.size, 0, 'buffers freed after failure')
+
+  // Modules with the packed export return the message in one call.
+  new Uint8Array(inst.exports.memory.buffer).set(detail, 61000)
+  inst.exports.last_error_message_packed = () =>
+    (BigInt(detail.length) << 32n) | 61000n
+  inst.exports.last_error_message = () => assert.fail('unpacked read')
+  assert.throws(
+    () => core.copy(new Uint8Array([1])),
+    (err) => err.message === 'copy failed: output buffer is too small (code 4)'
+  )
+  assert.strictEqual(core.packedView(0n), null)
+
+  // Without a recorded code the message keeps the bare status.
+  inst.exports.copy = () => -1
+  assert.throws(
+    () => core.copy(new Uint8Array([1])),
+    (err) => err instanceof core.WasmError && err.message === 'copy failed: -1'
+  )
+
+  rmSync(tempRoot, { recursive: true, force: true })
+})
+
+test('setInstances rejects a module built for another ABI version', async () => {
+  const tempRoot = mkdtempSync(join(tmpdir(), 'wbl-'))
+  const corePath = join(tempRoot, 'core.js')
+  writeFileSync(
+    corePath,
+    createCore({ exportsList: [{ abi: 'copy' }], autoInit: 'off' })
+  )
+  const core = await import(pathToFileURL(corePath).href)
+
+  const stale = fakeInstance()
+  stale.exports.abi_version = () => ABI_VERSION + 1
+  assert.throws(() => core.setInstance(stale), /ABI version/)
+
+  const current = fakeInstance()
+  current.exports.abi_version = () => ABI_VERSION
+  core.setInstance(current)
+  core.setInstance(fakeInstance()) // modules without the export are trusted
+  assert.deepStrictEqual([...core.copy(new Uint8Array([5]))], [5])
+
+  rmSync(tempRoot, { recursive: true, force: true })
+})
+
+test('runtime imports forward progress and log calls to listeners', async () => {
+  const tempRoot = mkdtempSync(join(tmpdir(), 'wbl-'))
+  const corePath = join(tempRoot, 'core.js')
+  writeFileSync(
+    corePath,
+    createCore({ exportsList: [{ abi: 'copy' }], autoInit: 'off' })
+  )
+  const core = await import(pathToFileURL(corePath).href)
+
+  const other = () => {}
+  const imports = core.withRuntimeImports({ env: { other }, js: {} })
+  assert.strictEqual(imports.env.other, other, 'other imports kept')
+  assert.ok(imports.js)
+  imports.env.wbl_progress(1, 2) // no listener yet
+
+  const seen = []
+  core.onProgress((done, total) => seen.push([done, total]))
+  imports.env.wbl_progress(5, 10)
+  core.onProgress(null)
+  imports.env.wbl_progress(10, 10)
+  assert.deepStrictEqual(seen, [[5, 10]])
+
+  const inst = fakeInstance()
+  core.setInstance(inst)
+  const text = new TextEncoder().encode('kernels::bytes: lane 3')
+  new Uint8Array(inst.exports.memory.buffer).set(text, 100)
+  const logs = []
+  core.onLog((level, message) => logs.push([level, message]))
+  imports.env.wbl_log(4, 100, text.length)
+  core.onLog(null)
+  assert.deepStrictEqual(logs, [[4, 'kernels::bytes: lane 3']])
+
+  const own = core.withRuntimeImports({ env: { wbl_progress: other } })
+  assert.strictEqual(own.env.wbl_progress, other, 'host import wins')
+
+  rmSync(tempRoot, { recursive: true, force: true })
+})
+
 test('streaming logic should be included when enabled', () => {
   const exportsList = [{ abi: 'process' }]
   const stream = {
@@ -0,0 +1,51 @@
+import test from 'node:test'
+import assert from 'node:assert'
+import { applyMultiValue } from '../src/cli/multivalue.js'
+
+const section = (id, bytes) => [id, bytes.length, ...bytes]
+const name = (s) => [s.length, ...new TextEncoder().encode(s)]
+
+// A module shaped like rustc output for `extern "C" fn f(x: f64) ->
+// Status<f64>`: `f(ret, x)` stores status 7 at `ret` and `x` at `ret + 8`.
+// Global 0 is the stack pointer, exported as `sp` to check it is restored.
+function module(manifest) {
+  const body = [
+    0x00,
+    ...[0x20, 0x00, 0x41, 0x07, 0x36, 0x02, 0x00], // i32.store ret, 7
+    ...[0x20, 0x00, 0x20, 0x01, 0x39, 0x03, 0x08], // f64.store ret+8, x
+    0x0b,
+  ]
+  const bytes = [
+    ...[0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00],
+    ...section(1, [0x01, 0x60, 0x02, 0x7f, 0x7c, 0x00]),
+    ...section(3, [0x01, 0x00]),
+    ...section(5, [0x01, 0x00, 0x01]),
+    ...section(6, [0x01, 0x7f, 0x01, 0x41, 0x80, 0x08, 0x0b]),
+    ...section(7, [0x02, ...name('f'), 0x00, 0x00, ...name('sp'), 0x03, 0x00]),
+    ...section(10, [0x01, body.length, ...body]),
+  ]
+  if (manifest) {
+    const text = new TextEncoder().encode(manifest)
+    bytes.push(...section(0, [...name('wbl.multi_value'), ...text]))
+  }
+  return new Uint8Array(bytes)
+}
+
+test('applyMultiValue returns pointer results as multiple values', () => {
+  assert.strictEqual(applyMultiValue(module()), null)
+
+  const rewritten = applyMultiValue(module('f i32 f64\n'))
+  const wasmModule = new WebAssembly.Module(rewritten)
+  assert.deepStrictEqual(
+    WebAssembly.Module.customSections(wasmModule, 'wbl.multi_value'),
+    []
+  )
+  const { exports } = new WebAssembly.Instance(wasmModule)
+  assert.deepStrictEqual(exports.f(2.5), [7, 2.5])
+  assert.strictEqual(exports.sp.value, 1024)
+})
+
+test('applyMultiValue rejects exports it cannot rewrite', () => {
+  assert.throws(() => applyMultiValue(module('g i32 f64\n')), /not exported/)
+  assert.throws(() => applyMultiValue(module('f i32 v128\n')), /unknown type/)
+})
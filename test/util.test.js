@@ -0,0 +1,105 @@
+import test from 'node:test'
+import assert from 'node:assert'
+
+// A module exporting a single one-page memory, and one that also exports a
+// mutable i32 global `counter` and an immutable one `base`.
+const GLOBALS_MODULE = new Uint8Array([
+  0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x05, 0x03, 0x01, 0x00, 0x01,
+  0x06, 0x0b, 0x02, 0x7f, 0x01, 0x41, 0x00, 0x0b, 0x7f, 0x00, 0x41, 0x08, 0x0b,
+  0x07, 0x1b, 0x03, 0x06, 0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00, 0x07,
+  0x63, 0x6f, 0x75, 0x6e, 0x74, 0x65, 0x72, 0x03, 0x00, 0x04, 0x62, 0x61, 0x73,
+  0x65, 0x03, 0x01,
+])
+
+// A module exporting a single one-page memory.
+const MEMORY_MODULE = new Uint8Array([
+  0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x05, 0x03, 0x01, 0x00, 0x01,
+  0x07, 0x0a, 0x01, 0x06, 0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00,
+])
+
+function fakeIndexedDB() {
+  const data = new Map()
+  const request = (fn) => {
+    const req = {}
+    queueMicrotask(() => {
+      req.result = fn()
+      req.onsuccess?.()
+    })
+    return req
+  }
+  const store = {
+    get: (key) => request(() => data.get(key)),
+    put: (value, key) => data.set(key, value),
+  }
+  const db = {
+    transaction() {
+      const tx = { objectStore: () => store }
+      queueMicrotask(() => tx.oncomplete?.())
+      return tx
+    },
+  }
+  return { data, open: () => request(() => db) }
+}
+
+const idb = fakeIndexedDB()
+globalThis.indexedDB = idb
+const { compileCached, snapshotInstance, applySnapshot } = await import(
+  '../src/js/util.js'
+)
+
+test('compileCached only fetches bytes on a miss', async () => {
+  let fetches = 0
+  const getBytes = async () => {
+    fetches++
+    return MEMORY_MODULE
+  }
+  const first = await compileCached('mod@1:base', getBytes)
+  const second = await compileCached('mod@1:base', getBytes)
+  assert.strictEqual(fetches, 1)
+  assert.ok(first instanceof WebAssembly.Module)
+  assert.ok(second instanceof WebAssembly.Module)
+  // Browsers refuse to structured-clone a Module; the bytes are stored.
+  const stored = idb.data.get('mod@1:base')
+  assert.ok(stored instanceof ArrayBuffer)
+  assert.deepStrictEqual(new Uint8Array(stored), MEMORY_MODULE)
+})
+
+test('snapshotInstance runs warmup once and restores it afterwards', async () => {
+  const module = await WebAssembly.compile(MEMORY_MODULE)
+  let warmups = 0
+  const warmup = (exports) => {
+    warmups++
+    exports.memory.grow(1)
+    new Uint8Array(exports.memory.buffer)[70000] = 42
+  }
+
+  const first = await WebAssembly.instantiate(module)
+  await snapshotInstance(first, 'mod@1:wasm:memory', warmup)
+
+  const second = await WebAssembly.instantiate(module)
+  const snapshot = await snapshotInstance(second, 'mod@1:wasm:memory', warmup)
+  assert.strictEqual(warmups, 1)
+  assert.strictEqual(new Uint8Array(second.exports.memory.buffer)[70000], 42)
+
+  const third = await WebAssembly.instantiate(module)
+  applySnapshot(third, snapshot)
+  assert.strictEqual(third.exports.memory.buffer.byteLength, 2 * 65536)
+})
+
+test('snapshots carry exported mutable globals with memory', async () => {
+  const module = await WebAssembly.compile(GLOBALS_MODULE)
+  const warmup = (exports) => {
+    exports.counter.value = 7
+    new Uint8Array(exports.memory.buffer)[16] = 1
+  }
+
+  const first = await WebAssembly.instantiate(module)
+  const snapshot = await snapshotInstance(first, 'globals@1:memory', warmup)
+  assert.deepStrictEqual(snapshot.globals, { counter: 7 })
+
+  const second = await WebAssembly.instantiate(module)
+  await snapshotInstance(second, 'globals@1:memory', warmup)
+  assert.strictEqual(second.exports.counter.value, 7)
+  assert.strictEqual(second.exports.base.value, 8)
+  assert.strictEqual(new Uint8Array(second.exports.memory.buffer)[16], 1)
+})